@@ -0,0 +1,148 @@
+//! Audit signatures binding an operator to the proofs it produced.
+//!
+//! The worker signs a digest of `(task id, proof bytes, params checksum)` with its Lagrange key,
+//! so that a reply can later be attributed to the operator during disputes.
+
+use anyhow::ensure;
+use anyhow::Result;
+use ethers::prelude::LocalWallet;
+use ethers::prelude::Signature;
+use ethers_core::types::H256;
+use ethers_core::utils::keccak256;
+
+use crate::jwt::recover_public_key_from_hash;
+
+/// Domain separator preventing audit signatures from being replayed in another context.
+const AUDIT_DOMAIN: &[u8] = b"lagrange-reply-audit-v1";
+
+/// Computes the digest signed by the operator for a reply.
+///
+/// Every field is length-prefixed so that distinct tuples can not produce the same digest.
+pub fn audit_digest(
+    task_id: &str,
+    proof: &[u8],
+    params_checksum: &str,
+) -> [u8; 32]
+{
+    let mut data = Vec::with_capacity(
+        AUDIT_DOMAIN.len() + task_id.len() + proof.len() + params_checksum.len() + 24,
+    );
+    for field in [
+        AUDIT_DOMAIN,
+        task_id.as_bytes(),
+        proof,
+        params_checksum.as_bytes(),
+    ]
+    {
+        data.extend_from_slice(&(field.len() as u64).to_be_bytes());
+        data.extend_from_slice(field);
+    }
+
+    keccak256(data)
+}
+
+/// Signs an audit digest with the operator wallet and returns the hex-encoded signature.
+pub fn sign_audit_digest(
+    digest: [u8; 32],
+    wallet: &LocalWallet,
+) -> Result<String>
+{
+    let signature = wallet.sign_hash(H256::from(digest))?;
+    Ok(hex::encode(signature.to_vec()))
+}
+
+/// Recovers the hex-encoded public key which produced the hex-encoded `signature` over `digest`.
+///
+/// The returned key uses the same format as
+/// [`JWTAuth::recover_public_key`](crate::jwt::JWTAuth::recover_public_key).
+pub fn recover_audit_signer(
+    digest: [u8; 32],
+    signature: &str,
+) -> Result<String>
+{
+    let bytes = hex::decode(signature.trim_start_matches("0x"))?;
+    let signature = Signature::try_from(bytes.as_slice())?;
+
+    recover_public_key_from_hash(
+        &digest,
+        &signature,
+    )
+}
+
+/// Verifies that `signature` over the reply tuple was produced by `expected_public_key`.
+pub fn verify_audit_signature(
+    task_id: &str,
+    proof: &[u8],
+    params_checksum: &str,
+    signature: &str,
+    expected_public_key: &str,
+) -> Result<()>
+{
+    let digest = audit_digest(
+        task_id,
+        proof,
+        params_checksum,
+    );
+    let public_key = recover_audit_signer(
+        digest,
+        signature,
+    )?;
+
+    ensure!(
+        public_key.eq_ignore_ascii_case(expected_public_key.trim_start_matches("0x")),
+        "audit signature was produced by `{public_key}`, expected `{expected_public_key}`"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::thread_rng;
+
+    use super::*;
+
+    #[test]
+    fn test_audit_signature_round_trip() -> Result<()>
+    {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let public_key = hex::encode(
+            &wallet
+                .signer()
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes()[1..],
+        );
+
+        let digest = audit_digest(
+            "task",
+            b"proof",
+            "checksum",
+        );
+        let signature = sign_audit_digest(
+            digest,
+            &wallet,
+        )?;
+
+        verify_audit_signature(
+            "task",
+            b"proof",
+            "checksum",
+            &signature,
+            &public_key,
+        )?;
+        assert!(
+            verify_audit_signature(
+                "task",
+                b"another proof",
+                "checksum",
+                &signature,
+                &public_key,
+            )
+            .is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -82,80 +82,91 @@ impl JWTAuth
             .to_base64()?;
         let message_hash = hash_message(msg.as_bytes());
 
-        let (recoverable_sig, recovery_id) = self.as_signature()?;
-        let verifying_key = VerifyingKey::recover_from_prehash(
+        recover_public_key_from_hash(
             message_hash.as_ref(),
-            &recoverable_sig,
-            recovery_id,
-        )?;
-
-        let public_key = PublicKey::from(&verifying_key);
-        let public_key = public_key.to_encoded_point(
-            // compress =
-            false,
-        );
-        let public_key = public_key.as_bytes();
-        debug_assert_eq!(
-            public_key[0],
-            0x04
-        );
-
-        let public_key = hex::encode(&public_key[1..]);
-        // Must be 64 bytes (128 hex chars).
-        debug_assert_eq!(
-            public_key.len(),
-            128
-        );
-
-        Ok(public_key)
+            &self.signature,
+        )
     }
+}
 
-    /// Get the recovery signature.
-    /// Copied from ethers-rs since it's private:
-    /// <https://github.com/gakonst/ethers-rs/blob/master/ethers-core/src/types/signature.rs#L129>
-    fn as_signature(
-        &self
-    ) -> Result<(
-        RecoverableSignature,
-        RecoveryId,
-    )>
-    {
-        let mut recovery_id = self
-            .signature
-            .recovery_id()?;
-        let mut signature = {
-            let mut r_bytes = [0u8; 32];
-            let mut s_bytes = [0u8; 32];
-            self.signature
-                .r
-                .to_big_endian(&mut r_bytes);
-            self.signature
-                .s
-                .to_big_endian(&mut s_bytes);
-            let gar: &GenericArray<u8, U32> = GenericArray::from_slice(&r_bytes);
-            let gas: &GenericArray<u8, U32> = GenericArray::from_slice(&s_bytes);
-            K256Signature::from_scalars(
-                *gar,
-                *gas,
-            )?
-        };
-
-        // Normalize into "low S" form. See:
-        // - https://github.com/RustCrypto/elliptic-curves/issues/988
-        // - https://github.com/bluealloy/revm/pull/870
-        if let Some(normalized) = signature.normalize_s()
-        {
-            signature = normalized;
-            recovery_id = RecoveryId::from_byte(recovery_id.to_byte() ^ 1).unwrap();
-        }
+/// Recovers the hex-encoded uncompressed public key (without the `0x04` prefix) which produced
+/// `signature` over the pre-hashed `message_hash`.
+pub(crate) fn recover_public_key_from_hash(
+    message_hash: &[u8],
+    signature: &Signature,
+) -> Result<String>
+{
+    let (recoverable_sig, recovery_id) = as_signature(signature)?;
+    let verifying_key = VerifyingKey::recover_from_prehash(
+        message_hash,
+        &recoverable_sig,
+        recovery_id,
+    )?;
+
+    let public_key = PublicKey::from(&verifying_key);
+    let public_key = public_key.to_encoded_point(
+        // compress =
+        false,
+    );
+    let public_key = public_key.as_bytes();
+    debug_assert_eq!(
+        public_key[0],
+        0x04
+    );
+
+    let public_key = hex::encode(&public_key[1..]);
+    // Must be 64 bytes (128 hex chars).
+    debug_assert_eq!(
+        public_key.len(),
+        128
+    );
+
+    Ok(public_key)
+}
 
-        Ok(
-            (
-                signature,
-                recovery_id,
-            ),
-        )
+/// Get the recovery signature.
+/// Copied from ethers-rs since it's private:
+/// <https://github.com/gakonst/ethers-rs/blob/master/ethers-core/src/types/signature.rs#L129>
+fn as_signature(
+    signature: &Signature
+) -> Result<(
+    RecoverableSignature,
+    RecoveryId,
+)>
+{
+    let mut recovery_id = signature.recovery_id()?;
+    let mut signature = {
+        let mut r_bytes = [0u8; 32];
+        let mut s_bytes = [0u8; 32];
+        signature
+            .r
+            .to_big_endian(&mut r_bytes);
+        signature
+            .s
+            .to_big_endian(&mut s_bytes);
+        let gar: &GenericArray<u8, U32> = GenericArray::from_slice(&r_bytes);
+        let gas: &GenericArray<u8, U32> = GenericArray::from_slice(&s_bytes);
+        K256Signature::from_scalars(
+            *gar,
+            *gas,
+        )?
+    };
+
+    // Normalize into "low S" form. See:
+    // - https://github.com/RustCrypto/elliptic-curves/issues/988
+    // - https://github.com/bluealloy/revm/pull/870
+    if let Some(normalized) = signature.normalize_s()
+    {
+        signature = normalized;
+        recovery_id = RecoveryId::from_byte(recovery_id.to_byte() ^ 1).unwrap();
     }
+
+    Ok(
+        (
+            signature,
+            recovery_id,
+        ),
+    )
 }
 
 #[cfg(test)]
@@ -0,0 +1,187 @@
+//! The bindings of `lgn-messages` for the JS tooling of the gateways, e.g. to build and inspect the
+//! task envelopes in the browser.
+//!
+//! Built for wasm32 on `lgn-messages` without its `tasks` feature: the content of the tasks, which
+//! embeds the inputs of the mp2 circuits, is handled as plain JSON, the envelopes around it and the
+//! proof keys are checked as the workers would.
+//!
+//! ```sh
+//! wasm-pack build lgn-messages-wasm --target web
+//! ```
+
+use lgn_messages::routing::RoutingKey;
+use lgn_messages::types::v1::groth16;
+use lgn_messages::types::v1::preprocessing::db_keys;
+use lgn_messages::types::v1::preprocessing::ext_keys;
+use lgn_messages::types::v1::query;
+use lgn_messages::types::MessageEnvelope;
+use serde_derive::Serialize;
+use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::JsError;
+
+/// A task envelope, its task being any JSON value.
+type Envelope = MessageEnvelope<serde_json::Value>;
+
+/// A proof key parsed by [`parse_proof_key`].
+#[derive(Serialize, Debug)]
+#[serde(
+    tag = "family",
+    content = "key",
+    rename_all = "snake_case"
+)]
+enum ParsedProofKey
+{
+    Extraction(ext_keys::ProofKey),
+    Database(db_keys::ProofKey),
+    Query(query::keys::ProofKey),
+    Groth16(groth16::keys::ProofKey),
+}
+
+/// Returns the envelope `task` for `domain`, e.g. `sp`, as JSON.
+#[wasm_bindgen(js_name = encodeEnvelope)]
+pub fn encode_envelope(
+    query_id: String,
+    task_id: String,
+    domain: String,
+    priority: u64,
+    task: &str,
+) -> Result<String, JsError>
+{
+    let task = serde_json::from_str(task)?;
+    let envelope = Envelope::new(
+        query_id,
+        task_id,
+        task,
+        RoutingKey::combined(
+            domain,
+            priority,
+        ),
+    );
+    Ok(serde_json::to_string(&envelope)?)
+}
+
+/// Decodes the JSON `envelope` as the workers would, returning it with its defaults filled in.
+#[wasm_bindgen(js_name = decodeEnvelope)]
+pub fn decode_envelope(envelope: &str) -> Result<String, JsError>
+{
+    let envelope: Envelope = serde_json::from_str(envelope)?;
+    Ok(serde_json::to_string(&envelope)?)
+}
+
+/// Returns the canonical JSON of the JSON `value`, e.g. of an envelope or a reply, the bytes the
+/// workers hash and sign it by.
+#[wasm_bindgen]
+pub fn canonicalize(value: &str) -> Result<String, JsError>
+{
+    let value: serde_json::Value = serde_json::from_str(value)?;
+    Ok(lgn_messages::canonical::to_string(&value)?)
+}
+
+/// Decrypts the proof of a reply encrypted to the key of `secret_key`, 32 bytes in hex, see
+/// `lgn_messages::encryption`.
+#[wasm_bindgen(js_name = decryptProof)]
+pub fn decrypt_proof(
+    secret_key: &str,
+    proof: &[u8],
+) -> Result<Vec<u8>, JsError>
+{
+    let secret_key = hex::decode(secret_key.trim_start_matches("0x"))?;
+    Ok(
+        lgn_messages::encryption::decrypt(
+            &secret_key,
+            proof,
+        )?,
+    )
+}
+
+/// Parses the proof key `key`, returning `{"family": ..., "key": ...}` as JSON, the family being
+/// one of `extraction`, `database`, `query` or `groth16`.
+#[wasm_bindgen(js_name = parseProofKey)]
+pub fn parse_proof_key(key: &str) -> Result<String, JsError>
+{
+    let parsed = proof_key(key).map_err(|err| JsError::new(&err))?;
+    Ok(serde_json::to_string(&parsed)?)
+}
+
+fn proof_key(key: &str) -> Result<ParsedProofKey, String>
+{
+    // The first family parsing the key wins, the errors of all of them are reported otherwise.
+    let mut errors = vec![];
+    match key.parse::<ext_keys::ProofKey>()
+    {
+        Ok(key) => return Ok(ParsedProofKey::Extraction(key)),
+        Err(err) =>
+        {
+            errors.push(
+                format!(
+                    "extraction: {}",
+                    err.reason
+                ),
+            )
+        },
+    }
+    match key.parse::<db_keys::ProofKey>()
+    {
+        Ok(key) => return Ok(ParsedProofKey::Database(key)),
+        Err(err) =>
+        {
+            errors.push(
+                format!(
+                    "database: {}",
+                    err.reason
+                ),
+            )
+        },
+    }
+    match key.parse::<query::keys::ProofKey>()
+    {
+        Ok(key) => return Ok(ParsedProofKey::Query(key)),
+        Err(err) =>
+        {
+            errors.push(
+                format!(
+                    "query: {}",
+                    err.reason
+                ),
+            )
+        },
+    }
+    match key.parse::<groth16::keys::ProofKey>()
+    {
+        Ok(key) => return Ok(ParsedProofKey::Groth16(key)),
+        Err(err) =>
+        {
+            errors.push(
+                format!(
+                    "groth16: {}",
+                    err.reason
+                ),
+            )
+        },
+    }
+    Err(
+        format!(
+            "`{key}` is not a proof key ({})",
+            errors.join("; ")
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parses_the_proof_keys_of_every_family()
+    {
+        let key = groth16::keys::ProofKey("q1".to_string()).to_string();
+        assert!(
+            matches!(
+                proof_key(&key),
+                Ok(ParsedProofKey::Groth16(_))
+            )
+        );
+        assert!(proof_key("V1_PREPROCESSING/nope").is_err());
+    }
+}
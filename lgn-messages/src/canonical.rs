@@ -0,0 +1,176 @@
+//! The canonical JSON of the messages, the bytes to hash or sign them by.
+//!
+//! The JSON written by `serde_json` depends on its features, e.g. `preserve_order` keeps the keys
+//! of the maps in their insertion order, which any crate of the build can turn on, and on the
+//! formatting of the floats of its version. The canonical JSON does not:
+//! - the keys of the objects are sorted by their UTF-8 bytes, at every depth;
+//! - there is no whitespace;
+//! - the floats with an integral value, up to 2^53, are written as integers, e.g. `1` for `1.0`,
+//!   the other ones in their shortest round-trip form;
+//! - the strings are escaped as `serde_json` does, only `"`, `\` and the control characters.
+//!
+//! Two messages equal once deserialized have the same canonical JSON, whatever wrote them.
+
+use std::io::Write;
+
+use serde::Serialize;
+use serde_json::Number;
+use serde_json::Value;
+
+/// The largest integer a float holds exactly, beyond which the floats are written as such.
+const MAX_EXACT_FLOAT: f64 = 9_007_199_254_740_992.0;
+
+/// The canonical JSON of `value`.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<Vec<u8>>
+{
+    let value = serde_json::to_value(value)?;
+    let mut canonical = vec![];
+    write_value(
+        &mut canonical,
+        &value,
+    )?;
+    Ok(canonical)
+}
+
+/// The canonical JSON of `value`, as a string.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String>
+{
+    let canonical = to_vec(value)?;
+    // Only `serde_json` wrote to it, which writes UTF-8.
+    Ok(String::from_utf8(canonical).expect("JSON is UTF-8"))
+}
+
+fn write_value(
+    out: &mut Vec<u8>,
+    value: &Value,
+) -> serde_json::Result<()>
+{
+    match value
+    {
+        Value::Null | Value::Bool(_) | Value::String(_) =>
+        {
+            serde_json::to_writer(
+                &mut *out,
+                value,
+            )?
+        },
+        Value::Number(number) =>
+        {
+            write_number(
+                out,
+                number,
+            )
+        },
+        Value::Array(items) =>
+        {
+            out.push(b'[');
+            for (i, item) in items
+                .iter()
+                .enumerate()
+            {
+                if i > 0
+                {
+                    out.push(b',');
+                }
+                write_value(
+                    out,
+                    item,
+                )?;
+            }
+            out.push(b']');
+        },
+        Value::Object(map) =>
+        {
+            let mut entries = map
+                .iter()
+                .collect::<Vec<_>>();
+            entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+            out.push(b'{');
+            for (i, (key, item)) in entries
+                .into_iter()
+                .enumerate()
+            {
+                if i > 0
+                {
+                    out.push(b',');
+                }
+                serde_json::to_writer(
+                    &mut *out,
+                    key,
+                )?;
+                out.push(b':');
+                write_value(
+                    out,
+                    item,
+                )?;
+            }
+            out.push(b'}');
+        },
+    }
+    Ok(())
+}
+
+fn write_number(
+    out: &mut Vec<u8>,
+    number: &Number,
+)
+{
+    // Writing to a `Vec` can not fail.
+    let _ = if let Some(int) = number.as_i64()
+    {
+        write!(
+            out,
+            "{int}"
+        )
+    }
+    else if let Some(int) = number.as_u64()
+    {
+        write!(
+            out,
+            "{int}"
+        )
+    }
+    else
+    {
+        let float = number
+            .as_f64()
+            .unwrap_or_default();
+        if float.fract() == 0.0 && float.abs() <= MAX_EXACT_FLOAT
+        {
+            // Also writes `-0.0` as `0`.
+            write!(
+                out,
+                "{}",
+                float as i64
+            )
+        }
+        else
+        {
+            write!(
+                out,
+                "{number}"
+            )
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests
+{
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn sorts_the_keys_and_normalizes_the_numbers()
+    {
+        let value = json!({
+            "b": [1.0, -0.0, 2.5, 1e300, u64::MAX],
+            "a": {"z": null, "y": "é\"\n"},
+        });
+        assert_eq!(
+            to_string(&value).unwrap(),
+            r#"{"a":{"y":"é\"\n","z":null},"b":[1,0,2.5,1e300,18446744073709551615]}"#
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! The encryption of the proofs to the customer requesting them, for only the customer to read
+//! them.
+//!
+//! A task whose envelope carries a `recipient_pubkey`, a secp256k1 public key in hex SEC1 form,
+//! gets the proof of its reply encrypted with ECIES: an ephemeral key agrees on a secret with the
+//! recipient key, HKDF-SHA256 derives an AES-256-GCM key from it, salted with the ephemeral public
+//! key, and the proof bytes become:
+//!
+//! ```text
+//! ephemeral public key, compressed (33 bytes) || nonce (12 bytes) || ciphertext || tag (16 bytes)
+//! ```
+//!
+//! The reply records the [`SCHEME`] and the id of the recipient key, see [`ProofEncryption`]. The
+//! proof key is left as is, the gateway storing and routing the ciphertext like any proof.
+
+use std::str::FromStr;
+
+use aes_gcm::aead::Aead;
+use aes_gcm::Aes256Gcm;
+use aes_gcm::KeyInit;
+use aes_gcm::Nonce;
+use k256::ecdh::EphemeralSecret;
+use k256::ecdh::SharedSecret;
+use k256::elliptic_curve::rand_core::CryptoRngCore;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use k256::PublicKey;
+use k256::SecretKey;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use sha2::Sha256;
+use thiserror::Error;
+
+/// The scheme the proofs are encrypted with, also the info of the key derivation.
+pub const SCHEME: &str = "ecies-secp256k1-hkdf-sha256-aes256gcm";
+
+const EPHEMERAL_KEY_LEN: usize = 33;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EncryptionError
+{
+    #[error("invalid recipient public key `{0}`, expected a hex SEC1 secp256k1 key")]
+    InvalidPublicKey(String),
+
+    #[error("invalid secret key, expected 32 bytes of a secp256k1 scalar")]
+    InvalidSecretKey,
+
+    #[error("the proof could not be encrypted")]
+    Encryption,
+
+    #[error("the ciphertext is corrupted or was not encrypted to this key")]
+    Decryption,
+}
+
+/// How the proof of a reply is encrypted.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProofEncryption
+{
+    /// The scheme of the ciphertext, [`SCHEME`].
+    pub scheme: String,
+
+    /// The id of the recipient key, see [`RecipientKey::key_id`].
+    pub key_id: String,
+}
+
+/// The public key of the customer the proofs are encrypted to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecipientKey(PublicKey);
+
+impl FromStr for RecipientKey
+{
+    type Err = EncryptionError;
+
+    /// Parses a hex SEC1 key, compressed or not, with or without `0x`.
+    fn from_str(key: &str) -> Result<Self, Self::Err>
+    {
+        hex::decode(key.trim_start_matches("0x"))
+            .ok()
+            .and_then(|bytes| PublicKey::from_sec1_bytes(&bytes).ok())
+            .map(RecipientKey)
+            .ok_or_else(|| EncryptionError::InvalidPublicKey(key.to_string()))
+    }
+}
+
+impl RecipientKey
+{
+    /// The hex BLAKE3 hash of the compressed key, the same whichever form the key was given in.
+    #[must_use]
+    pub fn key_id(&self) -> String
+    {
+        blake3::hash(
+            self.0
+                .to_encoded_point(true)
+                .as_bytes(),
+        )
+        .to_hex()
+        .to_string()
+    }
+
+    /// Encrypts `plaintext` to this key, see the [module](self) for the format.
+    pub fn encrypt(
+        &self,
+        plaintext: &[u8],
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<
+        (
+            Vec<u8>,
+            ProofEncryption,
+        ),
+        EncryptionError,
+    >
+    {
+        let ephemeral = EphemeralSecret::random(&mut *rng);
+        let ephemeral_key = ephemeral
+            .public_key()
+            .to_encoded_point(true);
+        let cipher = cipher(
+            &ephemeral.diffie_hellman(&self.0),
+            ephemeral_key.as_bytes(),
+        );
+        let mut nonce = [0; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+        let sealed = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                plaintext,
+            )
+            .map_err(|_| EncryptionError::Encryption)?;
+
+        let mut ciphertext = Vec::with_capacity(EPHEMERAL_KEY_LEN + NONCE_LEN + sealed.len());
+        ciphertext.extend_from_slice(ephemeral_key.as_bytes());
+        ciphertext.extend_from_slice(&nonce);
+        ciphertext.extend_from_slice(&sealed);
+        Ok(
+            (
+                ciphertext,
+                ProofEncryption {
+                    scheme: SCHEME.to_string(),
+                    key_id: self.key_id(),
+                },
+            ),
+        )
+    }
+}
+
+/// Decrypts a proof encrypted to the public key of `secret_key`, the 32 bytes of the scalar.
+pub fn decrypt(
+    secret_key: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, EncryptionError>
+{
+    let secret_key =
+        SecretKey::from_slice(secret_key).map_err(|_| EncryptionError::InvalidSecretKey)?;
+    if ciphertext.len() < EPHEMERAL_KEY_LEN + NONCE_LEN + TAG_LEN
+    {
+        return Err(EncryptionError::Decryption);
+    }
+    let (ephemeral_key, rest) = ciphertext.split_at(EPHEMERAL_KEY_LEN);
+    let (nonce, sealed) = rest.split_at(NONCE_LEN);
+    let ephemeral =
+        PublicKey::from_sec1_bytes(ephemeral_key).map_err(|_| EncryptionError::Decryption)?;
+    let shared = k256::ecdh::diffie_hellman(
+        secret_key.to_nonzero_scalar(),
+        ephemeral.as_affine(),
+    );
+    cipher(
+        &shared,
+        ephemeral_key,
+    )
+    .decrypt(
+        Nonce::from_slice(nonce),
+        sealed,
+    )
+    .map_err(|_| EncryptionError::Decryption)
+}
+
+fn cipher(
+    shared: &SharedSecret,
+    ephemeral_key: &[u8],
+) -> Aes256Gcm
+{
+    let mut key = [0; 32];
+    shared
+        .extract::<Sha256>(Some(ephemeral_key))
+        .expand(
+            SCHEME.as_bytes(),
+            &mut key,
+        )
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    Aes256Gcm::new(&key.into())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn only_the_recipient_decrypts_the_proof()
+    {
+        let mut rng = StdRng::seed_from_u64(7);
+        let secret = SecretKey::random(&mut rng);
+        let recipient = hex::encode(
+            secret
+                .public_key()
+                .to_encoded_point(false)
+                .as_bytes(),
+        )
+        .parse::<RecipientKey>()
+        .unwrap();
+
+        let (ciphertext, encryption) = recipient
+            .encrypt(
+                b"proof",
+                &mut rng,
+            )
+            .unwrap();
+        assert_eq!(
+            encryption.key_id,
+            format!(
+                "0x{}",
+                hex::encode(
+                    secret
+                        .public_key()
+                        .to_encoded_point(true)
+                        .as_bytes()
+                )
+            )
+            .parse::<RecipientKey>()
+            .unwrap()
+            .key_id()
+        );
+        assert_eq!(
+            decrypt(
+                &secret.to_bytes(),
+                &ciphertext
+            )
+            .unwrap(),
+            b"proof"
+        );
+
+        let other = SecretKey::random(&mut rng);
+        assert_eq!(
+            decrypt(
+                &other.to_bytes(),
+                &ciphertext
+            ),
+            Err(EncryptionError::Decryption)
+        );
+        assert!(
+            "02ab"
+                .parse::<RecipientKey>()
+                .is_err()
+        );
+    }
+}
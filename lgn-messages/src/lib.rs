@@ -1,4 +1,7 @@
+pub mod canonical;
+pub mod encryption;
 pub mod routing;
+pub mod streaming_hash;
 pub mod types;
 
 pub type BlockNr = u64;
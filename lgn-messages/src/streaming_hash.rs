@@ -0,0 +1,239 @@
+//! The digests of the large fields of the tasks, e.g. the inline proofs, hashed while they are
+//! deserialized rather than from a copy afterwards.
+//!
+//! The fields deserialized with [`bytes`] feed their bytes to a BLAKE3 hasher as the deserializer
+//! yields them, a chunk at a time, the hasher keeping a constant state whatever their size. Within
+//! [`collect`], the digest of each of them is recorded in the order they are deserialized, for the
+//! caller to dedup or cache them by digest; outside of it, they are not hashed at all.
+
+use std::cell::RefCell;
+use std::fmt::Formatter;
+
+use serde::de::SeqAccess;
+use serde::de::Visitor;
+use serde::Deserializer;
+
+/// The bytes hashed at once, a chunk staying in the cache of the core filling it.
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// The bytes reserved up front at most, whatever length the input claims.
+const MAX_RESERVED_BYTES: usize = 1024 * 1024;
+
+thread_local! {
+    static DIGESTS: RefCell<Option<Vec<FieldDigest>>> = const { RefCell::new(None) };
+}
+
+/// The digest of a field deserialized with [`bytes`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldDigest
+{
+    /// The BLAKE3 hash of the bytes.
+    pub hash: [u8; 32],
+
+    /// The number of bytes.
+    pub len: usize,
+}
+
+/// Runs `deserialize`, returning the digests of the fields it deserialized with [`bytes`].
+pub fn collect<T>(
+    deserialize: impl FnOnce() -> T
+) -> (
+    T,
+    Vec<FieldDigest>,
+)
+{
+    let outer = DIGESTS.with(|digests| digests.replace(Some(vec![])));
+    let value = deserialize();
+    let digests = DIGESTS
+        .with(|digests| digests.replace(outer))
+        .unwrap_or_default();
+    (
+        value,
+        digests,
+    )
+}
+
+/// Deserializes bytes, hashing them on the way within [`collect`], for
+/// `#[serde(deserialize_with = "crate::streaming_hash::bytes")]`.
+pub fn bytes<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_seq(HashingVisitor)
+}
+
+struct HashingVisitor;
+
+impl<'de> Visitor<'de> for HashingVisitor
+{
+    type Value = Vec<u8>;
+
+    fn expecting(
+        &self,
+        formatter: &mut Formatter,
+    ) -> std::fmt::Result
+    {
+        formatter.write_str("a sequence of bytes")
+    }
+
+    fn visit_seq<A>(
+        self,
+        mut seq: A,
+    ) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut bytes = Vec::with_capacity(
+            seq.size_hint()
+                .unwrap_or_default()
+                .min(MAX_RESERVED_BYTES),
+        );
+        let mut hasher = collecting().then(blake3::Hasher::new);
+        let mut hashed = 0;
+        while let Some(byte) = seq.next_element::<u8>()?
+        {
+            bytes.push(byte);
+            if let Some(hasher) = &mut hasher
+            {
+                if bytes.len() - hashed == CHUNK_BYTES
+                {
+                    hasher.update(&bytes[hashed..]);
+                    hashed = bytes.len();
+                }
+            }
+        }
+        if let Some(mut hasher) = hasher
+        {
+            hasher.update(&bytes[hashed..]);
+            record(
+                hasher,
+                bytes.len(),
+            );
+        }
+        Ok(bytes)
+    }
+
+    fn visit_bytes<E>(
+        self,
+        bytes: &[u8],
+    ) -> Result<Self::Value, E>
+    {
+        self.visit_byte_buf(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E>(
+        self,
+        bytes: Vec<u8>,
+    ) -> Result<Self::Value, E>
+    {
+        if collecting()
+        {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(&bytes);
+            record(
+                hasher,
+                bytes.len(),
+            );
+        }
+        Ok(bytes)
+    }
+}
+
+fn collecting() -> bool
+{
+    DIGESTS.with(
+        |digests| {
+            digests
+                .borrow()
+                .is_some()
+        },
+    )
+}
+
+fn record(
+    hasher: blake3::Hasher,
+    len: usize,
+)
+{
+    DIGESTS.with(
+        |digests| {
+            if let Some(digests) = digests
+                .borrow_mut()
+                .as_mut()
+            {
+                digests.push(
+                    FieldDigest {
+                        hash: *hasher
+                            .finalize()
+                            .as_bytes(),
+                        len,
+                    },
+                );
+            }
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests
+{
+    use serde_derive::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Task
+    {
+        #[serde(deserialize_with = "bytes")]
+        proof: Vec<u8>,
+        #[serde(deserialize_with = "bytes")]
+        other_proof: Vec<u8>,
+    }
+
+    #[test]
+    fn hashes_the_fields_while_deserializing_them()
+    {
+        let proof = (0..3 * CHUNK_BYTES / 2)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        let json = serde_json::json!({ "proof": proof, "other_proof": [1, 2, 3] }).to_string();
+
+        let (task, digests) = collect(|| serde_json::from_str::<Task>(&json).unwrap());
+        assert_eq!(
+            task.proof,
+            proof
+        );
+        assert_eq!(
+            digests,
+            [
+                FieldDigest {
+                    hash: *blake3::hash(&proof).as_bytes(),
+                    len: proof.len(),
+                },
+                FieldDigest {
+                    hash: *blake3::hash(
+                        &[
+                            1,
+                            2,
+                            3
+                        ]
+                    )
+                    .as_bytes(),
+                    len: 3,
+                },
+            ]
+        );
+
+        // Outside of `collect`, nothing is hashed nor kept.
+        let task = serde_json::from_str::<Task>(&json).unwrap();
+        assert_eq!(
+            task.other_proof,
+            [
+                1,
+                2,
+                3
+            ]
+        );
+        assert!(!collecting());
+    }
+}
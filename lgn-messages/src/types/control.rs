@@ -0,0 +1,172 @@
+//! The control messages of the gateway, sent on the task stream in place of a task.
+//!
+//! The gRPC schema only carries task documents and their replies, so a control message is a small
+//! JSON document such as `{"control": "Capabilities"}`, answered in place of a reply with a
+//! [`ControlReply`], e.g. `{"Capabilities": {...}}`. A worker predating a control message drops
+//! it as a malformed task.
+
+use std::collections::BTreeMap;
+
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+/// The control messages are small, a larger document is a task.
+pub const MAX_CONTROL_MESSAGE_BYTES: usize = 1024;
+
+/// A control message of the gateway.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ControlMessage
+{
+    pub control: ControlRequest,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ControlRequest
+{
+    /// What the worker can prove, to route the tasks to it.
+    Capabilities,
+
+    /// The journal entries of the tasks of the gateway after `seq`, to reconcile the replies it
+    /// missed, e.g. `{"control": {"GetJournalSince": {"seq": 0}}}` for all of them.
+    GetJournalSince
+    {
+        seq: u64,
+    },
+}
+
+/// The answer of the worker to a [`ControlMessage`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ControlReply
+{
+    Capabilities(Capabilities),
+    Journal(JournalPage),
+}
+
+/// The entries of the journal of a worker after a sequence number, oldest first.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct JournalPage
+{
+    /// When the worker started, as a UNIX timestamp. The sequence numbers start over with it, a
+    /// gateway seeing it change reconciles from `0`.
+    pub epoch: u64,
+
+    /// The oldest sequence number still in the journal, the entries before it having expired.
+    pub first_seq: u64,
+
+    /// The latest sequence number, of any gateway.
+    pub last_seq: u64,
+
+    /// The entries of the gateway, at most [`MAX_JOURNAL_PAGE`]: with as many, the gateway asks
+    /// again after the last one.
+    pub entries: Vec<JournalEntry>,
+}
+
+/// The entries of a [`JournalPage`] at most, for the page to fit in a gRPC message.
+pub const MAX_JOURNAL_PAGE: usize = 1000;
+
+/// A reply sent by the worker.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct JournalEntry
+{
+    /// The sequence number of the reply, increasing over the replies to all the gateways.
+    pub seq: u64,
+
+    pub task_id: String,
+
+    pub status: JournalStatus,
+
+    /// The hex BLAKE3 digest of the reply document as sent, or of the error message.
+    pub digest: String,
+
+    /// When the reply was sent, as a UNIX timestamp.
+    pub at_unix: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum JournalStatus
+{
+    /// The task was proven, the reply carrying its proof.
+    Completed,
+
+    /// The task failed, the reply carrying the error.
+    Failed,
+}
+
+/// The tasks the worker supports, as of the release and the configuration it runs.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Capabilities
+{
+    /// The release of the worker.
+    pub version: String,
+
+    /// The class the worker runs as, after a downgrade if any.
+    pub worker_class: String,
+
+    /// The task variants the worker parses, see [`TaskType::VARIANTS`].
+    ///
+    /// [`TaskType::VARIANTS`]: crate::types::TaskType::VARIANTS
+    pub task_types: Vec<String>,
+
+    /// The prover types registered, i.e. the task variants the worker proves.
+    pub prover_types: Vec<String>,
+
+    /// The version of the envelope, task and reply schema, see [`SCHEMA_VERSION`].
+    ///
+    /// [`SCHEMA_VERSION`]: crate::types::SCHEMA_VERSION
+    pub schema_version: u32,
+
+    /// The constants of the query circuits, empty if the worker does not prove queries.
+    pub circuit_constants: BTreeMap<String, usize>,
+
+    /// The optional behaviours compiled in or enabled, e.g. `prover-query` or `signed-replies`.
+    pub features: Vec<String>,
+
+    /// The threads the worker proves with, e.g. fewer than its cores in eco mode.
+    #[serde(default)]
+    pub proving_threads: usize,
+
+    /// The percentage of its cores the worker proves with in eco mode, trading latency for lower
+    /// CPU bursts, for the gateway to expect slower proofs; `None` outside of eco mode.
+    #[serde(default)]
+    pub eco_mode_percent: Option<u8>,
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn control_messages_are_told_from_tasks()
+    {
+        assert_eq!(
+            serde_json::from_str::<ControlMessage>(r#"{"control": "Capabilities"}"#).unwrap(),
+            ControlMessage {
+                control: ControlRequest::Capabilities,
+            }
+        );
+        assert!(
+            serde_json::from_str::<ControlMessage>(
+                r#"{"control": "Capabilities", "task_id": "t1", "inner": {}}"#
+            )
+            .is_err()
+        );
+        assert!(serde_json::from_str::<ControlMessage>(r#"{"control": "Shutdown"}"#).is_err());
+        assert_eq!(
+            serde_json::from_str::<ControlMessage>(
+                r#"{"control": {"GetJournalSince": {"seq": 42}}}"#
+            )
+            .unwrap(),
+            ControlMessage {
+                control: ControlRequest::GetJournalSince {
+                    seq: 42,
+                },
+            }
+        );
+
+        let reply =
+            serde_json::to_value(ControlReply::Capabilities(Capabilities::default())).unwrap();
+        assert!(reply["Capabilities"]["schema_version"].is_number());
+    }
+}
@@ -0,0 +1,247 @@
+//! The catalog of the worker errors.
+//!
+//! Each error reported by a worker, in a reply, a metric or an exit reason, carries one of these
+//! codes. The codes are stable, a code is never reused for another error, so that support can map
+//! a code to its runbook entry whatever the version of the worker.
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+/// The worker errors, grouped by thousands: admission, params, proving, transport and the worker
+/// itself.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[repr(u16)]
+pub enum ErrorCode
+{
+    /// The task deadline passed before the worker could start proving it.
+    DeadlineExceeded = 1001,
+    /// The reply to the task is expected to be over the message limit of the transport.
+    ReplyTooLarge = 1002,
+    /// The prover of the task failed too many times in a row and is in cooldown.
+    Quarantined = 1003,
+    /// The params failed their audit, the worker refuses tasks until restarted.
+    ParamsCorrupted = 1004,
+    /// The gateway sent a task the worker could not parse.
+    MalformedTask = 1005,
+    /// The gateway sent a task of a version the worker does not support, e.g. a v0 task.
+    UnsupportedTask = 1006,
+    /// The task was sent longer ago than the replay window of the worker.
+    StaleTask = 1007,
+    /// The task was already received within the replay window, the message was replayed.
+    ReplayedTask = 1008,
+    /// The query of the task used its share of the worker, the task is to be retried later.
+    QueryThrottled = 1009,
+    /// The worker is in its maintenance window, the task is to be sent to another worker.
+    Maintenance = 1010,
+    /// The replay window tracks as many messages as it may, the task is to be sent again later.
+    ReplayWindowFull = 1011,
+
+    /// An artifact derived from the params could not be computed.
+    ParamsArtifact = 2001,
+    /// The params audit could not complete.
+    ParamsAudit = 2002,
+    /// The params were built for circuits of another shape than the compiled ones.
+    ParamsShape = 2003,
+    /// The params could not be downloaded within the download deadline.
+    ParamsDownloadDeadline = 2004,
+
+    /// The prover failed on the task.
+    ProofProcessing = 3001,
+    /// The prover panicked on the task.
+    ProverPanic = 3002,
+    /// A query task does not match the schema of its table.
+    QuerySchema = 3003,
+    /// A task of the offline directory failed.
+    OfflineTask = 3004,
+    /// The reply could not be signed.
+    ReplySigning = 3005,
+    /// A query task does not fit the pruned query params of the worker.
+    QueryPruned = 3006,
+
+    /// The gateway sent an ACK outside of the authentication.
+    UnexpectedAck = 4001,
+    /// The gateway sent a frame of an unexpected type.
+    UnexpectedFrame = 4002,
+    /// The worker could not register with the gateway.
+    Registration = 4003,
+    /// The connection to the gateway failed.
+    GatewayConnection = 4004,
+
+    /// The compaction of the retained data failed.
+    Compaction = 5001,
+    /// The counters could not be persisted.
+    MetricsStore = 5002,
+    /// The CPU lacks a feature the worker was built for.
+    IncompatibleCpu = 5003,
+    /// A subsystem of the worker stopped.
+    SubsystemStopped = 5004,
+    /// A subsystem of the worker panicked.
+    SubsystemPanic = 5005,
+
+    /// An error not in the catalog yet.
+    General = 9000,
+}
+
+impl ErrorCode
+{
+    /// Every code of the catalog.
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::DeadlineExceeded,
+        ErrorCode::ReplyTooLarge,
+        ErrorCode::Quarantined,
+        ErrorCode::ParamsCorrupted,
+        ErrorCode::MalformedTask,
+        ErrorCode::UnsupportedTask,
+        ErrorCode::StaleTask,
+        ErrorCode::ReplayedTask,
+        ErrorCode::QueryThrottled,
+        ErrorCode::Maintenance,
+        ErrorCode::ReplayWindowFull,
+        ErrorCode::ParamsArtifact,
+        ErrorCode::ParamsAudit,
+        ErrorCode::ParamsShape,
+        ErrorCode::ParamsDownloadDeadline,
+        ErrorCode::ProofProcessing,
+        ErrorCode::ProverPanic,
+        ErrorCode::QuerySchema,
+        ErrorCode::OfflineTask,
+        ErrorCode::ReplySigning,
+        ErrorCode::QueryPruned,
+        ErrorCode::UnexpectedAck,
+        ErrorCode::UnexpectedFrame,
+        ErrorCode::Registration,
+        ErrorCode::GatewayConnection,
+        ErrorCode::Compaction,
+        ErrorCode::MetricsStore,
+        ErrorCode::IncompatibleCpu,
+        ErrorCode::SubsystemStopped,
+        ErrorCode::SubsystemPanic,
+        ErrorCode::General,
+    ];
+
+    /// The stable numeric code.
+    #[must_use]
+    pub fn number(self) -> u16
+    {
+        self as u16
+    }
+
+    /// The `error_type` label of the error metrics.
+    ///
+    /// The labels predate the catalog and are kept as they were so that the dashboards still work.
+    #[must_use]
+    pub fn label(self) -> &'static str
+    {
+        match self
+        {
+            ErrorCode::DeadlineExceeded => "deadline_exceeded",
+            ErrorCode::ReplyTooLarge => "reply_too_large",
+            ErrorCode::Quarantined => "quarantined",
+            ErrorCode::ParamsCorrupted => "params_corrupted",
+            ErrorCode::MalformedTask => "malformed_task",
+            ErrorCode::UnsupportedTask => "unsupported_task",
+            ErrorCode::StaleTask => "stale_task",
+            ErrorCode::ReplayedTask => "replayed_task",
+            ErrorCode::QueryThrottled => "query_throttled",
+            ErrorCode::Maintenance => "maintenance",
+            ErrorCode::ReplayWindowFull => "replay_window_full",
+            ErrorCode::ParamsArtifact => "params",
+            ErrorCode::ParamsAudit => "params_audit",
+            ErrorCode::ParamsShape => "params_shape",
+            ErrorCode::ParamsDownloadDeadline => "params_download_deadline",
+            ErrorCode::ProofProcessing => "proof processing",
+            ErrorCode::ProverPanic => "proof_processing",
+            ErrorCode::QuerySchema => "query_schema",
+            ErrorCode::OfflineTask => "offline_task",
+            ErrorCode::ReplySigning => "reply_signing",
+            ErrorCode::QueryPruned => "query_pruned",
+            ErrorCode::UnexpectedAck => "unexpected_ack",
+            ErrorCode::UnexpectedFrame => "unexpected_frame",
+            ErrorCode::Registration => "registration",
+            ErrorCode::GatewayConnection => "gateway_connection",
+            ErrorCode::Compaction => "compaction",
+            ErrorCode::MetricsStore => "metrics_store",
+            ErrorCode::IncompatibleCpu => "incompatible_cpu",
+            ErrorCode::SubsystemStopped => "subsystem_stopped",
+            ErrorCode::SubsystemPanic => "subsystem_panic",
+            ErrorCode::General => "general",
+        }
+    }
+
+    /// The code from its number, if in the catalog.
+    #[must_use]
+    pub fn from_number(number: u16) -> Option<Self>
+    {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|code| code.number() == number)
+    }
+
+    /// Appends the code to an error message sent to the gateway.
+    ///
+    /// The code goes last, the gateway matching on the start of some messages.
+    #[must_use]
+    pub fn annotate(
+        self,
+        message: impl Display,
+    ) -> String
+    {
+        format!("{message} [{self}]")
+    }
+}
+
+impl Display for ErrorCode
+{
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result
+    {
+        write!(
+            f,
+            "E{}",
+            self.number()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn codes_are_unique()
+    {
+        let numbers = ErrorCode::ALL
+            .iter()
+            .map(|code| code.number())
+            .collect::<HashSet<_>>();
+        assert_eq!(
+            numbers.len(),
+            ErrorCode::ALL.len()
+        );
+        for code in ErrorCode::ALL
+        {
+            assert_eq!(
+                ErrorCode::from_number(code.number()),
+                Some(*code)
+            );
+        }
+    }
+
+    #[test]
+    fn annotate_keeps_the_message_prefix()
+    {
+        assert_eq!(
+            ErrorCode::DeadlineExceeded.annotate("DeadlineExceeded: late"),
+            "DeadlineExceeded: late [E1001]"
+        );
+    }
+}
@@ -2,15 +2,31 @@ use std::fmt::Display;
 use std::fmt::Formatter;
 
 use derive_debug_plus::Dbg;
+use k256::elliptic_curve::rand_core::CryptoRngCore;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 use thiserror::Error;
 
+use crate::encryption::EncryptionError;
+use crate::encryption::ProofEncryption;
+use crate::encryption::RecipientKey;
 use crate::routing::RoutingKey;
+use crate::types::error_code::ErrorCode;
+use crate::types::reply_key::ReplyKey;
 
+pub mod control;
+pub mod error_code;
 pub mod experimental;
+pub mod reply_key;
+pub mod shard;
 pub mod v1;
 
+/// The version of the envelope, task and reply schema, raised when a gateway must tell the
+/// workers apart to route them tasks, e.g. for a new field they must honour.
+///
+/// - `2`: the proofs are encrypted to the `recipient_pubkey` of the envelope.
+pub const SCHEMA_VERSION: u32 = 2;
+
 const REQUIRED_STAKE_SMALL_USD: Stake = 98777;
 const REQUIRED_STAKE_MEDIUM_USD: Stake = 98777;
 const REQUIRED_STAKE_LARGE_USD: Stake = 169111;
@@ -28,6 +44,7 @@ pub trait ToKeyedPayload
 
 pub type HashOutput = [u8; 32];
 
+#[cfg(feature = "tasks")]
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum TaskType
 {
@@ -38,6 +55,20 @@ pub enum TaskType
     V1Groth16(v1::groth16::WorkerTask),
 }
 
+#[cfg(feature = "tasks")]
+impl TaskType
+{
+    /// The tags of the task variants in the envelopes, a task tagged otherwise being of a version
+    /// this release does not support, e.g. a v0 task.
+    pub const VARIANTS: &'static [&'static str] = &[
+        "TxTrie",
+        "RecProof",
+        "V1Preprocessing",
+        "V1Query",
+        "V1Groth16",
+    ];
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum ReplyType
 {
@@ -48,6 +79,81 @@ pub enum ReplyType
     V1Groth16(WorkerReply),
 }
 
+impl ReplyType
+{
+    /// Returns the proof bytes carried by this reply, if any.
+    pub fn proof(&self) -> Option<&[u8]>
+    {
+        match self
+        {
+            ReplyType::V1Preprocessing(reply)
+            | ReplyType::V1Query(reply)
+            | ReplyType::V1Groth16(reply) =>
+            {
+                reply
+                    .proof
+                    .as_ref()
+                    .map(|(_, proof)| proof.as_slice())
+            },
+            ReplyType::TxTrie(_) | ReplyType::RecProof(_) => None,
+        }
+    }
+
+    /// Keys the proof of this reply by its content, see [`WorkerReply::address_by_content`].
+    pub fn address_by_content(&mut self)
+    {
+        match self
+        {
+            ReplyType::V1Preprocessing(reply)
+            | ReplyType::V1Query(reply)
+            | ReplyType::V1Groth16(reply) => reply.address_by_content(),
+            ReplyType::TxTrie(_) | ReplyType::RecProof(_) =>
+            {},
+        }
+    }
+
+    /// Encrypts the proof of this reply to `recipient`, see [`WorkerReply::encrypt_proof`].
+    ///
+    /// The experimental replies carry no proof and are left as they are.
+    pub fn encrypt_proof(
+        &mut self,
+        recipient: &RecipientKey,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<(), EncryptionError>
+    {
+        match self
+        {
+            ReplyType::V1Preprocessing(reply)
+            | ReplyType::V1Query(reply)
+            | ReplyType::V1Groth16(reply) =>
+            {
+                reply.encrypt_proof(
+                    recipient,
+                    rng,
+                )
+            },
+            ReplyType::TxTrie(_) | ReplyType::RecProof(_) => Ok(()),
+        }
+    }
+
+    /// How the proof of this reply is encrypted, if it is.
+    pub fn encryption(&self) -> Option<&ProofEncryption>
+    {
+        match self
+        {
+            ReplyType::V1Preprocessing(reply)
+            | ReplyType::V1Query(reply)
+            | ReplyType::V1Groth16(reply) =>
+            {
+                reply
+                    .encryption
+                    .as_ref()
+            },
+            ReplyType::TxTrie(_) | ReplyType::RecProof(_) => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct MessageEnvelope<T>
 {
@@ -75,6 +181,28 @@ pub struct MessageEnvelope<T>
 
     /// Details of the task to be executed.
     pub inner: T,
+
+    /// Unix timestamp, in seconds, after which the gateway no longer needs the result.
+    #[serde(default)]
+    pub deadline_unix: Option<u64>,
+
+    /// Identifier correlating the task across systems, echoed back in the reply.
+    #[serde(default)]
+    pub trace_id: Option<String>,
+
+    /// The customer the task is proven for, to attribute the cost of shared workers.
+    #[serde(default)]
+    pub tenant: Option<String>,
+
+    /// Unix timestamp, in seconds, at which the gateway sent the task, for the workers to refuse
+    /// the stale and replayed messages.
+    #[serde(default)]
+    pub issued_at_unix: Option<u64>,
+
+    /// The secp256k1 public key, in hex SEC1 form, of the customer the proof is encrypted to, for
+    /// only them to read it, see [`crate::encryption`].
+    #[serde(default)]
+    pub recipient_pubkey: Option<String>,
 }
 
 impl<T> MessageEnvelope<T>
@@ -94,9 +222,64 @@ impl<T> MessageEnvelope<T>
             routing_key,
             task_id,
             db_task_id: None,
+            deadline_unix: None,
+            trace_id: None,
+            tenant: None,
+            issued_at_unix: None,
+            recipient_pubkey: None,
         }
     }
 
+    #[must_use]
+    pub fn with_tenant(
+        mut self,
+        tenant: String,
+    ) -> Self
+    {
+        self.tenant = Some(tenant);
+        self
+    }
+
+    #[must_use]
+    pub fn with_trace_id(
+        mut self,
+        trace_id: String,
+    ) -> Self
+    {
+        self.trace_id = Some(trace_id);
+        self
+    }
+
+    #[must_use]
+    pub fn with_recipient_pubkey(
+        mut self,
+        recipient_pubkey: String,
+    ) -> Self
+    {
+        self.recipient_pubkey = Some(recipient_pubkey);
+        self
+    }
+
+    #[must_use]
+    pub fn with_deadline(
+        mut self,
+        deadline_unix: u64,
+    ) -> Self
+    {
+        self.deadline_unix = Some(deadline_unix);
+        self
+    }
+
+    /// Returns true if the task has a deadline and it has passed at `now_unix`.
+    pub fn deadline_exceeded(
+        &self,
+        now_unix: u64,
+    ) -> bool
+    {
+        self.deadline_unix
+            .is_some_and(|deadline| now_unix > deadline)
+    }
+
     pub fn query_id(&self) -> &str
     {
         &self.query_id
@@ -126,6 +309,16 @@ impl<T> MessageEnvelope<T>
     }
 }
 
+impl<T: serde::Serialize> MessageEnvelope<T>
+{
+    /// The canonical JSON of the envelope, the bytes to hash or sign it by, see
+    /// [`crate::canonical`].
+    pub fn canonical_bytes(&self) -> serde_json::Result<Vec<u8>>
+    {
+        crate::canonical::to_vec(self)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct MessageReplyEnvelope<T>
 {
@@ -138,6 +331,99 @@ pub struct MessageReplyEnvelope<T>
     inner: T,
 
     error: Option<WorkerError>,
+
+    /// Operator signature attesting that this worker produced the reply.
+    #[serde(default)]
+    audit: Option<ReplyAudit>,
+
+    /// The trace id of the task this reply answers.
+    #[serde(default)]
+    trace_id: Option<String>,
+
+    /// How many times the worker proved the task, transient failures being retried.
+    #[serde(default)]
+    attempts: Option<u32>,
+
+    /// Where the worker spent the time of the task before sending this reply.
+    #[serde(default)]
+    timings: Option<TaskTimings>,
+}
+
+/// Non-repudiation data attached to a reply by the worker.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ReplyAudit
+{
+    /// Checksum of the public parameters the proof was generated with.
+    pub params_checksum: String,
+
+    /// Hex-encoded signature by the operator Lagrange key over the digest of the task id, the
+    /// proof bytes and `params_checksum`.
+    pub signature: String,
+}
+
+/// The time a task spent in each stage of the worker, in milliseconds, a stage the task did not
+/// go through, or not yet, being `None`.
+///
+/// A reply carries the stages before its own serialization, the worker exporting the others.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TaskTimings
+{
+    /// Parsing the task document.
+    pub parse_ms: Option<u64>,
+
+    /// Checking whether the task can be proven and replied to.
+    pub admission_ms: Option<u64>,
+
+    /// Fetching the inputs the task references but does not carry.
+    pub hydration_ms: Option<u64>,
+
+    /// Proving the task, including the retries.
+    pub prove_ms: Option<u64>,
+
+    /// Serializing the reply.
+    pub serialize_ms: Option<u64>,
+
+    /// Sending the reply to the gateway.
+    pub send_ms: Option<u64>,
+}
+
+impl TaskTimings
+{
+    /// The stages by name, with their duration in milliseconds.
+    pub fn stages(
+        &self
+    ) -> [(
+        &'static str,
+        Option<u64>,
+    ); 6]
+    {
+        [
+            (
+                "parse",
+                self.parse_ms,
+            ),
+            (
+                "admission",
+                self.admission_ms,
+            ),
+            (
+                "hydration",
+                self.hydration_ms,
+            ),
+            (
+                "prove",
+                self.prove_ms,
+            ),
+            (
+                "serialize",
+                self.serialize_ms,
+            ),
+            (
+                "send",
+                self.send_ms,
+            ),
+        ]
+    }
 }
 
 impl<T> MessageReplyEnvelope<T>
@@ -153,6 +439,10 @@ impl<T> MessageReplyEnvelope<T>
             task_id,
             inner,
             error: None,
+            audit: None,
+            trace_id: None,
+            attempts: None,
+            timings: None,
         }
     }
 
@@ -183,6 +473,74 @@ impl<T> MessageReplyEnvelope<T>
         &self.inner
     }
 
+    pub fn content_mut(&mut self) -> &mut T
+    {
+        &mut self.inner
+    }
+
+    /// Return the operator audit signature, if the worker attached one.
+    pub fn audit(&self) -> Option<&ReplyAudit>
+    {
+        self.audit
+            .as_ref()
+    }
+
+    /// Attach an operator audit signature to this reply.
+    pub fn set_audit(
+        &mut self,
+        audit: ReplyAudit,
+    )
+    {
+        self.audit = Some(audit);
+    }
+
+    /// Return the trace id of the task this reply answers.
+    pub fn trace_id(&self) -> Option<&str>
+    {
+        self.trace_id
+            .as_deref()
+    }
+
+    /// Set the trace id of the task this reply answers.
+    pub fn set_trace_id(
+        &mut self,
+        trace_id: Option<String>,
+    )
+    {
+        self.trace_id = trace_id;
+    }
+
+    /// Return how many times the worker proved the task, if reported.
+    pub fn attempts(&self) -> Option<u32>
+    {
+        self.attempts
+    }
+
+    /// Set how many times the worker proved the task.
+    pub fn set_attempts(
+        &mut self,
+        attempts: u32,
+    )
+    {
+        self.attempts = Some(attempts);
+    }
+
+    /// Return where the worker spent the time of the task, if reported.
+    pub fn timings(&self) -> Option<&TaskTimings>
+    {
+        self.timings
+            .as_ref()
+    }
+
+    /// Set where the worker spent the time of the task.
+    pub fn set_timings(
+        &mut self,
+        timings: TaskTimings,
+    )
+    {
+        self.timings = Some(timings);
+    }
+
     pub fn query_id(&self) -> &str
     {
         &self.query_id
@@ -194,6 +552,16 @@ impl<T> MessageReplyEnvelope<T>
     }
 }
 
+impl<T: serde::Serialize> MessageReplyEnvelope<T>
+{
+    /// The canonical JSON of the reply, the bytes to hash or sign it by, see
+    /// [`crate::canonical`].
+    pub fn canonical_bytes(&self) -> serde_json::Result<Vec<u8>>
+    {
+        crate::canonical::to_vec(self)
+    }
+}
+
 #[derive(Copy, Clone, Dbg, PartialEq, Eq, Deserialize, Serialize)]
 pub enum ProofCategory
 {
@@ -210,6 +578,20 @@ pub struct WorkerReply
     pub proof: Option<KeyedPayload>,
 
     pub proof_type: ProofCategory,
+
+    /// The semantic key of the proof, set when the proof is keyed by its content.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub semantic_key: Option<String>,
+
+    /// How the proof is encrypted, set when it is encrypted to the customer requesting it.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub encryption: Option<ProofEncryption>,
 }
 
 impl WorkerReply
@@ -225,8 +607,69 @@ impl WorkerReply
             chain_id,
             proof,
             proof_type,
+            semantic_key: None,
+            encryption: None,
+        }
+    }
+
+    /// Replaces the proof bytes by their encryption to `recipient`, keeping the proof key.
+    pub fn encrypt_proof(
+        &mut self,
+        recipient: &RecipientKey,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<(), EncryptionError>
+    {
+        if self
+            .encryption
+            .is_some()
+        {
+            return Ok(());
+        }
+        if let Some((_, proof)) = &mut self.proof
+        {
+            let (ciphertext, encryption) = recipient.encrypt(
+                proof,
+                rng,
+            )?;
+            *proof = ciphertext;
+            self.encryption = Some(encryption);
+        }
+        Ok(())
+    }
+
+    /// Keys the proof by the blake3 hash of its bytes, keeping its semantic key as metadata.
+    pub fn address_by_content(&mut self)
+    {
+        if self
+            .semantic_key
+            .is_some()
+        {
+            return;
+        }
+        if let Some((key, proof)) = &mut self.proof
+        {
+            let content_key = ReplyKey::of_proof(proof).to_string();
+            self.semantic_key = Some(
+                std::mem::replace(
+                    key,
+                    content_key,
+                ),
+            );
         }
     }
+
+    /// The key naming what the proof is about, whichever form the proof is keyed by.
+    #[must_use]
+    pub fn semantic_key(&self) -> Option<&str>
+    {
+        self.semantic_key
+            .as_deref()
+            .or(
+                self.proof
+                    .as_ref()
+                    .map(|(key, _)| key.as_str()),
+            )
+    }
 }
 
 #[derive(Error, Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
@@ -235,6 +678,100 @@ pub enum WorkerError
     // Start with general error to introduce the errors to replies
     #[error("{0}")]
     GeneralError(String),
+
+    /// The task deadline passed before the worker could start proving it.
+    #[error("DeadlineExceeded: the deadline {deadline_unix} of task {task_id} has passed")]
+    DeadlineExceeded
+    {
+        task_id: String,
+        deadline_unix: u64,
+    },
+
+    /// The reply to the task would not fit in a message of the transport.
+    #[error(
+        "ReplyTooLarge: the reply to task {task_id} is expected to be {size} bytes, over the \
+         {max_message_size} bytes message limit"
+    )]
+    ReplyTooLarge
+    {
+        task_id: String,
+        size: usize,
+        max_message_size: usize,
+    },
+
+    /// The task was sent longer ago than the replay window of the worker.
+    #[error(
+        "StaleTask: task {task_id} was issued at {issued_at_unix}, outside of the {window_secs}s \
+         replay window"
+    )]
+    StaleTask
+    {
+        task_id: String,
+        issued_at_unix: u64,
+        window_secs: u64,
+    },
+
+    /// The task was already received within the replay window.
+    #[error("ReplayedTask: task {task_id} issued at {issued_at_unix} was already received")]
+    ReplayedTask
+    {
+        task_id: String,
+        issued_at_unix: u64,
+    },
+
+    /// The replay window tracks as many messages as it may, none of them expired yet.
+    #[error(
+        "ReplayWindowFull: task {task_id} was refused, the replay window already tracks \
+         {max_tracked} messages"
+    )]
+    ReplayWindowFull
+    {
+        task_id: String,
+        max_tracked: usize,
+    },
+
+    /// The query of the task used its share of the worker while other queries wait.
+    #[error(
+        "QueryThrottled: query {query_id} used its share of the worker, retry task {task_id} in \
+         {retry_after_secs}s"
+    )]
+    QueryThrottled
+    {
+        query_id: String,
+        task_id: String,
+        retry_after_secs: u64,
+    },
+}
+
+impl WorkerError
+{
+    /// The catalog code of the error.
+    #[must_use]
+    pub fn as_code(&self) -> ErrorCode
+    {
+        match self
+        {
+            WorkerError::GeneralError(_) => ErrorCode::General,
+            WorkerError::DeadlineExceeded {
+                ..
+            } => ErrorCode::DeadlineExceeded,
+            WorkerError::ReplyTooLarge {
+                ..
+            } => ErrorCode::ReplyTooLarge,
+            WorkerError::StaleTask {
+                ..
+            } => ErrorCode::StaleTask,
+            WorkerError::ReplayedTask {
+                ..
+            } => ErrorCode::ReplayedTask,
+            WorkerError::ReplayWindowFull {
+                ..
+            } => ErrorCode::ReplayWindowFull,
+            WorkerError::QueryThrottled {
+                ..
+            } => ErrorCode::QueryThrottled,
+        }
+    }
 }
 
 #[derive(
@@ -398,6 +935,19 @@ pub enum DownstreamPayload<T>
     {
         envelope: MessageEnvelope<T>,
     },
+    /// change the runtime settings of the worker
+    Control(ControlMessage),
+}
+
+/// Operational commands the gateway may send to a single worker.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ControlMessage
+{
+    /// Replace the log filter of the worker, e.g. `info,lgn_worker=debug`.
+    SetLogLevel
+    {
+        directives: String,
+    },
 }
 
 pub type Stake = u128;
@@ -507,14 +1057,18 @@ pub fn kp_pretty(kp: &Option<KeyedPayload>) -> String
 pub enum ProverType
 {
     /// V0 query preprocessing handler.
+    #[cfg(feature = "legacy-v0")]
     Query2Preprocess,
 
     /// V0 query handler.
+    #[cfg(feature = "legacy-v0")]
     Query2Query,
 
+    #[cfg(feature = "legacy-v0")]
     QueryErc20,
 
     /// V0 Groth16 handler.
+    #[cfg(feature = "legacy-v0")]
     Query2Groth16,
 
     V1Preprocessing,
@@ -536,9 +1090,13 @@ impl Display for ProverType
             "{}",
             match self
             {
+                #[cfg(feature = "legacy-v0")]
                 ProverType::Query2Preprocess => "Query2Preprocess",
+                #[cfg(feature = "legacy-v0")]
                 ProverType::Query2Query => "Query2Query",
+                #[cfg(feature = "legacy-v0")]
                 ProverType::Query2Groth16 => "Query2Groth16",
+                #[cfg(feature = "legacy-v0")]
                 ProverType::QueryErc20 => "QueryErc20",
                 ProverType::V1Preprocessing => "V1Preprocessing",
                 ProverType::V1Query => "V1Query",
@@ -553,6 +1111,7 @@ pub trait ToProverType
     fn to_prover_type(&self) -> ProverType;
 }
 
+#[cfg(feature = "tasks")]
 impl ToProverType for TaskType
 {
     fn to_prover_type(&self) -> ProverType
@@ -0,0 +1,170 @@
+//! The two forms of the keys of the reply proofs.
+//!
+//! A semantic key, e.g. `V1_PREPROCESSING/1/2/ROW/abc`, names what a proof is about, and proofs
+//! of distinct blocks or tables have ended up under the same key in the downstream stores. A
+//! content-addressed key, `blake3/<hex>`, names the proof bytes instead; the semantic key then
+//! travels as metadata of the reply, see [`WorkerReply::semantic_key`].
+//!
+//! [`WorkerReply::semantic_key`]: crate::types::WorkerReply::semantic_key
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use crate::types::v1::key_grammar::ProofKeyParseError;
+use crate::types::HashOutput;
+
+/// The prefix of the content-addressed keys.
+pub const CONTENT_ADDRESSED_PREFIX: &str = "blake3/";
+
+/// The key of a reply proof, in either form.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ReplyKey
+{
+    /// The blake3 hash of the proof bytes.
+    ContentAddressed(HashOutput),
+    /// A key naming what the proof is about, in the grammar of its task domain.
+    Semantic(String),
+}
+
+impl ReplyKey
+{
+    /// The content-addressed key of `proof`.
+    #[must_use]
+    pub fn of_proof(proof: &[u8]) -> Self
+    {
+        ReplyKey::ContentAddressed(*blake3::hash(proof).as_bytes())
+    }
+
+    /// Whether the key may name `proof`, i.e. a semantic key or the hash of `proof`.
+    #[must_use]
+    pub fn matches(
+        &self,
+        proof: &[u8],
+    ) -> bool
+    {
+        match self
+        {
+            ReplyKey::ContentAddressed(hash) => blake3::hash(proof).as_bytes() == hash,
+            ReplyKey::Semantic(_) => true,
+        }
+    }
+}
+
+impl Display for ReplyKey
+{
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result
+    {
+        match self
+        {
+            ReplyKey::ContentAddressed(hash) =>
+            {
+                write!(
+                    f,
+                    "{CONTENT_ADDRESSED_PREFIX}{}",
+                    blake3::Hash::from(*hash).to_hex()
+                )
+            },
+            ReplyKey::Semantic(key) => f.write_str(key),
+        }
+    }
+}
+
+impl FromStr for ReplyKey
+{
+    type Err = ProofKeyParseError;
+
+    /// Parses a key of either form, the content-addressed ones only in their canonical lowercase
+    /// spelling.
+    fn from_str(key: &str) -> Result<Self, Self::Err>
+    {
+        let Some(hex) = key.strip_prefix(CONTENT_ADDRESSED_PREFIX)
+        else
+        {
+            if key.is_empty()
+            {
+                return Err(
+                    ProofKeyParseError::new(
+                        key,
+                        "empty key",
+                    ),
+                );
+            }
+            return Ok(ReplyKey::Semantic(key.to_string()));
+        };
+
+        if hex.len() != 64
+            || !hex
+                .chars()
+                .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        {
+            return Err(
+                ProofKeyParseError::new(
+                    key,
+                    "expected 64 lowercase hex digits",
+                ),
+            );
+        }
+        let hash = blake3::Hash::from_hex(hex).map_err(
+            |e| {
+                ProofKeyParseError::new(
+                    key,
+                    e.to_string(),
+                )
+            },
+        )?;
+
+        Ok(ReplyKey::ContentAddressed(*hash.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn both_forms_round_trip()
+    {
+        let proof = b"proof bytes";
+        for key in [
+            ReplyKey::of_proof(proof),
+            ReplyKey::Semantic("V1_PREPROCESSING/1/2/ROW/abc".to_string()),
+        ]
+        {
+            assert_eq!(
+                key.to_string()
+                    .parse::<ReplyKey>(),
+                Ok(key.clone())
+            );
+            assert!(key.matches(proof));
+        }
+        assert!(!ReplyKey::of_proof(proof).matches(b"other bytes"));
+    }
+
+    #[test]
+    fn non_canonical_hashes_are_rejected()
+    {
+        let key = ReplyKey::of_proof(b"proof bytes").to_string();
+        for invalid in [
+            key.to_uppercase()
+                .replace(
+                    "BLAKE3/",
+                    CONTENT_ADDRESSED_PREFIX,
+                ),
+            key[..key.len() - 1].to_string(),
+            "".to_string(),
+        ]
+        {
+            assert!(
+                invalid
+                    .parse::<ReplyKey>()
+                    .is_err(),
+                "`{invalid}` was accepted"
+            );
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! The split of a large aggregation task into shards, proven by several workers, and the
+//! recombination of their partial proofs by one more task.
+//!
+//! This is only the message model, for the sharded proving experiments: the gateway splits the
+//! envelope of a [`Shardable`] task with [`MessageEnvelope::shard`], dispatches the shards like any
+//! other task, then, once each shard is proven, dispatches the task of
+//! [`MessageEnvelope::recombine`] in place of the original one, under its task id. The workers
+//! prove the shards and the recombination as ordinary tasks.
+
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use crate::types::experimental::tx_trie;
+use crate::types::experimental::tx_trie::block_range;
+use crate::types::experimental::tx_trie::keys::ProofKey;
+use crate::types::MessageEnvelope;
+
+/// Separates the task id of the sharded task from the position of the shard in the shard task ids.
+const SHARD_SEPARATOR: &str = "#shard-";
+
+/// A task which can be split into shards, the partial results of which are recombined by a task
+/// of the same kind.
+pub trait Shardable: Sized
+{
+    /// What the reply of a shard gives to the recombination, e.g. the key of its proof.
+    type Partial;
+
+    /// Splits the task into at most `max_shards` shards, in order, or `None` if it is too small to
+    /// be split.
+    fn split(
+        &self,
+        max_shards: usize,
+    ) -> Option<Vec<Self>>;
+
+    /// The task recombining the `partials` of the shards, in the order of the shards.
+    fn recombine(
+        &self,
+        partials: Vec<Self::Partial>,
+    ) -> Self;
+}
+
+/// What the gateway needs to recombine the shards of a task.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Recombination
+{
+    pub query_id: String,
+
+    /// The task id of the sharded task, which the recombination is dispatched under.
+    pub task_id: String,
+
+    /// The task ids of the shards, in order: their partial results are recombined in this order.
+    pub shard_task_ids: Vec<String>,
+}
+
+/// The task id of the shard `index` of `count` of the task `task_id`, e.g. `t1#shard-0-of-4`.
+///
+/// Deterministic, so that a gateway splitting a task again, e.g. after a restart, dispatches the
+/// same shards.
+pub fn shard_task_id(
+    task_id: &str,
+    index: usize,
+    count: usize,
+) -> String
+{
+    format!("{task_id}{SHARD_SEPARATOR}{index}-of-{count}")
+}
+
+/// The task id of the sharded task, the index of the shard and the count of shards of the shard
+/// task id `task_id`, if it is one.
+pub fn parse_shard_task_id(
+    task_id: &str
+) -> Option<(
+    &str,
+    usize,
+    usize,
+)>
+{
+    let (parent, position) = task_id.rsplit_once(SHARD_SEPARATOR)?;
+    let (index, count) = position.split_once("-of-")?;
+    let index = index
+        .parse()
+        .ok()?;
+    let count = count
+        .parse()
+        .ok()?;
+    (index < count).then_some(
+        (
+            parent,
+            index,
+            count,
+        ),
+    )
+}
+
+impl<T: Shardable + Clone> MessageEnvelope<T>
+{
+    /// Splits the envelope into the envelopes of at most `max_shards` shards, along with what
+    /// recombines them, or `None` if the task is too small to be split.
+    ///
+    /// The shards are routed, timed and attributed like the task, but are not tasks of the DB.
+    pub fn shard(
+        &self,
+        max_shards: usize,
+    ) -> Option<(
+        Vec<MessageEnvelope<T>>,
+        Recombination,
+    )>
+    {
+        let shards = self
+            .inner
+            .split(max_shards)?;
+        let count = shards.len();
+        let envelopes = shards
+            .into_iter()
+            .enumerate()
+            .map(
+                |(index, shard)| {
+                    MessageEnvelope {
+                        task_id: shard_task_id(
+                            &self.task_id,
+                            index,
+                            count,
+                        ),
+                        db_task_id: None,
+                        inner: shard,
+                        ..self.clone()
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+        let recombination = Recombination {
+            query_id: self
+                .query_id
+                .clone(),
+            task_id: self
+                .task_id
+                .clone(),
+            shard_task_ids: envelopes
+                .iter()
+                .map(
+                    |envelope| {
+                        envelope
+                            .task_id
+                            .clone()
+                    },
+                )
+                .collect(),
+        };
+        Some(
+            (
+                envelopes,
+                recombination,
+            ),
+        )
+    }
+
+    /// The envelope recombining the `partials` of the shards of this envelope, in the order of
+    /// [`Recombination::shard_task_ids`], under the ids of this envelope.
+    #[must_use]
+    pub fn recombine(
+        &self,
+        partials: Vec<T::Partial>,
+    ) -> MessageEnvelope<T>
+    {
+        MessageEnvelope {
+            inner: self
+                .inner
+                .recombine(partials),
+            ..self.clone()
+        }
+    }
+}
+
+/// The blocks range proofs are split into contiguous ranges of at least two child proofs, each
+/// aggregated by a shard; the aggregations of the shards are then aggregated in turn.
+impl Shardable for tx_trie::WorkerTask
+{
+    type Partial = ProofKey;
+
+    fn split(
+        &self,
+        max_shards: usize,
+    ) -> Option<Vec<Self>>
+    {
+        let tx_trie::WorkerTaskType::BlocksRangeProof(block_range::ProofKind::Blocks(blocks)) =
+            &self.task_type
+        else
+        {
+            return None;
+        };
+        let count = max_shards.min(
+            blocks
+                .data_uris
+                .len()
+                / 2,
+        );
+        if count < 2
+        {
+            return None;
+        }
+        // The first shards take one more child proof each when they do not divide evenly.
+        let size = blocks
+            .data_uris
+            .len()
+            / count;
+        let rest = blocks
+            .data_uris
+            .len()
+            % count;
+        let mut start = 0;
+        Some(
+            (0..count)
+                .map(
+                    |shard| {
+                        let end = start + size + usize::from(shard < rest);
+                        let task = tx_trie::WorkerTask::block_range_task(
+                            self.computation
+                                .clone(),
+                            blocks.data_uris[start..end].to_vec(),
+                        );
+                        start = end;
+                        task
+                    },
+                )
+                .collect(),
+        )
+    }
+
+    fn recombine(
+        &self,
+        partials: Vec<ProofKey>,
+    ) -> Self
+    {
+        tx_trie::WorkerTask::block_range_task(
+            self.computation
+                .clone(),
+            partials,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::routing::RoutingKey;
+    use crate::types::experimental::tx_trie::Computation;
+    use crate::types::experimental::tx_trie::SumOfGasFees;
+
+    #[test]
+    fn shards_and_recombines_a_blocks_range()
+    {
+        let computation = Computation::SumOfGasFees(
+            SumOfGasFees {
+                dest_address: "0xabc".to_string(),
+            },
+        );
+        let blocks = (0..9)
+            .map(
+                |block| {
+                    ProofKey::Block(
+                        computation.id(),
+                        block,
+                    )
+                },
+            )
+            .collect::<Vec<_>>();
+        let envelope = MessageEnvelope::new(
+            "q1".to_string(),
+            "t1".to_string(),
+            tx_trie::WorkerTask::block_range_task(
+                computation.clone(),
+                blocks.clone(),
+            ),
+            RoutingKey::combined(
+                tx_trie::ROUTING_DOMAIN.to_string(),
+                0,
+            ),
+        );
+
+        let (shards, recombination) = envelope
+            .shard(4)
+            .unwrap();
+        assert_eq!(
+            recombination.shard_task_ids,
+            [
+                "t1#shard-0-of-4",
+                "t1#shard-1-of-4",
+                "t1#shard-2-of-4",
+                "t1#shard-3-of-4",
+            ]
+        );
+        assert_eq!(
+            parse_shard_task_id(&shards[1].task_id),
+            Some(
+                (
+                    "t1",
+                    1,
+                    4
+                )
+            )
+        );
+        assert_eq!(
+            shards[3].inner,
+            tx_trie::WorkerTask::block_range_task(
+                computation.clone(),
+                blocks[7..].to_vec(),
+            )
+        );
+
+        let partials = (0..4)
+            .map(
+                |shard| {
+                    ProofKey::Aggregation(
+                        computation.id(),
+                        format!("{shard}"),
+                    )
+                },
+            )
+            .collect::<Vec<_>>();
+        let recombined = envelope.recombine(partials.clone());
+        assert_eq!(
+            recombined.task_id,
+            "t1"
+        );
+        assert_eq!(
+            recombined.inner,
+            tx_trie::WorkerTask::block_range_task(
+                computation,
+                partials,
+            )
+        );
+        assert!(
+            envelope
+                .shard(1)
+                .is_none()
+        );
+    }
+}
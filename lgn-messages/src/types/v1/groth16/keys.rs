@@ -1,11 +1,17 @@
 use std::fmt::Display;
+use std::str::FromStr;
 
 use object_store::path::Path;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 
+use crate::types::v1::key_grammar::canonical;
+use crate::types::v1::key_grammar::ProofKeyParseError;
+use crate::types::v1::key_grammar::Segments;
 use crate::types::v1::query::keys::KEYS_QUERIES_PREFIX;
 
+const GROTH16: &str = "groth16";
+
 pub type QueryId = String;
 
 /// Where to store the Groth16 proof
@@ -22,7 +28,30 @@ impl Display for ProofKey
         let query_id = &self.0;
         write!(
             f,
-            "{KEYS_QUERIES_PREFIX}/{query_id}/groth16"
+            "{KEYS_QUERIES_PREFIX}/{query_id}/{GROTH16}"
+        )
+    }
+}
+
+/// Parses the canonical form rendered by `Display`, see
+/// [`key_grammar`](crate::types::v1::key_grammar).
+impl FromStr for ProofKey
+{
+    type Err = ProofKeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let mut segments = Segments::new(s);
+        segments.expect(KEYS_QUERIES_PREFIX)?;
+        let query_id = segments
+            .next_str("query id")?
+            .to_string();
+        segments.expect(GROTH16)?;
+        segments.finish()?;
+
+        canonical(
+            s,
+            ProofKey(query_id),
         )
     }
 }
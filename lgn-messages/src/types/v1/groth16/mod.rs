@@ -1,8 +1,13 @@
+#[cfg(feature = "tasks")]
 use derive_debug_plus::Dbg;
+#[cfg(feature = "tasks")]
 use serde_derive::Deserialize;
+#[cfg(feature = "tasks")]
 use serde_derive::Serialize;
 
+#[cfg(feature = "tasks")]
 use super::query::tasks::Hydratable;
+#[cfg(feature = "tasks")]
 use crate::types::v1::query;
 
 pub mod keys;
@@ -10,6 +15,7 @@ pub mod keys;
 /// Groth16 routing domain
 pub const ROUTING_DOMAIN: &str = "sg";
 
+#[cfg(feature = "tasks")]
 #[derive(Clone, Serialize, Deserialize, Dbg)]
 pub struct WorkerTask
 {
@@ -21,6 +27,7 @@ pub struct WorkerTask
     pub revelation_proof: Hydratable<query::keys::ProofKey>,
 }
 
+#[cfg(feature = "tasks")]
 impl WorkerTask
 {
     #[must_use]
@@ -0,0 +1,360 @@
+//! The canonical string grammar of the v1 proof keys.
+//!
+//! Keys are `/`-separated segments, as rendered by their `Display` implementation:
+//!
+//! ```text
+//! preprocessing-key = "V1_PREPROCESSING/" ( db-key / ext-key )
+//! db-key            = table-id "/" block-nr "/CELL/" id "/" cell-id
+//!                   / table-id "/" block-nr "/ROW/" id
+//!                   / "DB_BLOCK/" table-id "/" block-nr
+//!                   / "IVC/" table-id "/" block-nr
+//! ext-key           = table-hash "/MPT_VARIABLE/" block-nr "/" hash
+//!                   / table-hash "/MPT_LENGTH/" block-nr
+//!                   / "CONTRACT/" address "/" block-nr
+//!                   / "EXT_BLOCK/" block-nr
+//!                   / table-id "/FINAL_EXTRACTION/" block-nr
+//! public-params-key = "PublicParams_v1"
+//! query-key         = "V1_QUERIES/" id "/rows_tree/" block-nr "/" id
+//!                   / "V1_QUERIES/" id "/index_tree/" block-nr
+//!                   / "V1_QUERIES/" id "/revelation"
+//! groth16-key       = "V1_QUERIES/" id "/groth16"
+//!
+//! table-id, table-hash, block-nr, cell-id = decimal integer, without sign or leading zeros
+//! hash    = "0x" 64 lowercase hex digits
+//! address = "0x" 40 hex digits, EIP-55 checksummed
+//! id      = non-empty string without "/"
+//! ```
+//!
+//! Parsing only accepts the canonical form, i.e. `key.to_string().parse() == Ok(key)` and a
+//! parsed string renders back to itself.
+
+use std::fmt::Display;
+use std::str::FromStr;
+use std::str::Split;
+
+/// A string which is not the canonical rendering of a proof key.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid proof key `{key}`: {reason}")]
+pub struct ProofKeyParseError
+{
+    pub key: String,
+    pub reason: String,
+}
+
+impl ProofKeyParseError
+{
+    pub(crate) fn new(
+        key: &str,
+        reason: impl Into<String>,
+    ) -> Self
+    {
+        Self {
+            key: key.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Reads the segments of a key one at a time.
+pub(crate) struct Segments<'a>
+{
+    key: &'a str,
+    parts: Split<'a, char>,
+}
+
+impl<'a> Segments<'a>
+{
+    pub(crate) fn new(key: &'a str) -> Self
+    {
+        Self {
+            key,
+            parts: key.split('/'),
+        }
+    }
+
+    /// The next segment, which must be present and non-empty.
+    pub(crate) fn next_str(
+        &mut self,
+        what: &str,
+    ) -> Result<&'a str, ProofKeyParseError>
+    {
+        match self
+            .parts
+            .next()
+        {
+            Some(segment) if !segment.is_empty() => Ok(segment),
+            _ => Err(self.error(format!("missing {what}"))),
+        }
+    }
+
+    /// Parses the next segment.
+    pub(crate) fn parse<T: FromStr>(
+        &mut self,
+        what: &str,
+    ) -> Result<T, ProofKeyParseError>
+    {
+        let segment = self.next_str(what)?;
+        segment
+            .parse()
+            .map_err(|_| self.error(format!("invalid {what} `{segment}`")))
+    }
+
+    /// Consumes the next segment, which must be `literal`.
+    pub(crate) fn expect(
+        &mut self,
+        literal: &str,
+    ) -> Result<(), ProofKeyParseError>
+    {
+        let segment = self.next_str(literal)?;
+        if segment != literal
+        {
+            return Err(self.error(format!("expected `{literal}`, found `{segment}`")));
+        }
+
+        Ok(())
+    }
+
+    /// Ensures that all the segments were consumed.
+    pub(crate) fn finish(mut self) -> Result<(), ProofKeyParseError>
+    {
+        match self
+            .parts
+            .next()
+        {
+            Some(segment) => Err(self.error(format!("unexpected trailing segment `{segment}`"))),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn error(
+        &self,
+        reason: impl Into<String>,
+    ) -> ProofKeyParseError
+    {
+        ProofKeyParseError::new(
+            self.key,
+            reason,
+        )
+    }
+}
+
+/// Returns `key` if it renders back to `input`, rejecting non-canonical spellings such as leading
+/// zeros or uppercase hashes.
+pub(crate) fn canonical<K: Display>(
+    input: &str,
+    key: K,
+) -> Result<K, ProofKeyParseError>
+{
+    let rendered = key.to_string();
+    if rendered != input
+    {
+        return Err(
+            ProofKeyParseError::new(
+                input,
+                format!("not in canonical form, expected `{rendered}`"),
+            ),
+        );
+    }
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use alloy_primitives::Address;
+    use ethers::types::H256;
+    use rand::distributions::Alphanumeric;
+    use rand::thread_rng;
+    use rand::Rng;
+
+    use crate::types::v1::groth16;
+    use crate::types::v1::preprocessing::db_keys;
+    use crate::types::v1::preprocessing::ext_keys;
+    use crate::types::v1::query;
+
+    const ITERATIONS: usize = 256;
+
+    fn id(rng: &mut impl Rng) -> String
+    {
+        let len = rng.gen_range(1..32);
+        rng.sample_iter(&Alphanumeric)
+            .take(len)
+            .map(char::from)
+            .collect()
+    }
+
+    fn round_trip<K>(key: K)
+    where
+        K: std::fmt::Display + std::str::FromStr + PartialEq + std::fmt::Debug,
+        K::Err: std::fmt::Debug,
+    {
+        let rendered = key.to_string();
+        let parsed = rendered
+            .parse::<K>()
+            .unwrap_or_else(|e| panic!("failed to parse `{rendered}`: {e:?}"));
+        assert_eq!(
+            parsed, key,
+            "`{rendered}` did not round-trip"
+        );
+    }
+
+    #[test]
+    fn test_db_keys_round_trip()
+    {
+        let mut rng = thread_rng();
+        for _ in 0..ITERATIONS
+        {
+            round_trip(
+                db_keys::ProofKey::Cell(
+                    rng.gen(),
+                    rng.gen(),
+                    id(&mut rng),
+                    rng.gen(),
+                ),
+            );
+            round_trip(
+                db_keys::ProofKey::Row(
+                    rng.gen(),
+                    rng.gen(),
+                    id(&mut rng),
+                ),
+            );
+            round_trip(
+                db_keys::ProofKey::Block(
+                    rng.gen(),
+                    rng.gen(),
+                ),
+            );
+            round_trip(
+                db_keys::ProofKey::IVC(
+                    rng.gen(),
+                    rng.gen(),
+                ),
+            );
+        }
+    }
+
+    #[test]
+    fn test_ext_keys_round_trip()
+    {
+        let mut rng = thread_rng();
+        round_trip(ext_keys::ProofKey::PublicParams);
+        for _ in 0..ITERATIONS
+        {
+            round_trip(
+                ext_keys::ProofKey::MptVariable {
+                    table_hash: rng.gen(),
+                    mpt_node_version: (
+                        rng.gen(),
+                        H256::from(rng.gen::<[u8; 32]>()),
+                    ),
+                },
+            );
+            round_trip(
+                ext_keys::ProofKey::MptLength {
+                    table_hash: rng.gen(),
+                    block_nr: rng.gen(),
+                },
+            );
+            round_trip(
+                ext_keys::ProofKey::Contract {
+                    address: Address::from(rng.gen::<[u8; 20]>()),
+                    block_nr: rng.gen(),
+                },
+            );
+            round_trip(
+                ext_keys::ProofKey::Block {
+                    block_nr: rng.gen(),
+                },
+            );
+            round_trip(
+                ext_keys::ProofKey::FinalExtraction {
+                    table_id: rng.gen(),
+                    block_nr: rng.gen(),
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn test_query_keys_round_trip()
+    {
+        let mut rng = thread_rng();
+        for _ in 0..ITERATIONS
+        {
+            round_trip(
+                query::keys::ProofKey::Row(
+                    id(&mut rng),
+                    rng.gen(),
+                    id(&mut rng),
+                ),
+            );
+            round_trip(
+                query::keys::ProofKey::Index(
+                    id(&mut rng),
+                    rng.gen(),
+                ),
+            );
+            round_trip(query::keys::ProofKey::Revelation(id(&mut rng)));
+            round_trip(groth16::keys::ProofKey(id(&mut rng)));
+        }
+    }
+
+    #[test]
+    fn test_non_canonical_keys_are_rejected()
+    {
+        for key in [
+            "V1_PREPROCESSING/01/2/ROW/abc",
+            "V1_PREPROCESSING/+1/2/ROW/abc",
+            "V1_PREPROCESSING/1/2/ROW/",
+            "V1_PREPROCESSING/1/2/ROW/abc/def",
+            "V1_PREPROCESSING/DB_BLOCK/1",
+            "V1_PREPROCESSING/IVC/1/2/3",
+        ]
+        {
+            assert!(
+                key.parse::<db_keys::ProofKey>()
+                    .is_err(),
+                "`{key}` was accepted"
+            );
+        }
+
+        let hash = H256::repeat_byte(0xAB);
+        let uppercase = format!(
+            "V1_PREPROCESSING/1/MPT_VARIABLE/2/0x{}",
+            hex_upper(hash.as_bytes())
+        );
+        assert!(
+            uppercase
+                .parse::<ext_keys::ProofKey>()
+                .is_err()
+        );
+
+        for key in [
+            "V1_QUERIES/q/rows_tree/1",
+            "V1_QUERIES/q/revelation/1",
+            "V1_QUERIES//revelation",
+        ]
+        {
+            assert!(
+                key.parse::<query::keys::ProofKey>()
+                    .is_err(),
+                "`{key}` was accepted"
+            );
+        }
+        assert!(
+            "V1_QUERIES/q/revelation"
+                .parse::<groth16::keys::ProofKey>()
+                .is_err()
+        );
+    }
+
+    fn hex_upper(bytes: &[u8]) -> String
+    {
+        bytes
+            .iter()
+            .map(|b| format!("{b:02X}"))
+            .collect()
+    }
+}
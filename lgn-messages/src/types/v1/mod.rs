@@ -1,3 +1,4 @@
 pub mod groth16;
+pub mod key_grammar;
 pub mod preprocessing;
 pub mod query;
@@ -1,9 +1,13 @@
 use std::fmt::Display;
+use std::str::FromStr;
 
 use object_store::path::Path;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 
+use crate::types::v1::key_grammar::canonical;
+use crate::types::v1::key_grammar::ProofKeyParseError;
+use crate::types::v1::key_grammar::Segments;
 use crate::types::v1::preprocessing::KEYS_PREPROCESSING_PREFIX;
 use crate::BlockNr;
 use crate::TableId;
@@ -14,6 +18,7 @@ type CellId = usize;
 const CELL_PREFIX: &str = "CELL";
 const ROW_PREFIX: &str = "ROW";
 const BLOCK_PREFIX: &str = "DB_BLOCK";
+const IVC_PREFIX: &str = "IVC";
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Ord, Eq, Hash, Serialize, Deserialize)]
 pub enum ProofKey
@@ -79,13 +84,81 @@ impl Display for ProofKey
                 // Example: V1_PREPROCESSING/IVC/1/2
                 write!(
                     f,
-                    "{KEYS_PREPROCESSING_PREFIX}/IVC/{table_id}/{block_nr}"
+                    "{KEYS_PREPROCESSING_PREFIX}/{IVC_PREFIX}/{table_id}/{block_nr}"
                 )
             },
         }
     }
 }
 
+/// Parses the canonical form rendered by `Display`, see
+/// [`key_grammar`](crate::types::v1::key_grammar).
+impl FromStr for ProofKey
+{
+    type Err = ProofKeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let mut segments = Segments::new(s);
+        segments.expect(KEYS_PREPROCESSING_PREFIX)?;
+        let key = match segments.next_str("table id")?
+        {
+            BLOCK_PREFIX =>
+            {
+                ProofKey::Block(
+                    segments.parse("table id")?,
+                    segments.parse("block number")?,
+                )
+            },
+            IVC_PREFIX =>
+            {
+                ProofKey::IVC(
+                    segments.parse("table id")?,
+                    segments.parse("block number")?,
+                )
+            },
+            table_id =>
+            {
+                let table_id = table_id
+                    .parse()
+                    .map_err(|_| segments.error(format!("invalid table id `{table_id}`")))?;
+                let block_nr = segments.parse("block number")?;
+                match segments.next_str("proof kind")?
+                {
+                    CELL_PREFIX =>
+                    {
+                        ProofKey::Cell(
+                            table_id,
+                            block_nr,
+                            segments
+                                .next_str("row id")?
+                                .to_string(),
+                            segments.parse("cell id")?,
+                        )
+                    },
+                    ROW_PREFIX =>
+                    {
+                        ProofKey::Row(
+                            table_id,
+                            block_nr,
+                            segments
+                                .next_str("row id")?
+                                .to_string(),
+                        )
+                    },
+                    kind => return Err(segments.error(format!("unknown proof kind `{kind}`"))),
+                }
+            },
+        };
+        segments.finish()?;
+
+        canonical(
+            s,
+            key,
+        )
+    }
+}
+
 impl From<ProofKey> for Path
 {
     fn from(key: ProofKey) -> Self
@@ -25,6 +25,10 @@ pub enum DatabaseType
     Index(IndexInputs),
 
     IVC(IvcInput),
+
+    /// Prove a whole row, including its cells tree, in a single task.
+    #[serde(rename = "4")]
+    RowUpdate(RowUpdateInput),
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
@@ -145,6 +149,44 @@ pub struct RowFullInput
     pub cells_proof: Vec<u8>,
 }
 
+/// Inputs to prove a row and its cells tree in one go.
+///
+/// The worker proves the cells tree bottom-up, then the row node on top of the cells tree root
+/// proof, and only returns the final row proof.
+#[derive(Clone, Dbg, PartialEq, Deserialize, Serialize)]
+pub struct RowUpdateInput
+{
+    pub table_id: TableId,
+    pub row_id: String,
+    pub identifier: Identifier,
+    pub value: U256,
+    pub is_multiplier: bool,
+
+    /// The cells tree of the row in post-order, the last cell being the root of the tree.
+    pub cells: Vec<CellNode>,
+
+    /// Only meaningful when the row node has a single child.
+    pub is_child_left: bool,
+
+    /// Locations of the already proven children of the row node in the rows tree.
+    pub child_proofs_locations: Vec<db_keys::ProofKey>,
+
+    #[dbg(placeholder = "...")]
+    pub child_proofs: Vec<Vec<u8>>,
+}
+
+/// A node of the cells tree of a [`RowUpdateInput`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CellNode
+{
+    pub identifier: Identifier,
+    pub value: U256,
+    pub is_multiplier: bool,
+
+    /// Indices of the children of this cell in [`RowUpdateInput::cells`], they must precede it.
+    pub children: Vec<usize>,
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct IndexInputs
 {
@@ -433,6 +475,15 @@ impl From<&WorkerTask> for db_keys::ProofKey
                             tt.block_nr,
                         )
                     },
+                    DatabaseType::RowUpdate(ru) =>
+                    {
+                        db_keys::ProofKey::Row(
+                            ru.table_id,
+                            tt.block_nr,
+                            ru.row_id
+                                .to_string(),
+                        )
+                    },
                     DatabaseType::IVC(ivc) =>
                     {
                         db_keys::ProofKey::IVC(
@@ -1,23 +1,35 @@
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::str::FromStr;
 
 use alloy_primitives::Address;
+use ethers::types::H256;
 use object_store::path::Path;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 
-use crate::types::v1::preprocessing::ext_tasks::MptNodeVersion;
+use crate::types::v1::key_grammar::canonical;
+use crate::types::v1::key_grammar::ProofKeyParseError;
+use crate::types::v1::key_grammar::Segments;
 use crate::types::v1::preprocessing::KEYS_PREPROCESSING_PREFIX;
 use crate::BlockNr;
 use crate::TableHash;
 use crate::TableId;
 
+const PUBLIC_PARAMS_KEY: &str = "PublicParams_v1";
 const BLOCK_PREFIX: &str = "EXT_BLOCK";
 const CONTRACT_PREFIX: &str = "CONTRACT";
 const MPT_LENGTH_PREFIX: &str = "MPT_LENGTH";
 const MPT_VARIABLE_PREFIX: &str = "MPT_VARIABLE";
 
 const FINAL_EXTRACTION_PREFIX: &str = "FINAL_EXTRACTION";
+
+/// An MPT node, by the block it is proven at and its hash.
+pub type MptNodeVersion = (
+    BlockNr,
+    H256,
+);
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Ord, Eq, Hash, Serialize, Deserialize)]
 pub enum ProofKey
 {
@@ -72,7 +84,7 @@ impl Display for ProofKey
             {
                 write!(
                     f,
-                    "PublicParams_v1"
+                    "{PUBLIC_PARAMS_KEY}"
                 )
             },
             ProofKey::MptVariable {
@@ -145,6 +157,81 @@ impl Display for ProofKey
     }
 }
 
+/// Parses the canonical form rendered by `Display`, see
+/// [`key_grammar`](crate::types::v1::key_grammar).
+impl FromStr for ProofKey
+{
+    type Err = ProofKeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        if s == PUBLIC_PARAMS_KEY
+        {
+            return Ok(ProofKey::PublicParams);
+        }
+
+        let mut segments = Segments::new(s);
+        segments.expect(KEYS_PREPROCESSING_PREFIX)?;
+        let key = match segments.next_str("table hash")?
+        {
+            CONTRACT_PREFIX =>
+            {
+                ProofKey::Contract {
+                    address: segments.parse("address")?,
+                    block_nr: segments.parse("block number")?,
+                }
+            },
+            BLOCK_PREFIX =>
+            {
+                ProofKey::Block {
+                    block_nr: segments.parse("block number")?,
+                }
+            },
+            table =>
+            {
+                // Either a table hash or a table id, depending on the proof kind.
+                let table = table
+                    .parse()
+                    .map_err(|_| segments.error(format!("invalid table `{table}`")))?;
+                match segments.next_str("proof kind")?
+                {
+                    MPT_VARIABLE_PREFIX =>
+                    {
+                        ProofKey::MptVariable {
+                            table_hash: table,
+                            mpt_node_version: (
+                                segments.parse("block number")?,
+                                segments.parse("node hash")?,
+                            ),
+                        }
+                    },
+                    MPT_LENGTH_PREFIX =>
+                    {
+                        ProofKey::MptLength {
+                            table_hash: table,
+                            block_nr: segments.parse("block number")?,
+                        }
+                    },
+                    FINAL_EXTRACTION_PREFIX =>
+                    {
+                        ProofKey::FinalExtraction {
+                            table_id: table,
+                            block_nr: segments.parse("block number")?,
+                        }
+                    },
+                    kind => return Err(segments.error(format!("unknown proof kind `{kind}`"))),
+                }
+            },
+        };
+        segments.finish()?;
+
+        canonical(
+            s,
+            key,
+        )
+    }
+}
+
 impl From<ProofKey> for Path
 {
     fn from(key: ProofKey) -> Self
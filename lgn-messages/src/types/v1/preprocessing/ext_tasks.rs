@@ -1,10 +1,12 @@
 use alloy_primitives::Address;
 use derive_debug_plus::Dbg;
 use ethers::types::H256;
+use ethers::utils::keccak256;
 use ethers::utils::rlp;
 use mp2_common::digest::TableDimension;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use thiserror::Error;
 
 use crate::types::v1::preprocessing::ext_keys::ProofKey;
 use crate::types::v1::preprocessing::WorkerTask;
@@ -15,10 +17,7 @@ use crate::TableId;
 
 pub const ROUTING_DOMAIN: &str = "sp";
 pub type Identifier = u64;
-pub type MptNodeVersion = (
-    BlockNr,
-    H256,
-);
+pub use crate::types::v1::preprocessing::ext_keys::MptNodeVersion;
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum ExtractionType
@@ -37,6 +36,9 @@ pub enum ExtractionType
 
     #[serde(rename = "5")]
     FinalExtraction(Box<FinalExtraction>),
+
+    #[serde(rename = "6")]
+    TableExtraction(Box<TableExtractionInput>),
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
@@ -296,6 +298,167 @@ impl BlockExtractionInput
             rlp_header,
         }
     }
+
+    /// Decodes the header fields used to sanity check the task before proving it.
+    ///
+    /// Fails if the header is not a canonical RLP list of at least 15 items, or if it is
+    /// followed by trailing bytes.
+    pub fn header_fields(&self) -> Result<BlockHeaderFields, BlockHeaderError>
+    {
+        let invalid = |e: rlp::DecoderError| BlockHeaderError::InvalidRlp(e.to_string());
+
+        let header = rlp::Rlp::new(&self.rlp_header);
+        if !header.is_list()
+        {
+            return Err(BlockHeaderError::InvalidRlp("expected a list".to_string()));
+        }
+        let encoded_len = header
+            .payload_info()
+            .map_err(invalid)?
+            .total();
+        if encoded_len
+            != self
+                .rlp_header
+                .len()
+        {
+            return Err(
+                BlockHeaderError::InvalidRlp(
+                    format!(
+                        "{} trailing bytes after the header",
+                        self.rlp_header
+                            .len()
+                            .saturating_sub(encoded_len)
+                    ),
+                ),
+            );
+        }
+        let item_count = header
+            .item_count()
+            .map_err(invalid)?;
+        if item_count < BLOCK_HEADER_MIN_FIELDS
+        {
+            return Err(
+                BlockHeaderError::InvalidRlp(
+                    format!("expected at least {BLOCK_HEADER_MIN_FIELDS} fields, got {item_count}"),
+                ),
+            );
+        }
+
+        Ok(
+            BlockHeaderFields {
+                number: header
+                    .val_at(8)
+                    .map_err(invalid)?,
+                hash: H256(keccak256(&self.rlp_header)),
+                parent_hash: header
+                    .val_at(0)
+                    .map_err(invalid)?,
+                state_root: header
+                    .val_at(3)
+                    .map_err(invalid)?,
+            },
+        )
+    }
+}
+
+/// Number of fields of a pre-London block header, later forks only append fields.
+const BLOCK_HEADER_MIN_FIELDS: usize = 15;
+
+/// The block header fields checked before proving a block extraction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockHeaderFields
+{
+    pub number: BlockNr,
+    pub hash: H256,
+    pub parent_hash: H256,
+    pub state_root: H256,
+}
+
+impl BlockHeaderFields
+{
+    /// Checks that the header is the one of the block the task is for.
+    pub fn ensure_block_nr(
+        &self,
+        block_nr: BlockNr,
+    ) -> Result<(), BlockHeaderError>
+    {
+        if self.number != block_nr
+        {
+            return Err(
+                BlockHeaderError::BlockNumberMismatch {
+                    expected: block_nr,
+                    actual: self.number,
+                },
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum BlockHeaderError
+{
+    #[error("invalid RLP block header: {0}")]
+    InvalidRlp(String),
+
+    #[error("block header is for block {actual}, but the task is for block {expected}")]
+    BlockNumberMismatch
+    {
+        expected: BlockNr,
+        actual: BlockNr,
+    },
+}
+
+/// Inputs to prove the final extraction of a mapping with length table, e.g. an ERC-20 balances
+/// mapping, in a single task.
+///
+/// The worker proves the values, length, contract and block extractions internally instead of
+/// receiving them from separate tasks.
+#[derive(Clone, Dbg, PartialEq, Deserialize, Serialize)]
+pub struct TableExtractionInput
+{
+    pub table_id: TableId,
+    pub block_nr: BlockNr,
+    pub contract: Address,
+
+    /// The storage trie nodes of the mapping in post-order, the last node being the root.
+    pub value_nodes: Vec<ValueNode>,
+
+    pub length_slot: usize,
+    pub variable_slot: usize,
+
+    /// The storage trie path of the length slot, leaf first.
+    #[dbg(placeholder = "...")]
+    pub length_nodes: Vec<Vec<u8>>,
+
+    pub storage_root: Vec<u8>,
+
+    /// The state trie path of the contract account, leaf first.
+    #[dbg(placeholder = "...")]
+    pub contract_nodes: Vec<Vec<u8>>,
+
+    #[dbg(placeholder = "...")]
+    pub rlp_header: Vec<u8>,
+}
+
+/// A storage trie node of a [`TableExtractionInput`].
+#[derive(Clone, Dbg, PartialEq, Deserialize, Serialize)]
+pub enum ValueNode
+{
+    #[serde(rename = "1")]
+    Leaf(MappingLeafInput),
+
+    #[serde(rename = "2")]
+    Branch
+    {
+        #[dbg(placeholder = "...")]
+        node: Vec<u8>,
+
+        /// Indices of the children of this node in [`TableExtractionInput::value_nodes`], they
+        /// must precede it.
+        children: Vec<usize>,
+    },
 }
 
 /// Inputs for the final extraction.
@@ -386,15 +549,19 @@ pub struct SingleTableExtraction
     pub extraction_type: FinalExtractionType,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub block_proof: Vec<u8>,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub contract_proof: Vec<u8>,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub value_proof: Vec<u8>,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub length_proof: Vec<u8>,
 }
 
@@ -453,15 +620,19 @@ pub struct MergeTableExtraction
     pub value_proof_version: MptNodeVersion,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub block_proof: Vec<u8>,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub contract_proof: Vec<u8>,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub simple_table_proof: Vec<u8>,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub mapping_table_proof: Vec<u8>,
 }
 
@@ -491,13 +662,59 @@ impl MergeTableExtraction
     }
 }
 
+/// The kind of table of a [SingleTableExtraction].
+///
+/// Serialized as `{"Simple": "Single"}`, `{"Simple": "Compound"}` or `"Lengthed"`. The envelopes
+/// predating [TableDimension] carry `{"Simple": <compound>}`, which still deserializes, `true` as
+/// [TableDimension::Compound] and `false` as [TableDimension::Single].
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(from = "FinalExtractionTypeEncoding")]
 pub enum FinalExtractionType
 {
     Simple(TableDimension),
     Lengthed,
 }
 
+/// Every encoding of [FinalExtractionType] deserialized, current or historical.
+#[derive(Deserialize)]
+enum FinalExtractionTypeEncoding
+{
+    Simple(SimpleTableEncoding),
+    Lengthed,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SimpleTableEncoding
+{
+    Dimension(TableDimension),
+    /// Whether the table is compound, before [TableDimension].
+    Compound(bool),
+}
+
+impl From<FinalExtractionTypeEncoding> for FinalExtractionType
+{
+    fn from(encoding: FinalExtractionTypeEncoding) -> Self
+    {
+        match encoding
+        {
+            FinalExtractionTypeEncoding::Simple(SimpleTableEncoding::Dimension(dimension)) =>
+            {
+                Self::Simple(dimension)
+            },
+            FinalExtractionTypeEncoding::Simple(SimpleTableEncoding::Compound(true)) =>
+            {
+                Self::Simple(TableDimension::Compound)
+            },
+            FinalExtractionTypeEncoding::Simple(SimpleTableEncoding::Compound(false)) =>
+            {
+                Self::Simple(TableDimension::Single)
+            },
+            FinalExtractionTypeEncoding::Lengthed => Self::Lengthed,
+        }
+    }
+}
+
 impl From<&WorkerTask> for ProofKey
 {
     fn from(task: &WorkerTask) -> Self
@@ -573,9 +790,82 @@ impl From<&WorkerTask> for ProofKey
                             block_nr: final_extraction.block_nr(),
                         }
                     },
+                    ExtractionType::TableExtraction(table_extraction) =>
+                    {
+                        ProofKey::FinalExtraction {
+                            table_id: table_extraction.table_id,
+                            block_nr: table_extraction.block_nr,
+                        }
+                    },
                 }
             },
             _ => unimplemented!("WorkerTaskType not implemented"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn final_extraction_types_deserialize_from_every_encoding()
+    {
+        for (encoding, expected, normalized) in [
+            (
+                r#"{"Simple": true}"#,
+                FinalExtractionType::Simple(TableDimension::Compound),
+                r#"{"Simple":"Compound"}"#,
+            ),
+            (
+                r#"{"Simple": false}"#,
+                FinalExtractionType::Simple(TableDimension::Single),
+                r#"{"Simple":"Single"}"#,
+            ),
+            (
+                r#"{"Simple": "Compound"}"#,
+                FinalExtractionType::Simple(TableDimension::Compound),
+                r#"{"Simple":"Compound"}"#,
+            ),
+            (
+                r#"{"Simple": "Single"}"#,
+                FinalExtractionType::Simple(TableDimension::Single),
+                r#"{"Simple":"Single"}"#,
+            ),
+            (
+                r#""Lengthed""#,
+                FinalExtractionType::Lengthed,
+                r#""Lengthed""#,
+            ),
+        ]
+        {
+            let parsed = serde_json::from_str::<FinalExtractionType>(encoding).unwrap();
+            assert_eq!(
+                parsed, expected,
+                "{encoding}"
+            );
+            let serialized = serde_json::to_string(&parsed).unwrap();
+            assert_eq!(
+                serialized, normalized,
+                "{encoding}"
+            );
+            assert_eq!(
+                serde_json::from_str::<FinalExtractionType>(&serialized).unwrap(),
+                expected
+            );
+        }
+
+        for rejected in [
+            r#""Simple""#,
+            r#"{"Simple": 1}"#,
+            r#"{"Simple": "Double"}"#,
+        ]
+        {
+            assert!(
+                serde_json::from_str::<FinalExtractionType>(rejected).is_err(),
+                "{rejected}"
+            );
+        }
+    }
+}
@@ -1,41 +1,78 @@
+#[cfg(feature = "tasks")]
 use alloy_primitives::Address;
+#[cfg(feature = "tasks")]
 use alloy_primitives::U256;
+#[cfg(feature = "tasks")]
 use ethers::prelude::H256;
+#[cfg(feature = "tasks")]
 use mp2_common::digest::TableDimension;
+#[cfg(feature = "tasks")]
 use serde_derive::Deserialize;
+#[cfg(feature = "tasks")]
 use serde_derive::Serialize;
 
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::db_tasks::CellFullInput;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::db_tasks::CellLeafInput;
+#[cfg(feature = "tasks")]
+use crate::types::v1::preprocessing::db_tasks::CellNode;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::db_tasks::CellPartialInput;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::db_tasks::DatabaseType;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::db_tasks::IvcInput;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::db_tasks::RowLeafInput;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::BlockExtractionInput;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::Contract;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::ExtractionType;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::FinalExtraction;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::Identifier;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::Length;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::MappingBranchInput;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::MappingLeafInput;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::Mpt;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::MptNodeVersion;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::MptType;
+#[cfg(feature = "tasks")]
+use crate::types::v1::preprocessing::ext_tasks::TableExtractionInput;
+#[cfg(feature = "tasks")]
+use crate::types::v1::preprocessing::ext_tasks::ValueNode;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::VariableBranchInput;
+#[cfg(feature = "tasks")]
 use crate::types::v1::preprocessing::ext_tasks::VariableLeafInput;
+#[cfg(feature = "tasks")]
 use crate::BlockNr;
+#[cfg(feature = "tasks")]
 use crate::TableHash;
+#[cfg(feature = "tasks")]
 use crate::TableId;
 
 pub mod db_keys;
+#[cfg(feature = "tasks")]
 pub mod db_tasks;
 pub mod ext_keys;
+#[cfg(feature = "tasks")]
 pub mod ext_tasks;
 
 const KEYS_PREPROCESSING_PREFIX: &str = "V1_PREPROCESSING";
 pub const ROUTING_DOMAIN: &str = "sp";
 
+#[cfg(feature = "tasks")]
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct WorkerTask
 {
@@ -49,6 +86,7 @@ pub struct WorkerTask
     pub task_type: WorkerTaskType,
 }
 
+#[cfg(feature = "tasks")]
 impl WorkerTask
 {
     #[must_use]
@@ -66,6 +104,7 @@ impl WorkerTask
     }
 }
 
+#[cfg(feature = "tasks")]
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(tag = "type")]
 pub enum WorkerTaskType
@@ -77,6 +116,7 @@ pub enum WorkerTaskType
     Database(DatabaseType),
 }
 
+#[cfg(feature = "tasks")]
 impl WorkerTaskType
 {
     pub fn ext_variable_leaf(
@@ -310,6 +350,40 @@ impl WorkerTaskType
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
+    pub fn ext_table_extraction(
+        table_id: TableId,
+        block_nr: BlockNr,
+        contract: Address,
+        value_nodes: Vec<ValueNode>,
+        length_slot: usize,
+        variable_slot: usize,
+        length_nodes: Vec<Vec<u8>>,
+        storage_root: Vec<u8>,
+        contract_nodes: Vec<Vec<u8>>,
+        rlp_header: Vec<u8>,
+    ) -> WorkerTaskType
+    {
+        WorkerTaskType::Extraction(
+            ExtractionType::TableExtraction(
+                Box::new(
+                    TableExtractionInput {
+                        table_id,
+                        block_nr,
+                        contract,
+                        value_nodes,
+                        length_slot,
+                        variable_slot,
+                        length_nodes,
+                        storage_root,
+                        contract_nodes,
+                        rlp_header,
+                    },
+                ),
+            ),
+        )
+    }
+
     pub fn db_cell_leaf(
         table_id: TableId,
         row_id: String,
@@ -478,6 +552,35 @@ impl WorkerTaskType
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
+    pub fn db_row_update(
+        table_id: TableId,
+        row_id: String,
+        identifier: Identifier,
+        value: U256,
+        is_multiplier: bool,
+        cells: Vec<CellNode>,
+        is_child_left: bool,
+        child_proofs_locations: Vec<db_keys::ProofKey>,
+    ) -> WorkerTaskType
+    {
+        WorkerTaskType::Database(
+            DatabaseType::RowUpdate(
+                db_tasks::RowUpdateInput {
+                    table_id,
+                    row_id,
+                    identifier,
+                    value,
+                    is_multiplier,
+                    cells,
+                    is_child_left,
+                    child_proofs_locations,
+                    child_proofs: vec![],
+                },
+            ),
+        )
+    }
+
     pub fn ivc(
         table_id: TableId,
         block_nr: BlockNr,
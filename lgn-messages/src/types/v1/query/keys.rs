@@ -1,9 +1,14 @@
 use std::fmt::Display;
+use std::str::FromStr;
 
 use object_store::path::Path;
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
 
+use crate::types::v1::key_grammar::canonical;
+use crate::types::v1::key_grammar::ProofKeyParseError;
+use crate::types::v1::key_grammar::Segments;
+
 pub(crate) const KEYS_QUERIES_PREFIX: &str = "V1_QUERIES";
 
 type QueryId = String;
@@ -16,6 +21,8 @@ const ROWS_TREE: &str = "rows_tree";
 
 const INDEX_TREE: &str = "index_tree";
 
+const REVELATION: &str = "revelation";
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Ord, Eq, Hash, Serialize, Deserialize)]
 pub enum ProofKey
 {
@@ -63,7 +70,7 @@ impl Display for ProofKey
             {
                 write!(
                     f,
-                    "{}/{}/revelation",
+                    "{}/{}/{REVELATION}",
                     KEYS_QUERIES_PREFIX, query_id
                 )
             },
@@ -71,6 +78,50 @@ impl Display for ProofKey
     }
 }
 
+/// Parses the canonical form rendered by `Display`, see
+/// [`key_grammar`](crate::types::v1::key_grammar).
+impl FromStr for ProofKey
+{
+    type Err = ProofKeyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let mut segments = Segments::new(s);
+        segments.expect(KEYS_QUERIES_PREFIX)?;
+        let query_id = segments
+            .next_str("query id")?
+            .to_string();
+        let key = match segments.next_str("proof kind")?
+        {
+            ROWS_TREE =>
+            {
+                ProofKey::Row(
+                    query_id,
+                    segments.parse("block number")?,
+                    segments
+                        .next_str("row key id")?
+                        .to_string(),
+                )
+            },
+            INDEX_TREE =>
+            {
+                ProofKey::Index(
+                    query_id,
+                    segments.parse("block number")?,
+                )
+            },
+            REVELATION => ProofKey::Revelation(query_id),
+            kind => return Err(segments.error(format!("unknown proof kind `{kind}`"))),
+        };
+        segments.finish()?;
+
+        canonical(
+            s,
+            key,
+        )
+    }
+}
+
 impl From<ProofKey> for Path
 {
     fn from(key: ProofKey) -> Self
@@ -1,19 +1,31 @@
+#[cfg(feature = "tasks")]
 use std::collections::HashMap;
 
+#[cfg(feature = "tasks")]
 use alloy_primitives::U256;
+#[cfg(feature = "tasks")]
 use derive_debug_plus::Dbg;
+#[cfg(feature = "tasks")]
 use serde_derive::Deserialize;
+#[cfg(feature = "tasks")]
 use serde_derive::Serialize;
+#[cfg(feature = "tasks")]
 use verifiable_db::query::computational_hash_ids::PlaceholderIdentifier;
+#[cfg(feature = "tasks")]
 use verifiable_db::query::universal_circuit::universal_circuit_inputs::Placeholders;
 
+#[cfg(feature = "tasks")]
 use crate::types::v1::query::tasks::QueryInput;
+#[cfg(feature = "tasks")]
+use crate::TableId;
 
 pub mod keys;
+#[cfg(feature = "tasks")]
 pub mod tasks;
 
 pub const ROUTING_DOMAIN: &str = "sc";
 
+#[cfg(feature = "tasks")]
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct WorkerTask
 {
@@ -22,8 +34,13 @@ pub struct WorkerTask
 
     /// What we are proving.
     pub task_type: WorkerTaskType,
+
+    /// The table being queried, used to check the task against the table schema.
+    #[serde(default)]
+    pub table_id: Option<TableId>,
 }
 
+#[cfg(feature = "tasks")]
 impl WorkerTask
 {
     #[must_use]
@@ -35,10 +52,22 @@ impl WorkerTask
         Self {
             chain_id,
             task_type,
+            table_id: None,
         }
     }
+
+    #[must_use]
+    pub fn with_table_id(
+        mut self,
+        table_id: TableId,
+    ) -> Self
+    {
+        self.table_id = Some(table_id);
+        self
+    }
 }
 
+#[cfg(feature = "tasks")]
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(tag = "type")]
 pub enum WorkerTaskType
@@ -47,9 +76,23 @@ pub enum WorkerTaskType
     Query(QueryInput),
 }
 
+#[cfg(feature = "tasks")]
 #[derive(Dbg, Clone, PartialEq, Deserialize, Serialize)]
 pub struct PlaceHolderLgn(HashMap<String, U256>);
 
+#[cfg(feature = "tasks")]
+impl PlaceHolderLgn
+{
+    /// The placeholder identifiers, `0` and `1` being the query bounds on the primary index.
+    pub fn identifiers(&self) -> impl Iterator<Item = &str>
+    {
+        self.0
+            .keys()
+            .map(String::as_str)
+    }
+}
+
+#[cfg(feature = "tasks")]
 impl From<PlaceHolderLgn> for Placeholders
 {
     fn from(ph: PlaceHolderLgn) -> Self
@@ -86,6 +129,7 @@ impl From<PlaceHolderLgn> for Placeholders
     }
 }
 
+#[cfg(feature = "tasks")]
 impl From<Placeholders> for PlaceHolderLgn
 {
     fn from(ph: Placeholders) -> Self
@@ -78,11 +78,13 @@ pub struct FullNodeInput
     pub left_child_proof_location: ProofKey,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub left_child_proof: Vec<u8>,
 
     pub right_child_proof_location: ProofKey,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub right_child_proof: Vec<u8>,
 }
 
@@ -94,6 +96,7 @@ pub struct PartialNodeInput
     pub proven_child_proof_location: ProofKey,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub proven_child_proof: Vec<u8>,
 
     pub unproven_child_info: Option<NodeInfo>,
@@ -127,6 +130,7 @@ pub struct IndexEmbeddedProofInput
     pub rows_proof_key: ProofKey,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub rows_proof: Vec<u8>,
 }
 
@@ -144,6 +148,7 @@ pub struct SinglePathBranchInput
     pub proven_child_location: ProofKey,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub proven_child_proof: Vec<u8>,
 
     pub is_rows_tree_node: bool,
@@ -163,6 +168,7 @@ pub struct SinglePathLeafInput
     pub embedded_proof_location: Option<ProofKey>,
 
     #[dbg(placeholder = "...")]
+    #[serde(deserialize_with = "crate::streaming_hash::bytes")]
     pub embedded_proof: Vec<u8>,
 }
 
@@ -0,0 +1,153 @@
+//! Parses the envelopes and replies of every released version, see `tests/compat/README.md`.
+#![cfg(feature = "tasks")]
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::MessageReplyEnvelope;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskType;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+fn corpus() -> PathBuf
+{
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/compat")
+}
+
+/// The JSON documents of `dir`, with their file name.
+fn documents(
+    dir: &Path
+) -> Vec<(
+    String,
+    String,
+)>
+{
+    let mut documents = std::fs::read_dir(dir)
+        .unwrap()
+        .map(
+            |entry| {
+                entry
+                    .unwrap()
+                    .path()
+            },
+        )
+        .filter(|path| path.extension() == Some("json".as_ref()))
+        .map(
+            |path| {
+                (
+                    path.file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned(),
+                    std::fs::read_to_string(&path).unwrap(),
+                )
+            },
+        )
+        .collect::<Vec<_>>();
+    documents.sort();
+    documents
+}
+
+/// Parses `document` as a `T`, checking that it serializes back to a document parsing the same.
+fn parse<T: DeserializeOwned + Serialize>(document: &str) -> Result<(), String>
+{
+    let parsed = serde_json::from_str::<T>(document).map_err(|e| e.to_string())?;
+    let reserialized = serde_json::to_value(&parsed).unwrap();
+    let reparsed = serde_json::from_value::<T>(reserialized.clone())
+        .map_err(|e| format!("the document does not parse once reserialized: {e}"))?;
+    if serde_json::to_value(&reparsed).unwrap() != reserialized
+    {
+        return Err("the document changes when reserialized twice".to_string());
+    }
+    Ok(())
+}
+
+fn parse_by_name(
+    name: &str,
+    document: &str,
+) -> Result<(), String>
+{
+    if name.starts_with("envelope-")
+    {
+        parse::<MessageEnvelope<TaskType>>(document)
+    }
+    else if name.starts_with("reply-")
+    {
+        parse::<MessageReplyEnvelope<ReplyType>>(document)
+    }
+    else
+    {
+        panic!("`{name}` is neither an envelope nor a reply")
+    }
+}
+
+#[test]
+fn released_documents_still_parse()
+{
+    let mut versions = 0;
+    for entry in std::fs::read_dir(corpus()).unwrap()
+    {
+        let dir = entry
+            .unwrap()
+            .path();
+        if !dir.is_dir() || dir.ends_with("rejected")
+        {
+            continue;
+        }
+        versions += 1;
+        for (name, document) in documents(&dir)
+        {
+            if let Err(err) = parse_by_name(
+                &name,
+                &document,
+            )
+            {
+                panic!(
+                    "`{}/{name}` no longer parses: {err}",
+                    dir.file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                );
+            }
+        }
+    }
+    assert!(
+        versions > 0,
+        "the corpus has no version"
+    );
+}
+
+#[test]
+fn rejected_documents_fail_as_intended()
+{
+    let dir = corpus().join("rejected");
+    for (name, document) in documents(&dir)
+    {
+        let expected = std::fs::read_to_string(
+            dir.join(
+                name.replace(
+                    ".json",
+                    ".error",
+                ),
+            ),
+        )
+        .unwrap();
+        let expected = expected.trim();
+        match parse_by_name(
+            &name,
+            &document,
+        )
+        {
+            Ok(()) => panic!("`rejected/{name}` parses"),
+            Err(err) =>
+            {
+                assert!(
+                    err.contains(expected),
+                    "`rejected/{name}` failed with `{err}` instead of `{expected}`"
+                )
+            },
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Compares the reads of a params file through io_uring with those of the `BufReader` the workers
+//! use otherwise, both hashing the file, as the params are verified, and reading it through, as
+//! they are deserialized.
+//!
+//! ```text
+//! cargo bench -p lgn-provers --features io-uring --bench params_read -- [<file>]
+//! ```
+//!
+//! Run it on the disk of the params, e.g. with one of them as `<file>`; without one, a file of
+//! `LGN_BENCH_PARAMS_MB` MiB, 1024 by default, is written in the temporary directory. Run as root,
+//! the page cache is dropped before each read so that the disk is measured rather than the memory;
+//! otherwise the results only tell the overhead of each reader.
+
+#[cfg(target_os = "linux")]
+fn main()
+{
+    use std::fs::File;
+    use std::io;
+    use std::io::BufReader;
+    use std::io::Read;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::time::Instant;
+
+    use lgn_provers::params::uring::UringReader;
+
+    const ROUNDS: usize = 5;
+    const BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+    let (path, generated) = match std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+    {
+        Some(path) =>
+        {
+            (
+                PathBuf::from(path),
+                false,
+            )
+        },
+        None =>
+        {
+            let mb = std::env::var("LGN_BENCH_PARAMS_MB")
+                .ok()
+                .and_then(
+                    |mb| {
+                        mb.parse::<usize>()
+                            .ok()
+                    },
+                )
+                .unwrap_or(1024);
+            let path = std::env::temp_dir().join("lgn-params-read-bench.bin");
+            let mut file = File::create(&path).expect("failed to create the bench file");
+            let chunk = (0..1024 * 1024)
+                .map(|i| (i % 251) as u8)
+                .collect::<Vec<_>>();
+            for _ in 0..mb
+            {
+                file.write_all(&chunk)
+                    .expect("failed to write the bench file");
+            }
+            file.sync_all()
+                .expect("failed to sync the bench file");
+            (
+                path,
+                true,
+            )
+        },
+    };
+    let size = std::fs::metadata(&path)
+        .expect("failed to stat the bench file")
+        .len();
+
+    let cold = std::fs::write(
+        "/proc/sys/vm/drop_caches",
+        "3",
+    )
+    .is_ok();
+    if !cold
+    {
+        println!(
+            "Not root: the page cache is kept, the reads hit the memory rather than the disk."
+        );
+    }
+    let open = |uring: bool| -> Box<dyn Read> {
+        if cold
+        {
+            let _ = std::fs::write(
+                "/proc/sys/vm/drop_caches",
+                "3",
+            );
+        }
+        let file = File::open(&path).expect("failed to open the bench file");
+        if uring
+        {
+            Box::new(
+                UringReader::new(file)
+                    .unwrap_or_else(|_| panic!("io_uring is unavailable on this host")),
+            )
+        }
+        else
+        {
+            Box::new(
+                BufReader::with_capacity(
+                    BUFFER_SIZE,
+                    file,
+                ),
+            )
+        }
+    };
+
+    for (workload, hash) in [
+        (
+            "hash",
+            true,
+        ),
+        (
+            "read",
+            false,
+        ),
+    ]
+    {
+        for (reader, uring) in [
+            (
+                "BufReader",
+                false,
+            ),
+            (
+                "io_uring",
+                true,
+            ),
+        ]
+        {
+            let mut throughputs = (0..ROUNDS)
+                .map(
+                    |_| {
+                        let mut input = open(uring);
+                        let started = Instant::now();
+                        let copied = if hash
+                        {
+                            io::copy(
+                                &mut input,
+                                &mut blake3::Hasher::new(),
+                            )
+                        }
+                        else
+                        {
+                            io::copy(
+                                &mut input,
+                                &mut io::sink(),
+                            )
+                        }
+                        .expect("failed to read the bench file");
+                        assert_eq!(
+                            copied,
+                            size
+                        );
+                        size as f64
+                            / (1024.0 * 1024.0)
+                            / started
+                                .elapsed()
+                                .as_secs_f64()
+                    },
+                )
+                .collect::<Vec<_>>();
+            throughputs.sort_by(f64::total_cmp);
+            println!(
+                "{workload:<4} {reader:<9} median {:>8.0} MiB/s, min {:>8.0}, max {:>8.0}",
+                throughputs[ROUNDS / 2],
+                throughputs[0],
+                throughputs[ROUNDS - 1]
+            );
+        }
+    }
+
+    if generated
+    {
+        let _ = std::fs::remove_file(&path);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main()
+{
+    println!("io_uring is only available on Linux.");
+}
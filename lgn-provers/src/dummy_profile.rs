@@ -0,0 +1,216 @@
+//! Shaping of the dummy provers replies, so that load tests with dummy workers reflect the
+//! latency and proof sizes of production workers.
+//!
+//! The distributions are keyed by task type, e.g. `row_leaf`, or by prover family, e.g. `query`,
+//! for the task types of the family without their own.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+/// The prover families, with their task types.
+pub const TASK_TYPES: &[(
+    &str,
+    &[&str],
+)] = &[
+    (
+        "preprocessing",
+        &[
+            "single_variable_leaf",
+            "single_variable_branch",
+            "mapping_variable_leaf",
+            "mapping_variable_branch",
+            "length_leaf",
+            "length_branch",
+            "contract_leaf",
+            "contract_branch",
+            "block",
+            "final_extraction_simple",
+            "final_extraction_lengthed",
+            "final_extraction_merge",
+            "cell_leaf",
+            "cell_partial",
+            "cell_full",
+            "row_leaf",
+            "row_partial",
+            "row_full",
+            "membership",
+            "block_leaf",
+            "block_parent",
+            "ivc",
+        ],
+    ),
+    (
+        "query",
+        &[
+            "universal_circuit",
+            "full_node",
+            "partial_node",
+            "single_path_leaf",
+            "single_path_branch",
+            "aggregated_revelation",
+            "tabular_revelation",
+            "non_existence",
+        ],
+    ),
+    (
+        "groth16",
+        &["groth16"],
+    ),
+];
+
+/// Whether `key` names a prover family or one of their task types.
+pub fn is_known(key: &str) -> bool
+{
+    TASK_TYPES
+        .iter()
+        .any(|(family, tasks)| *family == key || tasks.contains(&key))
+}
+
+/// A normal distribution, truncated at zero.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Distribution
+{
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+#[cfg(feature = "dummy-prover")]
+impl Distribution
+{
+    fn sample(&self) -> f64
+    {
+        // Box-Muller transform, `u1` must not be zero for the logarithm.
+        let u1 = rand::random::<f64>().max(f64::MIN_POSITIVE);
+        let u2 = rand::random::<f64>();
+        let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+
+        (self.mean + z * self.stddev).max(0.0)
+    }
+}
+
+/// How the dummy prover of a family simulates proving its tasks.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DummyProfile
+{
+    /// The prover family, whose distributions apply to its task types without their own.
+    pub family: &'static str,
+
+    /// Proving time in milliseconds, proofs are returned instantly if unset.
+    pub latency_ms: BTreeMap<String, Distribution>,
+
+    /// Proof size in bytes, the dummy prover default size is used if unset.
+    pub proof_size: BTreeMap<String, Distribution>,
+}
+
+impl DummyProfile
+{
+    /// The distribution of `profile` for `task`, else for the family.
+    #[cfg(
+        any(
+            test,
+            feature = "dummy-prover"
+        )
+    )]
+    fn of(
+        &self,
+        profile: &BTreeMap<String, Distribution>,
+        task: &str,
+    ) -> Option<Distribution>
+    {
+        profile
+            .get(task)
+            .or_else(|| profile.get(self.family))
+            .copied()
+    }
+}
+
+#[cfg(feature = "dummy-prover")]
+impl DummyProfile
+{
+    /// Waits for a latency sampled for `task`, then returns a random proof of a sampled size.
+    pub(crate) fn proof(
+        &self,
+        task: &str,
+        default_size: usize,
+    ) -> Vec<u8>
+    {
+        if let Some(latency) = self.of(
+            &self.latency_ms,
+            task,
+        )
+        {
+            std::thread::sleep(std::time::Duration::from_millis(latency.sample() as u64));
+        }
+        let size = self
+            .of(
+                &self.proof_size,
+                task,
+            )
+            .map_or(
+                default_size,
+                |size| size.sample() as usize,
+            );
+
+        crate::dummy_utils::dummy_proof(size)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn a_task_type_overrides_its_family()
+    {
+        let family = Distribution {
+            mean: 2000.0,
+            stddev: 500.0,
+        };
+        let row_leaf = Distribution {
+            mean: 300.0,
+            stddev: 50.0,
+        };
+        let profile = DummyProfile {
+            family: "preprocessing",
+            latency_ms: [
+                (
+                    "preprocessing".to_string(),
+                    family,
+                ),
+                (
+                    "row_leaf".to_string(),
+                    row_leaf,
+                ),
+            ]
+            .into(),
+            proof_size: BTreeMap::new(),
+        };
+
+        assert_eq!(
+            profile.of(
+                &profile.latency_ms,
+                "row_leaf"
+            ),
+            Some(row_leaf)
+        );
+        assert_eq!(
+            profile.of(
+                &profile.latency_ms,
+                "cell_leaf"
+            ),
+            Some(family)
+        );
+        assert_eq!(
+            profile.of(
+                &profile.proof_size,
+                "row_leaf"
+            ),
+            None
+        );
+        assert!(is_known("row_leaf"));
+        assert!(is_known("query"));
+        assert!(!is_known("row_leaves"));
+    }
+}
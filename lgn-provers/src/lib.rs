@@ -1,4 +1,6 @@
 #![feature(generic_const_exprs)]
+pub mod dummy_profile;
+pub mod log_sampling;
 pub mod params;
 pub mod provers;
 
@@ -0,0 +1,184 @@
+//! The rate limit of the debug logs of the proving hot paths, e.g. the lines of each MPT node.
+//!
+//! With the debug logs enabled during an incident, a task logs a few lines per node it proves,
+//! which floods the output and slows proving. The events logged with [`sampled_debug!`] are
+//! limited to [`max_per_minute`] per key, e.g. the proof type, each minute: the next ones of the
+//! minute are dropped, and the first event logged after them carries how many were, as its
+//! `suppressed` field. A limit of `0` logs every event.
+//!
+//! The limit is changed at runtime with [`set_max_per_minute`], e.g. from the admin endpoint of
+//! the worker. Nothing is counted while the debug logs are disabled.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use metrics::counter;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// The default limit, low enough for an incident not to flood the output.
+pub const DEFAULT_MAX_PER_MINUTE: u32 = 10;
+
+static MAX_PER_MINUTE: AtomicU32 = AtomicU32::new(DEFAULT_MAX_PER_MINUTE);
+
+static WINDOWS: Mutex<BTreeMap<String, Window>> = Mutex::new(BTreeMap::new());
+
+/// The events of a key in the current minute.
+struct Window
+{
+    started: Instant,
+    logged: u32,
+    suppressed: u64,
+}
+
+/// Logs at debug level, within the limit of `key`, see the [module](self).
+#[macro_export]
+macro_rules! sampled_debug {
+    ($key:expr, $($arg:tt)+) => {
+        if tracing::enabled!(tracing::Level::DEBUG)
+        {
+            if let Some(suppressed) = $crate::log_sampling::admit($key)
+            {
+                tracing::debug!(suppressed, $($arg)+);
+            }
+        }
+    };
+}
+
+/// The events logged per key each minute, `0` for all of them.
+pub fn max_per_minute() -> u32
+{
+    MAX_PER_MINUTE.load(Ordering::Relaxed)
+}
+
+pub fn set_max_per_minute(max: u32)
+{
+    MAX_PER_MINUTE.store(
+        max,
+        Ordering::Relaxed,
+    );
+}
+
+/// Whether to log an event of `key`, with how many of its events were dropped since the last one
+/// logged if so.
+#[doc(hidden)]
+pub fn admit(key: &str) -> Option<u64>
+{
+    admit_at(
+        key,
+        Instant::now(),
+        max_per_minute(),
+    )
+}
+
+fn admit_at(
+    key: &str,
+    now: Instant,
+    max: u32,
+) -> Option<u64>
+{
+    if max == 0
+    {
+        return Some(0);
+    }
+    let mut windows = WINDOWS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let window = windows
+        .entry(key.to_string())
+        .or_insert(
+            Window {
+                started: now,
+                logged: 0,
+                suppressed: 0,
+            },
+        );
+    if now.duration_since(window.started) >= WINDOW
+    {
+        window.started = now;
+        window.logged = 0;
+    }
+    if window.logged >= max
+    {
+        window.suppressed += 1;
+        counter!("zkmr_worker_logs_suppressed_total").increment(1);
+        return None;
+    }
+    window.logged += 1;
+    Some(std::mem::take(&mut window.suppressed))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn limits_the_events_of_each_key_per_minute()
+    {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(
+            admit_at(
+                "sampling-a",
+                at(0),
+                2
+            ),
+            Some(0)
+        );
+        assert_eq!(
+            admit_at(
+                "sampling-a",
+                at(1),
+                2
+            ),
+            Some(0)
+        );
+        assert_eq!(
+            admit_at(
+                "sampling-a",
+                at(2),
+                2
+            ),
+            None
+        );
+        assert_eq!(
+            admit_at(
+                "sampling-b",
+                at(2),
+                2
+            ),
+            Some(0)
+        );
+        assert_eq!(
+            admit_at(
+                "sampling-a",
+                at(3),
+                2
+            ),
+            None
+        );
+        // The next minute, the first event tells how many were dropped.
+        assert_eq!(
+            admit_at(
+                "sampling-a",
+                at(61),
+                2
+            ),
+            Some(2)
+        );
+        assert_eq!(
+            admit_at(
+                "sampling-a",
+                at(62),
+                0
+            ),
+            Some(0)
+        );
+    }
+}
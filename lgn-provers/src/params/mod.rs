@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::error::Error;
 use std::fs::File;
@@ -8,6 +9,14 @@ use std::io::Read;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 
 use anyhow::*;
 use bytes::Bytes;
@@ -15,18 +24,373 @@ use checksums::ops::compare_hashes;
 use checksums::ops::create_hashes;
 use checksums::ops::read_hashes;
 use checksums::ops::write_hash_comparison_results;
+use lgn_messages::types::error_code::ErrorCode;
+use metrics::counter;
+use metrics::gauge;
+use resume::Partial;
+pub use shape::CircuitShape;
+use throttle::Backoff;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
+use tracing::warn;
+
+mod resume;
+mod shape;
+mod throttle;
+#[cfg(
+    all(
+        target_os = "linux",
+        feature = "io-uring"
+    )
+)]
+pub mod uring;
 
 pub struct ParamsLoader;
 
 // Could make configurable but 3600 should be enough
 const HTTP_TIMEOUT: u64 = 3600;
 const DOWNLOAD_MAX_RETRIES: u8 = 3;
+const DOWNLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+const DOWNLOAD_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+/// Large reads reduce the number of syscalls while streaming multi-GB params.
+const DESERIALIZE_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+/// The files verified by [`ParamsLoader::prefetch`], with their size and modification time at the
+/// time, so that loading them does not hash them again unless they changed.
+static VERIFIED: Mutex<BTreeMap<PathBuf, FileStamp>> = Mutex::new(BTreeMap::new());
+
+/// The operator-local mirror the params are downloaded from first, see
+/// [`ParamsLoader::prefer_mirror`].
+static MIRROR_URL: OnceLock<String> = OnceLock::new();
+
+/// Told of the progress of the loading of the params files, see
+/// [`ParamsLoader::observe_progress`].
+static PROGRESS_OBSERVER: OnceLock<fn(&Path, LoadPhase)> = OnceLock::new();
+
+/// When the params downloads must have completed, see [`ParamsLoader::set_download_deadline`].
+static DOWNLOAD_DEADLINE: OnceLock<(
+    Instant,
+    Duration,
+)> = OnceLock::new();
+
+type FileStamp = (
+    u64,
+    SystemTime,
+);
+
+fn file_stamp(file: &Path) -> Option<FileStamp>
+{
+    let metadata = fs::metadata(file).ok()?;
+    Some(
+        (
+            metadata.len(),
+            metadata
+                .modified()
+                .ok()?,
+        ),
+    )
+}
+
+/// What is being done to load a params file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadPhase
+{
+    /// The file on disk is hashed against its checksum.
+    Verifying,
+    /// The file is downloaded, `total` bytes being unknown until the server answered.
+    Downloading
+    {
+        downloaded: u64,
+        total: Option<u64>,
+    },
+    /// The file is read into memory.
+    Deserializing,
+}
+
+fn report(
+    file: &Path,
+    phase: LoadPhase,
+)
+{
+    if let Some(observer) = PROGRESS_OBSERVER.get()
+    {
+        observer(
+            file,
+            phase,
+        );
+    }
+}
+
+/// Errors caused by the public parameters themselves, rather than by the task being proven.
+#[derive(thiserror::Error, Debug)]
+pub enum ParamsError
+{
+    /// An artifact which should be derivable from the params could not be computed, the params
+    /// are likely corrupted or of an incompatible version.
+    #[error(
+        "failed to derive `{artifact}` from the public params, they may be corrupted or of an \
+         incompatible version: {reason}"
+    )]
+    Artifact
+    {
+        artifact: &'static str,
+        reason: String,
+    },
+
+    /// The params were built for circuits of another shape, they would deserialize but fail
+    /// every proof.
+    #[error(
+        "`{file}` was built for circuits of another shape than this worker, it disagrees on \
+         {mismatches}; the worker was compiled for {compiled}, the params were built for \
+         {params}"
+    )]
+    Shape
+    {
+        file: String,
+        mismatches: String,
+        compiled: CircuitShape,
+        params: CircuitShape,
+    },
+
+    /// The params download did not complete within the deadline, the downloaded part is kept for
+    /// the next start to resume.
+    #[error(
+        "`{file}` could not be downloaded within the {deadline_secs}s download deadline, \
+         {downloaded} bytes of {} kept to resume from",
+        total.map_or("an unknown size".to_string(), |total| total.to_string())
+    )]
+    DownloadDeadline
+    {
+        file: String,
+        downloaded: u64,
+        total: Option<u64>,
+        deadline_secs: u64,
+    },
+}
+
+impl ParamsError
+{
+    /// The catalog code of the error.
+    pub fn as_code(&self) -> ErrorCode
+    {
+        match self
+        {
+            ParamsError::Artifact {
+                ..
+            } => ErrorCode::ParamsArtifact,
+            ParamsError::Shape {
+                ..
+            } => ErrorCode::ParamsShape,
+            ParamsError::DownloadDeadline {
+                ..
+            } => ErrorCode::ParamsDownloadDeadline,
+        }
+    }
+}
 
 impl ParamsLoader
 {
+    /// Computes an artifact derived from loaded params, e.g. the empty cells tree proof.
+    ///
+    /// Meant to be called once when the prover is created, so that broken params are detected at
+    /// startup instead of failing tasks. The derivation is deterministic, it is not retried.
+    pub fn derive_artifact<T>(
+        artifact: &'static str,
+        derive: impl FnOnce() -> anyhow::Result<T>,
+    ) -> std::result::Result<T, ParamsError>
+    {
+        let value = derive().map_err(
+            |err| {
+                ParamsError::Artifact {
+                    artifact,
+                    reason: format!("{err:#}"),
+                }
+            },
+        )?;
+        debug!("Derived `{artifact}` from the public params");
+        std::result::Result::Ok(value)
+    }
+
+    /// Checks that `file_name` was built for the `compiled` shape of the circuits, before loading
+    /// it.
+    ///
+    /// The shape is fetched on each start from the `<file_name>.shape.json` published next to the
+    /// params, so that it follows their updates. Params published without their shape are loaded
+    /// unchecked.
+    pub fn check_shape(
+        base_url: &str,
+        file_name: &str,
+        compiled: &CircuitShape,
+    ) -> anyhow::Result<()>
+    {
+        let shape_file = CircuitShape::file_name(file_name);
+        // The mirror only serves the files of the checksums, the shape comes from the origin.
+        let content = match Self::download_from(
+            base_url,
+            &shape_file,
+        )
+        {
+            Result::Ok(content) => content,
+            Err(err) =>
+            {
+                warn!("The shape of `{file_name}` is not published, loading it unchecked: {err:?}");
+                counter!("zkmr_worker_params_shape_unchecked_total", "file" => file_name.to_string())
+                    .increment(1);
+                return Ok(());
+            },
+        };
+        let params = serde_json::from_slice::<CircuitShape>(&content)
+            .with_context(|| format!("`{shape_file}` is not a circuit shape"))?;
+
+        let mismatches = compiled.mismatches(&params);
+        if !mismatches.is_empty()
+        {
+            return Err(
+                ParamsError::Shape {
+                    file: file_name.to_string(),
+                    mismatches: mismatches.join(", "),
+                    compiled: compiled.clone(),
+                    params,
+                }
+                .into(),
+            );
+        }
+        info!("`{file_name}` matches the compiled circuits: {compiled}");
+        Ok(())
+    }
+
+    /// Verifies the present `file_names` and downloads the missing or corrupted ones ahead of the
+    /// provers creation, which then loads them without hashing them again.
+    ///
+    /// Up to `parallelism` files are hashed at once, which fast disks sustain, while downloads
+    /// happen one at a time and overlap with the hashing. Failures are only logged, loading the
+    /// params retries on its own.
+    pub fn prefetch(
+        base_url: &str,
+        base_dir: &str,
+        file_names: &[&str],
+        checksum_expected_local_path: &str,
+        parallelism: usize,
+    )
+    {
+        let start = Instant::now();
+        let next = AtomicUsize::new(0);
+        let (to_download, downloads) = mpsc::channel::<&str>();
+        std::thread::scope(
+            |scope| {
+                for _ in 0..parallelism
+                    .max(1)
+                    .min(file_names.len())
+                {
+                    let next = &next;
+                    let to_download = to_download.clone();
+                    scope.spawn(
+                        move || {
+                            while let Some(file_name) = file_names.get(
+                                next.fetch_add(
+                                    1,
+                                    Ordering::Relaxed,
+                                ),
+                            )
+                            {
+                                if !Self::verify_and_record(
+                                    base_dir,
+                                    file_name,
+                                    checksum_expected_local_path,
+                                )
+                                {
+                                    let _ = to_download.send(file_name);
+                                }
+                            }
+                        },
+                    );
+                }
+                drop(to_download);
+
+                scope.spawn(
+                    move || {
+                        for file_name in downloads
+                        {
+                            let file = Path::new(base_dir).join(file_name);
+                            if let Err(err) = Self::download_to(
+                                base_url,
+                                file_name,
+                                &file,
+                            )
+                            {
+                                warn!("Failed to prefetch `{file_name}`: {err:?}");
+                                continue;
+                            }
+                            // Hashing the downloaded file overlaps with the next download.
+                            scope.spawn(
+                                move || {
+                                    if !Self::verify_and_record(
+                                        base_dir,
+                                        file_name,
+                                        checksum_expected_local_path,
+                                    )
+                                    {
+                                        warn!("The downloaded `{file_name}` does not match its checksum");
+                                    }
+                                },
+                            );
+                        }
+                    },
+                );
+            },
+        );
+
+        info!(
+            "Prefetched {} params files in {:?}",
+            file_names.len(),
+            start.elapsed()
+        );
+        gauge!("zkmr_worker_params_prefetch_seconds").set(
+            start
+                .elapsed()
+                .as_secs_f64(),
+        );
+    }
+
+    /// Verifies `file_name`, recording it as verified if it matches its checksum.
+    fn verify_and_record(
+        base_dir: &str,
+        file_name: &str,
+        checksum_expected_local_path: &str,
+    ) -> bool
+    {
+        let file = Path::new(base_dir).join(file_name);
+        match Self::verify_file_checksum(
+            file_name,
+            &file,
+            checksum_expected_local_path,
+            false,
+        )
+        {
+            Result::Ok(true) =>
+            {
+                if let Some(stamp) = file_stamp(&file)
+                {
+                    VERIFIED
+                        .lock()
+                        .expect("verified params lock poisoned")
+                        .insert(
+                            file,
+                            stamp,
+                        );
+                }
+                true
+            },
+            Result::Ok(false) => false,
+            Err(err) =>
+            {
+                warn!("Failed to verify `{file_name}`: {err:?}");
+                false
+            },
+        }
+    }
+
     pub fn prepare_bincode<P: for<'a> serde::de::Deserialize<'a>>(
         base_url: &str,
         base_dir: &str,
@@ -46,6 +410,10 @@ impl ParamsLoader
             file_path
         );
         let mut retries = 0;
+        let mut backoff = Backoff::new(
+            throttle::RETRY_BASE,
+            throttle::RETRY_CAP,
+        );
         loop
         {
             debug!(
@@ -81,7 +449,11 @@ impl ParamsLoader
                         "Loading params from local storage {:?}",
                         file_path
                     );
-                    let reader = std::io::BufReader::new(
+                    report(
+                        &file_path,
+                        LoadPhase::Deserializing,
+                    );
+                    let reader = sequential_reader(
                         File::open(&file_path).with_context(
                             || {
                                 format!(
@@ -92,13 +464,9 @@ impl ParamsLoader
                         )?,
                     );
 
-                    return bincode::deserialize_from(reader).map_err(
-                        |e| {
-                            anyhow!(
-                                "failed to deserialize: {:?}",
-                                e.source()
-                            )
-                        },
+                    return Self::deserialize_bincode(
+                        file_name,
+                        reader,
                     );
                 },
                 _ =>
@@ -106,32 +474,104 @@ impl ParamsLoader
                     info!("public params are not locally stored yet, or checksum mismatch");
                     retries += 1;
 
-                    let params = Self::download_file(
-                        base_url,
-                        file_name,
-                    )?;
-                    if skip_checksum
+                    let downloaded = if skip_store
                     {
-                        info!(
-                            "skipping checksum and loading file from local storage {:?}",
-                            file_path
+                        report(
+                            &file_path,
+                            LoadPhase::Downloading {
+                                downloaded: 0,
+                                total: None,
+                            },
                         );
-                        let reader = std::io::BufReader::new(params.as_ref());
-                        return bincode::deserialize_from(reader).map_err(Into::into);
+                        match Self::download_file(
+                            base_url,
+                            file_name,
+                        )
+                        {
+                            Result::Ok(params) if skip_checksum =>
+                            {
+                                info!("skipping checksum and store, loading params from memory");
+                                report(
+                                    &file_path,
+                                    LoadPhase::Deserializing,
+                                );
+                                return Self::deserialize_bincode(
+                                    file_name,
+                                    params.as_ref(),
+                                );
+                            },
+                            result => result.map(drop),
+                        }
                     }
-                    if !skip_store
+                    else
                     {
-                        Self::store_file(
+                        // Streamed to the disk, the next attempt deserializes the params from the
+                        // stored file without the download being resident as well.
+                        Self::download_to(
+                            base_url,
+                            file_name,
                             &file_path,
-                            &params,
                         )
-                        .context("Failed to store params to local storage")?;
+                    };
+                    if let Err(err) = downloaded
+                    {
+                        Self::back_off(
+                            &mut backoff,
+                            retries,
+                            file_name,
+                            err,
+                        )?;
                     }
                 },
             }
         }
     }
 
+    /// Deserializes bincode params from `reader`, logging the peak memory usage of the process
+    /// before and after, so that regressions of the loading footprint are visible.
+    ///
+    /// The params are read from `reader` as they are deserialized, a stored file never being
+    /// resident in full, but bincode still builds the intermediate vectors of the params: they are
+    /// not deserialized section by section into preallocated structures, which the mp2 API does
+    /// not allow. With `skip_store` and `skip_checksum`, the download is resident as well.
+    fn deserialize_bincode<P: for<'a> serde::de::Deserialize<'a>>(
+        file_name: &str,
+        reader: impl Read,
+    ) -> anyhow::Result<P>
+    {
+        let peak_before = peak_rss_bytes();
+        let start = Instant::now();
+        let params = bincode::deserialize_from(reader).map_err(
+            |e| {
+                anyhow!(
+                    "failed to deserialize `{file_name}`: {:?}",
+                    e.source()
+                )
+            },
+        )?;
+
+        let peak_after = peak_rss_bytes();
+        info!(
+            "Deserialized {file_name} in {:?}. peak RSS before: {} MB, after: {} MB",
+            start.elapsed(),
+            peak_before.map_or(
+                "n/a".to_string(),
+                |b| (b / (1024 * 1024)).to_string()
+            ),
+            peak_after.map_or(
+                "n/a".to_string(),
+                |b| (b / (1024 * 1024)).to_string()
+            ),
+        );
+        if let Some(peak) = peak_after
+        {
+            gauge!("zkmr_worker_params_peak_rss_bytes", "file" => file_name.to_string())
+                .set(peak as f64);
+        }
+
+        Ok(params)
+    }
+
     pub fn prepare_raw(
         base_url: &str,
         base_dir: &str,
@@ -157,6 +597,10 @@ impl ParamsLoader
             file
         );
         let mut retries = 0;
+        let mut backoff = Backoff::new(
+            throttle::RETRY_BASE,
+            throttle::RETRY_CAP,
+        );
         loop
         {
             debug!(
@@ -188,6 +632,10 @@ impl ParamsLoader
             {
                 Result::Ok(true) =>
                 {
+                    report(
+                        &file,
+                        LoadPhase::Deserializing,
+                    );
                     let file = File::open(&file)?;
                     return Self::read_file(file);
                 },
@@ -197,29 +645,334 @@ impl ParamsLoader
                     info!("public params are not locally stored yet, or checksum mismatch");
                     retries += 1;
 
-                    let params = Self::download_file(
-                        base_url,
-                        file_name,
-                    )?;
-                    if !skip_store
+                    let downloaded = if skip_store
                     {
-                        Self::store_file(
+                        report(
                             &file,
-                            &params,
+                            LoadPhase::Downloading {
+                                downloaded: 0,
+                                total: None,
+                            },
+                        );
+                        Self::download_file(
+                            base_url,
+                            file_name,
                         )
-                        .context("Failed to store params to local storage")?;
+                        .map(drop)
+                    }
+                    else
+                    {
+                        Self::download_to(
+                            base_url,
+                            file_name,
+                            &file,
+                        )
+                    };
+                    if let Err(err) = downloaded
+                    {
+                        Self::back_off(
+                            &mut backoff,
+                            retries,
+                            file_name,
+                            err,
+                        )?;
                     }
                 },
             }
         }
     }
 
+    /// Waits before the next attempt to download `file_name`, or fails with `err` if there is
+    /// none left.
+    fn back_off(
+        backoff: &mut Backoff,
+        retries: u8,
+        file_name: &str,
+        err: anyhow::Error,
+    ) -> anyhow::Result<()>
+    {
+        if retries >= DOWNLOAD_MAX_RETRIES || err.is::<ParamsError>()
+        {
+            return Err(err);
+        }
+        let delay = backoff
+            .next()
+            .max(throttle::retry_in(&err));
+        warn!("Failed to download `{file_name}`, retrying in {delay:?}: {err:?}");
+        std::thread::sleep(delay);
+        Ok(())
+    }
+
+    /// Tells `observer` of the progress of the loading of each params file, by its path, e.g. to
+    /// report the progress of the startup. The downloads are reported at most every
+    /// `DOWNLOAD_CHUNK_SIZE` bytes.
+    pub fn observe_progress(observer: fn(&Path, LoadPhase))
+    {
+        let _ = PROGRESS_OBSERVER.set(observer);
+    }
+
+    /// Caps the params downloads running at once, from all the hosts, so that a recovering host
+    /// is not hit by every file at the same time. Defaults to 2.
+    pub fn set_max_concurrent_downloads(max: usize)
+    {
+        info!("Downloading at most {max} params files at once");
+        throttle::set_max_concurrent_downloads(max);
+    }
+
+    /// Downloads the params from `mirror_url`, e.g. an `lgn-params-mirror`, rather than from the
+    /// origin, which is only used when the mirror fails.
+    pub fn prefer_mirror(mirror_url: &str)
+    {
+        let mirror_url = mirror_url.trim_end_matches('/');
+        info!("Preferring the params mirror {mirror_url}");
+        let _ = MIRROR_URL.set(mirror_url.to_string());
+    }
+
+    /// Fails the params downloads still running `deadline` from now, keeping what they downloaded
+    /// for the next start to resume.
+    pub fn set_download_deadline(deadline: Duration)
+    {
+        info!("The params must be downloaded within {deadline:?}");
+        let _ = DOWNLOAD_DEADLINE.set(
+            (
+                Instant::now() + deadline,
+                deadline,
+            ),
+        );
+    }
+
+    /// The params mirror, if any.
+    pub fn mirror_url() -> Option<&'static str>
+    {
+        MIRROR_URL
+            .get()
+            .map(String::as_str)
+    }
+
+    /// The URL `file_name` is downloaded from under `base_url`, its path segments percent-encoded.
+    pub fn file_url(
+        base_url: &str,
+        file_name: &str,
+    ) -> String
+    {
+        let base_url = base_url.trim_end_matches('/');
+        let Some(mut url) = reqwest::Url::parse(base_url).ok()
+        else
+        {
+            return format!("{base_url}/{file_name}");
+        };
+        if let Some(mut segments) = url
+            .path_segments_mut()
+            .ok()
+        {
+            segments
+                .pop_if_empty()
+                .extend(file_name.split('/'));
+        }
+        url.to_string()
+    }
+
+    /// Downloads `file_name` from the mirror, falling back to `base_url`.
     fn download_file(
         base_url: &str,
         file_name: &str,
     ) -> anyhow::Result<Bytes>
     {
-        let file_url = format!("{base_url}/{file_name}");
+        if let Some(mirror_url) = Self::mirror_url()
+        {
+            match Self::download_from(
+                mirror_url,
+                file_name,
+            )
+            {
+                Result::Ok(params) => return Ok(params),
+                Err(err) if err.is::<ParamsError>() => return Err(err),
+                Err(err) =>
+                {
+                    warn!("Failed to download `{file_name}` from the mirror, falling back to the origin: {err:?}");
+                    counter!("zkmr_worker_params_mirror_fallbacks_total").increment(1);
+                },
+            }
+        }
+        Self::download_from(
+            base_url,
+            file_name,
+        )
+    }
+
+    /// Downloads `file_name` to `file`, from the mirror then from `base_url`, resuming the
+    /// download a previous attempt left partial.
+    fn download_to(
+        base_url: &str,
+        file_name: &str,
+        file: &Path,
+    ) -> anyhow::Result<()>
+    {
+        if let Some(parent) = file.parent()
+        {
+            fs::create_dir_all(parent).context("Failed to create directories for local storage")?;
+        }
+        if let Some(mirror_url) = Self::mirror_url()
+        {
+            match Self::download_resumable_from(
+                mirror_url,
+                file_name,
+                file,
+            )
+            {
+                Result::Ok(()) => return Ok(()),
+                Err(err) if err.is::<ParamsError>() => return Err(err),
+                Err(err) =>
+                {
+                    warn!("Failed to download `{file_name}` from the mirror, falling back to the origin: {err:?}");
+                    counter!("zkmr_worker_params_mirror_fallbacks_total").increment(1);
+                },
+            }
+        }
+        Self::download_resumable_from(
+            base_url,
+            file_name,
+            file,
+        )
+    }
+
+    fn download_resumable_from(
+        base_url: &str,
+        file_name: &str,
+        file: &Path,
+    ) -> anyhow::Result<()>
+    {
+        let file_url = Self::file_url(
+            base_url,
+            file_name,
+        );
+        throttle::guarded(
+            &file_url,
+            || {
+                Self::download_resumable(
+                    &file_url,
+                    file_name,
+                    file,
+                )
+            },
+        )
+    }
+
+    fn download_resumable(
+        file_url: &str,
+        file_name: &str,
+        file: &Path,
+    ) -> anyhow::Result<()>
+    {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(std::time::Duration::from_secs(HTTP_TIMEOUT))
+            .build()
+            .context("Failed to build reqwest client")?;
+
+        let partial = Partial::of(file);
+        let mut discarded = false;
+        let (mut response, mut output, resumed) = loop
+        {
+            let response = partial
+                .request(
+                    &client,
+                    file_url,
+                )
+                .context("Failed to download params from remote")?;
+            match partial.open(&response)?
+            {
+                Some((output, resumed)) =>
+                {
+                    break (
+                        response,
+                        output,
+                        resumed,
+                    )
+                },
+                None if !discarded => discarded = true,
+                None => bail!("Failed to resume the download of {file_url}"),
+            }
+        };
+        if resumed > 0
+        {
+            info!(
+                "Resuming the download of params from {file_url} at {} MB",
+                resumed / (1024 * 1024)
+            );
+            counter!("zkmr_worker_params_download_resumed_total", "file" => file_name.to_string())
+                .increment(1);
+        }
+        else
+        {
+            info!("Downloading params from {file_url}");
+        }
+
+        let mut progress = DownloadProgress::new(
+            file_name,
+            Some(file),
+            response
+                .content_length()
+                .map(|length| resumed + length),
+            resumed,
+        );
+        let mut chunk = vec![0; DOWNLOAD_CHUNK_SIZE];
+        loop
+        {
+            if let Err(err) = progress.check_deadline()
+            {
+                // What was downloaded is kept for the next start.
+                let _ = output.sync_data();
+                return Err(err.into());
+            }
+            let read = response
+                .read(&mut chunk)
+                .context("Failed to download params from remote")?;
+            if read == 0
+            {
+                break;
+            }
+            output
+                .write_all(&chunk[..read])
+                .context("Failed to write params to local storage")?;
+            progress.advance(read);
+        }
+        output
+            .sync_all()
+            .context("Failed to flush params to local storage")?;
+        partial.complete()?;
+
+        info!(
+            "Downloaded params of size in KB: {}",
+            progress.downloaded / 1024
+        );
+        Ok(())
+    }
+
+    pub(crate) fn download_from(
+        base_url: &str,
+        file_name: &str,
+    ) -> anyhow::Result<Bytes>
+    {
+        let file_url = Self::file_url(
+            base_url,
+            file_name,
+        );
+        throttle::guarded(
+            &file_url,
+            || {
+                Self::download(
+                    &file_url,
+                    file_name,
+                )
+            },
+        )
+    }
+
+    fn download(
+        file_url: &str,
+        file_name: &str,
+    ) -> anyhow::Result<Bytes>
+    {
         info!(
             "Downloading params from {}",
             file_url
@@ -230,7 +983,7 @@ impl ParamsLoader
             .build()
             .context("Failed to build reqwest client")?;
 
-        let response = client
+        let mut response = client
             .get(file_url)
             .send()
             .context("Failed to download params from remote")?;
@@ -239,21 +992,40 @@ impl ParamsLoader
             .status()
             .is_success()
         {
-            anyhow::bail!(
-                "Failed to download params from remote: {}",
-                response.status()
-            );
+            return Err(throttle::StatusError(response.status()).into());
         }
 
-        let params = response
-            .bytes()
-            .context("Failed to download params from remote")?;
+        let mut progress = DownloadProgress::new(
+            file_name,
+            None,
+            response.content_length(),
+            0,
+        );
+        let mut params = Vec::with_capacity(
+            response
+                .content_length()
+                .unwrap_or_default() as usize,
+        );
+        let mut chunk = vec![0; DOWNLOAD_CHUNK_SIZE];
+        loop
+        {
+            let read = response
+                .read(&mut chunk)
+                .context("Failed to download params from remote")?;
+            if read == 0
+            {
+                break;
+            }
+            params.extend_from_slice(&chunk[..read]);
+            progress.advance(read);
+            progress.check_deadline()?;
+        }
 
         info!(
             "Downloaded params of size in KB: {}",
             params.len() / 1024
         );
-        Ok(params)
+        Ok(Bytes::from(params))
     }
 
     fn verify_file_checksum(
@@ -263,6 +1035,20 @@ impl ParamsLoader
         skip_checksum: bool,
     ) -> anyhow::Result<bool>
     {
+        let verified = VERIFIED
+            .lock()
+            .expect("verified params lock poisoned")
+            .get(file)
+            .copied();
+        if verified.is_some() && verified == file_stamp(file)
+        {
+            debug!(
+                "Skipping the hash of the prefetched {:?}",
+                file
+            );
+            return Ok(true);
+        }
+
         // checking if file exists
         if File::open(file).is_err()
         {
@@ -279,16 +1065,11 @@ impl ParamsLoader
             "Computing file hash for: {:?}",
             file
         );
-        let computed_hashes = create_hashes(
-            Path::new(file),
-            BTreeSet::new(),
-            checksums::Algorithm::BLAKE3,
-            None,
-            true,
-            3,
-            &mut std::io::stdout(),
-            &mut std::io::stderr(),
+        report(
+            file,
+            LoadPhase::Verifying,
         );
+        let computed_hashes = Self::hash_file(file);
 
         debug!(
             "Computed hashes: {:?}",
@@ -391,6 +1172,31 @@ impl ParamsLoader
         }
     }
 
+    /// The BLAKE3 hash of `file`, through io_uring if the worker is built with it.
+    fn hash_file(file: &Path) -> BTreeMap<String, String>
+    {
+        #[cfg(
+            all(
+                target_os = "linux",
+                feature = "io-uring"
+            )
+        )]
+        if let Some(hashes) = uring::hash_file(file)
+        {
+            return hashes;
+        }
+        create_hashes(
+            file,
+            BTreeSet::new(),
+            checksums::Algorithm::BLAKE3,
+            None,
+            true,
+            3,
+            &mut std::io::stdout(),
+            &mut std::io::stderr(),
+        )
+    }
+
     fn read_file(file: File) -> anyhow::Result<Bytes>
     {
         info!(
@@ -398,7 +1204,7 @@ impl ParamsLoader
             file
         );
 
-        let mut reader = std::io::BufReader::new(file);
+        let mut reader = sequential_reader(file);
         let mut buffer = Vec::new();
         reader
             .read_to_end(&mut buffer)
@@ -415,33 +1221,179 @@ impl ParamsLoader
 
         Ok(bytes)
     }
+}
 
-    fn store_file(
-        file: &Path,
-        params: &Bytes,
-    ) -> anyhow::Result<()>
+/// A reader of `file` from its start to its end, through io_uring if the worker is built with it
+/// and the kernel allows it, with large buffered reads otherwise.
+fn sequential_reader(file: File) -> Box<dyn Read>
+{
+    #[cfg(
+        all(
+            target_os = "linux",
+            feature = "io-uring"
+        )
+    )]
+    let file = match uring::UringReader::new(file)
     {
-        info!(
-            "Storing params to local storage: {:?}",
-            file
+        std::result::Result::Ok(reader) => return Box::new(reader),
+        Err(file) => file,
+    };
+    Box::new(
+        std::io::BufReader::with_capacity(
+            DESERIALIZE_BUFFER_SIZE,
+            file,
+        ),
+    )
+}
+
+/// The peak resident set size of the process, as reported by the kernel.
+///
+/// Only available on Linux, `None` elsewhere.
+pub fn peak_rss_bytes() -> Option<u64>
+{
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let kb = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+/// Tracks the progress of a params download, for logs and metrics.
+struct DownloadProgress<'a>
+{
+    file_name: &'a str,
+    /// Where the file is downloaded to, unless it is only kept in memory.
+    file: Option<&'a Path>,
+    total: Option<u64>,
+    downloaded: u64,
+    /// The bytes a previous attempt downloaded, which this one resumed from.
+    resumed: u64,
+    start: Instant,
+    last_log: Instant,
+}
+
+impl<'a> DownloadProgress<'a>
+{
+    fn new(
+        file_name: &'a str,
+        file: Option<&'a Path>,
+        total: Option<u64>,
+        resumed: u64,
+    ) -> Self
+    {
+        let now = Instant::now();
+        gauge!("zkmr_worker_params_download_progress_ratio", "file" => file_name.to_string()).set(
+            resumed as f64
+                / total
+                    .unwrap_or(1)
+                    .max(1) as f64,
         );
+        let progress = Self {
+            file_name,
+            file,
+            total,
+            downloaded: resumed,
+            resumed,
+            start: now,
+            last_log: now,
+        };
+        progress.report();
+        progress
+    }
 
-        if let Some(parent) = std::path::Path::new(file).parent()
+    fn report(&self)
+    {
+        if let Some(file) = self.file
         {
-            std::fs::create_dir_all(parent)
-                .context("Failed to create directories for local storage")?;
+            report(
+                file,
+                LoadPhase::Downloading {
+                    downloaded: self.downloaded,
+                    total: self.total,
+                },
+            );
         }
+    }
 
-        let file = File::create(file).context("Failed to create file for local storage")?;
+    /// Fails once the download deadline passed, if any.
+    fn check_deadline(&self) -> std::result::Result<(), ParamsError>
+    {
+        match DOWNLOAD_DEADLINE.get()
+        {
+            Some((deadline, after)) if Instant::now() >= *deadline =>
+            {
+                Err(
+                    ParamsError::DownloadDeadline {
+                        file: self
+                            .file_name
+                            .to_string(),
+                        downloaded: self.downloaded,
+                        total: self.total,
+                        deadline_secs: after.as_secs(),
+                    },
+                )
+            },
+            _ => std::result::Result::Ok(()),
+        }
+    }
 
-        let mut buffer = std::io::BufWriter::new(file);
-        buffer
-            .write_all(params)
-            .context("Failed to write params to local storage")?;
-        buffer
-            .flush()
-            .context("Failed to flush params to local storage")?;
-        info!("Stored params to local storage");
-        Ok(())
+    fn advance(
+        &mut self,
+        read: usize,
+    )
+    {
+        self.downloaded += read as u64;
+        self.report();
+        counter!("zkmr_worker_params_download_bytes_total", "file" => self.file_name.to_string())
+            .increment(read as u64);
+        if let Some(total) = self.total
+        {
+            gauge!("zkmr_worker_params_download_progress_ratio", "file" => self.file_name.to_string())
+                .set(self.downloaded as f64 / total.max(1) as f64);
+        }
+
+        if self
+            .last_log
+            .elapsed()
+            < DOWNLOAD_PROGRESS_INTERVAL
+        {
+            return;
+        }
+        self.last_log = Instant::now();
+
+        let elapsed = self
+            .start
+            .elapsed()
+            .as_secs_f64();
+        let speed = (self.downloaded - self.resumed) as f64 / elapsed.max(f64::EPSILON);
+        let downloaded_mb = self.downloaded / (1024 * 1024);
+        match self.total
+        {
+            Some(total) =>
+            {
+                let eta = total.saturating_sub(self.downloaded) as f64 / speed.max(1.0);
+                info!(
+                    "Downloading {}: {downloaded_mb}/{} MB ({:.1}%), {:.1} MB/s, ETA {:.0}s",
+                    self.file_name,
+                    total / (1024 * 1024),
+                    100.0 * self.downloaded as f64 / total.max(1) as f64,
+                    speed / (1024.0 * 1024.0),
+                    eta,
+                );
+            },
+            None =>
+            {
+                info!(
+                    "Downloading {}: {downloaded_mb} MB, {:.1} MB/s",
+                    self.file_name,
+                    speed / (1024.0 * 1024.0),
+                );
+            },
+        }
     }
 }
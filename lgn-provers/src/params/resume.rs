@@ -0,0 +1,274 @@
+//! The partial params downloads, kept across the starts of the worker to resume them.
+//!
+//! A params file is downloaded to `<file>.partial`, next to the version of the remote file it is
+//! a part of, its `ETag` or `Last-Modified`, in `<file>.partial.version`. An interrupted download,
+//! e.g. by the download deadline, resumes with a ranged request, which the server only honours if
+//! the remote file is still of that version, see `If-Range`; otherwise it sends the whole file
+//! again. The complete file is renamed into place, then verified against its checksum like any
+//! other.
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::fs::{
+    self,
+};
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use reqwest::blocking::Client;
+use reqwest::blocking::Response;
+use reqwest::header::CONTENT_RANGE;
+use reqwest::header::ETAG;
+use reqwest::header::IF_RANGE;
+use reqwest::header::LAST_MODIFIED;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use tracing::warn;
+
+use super::throttle::StatusError;
+
+/// A download of a params file, possibly started by a previous run.
+pub(super) struct Partial
+{
+    file: PathBuf,
+    path: PathBuf,
+    version_path: PathBuf,
+}
+
+impl Partial
+{
+    pub(super) fn of(file: &Path) -> Self
+    {
+        let with_suffix = |suffix: &str| {
+            let mut path = file
+                .as_os_str()
+                .to_owned();
+            path.push(suffix);
+            PathBuf::from(path)
+        };
+        Self {
+            file: file.to_path_buf(),
+            path: with_suffix(".partial"),
+            version_path: with_suffix(".partial.version"),
+        }
+    }
+
+    /// The bytes downloaded so far.
+    pub(super) fn len(&self) -> u64
+    {
+        fs::metadata(&self.path).map_or(
+            0,
+            |metadata| metadata.len(),
+        )
+    }
+
+    /// Requests the rest of the file from `url`.
+    pub(super) fn request(
+        &self,
+        client: &Client,
+        url: &str,
+    ) -> reqwest::Result<Response>
+    {
+        let mut request = client.get(url);
+        let offset = self.len();
+        if offset > 0
+        {
+            request = request.header(
+                RANGE,
+                format!("bytes={offset}-"),
+            );
+            if let Ok(version) = fs::read_to_string(&self.version_path)
+            {
+                request = request.header(
+                    IF_RANGE,
+                    version,
+                );
+            }
+        }
+        request.send()
+    }
+
+    /// Opens the partial file to write the body of `response` to, returning it with the offset the
+    /// body starts at.
+    ///
+    /// Returns `None` if the partial file was discarded, to request the whole file again.
+    pub(super) fn open(
+        &self,
+        response: &Response,
+    ) -> anyhow::Result<
+        Option<(
+            File,
+            u64,
+        )>,
+    >
+    {
+        match response.status()
+        {
+            StatusCode::PARTIAL_CONTENT =>
+            {
+                let offset = self.len();
+                let start = response
+                    .headers()
+                    .get(CONTENT_RANGE)
+                    .and_then(
+                        |value| {
+                            value
+                                .to_str()
+                                .ok()
+                        },
+                    )
+                    .and_then(content_range_start);
+                if start != Some(offset)
+                {
+                    warn!(
+                        "The server resumed `{}` at {start:?} rather than at {offset}, downloading \
+                         it again",
+                        self.file
+                            .display()
+                    );
+                    self.discard();
+                    return Ok(None);
+                }
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+                    .context("Failed to open the partial params")?;
+                Ok(
+                    Some(
+                        (
+                            file,
+                            offset,
+                        ),
+                    ),
+                )
+            },
+            StatusCode::OK =>
+            {
+                // A new download, or the remote file changed since the partial one was started.
+                let file =
+                    File::create(&self.path).context("Failed to create the partial params")?;
+                let version = response
+                    .headers()
+                    .get(ETAG)
+                    .or_else(
+                        || {
+                            response
+                                .headers()
+                                .get(LAST_MODIFIED)
+                        },
+                    )
+                    .and_then(
+                        |value| {
+                            value
+                                .to_str()
+                                .ok()
+                        },
+                    );
+                match version
+                {
+                    Some(version) =>
+                    {
+                        fs::write(
+                            &self.version_path,
+                            version,
+                        )
+                        .context("Failed to save the version of the partial params")?;
+                    },
+                    None =>
+                    {
+                        let _ = fs::remove_file(&self.version_path);
+                    },
+                }
+                Ok(
+                    Some(
+                        (
+                            file,
+                            0,
+                        ),
+                    ),
+                )
+            },
+            StatusCode::RANGE_NOT_SATISFIABLE =>
+            {
+                // The partial file is not shorter than the remote one, which changed since.
+                warn!(
+                    "The partial `{}` is not shorter than the remote file, downloading it again",
+                    self.file
+                        .display()
+                );
+                self.discard();
+                Ok(None)
+            },
+            status => Err(StatusError(status).into()),
+        }
+    }
+
+    /// Moves the complete file into place.
+    pub(super) fn complete(self) -> anyhow::Result<()>
+    {
+        fs::rename(
+            &self.path,
+            &self.file,
+        )
+        .context("Failed to store params to local storage")?;
+        let _ = fs::remove_file(&self.version_path);
+        Ok(())
+    }
+
+    pub(super) fn discard(&self)
+    {
+        let _ = fs::remove_file(&self.path);
+        let _ = fs::remove_file(&self.version_path);
+    }
+}
+
+/// The first byte of a `Content-Range: bytes <start>-<end>/<size>` header.
+fn content_range_start(value: &str) -> Option<u64>
+{
+    value
+        .strip_prefix("bytes ")?
+        .split_once('-')?
+        .0
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn reads_the_start_of_the_content_range()
+    {
+        assert_eq!(
+            content_range_start("bytes 1024-2047/4096"),
+            Some(1024)
+        );
+        assert_eq!(
+            content_range_start("bytes 0-0/*"),
+            Some(0)
+        );
+        assert_eq!(
+            content_range_start("bytes */4096"),
+            None
+        );
+        assert_eq!(
+            content_range_start("items 0-1/2"),
+            None
+        );
+
+        let partial = Partial::of(Path::new("/params/query_params.bin"));
+        assert_eq!(
+            partial.path,
+            Path::new("/params/query_params.bin.partial")
+        );
+        assert_eq!(
+            partial.version_path,
+            Path::new("/params/query_params.bin.partial.version")
+        );
+    }
+}
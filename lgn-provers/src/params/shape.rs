@@ -0,0 +1,162 @@
+//! The shape of the circuits a params file was built for.
+//!
+//! The params are built for the constants of the circuits, e.g. `MAX_NUM_COLUMNS`, which are not
+//! part of their serialization: params of another shape deserialize fine and only fail once a
+//! proof is generated. The shape the params were built with is published next to them, in
+//! `<file>.shape.json`, and compared with the constants the worker was compiled with before the
+//! params are loaded.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The constants of the circuits, by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct CircuitShape(BTreeMap<String, usize>);
+
+impl CircuitShape
+{
+    pub fn new(
+        constants: &[(
+            &str,
+            usize,
+        )]
+    ) -> Self
+    {
+        Self(
+            constants
+                .iter()
+                .map(
+                    |(name, value)| {
+                        (
+                            name.to_string(),
+                            *value,
+                        )
+                    },
+                )
+                .collect(),
+        )
+    }
+
+    /// The name of the file describing the shape of the params file `file_name`.
+    pub fn file_name(file_name: &str) -> String
+    {
+        format!("{file_name}.shape.json")
+    }
+
+    /// The value of the constant `name`, if part of the shape.
+    pub fn get(
+        &self,
+        name: &str,
+    ) -> Option<usize>
+    {
+        self.0
+            .get(name)
+            .copied()
+    }
+
+    /// The constants, by name.
+    pub fn constants(&self) -> &BTreeMap<String, usize>
+    {
+        &self.0
+    }
+
+    /// The constants of `self` which `other` lacks or has a lower value for.
+    pub fn exceeding(
+        &self,
+        other: &CircuitShape,
+    ) -> Vec<String>
+    {
+        self.0
+            .iter()
+            .filter(|(name, value)| other.get(name) < Some(**value))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// The constants of `self` which `params` lacks or has another value for.
+    pub fn mismatches(
+        &self,
+        params: &CircuitShape,
+    ) -> Vec<String>
+    {
+        self.0
+            .iter()
+            .filter(
+                |(name, value)| {
+                    params
+                        .0
+                        .get(*name)
+                        != Some(value)
+                },
+            )
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+impl Display for CircuitShape
+{
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result
+    {
+        let constants = self
+            .0
+            .iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "{}",
+            constants.join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn lists_the_constants_the_params_disagree_on()
+    {
+        let compiled = CircuitShape::new(
+            &[
+                (
+                    "MAX_NUM_COLUMNS",
+                    20,
+                ),
+                (
+                    "ROW_TREE_MAX_DEPTH",
+                    24,
+                ),
+            ],
+        );
+        let params: CircuitShape =
+            serde_json::from_str(r#"{"MAX_NUM_COLUMNS": 25, "MAX_NUM_OUTPUTS": 3}"#).unwrap();
+
+        assert_eq!(
+            compiled.mismatches(&params),
+            vec![
+                "MAX_NUM_COLUMNS",
+                "ROW_TREE_MAX_DEPTH"
+            ]
+        );
+        assert!(
+            compiled
+                .mismatches(&compiled)
+                .is_empty()
+        );
+        assert_eq!(
+            compiled.to_string(),
+            "MAX_NUM_COLUMNS=20, ROW_TREE_MAX_DEPTH=24"
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! The restraint of the params downloads when their host, e.g. the CDN, fails, for the workers not
+//! to worsen its outage by retrying in lockstep.
+//!
+//! Each host has a circuit breaker: after [`FAILURE_THRESHOLD`] consecutive failures, i.e. no
+//! answer, a cut download or a `5xx` or `429`, it opens and the downloads from the host fail at
+//! once, falling back to the origin if the host is the mirror, until a cooldown passed. One
+//! download then probes the host: its success closes the breaker, its failure opens it again for a
+//! longer cooldown. The cooldowns, like the delays between the attempts of a download, follow a
+//! decorrelated jitter backoff, which spreads the retries of the workers over time. The other
+//! answers of the host, e.g. a `404`, say nothing of its health.
+//!
+//! The downloads running at once in the process are capped as well, see
+//! [`ParamsLoader::set_max_concurrent_downloads`](super::ParamsLoader::set_max_concurrent_downloads).
+
+use std::collections::BTreeMap;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use metrics::counter;
+use metrics::gauge;
+use reqwest::StatusCode;
+use tracing::info;
+use tracing::warn;
+
+/// The consecutive failures of a host opening its breaker.
+const FAILURE_THRESHOLD: u32 = 5;
+
+const COOLDOWN_BASE: Duration = Duration::from_secs(5);
+const COOLDOWN_CAP: Duration = Duration::from_secs(5 * 60);
+
+pub(super) const RETRY_BASE: Duration = Duration::from_secs(1);
+pub(super) const RETRY_CAP: Duration = Duration::from_secs(60);
+
+static BREAKERS: Mutex<BTreeMap<String, Breaker>> = Mutex::new(BTreeMap::new());
+
+static PERMITS: Permits = Permits {
+    slots: Mutex::new(
+        Slots {
+            running: 0,
+            max: DEFAULT_MAX_CONCURRENT_DOWNLOADS,
+        },
+    ),
+    released: Condvar::new(),
+};
+
+const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 2;
+
+/// The status a params host answered a download with, other than a success.
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to download params from remote: {0}")]
+pub(super) struct StatusError(pub(super) StatusCode);
+
+/// The downloads from a failing host are refused for a while.
+#[derive(thiserror::Error, Debug)]
+#[error("the params host `{host}` is failing, not downloading from it for {retry_in:?}")]
+pub(super) struct BreakerOpen
+{
+    host: String,
+    retry_in: Duration,
+}
+
+/// A decorrelated jitter backoff: each delay is drawn between `base` and three times the previous
+/// one, up to `cap`.
+pub(super) struct Backoff
+{
+    base: Duration,
+    cap: Duration,
+    last: Duration,
+}
+
+impl Backoff
+{
+    pub(super) fn new(
+        base: Duration,
+        cap: Duration,
+    ) -> Self
+    {
+        Self {
+            base,
+            cap,
+            last: base,
+        }
+    }
+
+    pub(super) fn next(&mut self) -> Duration
+    {
+        let upper = (self.last * 3)
+            .min(self.cap)
+            .max(self.base);
+        self.last = self.base + (upper - self.base).mul_f64(rand::random::<f64>());
+        self.last
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State
+{
+    Closed
+    {
+        failures: u32,
+    },
+    Open
+    {
+        until: Instant,
+    },
+    /// A download is probing the host.
+    HalfOpen,
+}
+
+impl State
+{
+    /// The value of `zkmr_worker_params_breaker_state`.
+    fn as_metric(self) -> f64
+    {
+        match self
+        {
+            State::Closed {
+                ..
+            } => 0.0,
+            State::HalfOpen => 1.0,
+            State::Open {
+                ..
+            } => 2.0,
+        }
+    }
+}
+
+struct Breaker
+{
+    state: State,
+    cooldown: Backoff,
+}
+
+impl Default for Breaker
+{
+    fn default() -> Self
+    {
+        Self {
+            state: State::Closed {
+                failures: 0,
+            },
+            cooldown: Backoff::new(
+                COOLDOWN_BASE,
+                COOLDOWN_CAP,
+            ),
+        }
+    }
+}
+
+struct Permits
+{
+    slots: Mutex<Slots>,
+    released: Condvar,
+}
+
+/// The downloads running, and at most.
+struct Slots
+{
+    running: usize,
+    max: usize,
+}
+
+/// A download slot, released on drop.
+struct Permit;
+
+impl Permit
+{
+    fn acquire() -> Self
+    {
+        let mut slots = PERMITS
+            .slots
+            .lock()
+            .expect("download permits lock poisoned");
+        gauge!("zkmr_worker_params_downloads_waiting").increment(1);
+        while slots.running >= slots.max
+        {
+            slots = PERMITS
+                .released
+                .wait(slots)
+                .expect("download permits lock poisoned");
+        }
+        gauge!("zkmr_worker_params_downloads_waiting").decrement(1);
+        slots.running += 1;
+        Permit
+    }
+}
+
+impl Drop for Permit
+{
+    fn drop(&mut self)
+    {
+        PERMITS
+            .slots
+            .lock()
+            .expect("download permits lock poisoned")
+            .running -= 1;
+        PERMITS
+            .released
+            .notify_one();
+    }
+}
+
+pub(super) fn set_max_concurrent_downloads(max: usize)
+{
+    PERMITS
+        .slots
+        .lock()
+        .expect("download permits lock poisoned")
+        .max = max.max(1);
+    PERMITS
+        .released
+        .notify_all();
+}
+
+/// Runs `download` from `url` within a download slot, unless the breaker of its host is open.
+pub(super) fn guarded<T>(
+    url: &str,
+    download: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<T>
+{
+    let host = host(url);
+    admit(&host)?;
+    let result = {
+        let _permit = Permit::acquire();
+        download()
+    };
+    match &result
+    {
+        Err(err) if is_host_failure(err) => failed(&host),
+        _ => succeeded(&host),
+    }
+    result
+}
+
+/// How long to wait for the breaker which failed a download with `err` to let a download through.
+pub(super) fn retry_in(err: &anyhow::Error) -> Duration
+{
+    err.downcast_ref::<BreakerOpen>()
+        .map_or(
+            Duration::ZERO,
+            |open| open.retry_in,
+        )
+}
+
+fn host(url: &str) -> String
+{
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(
+            |url| {
+                url.host_str()
+                    .map(str::to_string)
+            },
+        )
+        .unwrap_or_else(|| url.to_string())
+}
+
+fn admit(host: &str) -> anyhow::Result<()>
+{
+    let mut breakers = BREAKERS
+        .lock()
+        .expect("params breakers lock poisoned");
+    let breaker = breakers
+        .entry(host.to_string())
+        .or_default();
+    match breaker.state
+    {
+        State::Closed {
+            ..
+        } => Ok(()),
+        State::Open {
+            until,
+        } if Instant::now() >= until =>
+        {
+            info!("Probing the params host `{host}` again");
+            set_state(
+                host,
+                breaker,
+                State::HalfOpen,
+            );
+            Ok(())
+        },
+        State::Open {
+            until,
+        } =>
+        {
+            Err(
+                BreakerOpen {
+                    host: host.to_string(),
+                    retry_in: until.saturating_duration_since(Instant::now()),
+                }
+                .into(),
+            )
+        },
+        // Another download is probing the host.
+        State::HalfOpen =>
+        {
+            Err(
+                BreakerOpen {
+                    host: host.to_string(),
+                    retry_in: COOLDOWN_BASE,
+                }
+                .into(),
+            )
+        },
+    }
+}
+
+fn succeeded(host: &str)
+{
+    let mut breakers = BREAKERS
+        .lock()
+        .expect("params breakers lock poisoned");
+    let breaker = breakers
+        .entry(host.to_string())
+        .or_default();
+    if breaker.state == State::HalfOpen
+    {
+        info!("The params host `{host}` recovered");
+        breaker.cooldown = Backoff::new(
+            COOLDOWN_BASE,
+            COOLDOWN_CAP,
+        );
+    }
+    set_state(
+        host,
+        breaker,
+        State::Closed {
+            failures: 0,
+        },
+    );
+}
+
+fn failed(host: &str)
+{
+    let mut breakers = BREAKERS
+        .lock()
+        .expect("params breakers lock poisoned");
+    let breaker = breakers
+        .entry(host.to_string())
+        .or_default();
+    let failures = match breaker.state
+    {
+        State::Closed {
+            failures,
+        } => failures + 1,
+        State::HalfOpen
+        | State::Open {
+            ..
+        } => FAILURE_THRESHOLD,
+    };
+    if failures < FAILURE_THRESHOLD
+    {
+        set_state(
+            host,
+            breaker,
+            State::Closed {
+                failures,
+            },
+        );
+        return;
+    }
+
+    let cooldown = breaker
+        .cooldown
+        .next();
+    warn!("The params host `{host}` is failing, not downloading from it for {cooldown:?}");
+    counter!("zkmr_worker_params_breaker_trips_total", "host" => host.to_string()).increment(1);
+    set_state(
+        host,
+        breaker,
+        State::Open {
+            until: Instant::now() + cooldown,
+        },
+    );
+}
+
+fn set_state(
+    host: &str,
+    breaker: &mut Breaker,
+    state: State,
+)
+{
+    breaker.state = state;
+    gauge!("zkmr_worker_params_breaker_state", "host" => host.to_string()).set(state.as_metric());
+}
+
+/// Whether `err` tells of a failing host rather than of the download or the local storage.
+fn is_host_failure(err: &anyhow::Error) -> bool
+{
+    err.chain()
+        .any(
+            |cause| {
+                if let Some(status) = cause.downcast_ref::<StatusError>()
+                {
+                    return status
+                        .0
+                        .is_server_error()
+                        || status.0 == StatusCode::TOO_MANY_REQUESTS;
+                }
+                // The body of the blocking responses is read as `io::Error`s wrapping the
+                // `reqwest::Error`s.
+                cause.is::<reqwest::Error>()
+                    || cause
+                        .downcast_ref::<std::io::Error>()
+                        .and_then(std::io::Error::get_ref)
+                        .is_some_and(|inner| inner.is::<reqwest::Error>())
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn backs_off_with_decorrelated_jitter()
+    {
+        let mut backoff = Backoff::new(
+            RETRY_BASE,
+            RETRY_CAP,
+        );
+        let mut last = RETRY_BASE;
+        for _ in 0..100
+        {
+            let delay = backoff.next();
+            assert!(delay >= RETRY_BASE);
+            assert!(delay <= (last * 3).min(RETRY_CAP));
+            last = delay;
+        }
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures()
+    {
+        let host = "breaker.test";
+        for _ in 0..FAILURE_THRESHOLD - 1
+        {
+            failed(host);
+        }
+        succeeded(host);
+        for _ in 0..FAILURE_THRESHOLD - 1
+        {
+            failed(host);
+        }
+        assert!(admit(host).is_ok());
+
+        failed(host);
+        let err = admit(host).unwrap_err();
+        assert!(retry_in(&err) >= COOLDOWN_BASE - Duration::from_millis(100));
+        assert!(!is_host_failure(&err));
+        assert!(is_host_failure(&StatusError(StatusCode::BAD_GATEWAY).into()));
+        assert!(!is_host_failure(&StatusError(StatusCode::NOT_FOUND).into()));
+    }
+}
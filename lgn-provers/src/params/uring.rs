@@ -0,0 +1,372 @@
+//! The reads of the params files through io_uring, for the workers built with the `io-uring`
+//! feature on Linux.
+//!
+//! The params are read once, sequentially, to be hashed then deserialized: [`UringReader`] keeps
+//! [`QUEUE_DEPTH`] reads of [`CHUNK_BYTES`] in flight ahead of the one consumed, so that the NVMe
+//! queue stays busy while the caller hashes or deserializes the previous chunk, where a
+//! `BufReader` waits for each of its reads. See `benches/params_read.rs` for how both compare on
+//! the disks of a host.
+//!
+//! A kernel refusing the rings, e.g. older than 5.6 or with io_uring disabled by seccomp in a
+//! container, is told once in the logs and the params are read with the standard reads instead.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use io_uring::opcode;
+use io_uring::types;
+use io_uring::IoUring;
+use tracing::warn;
+
+/// The reads in flight at most.
+const QUEUE_DEPTH: usize = 4;
+
+/// The bytes of each read, the reads in flight taking as much memory as a deserialization buffer.
+const CHUNK_BYTES: usize = 2 * 1024 * 1024;
+
+/// Whether a ring could not be set up, the kernel refusing the next ones as well.
+static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// A sequential reader of a file keeping reads in flight ahead of the bytes consumed.
+pub struct UringReader
+{
+    file: File,
+    ring: IoUring,
+    slots: Vec<Slot>,
+    /// The slot consumed, the others being read ahead in order.
+    current: usize,
+    /// The offset of the next chunk to read ahead.
+    next_offset: u64,
+    in_flight: usize,
+    done: bool,
+}
+
+struct Slot
+{
+    buf: Box<[u8]>,
+    offset: u64,
+    filled: usize,
+    consumed: usize,
+    reading: bool,
+    /// Whether the file ends within the chunk.
+    end: bool,
+    error: Option<io::Error>,
+}
+
+impl UringReader
+{
+    /// Reads `file` through a ring, or gives it back if the kernel refuses one.
+    pub fn new(file: File) -> Result<Self, File>
+    {
+        if UNAVAILABLE.load(Ordering::Relaxed)
+        {
+            return Err(file);
+        }
+        let ring = match IoUring::new(QUEUE_DEPTH as u32)
+        {
+            Ok(ring) => ring,
+            Err(err) =>
+            {
+                if !UNAVAILABLE.swap(
+                    true,
+                    Ordering::Relaxed,
+                )
+                {
+                    warn!(
+                        "io_uring unavailable, reading the params with the standard reads: {err}"
+                    );
+                }
+                return Err(file);
+            },
+        };
+        let mut reader = Self {
+            file,
+            ring,
+            slots: (0..QUEUE_DEPTH)
+                .map(
+                    |_| {
+                        Slot {
+                            buf: vec![0; CHUNK_BYTES].into_boxed_slice(),
+                            offset: 0,
+                            filled: 0,
+                            consumed: 0,
+                            reading: false,
+                            end: false,
+                            error: None,
+                        }
+                    },
+                )
+                .collect(),
+            current: 0,
+            next_offset: 0,
+            in_flight: 0,
+            done: false,
+        };
+        // Submitted by the first wait.
+        for slot in 0..QUEUE_DEPTH
+        {
+            reader.read_ahead(slot);
+        }
+        Ok(reader)
+    }
+
+    /// Queues the read of the next chunk into `slot`.
+    fn read_ahead(
+        &mut self,
+        slot: usize,
+    )
+    {
+        let offset = self.next_offset;
+        self.next_offset += CHUNK_BYTES as u64;
+        let entry = &mut self.slots[slot];
+        entry.offset = offset;
+        entry.filled = 0;
+        entry.consumed = 0;
+        entry.end = false;
+        self.push(slot);
+    }
+
+    /// Queues the read of the rest of the chunk of `slot`.
+    fn push(
+        &mut self,
+        slot: usize,
+    )
+    {
+        let entry = &mut self.slots[slot];
+        let read = opcode::Read::new(
+            types::Fd(
+                self.file
+                    .as_raw_fd(),
+            ),
+            entry.buf[entry.filled..].as_mut_ptr(),
+            (CHUNK_BYTES - entry.filled) as u32,
+        )
+        .offset(entry.offset + entry.filled as u64)
+        .build()
+        .user_data(slot as u64);
+        // SAFETY: the buffer is neither moved nor freed while the read is in flight, `Drop` waiting
+        // for the reads left. The queue has room, each slot having at most one read in flight.
+        let pushed = unsafe {
+            self.ring
+                .submission()
+                .push(&read)
+        };
+        match pushed
+        {
+            Ok(()) =>
+            {
+                entry.reading = true;
+                self.in_flight += 1;
+            },
+            Err(err) =>
+            {
+                entry.error = Some(io::Error::other(err));
+            },
+        }
+    }
+
+    /// Waits for the chunk of `slot` to be read.
+    fn wait(
+        &mut self,
+        slot: usize,
+    ) -> io::Result<()>
+    {
+        while self.slots[slot].reading
+        {
+            self.ring
+                .submit_and_wait(1)?;
+            let completed = self
+                .ring
+                .completion()
+                .map(
+                    |entry| {
+                        (
+                            entry.user_data() as usize,
+                            entry.result(),
+                        )
+                    },
+                )
+                .collect::<Vec<_>>();
+            for (done, result) in completed
+            {
+                self.in_flight -= 1;
+                let entry = &mut self.slots[done];
+                entry.reading = false;
+                if result < 0
+                {
+                    entry.error = Some(io::Error::from_raw_os_error(-result));
+                }
+                else if result == 0
+                {
+                    entry.end = true;
+                }
+                else
+                {
+                    entry.filled += result as usize;
+                    // A short read, e.g. interrupted, is resumed where it stopped.
+                    if entry.filled < CHUNK_BYTES
+                    {
+                        self.push(done);
+                    }
+                }
+            }
+        }
+        self.ring
+            .submit()?;
+        Ok(())
+    }
+}
+
+impl Read for UringReader
+{
+    fn read(
+        &mut self,
+        out: &mut [u8],
+    ) -> io::Result<usize>
+    {
+        loop
+        {
+            if self.done
+            {
+                return Ok(0);
+            }
+            self.wait(self.current)?;
+            let slot = &mut self.slots[self.current];
+            if let Some(err) = slot
+                .error
+                .take()
+            {
+                self.done = true;
+                return Err(err);
+            }
+            if slot.consumed < slot.filled
+            {
+                let n = out
+                    .len()
+                    .min(slot.filled - slot.consumed);
+                out[..n].copy_from_slice(&slot.buf[slot.consumed..slot.consumed + n]);
+                slot.consumed += n;
+                return Ok(n);
+            }
+            if slot.end
+            {
+                self.done = true;
+                continue;
+            }
+            self.read_ahead(self.current);
+            self.ring
+                .submit()?;
+            self.current = (self.current + 1) % QUEUE_DEPTH;
+        }
+    }
+}
+
+impl Drop for UringReader
+{
+    fn drop(&mut self)
+    {
+        while self.in_flight > 0
+        {
+            if self
+                .ring
+                .submit_and_wait(1)
+                .is_err()
+            {
+                // The kernel may still write to the buffers: leak them rather than free them.
+                std::mem::forget(std::mem::take(&mut self.slots));
+                return;
+            }
+            self.in_flight -= self
+                .ring
+                .completion()
+                .count();
+        }
+    }
+}
+
+/// The BLAKE3 hash of `path` keyed by its path, in the format of `checksums::ops::create_hashes`,
+/// or `None` if it could not be read through a ring.
+pub(super) fn hash_file(path: &Path) -> Option<BTreeMap<String, String>>
+{
+    let mut reader = UringReader::new(File::open(path).ok()?).ok()?;
+    let mut hasher = blake3::Hasher::new();
+    if let Err(err) = io::copy(
+        &mut reader,
+        &mut hasher,
+    )
+    {
+        warn!("Failed to hash {path:?} through io_uring: {err}");
+        return None;
+    }
+    Some(
+        BTreeMap::from(
+            [
+                (
+                    path.display()
+                        .to_string(),
+                    hasher
+                        .finalize()
+                        .to_hex()
+                        .to_ascii_uppercase(),
+                ),
+            ],
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn reads_the_file_in_order()
+    {
+        let data = (0..3 * CHUNK_BYTES + 12345)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let path = std::env::temp_dir().join(
+            format!(
+                "lgn-uring-test-{}",
+                std::process::id()
+            ),
+        );
+        File::create(&path)
+            .unwrap()
+            .write_all(&data)
+            .unwrap();
+
+        let Ok(mut reader) = UringReader::new(File::open(&path).unwrap())
+        else
+        {
+            // io_uring is not available in this sandbox.
+            std::fs::remove_file(&path).unwrap();
+            return;
+        };
+        let mut read = vec![];
+        reader
+            .read_to_end(&mut read)
+            .unwrap();
+        assert!(read == data);
+        assert_eq!(
+            hash_file(&path)
+                .unwrap()
+                .into_values()
+                .collect::<Vec<_>>(),
+            [
+                blake3::hash(&data)
+                    .to_hex()
+                    .to_ascii_uppercase()
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -19,4 +19,57 @@ pub trait LgnProver<T, R>
         &self,
         envelope: &MessageEnvelope<T>,
     ) -> anyhow::Result<MessageReplyEnvelope<R>>;
+
+    /// Same as [`LgnProver::run`], handing over to `yield_point` between the internal stages of
+    /// the proof, e.g. the steps of an index task, which may cancel it.
+    ///
+    /// The provers whose proofs are a single stage keep the default, which never hands over.
+    fn run_cooperatively(
+        &self,
+        envelope: &MessageEnvelope<T>,
+        yield_point: &dyn YieldPoint,
+    ) -> anyhow::Result<MessageReplyEnvelope<R>>
+    {
+        let _ = yield_point;
+        self.run(envelope)
+    }
+}
+
+/// Where a proof made of several stages hands over between them, to report its progress and to be
+/// cancelled.
+pub trait YieldPoint: Sync
+{
+    /// Called once `done` out of the `total` steps of `stage` are proven, an error cancelling the
+    /// proof.
+    fn reached(
+        &self,
+        stage: &'static str,
+        done: usize,
+        total: usize,
+    ) -> anyhow::Result<()>;
+}
+
+/// Never cancels a proof.
+impl YieldPoint for ()
+{
+    fn reached(
+        &self,
+        _stage: &'static str,
+        _done: usize,
+        _total: usize,
+    ) -> anyhow::Result<()>
+    {
+        Ok(())
+    }
+}
+
+/// The error of a proof cancelled at a [`YieldPoint`].
+#[derive(thiserror::Error, Debug)]
+#[error("the proof was cancelled after {done}/{total} step(s) of {stage}: {reason}")]
+pub struct Cancelled
+{
+    pub stage: &'static str,
+    pub done: usize,
+    pub total: usize,
+    pub reason: String,
 }
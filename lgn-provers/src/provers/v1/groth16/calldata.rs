@@ -0,0 +1,127 @@
+//! Encoding of the groth16 proofs for the on-chain verifier.
+//!
+//! The groth16 prover returns the combined proof of the groth16 framework: the 8 words of the
+//! groth16 proof, then its 3 public inputs, then the public inputs of the wrapped plonky2 proof.
+//! The verifier contract takes the whole combined proof as `bytes32[]`, while the groth16
+//! verifier alone takes `uint256[8]` and `uint256[3]`; both are built here so that integrators
+//! stop hand-rolling them.
+
+use anyhow::ensure;
+use anyhow::Result;
+use serde::Serialize;
+
+/// The words of a groth16 proof: A, B and C, B being over the quadratic extension.
+pub const PROOF_WORDS: usize = 8;
+
+/// The public inputs of the groth16 circuit.
+pub const INPUT_WORDS: usize = 3;
+
+/// The modulus of the BN254 base field, of the coordinates of the proof points, big-endian.
+const BASE_FIELD_MODULUS: [u8; 32] =
+    hex32("30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd47");
+
+/// The modulus of the BN254 scalar field, of the public inputs, big-endian.
+const SCALAR_FIELD_MODULUS: [u8; 32] =
+    hex32("30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001");
+
+/// A combined proof encoded for the verifier contract, the words as `0x`-prefixed hex.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Calldata
+{
+    /// The `uint256[8]` groth16 proof.
+    pub proof: Vec<String>,
+    /// The `uint256[3]` groth16 public inputs.
+    pub inputs: Vec<String>,
+    /// The `bytes32[]` combined proof, the last word zero-padded.
+    pub data: Vec<String>,
+}
+
+/// Encodes the `combined_proof` returned by the groth16 prover for the verifier contract.
+///
+/// Fails if the proof is too short to hold the groth16 proof and inputs, or if one of their words
+/// is not a canonical field element, which the verifier would reject.
+pub fn encode(combined_proof: &[u8]) -> Result<Calldata>
+{
+    let groth16_len = (PROOF_WORDS + INPUT_WORDS) * 32;
+    ensure!(
+        combined_proof.len() >= groth16_len,
+        "the proof is {} bytes, a groth16 proof and its inputs take {groth16_len}",
+        combined_proof.len()
+    );
+
+    let words = combined_proof
+        .chunks(32)
+        .map(
+            |chunk| {
+                let mut word = [0u8; 32];
+                word[..chunk.len()].copy_from_slice(chunk);
+                word
+            },
+        )
+        .collect::<Vec<_>>();
+    for (i, word) in words[..PROOF_WORDS]
+        .iter()
+        .enumerate()
+    {
+        ensure!(
+            *word < BASE_FIELD_MODULUS,
+            "the proof word {i} is not a base field element"
+        );
+    }
+    for (i, word) in words[PROOF_WORDS..PROOF_WORDS + INPUT_WORDS]
+        .iter()
+        .enumerate()
+    {
+        ensure!(
+            *word < SCALAR_FIELD_MODULUS,
+            "the public input {i} is not a scalar field element"
+        );
+    }
+
+    let hex = |words: &[[u8; 32]]| -> Vec<String> {
+        words
+            .iter()
+            .map(
+                |word| {
+                    let digits = word
+                        .iter()
+                        .map(|b| format!("{b:02x}"))
+                        .collect::<String>();
+                    format!("0x{digits}")
+                },
+            )
+            .collect()
+    };
+    Ok(
+        Calldata {
+            proof: hex(&words[..PROOF_WORDS]),
+            inputs: hex(&words[PROOF_WORDS..PROOF_WORDS + INPUT_WORDS]),
+            data: hex(&words),
+        },
+    )
+}
+
+/// Parses 64 hex digits at compile time.
+const fn hex32(digits: &str) -> [u8; 32]
+{
+    const fn nibble(c: u8) -> u8
+    {
+        match c
+        {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            _ => panic!("invalid hex digit"),
+        }
+    }
+
+    let digits = digits.as_bytes();
+    assert!(digits.len() == 64);
+    let mut bytes = [0u8; 32];
+    let mut i = 0;
+    while i < 32
+    {
+        bytes[i] = nibble(digits[2 * i]) << 4 | nibble(digits[2 * i + 1]);
+        i += 1;
+    }
+    bytes
+}
@@ -1,10 +1,23 @@
-use crate::dummy_utils::dummy_proof;
+use crate::dummy_profile::DummyProfile;
 use crate::provers::v1::groth16::prover::Prover;
 
 const PROOF_SIZE: usize = 32;
 
 /// Prover implementation which performs no proving and returns random data as a proof.
-pub struct DummyProver;
+pub struct DummyProver
+{
+    profile: DummyProfile,
+}
+
+impl DummyProver
+{
+    pub(crate) fn new(profile: DummyProfile) -> Self
+    {
+        Self {
+            profile,
+        }
+    }
+}
 
 impl Prover for DummyProver
 {
@@ -13,6 +26,12 @@ impl Prover for DummyProver
         _aggregated_proof: &[u8],
     ) -> anyhow::Result<Vec<u8>>
     {
-        Ok(dummy_proof(PROOF_SIZE))
+        Ok(
+            self.profile
+                .proof(
+                    "groth16",
+                    PROOF_SIZE,
+                ),
+        )
     }
 }
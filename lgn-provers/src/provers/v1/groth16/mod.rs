@@ -2,8 +2,10 @@
 use prover::Prover;
 use tracing::info;
 
+use crate::dummy_profile::DummyProfile;
 use crate::provers::v1::groth16::task::Groth16;
 
+pub mod calldata;
 mod prover;
 mod task;
 
@@ -13,6 +15,16 @@ mod dummy_prover;
 #[cfg(not(feature = "dummy-prover"))]
 mod euclid_prover;
 
+/// Checks that `proof`, replied to a groth16 task, encodes for the on-chain verifier.
+pub fn check_proof(proof: &[u8]) -> anyhow::Result<()>
+{
+    #[cfg(feature = "dummy-prover")]
+    let _ = proof;
+    #[cfg(not(feature = "dummy-prover"))]
+    calldata::encode(proof)?;
+    Ok(())
+}
+
 #[allow(unused_variables)]
 #[allow(clippy::too_many_arguments)]
 pub fn create_prover(
@@ -24,13 +36,14 @@ pub fn create_prover(
     pk_file: &str,
     vk_file: &str,
     skip_store: bool,
+    dummy_profile: DummyProfile,
 ) -> anyhow::Result<Groth16<impl Prover>>
 {
     let prover = {
         #[cfg(feature = "dummy-prover")]
         {
             info!("Creating dummy Groth16Prover");
-            dummy_prover::DummyProver
+            dummy_prover::DummyProver::new(dummy_profile)
         }
         #[cfg(not(feature = "dummy-prover"))]
         {
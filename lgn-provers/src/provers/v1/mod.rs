@@ -1,3 +1,6 @@
+#[cfg(feature = "prover-groth16")]
 pub mod groth16;
+#[cfg(feature = "prover-preprocessing")]
 pub mod preprocessing;
+#[cfg(feature = "prover-query")]
 pub mod query;
@@ -0,0 +1,274 @@
+//! Checkpoints of the intermediate proofs of the index tasks.
+//!
+//! An index task proves a chain of steps, a failure in the middle of it used to discard the proofs
+//! of the completed steps. Each intermediate proof is saved to a scratch directory instead, a
+//! retry of the task resuming after the last saved step.
+
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use lgn_messages::types::v1::preprocessing::db_tasks::DbBlockType;
+use lgn_messages::BlockNr;
+use lgn_messages::TableId;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::debug;
+use tracing::warn;
+
+/// Where the intermediate proofs of the index tasks are saved, and for how long they are kept.
+#[derive(Debug, Clone)]
+pub struct IndexCheckpoints
+{
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint
+{
+    /// Fingerprint of the steps up to the checkpoint, a task with other inputs for the same block
+    /// must not resume from it.
+    fingerprint: [u8; 32],
+    proof: Vec<u8>,
+}
+
+impl IndexCheckpoints
+{
+    pub fn new(
+        dir: impl Into<PathBuf>,
+        ttl: Duration,
+    ) -> Self
+    {
+        Self {
+            dir: dir.into(),
+            ttl,
+        }
+    }
+
+    fn path(
+        &self,
+        table_id: TableId,
+        block_nr: BlockNr,
+        position: usize,
+    ) -> PathBuf
+    {
+        self.dir
+            .join(format!("index-{table_id}-{block_nr}-{position}.ckpt"))
+    }
+
+    /// The last saved step of the task with these `inputs` and its proof, if any.
+    pub(crate) fn resume(
+        &self,
+        table_id: TableId,
+        block_nr: BlockNr,
+        inputs: &[DbBlockType],
+    ) -> Option<(
+        usize,
+        Vec<u8>,
+    )>
+    {
+        self.prune();
+        (0..inputs.len())
+            .rev()
+            .find_map(
+                |position| {
+                    let path = self.path(
+                        table_id,
+                        block_nr,
+                        position,
+                    );
+                    let checkpoint = std::fs::read(&path)
+                        .ok()
+                        .and_then(|content| bincode::deserialize::<Checkpoint>(&content).ok())?;
+                    if Some(checkpoint.fingerprint) != fingerprint(&inputs[..=position])
+                    {
+                        debug!("Ignoring the checkpoint {path:?} of other inputs");
+                        return None;
+                    }
+                    Some(
+                        (
+                            position,
+                            checkpoint.proof,
+                        ),
+                    )
+                },
+            )
+    }
+
+    /// Saves the proof of the step at `position`.
+    ///
+    /// Failures are logged, a missing checkpoint only costs proving the step again.
+    pub(crate) fn save(
+        &self,
+        table_id: TableId,
+        block_nr: BlockNr,
+        inputs: &[DbBlockType],
+        position: usize,
+        proof: &[u8],
+    )
+    {
+        let path = self.path(
+            table_id,
+            block_nr,
+            position,
+        );
+        let result = std::fs::create_dir_all(&self.dir)
+            .map_err(anyhow::Error::from)
+            .and_then(
+                |()| {
+                    Ok(
+                        bincode::serialize(
+                            &Checkpoint {
+                                fingerprint: fingerprint(&inputs[..=position])
+                                    .context("failed to fingerprint the inputs")?,
+                                proof: proof.to_vec(),
+                            },
+                        )?,
+                    )
+                },
+            )
+            .and_then(
+                |content| {
+                    // Written aside then renamed, a crash must not leave a truncated checkpoint.
+                    let tmp = path.with_extension("ckpt.tmp");
+                    std::fs::write(
+                        &tmp,
+                        content,
+                    )?;
+                    std::fs::rename(
+                        &tmp,
+                        &path,
+                    )
+                    .context("failed to rename the checkpoint")
+                },
+            );
+        if let Err(err) = result
+        {
+            warn!("Failed to save the checkpoint {path:?}: {err:?}");
+        }
+    }
+
+    /// Removes the checkpoints of a completed task.
+    pub(crate) fn clear(
+        &self,
+        table_id: TableId,
+        block_nr: BlockNr,
+        steps: usize,
+    )
+    {
+        for position in 0..steps
+        {
+            let _ = std::fs::remove_file(
+                self.path(
+                    table_id,
+                    block_nr,
+                    position,
+                ),
+            );
+        }
+    }
+
+    /// Removes the checkpoints older than the TTL, whose task is not expected to be retried, the
+    /// other files of the directory being left as they are.
+    fn prune(&self)
+    {
+        let Ok(entries) = std::fs::read_dir(&self.dir)
+        else
+        {
+            return;
+        };
+        let now = SystemTime::now();
+        for entry in entries.flatten()
+        {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !name.starts_with("index-")
+                || !(name.ends_with(".ckpt") || name.ends_with(".ckpt.tmp"))
+            {
+                continue;
+            }
+            let expired = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(
+                    |modified| {
+                        now.duration_since(modified)
+                            .is_ok_and(|age| age > self.ttl)
+                    },
+                );
+            if expired
+            {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+/// The BLAKE3 hash of the `inputs`, stable across the releases of the worker and of Rust for the
+/// checkpoints to outlive an upgrade.
+fn fingerprint(inputs: &[DbBlockType]) -> Option<[u8; 32]>
+{
+    let inputs = bincode::serialize(inputs).ok()?;
+    Some(*blake3::hash(&inputs).as_bytes())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn prunes_only_the_checkpoints()
+    {
+        let dir = std::env::temp_dir().join(
+            format!(
+                "lgn-checkpoints-test-{}",
+                std::process::id()
+            ),
+        );
+        std::fs::create_dir_all(&dir).unwrap();
+        let checkpoints = IndexCheckpoints::new(
+            &dir,
+            Duration::ZERO,
+        );
+        for name in [
+            "index-1-2-0.ckpt",
+            "index-1-2-1.ckpt.tmp",
+            "other.ckpt",
+            "index-notes.txt",
+        ]
+        {
+            std::fs::write(
+                dir.join(name),
+                b"",
+            )
+            .unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        checkpoints.prune();
+
+        let mut left = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(
+                |entry| {
+                    entry
+                        .unwrap()
+                        .file_name()
+                        .to_string_lossy()
+                        .to_string()
+                },
+            )
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(
+            left,
+            [
+                "index-notes.txt",
+                "other.ckpt"
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
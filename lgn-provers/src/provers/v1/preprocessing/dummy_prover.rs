@@ -2,16 +2,29 @@ use alloy::primitives::Address;
 use alloy::primitives::U256;
 use mp2_common::digest::TableDimension;
 use mp2_common::types::HashOutput;
-use tracing::debug;
 
-use crate::dummy_utils::dummy_proof;
+use crate::dummy_profile::DummyProfile;
 use crate::provers::v1::preprocessing::prover::StorageDatabaseProver;
 use crate::provers::v1::preprocessing::prover::StorageExtractionProver;
+use crate::sampled_debug;
 
 const PROOF_SIZE: usize = 120;
 
 /// Prover implementation which performs no proving and returns random data as a proof.
-pub struct DummyProver;
+pub struct DummyProver
+{
+    profile: DummyProfile,
+}
+
+impl DummyProver
+{
+    pub(crate) fn new(profile: DummyProfile) -> Self
+    {
+        Self {
+            profile,
+        }
+    }
+}
 
 impl StorageExtractionProver for DummyProver
 {
@@ -22,8 +35,17 @@ impl StorageExtractionProver for DummyProver
         _column_id: u64,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving single variable leaf");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "single variable leaf",
+            "Proving single variable leaf"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "single_variable_leaf",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_single_variable_branch(
@@ -32,8 +54,17 @@ impl StorageExtractionProver for DummyProver
         _child_proofs: Vec<Vec<u8>>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving single variable branch");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "single variable branch",
+            "Proving single variable branch"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "single_variable_branch",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_mapping_variable_leaf(
@@ -45,8 +76,17 @@ impl StorageExtractionProver for DummyProver
         _value_id: u64,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving mapping variable leaf");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "mapping variable leaf",
+            "Proving mapping variable leaf"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "mapping_variable_leaf",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_mapping_variable_branch(
@@ -55,8 +95,17 @@ impl StorageExtractionProver for DummyProver
         _child_proofs: Vec<Vec<u8>>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving mapping variable branch");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "mapping variable branch",
+            "Proving mapping variable branch"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "mapping_variable_branch",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_length_leaf(
@@ -66,8 +115,17 @@ impl StorageExtractionProver for DummyProver
         _variable_slot: usize,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving length leaf");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "length leaf",
+            "Proving length leaf"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "length_leaf",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_length_branch(
@@ -76,8 +134,17 @@ impl StorageExtractionProver for DummyProver
         _child_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving length branch");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "length branch",
+            "Proving length branch"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "length_branch",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_contract_leaf(
@@ -87,8 +154,17 @@ impl StorageExtractionProver for DummyProver
         _contract_address: Address,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving contract leaf");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "contract leaf",
+            "Proving contract leaf"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "contract_leaf",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_contract_branch(
@@ -97,8 +173,17 @@ impl StorageExtractionProver for DummyProver
         _child_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving contract branch");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "contract branch",
+            "Proving contract branch"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "contract_branch",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_block(
@@ -106,8 +191,17 @@ impl StorageExtractionProver for DummyProver
         _rlp_header: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving block");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "block",
+            "Proving block"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "block",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_final_extraction_simple(
@@ -118,8 +212,17 @@ impl StorageExtractionProver for DummyProver
         _dimension: TableDimension,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving final extraction simple");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "final extraction simple",
+            "Proving final extraction simple"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "final_extraction_simple",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_final_extraction_lengthed(
@@ -130,8 +233,17 @@ impl StorageExtractionProver for DummyProver
         _length_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving final extraction lengthed");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "final extraction lengthed",
+            "Proving final extraction lengthed"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "final_extraction_lengthed",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_final_extraction_merge(
@@ -142,8 +254,17 @@ impl StorageExtractionProver for DummyProver
         _mapping_table_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving final extraction merge table");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "final extraction merge table",
+            "Proving final extraction merge table"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "final_extraction_merge",
+                    PROOF_SIZE,
+                ),
+        )
     }
 }
 
@@ -156,7 +277,13 @@ impl StorageDatabaseProver for DummyProver
         _is_multiplier: bool,
     ) -> anyhow::Result<Vec<u8>>
     {
-        Ok(dummy_proof(PROOF_SIZE))
+        Ok(
+            self.profile
+                .proof(
+                    "cell_leaf",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_cell_partial(
@@ -167,8 +294,17 @@ impl StorageDatabaseProver for DummyProver
         _child_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving cell partial");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "cell partial",
+            "Proving cell partial"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "cell_partial",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_cell_full(
@@ -179,8 +315,17 @@ impl StorageDatabaseProver for DummyProver
         _child_proofs: Vec<Vec<u8>>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving cell full");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "cell full",
+            "Proving cell full"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "cell_full",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_row_leaf(
@@ -191,8 +336,17 @@ impl StorageDatabaseProver for DummyProver
         _cells_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving row leaf");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "row leaf",
+            "Proving row leaf"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "row_leaf",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_row_partial(
@@ -205,8 +359,17 @@ impl StorageDatabaseProver for DummyProver
         _cells_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving row partial");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "row partial",
+            "Proving row partial"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "row_partial",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_row_full(
@@ -218,8 +381,17 @@ impl StorageDatabaseProver for DummyProver
         _cells_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving row full");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "row full",
+            "Proving row full"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "row_full",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_membership(
@@ -233,8 +405,17 @@ impl StorageDatabaseProver for DummyProver
         _right_child_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving membership");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "membership",
+            "Proving membership"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "membership",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_block_leaf(
@@ -244,8 +425,17 @@ impl StorageDatabaseProver for DummyProver
         _rows_tree_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving block leaf");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "block leaf",
+            "Proving block leaf"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "block_leaf",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_block_parent(
@@ -261,8 +451,17 @@ impl StorageDatabaseProver for DummyProver
         _rows_tree_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving block parent");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "block parent",
+            "Proving block parent"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "block_parent",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_ivc(
@@ -271,7 +470,16 @@ impl StorageDatabaseProver for DummyProver
         _previous_proof: Option<Vec<u8>>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving ivc");
-        Ok(dummy_proof(PROOF_SIZE))
+        sampled_debug!(
+            "ivc",
+            "Proving ivc"
+        );
+        Ok(
+            self.profile
+                .proof(
+                    "ivc",
+                    PROOF_SIZE,
+                ),
+        )
     }
 }
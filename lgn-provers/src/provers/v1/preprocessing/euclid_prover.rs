@@ -30,19 +30,31 @@ use tracing::debug;
 use crate::params::ParamsLoader;
 use crate::provers::v1::preprocessing::prover::StorageDatabaseProver;
 use crate::provers::v1::preprocessing::prover::StorageExtractionProver;
+use crate::sampled_debug;
 
 pub struct EuclidProver
 {
     params: PublicParameters,
+
+    /// Cells proof of the rows without any secondary cell, derived once from the params.
+    empty_cell_tree_proof: Vec<u8>,
 }
 
 impl EuclidProver
 {
-    pub fn new(params: PublicParameters) -> Self
+    pub fn new(params: PublicParameters) -> anyhow::Result<Self>
     {
-        Self {
-            params,
-        }
+        let empty_cell_tree_proof = ParamsLoader::derive_artifact(
+            "empty_cell_tree_proof",
+            || params.empty_cell_tree_proof(),
+        )?;
+
+        Ok(
+            Self {
+                params,
+                empty_cell_tree_proof,
+            },
+        )
     }
 
     pub(crate) fn init(
@@ -63,12 +75,27 @@ impl EuclidProver
             skip_checksum,
             skip_store,
         )?;
+        let prover = Self::new(params)?;
         debug!("Preprocessing prover created");
-        Ok(
-            Self {
-                params,
-            },
-        )
+        Ok(prover)
+    }
+
+    /// The proof of the cells tree of a row, the empty cells tree one if the row has no secondary
+    /// cells.
+    fn cells_proof(
+        &self,
+        cells_proof: Vec<u8>,
+    ) -> Vec<u8>
+    {
+        if cells_proof.is_empty()
+        {
+            self.empty_cell_tree_proof
+                .clone()
+        }
+        else
+        {
+            cells_proof
+        }
     }
 
     fn prove(
@@ -77,9 +104,9 @@ impl EuclidProver
         name: &str,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!(
-            "Proving {}",
-            name
+        sampled_debug!(
+            name,
+            "Proving {name}"
         );
 
         let now = std::time::Instant::now();
@@ -91,23 +118,22 @@ impl EuclidProver
         {
             Ok(proof) =>
             {
-                debug!(
+                sampled_debug!(
+                    name,
                     time = now
                         .elapsed()
                         .as_secs_f32(),
                     proof_type = name,
-                    "proof generation time: {:?}",
-                    now.elapsed()
-                );
-                debug!(
-                    "{name} size in kB: {}",
+                    "proof generation time: {:?}, size in kB: {}",
+                    now.elapsed(),
                     proof.len() / 1024
                 );
                 Ok(proof)
             },
             Err(err) =>
             {
-                debug!(
+                sampled_debug!(
+                    name,
                     "Proof generation failed in {:?}",
                     now.elapsed()
                 );
@@ -452,16 +478,7 @@ impl StorageDatabaseProver for EuclidProver
         cells_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        let cells_proof = if !cells_proof.is_empty()
-        {
-            cells_proof
-        }
-        else
-        {
-            // TODO: provide empty
-            unimplemented!("No cells proof provided")
-        };
-
+        let cells_proof = self.cells_proof(cells_proof);
         let input = RowsTree(
             verifiable_db::row_tree::CircuitInput::leaf(
                 identifier,
@@ -486,6 +503,7 @@ impl StorageDatabaseProver for EuclidProver
         cells_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
+        let cells_proof = self.cells_proof(cells_proof);
         let input = RowsTree(
             verifiable_db::row_tree::CircuitInput::partial(
                 identifier,
@@ -511,6 +529,7 @@ impl StorageDatabaseProver for EuclidProver
         cells_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
+        let cells_proof = self.cells_proof(cells_proof);
         let input = RowsTree(
             verifiable_db::row_tree::CircuitInput::full(
                 identifier,
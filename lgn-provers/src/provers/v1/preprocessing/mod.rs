@@ -1,9 +1,12 @@
 use tracing::info;
 
+use crate::dummy_profile::DummyProfile;
 use crate::provers::v1::preprocessing::prover::StorageDatabaseProver;
 use crate::provers::v1::preprocessing::prover::StorageExtractionProver;
 use crate::provers::v1::preprocessing::task::Preprocessing;
+pub mod checkpoints;
 pub mod prover;
+mod stages;
 pub mod task;
 
 #[cfg(feature = "dummy-prover")]
@@ -12,6 +15,16 @@ mod dummy_prover;
 #[cfg(not(feature = "dummy-prover"))]
 pub mod euclid_prover;
 
+/// Checks that `proof`, replied to a preprocessing task, deserializes with its verifying key.
+pub fn check_proof(proof: &[u8]) -> anyhow::Result<()>
+{
+    #[cfg(feature = "dummy-prover")]
+    let _ = proof;
+    #[cfg(not(feature = "dummy-prover"))]
+    mp2_common::proof::ProofWithVK::deserialize(proof)?;
+    Ok(())
+}
+
 #[allow(unused_variables)]
 pub fn create_prover(
     url: &str,
@@ -20,6 +33,7 @@ pub fn create_prover(
     checksum_expected_local_path: &str,
     skip_checksum: bool,
     skip_store: bool,
+    dummy_profile: DummyProfile,
 ) -> anyhow::Result<Preprocessing<impl StorageExtractionProver + StorageDatabaseProver>>
 {
     let prover = {
@@ -27,7 +41,7 @@ pub fn create_prover(
         {
             use dummy_prover::DummyProver;
             info!("Creating dummy storage prover");
-            DummyProver
+            DummyProver::new(dummy_profile)
         }
 
         #[cfg(not(feature = "dummy-prover"))]
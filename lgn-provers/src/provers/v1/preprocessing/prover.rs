@@ -130,6 +130,8 @@ pub trait StorageDatabaseProver
     ) -> anyhow::Result<Vec<u8>>;
 
     /// Prove a row tree leaf node.
+    ///
+    /// `cells_proof` is empty if the row has no secondary cells.
     fn prove_row_leaf(
         &self,
         identifier: u64,
@@ -139,6 +141,8 @@ pub trait StorageDatabaseProver
     ) -> anyhow::Result<Vec<u8>>;
 
     /// Prove a row tree partial branch node.
+    ///
+    /// `cells_proof` is empty if the row has no secondary cells.
     fn prove_row_partial(
         &self,
         identifier: u64,
@@ -150,6 +154,8 @@ pub trait StorageDatabaseProver
     ) -> anyhow::Result<Vec<u8>>;
 
     /// Prove a row tree full branch node.
+    ///
+    /// `cells_proof` is empty if the row has no secondary cells.
     fn prove_row_full(
         &self,
         identifier: u64,
@@ -0,0 +1,151 @@
+//! The time the chained preprocessing tasks spend in each of their stages, e.g. in the leaf and in
+//! the branches of a contract extraction, to tell which circuits are worth optimizing.
+//!
+//! Each proof is recorded in the `zkmr_worker_preprocessing_stage_seconds{chain, stage}`
+//! histogram, and the breakdown of a task is logged at debug level once it is proven.
+
+use std::fmt::Write;
+use std::time::Duration;
+use std::time::Instant;
+
+use metrics::histogram;
+use tracing::debug;
+
+/// The stages of a task, in the order they were first reached.
+pub(crate) struct StageTimings
+{
+    chain: &'static str,
+    stages: Vec<Stage>,
+}
+
+struct Stage
+{
+    name: &'static str,
+    proofs: u32,
+    total: Duration,
+}
+
+impl StageTimings
+{
+    pub(crate) fn new(chain: &'static str) -> Self
+    {
+        Self {
+            chain,
+            stages: vec![],
+        }
+    }
+
+    /// Runs `prove`, recording its time under `stage` if it succeeds.
+    pub(crate) fn time<T>(
+        &mut self,
+        stage: &'static str,
+        prove: impl FnOnce() -> anyhow::Result<T>,
+    ) -> anyhow::Result<T>
+    {
+        let started = Instant::now();
+        let proof = prove()?;
+        let elapsed = started.elapsed();
+        histogram!(
+            "zkmr_worker_preprocessing_stage_seconds",
+            "chain" => self.chain,
+            "stage" => stage,
+        )
+        .record(elapsed.as_secs_f64());
+        match self
+            .stages
+            .iter_mut()
+            .find(|known| known.name == stage)
+        {
+            Some(known) =>
+            {
+                known.proofs += 1;
+                known.total += elapsed;
+            },
+            None =>
+            {
+                self.stages
+                    .push(
+                        Stage {
+                            name: stage,
+                            proofs: 1,
+                            total: elapsed,
+                        },
+                    )
+            },
+        }
+        Ok(proof)
+    }
+
+    /// Logs the breakdown of the task.
+    pub(crate) fn finish(self)
+    {
+        debug!(
+            chain = self.chain,
+            "Stage timings: {}",
+            self.breakdown()
+        );
+    }
+
+    /// The proofs and time of each stage, e.g. `leaf 1 in 0.80s, branch 6 in 5.21s`.
+    fn breakdown(&self) -> String
+    {
+        let mut breakdown = String::new();
+        for (i, stage) in self
+            .stages
+            .iter()
+            .enumerate()
+        {
+            if i > 0
+            {
+                breakdown.push_str(", ");
+            }
+            let _ = write!(
+                breakdown,
+                "{} {} in {:.2}s",
+                stage.name,
+                stage.proofs,
+                stage
+                    .total
+                    .as_secs_f64()
+            );
+        }
+        breakdown
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn breaks_the_time_down_by_stage()
+    {
+        let mut timings = StageTimings::new("contract");
+        for stage in [
+            "leaf",
+            "branch",
+            "branch",
+        ]
+        {
+            timings
+                .time(
+                    stage,
+                    || Ok(()),
+                )
+                .unwrap();
+        }
+        assert!(
+            timings
+                .time(
+                    "branch",
+                    || -> anyhow::Result<()> { anyhow::bail!("failed") },
+                )
+                .is_err()
+        );
+        assert_eq!(
+            timings.breakdown(),
+            "leaf 1 in 0.00s, branch 2 in 0.00s"
+        );
+    }
+}
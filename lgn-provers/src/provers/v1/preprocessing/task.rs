@@ -1,13 +1,21 @@
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
 use lgn_messages::types::v1::preprocessing::db_keys;
 use lgn_messages::types::v1::preprocessing::db_tasks::DatabaseType;
 use lgn_messages::types::v1::preprocessing::db_tasks::DbBlockType;
 use lgn_messages::types::v1::preprocessing::db_tasks::DbCellType;
 use lgn_messages::types::v1::preprocessing::db_tasks::DbRowType;
+use lgn_messages::types::v1::preprocessing::db_tasks::IndexInputs;
+use lgn_messages::types::v1::preprocessing::db_tasks::RowUpdateInput;
 use lgn_messages::types::v1::preprocessing::ext_keys;
+use lgn_messages::types::v1::preprocessing::ext_tasks::BlockExtractionInput;
 use lgn_messages::types::v1::preprocessing::ext_tasks::ExtractionType;
 use lgn_messages::types::v1::preprocessing::ext_tasks::FinalExtraction;
 use lgn_messages::types::v1::preprocessing::ext_tasks::FinalExtractionType;
 use lgn_messages::types::v1::preprocessing::ext_tasks::MptType;
+use lgn_messages::types::v1::preprocessing::ext_tasks::TableExtractionInput;
+use lgn_messages::types::v1::preprocessing::ext_tasks::ValueNode;
 use lgn_messages::types::v1::preprocessing::WorkerTask;
 use lgn_messages::types::v1::preprocessing::WorkerTaskType;
 use lgn_messages::types::MessageEnvelope;
@@ -16,14 +24,20 @@ use lgn_messages::types::ProofCategory;
 use lgn_messages::types::ReplyType;
 use lgn_messages::types::TaskType;
 use lgn_messages::types::WorkerReply;
+use metrics::counter;
+use tracing::info;
 
+use crate::provers::v1::preprocessing::checkpoints::IndexCheckpoints;
 use crate::provers::v1::preprocessing::prover::StorageDatabaseProver;
 use crate::provers::v1::preprocessing::prover::StorageExtractionProver;
+use crate::provers::v1::preprocessing::stages::StageTimings;
 use crate::provers::LgnProver;
+use crate::provers::YieldPoint;
 
 pub struct Preprocessing<P>
 {
     prover: P,
+    checkpoints: Option<IndexCheckpoints>,
 }
 
 impl<P: StorageExtractionProver + StorageDatabaseProver> LgnProver<TaskType, ReplyType>
@@ -33,6 +47,18 @@ impl<P: StorageExtractionProver + StorageDatabaseProver> LgnProver<TaskType, Rep
         &self,
         envelope: &MessageEnvelope<TaskType>,
     ) -> anyhow::Result<MessageReplyEnvelope<ReplyType>>
+    {
+        self.run_cooperatively(
+            envelope,
+            &(),
+        )
+    }
+
+    fn run_cooperatively(
+        &self,
+        envelope: &MessageEnvelope<TaskType>,
+        yield_point: &dyn YieldPoint,
+    ) -> anyhow::Result<MessageReplyEnvelope<ReplyType>>
     {
         let query_id = envelope
             .query_id
@@ -60,7 +86,10 @@ impl<P: StorageExtractionProver + StorageDatabaseProver> LgnProver<TaskType, Rep
                     key.to_string()
                 },
             };
-            let result = self.run_inner(task.clone())?;
+            let result = self.run_inner(
+                task.clone(),
+                yield_point,
+            )?;
             let reply_type = ReplyType::V1Preprocessing(
                 WorkerReply::new(
                     *chain_id,
@@ -96,12 +125,24 @@ impl<P: StorageExtractionProver + StorageDatabaseProver> Preprocessing<P>
     {
         Self {
             prover,
+            checkpoints: None,
         }
     }
 
+    /// Checkpoints the intermediate proofs of the index tasks, so that their retries resume.
+    pub fn with_index_checkpoints(
+        mut self,
+        checkpoints: IndexCheckpoints,
+    ) -> Self
+    {
+        self.checkpoints = Some(checkpoints);
+        self
+    }
+
     pub fn run_inner(
         &self,
         task: WorkerTask,
+        yield_point: &dyn YieldPoint,
     ) -> anyhow::Result<Vec<u8>>
     {
         Ok(
@@ -169,6 +210,7 @@ impl<P: StorageExtractionProver + StorageDatabaseProver> Preprocessing<P>
                         },
                         ExtractionType::LengthExtraction(length) =>
                         {
+                            let mut timings = StageTimings::new("length");
                             let mut proofs = vec![];
                             for (i, node) in length
                                 .nodes
@@ -177,27 +219,37 @@ impl<P: StorageExtractionProver + StorageDatabaseProver> Preprocessing<P>
                             {
                                 if i == 0
                                 {
-                                    let proof = self
-                                        .prover
-                                        .prove_length_leaf(
-                                            node.clone(),
-                                            length.length_slot,
-                                            length.variable_slot,
-                                        )?;
+                                    let proof = timings.time(
+                                        "leaf",
+                                        || {
+                                            self.prover
+                                                .prove_length_leaf(
+                                                    node.clone(),
+                                                    length.length_slot,
+                                                    length.variable_slot,
+                                                )
+                                        },
+                                    )?;
                                     proofs.push(proof);
                                 }
                                 else
                                 {
-                                    self.prover
-                                        .prove_length_branch(
-                                            node.clone(),
-                                            proofs
-                                                .last()
-                                                .unwrap()
-                                                .clone(),
-                                        )?;
+                                    timings.time(
+                                        "branch",
+                                        || {
+                                            self.prover
+                                                .prove_length_branch(
+                                                    node.clone(),
+                                                    proofs
+                                                        .last()
+                                                        .unwrap()
+                                                        .clone(),
+                                                )
+                                        },
+                                    )?;
                                 }
                             }
+                            timings.finish();
                             proofs
                                 .last()
                                 .unwrap()
@@ -205,6 +257,7 @@ impl<P: StorageExtractionProver + StorageDatabaseProver> Preprocessing<P>
                         },
                         ExtractionType::ContractExtraction(contract) =>
                         {
+                            let mut timings = StageTimings::new("contract");
                             let mut proofs = vec![];
                             for (i, node) in contract
                                 .nodes
@@ -213,31 +266,40 @@ impl<P: StorageExtractionProver + StorageDatabaseProver> Preprocessing<P>
                             {
                                 if i == 0
                                 {
-                                    let proof = self
-                                        .prover
-                                        .prove_contract_leaf(
-                                            node.clone(),
-                                            contract
-                                                .storage_root
-                                                .clone(),
-                                            contract.contract,
-                                        )?;
+                                    let proof = timings.time(
+                                        "leaf",
+                                        || {
+                                            self.prover
+                                                .prove_contract_leaf(
+                                                    node.clone(),
+                                                    contract
+                                                        .storage_root
+                                                        .clone(),
+                                                    contract.contract,
+                                                )
+                                        },
+                                    )?;
                                     proofs.push(proof);
                                 }
                                 else
                                 {
-                                    let proof = self
-                                        .prover
-                                        .prove_contract_branch(
-                                            node.clone(),
-                                            proofs
-                                                .last()
-                                                .unwrap()
-                                                .clone(),
-                                        )?;
+                                    let proof = timings.time(
+                                        "branch",
+                                        || {
+                                            self.prover
+                                                .prove_contract_branch(
+                                                    node.clone(),
+                                                    proofs
+                                                        .last()
+                                                        .unwrap()
+                                                        .clone(),
+                                                )
+                                        },
+                                    )?;
                                     proofs.push(proof);
                                 }
                             }
+                            timings.finish();
                             proofs
                                 .last()
                                 .unwrap()
@@ -245,6 +307,16 @@ impl<P: StorageExtractionProver + StorageDatabaseProver> Preprocessing<P>
                         },
                         ExtractionType::BlockExtraction(block) =>
                         {
+                            let header = block.header_fields()?;
+                            info!(
+                                block_nr = header.number,
+                                hash = ?header.hash,
+                                parent_hash = ?header.parent_hash,
+                                state_root = ?header.state_root,
+                                "Decoded block header",
+                            );
+                            header.ensure_block_nr(task.block_nr)?;
+
                             self.prover
                                 .prove_block(
                                     block
@@ -316,6 +388,10 @@ impl<P: StorageExtractionProver + StorageDatabaseProver> Preprocessing<P>
                                 },
                             }
                         },
+                        ExtractionType::TableExtraction(table_extraction) =>
+                        {
+                            self.prove_table_extraction(*table_extraction)?
+                        },
                     }
                 },
                 WorkerTaskType::Database(db) =>
@@ -402,73 +478,17 @@ impl<P: StorageExtractionProver + StorageDatabaseProver> Preprocessing<P>
                         },
                         DatabaseType::Index(block) =>
                         {
-                            let mut last_proof = None;
-                            for input in &block.inputs
-                            {
-                                last_proof = Some(
-                                    match input
-                                    {
-                                        DbBlockType::Leaf(leaf) =>
-                                        {
-                                            self.prover
-                                                .prove_block_leaf(
-                                                    leaf.block_id,
-                                                    leaf.extraction_proof
-                                                        .to_owned(),
-                                                    leaf.rows_proof
-                                                        .to_owned(),
-                                                )?
-                                        },
-                                        DbBlockType::Parent(parent) =>
-                                        {
-                                            self.prover
-                                                .prove_block_parent(
-                                                    parent.block_id,
-                                                    parent.old_block_number,
-                                                    parent.old_min,
-                                                    parent.old_max,
-                                                    parent
-                                                        .prev_left_child
-                                                        .to_owned(),
-                                                    parent
-                                                        .prev_right_child
-                                                        .to_owned(),
-                                                    parent
-                                                        .old_rows_tree_hash
-                                                        .to_owned(),
-                                                    parent
-                                                        .extraction_proof
-                                                        .to_owned(),
-                                                    parent
-                                                        .rows_proof
-                                                        .to_owned(),
-                                                )?
-                                        },
-                                        DbBlockType::Membership(membership) =>
-                                        {
-                                            self.prover
-                                                .prove_membership(
-                                                    membership.block_id,
-                                                    membership.index_value,
-                                                    membership.old_min,
-                                                    membership.old_max,
-                                                    membership
-                                                        .left_child
-                                                        .to_owned(),
-                                                    membership
-                                                        .rows_tree_hash
-                                                        .to_owned(),
-                                                    last_proof
-                                                        .take()
-                                                        .unwrap(),
-                                                )?
-                                        },
-                                    },
-                                );
-                            }
-                            last_proof
-                                .take()
-                                .unwrap()
+                            self.prove_index(
+                                block,
+                                yield_point,
+                            )?
+                        },
+                        DatabaseType::RowUpdate(row_update) =>
+                        {
+                            self.prove_row_update(
+                                row_update,
+                                yield_point,
+                            )?
                         },
                         DatabaseType::IVC(ivc) =>
                         {
@@ -485,4 +505,1045 @@ impl<P: StorageExtractionProver + StorageDatabaseProver> Preprocessing<P>
             },
         )
     }
+
+    /// Prove the steps of an index task in order, each membership step consuming the proof of the
+    /// previous step, resuming from the last checkpoint of a previous attempt if enabled.
+    fn prove_index(
+        &self,
+        block: IndexInputs,
+        yield_point: &dyn YieldPoint,
+    ) -> anyhow::Result<Vec<u8>>
+    {
+        let (start, mut last_proof) = match self
+            .checkpoints
+            .as_ref()
+            .and_then(
+                |checkpoints| {
+                    checkpoints.resume(
+                        block.table_id,
+                        block.block_nr,
+                        &block.inputs,
+                    )
+                },
+            )
+        {
+            Some((position, proof)) =>
+            {
+                info!(
+                    "Resuming the index of table {} block {} after step {position}",
+                    block.table_id, block.block_nr
+                );
+                counter!("zkmr_worker_index_checkpoint_steps_skipped_total")
+                    .increment(position as u64 + 1);
+                (
+                    position + 1,
+                    Some(proof),
+                )
+            },
+            None =>
+            {
+                (
+                    0,
+                    None,
+                )
+            },
+        };
+
+        let mut timings = StageTimings::new("index");
+        for (position, input) in block
+            .inputs
+            .iter()
+            .enumerate()
+            .skip(start)
+        {
+            let stage = match input
+            {
+                DbBlockType::Leaf(_) => "leaf",
+                DbBlockType::Parent(_) => "parent",
+                DbBlockType::Membership(_) => "membership",
+            };
+            let proof = timings.time(
+                stage,
+                || {
+                    match input
+                    {
+                        DbBlockType::Leaf(leaf) =>
+                        {
+                            self.prover
+                                .prove_block_leaf(
+                                    leaf.block_id,
+                                    leaf.extraction_proof
+                                        .to_owned(),
+                                    leaf.rows_proof
+                                        .to_owned(),
+                                )
+                        },
+                        DbBlockType::Parent(parent) =>
+                        {
+                            self.prover
+                                .prove_block_parent(
+                                    parent.block_id,
+                                    parent.old_block_number,
+                                    parent.old_min,
+                                    parent.old_max,
+                                    parent
+                                        .prev_left_child
+                                        .to_owned(),
+                                    parent
+                                        .prev_right_child
+                                        .to_owned(),
+                                    parent
+                                        .old_rows_tree_hash
+                                        .to_owned(),
+                                    parent
+                                        .extraction_proof
+                                        .to_owned(),
+                                    parent
+                                        .rows_proof
+                                        .to_owned(),
+                                )
+                        },
+                        DbBlockType::Membership(membership) =>
+                        {
+                            self.prover
+                                .prove_membership(
+                                    membership.block_id,
+                                    membership.index_value,
+                                    membership.old_min,
+                                    membership.old_max,
+                                    membership
+                                        .left_child
+                                        .to_owned(),
+                                    membership
+                                        .rows_tree_hash
+                                        .to_owned(),
+                                    last_proof
+                                        .take()
+                                        .context("a membership step must follow another step")?,
+                                )
+                        },
+                    }
+                },
+            )?;
+
+            // The proof of the last step is the reply, it is not worth a checkpoint.
+            if let Some(checkpoints) = &self.checkpoints
+            {
+                if position + 1
+                    < block
+                        .inputs
+                        .len()
+                {
+                    checkpoints.save(
+                        block.table_id,
+                        block.block_nr,
+                        &block.inputs,
+                        position,
+                        &proof,
+                    );
+                }
+            }
+            last_proof = Some(proof);
+            yield_point.reached(
+                "index",
+                position + 1,
+                block
+                    .inputs
+                    .len(),
+            )?;
+        }
+
+        if let Some(checkpoints) = &self.checkpoints
+        {
+            checkpoints.clear(
+                block.table_id,
+                block.block_nr,
+                block
+                    .inputs
+                    .len(),
+            );
+        }
+        timings.finish();
+        last_proof.context("the index task has no step")
+    }
+
+    /// Prove the cells tree of a row bottom-up, then the row itself, returning the row proof.
+    fn prove_row_update(
+        &self,
+        row_update: RowUpdateInput,
+        yield_point: &dyn YieldPoint,
+    ) -> anyhow::Result<Vec<u8>>
+    {
+        let mut timings = StageTimings::new("row_update");
+        let mut cell_proofs: Vec<Option<Vec<u8>>> = Vec::with_capacity(
+            row_update
+                .cells
+                .len(),
+        );
+        for (i, cell) in row_update
+            .cells
+            .iter()
+            .enumerate()
+        {
+            let mut child_proofs = cell
+                .children
+                .iter()
+                .map(
+                    |&child| {
+                        ensure!(
+                            child < i,
+                            "cell {i} references cell {child} which does not precede it"
+                        );
+                        cell_proofs[child]
+                            .take()
+                            .with_context(|| format!("cell {child} is the child of several cells"))
+                    },
+                )
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            let proof = match child_proofs.len()
+            {
+                0 =>
+                {
+                    timings.time(
+                        "cell_leaf",
+                        || {
+                            self.prover
+                                .prove_cell_leaf(
+                                    cell.identifier,
+                                    cell.value,
+                                    cell.is_multiplier,
+                                )
+                        },
+                    )?
+                },
+                1 =>
+                {
+                    timings.time(
+                        "cell_partial",
+                        || {
+                            self.prover
+                                .prove_cell_partial(
+                                    cell.identifier,
+                                    cell.value,
+                                    cell.is_multiplier,
+                                    child_proofs.remove(0),
+                                )
+                        },
+                    )?
+                },
+                2 =>
+                {
+                    timings.time(
+                        "cell_full",
+                        || {
+                            self.prover
+                                .prove_cell_full(
+                                    cell.identifier,
+                                    cell.value,
+                                    cell.is_multiplier,
+                                    child_proofs,
+                                )
+                        },
+                    )?
+                },
+                n => bail!("cell {i} has {n} children, at most 2 are allowed"),
+            };
+            cell_proofs.push(Some(proof));
+            yield_point.reached(
+                "cells",
+                i + 1,
+                row_update
+                    .cells
+                    .len(),
+            )?;
+        }
+
+        let cells_proof = cell_proofs
+            .pop()
+            .flatten()
+            .unwrap_or_default();
+        ensure!(
+            cell_proofs
+                .iter()
+                .all(Option::is_none),
+            "the cells of row {} do not form a single tree",
+            row_update.row_id
+        );
+
+        let mut child_proofs = row_update.child_proofs;
+        let proof = match child_proofs.len()
+        {
+            0 =>
+            {
+                timings.time(
+                    "row_leaf",
+                    || {
+                        self.prover
+                            .prove_row_leaf(
+                                row_update.identifier,
+                                row_update.value,
+                                row_update.is_multiplier,
+                                cells_proof,
+                            )
+                    },
+                )?
+            },
+            1 =>
+            {
+                timings.time(
+                    "row_partial",
+                    || {
+                        self.prover
+                            .prove_row_partial(
+                                row_update.identifier,
+                                row_update.value,
+                                row_update.is_multiplier,
+                                row_update.is_child_left,
+                                child_proofs.remove(0),
+                                cells_proof,
+                            )
+                    },
+                )?
+            },
+            2 =>
+            {
+                timings.time(
+                    "row_full",
+                    || {
+                        self.prover
+                            .prove_row_full(
+                                row_update.identifier,
+                                row_update.value,
+                                row_update.is_multiplier,
+                                child_proofs,
+                                cells_proof,
+                            )
+                    },
+                )?
+            },
+            n =>
+            {
+                bail!(
+                    "row {} has {n} children, at most 2 are allowed",
+                    row_update.row_id
+                )
+            },
+        };
+        timings.finish();
+        Ok(proof)
+    }
+
+    /// Prove the values, length, contract and block extractions of a mapping with length table,
+    /// then its final extraction, returning the final extraction proof.
+    fn prove_table_extraction(
+        &self,
+        table: TableExtractionInput,
+    ) -> anyhow::Result<Vec<u8>>
+    {
+        let block = BlockExtractionInput::new(table.rlp_header);
+        block
+            .header_fields()?
+            .ensure_block_nr(table.block_nr)?;
+
+        let mut timings = StageTimings::new("table");
+        let mut value_proofs: Vec<Option<Vec<u8>>> = Vec::with_capacity(
+            table
+                .value_nodes
+                .len(),
+        );
+        for (i, value_node) in table
+            .value_nodes
+            .into_iter()
+            .enumerate()
+        {
+            let proof = match value_node
+            {
+                ValueNode::Leaf(leaf) =>
+                {
+                    timings.time(
+                        "mapping_leaf",
+                        || {
+                            self.prover
+                                .prove_mapping_variable_leaf(
+                                    leaf.key,
+                                    leaf.node,
+                                    leaf.slot,
+                                    leaf.key_id,
+                                    leaf.value_id,
+                                )
+                        },
+                    )?
+                },
+                ValueNode::Branch {
+                    node,
+                    children,
+                } =>
+                {
+                    ensure!(
+                        !children.is_empty(),
+                        "storage branch node {i} has no children"
+                    );
+                    let child_proofs = children
+                        .iter()
+                        .map(
+                            |&child| {
+                                ensure!(
+                                    child < i,
+                                    "storage node {i} references node {child} which does not precede it"
+                                );
+                                value_proofs[child]
+                                    .take()
+                                    .with_context(
+                                        || format!("storage node {child} is the child of several nodes"),
+                                    )
+                            },
+                        )
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    timings.time(
+                        "mapping_branch",
+                        || {
+                            self.prover
+                                .prove_mapping_variable_branch(
+                                    node,
+                                    child_proofs,
+                                )
+                        },
+                    )?
+                },
+            };
+            value_proofs.push(Some(proof));
+        }
+        let value_proof = value_proofs
+            .pop()
+            .flatten()
+            .context("no storage node to prove")?;
+        ensure!(
+            value_proofs
+                .iter()
+                .all(Option::is_none),
+            "the storage nodes of table {} do not form a single tree",
+            table.table_id
+        );
+
+        let mut length_nodes = table
+            .length_nodes
+            .into_iter();
+        let length_leaf = length_nodes
+            .next()
+            .context("no length slot node to prove")?;
+        let mut length_proof = timings.time(
+            "length_leaf",
+            || {
+                self.prover
+                    .prove_length_leaf(
+                        length_leaf,
+                        table.length_slot,
+                        table.variable_slot,
+                    )
+            },
+        )?;
+        for node in length_nodes
+        {
+            length_proof = timings.time(
+                "length_branch",
+                || {
+                    self.prover
+                        .prove_length_branch(
+                            node,
+                            length_proof,
+                        )
+                },
+            )?;
+        }
+
+        let mut contract_nodes = table
+            .contract_nodes
+            .into_iter();
+        let contract_leaf = contract_nodes
+            .next()
+            .context("no contract node to prove")?;
+        let mut contract_proof = timings.time(
+            "contract_leaf",
+            || {
+                self.prover
+                    .prove_contract_leaf(
+                        contract_leaf,
+                        table.storage_root,
+                        table.contract,
+                    )
+            },
+        )?;
+        for node in contract_nodes
+        {
+            contract_proof = timings.time(
+                "contract_branch",
+                || {
+                    self.prover
+                        .prove_contract_branch(
+                            node,
+                            contract_proof,
+                        )
+                },
+            )?;
+        }
+
+        let block_proof = timings.time(
+            "block",
+            || {
+                self.prover
+                    .prove_block(block.rlp_header)
+            },
+        )?;
+
+        let proof = timings.time(
+            "final",
+            || {
+                self.prover
+                    .prove_final_extraction_lengthed(
+                        block_proof,
+                        contract_proof,
+                        value_proof,
+                        length_proof,
+                    )
+            },
+        )?;
+        timings.finish();
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use alloy::primitives::Address;
+    use alloy::primitives::U256;
+    use lgn_messages::types::v1::preprocessing::db_tasks::CellNode;
+    use lgn_messages::types::v1::preprocessing::ext_tasks::MappingLeafInput;
+    use mp2_common::digest::TableDimension;
+    use mp2_common::types::HashOutput;
+
+    use super::*;
+
+    /// Replies with proofs naming the proving calls, `name(inputs)`.
+    struct Recorder;
+
+    fn proof(
+        name: &str,
+        inputs: &[&[u8]],
+    ) -> anyhow::Result<Vec<u8>>
+    {
+        let inputs = inputs
+            .iter()
+            .map(|input| String::from_utf8_lossy(input))
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(format!("{name}({inputs})").into_bytes())
+    }
+
+    impl StorageExtractionProver for Recorder
+    {
+        fn prove_single_variable_leaf(
+            &self,
+            _node: Vec<u8>,
+            _slot: u8,
+            _column_id: u64,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            unreachable!()
+        }
+
+        fn prove_single_variable_branch(
+            &self,
+            _node: Vec<u8>,
+            _child_proofs: Vec<Vec<u8>>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            unreachable!()
+        }
+
+        fn prove_mapping_variable_leaf(
+            &self,
+            key: Vec<u8>,
+            _node: Vec<u8>,
+            _slot: u8,
+            _key_id: u64,
+            _value_id: u64,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                "leaf",
+                &[&key],
+            )
+        }
+
+        fn prove_mapping_variable_branch(
+            &self,
+            node: Vec<u8>,
+            child_proofs: Vec<Vec<u8>>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            let mut inputs = vec![node.as_slice()];
+            inputs.extend(
+                child_proofs
+                    .iter()
+                    .map(Vec::as_slice),
+            );
+            proof(
+                "branch",
+                &inputs,
+            )
+        }
+
+        fn prove_length_leaf(
+            &self,
+            node: Vec<u8>,
+            _length_slot: usize,
+            _variable_slot: usize,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                "length_leaf",
+                &[&node],
+            )
+        }
+
+        fn prove_length_branch(
+            &self,
+            node: Vec<u8>,
+            child_proof: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                "length_branch",
+                &[
+                    &node,
+                    &child_proof,
+                ],
+            )
+        }
+
+        fn prove_contract_leaf(
+            &self,
+            node: Vec<u8>,
+            _storage_root: Vec<u8>,
+            _contract_address: Address,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                "contract_leaf",
+                &[&node],
+            )
+        }
+
+        fn prove_contract_branch(
+            &self,
+            node: Vec<u8>,
+            child_proof: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                "contract_branch",
+                &[
+                    &node,
+                    &child_proof,
+                ],
+            )
+        }
+
+        fn prove_block(
+            &self,
+            _rlp_header: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                "block",
+                &[],
+            )
+        }
+
+        fn prove_final_extraction_simple(
+            &self,
+            _block_proof: Vec<u8>,
+            _contract_proof: Vec<u8>,
+            _value_proof: Vec<u8>,
+            _dimension: TableDimension,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            unreachable!()
+        }
+
+        fn prove_final_extraction_lengthed(
+            &self,
+            block_proof: Vec<u8>,
+            contract_proof: Vec<u8>,
+            value_proof: Vec<u8>,
+            length_proof: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                "lengthed",
+                &[
+                    &block_proof,
+                    &contract_proof,
+                    &value_proof,
+                    &length_proof,
+                ],
+            )
+        }
+
+        fn prove_final_extraction_merge(
+            &self,
+            _block_proof: Vec<u8>,
+            _contract_proof: Vec<u8>,
+            _simple_table_proof: Vec<u8>,
+            _mapping_table_proof: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            unreachable!()
+        }
+    }
+
+    impl StorageDatabaseProver for Recorder
+    {
+        fn prove_cell_leaf(
+            &self,
+            identifier: u64,
+            _value: U256,
+            _is_multiplier: bool,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                &format!("cell_leaf{identifier}"),
+                &[],
+            )
+        }
+
+        fn prove_cell_partial(
+            &self,
+            identifier: u64,
+            _value: U256,
+            _is_multiplier: bool,
+            child_proof: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                &format!("cell_partial{identifier}"),
+                &[&child_proof],
+            )
+        }
+
+        fn prove_cell_full(
+            &self,
+            identifier: u64,
+            _value: U256,
+            _is_multiplier: bool,
+            child_proofs: Vec<Vec<u8>>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                &format!("cell_full{identifier}"),
+                &[
+                    &child_proofs[0],
+                    &child_proofs[1],
+                ],
+            )
+        }
+
+        fn prove_row_leaf(
+            &self,
+            _identifier: u64,
+            _value: U256,
+            _is_multiplier: bool,
+            cells_proof: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                "row_leaf",
+                &[&cells_proof],
+            )
+        }
+
+        fn prove_row_partial(
+            &self,
+            _identifier: u64,
+            _value: U256,
+            _is_multiplier: bool,
+            _is_child_left: bool,
+            child_proof: Vec<u8>,
+            cells_proof: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            proof(
+                "row_partial",
+                &[
+                    &child_proof,
+                    &cells_proof,
+                ],
+            )
+        }
+
+        fn prove_row_full(
+            &self,
+            _identifier: u64,
+            _value: U256,
+            _is_multiplier: bool,
+            _child_proofs: Vec<Vec<u8>>,
+            _cells_proof: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            unreachable!()
+        }
+
+        fn prove_block_leaf(
+            &self,
+            _block_id: u64,
+            _extraction_proof: Vec<u8>,
+            _rows_tree_proof: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            unreachable!()
+        }
+
+        fn prove_block_parent(
+            &self,
+            _block_id: u64,
+            _old_block_number: U256,
+            _old_min: U256,
+            _old_max: U256,
+            _old_left_child: Option<HashOutput>,
+            _old_right_child: Option<HashOutput>,
+            _old_rows_tree_hash: HashOutput,
+            _extraction_proof: Vec<u8>,
+            _rows_tree_proof: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            unreachable!()
+        }
+
+        fn prove_membership(
+            &self,
+            _block_id: u64,
+            _index_value: U256,
+            _old_min: U256,
+            _old_max: U256,
+            _left_child: HashOutput,
+            _rows_tree_hash: HashOutput,
+            _right_child_proof: Vec<u8>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            unreachable!()
+        }
+
+        fn prove_ivc(
+            &self,
+            _index_proof: Vec<u8>,
+            _previous_proof: Option<Vec<u8>>,
+        ) -> anyhow::Result<Vec<u8>>
+        {
+            unreachable!()
+        }
+    }
+
+    fn cell(
+        identifier: u64,
+        children: Vec<usize>,
+    ) -> CellNode
+    {
+        CellNode {
+            identifier,
+            value: U256::from(identifier),
+            is_multiplier: false,
+            children,
+        }
+    }
+
+    fn row_update(cells: Vec<CellNode>) -> RowUpdateInput
+    {
+        RowUpdateInput {
+            table_id: 1,
+            row_id: "row".to_string(),
+            identifier: 7,
+            value: U256::from(7),
+            is_multiplier: false,
+            cells,
+            is_child_left: true,
+            child_proofs_locations: vec![],
+            child_proofs: vec![b"child".to_vec()],
+        }
+    }
+
+    #[test]
+    fn proves_the_cells_tree_then_the_row()
+    {
+        let preprocessing = Preprocessing::new(Recorder);
+        let proof = preprocessing
+            .prove_row_update(
+                row_update(
+                    vec![
+                        cell(
+                            1,
+                            vec![],
+                        ),
+                        cell(
+                            2,
+                            vec![],
+                        ),
+                        cell(
+                            3,
+                            vec![
+                                0,
+                                1,
+                            ],
+                        ),
+                        cell(
+                            4,
+                            vec![2],
+                        ),
+                    ],
+                ),
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(proof).unwrap(),
+            "row_partial(child,cell_partial4(cell_full3(cell_leaf1(),cell_leaf2())))"
+        );
+
+        // A row without secondary cells leaves the empty cells tree proof to the prover.
+        let proof = preprocessing
+            .prove_row_update(row_update(vec![]))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(proof).unwrap(),
+            "row_partial(child,)"
+        );
+
+        for (cells, error) in [
+            (
+                vec![
+                    cell(
+                        1,
+                        vec![1],
+                    ),
+                    cell(
+                        2,
+                        vec![],
+                    ),
+                ],
+                "does not precede it",
+            ),
+            (
+                vec![
+                    cell(
+                        1,
+                        vec![],
+                    ),
+                    cell(
+                        2,
+                        vec![],
+                    ),
+                ],
+                "do not form a single tree",
+            ),
+        ]
+        {
+            let err = preprocessing
+                .prove_row_update(row_update(cells))
+                .unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains(error),
+                "{err}"
+            );
+        }
+    }
+
+    /// A header of block `number`, of the pre-London fields only.
+    fn rlp_header(number: u8) -> Vec<u8>
+    {
+        let mut fields = vec![];
+        for field in 0..15
+        {
+            match field
+            {
+                // The parent hash and the state root.
+                0 | 3 =>
+                {
+                    fields.push(0xA0);
+                    fields.extend([0; 32]);
+                },
+                8 => fields.push(number),
+                _ => fields.push(0x80),
+            }
+        }
+        let mut header = vec![
+            0xF8,
+            fields.len() as u8,
+        ];
+        header.extend(fields);
+        header
+    }
+
+    fn table_extraction(block_nr: u64) -> TableExtractionInput
+    {
+        let leaf = |key: &[u8]| {
+            ValueNode::Leaf(
+                MappingLeafInput {
+                    key: key.to_vec(),
+                    node: vec![],
+                    slot: 0,
+                    key_id: 0,
+                    value_id: 0,
+                },
+            )
+        };
+        TableExtractionInput {
+            table_id: 1,
+            block_nr,
+            contract: Address::ZERO,
+            value_nodes: vec![
+                leaf(b"a"),
+                leaf(b"b"),
+                ValueNode::Branch {
+                    node: b"root".to_vec(),
+                    children: vec![
+                        0,
+                        1,
+                    ],
+                },
+            ],
+            length_slot: 0,
+            variable_slot: 1,
+            length_nodes: vec![
+                b"len".to_vec(),
+                b"len_root".to_vec(),
+            ],
+            storage_root: vec![],
+            contract_nodes: vec![b"account".to_vec()],
+            rlp_header: rlp_header(5),
+        }
+    }
+
+    #[test]
+    fn proves_the_extractions_then_the_final_one()
+    {
+        let preprocessing = Preprocessing::new(Recorder);
+        let proof = preprocessing
+            .prove_table_extraction(table_extraction(5))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(proof).unwrap(),
+            "lengthed(block(),contract_leaf(account),branch(root,leaf(a),leaf(b)),\
+             length_branch(len_root,length_leaf(len)))"
+        );
+
+        let err = preprocessing
+            .prove_table_extraction(table_extraction(6))
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("the task is for block 6"),
+            "{err}"
+        );
+    }
 }
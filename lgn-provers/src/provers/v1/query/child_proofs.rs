@@ -0,0 +1,305 @@
+//! A bounded cache of the child proofs of the queries.
+//!
+//! The aggregation and revelation tasks of a query sent to the same worker reference the same
+//! child proofs again and again. The proofs received inline and the ones proven are kept by proof
+//! key, which embeds the query id, so that a task referencing a proof without its bytes, or with
+//! a dehydrated proof, is served from memory. The least recently used proofs are evicted past the
+//! size limit, and the proofs of a query are dropped once it is revealed.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use lgn_messages::types::v1::query::keys::ProofKey;
+use metrics::counter;
+use metrics::gauge;
+
+pub struct ChildProofCache
+{
+    max_bytes: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State
+{
+    entries: HashMap<ProofKey, Entry>,
+    /// The keys by last use, the least recent first.
+    recency: BTreeMap<u64, ProofKey>,
+    clock: u64,
+    bytes: usize,
+}
+
+struct Entry
+{
+    proof: Arc<Vec<u8>>,
+    last_used: u64,
+}
+
+impl ChildProofCache
+{
+    /// A cache holding up to `max_bytes` of proofs.
+    pub fn new(max_bytes: usize) -> Self
+    {
+        Self {
+            max_bytes,
+            state: Mutex::default(),
+        }
+    }
+
+    /// The proof stored under `key`, counting the hit or miss.
+    pub fn get(
+        &self,
+        key: &ProofKey,
+    ) -> Option<Arc<Vec<u8>>>
+    {
+        let mut state = self.lock();
+        let proof = state.touch(key);
+        let result = if proof.is_some()
+        {
+            "hit"
+        }
+        else
+        {
+            "miss"
+        };
+        counter!("zkmr_worker_child_proof_cache_lookups_total", "result" => result).increment(1);
+        proof
+    }
+
+    /// Stores `proof` under `key`, an inline duplicate of a stored proof only refreshing it.
+    pub fn insert(
+        &self,
+        key: &ProofKey,
+        proof: &[u8],
+    )
+    {
+        // A proof larger than the whole cache would evict everything else.
+        if proof.is_empty() || proof.len() > self.max_bytes
+        {
+            return;
+        }
+        let mut state = self.lock();
+        if let Some(cached) = state.touch(key)
+        {
+            if cached.as_slice() == proof
+            {
+                counter!("zkmr_worker_child_proof_cache_inline_duplicates_total").increment(1);
+                return;
+            }
+            state.remove(key);
+        }
+
+        state.clock += 1;
+        let last_used = state.clock;
+        state
+            .recency
+            .insert(
+                last_used,
+                key.clone(),
+            );
+        state.bytes += proof.len();
+        state
+            .entries
+            .insert(
+                key.clone(),
+                Entry {
+                    proof: Arc::new(proof.to_vec()),
+                    last_used,
+                },
+            );
+        while state.bytes > self.max_bytes
+        {
+            let Some(evicted) = state
+                .recency
+                .first_key_value()
+                .map(|(_, key)| key.clone())
+            else
+            {
+                break;
+            };
+            state.remove(&evicted);
+            counter!("zkmr_worker_child_proof_cache_evictions_total").increment(1);
+        }
+        state.report();
+    }
+
+    /// Drops the proofs of the query of `key`, which no task references anymore.
+    pub fn forget_query(
+        &self,
+        key: &ProofKey,
+    )
+    {
+        let query_id = self::query_id(key);
+        let mut state = self.lock();
+        let keys = state
+            .entries
+            .keys()
+            .filter(|cached| self::query_id(cached) == query_id)
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in keys
+        {
+            state.remove(&key);
+        }
+        state.report();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State>
+    {
+        self.state
+            .lock()
+            .expect("child proof cache lock poisoned")
+    }
+}
+
+impl State
+{
+    /// Marks the proof under `key` as the most recently used.
+    fn touch(
+        &mut self,
+        key: &ProofKey,
+    ) -> Option<Arc<Vec<u8>>>
+    {
+        self.clock += 1;
+        let clock = self.clock;
+        let entry = self
+            .entries
+            .get_mut(key)?;
+        self.recency
+            .remove(&entry.last_used);
+        self.recency
+            .insert(
+                clock,
+                key.clone(),
+            );
+        entry.last_used = clock;
+        Some(
+            entry
+                .proof
+                .clone(),
+        )
+    }
+
+    fn remove(
+        &mut self,
+        key: &ProofKey,
+    )
+    {
+        if let Some(entry) = self
+            .entries
+            .remove(key)
+        {
+            self.recency
+                .remove(&entry.last_used);
+            self.bytes -= entry
+                .proof
+                .len();
+        }
+    }
+
+    fn report(&self)
+    {
+        gauge!("zkmr_worker_child_proof_cache_bytes").set(self.bytes as f64);
+        gauge!("zkmr_worker_child_proof_cache_entries").set(
+            self.entries
+                .len() as f64,
+        );
+    }
+}
+
+fn query_id(key: &ProofKey) -> &str
+{
+    match key
+    {
+        ProofKey::Row(query_id, ..)
+        | ProofKey::Index(query_id, ..)
+        | ProofKey::Revelation(query_id) => query_id,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// The row proof named `<query id>/<row key id>`.
+    fn row(name: &str) -> ProofKey
+    {
+        let (query_id, row) = name
+            .split_once('/')
+            .unwrap();
+        ProofKey::Row(
+            query_id.to_string(),
+            1,
+            row.to_string(),
+        )
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_proofs()
+    {
+        let cache = ChildProofCache::new(8);
+        cache.insert(
+            &row("q/a"),
+            &[1; 4],
+        );
+        cache.insert(
+            &row("q/b"),
+            &[2; 4],
+        );
+        assert!(
+            cache
+                .get(&row("q/a"))
+                .is_some()
+        );
+        cache.insert(
+            &row("q/c"),
+            &[3; 4],
+        );
+
+        assert!(
+            cache
+                .get(&row("q/a"))
+                .is_some()
+        );
+        assert!(
+            cache
+                .get(&row("q/b"))
+                .is_none()
+        );
+        assert!(
+            cache
+                .get(&row("q/c"))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn forgets_the_proofs_of_a_revealed_query()
+    {
+        let cache = ChildProofCache::new(1024);
+        cache.insert(
+            &row("q1/a"),
+            &[1; 4],
+        );
+        cache.insert(
+            &row("q2/a"),
+            &[2; 4],
+        );
+        cache.forget_query(&ProofKey::Revelation("q1".to_string()));
+
+        assert!(
+            cache
+                .get(&row("q1/a"))
+                .is_none()
+        );
+        assert_eq!(
+            cache
+                .get(&row("q2/a"))
+                .as_deref(),
+            Some(&vec![2; 4])
+        );
+    }
+}
@@ -8,13 +8,26 @@ use verifiable_db::query::computational_hash_ids::ColumnIDs;
 use verifiable_db::query::universal_circuit::universal_circuit_inputs::Placeholders;
 use verifiable_db::revelation::api::MatchingRow;
 
-use crate::dummy_utils::dummy_proof;
+use crate::dummy_profile::DummyProfile;
 use crate::provers::v1::query::prover::StorageQueryProver;
 
 const PROOF_SIZE: usize = 120;
 
 /// Prover implementation which performs no proving and returns random data as a proof.
-pub struct DummyProver;
+pub struct DummyProver
+{
+    profile: DummyProfile,
+}
+
+impl DummyProver
+{
+    pub(crate) fn new(profile: DummyProfile) -> Self
+    {
+        Self {
+            profile,
+        }
+    }
+}
 
 impl StorageQueryProver for DummyProver
 {
@@ -24,7 +37,13 @@ impl StorageQueryProver for DummyProver
         _pis: &DynamicCircuitPis,
     ) -> anyhow::Result<Vec<u8>>
     {
-        Ok(dummy_proof(PROOF_SIZE))
+        Ok(
+            self.profile
+                .proof(
+                    "universal_circuit",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_full_node(
@@ -36,7 +55,13 @@ impl StorageQueryProver for DummyProver
         _is_rows_tree_node: bool,
     ) -> anyhow::Result<Vec<u8>>
     {
-        Ok(dummy_proof(PROOF_SIZE))
+        Ok(
+            self.profile
+                .proof(
+                    "full_node",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_partial_node(
@@ -46,7 +71,13 @@ impl StorageQueryProver for DummyProver
         _pis: &DynamicCircuitPis,
     ) -> anyhow::Result<Vec<u8>>
     {
-        Ok(dummy_proof(PROOF_SIZE))
+        Ok(
+            self.profile
+                .proof(
+                    "partial_node",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_single_path_leaf(
@@ -56,7 +87,13 @@ impl StorageQueryProver for DummyProver
         _pis: &DynamicCircuitPis,
     ) -> anyhow::Result<Vec<u8>>
     {
-        Ok(dummy_proof(PROOF_SIZE))
+        Ok(
+            self.profile
+                .proof(
+                    "single_path_leaf",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_single_path_branch(
@@ -66,7 +103,13 @@ impl StorageQueryProver for DummyProver
         _pis: &DynamicCircuitPis,
     ) -> anyhow::Result<Vec<u8>>
     {
-        Ok(dummy_proof(PROOF_SIZE))
+        Ok(
+            self.profile
+                .proof(
+                    "single_path_branch",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_aggregated_revelation(
@@ -77,7 +120,13 @@ impl StorageQueryProver for DummyProver
         _indexing_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        Ok(dummy_proof(PROOF_SIZE))
+        Ok(
+            self.profile
+                .proof(
+                    "aggregated_revelation",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_tabular_revelation(
@@ -91,7 +140,13 @@ impl StorageQueryProver for DummyProver
         _offset: u32,
     ) -> anyhow::Result<Vec<u8>>
     {
-        Ok(dummy_proof(PROOF_SIZE))
+        Ok(
+            self.profile
+                .proof(
+                    "tabular_revelation",
+                    PROOF_SIZE,
+                ),
+        )
     }
 
     fn prove_non_existence(
@@ -100,6 +155,12 @@ impl StorageQueryProver for DummyProver
         _pis: &DynamicCircuitPis,
     ) -> anyhow::Result<Vec<u8>>
     {
-        Ok(dummy_proof(PROOF_SIZE))
+        Ok(
+            self.profile
+                .proof(
+                    "non_existence",
+                    PROOF_SIZE,
+                ),
+        )
     }
 }
@@ -20,6 +20,7 @@ use verifiable_db::revelation::{
     self,
 };
 
+use super::circuit_shape;
 use super::prover::StorageQueryProver;
 use super::INDEX_TREE_MAX_DEPTH;
 use super::MAX_NUM_COLUMNS;
@@ -30,6 +31,7 @@ use super::MAX_NUM_PREDICATE_OPS;
 use super::MAX_NUM_RESULT_OPS;
 use super::ROW_TREE_MAX_DEPTH;
 use crate::params::ParamsLoader;
+use crate::sampled_debug;
 
 pub(crate) struct EuclidQueryProver
 {
@@ -76,6 +78,11 @@ impl EuclidQueryProver
     ) -> anyhow::Result<Self>
     {
         debug!("Creating preprocessing prover");
+        ParamsLoader::check_shape(
+            url,
+            file,
+            &circuit_shape(),
+        )?;
         let params = ParamsLoader::prepare_bincode(
             url,
             dir,
@@ -102,7 +109,10 @@ impl StorageQueryProver for EuclidQueryProver
         pis: &DynamicCircuitPis,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving universal circuit");
+        sampled_debug!(
+            "universal circuit",
+            "Proving universal circuit"
+        );
 
         let now = std::time::Instant::now();
 
@@ -136,7 +146,8 @@ impl StorageQueryProver for EuclidQueryProver
         );
         histogram!("zkmr_worker_proving_latency", "proof_type" => proof_type).record(time);
 
-        debug!(
+        sampled_debug!(
+            "universal circuit",
             "universal circuit size in kB: {}",
             proof.len() / 1024
         );
@@ -153,7 +164,10 @@ impl StorageQueryProver for EuclidQueryProver
         is_rows_tree_node: bool,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving full node");
+        sampled_debug!(
+            "full node",
+            "Proving full node"
+        );
 
         let now = std::time::Instant::now();
 
@@ -183,7 +197,8 @@ impl StorageQueryProver for EuclidQueryProver
         );
         histogram!("zkmr_worker_proving_latency", "proof_type" => proof_type).record(time);
 
-        debug!(
+        sampled_debug!(
+            "full node",
             "full node size in kB: {}",
             proof.len() / 1024
         );
@@ -198,7 +213,10 @@ impl StorageQueryProver for EuclidQueryProver
         pis: &DynamicCircuitPis,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving partial node");
+        sampled_debug!(
+            "partial node",
+            "Proving partial node"
+        );
 
         let now = std::time::Instant::now();
 
@@ -230,7 +248,8 @@ impl StorageQueryProver for EuclidQueryProver
         );
         histogram!("zkmr_worker_proving_latency", "proof_type" => proof_type).record(time);
 
-        debug!(
+        sampled_debug!(
+            "partial node",
             "partial node size in kB: {}",
             proof.len() / 1024
         );
@@ -245,7 +264,10 @@ impl StorageQueryProver for EuclidQueryProver
         pis: &DynamicCircuitPis,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving single path leaf");
+        sampled_debug!(
+            "single path leaf",
+            "Proving single path leaf"
+        );
 
         let now = std::time::Instant::now();
 
@@ -278,7 +300,8 @@ impl StorageQueryProver for EuclidQueryProver
         );
         histogram!("zkmr_worker_proving_latency", "proof_type" => proof_type).record(time);
 
-        debug!(
+        sampled_debug!(
+            "single path leaf",
             "single path leaf size in kB: {}",
             proof.len() / 1024
         );
@@ -293,7 +316,10 @@ impl StorageQueryProver for EuclidQueryProver
         pis: &DynamicCircuitPis,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving single path branch");
+        sampled_debug!(
+            "single path branch",
+            "Proving single path branch"
+        );
 
         let now = std::time::Instant::now();
 
@@ -328,7 +354,8 @@ impl StorageQueryProver for EuclidQueryProver
         );
         histogram!("zkmr_worker_proving_latency", "proof_type" => proof_type).record(time);
 
-        debug!(
+        sampled_debug!(
+            "single path branch",
             "single path branch size in kB: {}",
             proof.len() / 1024
         );
@@ -344,7 +371,10 @@ impl StorageQueryProver for EuclidQueryProver
         indexing_proof: Vec<u8>,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("proving aggregated revelation");
+        sampled_debug!(
+            "revelation",
+            "proving aggregated revelation"
+        );
         let now = std::time::Instant::now();
 
         let circuit_input = revelation::api::CircuitInput::new_revelation_aggregated(
@@ -376,7 +406,8 @@ impl StorageQueryProver for EuclidQueryProver
         );
         histogram!("zkmr_worker_proving_latency", "proof_type" => proof_type).record(time);
 
-        debug!(
+        sampled_debug!(
+            "revelation",
             "revelation size in kB: {}",
             proof.len() / 1024
         );
@@ -395,7 +426,10 @@ impl StorageQueryProver for EuclidQueryProver
         offset: u32,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("proving tabular revelation");
+        sampled_debug!(
+            "revelation",
+            "proving tabular revelation"
+        );
         let now = std::time::Instant::now();
 
         let circuit_input = revelation::api::CircuitInput::new_revelation_tabular(
@@ -430,7 +464,8 @@ impl StorageQueryProver for EuclidQueryProver
         );
         histogram!("zkmr_worker_proving_latency", "proof_type" => proof_type).record(time);
 
-        debug!(
+        sampled_debug!(
+            "revelation",
             "revelation size in kB: {}",
             proof.len() / 1024
         );
@@ -444,7 +479,10 @@ impl StorageQueryProver for EuclidQueryProver
         pis: &DynamicCircuitPis,
     ) -> anyhow::Result<Vec<u8>>
     {
-        debug!("Proving non-existence");
+        sampled_debug!(
+            "non-existence",
+            "Proving non-existence"
+        );
 
         let now = std::time::Instant::now();
 
@@ -510,7 +548,8 @@ impl StorageQueryProver for EuclidQueryProver
         );
         histogram!("zkmr_worker_proving_latency", "proof_type" => proof_type).record(time);
 
-        debug!(
+        sampled_debug!(
+            "non-existence",
             "non-existence size in kB: {}",
             proof.len() / 1024
         );
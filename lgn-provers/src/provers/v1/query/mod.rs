@@ -1,9 +1,13 @@
 use tracing::info;
 
+use crate::dummy_profile::DummyProfile;
+use crate::params::CircuitShape;
 use crate::provers::v1::query::prover::StorageQueryProver;
 use crate::provers::v1::query::task::Querying;
 
+pub mod child_proofs;
 pub(crate) mod prover;
+pub mod pruned;
 pub mod task;
 
 #[cfg(feature = "dummy-prover")]
@@ -21,6 +25,48 @@ pub const MAX_NUM_ITEMS_PER_OUTPUT: usize = 5;
 pub const MAX_NUM_PLACEHOLDERS: usize = 10;
 pub const MAX_NUM_COLUMNS: usize = 20;
 pub const MAX_NUM_PREDICATE_OPS: usize = 20;
+
+/// The constants the query params must have been built with.
+pub fn circuit_shape() -> CircuitShape
+{
+    CircuitShape::new(
+        &[
+            (
+                "ROW_TREE_MAX_DEPTH",
+                ROW_TREE_MAX_DEPTH,
+            ),
+            (
+                "INDEX_TREE_MAX_DEPTH",
+                INDEX_TREE_MAX_DEPTH,
+            ),
+            (
+                "MAX_NUM_COLUMNS",
+                MAX_NUM_COLUMNS,
+            ),
+            (
+                "MAX_NUM_PREDICATE_OPS",
+                MAX_NUM_PREDICATE_OPS,
+            ),
+            (
+                "MAX_NUM_RESULT_OPS",
+                MAX_NUM_RESULT_OPS,
+            ),
+            (
+                "MAX_NUM_OUTPUTS",
+                MAX_NUM_OUTPUTS,
+            ),
+            (
+                "MAX_NUM_ITEMS_PER_OUTPUT",
+                MAX_NUM_ITEMS_PER_OUTPUT,
+            ),
+            (
+                "MAX_NUM_PLACEHOLDERS",
+                MAX_NUM_PLACEHOLDERS,
+            ),
+        ],
+    )
+}
+
 #[allow(unused_variables)]
 pub fn create_prover(
     url: &str,
@@ -29,6 +75,7 @@ pub fn create_prover(
     checksum_expected_local_path: &str,
     skip_checksum: bool,
     skip_store: bool,
+    dummy_profile: DummyProfile,
 ) -> anyhow::Result<Querying<impl StorageQueryProver>>
 {
     let prover = {
@@ -36,7 +83,7 @@ pub fn create_prover(
         {
             use dummy_prover::DummyProver;
             info!("Creating dummy storage prover");
-            DummyProver
+            DummyProver::new(dummy_profile)
         }
 
         #[cfg(not(feature = "dummy-prover"))]
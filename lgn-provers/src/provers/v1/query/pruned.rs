@@ -0,0 +1,302 @@
+//! Query params pruned for the tables of a dedicated deployment.
+//!
+//! The query params hold the circuits of the largest tables and queries the worker is compiled
+//! for, most of which a deployment serving a few known tables never uses. A pruned params file is
+//! built offline for the tables of such a deployment, and published with the tables and the
+//! lowered limits it was built for in `<file>.pruned.json`:
+//!
+//! ```json
+//! {
+//!     "tables": [1, 7],
+//!     "limits": { "MAX_NUM_COLUMNS": 6, "MAX_NUM_PREDICATE_OPS": 4, "MAX_NUM_PLACEHOLDERS": 2 }
+//! }
+//! ```
+//!
+//! The limits are named after the compiled constants, the ones absent keeping their compiled
+//! value. The tasks are checked against them before being proven, the ones which do not fit being
+//! refused rather than failing deep in a circuit.
+
+use anyhow::ensure;
+use anyhow::Context;
+use lgn_messages::types::v1::query::tasks::EmbeddedProofInputType;
+use lgn_messages::types::v1::query::tasks::ProofInputKind;
+use lgn_messages::types::v1::query::tasks::QueryStep;
+use lgn_messages::types::v1::query::tasks::RevelationInput;
+use lgn_messages::types::v1::query::PlaceHolderLgn;
+use lgn_messages::types::v1::query::WorkerTask;
+use lgn_messages::types::v1::query::WorkerTaskType;
+use lgn_messages::TableId;
+use parsil::assembler::DynamicCircuitPis;
+use serde::Deserialize;
+use serde::Serialize;
+use tracing::info;
+
+use super::circuit_shape;
+use crate::params::CircuitShape;
+use crate::params::ParamsLoader;
+
+/// The tables and the limits a pruned params file was built for.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PrunedShape
+{
+    pub tables: Vec<TableId>,
+    pub limits: CircuitShape,
+}
+
+/// The error of a task which the pruned params can not prove.
+#[derive(thiserror::Error, Debug)]
+#[error("the task does not fit the pruned query params of the worker: {reason}")]
+pub struct OutsidePrunedParams
+{
+    pub reason: String,
+}
+
+impl PrunedShape
+{
+    /// The name of the file describing the pruned params file `file_name`.
+    pub fn file_name(file_name: &str) -> String
+    {
+        format!("{file_name}.pruned.json")
+    }
+
+    /// Fetches the shape of the pruned params file `file_name` from `base_url`.
+    ///
+    /// Unlike the circuit shape, it is required: the tasks could not be checked without it.
+    pub fn fetch(
+        base_url: &str,
+        file_name: &str,
+    ) -> anyhow::Result<Self>
+    {
+        let shape_file = Self::file_name(file_name);
+        let content = ParamsLoader::download_from(
+            base_url,
+            &shape_file,
+        )
+        .with_context(
+            || format!("the pruned params `{file_name}` are published without `{shape_file}`"),
+        )?;
+        let shape = serde_json::from_slice::<Self>(&content)
+            .with_context(|| format!("`{shape_file}` is not a pruned params shape"))?;
+
+        ensure!(
+            !shape
+                .tables
+                .is_empty(),
+            "`{shape_file}` lists no table"
+        );
+        let exceeding = shape
+            .limits
+            .exceeding(&circuit_shape());
+        ensure!(
+            exceeding.is_empty(),
+            "`{shape_file}` raises {} above the compiled circuits",
+            exceeding.join(", ")
+        );
+        info!(
+            "`{file_name}` is pruned for the tables {:?}, limits: {}",
+            shape.tables, shape.limits
+        );
+        Ok(shape)
+    }
+
+    /// Checks that the pruned params can prove `task`, of public inputs `pis`.
+    pub fn check(
+        &self,
+        task: &WorkerTask,
+        pis: &DynamicCircuitPis,
+    ) -> Result<(), OutsidePrunedParams>
+    {
+        match task.table_id
+        {
+            Some(table_id)
+                if self
+                    .tables
+                    .contains(&table_id) =>
+            {},
+            Some(table_id) =>
+            {
+                return Err(
+                    OutsidePrunedParams {
+                        reason: format!(
+                            "table {table_id} is not one of the tables {:?} they were pruned for",
+                            self.tables
+                        ),
+                    },
+                );
+            },
+            None =>
+            {
+                return Err(
+                    OutsidePrunedParams {
+                        reason: "the task does not tell its table".to_string(),
+                    },
+                );
+            },
+        }
+        self.within(
+            "MAX_NUM_PREDICATE_OPS",
+            pis.predication_operations
+                .len(),
+            "predicate operations",
+        )?;
+
+        let WorkerTaskType::Query(input) = &task.task_type;
+        match &input.query_step
+        {
+            QueryStep::Prepare(parts) =>
+            {
+                for part in parts
+                {
+                    if let Some(EmbeddedProofInputType::RowsTree(embedded)) =
+                        &part.embedded_proof_input
+                    {
+                        self.within_placeholders(&embedded.placeholders)?;
+                    }
+                    if let Some(ProofInputKind::NonExistence(non_existence)) =
+                        &part.aggregation_input_kind
+                    {
+                        self.within_placeholders(&non_existence.placeholders)?;
+                        self.within(
+                            "MAX_NUM_COLUMNS",
+                            non_existence
+                                .column_ids
+                                .len(),
+                            "columns",
+                        )?;
+                    }
+                }
+            },
+            QueryStep::Revelation(RevelationInput::Aggregated {
+                placeholders,
+                ..
+            }) => self.within_placeholders(placeholders)?,
+            QueryStep::Revelation(RevelationInput::Tabular {
+                placeholders,
+                matching_rows,
+                ..
+            }) =>
+            {
+                self.within_placeholders(placeholders)?;
+                for row in matching_rows
+                {
+                    self.within(
+                        "MAX_NUM_ITEMS_PER_OUTPUT",
+                        row.result
+                            .len(),
+                        "result items",
+                    )?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Checks the generic placeholders, the query bounds `0` and `1` aside.
+    fn within_placeholders(
+        &self,
+        placeholders: &PlaceHolderLgn,
+    ) -> Result<(), OutsidePrunedParams>
+    {
+        self.within(
+            "MAX_NUM_PLACEHOLDERS",
+            placeholders
+                .identifiers()
+                .filter(|identifier| *identifier != "0" && *identifier != "1")
+                .count(),
+            "placeholders",
+        )
+    }
+
+    fn within(
+        &self,
+        limit: &str,
+        count: usize,
+        what: &str,
+    ) -> Result<(), OutsidePrunedParams>
+    {
+        match self
+            .limits
+            .get(limit)
+        {
+            Some(max) if count > max =>
+            {
+                Err(
+                    OutsidePrunedParams {
+                        reason: format!("the task has {count} {what}, they allow at most {max}"),
+                    },
+                )
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn limits_are_checked_against_the_compiled_circuits()
+    {
+        let shape: PrunedShape = serde_json::from_str(
+            r#"{"tables": [1], "limits": {"MAX_NUM_COLUMNS": 6, "MAX_NUM_PLACEHOLDERS": 2}}"#,
+        )
+        .unwrap();
+        assert!(
+            shape
+                .limits
+                .exceeding(&circuit_shape())
+                .is_empty()
+        );
+        assert!(
+            shape
+                .within(
+                    "MAX_NUM_COLUMNS",
+                    6,
+                    "columns"
+                )
+                .is_ok()
+        );
+        assert!(
+            shape
+                .within(
+                    "MAX_NUM_COLUMNS",
+                    7,
+                    "columns"
+                )
+                .is_err()
+        );
+        // A limit the pruned params do not lower keeps its compiled value, checked by the circuits.
+        assert!(
+            shape
+                .within(
+                    "MAX_NUM_PREDICATE_OPS",
+                    50,
+                    "predicate operations"
+                )
+                .is_ok()
+        );
+
+        let raised = CircuitShape::new(
+            &[
+                (
+                    "MAX_NUM_COLUMNS",
+                    64,
+                ),
+                (
+                    "MAX_TABLES",
+                    1,
+                ),
+            ],
+        );
+        assert_eq!(
+            raised.exceeding(&circuit_shape()),
+            vec![
+                "MAX_NUM_COLUMNS",
+                "MAX_TABLES"
+            ]
+        );
+    }
+}
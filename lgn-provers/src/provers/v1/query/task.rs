@@ -1,8 +1,10 @@
 use std::collections::HashMap;
 
 use anyhow::bail;
+use anyhow::Context;
 use lgn_messages::types::v1::query::keys::ProofKey;
 use lgn_messages::types::v1::query::tasks::EmbeddedProofInputType;
+use lgn_messages::types::v1::query::tasks::Hydratable;
 use lgn_messages::types::v1::query::tasks::HydratableMatchingRow;
 use lgn_messages::types::v1::query::tasks::ProofInputKind;
 use lgn_messages::types::v1::query::tasks::QueryStep;
@@ -17,12 +19,17 @@ use lgn_messages::types::TaskType;
 use lgn_messages::types::WorkerReply;
 use parsil::assembler::DynamicCircuitPis;
 
+use crate::provers::v1::query::child_proofs::ChildProofCache;
 use crate::provers::v1::query::prover::StorageQueryProver;
+use crate::provers::v1::query::pruned::PrunedShape;
 use crate::provers::LgnProver;
+use crate::provers::YieldPoint;
 
 pub struct Querying<P>
 {
     prover: P,
+    child_proofs: Option<ChildProofCache>,
+    pruned: Option<PrunedShape>,
 }
 
 impl<P: StorageQueryProver> LgnProver<TaskType, ReplyType> for Querying<P>
@@ -31,6 +38,18 @@ impl<P: StorageQueryProver> LgnProver<TaskType, ReplyType> for Querying<P>
         &self,
         envelope: &MessageEnvelope<TaskType>,
     ) -> anyhow::Result<MessageReplyEnvelope<ReplyType>>
+    {
+        self.run_cooperatively(
+            envelope,
+            &(),
+        )
+    }
+
+    fn run_cooperatively(
+        &self,
+        envelope: &MessageEnvelope<TaskType>,
+        yield_point: &dyn YieldPoint,
+    ) -> anyhow::Result<MessageReplyEnvelope<ReplyType>>
     {
         let query_id = envelope
             .query_id
@@ -47,7 +66,10 @@ impl<P: StorageQueryProver> LgnProver<TaskType, ReplyType> for Querying<P>
         ) = envelope.inner
         {
             let key: ProofKey = task.into();
-            let result = self.run_inner(task)?;
+            let result = self.run_inner(
+                task,
+                yield_point,
+            )?;
             let reply_type = ReplyType::V1Query(
                 WorkerReply::new(
                     chain_id,
@@ -84,12 +106,91 @@ impl<P: StorageQueryProver> Querying<P>
     {
         Self {
             prover,
+            child_proofs: None,
+            pruned: None,
+        }
+    }
+
+    /// Refuses the tasks which the pruned params of `pruned` can not prove.
+    pub fn with_pruned_shape(
+        mut self,
+        pruned: PrunedShape,
+    ) -> Self
+    {
+        self.pruned = Some(pruned);
+        self
+    }
+
+    /// Keeps up to `max_bytes` of the child proofs of the queries, for the following tasks of
+    /// the same query.
+    pub fn with_child_proof_cache(
+        mut self,
+        max_bytes: usize,
+    ) -> Self
+    {
+        self.child_proofs = Some(ChildProofCache::new(max_bytes));
+        self
+    }
+
+    /// The child proof at `location`: the one `proven` in this task, else the `inline` one, else
+    /// the cached one if it was sent without its bytes.
+    fn child_proof(
+        &self,
+        proven: Option<Vec<u8>>,
+        location: &ProofKey,
+        inline: Vec<u8>,
+    ) -> Vec<u8>
+    {
+        if let Some(proof) = proven
+        {
+            return proof;
+        }
+        let Some(cache) = &self.child_proofs
+        else
+        {
+            return inline;
+        };
+        if inline.is_empty()
+        {
+            cache
+                .get(location)
+                .map(|proof| proof.to_vec())
+                .unwrap_or(inline)
+        }
+        else
+        {
+            cache.insert(
+                location,
+                &inline,
+            );
+            inline
+        }
+    }
+
+    /// The query proof of a revelation, hydrated from the cache if need be.
+    fn query_proof(
+        &self,
+        query_proof: &Hydratable<ProofKey>,
+    ) -> anyhow::Result<Vec<u8>>
+    {
+        match query_proof
+        {
+            Hydratable::Hydrated(proof) => Ok(proof.to_vec()),
+            Hydratable::Dehydrated(key) =>
+            {
+                self.child_proofs
+                    .as_ref()
+                    .and_then(|cache| cache.get(key))
+                    .map(|proof| proof.to_vec())
+                    .with_context(|| format!("the query proof `{key}` is neither sent nor cached"))
+            },
         }
     }
 
     pub fn run_inner(
         &self,
         task: &WorkerTask,
+        yield_point: &dyn YieldPoint,
     ) -> anyhow::Result<Vec<u8>>
     {
         #[allow(irrefutable_let_patterns)]
@@ -103,6 +204,13 @@ impl<P: StorageQueryProver> Querying<P>
         };
 
         let pis: DynamicCircuitPis = serde_json::from_slice(&input.pis)?;
+        if let Some(pruned) = &self.pruned
+        {
+            pruned.check(
+                task,
+                &pis,
+            )?;
+        }
 
         let mut proofs = HashMap::new();
 
@@ -110,7 +218,9 @@ impl<P: StorageQueryProver> Querying<P>
         {
             QueryStep::Prepare(ref parts) =>
             {
-                for part in parts
+                for (i, part) in parts
+                    .iter()
+                    .enumerate()
                 {
                     match (
                         &part.embedded_proof_input,
@@ -148,12 +258,12 @@ impl<P: StorageQueryProver> Querying<P>
                             {
                                 ProofInputKind::SinglePathBranch(sb) =>
                                 {
-                                    let child_proof = proofs
-                                        .remove(&sb.proven_child_location)
-                                        .unwrap_or(
-                                            sb.proven_child_proof
-                                                .to_owned(),
-                                        );
+                                    let child_proof = self.child_proof(
+                                        proofs.remove(&sb.proven_child_location),
+                                        &sb.proven_child_location,
+                                        sb.proven_child_proof
+                                            .to_owned(),
+                                    );
 
                                     let proof = self
                                         .prover
@@ -226,17 +336,16 @@ impl<P: StorageQueryProver> Querying<P>
                                 ProofInputKind::PartialNode(sp) =>
                                 {
                                     let mut sp = sp.clone();
-                                    if sp
+                                    let proven = sp
                                         .proven_child_proof
                                         .is_empty()
-                                    {
-                                        sp.proven_child_proof = proofs
-                                            .remove(&sp.proven_child_proof_location)
-                                            .unwrap_or(
-                                                sp.proven_child_proof
-                                                    .to_owned(),
-                                            );
-                                    }
+                                        .then(|| proofs.remove(&sp.proven_child_proof_location))
+                                        .flatten();
+                                    sp.proven_child_proof = self.child_proof(
+                                        proven,
+                                        &sp.proven_child_proof_location,
+                                        std::mem::take(&mut sp.proven_child_proof),
+                                    );
                                     let proof = self
                                         .prover
                                         .prove_partial_node(
@@ -253,21 +362,27 @@ impl<P: StorageQueryProver> Querying<P>
                                 ProofInputKind::FullNode(f) =>
                                 {
                                     let mut f = f.clone();
-                                    if f.left_child_proof
+                                    let proven = f
+                                        .left_child_proof
                                         .is_empty()
-                                    {
-                                        f.left_child_proof = proofs
-                                            .remove(&f.left_child_proof_location)
-                                            .unwrap_or(f.left_child_proof);
-                                    }
+                                        .then(|| proofs.remove(&f.left_child_proof_location))
+                                        .flatten();
+                                    f.left_child_proof = self.child_proof(
+                                        proven,
+                                        &f.left_child_proof_location,
+                                        std::mem::take(&mut f.left_child_proof),
+                                    );
 
-                                    if f.right_child_proof
+                                    let proven = f
+                                        .right_child_proof
                                         .is_empty()
-                                    {
-                                        f.right_child_proof = proofs
-                                            .remove(&f.right_child_proof_location)
-                                            .unwrap_or(f.right_child_proof);
-                                    }
+                                        .then(|| proofs.remove(&f.right_child_proof_location))
+                                        .flatten();
+                                    f.right_child_proof = self.child_proof(
+                                        proven,
+                                        &f.right_child_proof_location,
+                                        std::mem::take(&mut f.right_child_proof),
+                                    );
 
                                     let proof = self
                                         .prover
@@ -295,11 +410,16 @@ impl<P: StorageQueryProver> Querying<P>
                             bail!("Invalid inputs")
                         },
                     }
+                    yield_point.reached(
+                        "query_parts",
+                        i + 1,
+                        parts.len(),
+                    )?;
                 }
             },
             QueryStep::Revelation(rev) =>
             {
-                match rev
+                let proof = match rev
                 {
                     RevelationInput::Aggregated {
                         placeholders,
@@ -308,16 +428,15 @@ impl<P: StorageQueryProver> Querying<P>
                         ..
                     } =>
                     {
-                        return self
-                            .prover
+                        self.prover
                             .prove_aggregated_revelation(
                                 &pis,
                                 placeholders
                                     .clone()
                                     .into(),
-                                query_proof.clone_proof(),
+                                self.query_proof(query_proof)?,
                                 indexing_proof.clone_proof(),
-                            );
+                            )?
                     },
                     RevelationInput::Tabular {
                         placeholders,
@@ -329,8 +448,7 @@ impl<P: StorageQueryProver> Querying<P>
                         ..
                     } =>
                     {
-                        return self
-                            .prover
+                        self.prover
                             .prove_tabular_revelation(
                                 &pis,
                                 placeholders
@@ -345,9 +463,15 @@ impl<P: StorageQueryProver> Querying<P>
                                 column_ids,
                                 *limit,
                                 *offset,
-                            )
+                            )?
                     },
+                };
+                // A revealed query is done with, its proofs are not referenced anymore.
+                if let Some(cache) = &self.child_proofs
+                {
+                    cache.forget_query(&input.proof_key);
                 }
+                return Ok(proof);
             },
         }
 
@@ -365,6 +489,14 @@ impl<P: StorageQueryProver> Querying<P>
             .next()
             .unwrap()
             .clone();
+        // The aggregation tasks of the query reference it as their child.
+        if let Some(cache) = &self.child_proofs
+        {
+            cache.insert(
+                &input.proof_key,
+                &final_proof,
+            );
+        }
         Ok(final_proof)
     }
 }
@@ -0,0 +1,429 @@
+//! The admin endpoints of the worker, for its operator, on the port of `[admin]`.
+//!
+//! - `GET /tables`: the highest block proven per table and task family, see [`tables`].
+//! - `GET /startup`: `200` once every prover loaded its params, `503` until then, with the load
+//!   state of each prover, see [`startup`]. Meant for the startup probes.
+//! - `GET /readiness`: as `/startup`, but `503` again while the worker drains, e.g. after finding
+//!   its params corrupted or during a maintenance window.
+//! - `GET /info`: the version of the worker and its maintenance state, see [`maintenance`].
+//! - `GET /log-sampling`: the debug logs of the proving hot paths kept per minute, see
+//!   [`lgn_provers::log_sampling`]; `PUT /log-sampling?max_per_minute=N` changes it until the
+//!   worker restarts, `0` keeping all of them.
+//!
+//! Only `/log-sampling` changes the state of the worker, and only its logs. The requests are
+//! answered one per connection, their headers ignored.
+//!
+//! The endpoints are served on the loopback address unless `bind` is set. The connections from
+//! outside of `allowlist`, if set, are closed unanswered, and with `[admin.tls]` set the requests
+//! are served over TLS to the clients presenting a certificate of `client_ca` only.
+
+use std::fs::File;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use ipnet::IpNet;
+use metrics::counter;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::RootCertStore;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tracing::debug;
+use tracing::info;
+
+use crate::config::AdminConfig;
+use crate::config::AdminTlsConfig;
+use crate::maintenance;
+use crate::params_audit;
+use crate::startup;
+use crate::tables;
+
+/// A request taking longer than this to be sent is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The bytes of a request line or header read at most.
+const MAX_LINE_BYTES: u64 = 8 * 1024;
+
+/// The request headers read at most.
+const MAX_HEADERS: usize = 64;
+
+/// Serves the admin endpoints until the listener fails.
+pub(crate) async fn serve(config: AdminConfig) -> Result<()>
+{
+    let acceptor = config
+        .tls
+        .as_ref()
+        .map(tls_acceptor)
+        .transpose()?;
+    let listener = TcpListener::bind(
+        (
+            config.bind,
+            config.port,
+        ),
+    )
+    .await
+    .with_context(
+        || {
+            format!(
+                "failed to listen on the admin address {}:{}",
+                config.bind, config.port
+            )
+        },
+    )?;
+    info!(
+        "Serving the admin endpoints on {}:{}{}",
+        config.bind,
+        config.port,
+        if acceptor.is_some()
+        {
+            " over mutual TLS"
+        }
+        else
+        {
+            ""
+        }
+    );
+
+    loop
+    {
+        let (stream, peer) = listener
+            .accept()
+            .await?;
+        if !allowed(
+            &config.allowlist,
+            peer.ip(),
+        )
+        {
+            counter!("zkmr_worker_admin_refused_total").increment(1);
+            debug!("Refused the admin connection of {peer}, not in the allowlist");
+            continue;
+        }
+        let acceptor = acceptor.clone();
+        tokio::spawn(
+            async move {
+                if let Err(err) = tokio::time::timeout(
+                    REQUEST_TIMEOUT,
+                    accept(
+                        stream,
+                        acceptor,
+                    ),
+                )
+                .await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("request timed out")))
+                {
+                    debug!("Failed to answer the admin request of {peer}: {err:?}");
+                }
+            },
+        );
+    }
+}
+
+/// Whether `allowlist` lets `ip` in, any address if it is empty.
+fn allowed(
+    allowlist: &[IpNet],
+    ip: IpAddr,
+) -> bool
+{
+    // The IPv4 clients of a dual-stack listener connect from a mapped IPv6 address.
+    let ip = ip.to_canonical();
+    allowlist.is_empty()
+        || allowlist
+            .iter()
+            .any(|net| net.contains(&ip))
+}
+
+async fn accept(
+    stream: TcpStream,
+    acceptor: Option<TlsAcceptor>,
+) -> Result<()>
+{
+    match acceptor
+    {
+        Some(acceptor) =>
+        {
+            let stream = acceptor
+                .accept(stream)
+                .await
+                .context("TLS handshake failed")?;
+            answer(stream).await
+        },
+        None => answer(stream).await,
+    }
+}
+
+fn tls_acceptor(tls: &AdminTlsConfig) -> Result<TlsAcceptor>
+{
+    let certs = pem_certs(&tls.cert)?;
+    let key = rustls_pemfile::private_key(
+        &mut std::io::BufReader::new(
+            File::open(&tls.key).with_context(
+                || {
+                    format!(
+                        "failed to open {}",
+                        tls.key
+                            .display()
+                    )
+                },
+            )?,
+        ),
+    )
+    .with_context(
+        || {
+            format!(
+                "failed to read {}",
+                tls.key
+                    .display()
+            )
+        },
+    )?
+    .with_context(
+        || {
+            format!(
+                "no private key in {}",
+                tls.key
+                    .display()
+            )
+        },
+    )?;
+
+    let mut client_cas = RootCertStore::empty();
+    for ca in pem_certs(&tls.client_ca)?
+    {
+        client_cas
+            .add(ca)
+            .with_context(
+                || {
+                    format!(
+                        "invalid CA certificate in {}",
+                        tls.client_ca
+                            .display()
+                    )
+                },
+            )?;
+    }
+    let verifier = WebPkiClientVerifier::builder(Arc::new(client_cas))
+        .build()
+        .context("invalid admin client CA")?;
+    let config = ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(
+            certs,
+            key,
+        )
+        .context("invalid admin certificate or key")?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn pem_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>>
+{
+    let certs = rustls_pemfile::certs(
+        &mut std::io::BufReader::new(
+            File::open(path).with_context(
+                || {
+                    format!(
+                        "failed to open {}",
+                        path.display()
+                    )
+                },
+            )?,
+        ),
+    )
+    .collect::<Result<Vec<_>, _>>()
+    .with_context(
+        || {
+            format!(
+                "failed to read {}",
+                path.display()
+            )
+        },
+    )?;
+    if certs.is_empty()
+    {
+        bail!(
+            "no certificate in {}",
+            path.display()
+        );
+    }
+    Ok(certs)
+}
+
+async fn answer<S: AsyncRead + AsyncWrite + Unpin>(stream: S) -> Result<()>
+{
+    let mut stream = BufReader::new(stream);
+    let request_line = read_line(&mut stream).await?;
+    // The headers are read for the client not to see its connection reset.
+    for _ in 0..MAX_HEADERS
+    {
+        if read_line(&mut stream)
+            .await?
+            .trim()
+            .is_empty()
+        {
+            break;
+        }
+    }
+
+    let mut request = request_line.split_whitespace();
+    let (status, body) = match (
+        request.next(),
+        request.next(),
+    )
+    {
+        (Some("GET"), Some("/tables")) =>
+        {
+            (
+                "200 OK",
+                serde_json::to_string(&tables::summary())?,
+            )
+        },
+        (Some("GET"), Some("/startup")) =>
+        {
+            let startup = startup::status();
+            (
+                availability(startup.complete),
+                serde_json::to_string(&startup)?,
+            )
+        },
+        (Some("GET"), Some("/readiness")) =>
+        {
+            let startup = startup::status();
+            let draining = params_audit::is_draining() || maintenance::draining_until().is_some();
+            let ready = startup.complete && !draining;
+            (
+                availability(ready),
+                serde_json::json!(
+                    {
+                        "ready": ready,
+                        "draining": draining,
+                        "provers": startup.provers,
+                    }
+                )
+                .to_string(),
+            )
+        },
+        (Some("GET"), Some("/info")) =>
+        {
+            (
+                "200 OK",
+                serde_json::json!(
+                    {
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "maintenance": maintenance::status(),
+                    }
+                )
+                .to_string(),
+            )
+        },
+        (Some("GET"), Some("/log-sampling")) =>
+        {
+            (
+                "200 OK",
+                log_sampling(),
+            )
+        },
+        (Some("PUT"), Some(target)) if target.starts_with("/log-sampling") =>
+        {
+            match target
+                .split_once('?')
+                .and_then(|(_, query)| query.strip_prefix("max_per_minute="))
+                .and_then(
+                    |max| {
+                        max.parse::<u32>()
+                            .ok()
+                    },
+                )
+            {
+                Some(max) =>
+                {
+                    lgn_provers::log_sampling::set_max_per_minute(max);
+                    info!("Logging at most {max} debug lines per minute of each proving hot path");
+                    (
+                        "200 OK",
+                        log_sampling(),
+                    )
+                },
+                None =>
+                {
+                    (
+                        "400 Bad Request",
+                        String::new(),
+                    )
+                },
+            }
+        },
+        (Some("GET"), _) =>
+        {
+            (
+                "404 Not Found",
+                String::new(),
+            )
+        },
+        _ =>
+        {
+            (
+                "405 Method Not Allowed",
+                String::new(),
+            )
+        },
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: \
+         {}\r\n\r\n{body}",
+        body.len()
+    );
+    stream
+        .get_mut()
+        .write_all(response.as_bytes())
+        .await?;
+    Ok(())
+}
+
+fn log_sampling() -> String
+{
+    serde_json::json!(
+        {
+            "max_per_minute": lgn_provers::log_sampling::max_per_minute(),
+        }
+    )
+    .to_string()
+}
+
+fn availability(available: bool) -> &'static str
+{
+    if available
+    {
+        "200 OK"
+    }
+    else
+    {
+        "503 Service Unavailable"
+    }
+}
+
+async fn read_line<S: AsyncRead + Unpin>(stream: &mut BufReader<S>) -> Result<String>
+{
+    let mut line = String::new();
+    let read = (&mut *stream)
+        .take(MAX_LINE_BYTES)
+        .read_line(&mut line)
+        .await?;
+    if read == 0
+    {
+        bail!("the connection closed before the end of the request");
+    }
+    Ok(line)
+}
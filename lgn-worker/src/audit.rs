@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use anyhow::Context;
+use anyhow::Result;
+use ethers::signers::Wallet;
+use ethers::utils::keccak256;
+use k256::ecdsa::SigningKey;
+use lgn_auth::audit::audit_digest;
+use lgn_auth::audit::sign_audit_digest;
+use lgn_messages::types::MessageReplyEnvelope;
+use lgn_messages::types::ReplyAudit;
+use lgn_messages::types::ReplyType;
+
+/// Signs replies with the operator Lagrange key for later dispute resolution.
+pub(crate) struct ReplySigner
+{
+    wallet: Wallet<SigningKey>,
+
+    /// Hex-encoded keccak of the expected checksums file of the loaded public parameters.
+    params_checksum: String,
+}
+
+impl ReplySigner
+{
+    /// Creates a signer bound to the parameters described by `checksum_file`.
+    pub(crate) fn new(
+        wallet: Wallet<SigningKey>,
+        checksum_file: impl AsRef<Path>,
+    ) -> Result<Self>
+    {
+        let checksum_file = checksum_file.as_ref();
+        let checksums = std::fs::read(checksum_file).with_context(
+            || {
+                format!(
+                    "reply signing requires the params checksum file `{}`",
+                    checksum_file.display()
+                )
+            },
+        )?;
+
+        Ok(
+            Self {
+                wallet,
+                params_checksum: hex::encode(keccak256(checksums)),
+            },
+        )
+    }
+
+    /// Attaches an audit signature over the task id, proof and params checksum to `reply`.
+    pub(crate) fn sign(
+        &self,
+        reply: &mut MessageReplyEnvelope<ReplyType>,
+    ) -> Result<()>
+    {
+        let digest = audit_digest(
+            reply.task_id(),
+            reply
+                .content()
+                .proof()
+                .unwrap_or_default(),
+            &self.params_checksum,
+        );
+        let signature = sign_audit_digest(
+            digest,
+            &self.wallet,
+        )
+        .context("failed to sign the reply")?;
+
+        reply.set_audit(
+            ReplyAudit {
+                params_checksum: self
+                    .params_checksum
+                    .clone(),
+                signature,
+            },
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1345 @@
+//! The stages handling the tasks of the gRPC gateways, connected by channels.
+//!
+//! A task goes through the transport stage, which reads the gateway streams, the admission
+//! stage, which patches, parses and refuses the tasks, the proving stage and the reply stage, which
+//! checks, reports and sends the replies. Each stage owns its concern and is instrumented on its
+//! own, under the `stage` label.
+//!
+//! The time each task spends parsed, admitted, proven, serialized and sent is kept in its
+//! [`TaskTimings`], attached to its reply and exported by task stage.
+//!
+//! A gateway whose stream fails is connected to again, after a delay doubled at each failed
+//! attempt; the replies it could not be sent meanwhile are left for it to reconcile from the
+//! [`journal`]. The worker stops with an error once a gateway could not be connected to again in
+//! [`RECONNECT_ATTEMPTS`], or every gateway stream has ended, for its supervisor to restart it. The
+//! stages end without an error when the worker disconnects for a maintenance window, see
+//! [`maintenance`].
+//!
+//! The transport reads a task from a gateway only once the previous one is proven, the permit it
+//! read it with going along with the task until then, so that no task waits in the channels and
+//! the replay window and the reply sizes are checked right before each task is proven. The stages
+//! are polled on the same task, the proving stage blocking it while proving.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Result;
+use lgn_messages::streaming_hash;
+use lgn_messages::types::control::Capabilities;
+use lgn_messages::types::control::JournalStatus;
+use lgn_messages::types::error_code::ErrorCode;
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::MessageReplyEnvelope;
+use lgn_messages::types::ProverType;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskTimings;
+use lgn_messages::types::TaskType;
+use lgn_messages::types::WorkerError;
+use metrics::counter;
+use metrics::gauge;
+use metrics::histogram;
+use tokio::sync::mpsc;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+use tokio_stream::Stream;
+use tokio_stream::StreamExt;
+use tokio_stream::StreamMap;
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+use crate::capabilities;
+use crate::clock;
+use crate::config::Config;
+use crate::connect_to_grpc_gateway;
+use crate::events;
+use crate::handoff;
+use crate::inline_proofs;
+use crate::journal;
+use crate::lagrange;
+use crate::lagrange::worker_done::Reply;
+use crate::lagrange::WorkerDone;
+use crate::lagrange::WorkerToGwRequest;
+use crate::lagrange::WorkerToGwResponse;
+use crate::maintenance;
+use crate::manager::ProversManager;
+use crate::process_downstream_payload;
+use crate::replay;
+use crate::reply_size;
+use crate::reply_size::ReplySizeStats;
+use crate::task_patch;
+use crate::tenant;
+use crate::unix_now;
+use crate::GrpcGateway;
+
+/// The messages buffered between two stages, the transport not reading ahead anyway.
+const STAGE_CAPACITY: usize = 1;
+
+/// The delay before connecting again to a gateway whose stream failed, doubled after each failed
+/// attempt up to [`MAX_RECONNECT_DELAY`].
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// The attempts to connect again to a gateway before the worker stops.
+const RECONNECT_ATTEMPTS: u32 = 10;
+
+/// How long an attempt to connect again may take, the other gateways not being read meanwhile.
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The task stream of a gateway.
+pub(crate) type InboundStream =
+    Pin<Box<dyn Stream<Item = Result<WorkerToGwResponse, tonic::Status>> + Send>>;
+
+/// A task document read from a gateway.
+struct Inbound
+{
+    gateway: usize,
+    document: String,
+    /// Released once the task is proven, for the transport to read the next one.
+    permit: OwnedSemaphorePermit,
+}
+
+/// The next attempt to connect again to a gateway.
+struct Reconnect
+{
+    gateway: usize,
+    attempt: u32,
+    at: tokio::time::Instant,
+}
+
+/// What the stages after the admission know of a task.
+struct Ticket
+{
+    gateway: usize,
+    task_id: String,
+    prover_type: Option<ProverType>,
+    received: Instant,
+    timings: TaskTimings,
+}
+
+/// A task to prove, or the error replied if it was refused.
+struct Admitted
+{
+    ticket: Ticket,
+    task: Result<MessageEnvelope<TaskType>, String>,
+    permit: OwnedSemaphorePermit,
+}
+
+/// The serialized reply to a task, or the error replied instead.
+struct Proven
+{
+    ticket: Ticket,
+    reply: Result<MessageReplyEnvelope<ReplyType>, String>,
+}
+
+/// Handles the tasks of `gateways` until all their streams, in `inbounds` by gateway index, end,
+/// or the worker disconnects for a maintenance window.
+///
+/// The control messages of the gateways are answered as they are read, with `capabilities`.
+pub(crate) async fn run(
+    config: &Config,
+    provers_manager: &ProversManager<TaskType, ReplyType>,
+    capabilities: &Capabilities,
+    gateways: &[GrpcGateway<'_>],
+    inbounds: StreamMap<usize, InboundStream>,
+) -> Result<()>
+{
+    let reply_sizes = ReplySizeStats::default();
+    let (inbound_tx, inbound_rx) = mpsc::channel(STAGE_CAPACITY);
+    let (admitted_tx, admitted_rx) = mpsc::channel(STAGE_CAPACITY);
+    let (proven_tx, proven_rx) = mpsc::channel(STAGE_CAPACITY);
+
+    // Each stage ends when the previous one does, dropping its sender.
+    tokio::try_join!(
+        transport(
+            config,
+            capabilities,
+            gateways,
+            inbounds,
+            inbound_tx
+        ),
+        admission(
+            gateways,
+            &reply_sizes,
+            inbound_rx,
+            admitted_tx
+        ),
+        proving(
+            provers_manager,
+            gateways,
+            admitted_rx,
+            proven_tx
+        ),
+        reply(
+            gateways,
+            &reply_sizes,
+            proven_rx
+        ),
+    )?;
+
+    Ok(())
+}
+
+/// Reads the task documents of the gateway streams, answering the control messages.
+async fn transport(
+    config: &Config,
+    capabilities: &Capabilities,
+    gateways: &[GrpcGateway<'_>],
+    mut inbounds: StreamMap<usize, InboundStream>,
+    next: mpsc::Sender<Inbound>,
+) -> Result<()>
+{
+    for index in inbounds
+        .keys()
+        .copied()
+        .collect::<Vec<_>>()
+    {
+        if let Some(inbound) = inbounds.remove(&index)
+        {
+            inbounds.insert(
+                index,
+                closing(inbound),
+            );
+        }
+    }
+    let pace = Arc::new(Semaphore::new(1));
+    let mut permit = None;
+    let mut reconnects: Vec<Reconnect> = vec![];
+    loop
+    {
+        if inbounds.is_empty() && reconnects.is_empty()
+        {
+            break;
+        }
+        let next_reconnect = reconnects
+            .iter()
+            .map(|reconnect| reconnect.at)
+            .min();
+        let (index, message) = tokio::select! {
+            acquired = pace.clone().acquire_owned(), if permit.is_none() =>
+            {
+                permit = Some(acquired?);
+                continue;
+            },
+            next = inbounds.next(), if permit.is_some() && !inbounds.is_empty() =>
+            {
+                let Some(next) = next
+                else
+                {
+                    continue;
+                };
+                next
+            },
+            () = sleep_until(next_reconnect) =>
+            {
+                reconnect(
+                    config,
+                    gateways,
+                    &mut inbounds,
+                    &mut reconnects,
+                )
+                .await?;
+                continue;
+            },
+            () = maintenance::disconnecting() =>
+            {
+                // The next stages answer the tasks already read, then end.
+                info!("Disconnecting from the gateways for the maintenance window");
+                for gateway in gateways
+                {
+                    let gateway = gateway
+                        .avs
+                        .label();
+                    gauge!("zkmr_worker_gateway_connected", "gateway" => gateway.to_string()).set(0.0);
+                }
+                return Ok(());
+            },
+            () = handoff::requested() =>
+            {
+                info!("Disconnecting from the gateways to hand off to the next version");
+                for gateway in gateways
+                {
+                    let gateway = gateway
+                        .avs
+                        .label();
+                    gauge!("zkmr_worker_gateway_connected", "gateway" => gateway.to_string()).set(0.0);
+                }
+                return Ok(());
+            },
+        };
+        let gateway = gateways[index]
+            .avs
+            .label();
+        #[cfg(feature = "fault-injection")]
+        let message = crate::fault_injection::on_receive(message);
+        let message = match message
+        {
+            Ok(message) => message,
+            Err(e) =>
+            {
+                error!(
+                    "connection to the gateway `{gateway}` ended with status: {e}, connecting to \
+                     it again in {RECONNECT_DELAY:?}"
+                );
+                gauge!("zkmr_worker_gateway_connected", "gateway" => gateway.to_string()).set(0.0);
+                inbounds.remove(&index);
+                reconnects.push(
+                    Reconnect {
+                        gateway: index,
+                        attempt: 1,
+                        at: tokio::time::Instant::now() + RECONNECT_DELAY,
+                    },
+                );
+                continue;
+            },
+        };
+        let Some(lagrange::worker_to_gw_response::Response::Todo(document)) = message.response
+        else
+        {
+            warn!("Received WorkerToGwReponse with empty reponse field");
+            continue;
+        };
+
+        // The payload sizes before compression, tonic does not expose the wire sizes.
+        counter!(
+            "zkmr_worker_grpc_payload_bytes_total",
+            "gateway" => gateway.to_string(),
+            "direction" => "received",
+        )
+        .increment(document.len() as u64);
+        if let Some(answer) = capabilities::answer(
+            &document,
+            capabilities,
+            gateways[index].avs,
+        )
+        {
+            debug!("Answering a control message of gateway `{gateway}`: {document}");
+            counter!("zkmr_worker_control_messages_total", "gateway" => gateway.to_string())
+                .increment(1);
+            let sent = gateways[index]
+                .outbound()
+                .send(
+                    WorkerToGwRequest {
+                        request: Some(
+                            lagrange::worker_to_gw_request::Request::WorkerDone(
+                                WorkerDone {
+                                    reply: Some(Reply::ReplyString(answer)),
+                                },
+                            ),
+                        ),
+                    },
+                )
+                .await;
+            if sent.is_err()
+            {
+                warn!("Failed to answer a control message of gateway `{gateway}`, disconnected");
+            }
+            continue;
+        }
+        passed("transport");
+        if next
+            .send(
+                Inbound {
+                    gateway: index,
+                    document,
+                    permit: permit
+                        .take()
+                        .expect("a task is read with a permit"),
+                },
+            )
+            .await
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+
+    bail!("every gateway stream ended")
+}
+
+/// Ends `inbound` with an error once the gateway closes it, for the gateway to be connected to
+/// again: the stream map drops the streams which end without yielding anything.
+fn closing(inbound: InboundStream) -> InboundStream
+{
+    Box::pin(
+        inbound.chain(
+            tokio_stream::once(Err(tonic::Status::unavailable("the gateway closed the stream"))),
+        ),
+    )
+}
+
+/// Connects again to the gateway whose attempt is due, scheduling the next attempt if it fails.
+async fn reconnect(
+    config: &Config,
+    gateways: &[GrpcGateway<'_>],
+    inbounds: &mut StreamMap<usize, InboundStream>,
+    reconnects: &mut Vec<Reconnect>,
+) -> Result<()>
+{
+    let now = tokio::time::Instant::now();
+    let Some(due) = reconnects
+        .iter()
+        .position(|reconnect| reconnect.at <= now)
+    else
+    {
+        return Ok(());
+    };
+    let Reconnect {
+        gateway: index,
+        attempt,
+        ..
+    } = reconnects.swap_remove(due);
+    let grpc_gateway = &gateways[index];
+    let gateway = grpc_gateway
+        .avs
+        .label();
+    counter!("zkmr_worker_gateway_reconnects_total", "gateway" => gateway.to_string()).increment(1);
+    let connected = tokio::time::timeout(
+        RECONNECT_TIMEOUT,
+        connect_to_grpc_gateway(
+            config,
+            grpc_gateway.avs,
+        ),
+    )
+    .await
+    .unwrap_or_else(|_| Err(anyhow!("timed out after {RECONNECT_TIMEOUT:?}")));
+    match connected
+    {
+        Ok((outbound, inbound)) =>
+        {
+            info!("Connected again to the gateway `{gateway}`");
+            grpc_gateway.reconnected(outbound);
+            inbounds.insert(
+                index,
+                closing(Box::pin(inbound)),
+            );
+        },
+        Err(err) if attempt >= RECONNECT_ATTEMPTS =>
+        {
+            return Err(
+                err.context(
+                    format!(
+                        "failed to connect again to the gateway `{gateway}` in {attempt} attempts"
+                    ),
+                ),
+            );
+        },
+        Err(err) =>
+        {
+            let delay = (RECONNECT_DELAY * 2u32.pow(attempt)).min(MAX_RECONNECT_DELAY);
+            warn!(
+                "Failed to connect again to the gateway `{gateway}`, attempt {attempt}, retrying \
+                 in {delay:?}: {err:#}"
+            );
+            reconnects.push(
+                Reconnect {
+                    gateway: index,
+                    attempt: attempt + 1,
+                    at: tokio::time::Instant::now() + delay,
+                },
+            );
+        },
+    }
+    Ok(())
+}
+
+/// Sleeps until `deadline`, forever without one.
+async fn sleep_until(deadline: Option<tokio::time::Instant>)
+{
+    match deadline
+    {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Parses the tasks and refuses those which can not be replied to.
+async fn admission(
+    gateways: &[GrpcGateway<'_>],
+    reply_sizes: &ReplySizeStats,
+    mut inbound: mpsc::Receiver<Inbound>,
+    next: mpsc::Sender<Admitted>,
+) -> Result<()>
+{
+    while let Some(Inbound {
+        gateway: index,
+        document,
+        permit,
+    }) = inbound
+        .recv()
+        .await
+    {
+        let started = Instant::now();
+        let grpc_gateway = &gateways[index];
+        let gateway = grpc_gateway
+            .avs
+            .label();
+        // A task which does not parse has no id to reply to, it is dropped, unless it is only of a
+        // version the worker does not support.
+        let document = task_patch::apply(&document).unwrap_or(document);
+        let (parsed, proof_digests) = streaming_hash::collect(
+            || serde_json::from_str::<MessageEnvelope<TaskType>>(&document),
+        );
+        let admitting = Instant::now();
+        let mut timings = TaskTimings {
+            parse_ms: Some(millis(started)),
+            ..TaskTimings::default()
+        };
+        let envelope = match parsed
+        {
+            Ok(envelope) => envelope,
+            Err(err) =>
+            {
+                if let Some((task_id, variant)) = unsupported_task(&document)
+                {
+                    let code = ErrorCode::UnsupportedTask;
+                    warn!("Refusing the `{variant}` task {task_id} from gateway `{gateway}`");
+                    counter!(
+                        "zkmr_worker_error_count",
+                        "error_type" => code.label(),
+                        "error_code" => code.to_string(),
+                        "gateway" => gateway.to_string(),
+                    )
+                    .increment(1);
+                    let task = Err(
+                        code.annotate(
+                            format!(
+                                "`{variant}` tasks are not supported by this worker, only {}",
+                                TaskType::VARIANTS.join(", ")
+                            ),
+                        ),
+                    );
+                    timings.admission_ms = Some(millis(admitting));
+                    timed(
+                        "admission",
+                        started,
+                    );
+                    if next
+                        .send(
+                            Admitted {
+                                ticket: Ticket {
+                                    gateway: index,
+                                    task_id,
+                                    prover_type: None,
+                                    received: started,
+                                    timings,
+                                },
+                                task,
+                                permit,
+                            },
+                        )
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                    continue;
+                }
+                error!("Dropping a malformed task from gateway `{gateway}`: {err}");
+                counter!(
+                    "zkmr_worker_error_count",
+                    "error_type" => ErrorCode::MalformedTask.label(),
+                    "error_code" => ErrorCode::MalformedTask.to_string(),
+                    "gateway" => gateway.to_string(),
+                )
+                .increment(1);
+                continue;
+            },
+        };
+        if replay::admit(
+            gateway,
+            &envelope,
+            clock::gateway_now(gateway),
+        )
+        .is_err()
+        {
+            continue;
+        }
+        inline_proofs::record(
+            gateway,
+            &proof_digests,
+        );
+        let prover_type = reply_size::prover_type(&envelope.inner);
+        let task_id = envelope
+            .task_id
+            .clone();
+        events::emit(
+            || {
+                events::Event::Received {
+                    gateway: gateway.to_string(),
+                    task_id: task_id.clone(),
+                    query_id: envelope
+                        .query_id
+                        .clone(),
+                    tenant: tenant::label(
+                        envelope
+                            .tenant
+                            .as_deref(),
+                    ),
+                    prover_type: format!("{prover_type:?}"),
+                    task_bytes: document.len(),
+                    at: unix_now(),
+                }
+            },
+        );
+
+        // Chunked replies are not supported by the gateway protocol, a reply which can not fit in
+        // a message is rejected before proving rather than when sending.
+        let task = match reply_sizes.admit(
+            &task_id,
+            prover_type,
+            grpc_gateway.max_message_size,
+        )
+        {
+            Ok(()) => Ok(envelope),
+            Err(err) =>
+            {
+                warn!("Refusing task: {err}");
+                Err(
+                    err.as_code()
+                        .annotate(&err),
+                )
+            },
+        };
+        timings.admission_ms = Some(millis(admitting));
+        timed(
+            "admission",
+            started,
+        );
+        if next
+            .send(
+                Admitted {
+                    ticket: Ticket {
+                        gateway: index,
+                        task_id,
+                        prover_type,
+                        received: started,
+                        timings,
+                    },
+                    task,
+                    permit,
+                },
+            )
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Proves the admitted tasks.
+async fn proving(
+    provers_manager: &ProversManager<TaskType, ReplyType>,
+    gateways: &[GrpcGateway<'_>],
+    mut admitted: mpsc::Receiver<Admitted>,
+    next: mpsc::Sender<Proven>,
+) -> Result<()>
+{
+    while let Some(Admitted {
+        mut ticket,
+        task,
+        permit,
+    }) = admitted
+        .recv()
+        .await
+    {
+        let started = Instant::now();
+        let grpc_gateway = &gateways[ticket.gateway];
+        let reply = task.and_then(
+            |envelope| {
+                tokio::task::block_in_place(
+                    || {
+                        process_downstream_payload(
+                            provers_manager,
+                            grpc_gateway
+                                .avs
+                                .label(),
+                            grpc_gateway
+                                .signer
+                                .as_ref(),
+                            envelope,
+                            ticket
+                                .timings
+                                .clone(),
+                        )
+                    },
+                )
+            },
+        );
+        // A proven reply carries the timings it was signed with, a refused task only the time
+        // spent refusing it.
+        match reply
+            .as_ref()
+            .ok()
+            .and_then(|reply| reply.timings())
+        {
+            Some(timings) => ticket.timings = timings.clone(),
+            None =>
+            {
+                ticket
+                    .timings
+                    .prove_ms = Some(millis(started))
+            },
+        }
+        // The next task can be read.
+        drop(permit);
+        timed(
+            "proving",
+            started,
+        );
+        if next
+            .send(
+                Proven {
+                    ticket,
+                    reply,
+                },
+            )
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Serializes, checks, reports and sends the replies.
+async fn reply(
+    gateways: &[GrpcGateway<'_>],
+    reply_sizes: &ReplySizeStats,
+    mut proven: mpsc::Receiver<Proven>,
+) -> Result<()>
+{
+    while let Some(Proven {
+        mut ticket,
+        reply,
+    }) = proven
+        .recv()
+        .await
+    {
+        let started = Instant::now();
+        let grpc_gateway = &gateways[ticket.gateway];
+        let gateway = grpc_gateway
+            .avs
+            .label();
+        let max_message_size = grpc_gateway.max_message_size;
+        let reply = match reply
+        {
+            Ok(reply) =>
+            {
+                let serializing = Instant::now();
+                let reply = serde_json::to_string(&reply)?;
+                ticket
+                    .timings
+                    .serialize_ms = Some(millis(serializing));
+                reply_sizes.record(
+                    ticket.prover_type,
+                    reply.len(),
+                );
+                if reply.len() > max_message_size
+                {
+                    let err = WorkerError::ReplyTooLarge {
+                        task_id: ticket
+                            .task_id
+                            .clone(),
+                        size: reply.len(),
+                        max_message_size,
+                    };
+                    error!("Dropping reply: {err}");
+                    Err(
+                        err.as_code()
+                            .annotate(&err),
+                    )
+                }
+                else
+                {
+                    Ok(reply)
+                }
+            },
+            Err(err) => Err(err),
+        };
+        events::emit(
+            || {
+                let duration_ms = ticket
+                    .received
+                    .elapsed()
+                    .as_millis() as u64;
+                match &reply
+                {
+                    Ok(reply) =>
+                    {
+                        events::Event::Completed {
+                            gateway: gateway.to_string(),
+                            task_id: ticket
+                                .task_id
+                                .clone(),
+                            duration_ms,
+                            reply_bytes: reply.len(),
+                            at: unix_now(),
+                        }
+                    },
+                    Err(err) =>
+                    {
+                        events::Event::Failed {
+                            gateway: gateway.to_string(),
+                            task_id: ticket
+                                .task_id
+                                .clone(),
+                            duration_ms,
+                            error_code: events::error_code(err),
+                            at: unix_now(),
+                        }
+                    },
+                }
+            },
+        );
+        match &reply
+        {
+            Ok(reply) =>
+            {
+                journal::record(
+                    gateway,
+                    &ticket.task_id,
+                    JournalStatus::Completed,
+                    reply,
+                )
+            },
+            Err(err) =>
+            {
+                journal::record(
+                    gateway,
+                    &ticket.task_id,
+                    JournalStatus::Failed,
+                    err,
+                )
+            },
+        }
+
+        let reply = match reply
+        {
+            Ok(reply) =>
+            {
+                counter!(
+                    "zkmr_worker_grpc_payload_bytes_total",
+                    "gateway" => gateway.to_string(),
+                    "direction" => "sent",
+                )
+                .increment(reply.len() as u64);
+                Reply::ReplyString(reply)
+            },
+            Err(error_str) => Reply::WorkerError(error_str),
+        };
+        let sending = Instant::now();
+        #[cfg(feature = "fault-injection")]
+        crate::fault_injection::before_send().await;
+        let sent = grpc_gateway
+            .outbound()
+            .send(
+                WorkerToGwRequest {
+                    request: Some(
+                        lagrange::worker_to_gw_request::Request::WorkerDone(
+                            WorkerDone {
+                                reply: Some(reply),
+                            },
+                        ),
+                    ),
+                },
+            )
+            .await;
+        if sent.is_err()
+        {
+            warn!(
+                "Failed to send the reply to task {} to gateway `{gateway}`, disconnected, left \
+                 for it to reconcile from the journal",
+                ticket.task_id
+            );
+            continue;
+        }
+        ticket
+            .timings
+            .send_ms = Some(millis(sending));
+        record_timings(
+            &ticket.task_id,
+            &ticket.timings,
+        );
+
+        counter!("zkmr_worker_grpc_messages_sent_total",
+                        "message_type" => "text",
+                        "gateway" => gateway.to_string())
+        .increment(1);
+        timed(
+            "reply",
+            started,
+        );
+    }
+
+    Ok(())
+}
+
+/// The id and the variant of a task of a version the worker does not support, to reply to it.
+fn unsupported_task(
+    document: &str
+) -> Option<(
+    String,
+    String,
+)>
+{
+    let envelope = serde_json::from_str::<serde_json::Value>(document).ok()?;
+    let task_id = envelope
+        .get("task_id")?
+        .as_str()?
+        .to_string();
+    let variant = match envelope.get("inner")?
+    {
+        serde_json::Value::Object(inner) if inner.len() == 1 =>
+        {
+            inner
+                .keys()
+                .next()?
+                .clone()
+        },
+        serde_json::Value::String(variant) => variant.clone(),
+        _ => return None,
+    };
+    (!TaskType::VARIANTS.contains(&variant.as_str())).then_some(
+        (
+            task_id,
+            variant,
+        ),
+    )
+}
+
+/// Exports the time the task `task_id` spent in each of its stages.
+fn record_timings(
+    task_id: &str,
+    timings: &TaskTimings,
+)
+{
+    debug!("Task {task_id} timings: {timings:?}");
+    for (stage, ms) in timings.stages()
+    {
+        if let Some(ms) = ms
+        {
+            histogram!("zkmr_worker_task_stage_duration_seconds", "stage" => stage)
+                .record(ms as f64 / 1000.0);
+        }
+    }
+}
+
+/// The milliseconds elapsed since `started`.
+fn millis(started: Instant) -> u64
+{
+    started
+        .elapsed()
+        .as_millis() as u64
+}
+
+/// Counts a message passed on by `stage`.
+fn passed(stage: &'static str)
+{
+    counter!("zkmr_worker_bus_messages_total", "stage" => stage).increment(1);
+}
+
+/// Counts a message passed on by `stage`, which handled it since `started`.
+fn timed(
+    stage: &'static str,
+    started: Instant,
+)
+{
+    passed(stage);
+    histogram!("zkmr_worker_bus_stage_duration_seconds", "stage" => stage).record(
+        started
+            .elapsed()
+            .as_secs_f64(),
+    );
+}
+
+#[cfg(test)]
+mod tests
+{
+    use lgn_messages::routing::RoutingKey;
+    use lgn_messages::types::v1::groth16;
+    use lgn_messages::types::v1::query::keys::ProofKey;
+
+    use super::*;
+    use crate::config::AvsConfig;
+
+    fn gateway(
+        avs: &AvsConfig
+    ) -> (
+        GrpcGateway<'_>,
+        mpsc::Receiver<WorkerToGwRequest>,
+    )
+    {
+        let (outbound, sent) = mpsc::channel(8);
+        (
+            GrpcGateway {
+                avs,
+                signer: None,
+                outbound: std::sync::Mutex::new(outbound),
+                max_message_size: 1024 * 1024,
+            },
+            sent,
+        )
+    }
+
+    fn permit(pace: &Arc<Semaphore>) -> OwnedSemaphorePermit
+    {
+        pace.clone()
+            .try_acquire_owned()
+            .unwrap()
+    }
+
+    fn ticket(task_id: &str) -> Ticket
+    {
+        Ticket {
+            gateway: 0,
+            task_id: task_id.to_string(),
+            prover_type: None,
+            received: Instant::now(),
+            timings: TaskTimings::default(),
+        }
+    }
+
+    fn todo(document: &str) -> Result<WorkerToGwResponse, tonic::Status>
+    {
+        Ok(
+            WorkerToGwResponse {
+                response: Some(
+                    lagrange::worker_to_gw_response::Response::Todo(document.to_string()),
+                ),
+            },
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transport_reads_a_task_once_the_previous_one_is_proven()
+    {
+        let config = Config::load(None);
+        let (gateway, _sent) = gateway(&config.avs[0]);
+        let gateways = [gateway];
+        let mut inbounds = StreamMap::new();
+        inbounds.insert(
+            0,
+            Box::pin(
+                tokio_stream::iter(
+                    [
+                        todo("task-1"),
+                        todo("task-2"),
+                    ],
+                ),
+            ) as InboundStream,
+        );
+        let (next, mut read) = mpsc::channel(STAGE_CAPACITY);
+        let capabilities = Capabilities::default();
+        let transport = transport(
+            &config,
+            &capabilities,
+            &gateways,
+            inbounds,
+            next,
+        );
+        let stage = async {
+            let first = read
+                .recv()
+                .await
+                .unwrap();
+            assert_eq!(
+                first.document,
+                "task-1"
+            );
+            assert!(
+                tokio::time::timeout(
+                    Duration::from_millis(100),
+                    read.recv()
+                )
+                .await
+                .is_err()
+            );
+            drop(first);
+            let second = read
+                .recv()
+                .await
+                .unwrap();
+            assert_eq!(
+                second.document,
+                "task-2"
+            );
+        };
+        let (ended, ()) = tokio::join!(
+            transport,
+            stage
+        );
+        assert!(ended.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transport_connects_again_to_a_failed_gateway()
+    {
+        let config = Config::load(None);
+        let (gateway, _sent) = gateway(&config.avs[0]);
+        let gateways = [gateway];
+        let mut inbounds = StreamMap::new();
+        inbounds.insert(
+            0,
+            Box::pin(tokio_stream::iter([Err(tonic::Status::unavailable("gone"))]))
+                as InboundStream,
+        );
+        let (next, _read) = mpsc::channel(STAGE_CAPACITY);
+        let capabilities = Capabilities::default();
+        let started = tokio::time::Instant::now();
+
+        // The test gateway can not be connected to, each attempt fails until the worker stops.
+        let err = transport(
+            &config,
+            &capabilities,
+            &gateways,
+            inbounds,
+            next,
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(&format!("in {RECONNECT_ATTEMPTS} attempts")),
+            "{err:#}"
+        );
+        assert!(started.elapsed() >= RECONNECT_DELAY * (RECONNECT_ATTEMPTS - 1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transport_connects_again_to_a_gateway_which_closed_its_stream()
+    {
+        let config = Config::load(None);
+        let (gateway, _sent) = gateway(&config.avs[0]);
+        let gateways = [gateway];
+        let mut inbounds = StreamMap::new();
+        inbounds.insert(
+            0,
+            Box::pin(tokio_stream::iter([todo("task-1")])) as InboundStream,
+        );
+        let (next, mut read) = mpsc::channel(STAGE_CAPACITY);
+        let capabilities = Capabilities::default();
+        let transport = transport(
+            &config,
+            &capabilities,
+            &gateways,
+            inbounds,
+            next,
+        );
+        let stage = async {
+            while read
+                .recv()
+                .await
+                .is_some()
+            {}
+        };
+
+        // The stream ending is not the end of the gateway, it is connected to again.
+        let (ended, ()) = tokio::join!(
+            transport,
+            stage
+        );
+        let err = ended.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains(&format!("in {RECONNECT_ATTEMPTS} attempts")),
+            "{err:#}"
+        );
+    }
+
+    #[tokio::test]
+    async fn admission_refuses_the_tasks_which_can_not_be_proven()
+    {
+        let config = Config::load(None);
+        let (gateway, _sent) = gateway(&config.avs[0]);
+        let gateways = [gateway];
+        let pace = Arc::new(Semaphore::new(3));
+        let (inbound, received) = mpsc::channel(3);
+        let (next, mut admitted) = mpsc::channel(3);
+        let envelope = MessageEnvelope::new(
+            "query".to_string(),
+            "task-1".to_string(),
+            TaskType::V1Groth16(
+                groth16::WorkerTask::new(
+                    1,
+                    ProofKey::Revelation("query".to_string()),
+                ),
+            ),
+            RoutingKey::combined(
+                "domain".to_string(),
+                0,
+            ),
+        );
+        for document in [
+            "not a task".to_string(),
+            r#"{"task_id": "task-0", "inner": {"V0Query": {}}}"#.to_string(),
+            serde_json::to_string(&envelope).unwrap(),
+        ]
+        {
+            inbound
+                .send(
+                    Inbound {
+                        gateway: 0,
+                        document,
+                        permit: permit(&pace),
+                    },
+                )
+                .await
+                .unwrap();
+        }
+        drop(inbound);
+        admission(
+            &gateways,
+            &ReplySizeStats::default(),
+            received,
+            next,
+        )
+        .await
+        .unwrap();
+
+        // The malformed task is dropped, releasing its permit.
+        let unsupported = admitted
+            .recv()
+            .await
+            .unwrap();
+        assert_eq!(
+            unsupported
+                .ticket
+                .task_id,
+            "task-0"
+        );
+        assert!(
+            unsupported
+                .task
+                .is_err()
+        );
+        let task = admitted
+            .recv()
+            .await
+            .unwrap();
+        assert_eq!(
+            task.ticket
+                .prover_type,
+            Some(ProverType::V1Groth16)
+        );
+        assert!(
+            task.task
+                .is_ok()
+        );
+        assert!(
+            admitted
+                .recv()
+                .await
+                .is_none()
+        );
+        assert_eq!(
+            pace.available_permits(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn proving_releases_the_permit_of_the_task()
+    {
+        let config = Config::load(None);
+        let (gateway, _sent) = gateway(&config.avs[0]);
+        let gateways = [gateway];
+        let pace = Arc::new(Semaphore::new(1));
+        let (admit, admitted) = mpsc::channel(1);
+        let (next, mut proven) = mpsc::channel(1);
+        admit
+            .send(
+                Admitted {
+                    ticket: ticket("task-1"),
+                    task: Err("E1001 refused".to_string()),
+                    permit: permit(&pace),
+                },
+            )
+            .await
+            .unwrap();
+        drop(admit);
+        proving(
+            &ProversManager::new(),
+            &gateways,
+            admitted,
+            next,
+        )
+        .await
+        .unwrap();
+
+        let proven = proven
+            .recv()
+            .await
+            .unwrap();
+        assert_eq!(
+            proven
+                .reply
+                .err(),
+            Some("E1001 refused".to_string())
+        );
+        assert!(
+            proven
+                .ticket
+                .timings
+                .prove_ms
+                .is_some()
+        );
+        assert_eq!(
+            pace.available_permits(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn reply_sends_the_errors_to_the_gateway()
+    {
+        let config = Config::load(None);
+        let (gateway, mut sent) = gateway(&config.avs[0]);
+        let gateways = [gateway];
+        let (prove, proven) = mpsc::channel(1);
+        prove
+            .send(
+                Proven {
+                    ticket: ticket("task-1"),
+                    reply: Err("E1001 refused".to_string()),
+                },
+            )
+            .await
+            .unwrap();
+        drop(prove);
+        reply(
+            &gateways,
+            &ReplySizeStats::default(),
+            proven,
+        )
+        .await
+        .unwrap();
+
+        let Some(lagrange::worker_to_gw_request::Request::WorkerDone(WorkerDone {
+            reply: Some(Reply::WorkerError(error)),
+        })) = sent
+            .recv()
+            .await
+            .unwrap()
+            .request
+        else
+        {
+            panic!("expected an error reply");
+        };
+        assert_eq!(
+            error,
+            "E1001 refused"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! The answers to the control messages of the gateways, see [`ControlMessage`].
+//!
+//! A gateway asks a worker what it can prove with `{"control": "Capabilities"}` on its task
+//! stream, e.g. after the worker connected, and routes it the tasks it supports without the
+//! deployments of both being coordinated.
+
+use lgn_messages::types::control::Capabilities;
+use lgn_messages::types::control::ControlMessage;
+use lgn_messages::types::control::ControlReply;
+use lgn_messages::types::control::ControlRequest;
+use lgn_messages::types::control::MAX_CONTROL_MESSAGE_BYTES;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskType;
+use lgn_messages::types::SCHEMA_VERSION;
+
+use crate::config::AvsConfig;
+use crate::config::Config;
+use crate::fingerprint;
+use crate::journal;
+use crate::manager::ProversManager;
+
+/// What the worker configured by `config`, with the provers of `provers_manager`, can prove.
+pub(crate) fn of(
+    config: &Config,
+    provers_manager: &ProversManager<TaskType, ReplyType>,
+) -> Capabilities
+{
+    let mut prover_types = provers_manager
+        .prover_types()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    prover_types.sort();
+
+    let mut features = fingerprint::build_features();
+    for (feature, enabled) in [
+        (
+            "content-addressed-keys",
+            config
+                .worker
+                .content_addressed_keys,
+        ),
+        (
+            "deadline-ordering",
+            config
+                .worker
+                .deadline_ordering,
+        ),
+        (
+            "replay-window",
+            config
+                .worker
+                .replay_window
+                .is_some(),
+        ),
+        #[cfg(feature = "prover-query")]
+        (
+            "pruned-query-params",
+            config
+                .public_params
+                .query_params
+                .pruned,
+        ),
+    ]
+    {
+        if enabled
+        {
+            features.push(feature.to_string());
+        }
+    }
+
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        worker_class: config
+            .worker
+            .instance_type
+            .to_string(),
+        task_types: TaskType::VARIANTS
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        prover_types,
+        schema_version: SCHEMA_VERSION,
+        #[cfg(feature = "prover-query")]
+        circuit_constants: lgn_provers::provers::v1::query::circuit_shape()
+            .constants()
+            .clone(),
+        #[cfg(not(feature = "prover-query"))]
+        circuit_constants: Default::default(),
+        features,
+        proving_threads: rayon::current_num_threads(),
+        eco_mode_percent: config
+            .worker
+            .eco_mode,
+    }
+}
+
+/// The answer to the control message in `document` from the gateway of `avs`, `None` if
+/// `document` is a task.
+pub(crate) fn answer(
+    document: &str,
+    capabilities: &Capabilities,
+    avs: &AvsConfig,
+) -> Option<String>
+{
+    if document.len() > MAX_CONTROL_MESSAGE_BYTES
+    {
+        return None;
+    }
+    let message = serde_json::from_str::<ControlMessage>(document).ok()?;
+    let reply = match message.control
+    {
+        ControlRequest::Capabilities =>
+        {
+            let mut capabilities = capabilities.clone();
+            if avs.sign_replies
+            {
+                capabilities
+                    .features
+                    .push("signed-replies".to_string());
+            }
+            ControlReply::Capabilities(capabilities)
+        },
+        ControlRequest::GetJournalSince {
+            seq,
+        } =>
+        {
+            ControlReply::Journal(
+                journal::since(
+                    avs.label(),
+                    seq,
+                ),
+            )
+        },
+    };
+    serde_json::to_string(&reply).ok()
+}
@@ -0,0 +1,311 @@
+//! Capture of the inputs and outputs of selected tasks.
+//!
+//! Dumping every task is too heavy to leave enabled, the tasks whose id matches one of the
+//! configured patterns are captured instead. Patterns can be added while the worker runs through
+//! the patterns file, e.g. to capture the next tasks of a failing query.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::RwLock;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Context;
+use anyhow::Result;
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::MessageReplyEnvelope;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskType;
+use regex::Regex;
+use serde_derive::Serialize;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::DebugConfig;
+use crate::fingerprint;
+use crate::unix_now;
+
+/// Delay between two reads of the patterns file.
+const PATTERNS_FILE_INTERVAL: Duration = Duration::from_secs(10);
+
+static CAPTURE: OnceLock<Capture> = OnceLock::new();
+
+struct Capture
+{
+    dir: PathBuf,
+    config_patterns: Vec<Regex>,
+    file_patterns: RwLock<Vec<Regex>>,
+}
+
+/// The capture of a task in progress.
+pub(crate) struct TaskCapture
+{
+    dir: PathBuf,
+    received_unix: u64,
+    start: Instant,
+}
+
+#[derive(Serialize)]
+struct Timings
+{
+    received_unix: u64,
+    proving_ms: u128,
+    succeeded: bool,
+}
+
+/// Enables the capture, if any pattern is configured.
+pub(crate) fn init(config: &DebugConfig)
+{
+    if config
+        .capture_task_ids
+        .is_empty()
+        && config
+            .capture_patterns_file
+            .is_none()
+    {
+        return;
+    }
+
+    let config_patterns = config
+        .capture_task_ids
+        .iter()
+        .map(|pattern| Regex::new(pattern).expect("capture patterns are validated"))
+        .collect();
+    let _ = CAPTURE.set(
+        Capture {
+            dir: PathBuf::from(&config.capture_dir),
+            config_patterns,
+            file_patterns: Default::default(),
+        },
+    );
+
+    if let Some(path) = config
+        .capture_patterns_file
+        .clone()
+    {
+        std::thread::spawn(
+            move || {
+                loop
+                {
+                    reload_patterns(Path::new(&path));
+                    std::thread::sleep(PATTERNS_FILE_INTERVAL);
+                }
+            },
+        );
+    }
+}
+
+/// Replaces the patterns read from `path`, a missing file meaning no pattern.
+fn reload_patterns(path: &Path)
+{
+    let content = match std::fs::read_to_string(path)
+    {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) =>
+        {
+            warn!(
+                "Failed to read the capture patterns `{}`: {err}",
+                path.display()
+            );
+            return;
+        },
+    };
+
+    let mut patterns = vec![];
+    for line in content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        match Regex::new(line)
+        {
+            Ok(pattern) => patterns.push(pattern),
+            Err(err) => warn!("Ignoring the invalid capture pattern `{line}`: {err}"),
+        }
+    }
+
+    let capture = CAPTURE
+        .get()
+        .expect("capture initialized");
+    let mut file_patterns = capture
+        .file_patterns
+        .write()
+        .expect("capture patterns lock poisoned");
+    if file_patterns
+        .iter()
+        .map(Regex::as_str)
+        .ne(
+            patterns
+                .iter()
+                .map(Regex::as_str),
+        )
+    {
+        info!(
+            "Capture patterns updated. patterns: {:?}",
+            patterns
+                .iter()
+                .map(Regex::as_str)
+                .collect::<Vec<_>>()
+        );
+        *file_patterns = patterns;
+    }
+}
+
+/// Starts capturing `envelope` if its task id matches a pattern, saving the envelope.
+///
+/// Capture failures are logged, they must not fail the task.
+pub(crate) fn start(envelope: &MessageEnvelope<TaskType>) -> Option<TaskCapture>
+{
+    let capture = CAPTURE.get()?;
+    let task_id = &envelope.task_id;
+    let matches = capture
+        .config_patterns
+        .iter()
+        .any(|pattern| pattern.is_match(task_id))
+        || capture
+            .file_patterns
+            .read()
+            .expect("capture patterns lock poisoned")
+            .iter()
+            .any(|pattern| pattern.is_match(task_id));
+    if !matches
+    {
+        return None;
+    }
+
+    let received_unix = unix_now();
+    let name = task_id.replace(
+        |c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_',
+        "_",
+    );
+    let task_capture = TaskCapture {
+        dir: capture
+            .dir
+            .join(format!("{name}-{received_unix}")),
+        received_unix,
+        start: Instant::now(),
+    };
+    let result = std::fs::create_dir_all(&task_capture.dir)
+        .map_err(Into::into)
+        .and_then(
+            |()| {
+                task_capture.write(
+                    "envelope.json",
+                    &serde_json::to_vec_pretty(envelope)?,
+                )
+            },
+        )
+        .and_then(
+            |()| {
+                // Replaying a capture on another machine starts by comparing their environments.
+                match fingerprint::get()
+                {
+                    Some(fingerprint) =>
+                    {
+                        task_capture.write(
+                            "environment.json",
+                            &serde_json::to_vec_pretty(&fingerprint)?,
+                        )
+                    },
+                    None => Ok(()),
+                }
+            },
+        );
+    if let Err(err) = result
+    {
+        warn!("Failed to capture task {task_id}: {err:?}");
+        return None;
+    }
+    info!(
+        "Capturing task {task_id} to `{}`",
+        task_capture
+            .dir
+            .display()
+    );
+
+    Some(task_capture)
+}
+
+impl TaskCapture
+{
+    /// Saves the reply, or the error, and the timings of the task.
+    pub(crate) fn finish(
+        self,
+        outcome: Result<&MessageReplyEnvelope<ReplyType>, String>,
+    )
+    {
+        let proving_ms = self
+            .start
+            .elapsed()
+            .as_millis();
+        let result = match &outcome
+        {
+            Ok(reply) =>
+            {
+                serde_json::to_vec_pretty(reply)
+                    .map_err(Into::into)
+                    .and_then(
+                        |reply| {
+                            self.write(
+                                "reply.json",
+                                &reply,
+                            )
+                        },
+                    )
+            },
+            Err(err) =>
+            {
+                self.write(
+                    "error.txt",
+                    err.as_bytes(),
+                )
+            },
+        }
+        .and_then(
+            |()| {
+                let timings = Timings {
+                    received_unix: self.received_unix,
+                    proving_ms,
+                    succeeded: outcome.is_ok(),
+                };
+                self.write(
+                    "timings.json",
+                    &serde_json::to_vec_pretty(&timings)?,
+                )
+            },
+        );
+        if let Err(err) = result
+        {
+            warn!(
+                "Failed to complete the capture `{}`: {err:?}",
+                self.dir
+                    .display()
+            );
+        }
+    }
+
+    fn write(
+        &self,
+        file_name: &str,
+        content: &[u8],
+    ) -> Result<()>
+    {
+        let path = self
+            .dir
+            .join(file_name);
+        std::fs::write(
+            &path,
+            content,
+        )
+        .with_context(
+            || {
+                format!(
+                    "failed to write `{}`",
+                    path.display()
+                )
+            },
+        )
+    }
+}
@@ -15,10 +15,11 @@ use checksums::ops::write_hash_comparison_results;
 use checksums::ops::write_hashes;
 use checksums::ops::CompareFileResult;
 use checksums::Error;
-use reqwest::IntoUrl;
+use lgn_provers::params::ParamsLoader;
 use tracing::debug;
 use tracing::error;
 use tracing::info;
+use tracing::warn;
 
 pub(crate) fn verify_directory_checksums(
     dir: impl AsRef<OsStr> + Debug,
@@ -43,15 +44,17 @@ pub(crate) fn verify_directory_checksums(
         "Computed hashes: {:?}",
         computed_hashes
     );
+    // Next to the expected checksums rather than in the working directory, which may be
+    // read-only.
+    let expected_hashes_file = Path::new(expected_checksums_file.as_ref());
     write_hashes(
         &(
             "output".to_string(),
-            Path::new("public_params.hash").to_path_buf(),
+            expected_hashes_file.with_file_name("public_params.hash"),
         ),
         checksums::Algorithm::BLAKE3,
         computed_hashes.clone(),
     );
-    let expected_hashes_file = Path::new(expected_checksums_file.as_ref());
     let expected_hashes = read_hashes(
         &mut std::io::stderr(),
         &(
@@ -149,15 +152,37 @@ pub(crate) fn verify_directory_checksums(
     Ok(())
 }
 
+/// Fetches the expected checksums from the params mirror, if any, falling back to `url`.
+///
+/// The mirror serves the checksums file under the file name of `url`.
 pub(crate) fn fetch_checksum_file(
-    url: impl IntoUrl,
+    url: &str,
     local_path: impl AsRef<Path>,
 ) -> anyhow::Result<()>
 {
-    let response = reqwest::blocking::get(url)
-        .context("Failed to fetch checksum file")?
-        .text()
-        .context("Failed to read response text")?;
+    let mirrored = ParamsLoader::mirror_url().and_then(
+        |mirror_url| {
+            let file_name = url
+                .rsplit('/')
+                .next()?;
+            let mirrored = fetch_text(
+                &ParamsLoader::file_url(
+                    mirror_url,
+                    file_name,
+                ),
+            );
+            if let Err(err) = &mirrored
+            {
+                warn!("Failed to fetch the checksums from the mirror, falling back to the origin: {err:?}");
+            }
+            mirrored.ok()
+        },
+    );
+    let response = match mirrored
+    {
+        Some(response) => response,
+        None => fetch_text(url)?,
+    };
 
     let mut file = File::create(local_path).context("Failed to create local checksum file")?;
     file.write_all(response.as_bytes())
@@ -165,3 +190,12 @@ pub(crate) fn fetch_checksum_file(
 
     Ok(())
 }
+
+fn fetch_text(url: &str) -> anyhow::Result<String>
+{
+    reqwest::blocking::get(url)
+        .and_then(reqwest::blocking::Response::error_for_status)
+        .context("Failed to fetch checksum file")?
+        .text()
+        .context("Failed to read response text")
+}
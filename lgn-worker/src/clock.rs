@@ -0,0 +1,221 @@
+//! The skew between the clock of the worker and the clocks of the gateways.
+//!
+//! A worker on a host with a skewed clock issues tokens the gateways refuse as issued in the
+//! future, and misjudges the issuance and the deadlines of the tasks. The clock of each gateway is
+//! estimated from the `Date` header of its answers, to the opening of the task stream or to the
+//! websocket handshake, when it or its load balancer sends one: NTP-style, the answer is assumed
+//! dated halfway through the round trip. The tokens are then issued at the time of the gateway,
+//! minus `worker.clock.skew_allowance_secs`, and the tasks are checked against it.
+//!
+//! The `Date` header has a resolution of a second, the estimate is no finer. A gateway which never
+//! sent one is assumed on the clock of the worker.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use metrics::gauge;
+use tonic::metadata::MetadataMap;
+use tracing::debug;
+use tracing::warn;
+
+use crate::config::ClockConfig;
+use crate::unix_now;
+
+static CLOCK: OnceLock<Clock> = OnceLock::new();
+
+struct Clock
+{
+    config: ClockConfig,
+    /// The clock of each gateway minus the clock of the worker, in seconds, by label.
+    offsets: Mutex<BTreeMap<String, i64>>,
+}
+
+pub(crate) fn init(config: &ClockConfig)
+{
+    let _ = CLOCK.set(
+        Clock {
+            config: config.clone(),
+            offsets: Mutex::new(BTreeMap::new()),
+        },
+    );
+}
+
+/// Estimates the clock of `gateway` from the `date` header of its answer to a request sent at
+/// `sent_at`, if any.
+pub(crate) fn observe_metadata(
+    gateway: &str,
+    sent_at: SystemTime,
+    metadata: &MetadataMap,
+)
+{
+    if let Some(date) = metadata
+        .get("date")
+        .and_then(
+            |date| {
+                date.to_str()
+                    .ok()
+            },
+        )
+    {
+        observe(
+            gateway,
+            sent_at,
+            date,
+        );
+    }
+}
+
+/// Estimates the clock of `gateway` from the `date` of its answer to a request sent at `sent_at`.
+pub(crate) fn observe(
+    gateway: &str,
+    sent_at: SystemTime,
+    date: &str,
+)
+{
+    let Some(clock) = CLOCK.get()
+    else
+    {
+        return;
+    };
+    let Ok(date) = httpdate::parse_http_date(date)
+    else
+    {
+        debug!("Ignoring the malformed date `{date}` of gateway `{gateway}`");
+        return;
+    };
+    let skew = offset(
+        sent_at,
+        SystemTime::now(),
+        date,
+    );
+
+    let previous = clock
+        .offsets
+        .lock()
+        .expect("clock lock poisoned")
+        .insert(
+            gateway.to_string(),
+            skew,
+        );
+    gauge!("zkmr_worker_clock_skew_seconds", "gateway" => gateway.to_string()).set(skew as f64);
+    let exceeded = skew.unsigned_abs()
+        > clock
+            .config
+            .skew_warn_secs;
+    gauge!("zkmr_worker_clock_skew_exceeded", "gateway" => gateway.to_string())
+        .set(f64::from(u8::from(exceeded)));
+    if exceeded && previous != Some(skew)
+    {
+        warn!(
+            "The clock of the worker is {}s {} the clock of gateway `{gateway}`, check the time \
+             synchronization of the host",
+            skew.unsigned_abs(),
+            if skew > 0
+            {
+                "behind"
+            }
+            else
+            {
+                "ahead of"
+            },
+        );
+    }
+}
+
+/// The current time of `gateway`, in seconds since the Unix epoch, as estimated.
+pub(crate) fn gateway_now(gateway: &str) -> u64
+{
+    let skew = CLOCK
+        .get()
+        .and_then(
+            |clock| {
+                clock
+                    .offsets
+                    .lock()
+                    .expect("clock lock poisoned")
+                    .get(gateway)
+                    .copied()
+            },
+        )
+        .unwrap_or_default();
+    unix_now().saturating_add_signed(skew)
+}
+
+/// When the tokens presented to `gateway` are issued, in seconds since the Unix epoch.
+pub(crate) fn issued_at(gateway: &str) -> u64
+{
+    let allowance = CLOCK
+        .get()
+        .map_or(
+            0,
+            |clock| {
+                clock
+                    .config
+                    .skew_allowance_secs
+            },
+        );
+    gateway_now(gateway).saturating_sub(allowance)
+}
+
+/// The clock of a server minus the local one, in seconds, from the `date` of its answer to a
+/// request sent at `sent_at` and answered at `received_at`.
+fn offset(
+    sent_at: SystemTime,
+    received_at: SystemTime,
+    date: SystemTime,
+) -> i64
+{
+    let round_trip = received_at
+        .duration_since(sent_at)
+        .unwrap_or_default();
+    let local = sent_at + round_trip / 2;
+    // The date is truncated to the second, the local time is as well to compare them.
+    let secs = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64
+    };
+    secs(date) - secs(local)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn estimates_the_offset_halfway_through_the_round_trip()
+    {
+        let sent_at = UNIX_EPOCH + Duration::from_secs(1_000);
+        let received_at = sent_at + Duration::from_secs(2);
+        assert_eq!(
+            offset(
+                sent_at,
+                received_at,
+                UNIX_EPOCH + Duration::from_secs(1_001),
+            ),
+            0
+        );
+        assert_eq!(
+            offset(
+                sent_at,
+                received_at,
+                UNIX_EPOCH + Duration::from_secs(941),
+            ),
+            -60
+        );
+        assert_eq!(
+            offset(
+                sent_at,
+                sent_at,
+                httpdate::parse_http_date("Thu, 01 Jan 1970 00:17:00 GMT").unwrap(),
+            ),
+            20
+        );
+    }
+}
@@ -0,0 +1,443 @@
+//! The cron expressions of the config, e.g. `"0 3 * * *"`, evaluated in UTC.
+//!
+//! The five fields are the minute, the hour, the day of the month, the month and the day of the
+//! week, `0` or `7` being Sunday. A field is `*`, a number, a range `a-b`, any of them with a step
+//! `/n`, or a comma-separated list of those; the names of the months and days are not supported.
+//! As in cron, a time matches when both days are `*`, or either of those which are not.
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use serde::de::Error;
+use serde::Deserialize;
+use serde::Deserializer;
+
+const MINUTE_SECS: u64 = 60;
+const DAY_SECS: u64 = 24 * 3600;
+
+/// How far the next time of a schedule is searched, over a leap year for `0 0 29 2 *`.
+const MAX_SEARCH_DAYS: u64 = 5 * 366;
+
+/// The bounds of the fields, the day of the week also taking `7` for Sunday.
+const FIELDS: [(
+    &str,
+    u32,
+    u32,
+); 5] = [
+    (
+        "minute",
+        0,
+        59,
+    ),
+    (
+        "hour",
+        0,
+        23,
+    ),
+    (
+        "day of the month",
+        1,
+        31,
+    ),
+    (
+        "month",
+        1,
+        12,
+    ),
+    (
+        "day of the week",
+        0,
+        7,
+    ),
+];
+
+/// A parsed cron expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Schedule
+{
+    expression: String,
+    /// The values matched by each field, as bits.
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the days of the month and of the week are other than `*`.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl FromStr for Schedule
+{
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err>
+    {
+        let fields = expression
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        if fields.len() != FIELDS.len()
+        {
+            return Err(
+                format!(
+                    "`{expression}` has {} fields rather than the 5 of the minute, hour, day of \
+                     the month, month and day of the week",
+                    fields.len()
+                ),
+            );
+        }
+        let mut bits = [0; 5];
+        for (i, (field, (name, min, max))) in fields
+            .iter()
+            .zip(FIELDS)
+            .enumerate()
+        {
+            bits[i] = parse_field(
+                field,
+                min,
+                max,
+            )
+            .map_err(|err| format!("invalid {name} `{field}` in `{expression}`: {err}"))?;
+        }
+        // Sunday is both 0 and 7.
+        let weekdays = (bits[4] | (bits[4] >> 7)) & 0x7F;
+
+        Ok(
+            Self {
+                expression: expression.to_string(),
+                minutes: bits[0],
+                hours: bits[1],
+                days: bits[2],
+                months: bits[3],
+                weekdays,
+                days_restricted: fields[2] != "*",
+                weekdays_restricted: fields[4] != "*",
+            },
+        )
+    }
+}
+
+impl Display for Schedule
+{
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result
+    {
+        f.write_str(&self.expression)
+    }
+}
+
+impl Schedule
+{
+    /// The first time matching the schedule strictly after `after_unix`, if any within a few
+    /// years, e.g. none for `0 0 31 2 *`.
+    pub(crate) fn next(
+        &self,
+        after_unix: u64,
+    ) -> Option<u64>
+    {
+        let first_minute = (after_unix / MINUTE_SECS + 1) * MINUTE_SECS;
+        let first_day = first_minute / DAY_SECS;
+        (first_day..first_day + MAX_SEARCH_DAYS)
+            .filter(|day| self.matches_day(*day))
+            .find_map(
+                |day| {
+                    let start = (day * DAY_SECS).max(first_minute);
+                    (start..(day + 1) * DAY_SECS)
+                        .step_by(MINUTE_SECS as usize)
+                        .find(|time| self.matches_time(*time))
+                },
+            )
+    }
+
+    /// The last time matching the schedule within the `window_secs` up to `at_unix` included.
+    pub(crate) fn last_within(
+        &self,
+        at_unix: u64,
+        window_secs: u64,
+    ) -> Option<u64>
+    {
+        let mut last = None;
+        let mut after = at_unix.checked_sub(window_secs)?;
+        while let Some(time) = self
+            .next(after)
+            .filter(|time| *time <= at_unix)
+        {
+            last = Some(time);
+            after = time;
+        }
+        last
+    }
+
+    fn matches_day(
+        &self,
+        day: u64,
+    ) -> bool
+    {
+        let (_, month, day_of_month) = civil_from_days(day);
+        // 1970-01-01 was a Thursday.
+        let weekday = (day + 4) % 7;
+        let day_matches = bit(
+            self.days,
+            day_of_month,
+        );
+        let weekday_matches = bit(
+            self.weekdays,
+            weekday,
+        );
+        let day_matches = match (
+            self.days_restricted,
+            self.weekdays_restricted,
+        )
+        {
+            (true, true) => day_matches || weekday_matches,
+            (true, false) => day_matches,
+            (false, true) => weekday_matches,
+            (false, false) => true,
+        };
+        day_matches
+            && bit(
+                self.months,
+                month,
+            )
+    }
+
+    fn matches_time(
+        &self,
+        time: u64,
+    ) -> bool
+    {
+        let secs_of_day = time % DAY_SECS;
+        bit(
+            self.hours,
+            secs_of_day / 3600,
+        ) && bit(
+            self.minutes,
+            secs_of_day % 3600 / MINUTE_SECS,
+        )
+    }
+}
+
+/// Deserializes a cron expression.
+pub(crate) fn schedule<'de, D>(deserializer: D) -> Result<Schedule, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(D::Error::custom)
+}
+
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+) -> Result<u64, String>
+{
+    let mut bits = 0;
+    for part in field.split(',')
+    {
+        let (range, step) = match part.split_once('/')
+        {
+            Some((range, step)) =>
+            {
+                (
+                    range,
+                    Some(
+                        step.parse::<u32>()
+                            .ok()
+                            .filter(|step| *step > 0)
+                            .ok_or_else(|| format!("`{step}` is not a positive step"))?,
+                    ),
+                )
+            },
+            None =>
+            {
+                (
+                    part,
+                    None,
+                )
+            },
+        };
+        let (first, last) = match range.split_once('-')
+        {
+            _ if range == "*" =>
+            {
+                (
+                    min,
+                    max,
+                )
+            },
+            Some((first, last)) =>
+            {
+                (
+                    value(
+                        first,
+                        min,
+                        max,
+                    )?,
+                    value(
+                        last,
+                        min,
+                        max,
+                    )?,
+                )
+            },
+            None =>
+            {
+                let first = value(
+                    range,
+                    min,
+                    max,
+                )?;
+                // `5/15` runs from 5 to the end of the range.
+                (
+                    first,
+                    if step.is_some()
+                    {
+                        max
+                    }
+                    else
+                    {
+                        first
+                    },
+                )
+            },
+        };
+        if first > last
+        {
+            return Err(format!("the range `{range}` is reversed"));
+        }
+        for value in (first..=last).step_by(step.unwrap_or(1) as usize)
+        {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn value(
+    text: &str,
+    min: u32,
+    max: u32,
+) -> Result<u32, String>
+{
+    text.parse::<u32>()
+        .ok()
+        .filter(|value| (min..=max).contains(value))
+        .ok_or_else(|| format!("`{text}` is not a number from {min} to {max}"))
+}
+
+fn bit(
+    bits: u64,
+    value: u64,
+) -> bool
+{
+    bits & (1 << value) != 0
+}
+
+/// The year, month and day of the month of the `days` since 1970-01-01, in the proleptic
+/// Gregorian calendar.
+fn civil_from_days(
+    days: u64
+) -> (
+    u64,
+    u64,
+    u64,
+)
+{
+    // From http://howardhinnant.github.io/date_algorithms.html, shifted to start the years in
+    // March, for the leap day to end them.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10
+    {
+        month_from_march + 3
+    }
+    else
+    {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (
+        year,
+        month,
+        day,
+    )
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn finds_the_next_and_last_times_of_a_schedule()
+    {
+        // 2024-02-28T12:00:00Z, a Wednesday.
+        let now = 1_709_121_600;
+        let nightly = "0 3 * * *"
+            .parse::<Schedule>()
+            .unwrap();
+        assert_eq!(
+            nightly.next(now),
+            Some(now + 15 * 3600)
+        );
+        assert_eq!(
+            nightly.last_within(
+                now,
+                12 * 3600
+            ),
+            Some(now - 9 * 3600)
+        );
+        assert_eq!(
+            nightly.last_within(
+                now,
+                3600
+            ),
+            None
+        );
+
+        // The leap day, then a Sunday given as 7.
+        let leap_day = "30 1 29 2 *"
+            .parse::<Schedule>()
+            .unwrap();
+        assert_eq!(
+            leap_day.next(now),
+            Some(now + 13 * 3600 + 30 * 60)
+        );
+        let sundays = "*/20 0 * * 7"
+            .parse::<Schedule>()
+            .unwrap();
+        assert_eq!(
+            sundays.next(now),
+            Some(now + 3 * DAY_SECS + 12 * 3600)
+        );
+
+        assert!(
+            "0 3 * *"
+                .parse::<Schedule>()
+                .is_err()
+        );
+        assert!(
+            "0 24 * * *"
+                .parse::<Schedule>()
+                .is_err()
+        );
+        assert!(
+            "0 3 31 2 *"
+                .parse::<Schedule>()
+                .unwrap()
+                .next(now)
+                .is_none()
+        );
+    }
+}
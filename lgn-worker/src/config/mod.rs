@@ -1,21 +1,673 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::path::PathBuf;
+
 use config::FileFormat;
+use ipnet::IpNet;
 use lazy_static_include::*;
 use lgn_messages::types::TaskDifficulty;
+use lgn_messages::TableId;
+use lgn_provers::dummy_profile;
+use lgn_provers::dummy_profile::Distribution;
+use lgn_provers::dummy_profile::DummyProfile;
 use redact::Secret;
+use serde::Deserializer;
 use serde_derive::Deserialize;
 use tracing::debug;
 
+use crate::config::cron::Schedule;
+
+pub(crate) mod cron;
+mod units;
+mod urls;
+
 lazy_static_include_str! {
     DEFAULT_CONFIG => "src/config/default.toml",
 }
 
+/// The checksum file of `default.toml`, moved under the data directory if one is configured.
+const DEFAULT_CHECKSUM_PATH: &str = "/tmp/expected_checksums.txt";
+
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub(crate) struct Config
 {
     pub(crate) worker: WorkerConfig,
-    pub(crate) avs: AvsConfig,
+    /// The gateways to serve, either a single `[avs]` table or several `[[avs]]` ones.
+    #[serde(deserialize_with = "one_or_many")]
+    pub(crate) avs: Vec<AvsConfig>,
     pub(crate) public_params: PublicParamsConfig,
     pub(crate) prometheus: PrometheusConfig,
+    pub(crate) retention: RetentionConfig,
+    /// Only used by workers built with the dummy provers.
+    #[serde(default)]
+    pub(crate) dummy: DummyConfig,
+    /// If set, tasks are read from a local directory instead of the gateways.
+    #[serde(default)]
+    pub(crate) offline: Option<OfflineConfig>,
+    #[serde(default)]
+    pub(crate) debug: DebugConfig,
+    #[serde(default)]
+    pub(crate) tenants: TenantsConfig,
+    #[serde(default)]
+    pub(crate) tables: TablesConfig,
+    #[serde(default)]
+    pub(crate) journal: JournalConfig,
+    /// If set, the admin endpoints are served on this port, see `admin`.
+    #[serde(default)]
+    pub(crate) admin: Option<AdminConfig>,
+    /// If set, the task events are published to a sink of the operator.
+    #[serde(default)]
+    pub(crate) events: Option<EventsConfig>,
+    /// If set, the replies are notarized by a third party.
+    #[serde(default)]
+    pub(crate) notary: Option<NotaryConfig>,
+    /// If set, the final groth16 proofs are handed to an on-chain submission helper.
+    #[cfg(feature = "prover-groth16")]
+    #[serde(default)]
+    pub(crate) submission: Option<SubmissionConfig>,
+    /// If set, the tasks are patched at admission by the rules of a local file, during incidents.
+    #[serde(default)]
+    pub(crate) task_patches: Option<TaskPatchesConfig>,
+    /// If set, the tasks are proven again with a second params set, to A/B validate it.
+    #[serde(default)]
+    pub(crate) shadow_params: Option<ShadowParamsConfig>,
+    /// If set, the worker drains during recurring windows, e.g. for the patching of its host.
+    #[serde(default)]
+    pub(crate) maintenance: Option<MaintenanceConfig>,
+    /// If set, the metrics and logs are labelled with the region, zone and instance of the host.
+    #[serde(default)]
+    pub(crate) host_metadata: Option<HostMetadataConfig>,
+    /// If set, the worker hands its state off to the next version during an upgrade.
+    #[serde(default)]
+    pub(crate) handoff: Option<HandoffConfig>,
+    #[serde(default)]
+    pub(crate) network: NetworkConfig,
+    /// If set, every file the worker writes goes under this directory, e.g. for containers with
+    /// a read-only root filesystem.
+    #[serde(default)]
+    pub(crate) data_dir: Option<String>,
+}
+
+/// How the worker reaches the gateways.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct NetworkConfig
+{
+    /// If set, the gRPC channels are tunnelled through this HTTP proxy, see `grpc_proxy`.
+    #[serde(default)]
+    pub(crate) grpc_proxy: Option<GrpcProxyConfig>,
+}
+
+/// An HTTP proxy opening tunnels with `CONNECT`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct GrpcProxyConfig
+{
+    /// `http://host:port` of the proxy.
+    pub(crate) url: String,
+    /// The credentials of the proxy, sent as basic `Proxy-Authorization`.
+    #[serde(default)]
+    pub(crate) username: Option<String>,
+    #[serde(default)]
+    pub(crate) password: Option<Secret<String>>,
+}
+
+impl GrpcProxyConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            self.url
+                .starts_with("http://"),
+            "gRPC proxy URL must be `http://host:port`"
+        );
+        assert!(
+            self.username
+                .is_some()
+                || self
+                    .password
+                    .is_none(),
+            "gRPC proxy password requires a username"
+        );
+    }
+}
+
+/// Publication of the task events, for the analytics of the operator.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct EventsConfig
+{
+    pub(crate) kind: EventSinkKind,
+    /// `host:port` of the NATS server, or URL of the webhook.
+    pub(crate) url: String,
+    /// The NATS subject of the events.
+    #[serde(default = "default_events_subject")]
+    pub(crate) subject: String,
+    /// How many events can wait for the sink, the next ones being dropped.
+    #[serde(default = "default_events_queue_size")]
+    pub(crate) queue_size: usize,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum EventSinkKind
+{
+    Nats,
+    Webhook,
+}
+
+fn default_events_subject() -> String
+{
+    "lgn.worker.tasks".to_string()
+}
+
+fn default_events_queue_size() -> usize
+{
+    1024
+}
+
+impl EventsConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            !self
+                .url
+                .is_empty(),
+            "Events sink URL is required"
+        );
+        assert!(
+            self.queue_size > 0,
+            "Events queue size must be positive"
+        );
+    }
+}
+
+/// A second params set, the tasks are proven again with to compare the proofs, see `shadow`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ShadowParamsConfig
+{
+    /// Where the second params set is stored, downloaded if missing.
+    pub(crate) dir: String,
+    /// Where the second params set is downloaded from, `public_params.url` if unset.
+    #[serde(default)]
+    pub(crate) url: Option<String>,
+    /// The checksums of the second params set, `public_params.checksum_url` if unset.
+    #[serde(default)]
+    pub(crate) checksum_url: Option<String>,
+    #[serde(default = "default_shadow_checksum_path")]
+    pub(crate) checksum_expected_local_path: String,
+    /// How many tasks can wait for the second proof, the next ones not being compared.
+    #[serde(default = "default_shadow_queue_size")]
+    pub(crate) queue_size: usize,
+}
+
+fn default_shadow_checksum_path() -> String
+{
+    "/tmp/shadow_params/expected_checksums.txt".to_string()
+}
+
+fn default_shadow_queue_size() -> usize
+{
+    4
+}
+
+impl ShadowParamsConfig
+{
+    pub fn validate(
+        &self,
+        public_params: &PublicParamsConfig,
+    )
+    {
+        assert!(
+            !self
+                .dir
+                .is_empty(),
+            "Shadow params dir is required"
+        );
+        assert_ne!(
+            self.dir, public_params.dir,
+            "Shadow params dir must differ from the public params one"
+        );
+        assert_ne!(
+            self.checksum_expected_local_path, public_params.checksum_expected_local_path,
+            "Shadow params checksum path must differ from the public params one"
+        );
+        assert!(
+            self.queue_size > 0,
+            "Shadow params queue size must be positive"
+        );
+    }
+}
+
+/// Notarization of the replies, as third-party evidence of when the proofs were produced.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct NotaryConfig
+{
+    pub(crate) kind: NotaryKind,
+    /// URL of the timestamping authority or of the anchoring endpoint.
+    pub(crate) url: String,
+    /// Where the receipts are written, one file per task.
+    #[serde(default = "default_notary_receipts_dir")]
+    pub(crate) receipts_dir: String,
+    /// How many replies can wait for the notary, the next ones not being notarized.
+    #[serde(default = "default_notary_queue_size")]
+    pub(crate) queue_size: usize,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum NotaryKind
+{
+    /// An RFC 3161 timestamping authority, answering a DER `TimeStampResp`.
+    Rfc3161,
+    /// An endpoint anchoring the digests, e.g. on a blockchain, answering a JSON receipt.
+    Anchor,
+}
+
+fn default_notary_receipts_dir() -> String
+{
+    "./notary_receipts".to_string()
+}
+
+fn default_notary_queue_size() -> usize
+{
+    256
+}
+
+impl NotaryConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            !self
+                .url
+                .is_empty(),
+            "Notary URL is required"
+        );
+        assert!(
+            self.queue_size > 0,
+            "Notary queue size must be positive"
+        );
+    }
+}
+
+/// The hand-off of the final groth16 proofs to an on-chain submission helper.
+#[cfg(feature = "prover-groth16")]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct SubmissionConfig
+{
+    /// The endpoint the payloads are posted to, as JSON.
+    #[serde(default)]
+    pub(crate) url: Option<String>,
+    /// Where the payloads are written, one file per task, ready to send.
+    #[serde(default)]
+    pub(crate) payload_dir: Option<String>,
+    /// How many times a payload is posted before giving up on it.
+    #[serde(default = "default_submission_max_attempts")]
+    pub(crate) max_attempts: u32,
+    /// Where each submission is recorded, one JSON object per line.
+    #[serde(default = "default_submission_audit_file")]
+    pub(crate) audit_file: String,
+    /// How many proofs can wait for their submission, the next ones not being submitted.
+    #[serde(default = "default_submission_queue_size")]
+    pub(crate) queue_size: usize,
+}
+
+#[cfg(feature = "prover-groth16")]
+fn default_submission_max_attempts() -> u32
+{
+    5
+}
+
+#[cfg(feature = "prover-groth16")]
+fn default_submission_audit_file() -> String
+{
+    "./submissions_audit.jsonl".to_string()
+}
+
+#[cfg(feature = "prover-groth16")]
+fn default_submission_queue_size() -> usize
+{
+    64
+}
+
+#[cfg(feature = "prover-groth16")]
+impl SubmissionConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            self.url
+                .is_some()
+                || self
+                    .payload_dir
+                    .is_some(),
+            "Submission URL or payload directory is required"
+        );
+        assert!(
+            self.max_attempts > 0,
+            "Submission attempts must be positive"
+        );
+        assert!(
+            self.queue_size > 0,
+            "Submission queue size must be positive"
+        );
+    }
+}
+
+/// Emergency patches of the incoming tasks.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct TaskPatchesConfig
+{
+    /// The JSON file of the rules, read on startup.
+    pub(crate) rules_file: String,
+    /// Where each patch applied is recorded, one JSON object per line.
+    #[serde(default = "default_task_patches_audit_file")]
+    pub(crate) audit_file: String,
+}
+
+fn default_task_patches_audit_file() -> String
+{
+    "./task_patches_audit.jsonl".to_string()
+}
+
+impl TaskPatchesConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            !self
+                .rules_file
+                .is_empty(),
+            "Task patches rules file is required"
+        );
+    }
+}
+
+/// The tenant label of the task metrics.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct TenantsConfig
+{
+    /// The tenants labelled by name, the others being labelled by a bucket of their hash.
+    pub(crate) allowlist: Vec<String>,
+    /// The number of buckets of the tenants not in the allowlist.
+    pub(crate) hash_buckets: u32,
+}
+
+/// The journal of the replies sent, see `journal`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct JournalConfig
+{
+    /// The entries kept at most, the oldest being dropped.
+    pub(crate) max_entries: usize,
+    /// How long an entry is kept.
+    #[serde(deserialize_with = "units::secs")]
+    pub(crate) max_age_secs: u64,
+}
+
+impl Default for JournalConfig
+{
+    fn default() -> Self
+    {
+        Self {
+            max_entries: 100_000,
+            max_age_secs: 24 * 3600,
+        }
+    }
+}
+
+/// The progress of the tables, see `tables`.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub(crate) struct TablesConfig
+{
+    /// The tables whose highest block proven is exported as a gauge.
+    pub(crate) allowlist: Vec<TableId>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct AdminConfig
+{
+    pub(crate) port: u16,
+    /// The address the endpoints are served on, the loopback one by default.
+    #[serde(default = "default_admin_bind")]
+    pub(crate) bind: IpAddr,
+    /// If not empty, only the clients in these networks are answered, e.g. `10.0.0.0/8`.
+    #[serde(default)]
+    pub(crate) allowlist: Vec<IpNet>,
+    /// If set, the endpoints are served over TLS, to the clients with a certificate of
+    /// `client_ca`.
+    #[serde(default)]
+    pub(crate) tls: Option<AdminTlsConfig>,
+}
+
+fn default_admin_bind() -> IpAddr
+{
+    IpAddr::from(
+        [
+            127,
+            0,
+            0,
+            1,
+        ],
+    )
+}
+
+/// The discovery of the region, zone and instance of the host, see `host_metadata`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct HostMetadataConfig
+{
+    /// Where the metadata is looked up, in order, each source filling the fields still missing.
+    pub(crate) sources: Vec<HostMetadataSource>,
+    /// How long a metadata endpoint is waited for.
+    #[serde(
+        default = "default_host_metadata_timeout_ms",
+        deserialize_with = "units::millis"
+    )]
+    pub(crate) timeout_ms: u64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum HostMetadataSource
+{
+    /// The `LGN_HOST_REGION`, `LGN_HOST_ZONE` and `LGN_HOST_INSTANCE_ID` variables.
+    Env,
+    /// The instance metadata service of AWS.
+    Ec2,
+    /// The metadata server of Google Cloud.
+    Gce,
+}
+
+fn default_host_metadata_timeout_ms() -> u64
+{
+    500
+}
+
+impl HostMetadataConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            !self
+                .sources
+                .is_empty(),
+            "At least one host metadata source is required"
+        );
+    }
+}
+
+/// The warm handoff of the worker to its next version during an upgrade, see `handoff`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct HandoffConfig
+{
+    /// The unix socket the running worker listens on, for the next version to connect to.
+    pub(crate) socket: String,
+    /// How long the next version waits for the running worker to complete its tasks.
+    #[serde(
+        default = "default_handoff_drain_timeout_secs",
+        deserialize_with = "units::secs"
+    )]
+    pub(crate) drain_timeout_secs: u64,
+}
+
+fn default_handoff_drain_timeout_secs() -> u64
+{
+    30 * 60
+}
+
+/// The mutual TLS of the admin endpoints, the files being PEM encoded.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct AdminTlsConfig
+{
+    /// The certificate chain of the worker.
+    pub(crate) cert: PathBuf,
+    /// The private key of the worker.
+    pub(crate) key: PathBuf,
+    /// The CA certificates the clients must present a certificate of.
+    pub(crate) client_ca: PathBuf,
+}
+
+impl Default for TenantsConfig
+{
+    fn default() -> Self
+    {
+        Self {
+            allowlist: vec![],
+            hash_buckets: 16,
+        }
+    }
+}
+
+impl TenantsConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            self.hash_buckets > 0,
+            "At least one tenant hash bucket is required"
+        );
+    }
+}
+
+/// Capture of the inputs and outputs of selected tasks, to debug them offline.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct DebugConfig
+{
+    /// Regular expressions of the task ids to capture.
+    pub(crate) capture_task_ids: Vec<String>,
+    /// A file of additional patterns, one per line, re-read while the worker runs.
+    pub(crate) capture_patterns_file: Option<String>,
+    /// Where the captures are written, one directory per task.
+    pub(crate) capture_dir: String,
+    /// The debug logs of the proving hot paths kept per minute and event kind, `0` for all of
+    /// them; changed at runtime on the admin endpoint `/log-sampling`.
+    pub(crate) sampled_logs_per_minute: u32,
+}
+
+impl Default for DebugConfig
+{
+    fn default() -> Self
+    {
+        Self {
+            capture_task_ids: vec![],
+            capture_patterns_file: None,
+            capture_dir: "./zkmr_captures".to_string(),
+            sampled_logs_per_minute: lgn_provers::log_sampling::DEFAULT_MAX_PER_MINUTE,
+        }
+    }
+}
+
+impl DebugConfig
+{
+    pub fn validate(&self)
+    {
+        for pattern in &self.capture_task_ids
+        {
+            assert!(
+                regex::Regex::new(pattern).is_ok(),
+                "Invalid capture pattern `{pattern}`"
+            );
+        }
+    }
+}
+
+/// The recurring windows the worker stops taking tasks during.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct MaintenanceConfig
+{
+    /// When the windows start, a cron expression in UTC, e.g. `"0 3 * * *"`.
+    #[serde(deserialize_with = "cron::schedule")]
+    pub(crate) cron: Schedule,
+    /// How long the windows last, in seconds.
+    #[serde(deserialize_with = "units::secs")]
+    pub(crate) duration: u64,
+    /// If set, the worker also disconnects from the gRPC gateways during the windows.
+    #[serde(default)]
+    pub(crate) disconnect: bool,
+}
+
+impl MaintenanceConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            self.duration > 0,
+            "The maintenance windows must last"
+        );
+        assert!(
+            self.cron
+                .next(0)
+                .is_some(),
+            "The maintenance cron `{}` never matches",
+            self.cron
+        );
+    }
+}
+
+/// Air-gapped proving of the task files dropped in a directory.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct OfflineConfig
+{
+    /// Where the task envelopes are read from, as `.json` files.
+    pub(crate) input_dir: String,
+    /// Where the replies and the ledger of the processed tasks are written.
+    pub(crate) output_dir: String,
+    #[serde(
+        default = "default_poll_interval_secs",
+        deserialize_with = "units::secs"
+    )]
+    pub(crate) poll_interval_secs: u64,
+}
+
+fn default_poll_interval_secs() -> u64
+{
+    5
+}
+
+impl OfflineConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            !self
+                .input_dir
+                .is_empty(),
+            "Offline input directory is required"
+        );
+        assert!(
+            !self
+                .output_dir
+                .is_empty(),
+            "Offline output directory is required"
+        );
+        assert_ne!(
+            self.input_dir, self.output_dir,
+            "Offline input and output directories must differ"
+        );
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
@@ -23,151 +675,739 @@ pub(crate) struct PublicParamsConfig
 {
     pub(crate) url: String,
     pub(crate) checksum_url: String,
+    /// Base URL of an operator-local `lgn-params-mirror`, tried before `url` and `checksum_url`.
+    #[serde(default)]
+    pub(crate) mirror_url: Option<String>,
     pub(crate) checksum_expected_local_path: String,
     pub(crate) skip_checksum: bool,
     pub(crate) dir: String,
     /// If set to true, the parameters will not be written to disk, ever.
     pub(crate) skip_store: bool,
+    /// How many params files are hashed at once when verifying them at startup.
+    #[serde(default = "default_verify_parallelism")]
+    pub(crate) verify_parallelism: usize,
+    /// If set, the worker stops when the params are not downloaded within this many seconds of
+    /// its start, keeping what it downloaded for the next start to resume.
+    #[serde(
+        default,
+        deserialize_with = "units::option_secs"
+    )]
+    pub(crate) download_deadline: Option<u64>,
+    /// How many params files are downloaded at once, from all the hosts.
+    #[serde(default = "default_max_concurrent_downloads")]
+    pub(crate) max_concurrent_downloads: usize,
+    #[cfg(feature = "prover-preprocessing")]
     pub(crate) preprocessing_params: PreprocessingParams,
+    #[cfg(feature = "prover-query")]
     pub(crate) query_params: QueryParams,
+    #[cfg(feature = "prover-groth16")]
     pub(crate) groth16_assets: Groth16Assets,
+    #[serde(default)]
+    pub(crate) audit: ParamsAuditConfig,
+}
+
+fn default_verify_parallelism() -> usize
+{
+    4
+}
+
+fn default_max_concurrent_downloads() -> usize
+{
+    2
+}
+
+/// Periodic re-verification of the params on disk.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct ParamsAuditConfig
+{
+    /// Delay between two verifications, the audit is disabled if unset.
+    #[serde(deserialize_with = "units::option_secs")]
+    pub(crate) interval_secs: Option<u64>,
+    /// Read rate limit, so that the audit does not slow down proving.
+    #[serde(deserialize_with = "units::bytes")]
+    pub(crate) max_bytes_per_sec: u64,
+    /// If set, tasks are refused once a corrupted file is found, until the worker is restarted.
+    pub(crate) drain_on_mismatch: bool,
+}
+
+impl Default for ParamsAuditConfig
+{
+    fn default() -> Self
+    {
+        Self {
+            interval_secs: None,
+            max_bytes_per_sec: 50 * 1024 * 1024,
+            drain_on_mismatch: false,
+        }
+    }
+}
+
+impl PublicParamsConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            !self
+                .url
+                .is_empty(),
+            "URL is required"
+        );
+        assert!(
+            !self
+                .checksum_url
+                .is_empty(),
+            "Checksum URL is required"
+        );
+        assert!(
+            !self
+                .checksum_expected_local_path
+                .is_empty(),
+            "Checksum local path for expected checksum file is required"
+        );
+        assert!(
+            !self
+                .dir
+                .is_empty(),
+            "Directory is required"
+        );
+        assert!(
+            self.verify_parallelism > 0,
+            "Params verification parallelism must be positive"
+        );
+        assert!(
+            self.download_deadline != Some(0),
+            "Params download deadline must be positive"
+        );
+        assert!(
+            self.max_concurrent_downloads > 0,
+            "Params concurrent downloads must be positive"
+        );
+        #[cfg(feature = "prover-preprocessing")]
+        self.preprocessing_params
+            .validate();
+        #[cfg(feature = "prover-query")]
+        self.query_params
+            .validate();
+        #[cfg(feature = "prover-groth16")]
+        self.groth16_assets
+            .validate();
+    }
+}
+
+#[cfg(feature = "prover-preprocessing")]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct PreprocessingParams
+{
+    pub(crate) file: String,
+}
+
+#[cfg(feature = "prover-preprocessing")]
+impl PreprocessingParams
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            !self
+                .file
+                .is_empty(),
+            "Preprocessing file is required"
+        );
+    }
+}
+
+#[cfg(feature = "prover-query")]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct QueryParams
+{
+    pub(crate) file: String,
+    /// Whether `file` is pruned for the tables of a dedicated deployment, the tasks outside of the
+    /// tables and limits of its `<file>.pruned.json` being refused.
+    #[serde(default)]
+    pub(crate) pruned: bool,
+}
+
+#[cfg(feature = "prover-query")]
+impl QueryParams
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            !self
+                .file
+                .is_empty(),
+            "Query2 file is required"
+        );
+    }
+}
+
+#[cfg(feature = "prover-groth16")]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Groth16Assets
+{
+    pub(crate) circuit_file: String,
+    pub(crate) r1cs_file: String,
+    pub(crate) pk_file: String,
+}
+
+#[cfg(feature = "prover-groth16")]
+impl Groth16Assets
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            !self
+                .circuit_file
+                .is_empty(),
+            "Circuit URL is required"
+        );
+        assert!(
+            !self
+                .r1cs_file
+                .is_empty(),
+            "R1CS URL is required"
+        );
+        assert!(
+            !self
+                .pk_file
+                .is_empty(),
+            "PK URL is required"
+        );
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct WorkerConfig
+{
+    pub(crate) instance_type: TaskDifficulty,
+    /// Path or URL of the JSON table schemas used to lint query tasks before proving them.
+    #[cfg(feature = "prover-query")]
+    #[serde(default)]
+    pub(crate) query_schema: Option<String>,
+    /// Size of the cache of the child proofs referenced by the following tasks of a query, 0 to
+    /// disable it.
+    #[cfg(feature = "prover-query")]
+    #[serde(
+        default = "default_child_proof_cache_mb",
+        deserialize_with = "units::mebibytes"
+    )]
+    pub(crate) child_proof_cache_mb: usize,
+    #[serde(default)]
+    pub(crate) quarantine: QuarantineConfig,
+    #[serde(default)]
+    pub(crate) threads: ThreadsConfig,
+    /// If set, the worker proves with this percentage of the cores, trading latency for lower CPU
+    /// bursts, e.g. to share its host; `true` is half of them.
+    #[serde(
+        default,
+        deserialize_with = "eco_mode"
+    )]
+    pub(crate) eco_mode: Option<u8>,
+    /// How many times a task failing for a transient reason, e.g. an interrupted read, is retried
+    /// locally before the failure is reported.
+    #[serde(default = "default_transient_retries")]
+    pub(crate) transient_retries: u32,
+    /// If set, the reply proofs are keyed by the blake3 hash of their bytes, their semantic key
+    /// being sent as metadata.
+    #[serde(default)]
+    pub(crate) content_addressed_keys: bool,
+    /// If set, the tasks held locally are proven by earliest deadline, then highest priority,
+    /// rather than in arrival order.
+    #[serde(default)]
+    pub(crate) deadline_ordering: bool,
+    /// If set, the error replies carry the environment fingerprint of the worker, so that the
+    /// failures of a task on some machines only can be told apart.
+    #[serde(default)]
+    pub(crate) fingerprint_failures: bool,
+    /// If set, the worker runs one class below `instance_type` after an OOM kill or sustained
+    /// memory pressure, rather than crash-looping.
+    #[serde(default)]
+    pub(crate) downgrade: Option<DowngradeConfig>,
+    /// If set, the task messages issued outside of the window, or received twice within it, are
+    /// dropped as replayed.
+    #[serde(default)]
+    pub(crate) replay_window: Option<ReplayWindowConfig>,
+    #[serde(default)]
+    pub(crate) clock: ClockConfig,
+    /// If set, the tasks of a query over its share of the worker are refused while other queries
+    /// wait.
+    #[serde(default)]
+    pub(crate) fairness: Option<FairnessConfig>,
+    /// If set, the intermediate proofs of the index tasks are saved so that retries resume.
+    #[cfg(feature = "prover-preprocessing")]
+    #[serde(default)]
+    pub(crate) index_checkpoints: Option<IndexCheckpointsConfig>,
+    /// Where the session id and the resumption tokens of the gateways are kept across restarts.
+    #[serde(default = "default_session_file")]
+    pub(crate) session_file: String,
+}
+
+#[cfg(feature = "prover-query")]
+fn default_child_proof_cache_mb() -> usize
+{
+    64
+}
+
+/// The share of the cores used in eco mode by `eco_mode = true`, in percent.
+const DEFAULT_ECO_MODE_PERCENT: u8 = 50;
+
+/// Eco mode, either `true`, `false` or the percentage of the cores to prove with.
+fn eco_mode<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum EcoMode
+    {
+        Enabled(bool),
+        Percent(u8),
+    }
+
+    match <EcoMode as serde::Deserialize>::deserialize(deserializer)?
+    {
+        EcoMode::Enabled(true) => Ok(Some(DEFAULT_ECO_MODE_PERCENT)),
+        EcoMode::Enabled(false) => Ok(None),
+        EcoMode::Percent(percent) => Ok(Some(percent)),
+    }
+}
+
+fn default_transient_retries() -> u32
+{
+    1
+}
+
+fn default_session_file() -> String
+{
+    "./worker_session.json".to_string()
+}
+
+/// Downgrade of the advertised class under memory pressure.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct DowngradeConfig
+{
+    /// Where the downgrade is kept across restarts.
+    pub(crate) state_file: String,
+    /// Fraction of the memory limit above which the memory is under pressure.
+    pub(crate) high_watermark: f64,
+    /// Fraction of the memory limit below which a downgraded worker is not under pressure
+    /// anymore.
+    pub(crate) low_watermark: f64,
+    /// How long the memory must stay above the high watermark for the worker to downgrade.
+    #[serde(deserialize_with = "units::secs")]
+    pub(crate) sustain_secs: u64,
+    /// How long the memory must stay below the low watermark for the worker to go one class up
+    /// again on its next start.
+    #[serde(deserialize_with = "units::secs")]
+    pub(crate) recover_after_secs: u64,
+}
+
+impl Default for DowngradeConfig
+{
+    fn default() -> Self
+    {
+        Self {
+            state_file: "./downgrade.json".to_string(),
+            high_watermark: 0.9,
+            low_watermark: 0.7,
+            sustain_secs: 60,
+            recover_after_secs: 6 * 3600,
+        }
+    }
+}
+
+impl DowngradeConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            0.0 < self.low_watermark
+                && self.low_watermark < self.high_watermark
+                && self.high_watermark <= 1.0,
+            "Downgrade watermarks must satisfy 0 < low_watermark < high_watermark <= 1"
+        );
+    }
+}
+
+/// Protection against the replayed task messages.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub(crate) struct ReplayWindowConfig
+{
+    /// How far from now the messages may have been issued, and how long they are remembered.
+    #[serde(deserialize_with = "units::secs")]
+    pub(crate) window_secs: u64,
+    /// How many messages are remembered at most, the new ones being dropped past it until the
+    /// oldest expire.
+    pub(crate) max_tracked: usize,
+    /// If set, the messages without an issuance time are dropped rather than accepted unchecked.
+    pub(crate) require_issued_at: bool,
 }
 
-impl PublicParamsConfig
+impl Default for ReplayWindowConfig
+{
+    fn default() -> Self
+    {
+        Self {
+            window_secs: 600,
+            max_tracked: 100_000,
+            require_issued_at: false,
+        }
+    }
+}
+
+impl ReplayWindowConfig
 {
     pub fn validate(&self)
     {
         assert!(
-            !self
-                .url
-                .is_empty(),
-            "URL is required"
-        );
-        assert!(
-            !self
-                .checksum_url
-                .is_empty(),
-            "Checksum URL is required"
-        );
-        assert!(
-            !self
-                .checksum_expected_local_path
-                .is_empty(),
-            "Checksum local path for expected checksum file is required"
+            self.window_secs > 0,
+            "Replay window must be positive"
         );
         assert!(
-            !self
-                .dir
-                .is_empty(),
-            "Directory is required"
+            self.max_tracked > 0,
+            "Replay window must track at least one message"
         );
-        self.preprocessing_params
-            .validate();
-        self.query_params
-            .validate();
-        self.groth16_assets
-            .validate();
     }
 }
 
+/// The share of the worker each query gets when several compete for it.
 #[derive(Deserialize, Debug, Clone, PartialEq)]
-pub(crate) struct PreprocessingParams
+#[serde(default)]
+pub(crate) struct FairnessConfig
 {
-    pub(crate) file: String,
+    /// How many tasks of a query are proven within the window before its next ones are refused,
+    /// if another query is under the cap.
+    pub(crate) max_tasks_per_query: usize,
+    /// How long the tasks of a query count against its cap.
+    #[serde(deserialize_with = "units::secs")]
+    pub(crate) window_secs: u64,
 }
 
-impl PreprocessingParams
+impl Default for FairnessConfig
+{
+    fn default() -> Self
+    {
+        Self {
+            max_tasks_per_query: 20,
+            window_secs: 600,
+        }
+    }
+}
+
+impl FairnessConfig
 {
     pub fn validate(&self)
     {
         assert!(
-            !self
-                .file
-                .is_empty(),
-            "Preprocessing file is required"
+            self.max_tasks_per_query > 0,
+            "Fairness cap must be positive"
+        );
+        assert!(
+            self.window_secs > 0,
+            "Fairness window must be positive"
         );
     }
 }
 
+/// The handling of the skew between the clocks of the worker and of the gateways.
 #[derive(Deserialize, Debug, Clone, PartialEq)]
-pub(crate) struct QueryParams
+#[serde(default)]
+pub(crate) struct ClockConfig
 {
-    pub(crate) file: String,
+    /// How far in the past the tokens are issued, for a gateway with a clock slightly behind not
+    /// to refuse them as issued in the future.
+    #[serde(deserialize_with = "units::secs")]
+    pub(crate) skew_allowance_secs: u64,
+    /// The skew with a gateway above which the worker warns.
+    #[serde(deserialize_with = "units::secs")]
+    pub(crate) skew_warn_secs: u64,
 }
 
-impl QueryParams
+impl Default for ClockConfig
 {
-    pub fn validate(&self)
+    fn default() -> Self
     {
-        assert!(
-            !self
-                .file
-                .is_empty(),
-            "Query2 file is required"
-        );
+        Self {
+            skew_allowance_secs: 5,
+            skew_warn_secs: 30,
+        }
     }
 }
 
+/// Checkpoints of the intermediate proofs of the index tasks.
+#[cfg(feature = "prover-preprocessing")]
 #[derive(Deserialize, Debug, Clone, PartialEq)]
-pub(crate) struct Groth16Assets
+pub(crate) struct IndexCheckpointsConfig
 {
-    pub(crate) circuit_file: String,
-    pub(crate) r1cs_file: String,
-    pub(crate) pk_file: String,
+    /// The scratch directory of the checkpoints.
+    pub(crate) dir: String,
+    /// Age after which a checkpoint is discarded, its task not being expected to be retried.
+    #[serde(
+        default = "default_index_checkpoints_ttl_secs",
+        deserialize_with = "units::secs"
+    )]
+    pub(crate) ttl_secs: u64,
 }
 
-impl Groth16Assets
+#[cfg(feature = "prover-preprocessing")]
+fn default_index_checkpoints_ttl_secs() -> u64
+{
+    3600
+}
+
+#[cfg(feature = "prover-preprocessing")]
+impl IndexCheckpointsConfig
 {
     pub fn validate(&self)
     {
         assert!(
             !self
-                .circuit_file
-                .is_empty(),
-            "Circuit URL is required"
-        );
-        assert!(
-            !self
-                .r1cs_file
-                .is_empty(),
-            "R1CS URL is required"
-        );
-        assert!(
-            !self
-                .pk_file
+                .dir
                 .is_empty(),
-            "PK URL is required"
+            "Index checkpoints directory is required"
         );
     }
 }
 
+/// Size of the proving thread pool of each task kind.
+///
+/// The small proofs default to a pool sized after the instance type, the other kinds to the global
+/// pool.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub(crate) struct ThreadsConfig
+{
+    pub(crate) extraction: Option<usize>,
+    pub(crate) cell: Option<usize>,
+    pub(crate) row: Option<usize>,
+    pub(crate) index: Option<usize>,
+    pub(crate) ivc: Option<usize>,
+    pub(crate) query: Option<usize>,
+    pub(crate) groth16: Option<usize>,
+    /// If set, the proving threads are limited to the CPU quota of the cgroup of the worker.
+    pub(crate) respect_cpu_quota: bool,
+}
+
+/// Refusal of the tasks of a prover type after repeated failures, e.g. because of bad params.
 #[derive(Deserialize, Debug, Clone, PartialEq)]
-pub(crate) struct WorkerConfig
+#[serde(default)]
+pub(crate) struct QuarantineConfig
 {
-    pub(crate) instance_type: TaskDifficulty,
+    /// Number of consecutive failures quarantining a prover type, disabled if unset.
+    pub(crate) max_consecutive_failures: Option<u32>,
+    /// Delay after which a quarantined prover type accepts tasks again.
+    #[serde(deserialize_with = "units::secs")]
+    pub(crate) cooldown_secs: u64,
+    /// If set, the quarantine is only lifted once the params were verified again.
+    pub(crate) reverify_params: bool,
+}
+
+impl Default for QuarantineConfig
+{
+    fn default() -> Self
+    {
+        Self {
+            max_consecutive_failures: None,
+            cooldown_secs: 600,
+            reverify_params: false,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub(crate) struct AvsConfig
 {
+    /// Name of the gateway in logs and metrics labels, defaults to the issuer.
+    #[serde(default)]
+    pub(crate) name: Option<String>,
     pub(crate) gateway_url: String,
     pub(crate) gateway_grpc_url: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "units::option_mebibytes"
+    )]
     pub(crate) max_grpc_message_size_mb: Option<usize>,
     pub(crate) issuer: String,
     pub(crate) worker_id: String,
     pub(crate) lagr_keystore: Option<String>,
     pub(crate) lagr_pwd: Option<Secret<String>>,
     pub(crate) lagr_private_key: Option<Secret<String>>,
+    /// If set to true, replies are signed with the Lagrange key for non-repudiation.
+    #[serde(default)]
+    pub(crate) sign_replies: bool,
+    /// If set, the worker registers with the operator registry at this URL before opening the
+    /// task stream.
+    #[serde(default)]
+    pub(crate) registration_url: Option<String>,
+    /// Compression of the gRPC messages, the worker falls back to uncompressed messages if the
+    /// gateway does not support it.
+    #[serde(default)]
+    pub(crate) grpc_compression: Option<GrpcCompression>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum GrpcCompression
+{
+    Gzip,
+    Zstd,
+}
+
+impl GrpcCompression
+{
+    pub(crate) fn encoding(self) -> tonic::codec::CompressionEncoding
+    {
+        match self
+        {
+            GrpcCompression::Gzip => tonic::codec::CompressionEncoding::Gzip,
+            GrpcCompression::Zstd => tonic::codec::CompressionEncoding::Zstd,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub(crate) struct PrometheusConfig
 {
     pub(crate) port: u16,
+    /// The address the metrics are served on, all of them by default for the scrapers.
+    #[serde(default = "default_prometheus_bind")]
+    pub(crate) bind: IpAddr,
+    /// If set, the task counters are saved to this JSON file and restored from it on startup.
+    #[serde(default)]
+    pub(crate) persist_path: Option<String>,
+    #[serde(
+        default = "default_persist_interval_secs",
+        deserialize_with = "units::secs"
+    )]
+    pub(crate) persist_interval_secs: u64,
+    /// If set, renamed metrics are also emitted under their deprecated name.
+    #[serde(default = "default_emit_deprecated_names")]
+    pub(crate) emit_deprecated_names: bool,
+    /// If not empty, only the clients in these networks are answered, e.g. the scrapers.
+    #[serde(default)]
+    pub(crate) allowlist: Vec<IpNet>,
+}
+
+fn default_prometheus_bind() -> IpAddr
+{
+    IpAddr::from(
+        [
+            0,
+            0,
+            0,
+            0,
+        ],
+    )
+}
+
+fn default_emit_deprecated_names() -> bool
+{
+    true
+}
+
+fn default_persist_interval_secs() -> u64
+{
+    60
+}
+
+/// Shaping of the dummy provers replies per task type, for load tests.
+///
+/// The distributions are keyed by task type, e.g. `row_leaf`, or by prover family, e.g. `query`,
+/// for the task types of the family without their own.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub(crate) struct DummyConfig
+{
+    /// Proving time in milliseconds.
+    #[serde(default)]
+    pub(crate) latency_profile: BTreeMap<String, Distribution>,
+    /// Proof size in bytes.
+    #[serde(default)]
+    pub(crate) size_profile: BTreeMap<String, Distribution>,
+}
+
+impl DummyConfig
+{
+    pub fn validate(&self)
+    {
+        for key in self
+            .latency_profile
+            .keys()
+            .chain(
+                self.size_profile
+                    .keys(),
+            )
+        {
+            assert!(
+                dummy_profile::is_known(key),
+                "Unknown dummy profile `{key}`, expected a task type or a prover family"
+            );
+        }
+    }
+
+    /// The profile of the dummy prover of `family`.
+    pub(crate) fn profile(
+        &self,
+        family: &'static str,
+    ) -> DummyProfile
+    {
+        DummyProfile {
+            family,
+            latency_ms: self
+                .latency_profile
+                .clone(),
+            proof_size: self
+                .size_profile
+                .clone(),
+        }
+    }
+}
+
+/// Retention policy of the worker-owned local stores, e.g. proofs and journals.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct RetentionConfig
+{
+    /// Directories compacted by the background job, compaction is disabled if empty.
+    pub(crate) dirs: Vec<String>,
+    /// Files older than this are deleted, if set.
+    #[serde(
+        default,
+        deserialize_with = "units::option_days"
+    )]
+    pub(crate) max_age_days: Option<u64>,
+    /// The oldest files are deleted until the directories fit in this size, if set.
+    #[serde(
+        default,
+        deserialize_with = "units::option_bytes"
+    )]
+    pub(crate) max_bytes: Option<u64>,
+    /// Delay between two compactions.
+    #[serde(deserialize_with = "units::secs")]
+    pub(crate) interval_secs: u64,
+}
+
+impl RetentionConfig
+{
+    pub fn validate(&self)
+    {
+        assert!(
+            self.interval_secs > 0,
+            "Retention interval must be positive"
+        );
+    }
 }
 
 impl AvsConfig
 {
+    /// The name identifying this gateway in logs and metrics.
+    pub fn label(&self) -> &str
+    {
+        self.name
+            .as_deref()
+            .unwrap_or(&self.issuer)
+    }
+
     pub fn validate(&self)
     {
         assert!(
@@ -188,6 +1428,13 @@ impl AvsConfig
                 .is_empty(),
             "Worker ID is required"
         );
+        if let Some(url) = &self.registration_url
+        {
+            assert!(
+                url.starts_with("http://") || url.starts_with("https://"),
+                "Registration URL must be an HTTP(S) URL"
+            );
+        }
 
         match (
             &self.lagr_keystore,
@@ -251,16 +1498,351 @@ impl Config
             .build()
             .expect("Could not load configuration");
 
-        config_builder
+        let mut config: Config = config_builder
             .try_deserialize()
-            .expect("Could not deserialize configuration")
+            .expect("Could not deserialize configuration");
+        config.relocate_under_data_dir();
+        config.normalize_params_urls();
+        config
+    }
+
+    /// Normalizes the params URLs, failing on the malformed ones, see [`urls`].
+    fn normalize_params_urls(&mut self)
+    {
+        let params = &mut self.public_params;
+        params.url = urls::normalize_base(
+            "public_params.url",
+            &params.url,
+        )
+        .unwrap_or_else(|err| panic!("{err}"));
+        params.checksum_url = urls::normalize_file(
+            "public_params.checksum_url",
+            &params.checksum_url,
+        )
+        .unwrap_or_else(|err| panic!("{err}"));
+        if let Some(mirror_url) = &mut params.mirror_url
+        {
+            *mirror_url = urls::normalize_base(
+                "public_params.mirror_url",
+                mirror_url,
+            )
+            .unwrap_or_else(|err| panic!("{err}"));
+        }
+        if let Some(shadow) = &mut self.shadow_params
+        {
+            if let Some(url) = &mut shadow.url
+            {
+                *url = urls::normalize_base(
+                    "shadow_params.url",
+                    url,
+                )
+                .unwrap_or_else(|err| panic!("{err}"));
+            }
+            if let Some(checksum_url) = &mut shadow.checksum_url
+            {
+                *checksum_url = urls::normalize_file(
+                    "shadow_params.checksum_url",
+                    checksum_url,
+                )
+                .unwrap_or_else(|err| panic!("{err}"));
+            }
+        }
+    }
+
+    /// Moves the paths the worker writes to under the data directory, if configured.
+    ///
+    /// The relative paths are resolved against the data directory, the absolute ones are kept
+    /// except for the checksum file, whose default is under `/tmp`. The temporary files of the
+    /// dependencies go to its `tmp` subdirectory.
+    fn relocate_under_data_dir(&mut self)
+    {
+        let Some(data_dir) = self
+            .data_dir
+            .clone()
+        else
+        {
+            return;
+        };
+        let relocate = |path: &mut String| {
+            if Path::new(path.as_str()).is_relative()
+            {
+                *path = Path::new(&data_dir)
+                    .join(path.trim_start_matches("./"))
+                    .to_string_lossy()
+                    .to_string();
+            }
+        };
+
+        relocate(
+            &mut self
+                .public_params
+                .dir,
+        );
+        if self
+            .public_params
+            .checksum_expected_local_path
+            == DEFAULT_CHECKSUM_PATH
+        {
+            self.public_params
+                .checksum_expected_local_path = "expected_checksums.txt".to_string();
+        }
+        relocate(
+            &mut self
+                .public_params
+                .checksum_expected_local_path,
+        );
+        relocate(
+            &mut self
+                .debug
+                .capture_dir,
+        );
+        if let Some(downgrade) = &mut self
+            .worker
+            .downgrade
+        {
+            relocate(&mut downgrade.state_file);
+        }
+        relocate(
+            &mut self
+                .worker
+                .session_file,
+        );
+        if let Some(path) = &mut self
+            .prometheus
+            .persist_path
+        {
+            relocate(path);
+        }
+        for dir in &mut self
+            .retention
+            .dirs
+        {
+            relocate(dir);
+        }
+        if let Some(offline) = &mut self.offline
+        {
+            relocate(&mut offline.output_dir);
+        }
+        if let Some(task_patches) = &mut self.task_patches
+        {
+            relocate(&mut task_patches.audit_file);
+        }
+        if let Some(notary) = &mut self.notary
+        {
+            relocate(&mut notary.receipts_dir);
+        }
+        if let Some(handoff) = &mut self.handoff
+        {
+            relocate(&mut handoff.socket);
+        }
+        #[cfg(feature = "prover-groth16")]
+        if let Some(submission) = &mut self.submission
+        {
+            if let Some(payload_dir) = &mut submission.payload_dir
+            {
+                relocate(payload_dir);
+            }
+            relocate(&mut submission.audit_file);
+        }
+        if let Some(shadow) = &mut self.shadow_params
+        {
+            relocate(&mut shadow.dir);
+            if shadow.checksum_expected_local_path == default_shadow_checksum_path()
+            {
+                shadow.checksum_expected_local_path =
+                    "shadow_params/expected_checksums.txt".to_string();
+            }
+            relocate(&mut shadow.checksum_expected_local_path);
+        }
+        #[cfg(feature = "prover-preprocessing")]
+        if let Some(index_checkpoints) = &mut self
+            .worker
+            .index_checkpoints
+        {
+            relocate(&mut index_checkpoints.dir);
+        }
+    }
+
+    /// The directory of the temporary files, under the data directory if configured.
+    pub(crate) fn tmp_dir(&self) -> Option<PathBuf>
+    {
+        self.data_dir
+            .as_ref()
+            .map(|data_dir| Path::new(data_dir).join("tmp"))
     }
 
     pub fn validate(&self)
     {
         self.public_params
             .validate();
-        self.avs
+        self.dummy
+            .validate();
+        self.retention
             .validate();
+        if let Some(offline) = &self.offline
+        {
+            offline.validate();
+        }
+        self.debug
+            .validate();
+        self.tenants
+            .validate();
+        if let Some(events) = &self.events
+        {
+            events.validate();
+        }
+        if let Some(notary) = &self.notary
+        {
+            notary.validate();
+        }
+        #[cfg(feature = "prover-groth16")]
+        if let Some(submission) = &self.submission
+        {
+            submission.validate();
+        }
+        if let Some(shadow) = &self.shadow_params
+        {
+            shadow.validate(&self.public_params);
+        }
+        if let Some(grpc_proxy) = &self
+            .network
+            .grpc_proxy
+        {
+            grpc_proxy.validate();
+        }
+        if let Some(admin) = &self.admin
+        {
+            assert_ne!(
+                admin.port,
+                self.prometheus
+                    .port,
+                "Admin port must differ from the Prometheus one"
+            );
+        }
+        if let Some(task_patches) = &self.task_patches
+        {
+            task_patches.validate();
+        }
+        if let Some(maintenance) = &self.maintenance
+        {
+            maintenance.validate();
+        }
+        if let Some(host_metadata) = &self.host_metadata
+        {
+            host_metadata.validate();
+        }
+        if self
+            .handoff
+            .is_some()
+        {
+            assert!(
+                self.offline
+                    .is_none()
+                    && self
+                        .avs
+                        .iter()
+                        .all(
+                            |avs| {
+                                avs.gateway_grpc_url
+                                    .is_some()
+                            },
+                        ),
+                "The handoff requires gRPC gateways"
+            );
+        }
+        if let Some(percent) = self
+            .worker
+            .eco_mode
+        {
+            assert!(
+                (1..=100).contains(&percent),
+                "Eco mode must be a percentage of the cores from 1 to 100"
+            );
+        }
+        if let Some(downgrade) = &self
+            .worker
+            .downgrade
+        {
+            downgrade.validate();
+        }
+        if let Some(replay_window) = &self
+            .worker
+            .replay_window
+        {
+            replay_window.validate();
+        }
+        if let Some(fairness) = &self
+            .worker
+            .fairness
+        {
+            fairness.validate();
+        }
+        #[cfg(feature = "prover-preprocessing")]
+        if let Some(index_checkpoints) = &self
+            .worker
+            .index_checkpoints
+        {
+            index_checkpoints.validate();
+        }
+
+        assert!(
+            !self
+                .avs
+                .is_empty(),
+            "At least one gateway is required"
+        );
+        for avs in &self.avs
+        {
+            avs.validate();
+        }
+        if self
+            .avs
+            .len()
+            > 1
+        {
+            assert!(
+                self.avs
+                    .iter()
+                    .all(
+                        |avs| {
+                            avs.gateway_grpc_url
+                                .is_some()
+                        },
+                    ),
+                "Serving multiple gateways requires a gRPC URL for each of them"
+            );
+        }
+        let labels = self
+            .avs
+            .iter()
+            .map(AvsConfig::label)
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(
+            labels.len(),
+            self.avs
+                .len(),
+            "Gateway names must be unique"
+        );
+    }
+}
+
+/// Accepts either a single value or a list of values.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T>
+    {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    match <OneOrMany<T> as serde::Deserialize>::deserialize(deserializer)?
+    {
+        OneOrMany::One(value) => Ok(vec![value]),
+        OneOrMany::Many(values) => Ok(values),
     }
 }
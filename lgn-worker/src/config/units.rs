@@ -0,0 +1,330 @@
+//! Durations and sizes of the config, either as bare numbers in the unit of the field, as before,
+//! or as strings with their unit, e.g. `"90s"`, `"1h30m"` or `"16MiB"`.
+
+use serde::de::Error;
+use serde::Deserializer;
+use serde_derive::Deserialize;
+
+const DURATION_FORMATS: &str = "a number of the unit of the field, or numbers with the units ms, \
+                                s, m, h and d, e.g. \"90s\", \"15m\" or \"1h30m\"";
+
+const SIZE_FORMATS: &str = "a number of the unit of the field, or a number with the units B, KB, \
+                            MB, GB, TB, KiB, MiB, GiB or TiB, e.g. \"512KiB\" or \"1.5GB\"";
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+const SECOND_MS: u64 = 1000;
+const DAY_MS: u64 = 24 * 3600 * SECOND_MS;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrText
+{
+    Number(u64),
+    Text(String),
+}
+
+/// A duration in milliseconds.
+pub(crate) fn millis<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    duration(
+        deserializer,
+        1,
+    )
+}
+
+/// A duration in seconds.
+pub(crate) fn secs<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    duration(
+        deserializer,
+        SECOND_MS,
+    )
+}
+
+/// An optional duration in seconds.
+pub(crate) fn option_secs<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    secs(deserializer).map(Some)
+}
+
+/// An optional duration in days.
+pub(crate) fn option_days<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    duration(
+        deserializer,
+        DAY_MS,
+    )
+    .map(Some)
+}
+
+/// A size in bytes.
+pub(crate) fn bytes<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    size(
+        deserializer,
+        1,
+    )
+}
+
+/// An optional size in bytes.
+pub(crate) fn option_bytes<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    bytes(deserializer).map(Some)
+}
+
+/// A size in MiB, the historical unit of the `_mb` fields.
+pub(crate) fn mebibytes<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    size(
+        deserializer,
+        MIB,
+    )
+    .map(|mebibytes| mebibytes as usize)
+}
+
+/// An optional size in MiB.
+pub(crate) fn option_mebibytes<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    mebibytes(deserializer).map(Some)
+}
+
+/// A duration counted in units of `unit_ms` milliseconds.
+fn duration<'de, D>(
+    deserializer: D,
+    unit_ms: u64,
+) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = match <NumberOrText as serde::Deserialize>::deserialize(deserializer)?
+    {
+        NumberOrText::Number(number) => return Ok(number),
+        NumberOrText::Text(text) => text,
+    };
+    let invalid = |reason: &str| {
+        D::Error::custom(
+            format!("invalid duration `{text}`, {reason}; expected {DURATION_FORMATS}"),
+        )
+    };
+    if let Ok(number) = text
+        .trim()
+        .parse()
+    {
+        return Ok(number);
+    }
+
+    let mut total_ms = 0u64;
+    let mut rest = text.trim();
+    if rest.is_empty()
+    {
+        return Err(invalid("it is empty"));
+    }
+    while !rest.is_empty()
+    {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let units = rest[digits..]
+            .find(|c: char| c.is_ascii_digit())
+            .map_or(
+                rest.len(),
+                |units| digits + units,
+            );
+        let number = rest[..digits]
+            .parse::<u64>()
+            .map_err(|_| invalid("a unit lacks its number"))?;
+        let unit_ms = match rest[digits..units].trim()
+        {
+            "ms" => 1,
+            "s" => SECOND_MS,
+            "m" => 60 * SECOND_MS,
+            "h" => 3600 * SECOND_MS,
+            "d" => DAY_MS,
+            "" => return Err(invalid("a number lacks its unit")),
+            unit => return Err(invalid(&format!("`{unit}` is not a unit"))),
+        };
+        total_ms = number
+            .checked_mul(unit_ms)
+            .and_then(|ms| total_ms.checked_add(ms))
+            .ok_or_else(|| invalid("it is too long"))?;
+        rest = &rest[units..];
+    }
+    if total_ms % unit_ms != 0
+    {
+        return Err(
+            invalid(
+                &format!(
+                    "it is not a whole number of {}",
+                    if unit_ms == DAY_MS
+                    {
+                        "days"
+                    }
+                    else
+                    {
+                        "seconds"
+                    }
+                ),
+            ),
+        );
+    }
+    Ok(total_ms / unit_ms)
+}
+
+/// A size counted in units of `unit` bytes, rounded up.
+fn size<'de, D>(
+    deserializer: D,
+    unit: u64,
+) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = match <NumberOrText as serde::Deserialize>::deserialize(deserializer)?
+    {
+        NumberOrText::Number(number) => return Ok(number),
+        NumberOrText::Text(text) => text,
+    };
+    let invalid = |reason: &str| {
+        D::Error::custom(format!("invalid size `{text}`, {reason}; expected {SIZE_FORMATS}"))
+    };
+    if let Ok(number) = text
+        .trim()
+        .parse()
+    {
+        return Ok(number);
+    }
+
+    let trimmed = text.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let number = trimmed[..split]
+        .parse::<f64>()
+        .map_err(|_| invalid("it does not start with a number"))?;
+    let multiplier = match trimmed[split..].trim()
+    {
+        "B" => 1,
+        "KB" => 1000,
+        "MB" => 1000 * 1000,
+        "GB" => 1000 * 1000 * 1000,
+        "TB" => 1000 * 1000 * 1000 * 1000,
+        "KiB" => KIB,
+        "MiB" => MIB,
+        "GiB" => 1024 * MIB,
+        "TiB" => 1024 * 1024 * MIB,
+        "" => return Err(invalid("the number lacks its unit")),
+        unit => return Err(invalid(&format!("`{unit}` is not a unit"))),
+    };
+    let bytes = number * multiplier as f64;
+    if !bytes.is_finite() || bytes > u64::MAX as f64
+    {
+        return Err(invalid("it is too large"));
+    }
+    Ok((bytes.ceil() as u64).div_ceil(unit))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use serde::de::value::Error;
+    use serde::de::value::StrDeserializer;
+    use serde::de::value::U64Deserializer;
+    use serde::de::IntoDeserializer;
+
+    use super::*;
+
+    fn text(value: &str) -> StrDeserializer<'_, Error>
+    {
+        value.into_deserializer()
+    }
+
+    #[test]
+    fn durations_take_units_or_the_unit_of_the_field()
+    {
+        let number: U64Deserializer<Error> = 90u64.into_deserializer();
+        assert_eq!(
+            secs(number),
+            Ok(90)
+        );
+        assert_eq!(
+            secs(text("90")),
+            Ok(90)
+        );
+        assert_eq!(
+            secs(text("15m")),
+            Ok(900)
+        );
+        assert_eq!(
+            secs(text("1h30m")),
+            Ok(5400)
+        );
+        assert_eq!(
+            option_days(text("2d")),
+            Ok(Some(2))
+        );
+
+        let err = secs(text("1500ms"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("whole number of seconds"),
+            "{err}"
+        );
+        let err = secs(text("15 minutes"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("\"1h30m\""),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn sizes_take_units_or_the_unit_of_the_field()
+    {
+        assert_eq!(
+            bytes(text("512KiB")),
+            Ok(512 * 1024)
+        );
+        assert_eq!(
+            bytes(text("1.5GB")),
+            Ok(1_500_000_000)
+        );
+        assert_eq!(
+            mebibytes(text("16MiB")),
+            Ok(16)
+        );
+        assert_eq!(
+            mebibytes(text("16")),
+            Ok(16)
+        );
+        assert_eq!(
+            mebibytes(text("1MB")),
+            Ok(1)
+        );
+
+        let err = bytes(text("16 megs"))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("\"512KiB\""),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Validation and normalization of the params URLs.
+//!
+//! The params files are downloaded from `<base URL>/<file name>`, so a base URL with a missing
+//! scheme, a trailing slash or the checksums file appended downloads from somewhere else than
+//! intended, only failing after a long startup. The URLs are parsed when the config is loaded, the
+//! malformed ones failing with what to change.
+
+use reqwest::Url;
+
+/// Normalizes the base URL `url` of `field`, that the params file names are appended to: without
+/// a trailing slash, its path kept and percent-encoded.
+pub(super) fn normalize_base(
+    field: &str,
+    url: &str,
+) -> Result<String, String>
+{
+    let parsed = parse(
+        field,
+        url,
+    )?;
+    if parsed
+        .query()
+        .is_some()
+        || parsed
+            .fragment()
+            .is_some()
+    {
+        return Err(
+            format!(
+                "`{field}` = `{url}` has a query or a fragment, the file names would be appended \
+                 after it; set the directory of the params only"
+            ),
+        );
+    }
+    if parsed
+        .path_segments()
+        .and_then(Iterator::last)
+        .is_some_and(|last| last.ends_with(".hash"))
+    {
+        return Err(
+            format!(
+                "`{field}` = `{url}` names the checksums file, set it as `checksum_url` and the \
+                 directory of the params as `{field}`"
+            ),
+        );
+    }
+
+    Ok(
+        parsed
+            .as_str()
+            .trim_end_matches('/')
+            .to_string(),
+    )
+}
+
+/// Normalizes the URL `url` of `field`, naming a file.
+pub(super) fn normalize_file(
+    field: &str,
+    url: &str,
+) -> Result<String, String>
+{
+    let parsed = parse(
+        field,
+        url,
+    )?;
+    if parsed
+        .path()
+        .ends_with('/')
+    {
+        return Err(
+            format!(
+                "`{field}` = `{url}` names a directory, set the URL of the file, e.g. \
+                 `{}public_params.hash`",
+                parsed.as_str()
+            ),
+        );
+    }
+    Ok(parsed.to_string())
+}
+
+fn parse(
+    field: &str,
+    url: &str,
+) -> Result<Url, String>
+{
+    let url = url.trim();
+    if !url.contains("://")
+    {
+        return Err(
+            format!(
+                "`{field}` = `{url}` has no scheme, e.g. `https://{}`",
+                url.trim_start_matches(
+                    [
+                        ':',
+                        '/',
+                    ]
+                )
+            ),
+        );
+    }
+    let parsed =
+        Url::parse(url).map_err(|err| format!("`{field}` = `{url}` is not a valid URL: {err}"))?;
+    if !matches!(
+        parsed.scheme(),
+        "http" | "https"
+    )
+    {
+        return Err(
+            format!(
+                "`{field}` = `{url}` has the scheme `{}`, the params are downloaded over http or \
+                 https",
+                parsed.scheme()
+            ),
+        );
+    }
+    if parsed
+        .host_str()
+        .is_none_or(str::is_empty)
+    {
+        return Err(format!("`{field}` = `{url}` has no host"));
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn normalizes_the_params_urls()
+    {
+        let base = |url| {
+            normalize_base(
+                "url",
+                url,
+            )
+        };
+        assert_eq!(
+            base(" https://pub-abc.r2.dev/ "),
+            Ok("https://pub-abc.r2.dev".to_string())
+        );
+        assert_eq!(
+            base("https://pub-abc.r2.dev/v1.1/params//"),
+            Ok("https://pub-abc.r2.dev/v1.1/params".to_string())
+        );
+        assert_eq!(
+            base("https://pub-abc.r2.dev/v1 params"),
+            Ok("https://pub-abc.r2.dev/v1%20params".to_string())
+        );
+        assert!(
+            base("pub-abc.r2.dev")
+                .unwrap_err()
+                .contains("https://pub-abc.r2.dev")
+        );
+        assert!(base("ftp://pub-abc.r2.dev").is_err());
+        assert!(base("https://pub-abc.r2.dev/params?v=1").is_err());
+        assert!(base("https://pub-abc.r2.dev/public_params.hash").is_err());
+
+        assert_eq!(
+            normalize_file(
+                "checksum_url",
+                "https://pub-abc.r2.dev/public_params.hash"
+            ),
+            Ok("https://pub-abc.r2.dev/public_params.hash".to_string())
+        );
+        assert!(
+            normalize_file(
+                "checksum_url",
+                "https://pub-abc.r2.dev/"
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Detection of the CPU features the binary was compiled for.
+//!
+//! A binary built with e.g. `-Ctarget-cpu` enabling AVX-512 dies with SIGILL on older CPUs, which
+//! only shows as a restart loop. Checking the features at startup gives a clear exit reason
+//! instead.
+
+use lgn_messages::types::error_code::ErrorCode;
+use lgn_worker::exit_code::ExitClass;
+
+/// A CPU feature, whether the binary was compiled to use it and whether the CPU supports it.
+struct Feature
+{
+    name: &'static str,
+    compiled: bool,
+    detected: bool,
+}
+
+/// The features relevant to the provers performance.
+pub(crate) struct CpuFeatures
+{
+    features: Vec<Feature>,
+}
+
+/// Checks every feature both at compile time and at runtime.
+#[cfg(
+    any(
+        target_arch = "x86",
+        target_arch = "x86_64"
+    )
+)]
+macro_rules! compiled_features {
+    ($($feature:tt),* $(,)?) => {
+        {
+            vec![
+                $(
+                    Feature {
+                        name: $feature,
+                        compiled: cfg!(target_feature = $feature),
+                        detected: std::arch::is_x86_feature_detected!($feature),
+                    },
+                )*
+            ]
+        }
+    };
+}
+
+#[cfg(target_arch = "aarch64")]
+macro_rules! compiled_features {
+    ($($feature:tt),* $(,)?) => {
+        {
+            vec![
+                $(
+                    Feature {
+                        name: $feature,
+                        compiled: cfg!(target_feature = $feature),
+                        detected: std::arch::is_aarch64_feature_detected!($feature),
+                    },
+                )*
+            ]
+        }
+    };
+}
+
+impl CpuFeatures
+{
+    pub(crate) fn detect() -> Self
+    {
+        #[cfg(
+            any(
+                target_arch = "x86",
+                target_arch = "x86_64"
+            )
+        )]
+        let features = compiled_features!(
+            "sse3",
+            "ssse3",
+            "sse4.1",
+            "sse4.2",
+            "popcnt",
+            "avx",
+            "avx2",
+            "fma",
+            "bmi1",
+            "bmi2",
+            "adx",
+            "lzcnt",
+            "avx512f",
+            "avx512bw",
+            "avx512cd",
+            "avx512dq",
+            "avx512vl",
+            "avx512ifma",
+        );
+        // Graviton2 is `neoverse-n1`, Graviton3 adds SVE and Graviton4 SVE2.
+        #[cfg(target_arch = "aarch64")]
+        let features = compiled_features!(
+            "neon",
+            "aes",
+            "sha2",
+            "sha3",
+            "crc",
+            "lse",
+            "rcpc",
+            "dotprod",
+            "fp16",
+            "i8mm",
+            "bf16",
+            "sve",
+            "sve2",
+        );
+        #[cfg(
+            not(
+                any(
+                    target_arch = "x86",
+                    target_arch = "x86_64",
+                    target_arch = "aarch64"
+                )
+            )
+        )]
+        let features = vec![];
+
+        Self {
+            features,
+        }
+    }
+
+    /// The features the binary was compiled to use.
+    pub(crate) fn required(&self) -> Vec<&'static str>
+    {
+        self.select(|feature| feature.compiled)
+    }
+
+    /// The features supported by the CPU.
+    pub(crate) fn detected(&self) -> Vec<&'static str>
+    {
+        self.select(|feature| feature.detected)
+    }
+
+    /// The compiled-in features the CPU does not support.
+    pub(crate) fn missing(&self) -> Vec<&'static str>
+    {
+        self.select(|feature| feature.compiled && !feature.detected)
+    }
+
+    fn select(
+        &self,
+        filter: impl Fn(&Feature) -> bool,
+    ) -> Vec<&'static str>
+    {
+        self.features
+            .iter()
+            .filter(|feature| filter(feature))
+            .map(|feature| feature.name)
+            .collect()
+    }
+
+    /// Exits the process if the CPU lacks a compiled-in feature.
+    ///
+    /// Runs before logging is set up, hence writes to stderr directly.
+    pub(crate) fn exit_if_incompatible(&self)
+    {
+        let missing = self.missing();
+        if !missing.is_empty()
+        {
+            eprintln!(
+                "exit_reason=incompatible_cpu error_code={} missing_features={} the worker was \
+                 built for a newer CPU, use a build matching this host",
+                ErrorCode::IncompatibleCpu,
+                missing.join(",")
+            );
+            std::process::exit(
+                ExitClass::IncompatibleCpu
+                    .code()
+                    .into(),
+            );
+        }
+    }
+}
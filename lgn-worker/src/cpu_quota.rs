@@ -0,0 +1,165 @@
+//! Sizing of the proving threads after the CPU quota of the container.
+//!
+//! A container limited to a few CPUs of a large host still sees all the host cores, the provers
+//! then run more threads than the quota allows and get throttled, which shows as erratic proof
+//! times. In eco mode, the pool is sized to a share of the cores or of the quota, for the worker
+//! to share its host.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use tracing::info;
+
+/// Sizes the global rayon pool after the CPU quota if `respect_quota` is set, and to the
+/// `eco_percent` of the cores left if set, returning the quota in CPUs.
+///
+/// Must run before anything uses rayon, the global pool can only be configured once.
+pub(crate) fn apply(
+    respect_quota: bool,
+    eco_percent: Option<u8>,
+) -> Result<Option<f64>>
+{
+    let host = std::thread::available_parallelism()
+        .map(usize::from)
+        .unwrap_or(1);
+    let quota = respect_quota
+        .then(detect_quota)
+        .flatten();
+    // A fractional quota still lets the last thread run part of the time.
+    let mut effective = quota.map_or(
+        host,
+        |quota| {
+            (quota.ceil() as usize).clamp(
+                1,
+                host,
+            )
+        },
+    );
+    if let Some(percent) = eco_percent
+    {
+        effective = (effective * usize::from(percent))
+            .div_ceil(100)
+            .max(1);
+    }
+    info!(
+        "Proving parallelism. host cores: {host}, cgroup quota: {quota:?}, eco mode: \
+         {eco_percent:?}%, threads: {effective}"
+    );
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(effective)
+        .build_global()
+        .context("failed to size the global thread pool")?;
+
+    Ok(quota)
+}
+
+/// The CPU quota of the cgroup of the process, `None` if unlimited or unknown.
+pub(crate) fn detect_quota() -> Option<f64>
+{
+    let cgroup = std::fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+
+    // cgroup v2, a single `0::<path>` hierarchy.
+    if let Some(path) = cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+    {
+        for dir in candidate_dirs(
+            "/sys/fs/cgroup",
+            path,
+        )
+        {
+            if let Ok(content) = std::fs::read_to_string(dir.join("cpu.max"))
+            {
+                return parse_cpu_max(&content);
+            }
+        }
+    }
+
+    // cgroup v1, the `cpu` controller having its own hierarchy.
+    let path = cgroup
+        .lines()
+        .find_map(
+            |line| {
+                let mut fields = line.splitn(
+                    3,
+                    ':',
+                );
+                let _id = fields.next()?;
+                let controllers = fields.next()?;
+                let path = fields.next()?;
+                controllers
+                    .split(',')
+                    .any(|controller| controller == "cpu")
+                    .then_some(path)
+            },
+        )
+        .unwrap_or("/");
+    for root in [
+        "/sys/fs/cgroup/cpu",
+        "/sys/fs/cgroup/cpu,cpuacct",
+    ]
+    {
+        for dir in candidate_dirs(
+            root,
+            path,
+        )
+        {
+            let read = |file: &str| -> Option<i64> {
+                std::fs::read_to_string(dir.join(file))
+                    .ok()?
+                    .trim()
+                    .parse()
+                    .ok()
+            };
+            if let (Some(quota), Some(period)) = (
+                read("cpu.cfs_quota_us"),
+                read("cpu.cfs_period_us"),
+            )
+            {
+                return (quota > 0 && period > 0).then(|| quota as f64 / period as f64);
+            }
+        }
+    }
+
+    None
+}
+
+/// The cgroup directory of the process, then the mount root, which is what a container sees when
+/// its cgroup namespace is private.
+pub(crate) fn candidate_dirs(
+    root: &str,
+    path: &str,
+) -> Vec<PathBuf>
+{
+    let root = Path::new(root);
+    let path = path.trim_start_matches('/');
+    if path.is_empty()
+    {
+        vec![root.to_path_buf()]
+    }
+    else
+    {
+        vec![
+            root.join(path),
+            root.to_path_buf(),
+        ]
+    }
+}
+
+/// Parses the `<quota> <period>` of a cgroup v2 `cpu.max`, the quota being `max` if unlimited.
+fn parse_cpu_max(content: &str) -> Option<f64>
+{
+    let mut fields = content.split_whitespace();
+    let quota = fields
+        .next()?
+        .parse::<f64>()
+        .ok()?;
+    let period = fields
+        .next()?
+        .parse::<f64>()
+        .ok()?;
+    (quota > 0.0 && period > 0.0).then(|| quota / period)
+}
@@ -0,0 +1,260 @@
+//! Proves a recorded task again with a pinned, historical params version, as a worker running it
+//! would have, to settle a dispute over one of its proofs.
+//!
+//! The params version is fetched into a directory of its own, never the one of
+//! `public_params.dir`, so that a worker running on the same host keeps proving with its params.
+//! The reply is written along with its provenance: the params it was proven with, identified by the
+//! digest of their checksums, the task and the worker.
+
+use std::path::Path;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::*;
+use checksum::fetch_checksum_file;
+use checksum::verify_directory_checksums;
+use clap::Parser;
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::MessageReplyEnvelope;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskType;
+use manager::v1::register_v1_provers;
+use manager::ProversManager;
+use serde_derive::Serialize;
+use tracing::info;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+mod checksum;
+mod config;
+mod manager;
+#[cfg(feature = "prover-query")]
+mod schema;
+mod startup;
+
+#[derive(Parser, Clone, Debug)]
+/// Prove a recorded task envelope with a historical params version, writing the reply along with
+/// its provenance.
+struct Cli
+{
+    #[clap(
+        short,
+        long
+    )]
+    /// The config file of the worker; its params files and class are used.
+    config: String,
+
+    #[clap(long)]
+    /// The directory the historical params are published in.
+    params_url: String,
+
+    #[clap(long)]
+    /// The checksums file of the historical params.
+    checksum_url: String,
+
+    #[clap(long)]
+    /// The BLAKE3 digest, in hex, the checksums file must have, pinning the params version.
+    checksums_digest: Option<String>,
+
+    #[clap(long)]
+    /// Where the historical params are stored, downloaded if missing; must differ from
+    /// `public_params.dir`.
+    params_dir: String,
+
+    #[clap(
+        short,
+        long
+    )]
+    /// Where the reply and its provenance are written, as JSON.
+    output: String,
+
+    #[clap()]
+    /// The recorded task envelope, as JSON.
+    input: String,
+}
+
+/// Where a disputed proof comes from.
+#[derive(Serialize, Debug)]
+struct Provenance
+{
+    worker_version: String,
+    params_url: String,
+    checksum_url: String,
+    /// The BLAKE3 digest of the checksums file, which identifies the params version.
+    checksums_digest: String,
+    /// The BLAKE3 digest of the task file, as recorded.
+    task_digest: String,
+    proven_at_unix: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct Output
+{
+    provenance: Provenance,
+    reply: MessageReplyEnvelope<ReplyType>,
+}
+
+fn main() -> Result<()>
+{
+    let subscriber = tracing_subscriber::fmt()
+        .pretty()
+        .compact()
+        .with_level(true)
+        .with_file(false)
+        .with_line_number(false)
+        .without_time()
+        .with_target(false)
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("Setting up logging failed");
+
+    let cli = Cli::parse();
+
+    let mut config = config::Config::load(Some(cli.config));
+    config.validate();
+    ensure!(
+        Path::new(&cli.params_dir)
+            != Path::new(
+                &config
+                    .public_params
+                    .dir
+            ),
+        "`--params-dir` must differ from `public_params.dir`, not to replace the active params"
+    );
+
+    let task = std::fs::read(&cli.input).with_context(
+        || {
+            format!(
+                "failed to open `{}`",
+                cli.input
+            )
+        },
+    )?;
+    let envelope = serde_json::from_slice::<MessageEnvelope<TaskType>>(&task)
+        .context("failed to parse input JSON")?;
+
+    let params = &mut config.public_params;
+    params.url = cli
+        .params_url
+        .trim_end_matches('/')
+        .to_string();
+    params.checksum_url = cli
+        .checksum_url
+        .clone();
+    params.mirror_url = None;
+    params.dir = cli
+        .params_dir
+        .clone();
+    params.checksum_expected_local_path = Path::new(&cli.params_dir)
+        .with_extension("expected_checksums.txt")
+        .to_string_lossy()
+        .to_string();
+    params.skip_store = false;
+    params.skip_checksum = false;
+    std::fs::create_dir_all(&params.dir).with_context(
+        || {
+            format!(
+                "failed to create `{}`",
+                params.dir
+            )
+        },
+    )?;
+
+    info!(
+        "Fetching the checksums of the historical params from `{}`",
+        params.checksum_url
+    );
+    fetch_checksum_file(
+        &params.checksum_url,
+        &params.checksum_expected_local_path,
+    )?;
+    let checksums_digest = blake3::hash(
+        &std::fs::read(&params.checksum_expected_local_path)
+            .context("failed to read the checksums file")?,
+    )
+    .to_hex()
+    .to_string();
+    if let Some(pinned) = &cli.checksums_digest
+    {
+        ensure!(
+            pinned.eq_ignore_ascii_case(&checksums_digest),
+            "the checksums file digest is `{checksums_digest}` rather than `{pinned}`, the params \
+             published at `{}` are not the pinned version",
+            params.checksum_url
+        );
+    }
+
+    info!(
+        "Registering the provers from `{}`",
+        params.dir
+    );
+    let mut provers_manager = ProversManager::<TaskType, ReplyType>::new();
+    register_v1_provers(
+        &config,
+        &mut provers_manager,
+    )
+    .context("while registering provers")?;
+    verify_directory_checksums(
+        &config
+            .public_params
+            .dir,
+        &config
+            .public_params
+            .checksum_expected_local_path,
+    )
+    .context("Failed to verify checksums")?;
+
+    info!(
+        "Proving task `{}` of query `{}`",
+        envelope.task_id, envelope.query_id
+    );
+    let reply = provers_manager
+        .delegate_proving(
+            &envelope,
+            &(),
+        )
+        .context("proof failed")?;
+
+    let output = Output {
+        provenance: Provenance {
+            worker_version: env!("CARGO_PKG_VERSION").to_string(),
+            params_url: config
+                .public_params
+                .url,
+            checksum_url: config
+                .public_params
+                .checksum_url,
+            checksums_digest,
+            task_digest: blake3::hash(&task)
+                .to_hex()
+                .to_string(),
+            proven_at_unix: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Epoch can not be in the future")
+                .as_secs(),
+        },
+        reply,
+    };
+    std::fs::write(
+        &cli.output,
+        serde_json::to_vec_pretty(&output)?,
+    )
+    .with_context(
+        || {
+            format!(
+                "failed to write `{}`",
+                cli.output
+            )
+        },
+    )?;
+    info!(
+        "Wrote the reply and its provenance to `{}`",
+        cli.output
+    );
+
+    Ok(())
+}
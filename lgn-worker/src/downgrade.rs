@@ -0,0 +1,403 @@
+//! Downgrade of the advertised class of the worker under memory pressure.
+//!
+//! A worker whose provers do not fit in its memory gets OOM-killed, restarted and killed again.
+//! With `worker.downgrade` set, a worker which was OOM-killed starts one class below instead, e.g.
+//! medium rather than large, which registers it as such and does not load the groth16 params. A
+//! worker whose memory stays above the high watermark stops on its own to restart one class
+//! below. The downgrade is kept in a state file across restarts, and the configured class is
+//! restored one class at a time, on the starts following `recover_after_secs` with the memory
+//! below the low watermark.
+//!
+//! The memory and the OOM kills are read from the cgroup v2 of the worker. Outside of one, the
+//! memory of the host is used and the OOM kills go unnoticed.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use lgn_messages::types::TaskDifficulty;
+use metrics::gauge;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::config::DowngradeConfig;
+use crate::cpu_quota;
+use crate::unix_now;
+
+/// The classes a worker can be downgraded through, from the smallest.
+const CLASSES: [TaskDifficulty; 3] = [
+    TaskDifficulty::Small,
+    TaskDifficulty::Medium,
+    TaskDifficulty::Large,
+];
+
+/// How often the memory is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the last time the memory was under pressure is saved.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+static DOWNGRADE: OnceLock<Downgrade> = OnceLock::new();
+
+struct Downgrade
+{
+    config: DowngradeConfig,
+    configured: TaskDifficulty,
+    state: Mutex<State>,
+}
+
+/// What is kept across restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct State
+{
+    /// How many classes below the configured one the worker runs.
+    steps: usize,
+    /// The `oom_kill` count of the cgroup when last read.
+    oom_kills: u64,
+    /// The last time a downgraded worker had its memory above the low watermark.
+    last_pressure_unix: u64,
+}
+
+/// Lowers the class of `config` after the OOM kills and the pressure of the previous runs, if
+/// enabled.
+///
+/// Must run before anything reads the class, e.g. the provers registration.
+pub(crate) fn apply(config: &mut Config) -> Result<()>
+{
+    let Some(downgrade) = config
+        .worker
+        .downgrade
+        .clone()
+    else
+    {
+        return Ok(());
+    };
+    let configured = config
+        .worker
+        .instance_type;
+    let state_file = PathBuf::from(&downgrade.state_file);
+    let mut state = read(&state_file);
+    let now = unix_now();
+
+    let oom_kills = oom_kills();
+    if oom_kills.is_some_and(|kills| kills > state.oom_kills)
+    {
+        warn!(
+            "The worker was OOM-killed since its last start, downgrading it. kills: {}",
+            oom_kills.unwrap_or_default() - state.oom_kills
+        );
+        state.steps += 1;
+        state.last_pressure_unix = now;
+    }
+    else if state.steps > 0
+        && now.saturating_sub(state.last_pressure_unix) >= downgrade.recover_after_secs
+    {
+        info!("The memory pressure is over, upgrading the worker by one class");
+        state.steps -= 1;
+        state.last_pressure_unix = now;
+    }
+    // A new cgroup counts from zero again.
+    state.oom_kills = oom_kills.unwrap_or_default();
+    state.steps = state
+        .steps
+        .min(max_steps(configured));
+    write(
+        &state_file,
+        &state,
+    )?;
+
+    let effective = lower(
+        configured,
+        state.steps,
+    );
+    if effective != configured
+    {
+        warn!("Running as a {effective} worker rather than the configured {configured} one");
+    }
+    config
+        .worker
+        .instance_type = effective;
+    let _ = DOWNGRADE.set(
+        Downgrade {
+            config: downgrade,
+            configured,
+            state: Mutex::new(state),
+        },
+    );
+
+    Ok(())
+}
+
+/// Samples the memory until it stays above the high watermark or the worker gets OOM-killed, then
+/// fails for the worker to restart one class below.
+///
+/// Runs forever if the downgrade is disabled, or once the worker runs as the smallest class.
+pub(crate) async fn monitor() -> Result<()>
+{
+    let Some(downgrade) = DOWNGRADE.get()
+    else
+    {
+        return std::future::pending().await;
+    };
+    report(
+        downgrade.configured,
+        &downgrade
+            .state
+            .lock()
+            .expect("downgrade lock poisoned"),
+    );
+    let state_file = Path::new(
+        &downgrade
+            .config
+            .state_file,
+    );
+    let mut pressure_since = None;
+    let mut saved = Instant::now();
+    loop
+    {
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+        let Some(usage) = memory_usage()
+        else
+        {
+            continue;
+        };
+        gauge!("zkmr_worker_memory_usage_ratio").set(usage);
+
+        let mut state = downgrade
+            .state
+            .lock()
+            .expect("downgrade lock poisoned");
+        let killed = oom_kills().is_some_and(|kills| kills > state.oom_kills);
+        if usage
+            >= downgrade
+                .config
+                .high_watermark
+        {
+            pressure_since.get_or_insert_with(Instant::now);
+        }
+        else
+        {
+            pressure_since = None;
+        }
+        let sustained = pressure_since.is_some_and(
+            |since: Instant| {
+                since
+                    .elapsed()
+                    .as_secs()
+                    >= downgrade
+                        .config
+                        .sustain_secs
+            },
+        );
+
+        if (killed || sustained) && state.steps < max_steps(downgrade.configured)
+        {
+            state.steps += 1;
+            state.last_pressure_unix = unix_now();
+            state.oom_kills = oom_kills().unwrap_or(state.oom_kills);
+            write(
+                state_file,
+                &state,
+            )?;
+            let lowered = lower(
+                downgrade.configured,
+                state.steps,
+            );
+            bail!(
+                "the memory is under pressure (usage {:.0}%, OOM kill: {killed}), restarting as a \
+                 {lowered} worker",
+                usage * 100.0
+            );
+        }
+        if state.steps > 0
+            && usage
+                >= downgrade
+                    .config
+                    .low_watermark
+        {
+            state.last_pressure_unix = unix_now();
+            if saved.elapsed() >= SAVE_INTERVAL
+            {
+                write(
+                    state_file,
+                    &state,
+                )?;
+                saved = Instant::now();
+            }
+        }
+    }
+}
+
+/// How many classes `configured` can be lowered by.
+fn max_steps(configured: TaskDifficulty) -> usize
+{
+    CLASSES
+        .iter()
+        .position(|class| *class == configured)
+        .unwrap_or_default()
+}
+
+/// The class `steps` classes below `configured`, never below the smallest one.
+fn lower(
+    configured: TaskDifficulty,
+    steps: usize,
+) -> TaskDifficulty
+{
+    match CLASSES
+        .iter()
+        .position(|class| *class == configured)
+    {
+        Some(index) => CLASSES[index.saturating_sub(steps)],
+        None => configured,
+    }
+}
+
+/// Exports the configured and the effective classes.
+fn report(
+    configured: TaskDifficulty,
+    state: &State,
+)
+{
+    gauge!(
+        "zkmr_worker_class",
+        "configured" => configured.to_string(),
+        "effective" => lower(configured, state.steps).to_string(),
+    )
+    .set(1.0);
+    gauge!("zkmr_worker_class_downgrade_steps").set(state.steps as f64);
+}
+
+fn read(state_file: &Path) -> State
+{
+    std::fs::read(state_file)
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write(
+    state_file: &Path,
+    state: &State,
+) -> Result<()>
+{
+    std::fs::write(
+        state_file,
+        serde_json::to_vec(state)?,
+    )
+    .with_context(
+        || {
+            format!(
+                "failed to save the downgrade state to `{}`",
+                state_file.display()
+            )
+        },
+    )
+}
+
+/// The directory of the cgroup v2 of the process holding `file`.
+fn cgroup_file(file: &str) -> Option<PathBuf>
+{
+    let cgroup = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))?;
+    cpu_quota::candidate_dirs(
+        "/sys/fs/cgroup",
+        path,
+    )
+    .into_iter()
+    .map(|dir| dir.join(file))
+    .find(|file| file.exists())
+}
+
+/// How many processes of the cgroup were OOM-killed, the worker included.
+fn oom_kills() -> Option<u64>
+{
+    let events = std::fs::read_to_string(cgroup_file("memory.events")?).ok()?;
+    events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// The fraction of its memory limit the cgroup uses, or of the memory of the host outside of a
+/// limited cgroup.
+fn memory_usage() -> Option<f64>
+{
+    let read = |file: &str| -> Option<u64> {
+        std::fs::read_to_string(cgroup_file(file)?)
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    };
+    if let (Some(current), Some(max)) = (
+        read("memory.current"),
+        read("memory.max"),
+    )
+    {
+        return (max > 0).then(|| current as f64 / max as f64);
+    }
+
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let field = |name: &str| -> Option<u64> {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()
+    };
+    let total = field("MemTotal:")?;
+    let available = field("MemAvailable:")?;
+    (total > 0).then(|| 1.0 - available as f64 / total as f64)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn lowers_down_to_the_smallest_class()
+    {
+        assert_eq!(
+            lower(
+                TaskDifficulty::Large,
+                1
+            ),
+            TaskDifficulty::Medium
+        );
+        assert_eq!(
+            lower(
+                TaskDifficulty::Large,
+                5
+            ),
+            TaskDifficulty::Small
+        );
+        assert_eq!(
+            lower(
+                TaskDifficulty::Medium,
+                0
+            ),
+            TaskDifficulty::Medium
+        );
+        assert_eq!(
+            max_steps(TaskDifficulty::Disabled),
+            0
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! Publication of the task events to a sink of the operator, for analytics.
+//!
+//! The events are queued and published by a thread of their own, so that a slow or unreachable
+//! sink never delays the replies to the gateway. The events are dropped when the queue is full.
+
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::TrySendError;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use metrics::counter;
+use serde_derive::Serialize;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::EventSinkKind;
+use crate::config::EventsConfig;
+
+static EVENTS: OnceLock<SyncSender<Event>> = OnceLock::new();
+
+/// How long the publishing thread waits for an event before keeping the sink connection alive.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+const SINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An event of the lifecycle of a task, published as a JSON object tagged by `event`.
+#[derive(Serialize, Debug)]
+#[serde(
+    tag = "event",
+    rename_all = "snake_case"
+)]
+pub(crate) enum Event
+{
+    Received
+    {
+        gateway: String,
+        task_id: String,
+        query_id: String,
+        tenant: String,
+        prover_type: String,
+        task_bytes: usize,
+        at: u64,
+    },
+    Completed
+    {
+        gateway: String,
+        task_id: String,
+        duration_ms: u64,
+        reply_bytes: usize,
+        at: u64,
+    },
+    Failed
+    {
+        gateway: String,
+        task_id: String,
+        duration_ms: u64,
+        /// The code of the catalog, e.g. `E3001`, if the error carries one.
+        error_code: Option<String>,
+        at: u64,
+    },
+}
+
+/// Starts the publication of the events, if configured.
+pub(crate) fn init(config: Option<&EventsConfig>)
+{
+    let Some(config) = config
+    else
+    {
+        return;
+    };
+    let (sender, receiver) = mpsc::sync_channel(config.queue_size);
+    if EVENTS
+        .set(sender)
+        .is_err()
+    {
+        return;
+    }
+
+    let sink: Box<dyn EventSink + Send> = match config.kind
+    {
+        EventSinkKind::Nats =>
+        {
+            Box::new(
+                NatsSink {
+                    address: config
+                        .url
+                        .clone(),
+                    subject: config
+                        .subject
+                        .clone(),
+                    stream: None,
+                },
+            )
+        },
+        EventSinkKind::Webhook =>
+        {
+            Box::new(
+                WebhookSink {
+                    url: config
+                        .url
+                        .clone(),
+                    client: reqwest::blocking::Client::new(),
+                },
+            )
+        },
+    };
+    info!(
+        "Publishing the task events to {:?} sink `{}`",
+        config.kind, config.url
+    );
+    std::thread::spawn(
+        move || {
+            publish(
+                sink,
+                receiver,
+            )
+        },
+    );
+}
+
+/// Queues the event built by `event`, which is only called if the events are enabled.
+pub(crate) fn emit(event: impl FnOnce() -> Event)
+{
+    let Some(sender) = EVENTS.get()
+    else
+    {
+        return;
+    };
+    if let Err(TrySendError::Full(_)) = sender.try_send(event())
+    {
+        counter!("zkmr_worker_events_dropped_total").increment(1);
+    }
+}
+
+/// The catalog code ending an error message sent to the gateway, e.g. `E3001`.
+pub(crate) fn error_code(message: &str) -> Option<String>
+{
+    let code = message
+        .strip_suffix(']')?
+        .rsplit_once('[')?
+        .1;
+    (code.len() > 1
+        && code.starts_with('E')
+        && code[1..]
+            .chars()
+            .all(|c| c.is_ascii_digit()))
+    .then(|| code.to_string())
+}
+
+fn publish(
+    mut sink: Box<dyn EventSink + Send>,
+    receiver: mpsc::Receiver<Event>,
+)
+{
+    // Only the transitions are logged, an unreachable sink failing every event.
+    let mut healthy = true;
+    loop
+    {
+        let result = match receiver.recv_timeout(KEEPALIVE_INTERVAL)
+        {
+            Ok(event) =>
+            {
+                let published = serde_json::to_vec(&event)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|payload| sink.publish(&payload));
+                if published.is_ok()
+                {
+                    counter!("zkmr_worker_events_published_total").increment(1);
+                }
+                else
+                {
+                    counter!("zkmr_worker_events_dropped_total").increment(1);
+                }
+                published
+            },
+            Err(RecvTimeoutError::Timeout) => sink.keepalive(),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        match result
+        {
+            Ok(()) if !healthy =>
+            {
+                info!("The events sink is reachable again");
+                healthy = true;
+            },
+            Err(err) if healthy =>
+            {
+                warn!("Failed to publish the task events: {err:?}");
+                healthy = false;
+            },
+            _ =>
+            {},
+        }
+    }
+}
+
+trait EventSink
+{
+    fn publish(
+        &mut self,
+        payload: &[u8],
+    ) -> Result<()>;
+
+    /// Keeps the connection to the sink alive while there is no event to publish.
+    fn keepalive(&mut self) -> Result<()>
+    {
+        Ok(())
+    }
+}
+
+/// Publishes to a NATS subject, speaking the text protocol of NATS directly.
+struct NatsSink
+{
+    address: String,
+    subject: String,
+    stream: Option<TcpStream>,
+}
+
+impl NatsSink
+{
+    fn connect(&self) -> Result<TcpStream>
+    {
+        let address = self
+            .address
+            .to_socket_addrs()?
+            .next()
+            .with_context(
+                || {
+                    format!(
+                        "no address for `{}`",
+                        self.address
+                    )
+                },
+            )?;
+        let mut stream = TcpStream::connect_timeout(
+            &address,
+            SINK_TIMEOUT,
+        )
+        .with_context(
+            || {
+                format!(
+                    "failed to connect to `{}`",
+                    self.address
+                )
+            },
+        )?;
+        stream.set_write_timeout(Some(SINK_TIMEOUT))?;
+        stream.write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false}\r\n")?;
+        Ok(stream)
+    }
+
+    /// Runs `f` on the connection, connecting first if needed, and drops the connection on error
+    /// so that the next call reconnects.
+    fn with_stream(
+        &mut self,
+        f: impl FnOnce(&mut TcpStream) -> std::io::Result<()>,
+    ) -> Result<()>
+    {
+        if self
+            .stream
+            .is_none()
+        {
+            self.stream = Some(self.connect()?);
+        }
+        let stream = self
+            .stream
+            .as_mut()
+            .expect("connected above");
+        let result = answer_pings(stream).and_then(|()| f(stream));
+        if result.is_err()
+        {
+            self.stream = None;
+        }
+        result.with_context(
+            || {
+                format!(
+                    "failed to publish to `{}`",
+                    self.address
+                )
+            },
+        )
+    }
+}
+
+impl EventSink for NatsSink
+{
+    fn publish(
+        &mut self,
+        payload: &[u8],
+    ) -> Result<()>
+    {
+        let header = format!(
+            "PUB {} {}\r\n",
+            self.subject,
+            payload.len()
+        );
+        self.with_stream(
+            |stream| {
+                stream.write_all(header.as_bytes())?;
+                stream.write_all(payload)?;
+                stream.write_all(b"\r\n")
+            },
+        )
+    }
+
+    fn keepalive(&mut self) -> Result<()>
+    {
+        self.with_stream(|_| Ok(()))
+    }
+}
+
+/// Answers the pings of the server, which otherwise closes the connection.
+///
+/// The other messages of the server, e.g. `INFO` or `+OK`, are discarded.
+fn answer_pings(stream: &mut TcpStream) -> std::io::Result<()>
+{
+    stream.set_nonblocking(true)?;
+    let mut pending = vec![];
+    let mut buffer = [0u8; 4096];
+    let result = loop
+    {
+        match stream.read(&mut buffer)
+        {
+            Ok(0) =>
+            {
+                break Err(std::io::ErrorKind::UnexpectedEof.into());
+            },
+            Ok(read) => pending.extend_from_slice(&buffer[..read]),
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break Ok(()),
+            Err(err) => break Err(err),
+        }
+    };
+    stream.set_nonblocking(false)?;
+    result?;
+
+    let pings = pending
+        .windows(4)
+        .filter(|window| *window == b"PING")
+        .count();
+    for _ in 0..pings
+    {
+        stream.write_all(b"PONG\r\n")?;
+    }
+    Ok(())
+}
+
+/// POSTs each event to a webhook.
+struct WebhookSink
+{
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl EventSink for WebhookSink
+{
+    fn publish(
+        &mut self,
+        payload: &[u8],
+    ) -> Result<()>
+    {
+        self.client
+            .post(&self.url)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "application/json",
+            )
+            .timeout(SINK_TIMEOUT)
+            .body(payload.to_vec())
+            .send()
+            .and_then(reqwest::blocking::Response::error_for_status)
+            .with_context(
+                || {
+                    format!(
+                        "failed to post to `{}`",
+                        self.url
+                    )
+                },
+            )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+//! The exit codes of the worker, a stable contract for the supervisors to restart each class of
+//! failure with its own policy, e.g. backing off while the gateway is unreachable but not
+//! restarting a worker whose config is invalid.
+//!
+//! The class of an error is the one it was tagged with, e.g. `.context(ExitClass::Auth)`, or else
+//! the one its causes tell: a [`ParamsError`], the status of a gateway or a panic. A code, like an
+//! [`ErrorCode`], is never reused for another class.
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::process::ExitCode;
+
+use lgn_messages::types::error_code::ErrorCode;
+use lgn_provers::params::ParamsError;
+use tonic::Code;
+
+/// The classes of failure the worker exits with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExitClass
+{
+    /// A failure of no other class, `1` as every failure before the classes.
+    #[default]
+    Failure,
+    /// The config could not be loaded or is invalid.
+    Config,
+    /// The params could not be downloaded, verified or loaded.
+    Params,
+    /// The keystore could not be read, or a gateway refused the credentials of the worker.
+    Auth,
+    /// A gateway could not be reached or dropped the connection.
+    GatewayUnreachable,
+    /// A gateway does not support the protocol of this version of the worker.
+    IncompatibleVersion,
+    /// The worker panicked.
+    InternalPanic,
+    /// The CPU lacks a feature the worker was built for, see `cpu_features`.
+    IncompatibleCpu,
+}
+
+impl ExitClass
+{
+    /// Every class.
+    pub const ALL: &'static [ExitClass] = &[
+        ExitClass::Failure,
+        ExitClass::Config,
+        ExitClass::Params,
+        ExitClass::Auth,
+        ExitClass::GatewayUnreachable,
+        ExitClass::IncompatibleVersion,
+        ExitClass::InternalPanic,
+        ExitClass::IncompatibleCpu,
+    ];
+
+    /// The exit code of the process.
+    #[must_use]
+    pub fn code(self) -> u8
+    {
+        match self
+        {
+            ExitClass::Failure => 1,
+            ExitClass::Config => 10,
+            ExitClass::Params => 11,
+            ExitClass::Auth => 12,
+            ExitClass::GatewayUnreachable => 13,
+            ExitClass::IncompatibleVersion => 14,
+            ExitClass::InternalPanic => 15,
+            ExitClass::IncompatibleCpu => 78,
+        }
+    }
+
+    /// The class of an exit code, for the supervisors.
+    #[must_use]
+    pub fn from_code(code: i32) -> Option<Self>
+    {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|class| i32::from(class.code()) == code)
+    }
+
+    /// The `exit_class` logged when the worker stops.
+    #[must_use]
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            ExitClass::Failure => "failure",
+            ExitClass::Config => "config",
+            ExitClass::Params => "params",
+            ExitClass::Auth => "auth",
+            ExitClass::GatewayUnreachable => "gateway_unreachable",
+            ExitClass::IncompatibleVersion => "incompatible_version",
+            ExitClass::InternalPanic => "internal_panic",
+            ExitClass::IncompatibleCpu => "incompatible_cpu",
+        }
+    }
+
+    /// The class `err` was tagged with, or else the one its causes tell, if any.
+    #[must_use]
+    pub fn of(err: &anyhow::Error) -> Option<Self>
+    {
+        err.downcast_ref::<ExitClass>()
+            .copied()
+            .or_else(
+                || {
+                    err.chain()
+                        .find_map(Self::of_cause)
+                },
+            )
+    }
+
+    fn of_cause(cause: &(dyn std::error::Error + 'static)) -> Option<Self>
+    {
+        if cause
+            .downcast_ref::<ParamsError>()
+            .is_some()
+        {
+            return Some(ExitClass::Params);
+        }
+        if cause
+            .downcast_ref::<tokio::task::JoinError>()
+            .is_some_and(tokio::task::JoinError::is_panic)
+        {
+            return Some(ExitClass::InternalPanic);
+        }
+        if cause
+            .downcast_ref::<tonic::transport::Error>()
+            .is_some()
+            || cause
+                .downcast_ref::<tungstenite::Error>()
+                .is_some()
+        {
+            return Some(ExitClass::GatewayUnreachable);
+        }
+        match cause
+            .downcast_ref::<tonic::Status>()?
+            .code()
+        {
+            Code::Unauthenticated | Code::PermissionDenied => Some(ExitClass::Auth),
+            Code::Unimplemented | Code::FailedPrecondition => Some(ExitClass::IncompatibleVersion),
+            Code::Unavailable | Code::DeadlineExceeded | Code::Cancelled =>
+            {
+                Some(ExitClass::GatewayUnreachable)
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The class of the `exit_reason` of an error code, for the failures only telling their code.
+impl From<ErrorCode> for ExitClass
+{
+    fn from(code: ErrorCode) -> Self
+    {
+        match code
+        {
+            ErrorCode::ParamsCorrupted
+            | ErrorCode::ParamsArtifact
+            | ErrorCode::ParamsAudit
+            | ErrorCode::ParamsShape
+            | ErrorCode::ParamsDownloadDeadline => ExitClass::Params,
+            ErrorCode::Registration => ExitClass::Auth,
+            ErrorCode::GatewayConnection => ExitClass::GatewayUnreachable,
+            ErrorCode::ProverPanic | ErrorCode::SubsystemPanic => ExitClass::InternalPanic,
+            ErrorCode::IncompatibleCpu => ExitClass::IncompatibleCpu,
+            _ => ExitClass::Failure,
+        }
+    }
+}
+
+impl From<ExitClass> for ExitCode
+{
+    fn from(class: ExitClass) -> Self
+    {
+        ExitCode::from(class.code())
+    }
+}
+
+impl Display for ExitClass
+{
+    fn fmt(
+        &self,
+        f: &mut Formatter<'_>,
+    ) -> std::fmt::Result
+    {
+        write!(
+            f,
+            "exit_class={} exit_code={}",
+            self.name(),
+            self.code()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn classifies_the_errors_by_tag_then_cause()
+    {
+        for class in ExitClass::ALL
+        {
+            assert_eq!(
+                ExitClass::from_code(
+                    class
+                        .code()
+                        .into()
+                ),
+                Some(*class)
+            );
+        }
+        assert_eq!(
+            ExitClass::from_code(0),
+            None
+        );
+
+        let tagged = Err::<(), _>(anyhow::anyhow!("no keystore"))
+            .context(ExitClass::Auth)
+            .context("exit_reason=main_loop")
+            .unwrap_err();
+        assert_eq!(
+            ExitClass::of(&tagged),
+            Some(ExitClass::Auth)
+        );
+
+        let refused = anyhow::Error::new(tonic::Status::unauthenticated("expired token"))
+            .context("while connecting to gateway `a`");
+        assert_eq!(
+            ExitClass::of(&refused),
+            Some(ExitClass::Auth)
+        );
+
+        assert_eq!(
+            ExitClass::of(&anyhow::anyhow!("the subsystem stopped")),
+            None
+        );
+        assert_eq!(
+            ExitClass::from(ErrorCode::GatewayConnection),
+            ExitClass::GatewayUnreachable
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! The share of the worker each query gets, for a huge query not to starve the others.
+//!
+//! The worker proves one task at a time, a query monopolizes it by the number of its tasks rather
+//! than by running them at once. With `worker.fairness` set, the tasks of each query admitted
+//! within `window_secs` are counted, and those of a query past `max_tasks_per_query` are refused
+//! with a retry hint, the time until its oldest counted task leaves the window, as long as another
+//! query seen within the window is under the cap: a query alone on the worker is never throttled.
+//! The gateway hands the refused tasks to other workers or sends them again later, the tasks of the
+//! other queries getting through in the meantime.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
+
+use lgn_messages::types::WorkerError;
+use metrics::gauge;
+
+use crate::config::FairnessConfig;
+
+static FAIRNESS: OnceLock<Fairness> = OnceLock::new();
+
+struct Fairness
+{
+    config: FairnessConfig,
+    /// When the tasks of each query counted against its cap were admitted, oldest first.
+    queries: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+pub(crate) fn init(config: Option<&FairnessConfig>)
+{
+    if let Some(config) = config
+    {
+        let _ = FAIRNESS.set(
+            Fairness {
+                config: config.clone(),
+                queries: Mutex::default(),
+            },
+        );
+    }
+}
+
+/// Counts the task `task_id` of `query_id` against the cap of the query, unless it is refused.
+///
+/// Accepts everything if the fairness is disabled.
+pub(crate) fn admit(
+    query_id: &str,
+    task_id: &str,
+) -> Result<(), WorkerError>
+{
+    let Some(fairness) = FAIRNESS.get()
+    else
+    {
+        return Ok(());
+    };
+    fairness.admit(
+        query_id,
+        task_id,
+        Instant::now(),
+    )
+}
+
+impl Fairness
+{
+    fn admit(
+        &self,
+        query_id: &str,
+        task_id: &str,
+        now: Instant,
+    ) -> Result<(), WorkerError>
+    {
+        let window = Duration::from_secs(
+            self.config
+                .window_secs,
+        );
+        let cap = self
+            .config
+            .max_tasks_per_query;
+        let mut queries = self
+            .queries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        queries.retain(
+            |_, admitted| {
+                while admitted
+                    .front()
+                    .is_some_and(|oldest| now.duration_since(*oldest) >= window)
+                {
+                    admitted.pop_front();
+                }
+                !admitted.is_empty()
+            },
+        );
+
+        let admitted = queries
+            .get(query_id)
+            .map_or(
+                0,
+                VecDeque::len,
+            );
+        let others_waiting = queries
+            .iter()
+            .any(|(other, admitted)| other != query_id && admitted.len() < cap);
+        if admitted >= cap && others_waiting
+        {
+            let oldest = queries[query_id][admitted - cap];
+            let retry_after = window.saturating_sub(now.duration_since(oldest));
+            return Err(
+                WorkerError::QueryThrottled {
+                    query_id: query_id.to_string(),
+                    task_id: task_id.to_string(),
+                    retry_after_secs: retry_after
+                        .as_secs()
+                        .max(1),
+                },
+            );
+        }
+
+        queries
+            .entry(query_id.to_string())
+            .or_default()
+            .push_back(now);
+        gauge!("zkmr_worker_fairness_active_queries").set(queries.len() as f64);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn throttles_a_query_past_its_cap_while_another_waits()
+    {
+        let fairness = Fairness {
+            config: FairnessConfig {
+                max_tasks_per_query: 2,
+                window_secs: 60,
+            },
+            queries: Mutex::default(),
+        };
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // Alone on the worker, the query is never throttled.
+        for secs in 0..3
+        {
+            assert!(
+                fairness
+                    .admit(
+                        "q1",
+                        "t",
+                        at(secs)
+                    )
+                    .is_ok()
+            );
+        }
+        assert!(
+            fairness
+                .admit(
+                    "q2",
+                    "t",
+                    at(10)
+                )
+                .is_ok()
+        );
+        assert!(
+            matches!(
+                fairness.admit(
+                    "q1",
+                    "t",
+                    at(20)
+                ),
+                Err(
+                    WorkerError::QueryThrottled {
+                        retry_after_secs: 41,
+                        ..
+                    }
+                )
+            )
+        );
+        assert!(
+            fairness
+                .admit(
+                    "q2",
+                    "t",
+                    at(20)
+                )
+                .is_ok()
+        );
+
+        // Both queries reached the cap, neither waits for the other.
+        assert!(
+            fairness
+                .admit(
+                    "q1",
+                    "t",
+                    at(30)
+                )
+                .is_ok()
+        );
+        // The first tasks left the window.
+        assert!(
+            fairness
+                .admit(
+                    "q2",
+                    "t",
+                    at(75)
+                )
+                .is_ok()
+        );
+    }
+}
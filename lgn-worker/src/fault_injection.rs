@@ -0,0 +1,178 @@
+//! Faults injected into the gRPC transport, for the resilience tests only.
+//!
+//! Built with the `fault-injection` feature and enabled by the `LGN_FAULT_INJECTION` environment
+//! variable, a comma-separated list of `name=value`, e.g.
+//! `stream_error=0.05,malformed=0.1,send_delay_ms=500,seed=7`:
+//!
+//! - `stream_error`, the probability that a received message is replaced by a stream error, which
+//!   drops the stream of its gateway;
+//! - `malformed`, the probability that a received task is truncated;
+//! - `send_delay_ms`, the maximum random delay before sending a reply;
+//! - `seed`, the seed of the random faults, for reproducible runs.
+
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::Rng;
+use rand::SeedableRng;
+use tracing::warn;
+
+use crate::lagrange::worker_to_gw_response::Response;
+use crate::lagrange::WorkerToGwResponse;
+
+const FAULT_INJECTION_VAR: &str = "LGN_FAULT_INJECTION";
+
+static FAULTS: OnceLock<Option<Faults>> = OnceLock::new();
+
+struct Faults
+{
+    stream_error: f64,
+    malformed: f64,
+    send_delay_ms: u64,
+    rng: Mutex<StdRng>,
+}
+
+fn faults() -> Option<&'static Faults>
+{
+    FAULTS
+        .get_or_init(
+            || {
+                let spec = std::env::var(FAULT_INJECTION_VAR).ok()?;
+                let faults = parse(&spec).unwrap_or_else(
+                    |err| panic!("invalid `{FAULT_INJECTION_VAR}` `{spec}`: {err}"),
+                );
+                warn!("Injecting transport faults: {spec}");
+                Some(faults)
+            },
+        )
+        .as_ref()
+}
+
+fn parse(spec: &str) -> Result<Faults, String>
+{
+    let mut faults = Faults {
+        stream_error: 0.0,
+        malformed: 0.0,
+        send_delay_ms: 0,
+        rng: Mutex::new(StdRng::from_entropy()),
+    };
+    for setting in spec
+        .split(',')
+        .filter(|setting| !setting.is_empty())
+    {
+        let (name, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("`{setting}` is not `name=value`"))?;
+        let invalid = |e: &dyn std::fmt::Display| format!("`{name}`: {e}");
+        match name
+        {
+            "stream_error" =>
+            {
+                faults.stream_error = value
+                    .parse()
+                    .map_err(|e| invalid(&e))?
+            },
+            "malformed" =>
+            {
+                faults.malformed = value
+                    .parse()
+                    .map_err(|e| invalid(&e))?
+            },
+            "send_delay_ms" =>
+            {
+                faults.send_delay_ms = value
+                    .parse()
+                    .map_err(|e| invalid(&e))?
+            },
+            "seed" =>
+            {
+                faults.rng = Mutex::new(
+                    StdRng::seed_from_u64(
+                        value
+                            .parse()
+                            .map_err(|e| invalid(&e))?,
+                    ),
+                )
+            },
+            _ => return Err(format!("unknown fault `{name}`")),
+        }
+    }
+    Ok(faults)
+}
+
+impl Faults
+{
+    fn chance(
+        &self,
+        probability: f64,
+    ) -> bool
+    {
+        probability > 0.0
+            && self
+                .rng
+                .lock()
+                .unwrap()
+                .gen_bool(probability.min(1.0))
+    }
+}
+
+/// Replaces a message received from a gateway by a stream error or truncates its task, at random.
+pub(crate) fn on_receive(
+    message: Result<WorkerToGwResponse, tonic::Status>
+) -> Result<WorkerToGwResponse, tonic::Status>
+{
+    let Some(faults) = faults()
+    else
+    {
+        return message;
+    };
+    let mut message = message?;
+    if faults.chance(faults.stream_error)
+    {
+        return Err(tonic::Status::unavailable("injected stream error"));
+    }
+    if let Some(Response::Todo(document)) = &mut message.response
+    {
+        if faults.chance(faults.malformed)
+        {
+            let len = faults
+                .rng
+                .lock()
+                .unwrap()
+                .gen_range(
+                    0..document
+                        .len()
+                        .max(1),
+                );
+            // Truncating inside a character would panic.
+            let len = (0..=len)
+                .rev()
+                .find(|&len| document.is_char_boundary(len))
+                .unwrap_or(0);
+            document.truncate(len);
+        }
+    }
+    Ok(message)
+}
+
+/// Waits a random delay before a reply is sent.
+pub(crate) async fn before_send()
+{
+    let Some(faults) = faults()
+    else
+    {
+        return;
+    };
+    if faults.send_delay_ms == 0
+    {
+        return;
+    }
+    let delay_ms = faults
+        .rng
+        .lock()
+        .unwrap()
+        .gen_range(0..=faults.send_delay_ms);
+    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+}
@@ -0,0 +1,298 @@
+//! The environment of the worker, to tell apart the machines a proof fails on from the others.
+//!
+//! The fingerprint is taken once the proving thread pool is sized. It is written with the crash
+//! logs and the captured tasks and, if enabled, appended to the failure replies.
+//! `lgn-worker fingerprint --diff` compares two of them.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use anyhow::Result;
+use ethers::utils::keccak256;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use crate::config::Config;
+use crate::cpu_features::CpuFeatures;
+use crate::cpu_quota;
+use crate::report::total_memory_bytes;
+
+static FINGERPRINT: OnceLock<State> = OnceLock::new();
+
+struct State
+{
+    fingerprint: Fingerprint,
+    /// The expected checksums of the params, which are fetched after the fingerprint is taken on
+    /// the first start of a node.
+    checksum_file: PathBuf,
+    /// Whether the failure replies carry the fingerprint.
+    on_failures: bool,
+}
+
+/// The environment proofs depend on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Fingerprint
+{
+    version: String,
+    arch: String,
+    cpu_model: Option<String>,
+    kernel: Option<String>,
+    logical_cpus: usize,
+    /// The CPU quota of the container, in CPUs, if limited.
+    cpu_quota: Option<f64>,
+    memory_gb: Option<f64>,
+    cpu_features: Vec<String>,
+    /// The CPU features the binary was compiled for.
+    compiled_cpu_features: Vec<String>,
+    allocator: String,
+    proving_threads: usize,
+    /// The cargo features the worker was built with, e.g. the provers.
+    build_features: Vec<String>,
+    /// The keccak of the expected checksums of the params, as in the reply audits.
+    params_checksum: Option<String>,
+}
+
+/// A field of two fingerprints with different values.
+#[derive(Debug)]
+pub(crate) struct Difference
+{
+    pub(crate) field: String,
+    pub(crate) left: serde_json::Value,
+    pub(crate) right: serde_json::Value,
+}
+
+/// Takes the fingerprint of the worker, for the crash logs, the captures and, if enabled, the
+/// failure replies.
+pub(crate) fn init(config: &Config)
+{
+    let _ = FINGERPRINT.set(
+        State {
+            fingerprint: Fingerprint::take(config),
+            checksum_file: config
+                .public_params
+                .checksum_expected_local_path
+                .clone()
+                .into(),
+            on_failures: config
+                .worker
+                .fingerprint_failures,
+        },
+    );
+}
+
+/// The fingerprint of the worker, once taken.
+pub(crate) fn get() -> Option<Fingerprint>
+{
+    let state = FINGERPRINT.get()?;
+    let mut fingerprint = state
+        .fingerprint
+        .clone();
+    if fingerprint
+        .params_checksum
+        .is_none()
+    {
+        fingerprint.params_checksum = params_checksum(&state.checksum_file);
+    }
+    Some(fingerprint)
+}
+
+/// The text appended to the failure replies, empty unless enabled.
+pub(crate) fn failure_suffix() -> String
+{
+    match FINGERPRINT
+        .get()
+        .filter(|state| state.on_failures)
+        .and(get())
+    {
+        Some(fingerprint) =>
+        {
+            format!(
+                ", environment {}",
+                fingerprint.to_json()
+            )
+        },
+        None => String::new(),
+    }
+}
+
+/// The optional features the worker was compiled with.
+pub(crate) fn build_features() -> Vec<String>
+{
+    [
+        (
+            "prover-preprocessing",
+            cfg!(feature = "prover-preprocessing"),
+        ),
+        (
+            "prover-query",
+            cfg!(feature = "prover-query"),
+        ),
+        (
+            "prover-groth16",
+            cfg!(feature = "prover-groth16"),
+        ),
+        (
+            "fault-injection",
+            cfg!(feature = "fault-injection"),
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(feature, _)| feature.to_string())
+    .collect()
+}
+
+impl Fingerprint
+{
+    pub(crate) fn take(config: &Config) -> Self
+    {
+        let cpu_features = CpuFeatures::detect();
+        let strings = |features: Vec<&'static str>| {
+            features
+                .into_iter()
+                .map(str::to_string)
+                .collect()
+        };
+        let build_features = build_features();
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            cpu_model: cpu_model(),
+            kernel: std::fs::read_to_string("/proc/sys/kernel/osrelease")
+                .ok()
+                .map(
+                    |kernel| {
+                        kernel
+                            .trim()
+                            .to_string()
+                    },
+                ),
+            logical_cpus: std::thread::available_parallelism()
+                .map(usize::from)
+                .unwrap_or(1),
+            cpu_quota: cpu_quota::detect_quota(),
+            memory_gb: total_memory_bytes().map(|bytes| bytes as f64 / 1e9),
+            cpu_features: strings(cpu_features.detected()),
+            compiled_cpu_features: strings(cpu_features.required()),
+            allocator: "mimalloc".to_string(),
+            proving_threads: rayon::current_num_threads(),
+            build_features,
+            params_checksum: params_checksum(
+                Path::new(
+                    &config
+                        .public_params
+                        .checksum_expected_local_path,
+                ),
+            ),
+        }
+    }
+
+    /// Reads a fingerprint saved as JSON, e.g. the `environment.json` of a captured task.
+    pub(crate) fn read(path: &Path) -> Result<Self>
+    {
+        let content = std::fs::read(path).with_context(
+            || {
+                format!(
+                    "failed to read `{}`",
+                    path.display()
+                )
+            },
+        )?;
+        serde_json::from_slice(&content).with_context(
+            || {
+                format!(
+                    "`{}` is not a fingerprint",
+                    path.display()
+                )
+            },
+        )
+    }
+
+    pub(crate) fn to_json(&self) -> String
+    {
+        serde_json::to_string(self).expect("fingerprints serialize")
+    }
+
+    /// The fields whose values differ, missing fields being null.
+    pub(crate) fn diff(
+        &self,
+        other: &Fingerprint,
+    ) -> Vec<Difference>
+    {
+        let fields = |fingerprint: &Fingerprint| {
+            match serde_json::to_value(fingerprint).expect("fingerprints serialize")
+            {
+                serde_json::Value::Object(fields) => fields,
+                _ => unreachable!("fingerprints are objects"),
+            }
+        };
+        let left = fields(self);
+        let right = fields(other);
+        left.keys()
+            .chain(right.keys())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .filter_map(
+                |field| {
+                    let left = left
+                        .get(field)
+                        .cloned()
+                        .unwrap_or_default();
+                    let right = right
+                        .get(field)
+                        .cloned()
+                        .unwrap_or_default();
+                    (left != right).then(
+                        || {
+                            Difference {
+                                field: field.clone(),
+                                left,
+                                right,
+                            }
+                        },
+                    )
+                },
+            )
+            .collect()
+    }
+}
+
+fn params_checksum(checksum_file: &Path) -> Option<String>
+{
+    std::fs::read(checksum_file)
+        .ok()
+        .map(|checksums| hex::encode(keccak256(checksums)))
+}
+
+/// The model name of the CPU, or its implementer and part numbers on ARM.
+fn cpu_model() -> Option<String>
+{
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+    let field = |name: &str| {
+        cpuinfo
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == name)
+            .map(
+                |(_, value)| {
+                    value
+                        .trim()
+                        .to_string()
+                },
+            )
+    };
+    field("model name").or_else(
+        || {
+            Some(
+                format!(
+                    "implementer {} part {}",
+                    field("CPU implementer")?,
+                    field("CPU part")?
+                ),
+            )
+        },
+    )
+}
@@ -0,0 +1,479 @@
+//! The warm handoff of the worker to its next version during a rolling upgrade, for the long
+//! proofs in flight, e.g. groth16 ones, not to be abandoned.
+//!
+//! With `[handoff]` set, the running worker listens on the unix socket `socket`. The next version,
+//! started with the same config next to it, connects to it before connecting to the gateways and
+//! asks to take over, the handshake being one JSON line per [`Message`]:
+//! - the next version sends `Request`;
+//! - the running worker stops reading the tasks, as for a maintenance window, completes and replies
+//!   to those it read, then sends its `State`: the reply journal, the replay window and the
+//!   progress of the tables;
+//! - the next version imports them and answers `Imported`, then registers with the gateways;
+//! - the running worker exits, with `exit_reason=handoff` and the code `0`.
+//!
+//! If the next version does not answer `Imported`, the running worker connects to the gateways
+//! again; if the running worker does not send its state within `drain_timeout_secs`, the next
+//! version stops with an error, for its supervisor to start it again later. If nothing listens on
+//! the socket, e.g. on a first start, the next version starts as usual. The session file is shared
+//! as is, the next version reading it once it took over; the persisted counters miss what the
+//! running worker counted since it last saved them.
+//!
+//! The next version takes over before loading its params, the host not having to fit both sets.
+//! The stores kept on disk are shared with it as they are, the config being the same: the
+//! checkpoints of the index tasks are resumed from `index_checkpoints.dir`. The cache of the child
+//! proofs of the queries lives in the query prover, loaded with the params after the takeover, and
+//! is not handed off: a task referencing a child proof without its bytes fails as after a restart,
+//! for the gateway to send it again with the proof.
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use metrics::gauge;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::UnixListener;
+use tokio::net::UnixStream;
+use tokio::sync::watch;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::HandoffConfig;
+use crate::journal;
+use crate::replay;
+use crate::tables;
+
+/// How long the running worker waits for the next version to import its state.
+const IMPORT_TIMEOUT: Duration = Duration::from_secs(60);
+
+static HANDOFF: OnceLock<Handoff> = OnceLock::new();
+
+struct Handoff
+{
+    requested: watch::Sender<bool>,
+    /// The connection of the next version, until the state is sent to it.
+    peer: Mutex<Option<BufReader<UnixStream>>>,
+}
+
+/// The lines of the handshake.
+#[derive(Serialize, Deserialize, Debug)]
+enum Message
+{
+    Request
+    {
+        version: String,
+    },
+    State(State),
+    Imported,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct State
+{
+    version: String,
+    journal: journal::State,
+    replay: Vec<(
+        String,
+        u64,
+    )>,
+    #[serde(default)]
+    tables: Vec<tables::Progress>,
+}
+
+impl State
+{
+    /// The state of this worker.
+    fn export() -> Self
+    {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            journal: journal::export(),
+            replay: replay::export(),
+            tables: tables::export(),
+        }
+    }
+
+    /// Continues from the state of the previous version.
+    fn import(self)
+    {
+        journal::import(self.journal);
+        replay::import(self.replay);
+        tables::import(self.tables);
+    }
+}
+
+pub(crate) fn init()
+{
+    let _ = HANDOFF.set(
+        Handoff {
+            requested: watch::channel(false).0,
+            peer: Mutex::new(None),
+        },
+    );
+}
+
+/// Takes the state of the worker running with the same config over, if there is one, waiting for
+/// it to complete its tasks.
+pub(crate) async fn take_over(config: &HandoffConfig) -> Result<()>
+{
+    let stream = match UnixStream::connect(&config.socket).await
+    {
+        Ok(stream) => stream,
+        Err(err) =>
+        {
+            info!(
+                "No worker to take over from at `{}`: {err}",
+                config.socket
+            );
+            return Ok(());
+        },
+    };
+    info!(
+        "Taking over from the worker at `{}`, waiting for it to complete its tasks",
+        config.socket
+    );
+    let mut peer = BufReader::new(stream);
+    let state = request(
+        &mut peer,
+        Duration::from_secs(config.drain_timeout_secs),
+    )
+    .await?;
+
+    let version = state
+        .version
+        .clone();
+    let replayed = state
+        .replay
+        .len();
+    state.import();
+    send(
+        &mut peer,
+        &Message::Imported,
+    )
+    .await?;
+    info!(
+        "Took over from the worker version {version}, with {replayed} messages of the replay window"
+    );
+    Ok(())
+}
+
+/// Asks the running worker at `peer` for its state, sent once it completed its tasks.
+async fn request(
+    peer: &mut BufReader<UnixStream>,
+    drain_timeout: Duration,
+) -> Result<State>
+{
+    send(
+        peer,
+        &Message::Request {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        },
+    )
+    .await?;
+    let message = tokio::time::timeout(
+        drain_timeout,
+        receive(peer),
+    )
+    .await
+    .context("the running worker did not complete its tasks in time")??;
+    let Message::State(state) = message
+    else
+    {
+        bail!("expected the state of the running worker, got {message:?}");
+    };
+    Ok(state)
+}
+
+/// Listens for the next version of the worker, forever.
+pub(crate) async fn serve(config: HandoffConfig) -> Result<()>
+{
+    let handoff = HANDOFF
+        .get()
+        .context("the handoff is not initialized")?;
+    // The socket of the previous version, which exited, or of a crashed worker.
+    let _ = std::fs::remove_file(&config.socket);
+    let listener = UnixListener::bind(&config.socket).with_context(
+        || {
+            format!(
+                "failed to listen on `{}`",
+                config.socket
+            )
+        },
+    )?;
+    std::fs::set_permissions(
+        Path::new(&config.socket),
+        std::fs::Permissions::from_mode(0o600),
+    )?;
+    gauge!("zkmr_worker_handoff_draining").set(0.0);
+
+    loop
+    {
+        let (stream, _) = listener
+            .accept()
+            .await?;
+        if let Err(err) = accept(
+            handoff,
+            BufReader::new(stream),
+        )
+        .await
+        {
+            warn!("Ignoring a handoff connection: {err}");
+        }
+    }
+}
+
+/// Reads the request of the next version at `peer`, for the worker to complete its tasks.
+async fn accept(
+    handoff: &Handoff,
+    mut peer: BufReader<UnixStream>,
+) -> Result<()>
+{
+    let message = receive(&mut peer).await?;
+    let Message::Request {
+        version,
+    } = message
+    else
+    {
+        bail!("expected a request, got {message:?}");
+    };
+    if *handoff
+        .requested
+        .borrow()
+    {
+        bail!("another handoff than the one to version {version} is in progress");
+    }
+    info!("Handing off to version {version}, completing the tasks read");
+    gauge!("zkmr_worker_handoff_draining").set(1.0);
+    *lock(&handoff.peer) = Some(peer);
+    handoff
+        .requested
+        .send_replace(true);
+    Ok(())
+}
+
+/// Resolves once the next version asked to take over, never without `[handoff]`.
+pub(crate) async fn requested()
+{
+    if let Some(handoff) = HANDOFF.get()
+    {
+        if handoff
+            .requested
+            .subscribe()
+            .wait_for(|requested| *requested)
+            .await
+            .is_ok()
+        {
+            return;
+        }
+    }
+    std::future::pending().await
+}
+
+/// Whether the next version asked to take over.
+pub(crate) fn is_requested() -> bool
+{
+    HANDOFF
+        .get()
+        .is_some_and(
+            |handoff| {
+                *handoff
+                    .requested
+                    .borrow()
+            },
+        )
+}
+
+/// Sends the state to the next version once the tasks read are replied to, returning whether it
+/// imported it and the worker is to exit, else the worker takes tasks again.
+pub(crate) async fn hand_off() -> bool
+{
+    let Some(handoff) = HANDOFF.get()
+    else
+    {
+        return false;
+    };
+    let Some(mut peer) = lock(&handoff.peer).take()
+    else
+    {
+        return false;
+    };
+    let state = State::export();
+    let imported: Result<Message> = async {
+        send(
+            &mut peer,
+            &Message::State(state),
+        )
+        .await?;
+        tokio::time::timeout(
+            IMPORT_TIMEOUT,
+            receive(&mut peer),
+        )
+        .await?
+    }
+    .await;
+    match imported
+    {
+        Ok(Message::Imported) =>
+        {
+            info!("exit_reason=handoff the next version took over");
+            true
+        },
+        other =>
+        {
+            warn!("The next version did not take over, taking tasks again: {other:?}");
+            gauge!("zkmr_worker_handoff_draining").set(0.0);
+            handoff
+                .requested
+                .send_replace(false);
+            false
+        },
+    }
+}
+
+async fn send(
+    peer: &mut BufReader<UnixStream>,
+    message: &Message,
+) -> Result<()>
+{
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    peer.get_mut()
+        .write_all(&line)
+        .await?;
+    Ok(())
+}
+
+async fn receive(peer: &mut BufReader<UnixStream>) -> Result<Message>
+{
+    let mut line = String::new();
+    if peer
+        .read_line(&mut line)
+        .await?
+        == 0
+    {
+        bail!("the other worker closed the connection");
+    }
+    Ok(serde_json::from_str(&line)?)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T>
+{
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use lgn_messages::types::control::JournalStatus;
+
+    use super::*;
+    use crate::config::JournalConfig;
+
+    #[test]
+    fn encodes_the_handshake_as_lines()
+    {
+        let line = serde_json::to_string(
+            &Message::Request {
+                version: "1.2.0".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            line,
+            r#"{"Request":{"version":"1.2.0"}}"#
+        );
+        assert!(
+            matches!(
+                serde_json::from_str::<Message>(r#""Imported""#).unwrap(),
+                Message::Imported
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn hands_the_state_off_to_the_next_version()
+    {
+        init();
+        journal::init(&JournalConfig::default());
+        journal::record(
+            "gateway",
+            "task-1",
+            JournalStatus::Completed,
+            "{}",
+        );
+        let handoff = HANDOFF
+            .get()
+            .unwrap();
+
+        // The next version leaving without importing, the worker takes tasks again.
+        let (running, next) = UnixStream::pair().unwrap();
+        send(
+            &mut BufReader::new(next),
+            &Message::Request {
+                version: "1.2.0".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        accept(
+            handoff,
+            BufReader::new(running),
+        )
+        .await
+        .unwrap();
+        assert!(is_requested());
+        assert!(!hand_off().await);
+        assert!(!is_requested());
+
+        let (running, next) = UnixStream::pair().unwrap();
+        let taking_over = tokio::spawn(
+            async move {
+                let mut peer = BufReader::new(next);
+                let state = request(
+                    &mut peer,
+                    Duration::from_secs(5),
+                )
+                .await?;
+                send(
+                    &mut peer,
+                    &Message::Imported,
+                )
+                .await?;
+                anyhow::Ok(state)
+            },
+        );
+        accept(
+            handoff,
+            BufReader::new(running),
+        )
+        .await
+        .unwrap();
+        assert!(hand_off().await);
+        let state = taking_over
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(
+            state.journal,
+            journal::State::default()
+        );
+        assert_eq!(
+            state.journal,
+            journal::export()
+        );
+        assert_eq!(
+            state.version,
+            env!("CARGO_PKG_VERSION")
+        );
+    }
+}
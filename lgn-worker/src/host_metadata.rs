@@ -0,0 +1,282 @@
+//! The region, zone and instance of the host, discovered at startup to label the metrics and the
+//! logs of the worker, e.g. for the per-region dashboards of a fleet.
+//!
+//! Off by default, for privacy. With `[host_metadata]` set, its `sources` are asked in order, each
+//! filling the fields the previous ones did not:
+//! - `env`: the `LGN_HOST_REGION`, `LGN_HOST_ZONE` and `LGN_HOST_INSTANCE_ID` variables;
+//! - `ec2`: the instance metadata service of AWS, with an IMDSv2 session token;
+//! - `gce`: the metadata server of Google Cloud.
+//!
+//! A source which does not answer within `timeout_ms`, e.g. the endpoint of another cloud, is
+//! skipped with a warning, the worker starting with whatever was found.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use reqwest::Client;
+use reqwest::RequestBuilder;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::HostMetadataConfig;
+use crate::config::HostMetadataSource;
+
+const EC2_METADATA: &str = "http://169.254.169.254/latest";
+
+const GCE_METADATA: &str = "http://metadata.google.internal/computeMetadata/v1/instance";
+
+/// The region, zone and instance of the host, those found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct HostMetadata
+{
+    pub(crate) region: Option<String>,
+    pub(crate) zone: Option<String>,
+    pub(crate) instance_id: Option<String>,
+}
+
+impl HostMetadata
+{
+    /// The labels of the fields found, e.g. `("region", "us-east-1")`.
+    pub(crate) fn labels(
+        &self
+    ) -> Vec<(
+        &'static str,
+        String,
+    )>
+    {
+        [
+            (
+                "region",
+                &self.region,
+            ),
+            (
+                "zone",
+                &self.zone,
+            ),
+            (
+                "instance_id",
+                &self.instance_id,
+            ),
+        ]
+        .into_iter()
+        .filter_map(
+            |(label, value)| {
+                value
+                    .clone()
+                    .map(
+                        |value| {
+                            (
+                                label,
+                                value,
+                            )
+                        },
+                    )
+            },
+        )
+        .collect()
+    }
+
+    fn is_complete(&self) -> bool
+    {
+        self.region
+            .is_some()
+            && self
+                .zone
+                .is_some()
+            && self
+                .instance_id
+                .is_some()
+    }
+
+    /// Fills the fields still missing from `other`.
+    fn fill(
+        &mut self,
+        other: HostMetadata,
+    )
+    {
+        self.region = self
+            .region
+            .take()
+            .or(other.region);
+        self.zone = self
+            .zone
+            .take()
+            .or(other.zone);
+        self.instance_id = self
+            .instance_id
+            .take()
+            .or(other.instance_id);
+    }
+}
+
+/// Looks the metadata of the host up from the sources of `config`.
+pub(crate) async fn discover(config: &HostMetadataConfig) -> HostMetadata
+{
+    let mut metadata = HostMetadata::default();
+    let client = match Client::builder()
+        .timeout(Duration::from_millis(config.timeout_ms))
+        .no_proxy()
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) =>
+        {
+            warn!("Failed to build the host metadata client: {err:?}");
+            return metadata;
+        },
+    };
+    for source in &config.sources
+    {
+        if metadata.is_complete()
+        {
+            break;
+        }
+        let found = match source
+        {
+            HostMetadataSource::Env => Ok(from_env()),
+            HostMetadataSource::Ec2 => from_ec2(&client).await,
+            HostMetadataSource::Gce => from_gce(&client).await,
+        };
+        match found
+        {
+            Ok(found) => metadata.fill(found),
+            Err(err) => warn!("No host metadata from the {source:?} source: {err:?}"),
+        }
+    }
+    info!(
+        "Labelling the metrics and logs with the host metadata {:?}",
+        metadata.labels()
+    );
+    metadata
+}
+
+fn from_env() -> HostMetadata
+{
+    let var = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .filter(|value| !value.is_empty())
+    };
+    HostMetadata {
+        region: var("LGN_HOST_REGION"),
+        zone: var("LGN_HOST_ZONE"),
+        instance_id: var("LGN_HOST_INSTANCE_ID"),
+    }
+}
+
+async fn from_ec2(client: &Client) -> Result<HostMetadata>
+{
+    let token = text(
+        client
+            .put(format!("{EC2_METADATA}/api/token"))
+            .header(
+                "X-aws-ec2-metadata-token-ttl-seconds",
+                "60",
+            ),
+    )
+    .await
+    .context("failed to get an IMDSv2 token")?;
+    let get = |path: &str| {
+        client
+            .get(format!("{EC2_METADATA}/meta-data/{path}"))
+            .header(
+                "X-aws-ec2-metadata-token",
+                &token,
+            )
+    };
+    Ok(
+        HostMetadata {
+            region: Some(text(get("placement/region")).await?),
+            zone: Some(text(get("placement/availability-zone")).await?),
+            instance_id: Some(text(get("instance-id")).await?),
+        },
+    )
+}
+
+async fn from_gce(client: &Client) -> Result<HostMetadata>
+{
+    let get = |path: &str| {
+        client
+            .get(format!("{GCE_METADATA}/{path}"))
+            .header(
+                "Metadata-Flavor",
+                "Google",
+            )
+    };
+    // The zone is answered as `projects/<number>/zones/<zone>`.
+    let zone = text(get("zone")).await?;
+    let zone = zone
+        .rsplit('/')
+        .next()
+        .unwrap_or(&zone)
+        .to_string();
+    Ok(
+        HostMetadata {
+            region: gce_region(&zone),
+            zone: Some(zone),
+            instance_id: Some(text(get("id")).await?),
+        },
+    )
+}
+
+/// The region of a Google Cloud zone, e.g. `us-central1` of `us-central1-a`.
+fn gce_region(zone: &str) -> Option<String>
+{
+    zone.rsplit_once('-')
+        .map(|(region, _)| region.to_string())
+}
+
+async fn text(request: RequestBuilder) -> Result<String>
+{
+    let text = request
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    Ok(
+        text.trim()
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn fills_the_missing_fields_from_the_next_sources()
+    {
+        let mut metadata = HostMetadata {
+            region: Some("eu-west-1".to_string()),
+            ..HostMetadata::default()
+        };
+        metadata.fill(
+            HostMetadata {
+                region: Some("us-central1".to_string()),
+                zone: Some("us-central1-a".to_string()),
+                instance_id: None,
+            },
+        );
+        assert_eq!(
+            metadata.labels(),
+            [
+                (
+                    "region",
+                    "eu-west-1".to_string()
+                ),
+                (
+                    "zone",
+                    "us-central1-a".to_string()
+                ),
+            ]
+        );
+        assert!(!metadata.is_complete());
+        assert_eq!(
+            gce_region("us-central1-a").as_deref(),
+            Some("us-central1")
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! The proofs sent inline in the tasks, e.g. the child proofs of the query aggregations, counted
+//! by digest to tell how much of them is sent again and a cache would spare.
+//!
+//! The digests are computed while the tasks are deserialized, see
+//! [`lgn_messages::streaming_hash`], and only the `MAX_TRACKED` most recent are kept, never the
+//! proofs. A proof whose digest is among them is counted in
+//! `zkmr_worker_inline_proof_duplicates_total{gateway}` and
+//! `zkmr_worker_inline_proof_duplicate_bytes_total{gateway}`, against
+//! `zkmr_worker_inline_proofs_total{gateway}` and `zkmr_worker_inline_proof_bytes_total{gateway}`.
+
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use lgn_messages::streaming_hash::FieldDigest;
+use metrics::counter;
+
+/// The digests kept at most, the least recently seen being forgotten.
+const MAX_TRACKED: usize = 4096;
+
+static RECENT: Mutex<Recent> = Mutex::new(
+    Recent {
+        order: VecDeque::new(),
+        known: BTreeSet::new(),
+    },
+);
+
+struct Recent
+{
+    /// The digests by first sight, the least recent first.
+    order: VecDeque<[u8; 32]>,
+    known: BTreeSet<[u8; 32]>,
+}
+
+/// Counts the inline proofs of a task from `gateway`, the empty ones, i.e. not sent, aside.
+pub(crate) fn record(
+    gateway: &str,
+    digests: &[FieldDigest],
+)
+{
+    let mut recent = RECENT
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    for digest in digests
+        .iter()
+        .filter(|digest| digest.len > 0)
+    {
+        counter!("zkmr_worker_inline_proofs_total", "gateway" => gateway.to_string()).increment(1);
+        counter!("zkmr_worker_inline_proof_bytes_total", "gateway" => gateway.to_string())
+            .increment(digest.len as u64);
+        if recent
+            .known
+            .contains(&digest.hash)
+        {
+            counter!("zkmr_worker_inline_proof_duplicates_total", "gateway" => gateway.to_string())
+                .increment(1);
+            counter!(
+                "zkmr_worker_inline_proof_duplicate_bytes_total",
+                "gateway" => gateway.to_string(),
+            )
+            .increment(digest.len as u64);
+            continue;
+        }
+        recent
+            .known
+            .insert(digest.hash);
+        recent
+            .order
+            .push_back(digest.hash);
+        if recent
+            .order
+            .len()
+            > MAX_TRACKED
+        {
+            if let Some(oldest) = recent
+                .order
+                .pop_front()
+            {
+                recent
+                    .known
+                    .remove(&oldest);
+            }
+        }
+    }
+}
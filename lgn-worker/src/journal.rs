@@ -0,0 +1,374 @@
+//! The journal of the replies the worker sent, for the gateways to reconcile the ones they missed,
+//! e.g. across a network partition.
+//!
+//! Each reply sent to a gRPC gateway is recorded with a sequence number, increasing over the
+//! replies to all the gateways, the id of its task, whether it carries a proof or an error, and
+//! the BLAKE3 digest of the document sent. A gateway asks for the entries of its tasks after the
+//! last sequence number it reconciled with `{"control": {"GetJournalSince": {"seq": <seq>}}}`, see
+//! [`JournalPage`]. The journal is kept in memory, up to `[journal] max_entries` entries of at most
+//! `max_age_secs`, and starts over with the `epoch` of the page when the worker restarts, unless
+//! the next version took it over, see `handoff`.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+use lgn_messages::types::control::JournalEntry;
+use lgn_messages::types::control::JournalPage;
+use lgn_messages::types::control::JournalStatus;
+use lgn_messages::types::control::MAX_JOURNAL_PAGE;
+use metrics::gauge;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use crate::config::JournalConfig;
+use crate::unix_now;
+
+static JOURNAL: OnceLock<Journal> = OnceLock::new();
+
+struct Journal
+{
+    config: JournalConfig,
+    state: Mutex<State>,
+}
+
+/// The entries of the journal, as handed off to the next version of the worker.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct State
+{
+    epoch: u64,
+    /// The sequence number of the last entry, `0` before the first one.
+    last_seq: u64,
+    /// The entries with their gateway, the oldest first.
+    entries: VecDeque<(
+        String,
+        JournalEntry,
+    )>,
+}
+
+pub(crate) fn init(config: &JournalConfig)
+{
+    let _ = JOURNAL.set(
+        Journal::new(
+            config.clone(),
+            unix_now(),
+        ),
+    );
+}
+
+/// Records the reply to the task `task_id` of `gateway`, the `document` sent or the error message.
+pub(crate) fn record(
+    gateway: &str,
+    task_id: &str,
+    status: JournalStatus,
+    document: &str,
+)
+{
+    if let Some(journal) = JOURNAL.get()
+    {
+        journal.record(
+            gateway,
+            task_id,
+            status,
+            document,
+            unix_now(),
+        );
+    }
+}
+
+/// The entries of the journal, for the next version of the worker.
+pub(crate) fn export() -> State
+{
+    JOURNAL
+        .get()
+        .map(
+            |journal| {
+                journal
+                    .lock()
+                    .clone()
+            },
+        )
+        .unwrap_or_default()
+}
+
+/// Continues the journal handed off by the previous version of the worker, before any reply.
+pub(crate) fn import(state: State)
+{
+    if let Some(journal) = JOURNAL.get()
+    {
+        let mut current = journal.lock();
+        *current = state;
+        journal.prune(
+            &mut current,
+            unix_now(),
+        );
+    }
+}
+
+/// The entries of the tasks of `gateway` after `seq`.
+pub(crate) fn since(
+    gateway: &str,
+    seq: u64,
+) -> JournalPage
+{
+    JOURNAL
+        .get()
+        .map(
+            |journal| {
+                journal.since(
+                    gateway,
+                    seq,
+                    unix_now(),
+                )
+            },
+        )
+        .unwrap_or_default()
+}
+
+impl Journal
+{
+    fn new(
+        config: JournalConfig,
+        epoch: u64,
+    ) -> Self
+    {
+        Self {
+            config,
+            state: Mutex::new(
+                State {
+                    epoch,
+                    ..State::default()
+                },
+            ),
+        }
+    }
+
+    fn record(
+        &self,
+        gateway: &str,
+        task_id: &str,
+        status: JournalStatus,
+        document: &str,
+        now: u64,
+    )
+    {
+        let mut state = self.lock();
+        state.last_seq += 1;
+        let entry = JournalEntry {
+            seq: state.last_seq,
+            task_id: task_id.to_string(),
+            status,
+            digest: blake3::hash(document.as_bytes())
+                .to_hex()
+                .to_string(),
+            at_unix: now,
+        };
+        state
+            .entries
+            .push_back(
+                (
+                    gateway.to_string(),
+                    entry,
+                ),
+            );
+        self.prune(
+            &mut state,
+            now,
+        );
+    }
+
+    fn since(
+        &self,
+        gateway: &str,
+        seq: u64,
+        now: u64,
+    ) -> JournalPage
+    {
+        let mut state = self.lock();
+        self.prune(
+            &mut state,
+            now,
+        );
+        JournalPage {
+            epoch: state.epoch,
+            first_seq: state
+                .entries
+                .front()
+                .map_or(
+                    state.last_seq + 1,
+                    |(_, entry)| entry.seq,
+                ),
+            last_seq: state.last_seq,
+            entries: state
+                .entries
+                .iter()
+                .filter(|(entry_gateway, entry)| entry.seq > seq && entry_gateway == gateway)
+                .map(|(_, entry)| entry.clone())
+                .take(MAX_JOURNAL_PAGE)
+                .collect(),
+        }
+    }
+
+    /// Drops the entries past the age or the size limit.
+    fn prune(
+        &self,
+        state: &mut State,
+        now: u64,
+    )
+    {
+        let oldest = now.saturating_sub(
+            self.config
+                .max_age_secs,
+        );
+        while state
+            .entries
+            .front()
+            .is_some_and(
+                |(_, entry)| {
+                    entry.at_unix < oldest
+                        || state
+                            .entries
+                            .len()
+                            > self
+                                .config
+                                .max_entries
+                },
+            )
+        {
+            state
+                .entries
+                .pop_front();
+        }
+        gauge!("zkmr_worker_journal_entries").set(
+            state
+                .entries
+                .len() as f64,
+        );
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State>
+    {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn pages_the_entries_of_a_gateway_within_the_limits()
+    {
+        let journal = Journal::new(
+            JournalConfig {
+                max_entries: 3,
+                max_age_secs: 60,
+            },
+            1000,
+        );
+        for (gateway, task_id, at) in [
+            (
+                "a",
+                "t1",
+                1000,
+            ),
+            (
+                "b",
+                "t2",
+                1030,
+            ),
+            (
+                "a",
+                "t3",
+                1070,
+            ),
+            (
+                "a",
+                "t4",
+                1080,
+            ),
+        ]
+        {
+            journal.record(
+                gateway,
+                task_id,
+                JournalStatus::Completed,
+                task_id,
+                at,
+            );
+        }
+
+        // `t1` expired, the other entries being within a minute.
+        let page = journal.since(
+            "a",
+            0,
+            1080,
+        );
+        assert_eq!(
+            (
+                page.epoch,
+                page.first_seq,
+                page.last_seq
+            ),
+            (
+                1000,
+                2,
+                4
+            )
+        );
+        assert_eq!(
+            page.entries
+                .iter()
+                .map(
+                    |entry| {
+                        entry
+                            .task_id
+                            .as_str()
+                    }
+                )
+                .collect::<Vec<_>>(),
+            [
+                "t3",
+                "t4"
+            ]
+        );
+        assert_eq!(
+            page.entries[1].digest,
+            blake3::hash(b"t4")
+                .to_hex()
+                .to_string()
+        );
+        assert!(
+            journal
+                .since(
+                    "a",
+                    4,
+                    1080
+                )
+                .entries
+                .is_empty()
+        );
+
+        // Past the size limit, the oldest entries are dropped.
+        journal.record(
+            "b",
+            "t5",
+            JournalStatus::Failed,
+            "E1001",
+            1085,
+        );
+        assert_eq!(
+            journal
+                .since(
+                    "b",
+                    0,
+                    1085
+                )
+                .first_seq,
+            3
+        );
+    }
+}
@@ -1 +1,2 @@
 pub mod avs;
+pub mod exit_code;
@@ -0,0 +1,51 @@
+//! Runtime changes of the log filter, requested by the gateway.
+
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use anyhow::Result;
+use lgn_messages::types::ControlMessage;
+use tracing::info;
+use tracing::warn;
+use tracing_subscriber::EnvFilter;
+
+type Reload = Box<dyn Fn(EnvFilter) -> Result<()> + Send + Sync>;
+
+static RELOAD: OnceLock<Reload> = OnceLock::new();
+
+/// Registers how to replace the filter of the installed subscriber.
+pub(crate) fn install(reload: Reload)
+{
+    let _ = RELOAD.set(reload);
+}
+
+/// Applies a control message of the gateway.
+///
+/// An invalid message is logged and ignored, it must not disconnect the worker.
+pub(crate) fn apply(control: &ControlMessage)
+{
+    let result = match control
+    {
+        ControlMessage::SetLogLevel {
+            directives,
+        } => set_log_level(directives),
+    };
+    if let Err(err) = result
+    {
+        warn!("Ignoring control message {control:?}: {err:?}");
+    }
+}
+
+fn set_log_level(directives: &str) -> Result<()>
+{
+    let filter = EnvFilter::builder()
+        .parse(directives)
+        .with_context(|| format!("invalid log directives `{directives}`"))?;
+    let reload = RELOAD
+        .get()
+        .context("the log filter is not reloadable")?;
+    reload(filter)?;
+    info!("Log filter set to `{directives}`");
+
+    Ok(())
+}
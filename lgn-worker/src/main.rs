@@ -2,41 +2,61 @@ use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::net::TcpStream;
 use std::panic;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::result::Result::Ok;
 use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
 use anyhow::*;
 use backtrace::Backtrace;
 use clap::Parser;
+use clap::Subcommand;
 use ethers::signers::Wallet;
 use jwt::Claims;
 use jwt::RegisteredClaims;
 use k256::ecdsa::SigningKey;
-use lagrange::worker_done::Reply;
-use lagrange::WorkerDone;
 use lagrange::WorkerToGwRequest;
 use lagrange::WorkerToGwResponse;
 use lgn_auth::jwt::JWTAuth;
+use lgn_messages::encryption::RecipientKey;
+use lgn_messages::streaming_hash;
+use lgn_messages::types::control::Capabilities;
+use lgn_messages::types::error_code::ErrorCode;
 use lgn_messages::types::DownstreamPayload;
 use lgn_messages::types::MessageEnvelope;
 use lgn_messages::types::MessageReplyEnvelope;
 use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskTimings;
 use lgn_messages::types::TaskType;
 use lgn_messages::types::UpstreamPayload;
+use lgn_messages::types::WorkerError;
+use lgn_provers::params::ParamsError;
+use lgn_provers::params::ParamsLoader;
+use lgn_provers::provers::v1::query::pruned::OutsidePrunedParams;
+use lgn_provers::provers::Cancelled;
 use lgn_worker::avs::utils::read_keystore;
+use lgn_worker::exit_code::ExitClass;
 use metrics::counter;
+use metrics::gauge;
 use mimalloc::MiMalloc;
-use tokio::io::AsyncWriteExt;
-use tokio_stream::StreamExt;
+use rand::rngs::OsRng;
+use tokio::task::JoinSet;
+use tokio_stream::StreamMap;
 use tonic::metadata::MetadataValue;
+use tonic::transport::ClientTlsConfig;
 use tonic::Request;
 use tracing::error;
 use tracing::info;
 use tracing::level_filters::LevelFilter;
 use tracing::span;
 use tracing::trace;
+use tracing::warn;
 use tracing::Level;
 use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::EnvFilter;
@@ -45,26 +65,85 @@ use tungstenite::stream::MaybeTlsStream;
 use tungstenite::Message;
 use tungstenite::WebSocket;
 
+use crate::audit::ReplySigner;
 use crate::checksum::fetch_checksum_file;
 use crate::checksum::verify_directory_checksums;
+use crate::config::AvsConfig;
 use crate::config::Config;
+use crate::config::GrpcCompression;
+use crate::cpu_features::CpuFeatures;
+use crate::fingerprint::Fingerprint;
+use crate::grpc_proxy::ProxyConnector;
+use crate::host_metadata::HostMetadata;
 use crate::manager::v1::register_v1_provers;
 use crate::manager::ProversManager;
+use crate::proof_control::ProofControl;
+use crate::self_test::run_self_test;
 
 pub mod lagrange
 {
     tonic::include_proto!("lagrange");
 }
 
+mod admin;
+mod audit;
+mod bus;
+mod capabilities;
+mod capture;
 mod checksum;
+mod clock;
 mod config;
+mod cpu_features;
+mod cpu_quota;
+mod downgrade;
+mod events;
+mod fairness;
+#[cfg(feature = "fault-injection")]
+mod fault_injection;
+mod fingerprint;
+mod grpc_proxy;
+mod handoff;
+mod host_metadata;
+mod inline_proofs;
+mod journal;
+mod log_control;
+mod maintenance;
 mod manager;
+mod metric_names;
+mod metrics_store;
+mod notary;
+mod offline;
+mod params_audit;
+mod params_layout;
+mod proof_control;
+mod quarantine;
+mod registration;
+mod replay;
+mod reply_size;
+mod report;
+mod retention;
+mod retry;
+#[cfg(feature = "prover-query")]
+mod schema;
+mod self_test;
+mod session;
+mod shadow;
+mod startup;
+#[cfg(feature = "prover-groth16")]
+mod submission;
+mod tables;
+mod task_patch;
+mod task_profile;
+mod tenant;
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
 const MAX_GRPC_MESSAGE_SIZE_MB: usize = 16;
 
+/// Whether the reply proofs are keyed by their content rather than by their semantic key.
+static CONTENT_ADDRESSED_KEYS: OnceLock<bool> = OnceLock::new();
+
 #[derive(Parser, Clone, Debug)]
 struct Cli
 {
@@ -82,13 +161,87 @@ struct Cli
         action
     )]
     json: bool,
+
+    /// Migrate the params directory to the current layout, verifying its files, then exit.
+    #[clap(long)]
+    migrate_params: bool,
+
+    /// Print the URLs the checksums and the params files are downloaded from, then exit.
+    #[clap(long)]
+    print_urls: bool,
+
+    /// Sample the proofs and write a flamegraph and a pprof profile per task type to this
+    /// directory, every minute.
+    #[clap(long)]
+    profile_tasks: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Command
+{
+    /// Smoke test: load the configured params, run one proof per prover category and print a
+    /// pass/fail matrix, without connecting to the gateway. The proofs are checked to be
+    /// well-formed, not verified.
+    SelfTest
+    {
+        /// Directory of recorded task envelopes (JSON) to prove in addition to the synthetic
+        /// tasks, e.g. tabular queries or groth16 tasks.
+        #[clap(
+            short,
+            long
+        )]
+        fixtures: Option<PathBuf>,
+    },
+    /// Benchmark the node and print a capability report signed with the Lagrange key, to submit
+    /// when onboarding to get a recommended instance type.
+    Report
+    {
+        /// Write the report to this file instead of the standard output.
+        #[clap(
+            short,
+            long
+        )]
+        output: Option<PathBuf>,
+        /// Also load the params and time one proof per prover category.
+        #[clap(long)]
+        prove: bool,
+        /// Directory of recorded task envelopes (JSON) to time in addition to the synthetic tasks.
+        #[clap(
+            short,
+            long
+        )]
+        fixtures: Option<PathBuf>,
+    },
+    /// Print a groth16 proof encoded for the on-chain verifier: the `uint256` arrays of the
+    /// groth16 proof and inputs, and the `bytes32[]` chunks of the combined proof.
+    #[cfg(feature = "prover-groth16")]
+    Groth16Calldata
+    {
+        /// File of the raw combined proof, as returned by the groth16 prover.
+        proof: PathBuf,
+    },
+    /// Print the environment fingerprint of this host as JSON, or the differences between two
+    /// fingerprints, e.g. the `environment.json` of a task captured on two machines.
+    Fingerprint
+    {
+        /// The two fingerprint files to compare.
+        #[clap(
+            long,
+            num_args = 2,
+            value_names = ["LEFT", "RIGHT"]
+        )]
+        diff: Option<Vec<PathBuf>>,
+    },
 }
 
 fn setup_logging(json: bool)
 {
     if json
     {
-        let subscriber = tracing_subscriber::fmt()
+        let builder = tracing_subscriber::fmt()
             .json()
             .with_level(true)
             .with_file(true)
@@ -100,12 +253,23 @@ fn setup_logging(json: bool)
                     .from_env_lossy(),
             )
             .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
-            .finish();
-        tracing::subscriber::set_global_default(subscriber).expect("Setting up logging failed");
+            .with_filter_reloading();
+        let handle = builder.reload_handle();
+        log_control::install(
+            Box::new(
+                move |filter: EnvFilter| -> Result<()> {
+                    handle
+                        .reload(filter)
+                        .map_err(|err| anyhow!("{err}"))
+                },
+            ),
+        );
+        tracing::subscriber::set_global_default(builder.finish())
+            .expect("Setting up logging failed");
     }
     else
     {
-        let subscriber = tracing_subscriber::fmt()
+        let builder = tracing_subscriber::fmt()
             .pretty()
             .compact()
             .with_level(true)
@@ -118,16 +282,35 @@ fn setup_logging(json: bool)
                     .from_env_lossy(),
             )
             .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
-            .finish();
-        tracing::subscriber::set_global_default(subscriber).expect("Setting up logging failed");
+            .with_filter_reloading();
+        let handle = builder.reload_handle();
+        log_control::install(
+            Box::new(
+                move |filter: EnvFilter| -> Result<()> {
+                    handle
+                        .reload(filter)
+                        .map_err(|err| anyhow!("{err}"))
+                },
+            ),
+        );
+        tracing::subscriber::set_global_default(builder.finish())
+            .expect("Setting up logging failed");
     };
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()>
+fn main() -> ExitCode
 {
-    let cli = Cli::parse();
+    // First thing, before any code compiled for missing CPU features may run.
+    let cpu_features = CpuFeatures::detect();
+    cpu_features.exit_if_incompatible();
+
+    let mut cli = Cli::parse();
     setup_logging(cli.json);
+    info!(
+        "CPU features. required: {:?}, detected: {:?}",
+        cpu_features.required(),
+        cpu_features.detected()
+    );
 
     panic::set_hook(
         Box::new(
@@ -168,11 +351,15 @@ async fn main() -> anyhow::Result<()>
                     },
                 };
 
+                let environment = fingerprint::get()
+                    .map(|fingerprint| fingerprint.to_json())
+                    .unwrap_or_default();
                 error!(
                     msg,
                     file,
                     lineno,
                     col,
+                    environment,
                     "Panic occurred: {:?}",
                     Backtrace::new(),
                 );
@@ -180,21 +367,48 @@ async fn main() -> anyhow::Result<()>
         ),
     );
 
-    if let Err(err) = run(cli).await
-    {
-        error!(
-            "Service exiting with an error. err: {:?}",
-            err
-        );
-        bail!("Worker exited due to an error")
-    }
-    else
+    // The runtime is built once the config is loaded, its environment being set while the
+    // process is single-threaded.
+    let result = load_config(
+        cli.config
+            .take(),
+    )
+    .and_then(
+        |config| {
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .expect("Building the tokio runtime failed")
+                .block_on(
+                    run(
+                        cli,
+                        config,
+                    ),
+                )
+        },
+    );
+    match result
     {
-        Ok(())
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) =>
+        {
+            let class = ExitClass::of(&err).unwrap_or_default();
+            error!(
+                exit_class = class.name(),
+                exit_code = class.code(),
+                "Service exiting with an error. err: {:?}",
+                err
+            );
+            class.into()
+        },
     }
 }
 
-async fn run(cli: Cli) -> Result<()>
+/// Loads the config, and creates its data directory for the temporary files.
+///
+/// Run before the runtime starts its threads, setting `TMPDIR` being unsound once they may read
+/// the environment.
+fn load_config(config_file: Option<String>) -> Result<Config>
 {
     let version = env!("CARGO_PKG_VERSION");
     info!(
@@ -202,74 +416,778 @@ async fn run(cli: Cli) -> Result<()>
         version
     );
 
-    let config = Config::load(cli.config);
-    config.validate();
+    // The config is validated with assertions, their panics are logged by the hook.
+    let config = panic::catch_unwind(
+        || {
+            let config = Config::load(config_file);
+            config.validate();
+            config
+        },
+    )
+    .map_err(|_| anyhow!(ExitClass::Config).context("exit_reason=config the config is invalid"))?;
     info!(
         "Loaded configuration: {:?}",
         config
     );
+    if let Some(tmp_dir) = config.tmp_dir()
+    {
+        std::fs::create_dir_all(&tmp_dir).with_context(
+            || {
+                format!(
+                    "failed to create the data directory `{}`",
+                    tmp_dir.display()
+                )
+            },
+        )?;
+        // The dependencies write their temporary files there too, e.g. on a read-only root.
+        if std::env::var_os("TMPDIR").is_none()
+        {
+            std::env::set_var(
+                "TMPDIR",
+                &tmp_dir,
+            );
+        }
+    }
 
+    Ok(config)
+}
+
+async fn run(
+    cli: Cli,
+    mut config: Config,
+) -> Result<()>
+{
+    let version = env!("CARGO_PKG_VERSION");
+    if let Some(mirror_url) = &config
+        .public_params
+        .mirror_url
+    {
+        ParamsLoader::prefer_mirror(mirror_url);
+    }
+    let params_urls = params_urls(&config);
+    if cli.print_urls
+    {
+        for (what, url) in params_urls
+        {
+            println!("{what}\t{url}");
+        }
+        return Ok(());
+    }
+    for (what, url) in &params_urls
+    {
+        info!("Downloading the {what} from {url}");
+    }
+    if let Some(deadline) = config
+        .public_params
+        .download_deadline
+    {
+        ParamsLoader::set_download_deadline(Duration::from_secs(deadline));
+    }
+    ParamsLoader::set_max_concurrent_downloads(
+        config
+            .public_params
+            .max_concurrent_downloads,
+    );
+
+    // Before the provers are created, the global thread pool can only be sized once.
+    let cpu_quota = if config
+        .worker
+        .threads
+        .respect_cpu_quota
+        || config
+            .worker
+            .eco_mode
+            .is_some()
+    {
+        cpu_quota::apply(
+            config
+                .worker
+                .threads
+                .respect_cpu_quota,
+            config
+                .worker
+                .eco_mode,
+        )?
+    }
+    else
+    {
+        None
+    };
+
+    if cli.migrate_params
+    {
+        return tokio::task::block_in_place(move || migrate_params(&config));
+    }
+    params_layout::ensure_supported(
+        &config
+            .public_params
+            .dir,
+    )?;
+
+    match cli.command
+    {
+        Some(Command::SelfTest {
+            fixtures,
+        }) =>
+        {
+            return tokio::task::block_in_place(
+                move || {
+                    self_test(
+                        &config,
+                        fixtures.as_deref(),
+                    )
+                },
+            );
+        },
+        Some(Command::Report {
+            output,
+            prove,
+            fixtures,
+        }) =>
+        {
+            return tokio::task::block_in_place(
+                move || {
+                    let provers_manager = prove
+                        .then(|| load_provers(&config))
+                        .transpose()?;
+                    report::run_report(
+                        &config,
+                        provers_manager.as_ref(),
+                        fixtures.as_deref(),
+                        output.as_deref(),
+                    )
+                },
+            );
+        },
+        #[cfg(feature = "prover-groth16")]
+        Some(Command::Groth16Calldata {
+            proof,
+        }) =>
+        {
+            return groth16_calldata(&proof);
+        },
+        Some(Command::Fingerprint {
+            diff,
+        }) =>
+        {
+            return print_fingerprint(
+                &config,
+                diff.as_deref(),
+            );
+        },
+        None =>
+        {},
+    }
+
+    downgrade::apply(&mut config)?;
+    let host = match &config.host_metadata
+    {
+        Some(host_metadata) => host_metadata::discover(host_metadata).await,
+        None => HostMetadata::default(),
+    };
     let span = span!(
         Level::INFO,
         "Starting node",
-        "worker" = config
-            .avs
-            .worker_id
-            .to_string(),
-        "issuer" = config
-            .avs
-            .issuer
-            .to_string(),
         "version" = version,
         "class" = config
             .worker
             .instance_type
             .to_string(),
+        "region" = host
+            .region
+            .as_deref(),
+        "zone" = host
+            .zone
+            .as_deref(),
+        "instance_id" = host
+            .instance_id
+            .as_deref(),
     );
     let _guard = span.enter();
 
-    metrics_exporter_prometheus::PrometheusBuilder::new()
-        .with_http_listener(
+    let mut prometheus = metrics_exporter_prometheus::PrometheusBuilder::new().with_http_listener(
+        (
+            config
+                .prometheus
+                .bind,
+            config
+                .prometheus
+                .port,
+        ),
+    );
+    for net in &config
+        .prometheus
+        .allowlist
+    {
+        prometheus = prometheus.add_allowed_address(net.to_string())?;
+    }
+    for (label, value) in host.labels()
+    {
+        prometheus = prometheus.add_global_label(
+            label,
+            value,
+        );
+    }
+    let (recorder, exporter) = prometheus.build()?;
+    let metrics_handle = recorder.handle();
+    metrics::set_global_recorder(
+        metric_names::DualNamesRecorder::new(
+            recorder,
+            config
+                .prometheus
+                .emit_deprecated_names,
+        ),
+    )
+    .map_err(|_| anyhow!("a metrics recorder is already installed"))?;
+
+    // The subsystems the worker can not run without, any of them stopping stops the worker.
+    let mut subsystems = JoinSet::new();
+    subsystems.spawn(
+        async move {
             (
-                [
-                    0,
-                    0,
-                    0,
-                    0,
-                ],
+                "metrics_exporter",
+                exporter
+                    .await
+                    .map_err(|err| anyhow!("{err:?}")),
+            )
+        },
+    );
+    subsystems.spawn(
+        async move {
+            (
+                "memory_pressure",
+                downgrade::monitor().await,
+            )
+        },
+    );
+    if let Some(admin) = config
+        .admin
+        .clone()
+    {
+        subsystems.spawn(
+            async move {
+                (
+                    "admin",
+                    admin::serve(admin).await,
+                )
+            },
+        );
+    }
+    let cpu_features = CpuFeatures::detect();
+    gauge!(
+        "zkmr_worker_info",
+        "version" => version,
+        "arch" => std::env::consts::ARCH,
+        "cpu_features_required" => cpu_features.required().join(","),
+        "cpu_features_detected" => cpu_features.detected().join(","),
+    )
+    .set(1.0);
+    gauge!("zkmr_worker_proving_threads").set(rayon::current_num_threads() as f64);
+    if let Some(quota) = cpu_quota
+    {
+        gauge!("zkmr_worker_cpu_quota").set(quota);
+    }
+
+    if let Some(path) = &config
+        .prometheus
+        .persist_path
+    {
+        metrics_store::restore(Path::new(path)).context("while restoring the counters")?;
+        metrics_store::spawn_persistence(
+            metrics_handle,
+            PathBuf::from(path),
+            Duration::from_secs(
                 config
                     .prometheus
-                    .port,
+                    .persist_interval_secs,
             ),
-        )
-        .install()?;
+        );
+    }
 
-    if let Some(grpc_url) = &config
-        .avs
-        .gateway_grpc_url
+    retention::spawn_compaction(
+        config
+            .retention
+            .clone(),
+    );
+    quarantine::init(&config);
+    capture::init(&config.debug);
+    lgn_provers::log_sampling::set_max_per_minute(
+        config
+            .debug
+            .sampled_logs_per_minute,
+    );
+    fingerprint::init(&config);
+    tenant::init(&config.tenants);
+    tables::init(&config.tables);
+    journal::init(&config.journal);
+    proof_control::init(
+        config
+            .worker
+            .eco_mode,
+    );
+    startup::init(&config);
+    events::init(
+        config
+            .events
+            .as_ref(),
+    );
+    retry::init(
+        config
+            .worker
+            .transient_retries,
+    );
+    notary::init(
+        config
+            .notary
+            .as_ref(),
+    )?;
+    #[cfg(feature = "prover-groth16")]
+    submission::init(
+        config
+            .submission
+            .as_ref(),
+    )?;
+    task_patch::init(
+        config
+            .task_patches
+            .as_ref(),
+    )?;
+    replay::init(
+        config
+            .worker
+            .replay_window
+            .as_ref(),
+    );
+    clock::init(
+        &config
+            .worker
+            .clock,
+    );
+    // Before the session is read and the params loaded, which the running worker still uses.
+    if let Some(handoff_config) = config
+        .handoff
+        .clone()
     {
-        run_with_grpc(
-            &config,
-            grpc_url,
-        )
-        .await
+        handoff::init();
+        handoff::take_over(&handoff_config)
+            .await
+            .context("while taking over from the running worker")?;
+        subsystems.spawn(
+            async move {
+                (
+                    "handoff",
+                    handoff::serve(handoff_config).await,
+                )
+            },
+        );
+    }
+    // The offline tasks are all proven in turn, none waits for another query, and no gateway
+    // could hand them to another worker during a maintenance window.
+    if config
+        .offline
+        .is_none()
+    {
+        if config
+            .maintenance
+            .is_some()
+        {
+            maintenance::init(
+                config
+                    .maintenance
+                    .as_ref(),
+            );
+            subsystems.spawn(
+                async move {
+                    (
+                        "maintenance",
+                        maintenance::run().await,
+                    )
+                },
+            );
+        }
+        fairness::init(
+            config
+                .worker
+                .fairness
+                .as_ref(),
+        );
+    }
+    task_profile::init(
+        cli.profile_tasks
+            .as_deref(),
+    )?;
+    shadow::init(&config)?;
+    let _ = CONTENT_ADDRESSED_KEYS.set(
+        config
+            .worker
+            .content_addressed_keys,
+    );
+    params_audit::spawn_audit(
+        config
+            .public_params
+            .audit
+            .clone(),
+        config
+            .public_params
+            .dir
+            .clone(),
+        config
+            .public_params
+            .checksum_expected_local_path
+            .clone(),
+    );
+
+    if config
+        .offline
+        .is_none()
+    {
+        session::init(
+            &config
+                .worker
+                .session_file,
+        )?;
+        for avs in &config.avs
+        {
+            registration::maybe_register(
+                &config,
+                avs,
+            )
+            .await?;
+        }
+    }
+
+    let main_loop_code = if config
+        .offline
+        .is_some()
+    {
+        ErrorCode::OfflineTask
     }
     else
     {
-        tokio::task::block_in_place(move || run_with_websocket(&config))
+        ErrorCode::GatewayConnection
+    };
+    let main_loop = async move {
+        if let Some(offline) = config
+            .offline
+            .clone()
+        {
+            tokio::task::spawn_blocking(
+                move || {
+                    offline::run(
+                        &config,
+                        &offline,
+                    )
+                },
+            )
+            .await?
+        }
+        else if config
+            .avs
+            .iter()
+            .all(
+                |avs| {
+                    avs.gateway_grpc_url
+                        .is_some()
+                },
+            )
+        {
+            run_with_grpc(&config).await
+        }
+        else
+        {
+            // Validation ensures that a websocket gateway is the only one configured. The loop
+            // blocks, it runs on its own thread so that the subsystems are still supervised.
+            tokio::task::spawn_blocking(
+                move || {
+                    run_with_websocket(
+                        &config,
+                        &config.avs[0],
+                    )
+                },
+            )
+            .await?
+        }
+    };
+
+    let result = tokio::select! {
+        result = main_loop =>
+        {
+            result.map_err(
+                |err| {
+                    let deadline = err
+                        .chain()
+                        .filter_map(|cause| cause.downcast_ref::<ParamsError>())
+                        .any(
+                            |cause| {
+                                matches!(
+                                    cause,
+                                    ParamsError::DownloadDeadline { .. }
+                                )
+                            },
+                        );
+                    if deadline
+                    {
+                        err.context(
+                            format!(
+                                "exit_reason=params_download_deadline error_code={}",
+                                ErrorCode::ParamsDownloadDeadline
+                            ),
+                        )
+                    }
+                    else
+                    {
+                        let class = ExitClass::of(&err).unwrap_or(main_loop_code.into());
+                        err.context(class)
+                            .context(format!("exit_reason=main_loop error_code={main_loop_code}"))
+                    }
+                },
+            )
+        },
+        Some(joined) = subsystems.join_next() =>
+        {
+            match joined
+            {
+                Ok((name, Ok(()))) =>
+                {
+                    Err(
+                        anyhow!(
+                            "exit_reason={name} error_code={} the subsystem stopped",
+                            ErrorCode::SubsystemStopped
+                        ),
+                    )
+                },
+                Ok((name, Err(err))) =>
+                {
+                    Err(
+                        err.context(
+                            format!(
+                                "exit_reason={name} error_code={}",
+                                ErrorCode::SubsystemStopped
+                            ),
+                        ),
+                    )
+                },
+                Err(err) =>
+                {
+                    Err(
+                        anyhow::Error::new(err).context(
+                            format!(
+                                "exit_reason=subsystem_panic error_code={}",
+                                ErrorCode::SubsystemPanic
+                            ),
+                        ),
+                    )
+                },
+            }
+        },
+    };
+    subsystems
+        .shutdown()
+        .await;
+
+    result
+}
+
+/// What is downloaded, the checksums and the params files of the configured class, and the URLs
+/// it is downloaded from, the mirror first.
+fn params_urls(
+    config: &Config
+) -> Vec<(
+    String,
+    String,
+)>
+{
+    let params = &config.public_params;
+    let mut urls = vec![];
+    if let Some(mirror_url) = ParamsLoader::mirror_url()
+    {
+        if let Some(file_name) = params
+            .checksum_url
+            .rsplit('/')
+            .next()
+        {
+            urls.push(
+                (
+                    "checksums".to_string(),
+                    ParamsLoader::file_url(
+                        mirror_url,
+                        file_name,
+                    ),
+                ),
+            );
+        }
     }
+    urls.push(
+        (
+            "checksums".to_string(),
+            params
+                .checksum_url
+                .clone(),
+        ),
+    );
+    for file_name in manager::v1::params_files(config)
+    {
+        for base_url in ParamsLoader::mirror_url()
+            .into_iter()
+            .chain(
+                [
+                    params
+                        .url
+                        .as_str(),
+                ],
+            )
+        {
+            urls.push(
+                (
+                    format!("params `{file_name}`"),
+                    ParamsLoader::file_url(
+                        base_url,
+                        file_name,
+                    ),
+                ),
+            );
+        }
+    }
+    urls
 }
 
-async fn maybe_verify_checksums(config: &Config) -> Result<()>
+/// Migrates the params directory to the current layout, instead of downloading the params again.
+fn migrate_params(config: &Config) -> Result<()>
 {
-    if config
+    let params = &config.public_params;
+    let checksum_file = if params.skip_checksum
+    {
+        None
+    }
+    else
+    {
+        fetch_checksum_file(
+            &params.checksum_url,
+            &params.checksum_expected_local_path,
+        )?;
+        Some(Path::new(&params.checksum_expected_local_path))
+    };
+
+    params_layout::migrate(
+        &params.dir,
+        checksum_file,
+    )
+}
+
+fn self_test(
+    config: &Config,
+    fixtures: Option<&std::path::Path>,
+) -> Result<()>
+{
+    let provers_manager = load_provers(config)?;
+    run_self_test(
+        config,
+        &provers_manager,
+        fixtures,
+    )
+}
+
+/// Prints the calldata of the combined groth16 proof stored in `proof`.
+#[cfg(feature = "prover-groth16")]
+fn groth16_calldata(proof: &Path) -> Result<()>
+{
+    let proof = std::fs::read(proof).with_context(
+        || {
+            format!(
+                "failed to read `{}`",
+                proof.display()
+            )
+        },
+    )?;
+    let calldata = lgn_provers::provers::v1::groth16::calldata::encode(&proof)?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&calldata)?
+    );
+    Ok(())
+}
+
+fn print_fingerprint(
+    config: &Config,
+    diff: Option<&[PathBuf]>,
+) -> Result<()>
+{
+    let Some([left, right]) = diff
+    else
+    {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&Fingerprint::take(config))?
+        );
+        return Ok(());
+    };
+    let differences = Fingerprint::read(left)?.diff(&Fingerprint::read(right)?);
+    if differences.is_empty()
+    {
+        println!("The fingerprints are identical");
+    }
+    for difference in differences
+    {
+        println!(
+            "{}: {} -> {}",
+            difference.field, difference.left, difference.right
+        );
+    }
+    Ok(())
+}
+
+/// Loads the provers of the worker class, verifying their params, without connecting to the
+/// gateway.
+fn load_provers(config: &Config) -> Result<ProversManager<TaskType, ReplyType>>
+{
+    let expected_checksums_file = &config
+        .public_params
+        .checksum_expected_local_path;
+    fetch_checksum_file(
+        &config
+            .public_params
+            .checksum_url,
+        expected_checksums_file,
+    )?;
+
+    let mut provers_manager = ProversManager::<TaskType, ReplyType>::new();
+    register_v1_provers(
+        config,
+        &mut provers_manager,
+    )
+    .context("while registering provers")?;
+
+    if !config
         .public_params
         .skip_checksum
     {
-        return Ok(());
+        verify_directory_checksums(
+            &config
+                .public_params
+                .dir,
+            expected_checksums_file,
+        )
+        .context("Public parameters verification failed")?;
     }
 
-    info!("Verifying the checksums");
+    Ok(provers_manager)
+}
+
+async fn maybe_verify_checksums(config: &Config) -> Result<()>
+{
+    // The replies are signed along with the checksums, which are fetched for it even if the params
+    // are not verified.
+    let signs_replies = config
+        .avs
+        .iter()
+        .any(|avs| avs.sign_replies);
+    let skip_checksum = config
+        .public_params
+        .skip_checksum;
+    if skip_checksum && !signs_replies
+    {
+        return Ok(());
+    }
 
     // Fetch checksum file
     // The checksum file can be generated in two ways.
@@ -283,20 +1201,20 @@ async fn maybe_verify_checksums(config: &Config) -> Result<()>
         .public_params
         .checksum_expected_local_path;
 
-    let response = reqwest::get(checksum_url)
-        .await
-        .context("Failed to fetch checksum file")?
-        .text()
-        .await
-        .context("Failed to read response text")?;
-
-    tokio::fs::File::create(expected_checksums_file)
-        .await
-        .context("Failed to create local checksum file")?
-        .write_all(response.as_bytes())
-        .await
-        .context("Failed to write checksum file")?;
+    tokio::task::block_in_place(
+        || {
+            fetch_checksum_file(
+                checksum_url,
+                expected_checksums_file,
+            )
+        },
+    )?;
+    if skip_checksum
+    {
+        return Ok(());
+    }
 
+    info!("Verifying the checksums");
     verify_directory_checksums(
         &config
             .public_params
@@ -308,15 +1226,44 @@ async fn maybe_verify_checksums(config: &Config) -> Result<()>
     .context("Failed to verify checksums")
 }
 
-async fn run_with_grpc(
-    config: &Config,
-    grpc_url: &str,
-) -> Result<()>
+/// A gateway connection served over gRPC.
+struct GrpcGateway<'a>
 {
-    let uri = grpc_url.parse::<tonic::transport::Uri>()?;
-    let (mut outbound, outbound_rx) = tokio::sync::mpsc::channel(1024);
+    avs: &'a AvsConfig,
+    signer: Option<ReplySigner>,
+    /// The sender of the messages to the gateway, replaced when it is connected to again.
+    outbound: std::sync::Mutex<tokio::sync::mpsc::Sender<WorkerToGwRequest>>,
+    /// The negotiated maximum size of a gRPC message, in bytes.
+    max_message_size: usize,
+}
 
-    let mut provers_manager = tokio::task::block_in_place(
+impl GrpcGateway<'_>
+{
+    /// The sender of the messages to the gateway over its current stream.
+    fn outbound(&self) -> tokio::sync::mpsc::Sender<WorkerToGwRequest>
+    {
+        self.outbound
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Sends the next messages over the stream the gateway was connected to again with.
+    fn reconnected(
+        &self,
+        outbound: tokio::sync::mpsc::Sender<WorkerToGwRequest>,
+    )
+    {
+        *self
+            .outbound
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = outbound;
+    }
+}
+
+async fn run_with_grpc(config: &Config) -> Result<()>
+{
+    let provers_manager = tokio::task::block_in_place(
         move || -> Result<ProversManager<TaskType, ReplyType>> {
             let mut provers_manager = ProversManager::<TaskType, ReplyType>::new();
             register_v1_provers(
@@ -328,50 +1275,195 @@ async fn run_with_grpc(
         },
     )?;
 
-    maybe_verify_checksums(config).await?;
+    maybe_verify_checksums(config).await?;
+    let capabilities = capabilities::of(
+        config,
+        &provers_manager,
+    );
+
+    // The gateways are connected to again after each maintenance window the worker disconnects
+    // during, the tasks only ending without an error then.
+    loop
+    {
+        tokio::select! {
+            () = maintenance::connectable() => {},
+            () = handoff::requested() => {},
+        }
+        if !handoff::is_requested()
+        {
+            serve_grpc_gateways(
+                config,
+                &provers_manager,
+                &capabilities,
+            )
+            .await?;
+        }
+        // The tasks read are replied to, the next version can take over.
+        if handoff::is_requested()
+        {
+            if handoff::hand_off().await
+            {
+                return Ok(());
+            }
+            continue;
+        }
+        info!("Disconnected from the gateways until the end of the maintenance window");
+    }
+}
+
+/// Connects to the gRPC gateways and handles their tasks, see [`bus::run`].
+async fn serve_grpc_gateways(
+    config: &Config,
+    provers_manager: &ProversManager<TaskType, ReplyType>,
+    capabilities: &Capabilities,
+) -> Result<()>
+{
+    // The provers are shared by all the gateways. Tasks are proven one at a time, and the
+    // inbound streams are polled starting from a random one, so that a busy gateway can not
+    // starve the others.
+    let mut inbounds = StreamMap::new();
+    let mut gateways = Vec::with_capacity(
+        config
+            .avs
+            .len(),
+    );
+    for (index, avs) in config
+        .avs
+        .iter()
+        .enumerate()
+    {
+        let (outbound, inbound) = connect_to_grpc_gateway(
+            config,
+            avs,
+        )
+        .await
+        .with_context(
+            || {
+                format!(
+                    "while connecting to gateway `{}`",
+                    avs.label()
+                )
+            },
+        )?;
+        inbounds.insert(
+            index,
+            Box::pin(inbound) as bus::InboundStream,
+        );
+        gateways.push(
+            GrpcGateway {
+                avs,
+                signer: maybe_reply_signer(
+                    config,
+                    avs,
+                )?,
+                outbound: std::sync::Mutex::new(outbound),
+                max_message_size: max_grpc_message_size(avs),
+            },
+        );
+    }
+
+    bus::run(
+        config,
+        provers_manager,
+        capabilities,
+        &gateways,
+        inbounds,
+    )
+    .await
+}
 
-    info!("Connecting to Gateway at uri `{uri}`");
-    let outbound_rx = tokio_stream::wrappers::ReceiverStream::new(outbound_rx);
+/// Opens the task stream with a gateway and announces the worker as ready.
+///
+/// Returns the sender for the replies and the stream of incoming tasks.
+async fn connect_to_grpc_gateway(
+    config: &Config,
+    avs: &AvsConfig,
+) -> Result<(
+    tokio::sync::mpsc::Sender<WorkerToGwRequest>,
+    tonic::Streaming<WorkerToGwResponse>,
+)>
+{
+    let grpc_url = avs
+        .gateway_grpc_url
+        .as_deref()
+        .context("missing gRPC URL")?;
+    let uri = grpc_url.parse::<tonic::transport::Uri>()?;
+
+    info!(
+        "Connecting to Gateway `{}` at uri `{uri}`",
+        avs.label()
+    );
 
-    let wallet = get_wallet(config)?;
-    let claims = get_claims(config)?;
+    let wallet = get_wallet(avs).context(ExitClass::Auth)?;
+    let claims = get_claims(
+        config,
+        avs,
+    )?;
     let token = JWTAuth::new(
         claims,
         &wallet,
     )?
     .encode()?;
 
-    let channel = tonic::transport::Channel::builder(uri)
-        .connect()
-        .await?;
+    let mut endpoint = tonic::transport::Channel::builder(uri.clone());
+    if uri.scheme_str() == Some("https")
+    {
+        endpoint = endpoint.tls_config(ClientTlsConfig::new().with_webpki_roots())?;
+    }
+    let channel = match &config
+        .network
+        .grpc_proxy
+    {
+        Some(grpc_proxy) =>
+        {
+            info!(
+                "Tunnelling to Gateway `{}` through the proxy `{}`",
+                avs.label(),
+                grpc_proxy.url
+            );
+            endpoint
+                .connect_with_connector(ProxyConnector::new(grpc_proxy)?)
+                .await?
+        },
+        None =>
+        {
+            endpoint
+                .connect()
+                .await?
+        },
+    };
     let token: MetadataValue<_> = format!("Bearer {token}").parse()?;
 
-    let max_message_size = config
-        .avs
-        .max_grpc_message_size_mb
-        .unwrap_or(MAX_GRPC_MESSAGE_SIZE_MB)
-        * 1024
-        * 1024;
-
-    let mut client = lagrange::workers_service_client::WorkersServiceClient::with_interceptor(
-        channel,
-        move |mut req: Request<()>| {
-            req.metadata_mut()
-                .insert(
-                    "authorization",
-                    token.clone(),
-                );
-            Ok(req)
-        },
+    let (outbound, inbound) = match open_grpc_stream(
+        avs,
+        channel.clone(),
+        token.clone(),
+        avs.grpc_compression,
     )
-    .max_decoding_message_size(max_message_size)
-    .max_encoding_message_size(max_message_size);
-
-    let response = client
-        .worker_to_gw(tonic::Request::new(outbound_rx))
-        .await?;
-
-    let mut inbound = response.into_inner();
+    .await
+    {
+        Err(status)
+            if avs
+                .grpc_compression
+                .is_some()
+                && status.code() == tonic::Code::Unimplemented =>
+        {
+            warn!(
+                "Gateway `{}` does not support {:?} compression, falling back to uncompressed \
+                 messages: {status}",
+                avs.label(),
+                avs.grpc_compression
+            );
+            open_grpc_stream(
+                avs,
+                channel,
+                token,
+                None,
+            )
+            .await?
+        },
+        result => result?,
+    };
 
     outbound
         .send(
@@ -390,59 +1482,412 @@ async fn run_with_grpc(
             },
         )
         .await?;
+    gauge!("zkmr_worker_gateway_connected", "gateway" => avs.label().to_string()).set(1.0);
 
-    loop
+    Ok(
+        (
+            outbound,
+            inbound,
+        ),
+    )
+}
+
+/// Opens the bidirectional task stream over `channel`, compressing the messages with
+/// `compression` if set.
+async fn open_grpc_stream(
+    avs: &AvsConfig,
+    channel: tonic::transport::Channel,
+    token: MetadataValue<tonic::metadata::Ascii>,
+    compression: Option<GrpcCompression>,
+) -> Result<
+    (
+        tokio::sync::mpsc::Sender<WorkerToGwRequest>,
+        tonic::Streaming<WorkerToGwResponse>,
+    ),
+    tonic::Status,
+>
+{
+    let (outbound, outbound_rx) = tokio::sync::mpsc::channel(1024);
+    let outbound_rx = tokio_stream::wrappers::ReceiverStream::new(outbound_rx);
+    let max_message_size = max_grpc_message_size(avs);
+
+    let mut client = lagrange::workers_service_client::WorkersServiceClient::with_interceptor(
+        channel,
+        move |mut req: Request<()>| {
+            req.metadata_mut()
+                .insert(
+                    "authorization",
+                    token.clone(),
+                );
+            Ok(req)
+        },
+    )
+    .max_decoding_message_size(max_message_size)
+    .max_encoding_message_size(max_message_size)
+    // Compressed replies from the gateway are accepted whatever the worker sends.
+    .accept_compressed(tonic::codec::CompressionEncoding::Gzip)
+    .accept_compressed(tonic::codec::CompressionEncoding::Zstd);
+    if let Some(compression) = compression
     {
-        tokio::select! {
-            Some(inbound_message) = inbound.next() => {
-                let msg = match inbound_message {
-                    Ok(ref msg) => msg,
-                    Err(e) => {
-                        error!("connection to the gateway ended with status: {e}");
-                        break;
-                    }
-                };
-                process_message_from_gateway(&mut provers_manager, msg, &mut outbound).await?;
-            }
-            else => break,
-        }
+        client = client.send_compressed(compression.encoding());
     }
 
-    Ok(())
+    let mut request = tonic::Request::new(outbound_rx);
+    session::annotate(
+        avs.label(),
+        request.metadata_mut(),
+    );
+    let sent_at = SystemTime::now();
+    let response = match client
+        .worker_to_gw(request)
+        .await
+    {
+        Ok(response) => response,
+        Err(status) =>
+        {
+            // A token refused as issued in the future is issued again at the time of the gateway.
+            clock::observe_metadata(
+                avs.label(),
+                sent_at,
+                status.metadata(),
+            );
+            return Err(status);
+        },
+    };
+    clock::observe_metadata(
+        avs.label(),
+        sent_at,
+        response.metadata(),
+    );
+    session::record(
+        avs.label(),
+        response.metadata(),
+    );
+    gauge!(
+        "zkmr_worker_grpc_compression",
+        "gateway" => avs.label().to_string(),
+        "encoding" => compression.map_or("none".to_string(), |c| format!("{c:?}").to_lowercase()),
+    )
+    .set(1.0);
+
+    Ok(
+        (
+            outbound,
+            response.into_inner(),
+        ),
+    )
+}
+
+/// The maximum size of the gRPC messages exchanged with a gateway, in bytes.
+fn max_grpc_message_size(avs: &AvsConfig) -> usize
+{
+    avs.max_grpc_message_size_mb
+        .unwrap_or(MAX_GRPC_MESSAGE_SIZE_MB)
+        * 1024
+        * 1024
+}
+
+/// Builds the reply signer if reply signing is enabled in the configuration.
+fn maybe_reply_signer(
+    config: &Config,
+    avs: &AvsConfig,
+) -> Result<Option<ReplySigner>>
+{
+    if !avs.sign_replies
+    {
+        return Ok(None);
+    }
+
+    let signer = ReplySigner::new(
+        get_wallet(avs)?,
+        &config
+            .public_params
+            .checksum_expected_local_path,
+    )?;
+    info!(
+        "Replies to gateway `{}` will be signed with the Lagrange key",
+        avs.label()
+    );
+
+    Ok(Some(signer))
 }
 
 fn process_downstream_payload(
     provers_manager: &ProversManager<TaskType, ReplyType>,
+    gateway: &str,
+    signer: Option<&ReplySigner>,
     envelope: MessageEnvelope<TaskType>,
+    mut timings: TaskTimings,
 ) -> Result<MessageReplyEnvelope<ReplyType>, String>
 {
     let span = span!(
         Level::INFO,
         "Received Task",
+        "gateway" = gateway,
         "query_id" = envelope.query_id,
         "task_id" = envelope.task_id,
         "db_id" = ?envelope.db_task_id,
+        "trace_id" = ?envelope.trace_id,
+        "tenant" = ?envelope.tenant,
     );
     let _guard = span.enter();
+    let tenant = tenant::label(
+        envelope
+            .tenant
+            .as_deref(),
+    );
 
     trace!(
         "Received task. envelope: {:?}",
         envelope
     );
-    counter!("zkmr_worker_tasks_received_total").increment(1);
-    match std::panic::catch_unwind(|| provers_manager.delegate_proving(&envelope))
+    counter!(
+        "zkmr_worker_tasks_received_total",
+        "gateway" => gateway.to_string(),
+        "tenant" => tenant.clone(),
+    )
+    .increment(1);
+
+    if params_audit::is_draining()
+    {
+        counter!(
+            "zkmr_worker_error_count",
+            "error_type" => ErrorCode::ParamsCorrupted.label(),
+            "error_code" => ErrorCode::ParamsCorrupted.to_string(),
+            "gateway" => gateway.to_string(),
+            "tenant" => tenant.clone(),
+        )
+        .increment(1);
+        return Err(
+            ErrorCode::ParamsCorrupted
+                .annotate("the params of the worker are corrupted, it is draining"),
+        );
+    }
+
+    if let Some(ends_at_unix) = maintenance::draining_until()
+    {
+        counter!(
+            "zkmr_worker_error_count",
+            "error_type" => ErrorCode::Maintenance.label(),
+            "error_code" => ErrorCode::Maintenance.to_string(),
+            "gateway" => gateway.to_string(),
+            "tenant" => tenant.clone(),
+        )
+        .increment(1);
+        return Err(
+            ErrorCode::Maintenance
+                .annotate(format!("the worker is in its maintenance window until {ends_at_unix}")),
+        );
+    }
+
+    if envelope.deadline_exceeded(clock::gateway_now(gateway))
+    {
+        let err = WorkerError::DeadlineExceeded {
+            task_id: envelope
+                .task_id
+                .clone(),
+            deadline_unix: envelope
+                .deadline_unix
+                .unwrap_or_default(),
+        };
+        warn!("Refusing task: {err}");
+        counter!(
+            "zkmr_worker_error_count",
+            "error_type" => err.as_code().label(),
+            "error_code" => err.as_code().to_string(),
+            "gateway" => gateway.to_string(),
+            "tenant" => tenant.clone(),
+        )
+        .increment(1);
+        return Err(
+            err.as_code()
+                .annotate(&err),
+        );
+    }
+
+    let prover_type = reply_size::prover_type(&envelope.inner);
+    if let Err(err) = quarantine::admit(prover_type)
+    {
+        warn!("Refusing task: {err}");
+        counter!(
+            "zkmr_worker_error_count",
+            "error_type" => ErrorCode::Quarantined.label(),
+            "error_code" => ErrorCode::Quarantined.to_string(),
+            "gateway" => gateway.to_string(),
+            "tenant" => tenant.clone(),
+        )
+        .increment(1);
+        return Err(ErrorCode::Quarantined.annotate(err));
+    }
+
+    if let Err(err) = fairness::admit(
+        &envelope.query_id,
+        &envelope.task_id,
+    )
+    {
+        warn!("Refusing task: {err}");
+        counter!(
+            "zkmr_worker_fairness_throttled_total",
+            "gateway" => gateway.to_string(),
+            "tenant" => tenant.clone(),
+        )
+        .increment(1);
+        counter!(
+            "zkmr_worker_error_count",
+            "error_type" => err.as_code().label(),
+            "error_code" => err.as_code().to_string(),
+            "gateway" => gateway.to_string(),
+            "tenant" => tenant.clone(),
+        )
+        .increment(1);
+        return Err(
+            err.as_code()
+                .annotate(&err),
+        );
+    }
+
+    // Checked before proving, for a task the proof can not be encrypted for not to waste a proof.
+    let recipient = match envelope
+        .recipient_pubkey
+        .as_deref()
+        .map(str::parse::<RecipientKey>)
+        .transpose()
+    {
+        Ok(recipient) => recipient,
+        Err(err) =>
+        {
+            warn!("Refusing task: {err}");
+            counter!(
+                "zkmr_worker_error_count",
+                "error_type" => ErrorCode::MalformedTask.label(),
+                "error_code" => ErrorCode::MalformedTask.to_string(),
+                "gateway" => gateway.to_string(),
+                "tenant" => tenant.clone(),
+            )
+            .increment(1);
+            return Err(ErrorCode::MalformedTask.annotate(err));
+        },
+    };
+
+    let task_capture = capture::start(&envelope);
+    let task_profile = task_profile::start(
+        &prover_type.map_or_else(
+            || "unknown".to_string(),
+            |prover_type| prover_type.to_string(),
+        ),
+    );
+    let proving = Instant::now();
+    let (result, attempts) = retry::prove(
+        provers_manager,
+        &envelope,
+        &ProofControl::new(&envelope.task_id),
+    );
+    if let Some(task_profile) = task_profile
+    {
+        task_profile.finish();
+    }
+    timings.prove_ms = Some(
+        proving
+            .elapsed()
+            .as_millis() as u64,
+    );
+    if let Some(task_capture) = task_capture
+    {
+        task_capture.finish(
+            match &result
+            {
+                Ok(Ok(reply)) => Ok(reply),
+                Ok(Err(err)) => Err(format!("{err:?}")),
+                Err(_) => Err("the prover panicked".to_string()),
+            },
+        );
+    }
+    // A task refused by the pruned params says nothing of the health of the prover.
+    quarantine::record(
+        prover_type,
+        matches!(
+            &result,
+            Ok(Ok(_))
+        ) || matches!(
+            &result,
+            Ok(Err(err)) if err.chain().any(|cause| cause.is::<OutsidePrunedParams>())
+        ),
+    );
+    shadow::submit(
+        &envelope,
+        &result,
+    );
+    match result
     {
         Ok(result) =>
         {
             match result
             {
-                Ok(reply) =>
+                Ok(mut reply) =>
                 {
+                    tables::record(&envelope.inner);
+                    reply.set_trace_id(
+                        envelope
+                            .trace_id
+                            .clone(),
+                    );
+                    reply.set_attempts(attempts);
+                    reply.set_timings(timings);
+                    // Before keying the proof by its content, for the key not to reveal the hash
+                    // of the plaintext.
+                    if let Some(recipient) = &recipient
+                    {
+                        reply
+                            .content_mut()
+                            .encrypt_proof(
+                                recipient,
+                                &mut OsRng,
+                            )
+                            .map_err(|e| ErrorCode::ProofProcessing.annotate(format!("{e:?}")))?;
+                    }
+                    if CONTENT_ADDRESSED_KEYS
+                        .get()
+                        .copied()
+                        .unwrap_or_default()
+                    {
+                        reply
+                            .content_mut()
+                            .address_by_content();
+                    }
+                    // Proving can not be interrupted, a late proof is still sent as the gateway
+                    // may make use of it, but the miss is recorded.
+                    if envelope.deadline_exceeded(clock::gateway_now(gateway))
+                    {
+                        warn!(
+                            "Task completed after its deadline. deadline: {:?}",
+                            envelope.deadline_unix
+                        );
+                        counter!(
+                            "zkmr_worker_tasks_deadline_missed_total",
+                            "gateway" => gateway.to_string(),
+                            "tenant" => tenant.clone(),
+                        )
+                        .increment(1);
+                    }
+                    if let Some(signer) = signer
+                    {
+                        signer
+                            .sign(&mut reply)
+                            .map_err(|e| ErrorCode::ReplySigning.annotate(format!("{e:?}")))?;
+                    }
+                    notary::submit(&reply);
+                    #[cfg(feature = "prover-groth16")]
+                    submission::submit(&reply);
                     trace!(
                         "Sending reply: {:?}",
                         reply
                     );
-                    counter!("zkmr_worker_tasks_processed_total").increment(1);
+                    counter!(
+                        "zkmr_worker_gateway_tasks_processed_total",
+                        "gateway" => gateway.to_string(),
+                        "tenant" => tenant.clone(),
+                    )
+                    .increment(1);
                     Ok(reply)
                 },
                 Err(e) =>
@@ -451,10 +1896,46 @@ fn process_downstream_payload(
                         "Error processing task: {:?}",
                         e
                     );
-                    counter!("zkmr_worker_error_count", "error_type" =>  "proof processing")
-                        .increment(1);
+                    // Distinguish broken params, which need operator action, from bad tasks. The
+                    // proofs are only cancelled once the params are found corrupted.
+                    let code = if e
+                        .chain()
+                        .any(|cause| cause.is::<Cancelled>())
+                    {
+                        ErrorCode::ParamsCorrupted
+                    }
+                    else if e
+                        .chain()
+                        .any(|cause| cause.is::<OutsidePrunedParams>())
+                    {
+                        ErrorCode::QueryPruned
+                    }
+                    else
+                    {
+                        e.chain()
+                            .find_map(|cause| cause.downcast_ref::<ParamsError>())
+                            .map_or(
+                                ErrorCode::ProofProcessing,
+                                ParamsError::as_code,
+                            )
+                    };
+                    counter!(
+                        "zkmr_worker_error_count",
+                        "error_type" => code.label(),
+                        "error_code" => code.to_string(),
+                        "gateway" => gateway.to_string(),
+                        "tenant" => tenant.clone(),
+                    )
+                    .increment(1);
 
-                    Err(format!("{e:?}"))
+                    Err(
+                        code.annotate(
+                            format!(
+                                "{e:?}, after {attempts} attempt(s){}",
+                                fingerprint::failure_suffix()
+                            ),
+                        ),
+                    )
                 },
             }
         },
@@ -462,7 +1943,10 @@ fn process_downstream_payload(
         {
             counter!(
                 "zkmr_worker_error_count",
-                "error_type" => "proof_processing"
+                "error_type" => ErrorCode::ProverPanic.label(),
+                "error_code" => ErrorCode::ProverPanic.to_string(),
+                "gateway" => gateway.to_string(),
+                "tenant" => tenant.clone(),
             )
             .increment(1);
 
@@ -484,100 +1968,33 @@ fn process_downstream_payload(
                 envelope.id()
             );
             Err(
-                format!(
-                    "{}: {msg}",
-                    envelope.id()
+                ErrorCode::ProverPanic.annotate(
+                    format!(
+                        "{}: {msg}{}",
+                        envelope.id(),
+                        fingerprint::failure_suffix()
+                    ),
                 ),
             )
         },
     }
 }
 
-async fn process_message_from_gateway(
-    provers_manager: &mut ProversManager<TaskType, ReplyType>,
-    message: &WorkerToGwResponse,
-    outbound: &mut tokio::sync::mpsc::Sender<WorkerToGwRequest>,
-) -> Result<()>
+/// The current time, in seconds since the Unix epoch.
+fn unix_now() -> u64
 {
-    match &message.response
-    {
-        Some(response) =>
-        {
-            match response
-            {
-                lagrange::worker_to_gw_response::Response::Todo(json_document) =>
-                {
-                    let message_envelope =
-                        serde_json::from_str::<MessageEnvelope<TaskType>>(json_document)?;
-
-                    let reply = tokio::task::block_in_place(
-                        move || -> Result<MessageReplyEnvelope<ReplyType>, String> {
-                            process_downstream_payload(
-                                provers_manager,
-                                message_envelope,
-                            )
-                        },
-                    );
-
-                    let outbound_msg = match reply
-                    {
-                        Ok(reply) =>
-                        {
-                            WorkerToGwRequest {
-                                request: Some(
-                                    lagrange::worker_to_gw_request::Request::WorkerDone(
-                                        WorkerDone {
-                                            reply: Some(
-                                                Reply::ReplyString(serde_json::to_string(&reply)?),
-                                            ),
-                                        },
-                                    ),
-                                ),
-                            }
-                        },
-                        Err(error_str) =>
-                        {
-                            WorkerToGwRequest {
-                                request: Some(
-                                    lagrange::worker_to_gw_request::Request::WorkerDone(
-                                        WorkerDone {
-                                            reply: Some(Reply::WorkerError(error_str)),
-                                        },
-                                    ),
-                                ),
-                            }
-                        },
-                    };
-                    outbound
-                        .send(outbound_msg)
-                        .await?;
-
-                    counter!("zkmr_worker_grpc_messages_sent_total",
-                                    "message_type" => "text")
-                    .increment(1);
-                },
-            }
-        },
-        None =>
-        {
-            tracing::warn!("Received WorkerToGwReponse with empty reponse field");
-        },
-    }
-    Ok(())
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Epoch can not be in the future")
+        .as_secs()
 }
 
-fn get_wallet(config: &Config) -> Result<Wallet<SigningKey>>
+fn get_wallet(avs: &AvsConfig) -> Result<Wallet<SigningKey>>
 {
     let res = match (
-        &config
-            .avs
-            .lagr_keystore,
-        &config
-            .avs
-            .lagr_pwd,
-        &config
-            .avs
-            .lagr_private_key,
+        &avs.lagr_keystore,
+        &avs.lagr_pwd,
+        &avs.lagr_private_key,
     )
     {
         (Some(keystore_path), Some(password), None) =>
@@ -597,27 +2014,21 @@ fn get_wallet(config: &Config) -> Result<Wallet<SigningKey>>
     Ok(res)
 }
 
-fn get_claims(config: &Config) -> Result<Claims>
+fn get_claims(
+    config: &Config,
+    avs: &AvsConfig,
+) -> Result<Claims>
 {
     let registered = RegisteredClaims {
         issuer: Some(
-            config
-                .avs
-                .issuer
+            avs.issuer
                 .clone(),
         ),
         subject: Some(
-            config
-                .avs
-                .worker_id
+            avs.worker_id
                 .clone(),
         ),
-        issued_at: Some(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Epoch can not be in the future")
-                .as_secs(),
-        ),
+        issued_at: Some(clock::issued_at(avs.label())),
         ..Default::default()
     };
 
@@ -648,25 +2059,42 @@ fn get_claims(config: &Config) -> Result<Claims>
     )
 }
 
-fn run_with_websocket(config: &Config) -> Result<()>
+fn run_with_websocket(
+    config: &Config,
+    avs: &AvsConfig,
+) -> Result<()>
 {
-    let lagrange_wallet = get_wallet(config)?;
+    let lagrange_wallet = get_wallet(avs).context(ExitClass::Auth)?;
 
     info!(
         "Connecting to the gateway. gateway_url: {}",
-        &config
-            .avs
-            .gateway_url
+        &avs.gateway_url
     );
 
-    let claims = get_claims(config)?;
-
-    let (mut ws_socket, _) = connect(
-        &config
-            .avs
-            .gateway_url,
+    let sent_at = SystemTime::now();
+    let (mut ws_socket, handshake) = connect(&avs.gateway_url)?;
+    if let Some(date) = handshake
+        .headers()
+        .get("date")
+        .and_then(
+            |date| {
+                date.to_str()
+                    .ok()
+            },
+        )
+    {
+        clock::observe(
+            avs.label(),
+            sent_at,
+            date,
+        );
+    }
+    let claims = get_claims(
+        config,
+        avs,
     )?;
-    counter!("zkmr_worker_gateway_connection_count").increment(1);
+    counter!("zkmr_worker_gateway_connection_count", "gateway" => avs.label().to_string())
+        .increment(1);
 
     info!("Authenticating");
     let token = JWTAuth::new(
@@ -716,6 +2144,13 @@ fn run_with_websocket(config: &Config) -> Result<()>
                             envelope
                         )
                     },
+                    Ok(DownstreamPayload::Control(control)) =>
+                    {
+                        bail!(
+                            "Unexpected Control message during authentication. msg: {:?}",
+                            control
+                        )
+                    },
                     Err(err) =>
                     {
                         bail!(
@@ -763,9 +2198,16 @@ fn run_with_websocket(config: &Config) -> Result<()>
         .context("Public parameters verification failed")?;
     }
 
+    let signer = maybe_reply_signer(
+        config,
+        avs,
+    )?;
+
     start_work(
         &mut ws_socket,
-        &mut provers_manager,
+        &provers_manager,
+        avs.label(),
+        signer.as_ref(),
     )?;
 
     Ok(())
@@ -773,7 +2215,9 @@ fn run_with_websocket(config: &Config) -> Result<()>
 
 fn start_work(
     ws_socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
-    provers_manager: &mut ProversManager<TaskType, ReplyType>,
+    provers_manager: &ProversManager<TaskType, ReplyType>,
+    gateway: &str,
+    signer: Option<&ReplySigner>,
 ) -> Result<()>
 {
     let ready = UpstreamPayload::<ReplyType>::Ready;
@@ -799,10 +2243,14 @@ fn start_work(
                 counter!(
                     "zkmr_worker_websocket_messages_received_total",
                     "message_type" => "text",
+                    "gateway" => gateway.to_string(),
                 )
                 .increment(1);
 
-                match serde_json::from_str::<DownstreamPayload<TaskType>>(&content).with_context(
+                let (payload, proof_digests) = streaming_hash::collect(
+                    || serde_json::from_str::<DownstreamPayload<TaskType>>(&content),
+                );
+                match payload.with_context(
                     || {
                         format!(
                             "Failed to decode msg. content: {}",
@@ -815,10 +2263,26 @@ fn start_work(
                         envelope,
                     } =>
                     {
+                        if replay::admit(
+                            gateway,
+                            &envelope,
+                            clock::gateway_now(gateway),
+                        )
+                        .is_err()
+                        {
+                            continue;
+                        }
+                        inline_proofs::record(
+                            gateway,
+                            &proof_digests,
+                        );
                         let envelope_id = envelope.id();
                         let reply = match process_downstream_payload(
                             provers_manager,
+                            gateway,
+                            signer,
                             envelope,
+                            TaskTimings::default(),
                         )
                         {
                             Ok(reply) => UpstreamPayload::Done(reply),
@@ -832,15 +2296,18 @@ fn start_work(
                             },
                         };
                         counter!("zkmr_worker_websocket_messages_sent_total",
-                                    "message_type" => "text")
+                                    "message_type" => "text",
+                                    "gateway" => gateway.to_string())
                         .increment(1);
                         ws_socket.send(Message::Text(serde_json::to_string(&reply)?))?;
                     },
+                    DownstreamPayload::Control(control) => log_control::apply(&control),
                     DownstreamPayload::Ack =>
                     {
                         counter!(
                             "zkmr_worker_error_count",
-                            "error_type" => "unexpected_ack",
+                            "error_type" => ErrorCode::UnexpectedAck.label(),
+                            "error_code" => ErrorCode::UnexpectedAck.to_string(),
                         )
                         .increment(1);
                         bail!("Unexpected ACK frame")
@@ -854,6 +2321,7 @@ fn start_work(
                 counter!(
                     "zkmr_worker_websocket_messages_received_total",
                     "message_type" => "ping",
+                    "gateway" => gateway.to_string(),
                 )
                 .increment(1);
             },
@@ -867,7 +2335,8 @@ fn start_work(
                 error!("Unexpected frame: {msg}");
                 counter!(
                     "zkmr_worker_error_count",
-                    "error_type" => "unexpected_frame",
+                    "error_type" => ErrorCode::UnexpectedFrame.label(),
+                    "error_code" => ErrorCode::UnexpectedFrame.to_string(),
                 )
                 .increment(1);
             },
@@ -0,0 +1,515 @@
+//! The maintenance windows of the worker, e.g. for the nightly patching of its host.
+//!
+//! With `[maintenance]` set, the worker drains from each time matching `cron` for `duration`: the
+//! tasks are refused with the `Maintenance` error code, for the gateways to hand them to other
+//! workers, while the proof in flight completes and is replied to. With `disconnect` set, the
+//! worker also stops reading the gRPC streams when a window starts, answers the tasks it already
+//! read, and stays disconnected from the gateways until the window ends. The websocket gateway is
+//! only refused the tasks.
+//!
+//! The state is served on the admin endpoint `/info`, and exported as the gauges
+//! `zkmr_worker_maintenance_draining` and `zkmr_worker_maintenance_next_start_unix`.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use metrics::gauge;
+use serde_derive::Serialize;
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::config::MaintenanceConfig;
+use crate::unix_now;
+
+/// The longest the schedule goes without being evaluated again, e.g. after the clock jumped.
+const MAX_SLEEP_SECS: u64 = 60;
+
+static MAINTENANCE: OnceLock<Maintenance> = OnceLock::new();
+
+struct Maintenance
+{
+    config: MaintenanceConfig,
+    status: watch::Sender<Status>,
+}
+
+/// The maintenance state of the worker.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Status
+{
+    cron: String,
+    duration_secs: u64,
+    disconnect: bool,
+    /// Whether the worker is in a window, refusing the tasks.
+    draining: bool,
+    /// When the current window ends, if in one.
+    ends_at_unix: Option<u64>,
+    /// When the next window starts, if not in one.
+    next_start_unix: Option<u64>,
+}
+
+pub(crate) fn init(config: Option<&MaintenanceConfig>)
+{
+    if let Some(config) = config
+    {
+        let status = status_at(
+            config,
+            unix_now(),
+        );
+        let _ = MAINTENANCE.set(
+            Maintenance {
+                config: config.clone(),
+                status: watch::channel(status).0,
+            },
+        );
+    }
+}
+
+/// Follows the schedule of the windows, forever.
+pub(crate) async fn run() -> Result<()>
+{
+    let maintenance = MAINTENANCE
+        .get()
+        .context("the maintenance windows are not configured")?;
+    info!(
+        "Draining the worker for {}s at `{}` UTC",
+        maintenance
+            .config
+            .duration,
+        maintenance
+            .config
+            .cron
+    );
+    loop
+    {
+        let now = unix_now();
+        let next_change = maintenance.update(now)?;
+        tokio::time::sleep(
+            Duration::from_secs(
+                next_change
+                    .saturating_sub(now)
+                    .clamp(
+                        1,
+                        MAX_SLEEP_SECS,
+                    ),
+            ),
+        )
+        .await;
+    }
+}
+
+/// The maintenance state, if the windows are configured.
+pub(crate) fn status() -> Option<Status>
+{
+    MAINTENANCE
+        .get()
+        .map(Maintenance::status)
+}
+
+/// When the current window ends, if the worker is in one.
+pub(crate) fn draining_until() -> Option<u64>
+{
+    MAINTENANCE
+        .get()
+        .and_then(Maintenance::draining_until)
+}
+
+/// Resolves once a window the worker disconnects during starts, never if it does not disconnect.
+pub(crate) async fn disconnecting()
+{
+    match MAINTENANCE.get()
+    {
+        Some(maintenance) =>
+        {
+            maintenance
+                .disconnecting()
+                .await
+        },
+        None => std::future::pending().await,
+    }
+}
+
+/// Resolves once the worker may connect to the gateways, i.e. at once unless it is in a window it
+/// disconnects during.
+pub(crate) async fn connectable()
+{
+    if let Some(maintenance) = MAINTENANCE.get()
+    {
+        maintenance
+            .connectable()
+            .await;
+    }
+}
+
+impl Maintenance
+{
+    /// Updates the state at `now`, returning when it changes next.
+    fn update(
+        &self,
+        now: u64,
+    ) -> Result<u64>
+    {
+        let status = status_at(
+            &self.config,
+            now,
+        );
+        let Some(next_change) = status
+            .ends_at_unix
+            .or(status.next_start_unix)
+        else
+        {
+            bail!(
+                "the maintenance cron `{}` matches no time anymore",
+                self.config
+                    .cron
+            );
+        };
+        gauge!("zkmr_worker_maintenance_draining").set(f64::from(u8::from(status.draining)));
+        gauge!("zkmr_worker_maintenance_next_start_unix").set(
+            status
+                .next_start_unix
+                .unwrap_or_default() as f64,
+        );
+        self.status
+            .send_if_modified(
+                |current| {
+                    if current.draining == status.draining
+                    {
+                        *current = status.clone();
+                        return false;
+                    }
+                    if status.draining
+                    {
+                        info!(
+                            "Maintenance window started, refusing the tasks until {}",
+                            next_change
+                        );
+                    }
+                    else
+                    {
+                        info!("Maintenance window ended, taking tasks again");
+                    }
+                    *current = status.clone();
+                    true
+                },
+            );
+
+        Ok(next_change)
+    }
+
+    fn status(&self) -> Status
+    {
+        self.status
+            .borrow()
+            .clone()
+    }
+
+    fn draining_until(&self) -> Option<u64>
+    {
+        Some(self.status())
+            .filter(|status| status.draining)
+            .and_then(|status| status.ends_at_unix)
+    }
+
+    async fn disconnecting(&self)
+    {
+        if self
+            .config
+            .disconnect
+            && self
+                .status
+                .subscribe()
+                .wait_for(|status| status.draining)
+                .await
+                .is_ok()
+        {
+            return;
+        }
+        std::future::pending().await
+    }
+
+    async fn connectable(&self)
+    {
+        if self
+            .config
+            .disconnect
+        {
+            let _ = self
+                .status
+                .subscribe()
+                .wait_for(|status| !status.draining)
+                .await;
+        }
+    }
+}
+
+fn status_at(
+    config: &MaintenanceConfig,
+    now: u64,
+) -> Status
+{
+    let ends_at_unix = config
+        .cron
+        .last_within(
+            now,
+            config.duration,
+        )
+        .map(|started| started + config.duration);
+    Status {
+        cron: config
+            .cron
+            .to_string(),
+        duration_secs: config.duration,
+        disconnect: config.disconnect,
+        draining: ends_at_unix.is_some(),
+        ends_at_unix,
+        next_start_unix: ends_at_unix
+            .is_none()
+            .then(
+                || {
+                    config
+                        .cron
+                        .next(now)
+                },
+            )
+            .flatten(),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use serde_json::json;
+
+    use super::*;
+
+    /// 2024-02-28T00:00:00Z.
+    const MIDNIGHT: u64 = 1_709_078_400;
+    const HOUR: u64 = 3600;
+
+    fn maintenance(
+        cron: &str,
+        duration: &str,
+        disconnect: bool,
+    ) -> Maintenance
+    {
+        let config = serde_json::from_value::<MaintenanceConfig>(
+            json!(
+                {
+                    "cron": cron,
+                    "duration": duration,
+                    "disconnect": disconnect,
+                }
+            ),
+        )
+        .unwrap();
+        Maintenance {
+            status: watch::channel(
+                status_at(
+                    &config,
+                    0,
+                ),
+            )
+            .0,
+            config,
+        }
+    }
+
+    async fn resolves(future: impl std::future::Future<Output = ()>) -> bool
+    {
+        tokio::time::timeout(
+            Duration::ZERO,
+            future,
+        )
+        .await
+        .is_ok()
+    }
+
+    #[test]
+    fn parses_the_windows()
+    {
+        let config = serde_json::from_value::<MaintenanceConfig>(
+            json!(
+                {
+                    "cron": "30 23 * * *",
+                    "duration": "1h30m",
+                }
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            config.duration,
+            90 * 60
+        );
+        assert!(!config.disconnect);
+        assert_eq!(
+            config
+                .cron
+                .to_string(),
+            "30 23 * * *"
+        );
+
+        assert!(
+            serde_json::from_value::<MaintenanceConfig>(
+                json!(
+                    {
+                        "cron": "30 24 * * *",
+                        "duration": 3600,
+                    }
+                ),
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn drains_within_the_windows()
+    {
+        let config = maintenance(
+            "0 22 * * *",
+            "1h",
+            false,
+        )
+        .config;
+        let at = |hours: f64| {
+            status_at(
+                &config,
+                MIDNIGHT + (hours * HOUR as f64) as u64,
+            )
+        };
+
+        // Before.
+        let before = at(21.5);
+        assert!(!before.draining);
+        assert_eq!(
+            before.next_start_unix,
+            Some(MIDNIGHT + 22 * HOUR)
+        );
+        // Inside, from its start.
+        for hours in [
+            22.0,
+            22.5,
+        ]
+        {
+            let inside = at(hours);
+            assert!(inside.draining);
+            assert_eq!(
+                inside.ends_at_unix,
+                Some(MIDNIGHT + 23 * HOUR)
+            );
+            assert_eq!(
+                inside.next_start_unix,
+                None
+            );
+        }
+        // After, from its end.
+        for hours in [
+            23.0,
+            23.5,
+        ]
+        {
+            let after = at(hours);
+            assert!(!after.draining);
+            assert_eq!(
+                after.next_start_unix,
+                Some(MIDNIGHT + 46 * HOUR)
+            );
+        }
+    }
+
+    #[test]
+    fn drains_across_midnight()
+    {
+        let config = maintenance(
+            "30 23 * * *",
+            "1h",
+            false,
+        )
+        .config;
+
+        let inside = status_at(
+            &config,
+            MIDNIGHT + 24 * HOUR + 15 * 60,
+        );
+        assert!(inside.draining);
+        assert_eq!(
+            inside.ends_at_unix,
+            Some(MIDNIGHT + 24 * HOUR + 30 * 60)
+        );
+
+        let after = status_at(
+            &config,
+            MIDNIGHT + 24 * HOUR + 30 * 60,
+        );
+        assert!(!after.draining);
+        assert_eq!(
+            after.next_start_unix,
+            Some(MIDNIGHT + 47 * HOUR + 30 * 60)
+        );
+    }
+
+    #[tokio::test]
+    async fn disconnects_during_the_windows()
+    {
+        let maintenance = maintenance(
+            "0 22 * * *",
+            "1h",
+            true,
+        );
+
+        maintenance
+            .update(MIDNIGHT + 21 * HOUR)
+            .unwrap();
+        assert_eq!(
+            maintenance.draining_until(),
+            None
+        );
+        assert!(resolves(maintenance.connectable()).await);
+        assert!(!resolves(maintenance.disconnecting()).await);
+
+        assert_eq!(
+            maintenance
+                .update(MIDNIGHT + 22 * HOUR)
+                .unwrap(),
+            MIDNIGHT + 23 * HOUR
+        );
+        assert_eq!(
+            maintenance.draining_until(),
+            Some(MIDNIGHT + 23 * HOUR)
+        );
+        assert!(!resolves(maintenance.connectable()).await);
+        assert!(resolves(maintenance.disconnecting()).await);
+
+        maintenance
+            .update(MIDNIGHT + 23 * HOUR)
+            .unwrap();
+        assert_eq!(
+            maintenance.draining_until(),
+            None
+        );
+        assert!(resolves(maintenance.connectable()).await);
+    }
+
+    #[tokio::test]
+    async fn stays_connected_unless_disconnecting()
+    {
+        let maintenance = maintenance(
+            "0 22 * * *",
+            "1h",
+            false,
+        );
+
+        maintenance
+            .update(MIDNIGHT + 22 * HOUR)
+            .unwrap();
+        // Refusing the tasks, still connected.
+        assert_eq!(
+            maintenance.draining_until(),
+            Some(MIDNIGHT + 23 * HOUR)
+        );
+        assert!(resolves(maintenance.connectable()).await);
+        assert!(!resolves(maintenance.disconnecting()).await);
+    }
+}
@@ -1,3 +1,5 @@
+pub(crate) mod task_queue;
+pub(crate) mod thread_pools;
 pub(crate) mod v1;
 
 use std::collections::HashMap;
@@ -10,16 +12,27 @@ use lgn_messages::types::MessageReplyEnvelope;
 use lgn_messages::types::ProverType;
 use lgn_messages::types::ToProverType;
 use lgn_provers::provers::LgnProver;
+use lgn_provers::provers::YieldPoint;
 use metrics::counter;
 use metrics::histogram;
 use tracing::info;
 
+use crate::manager::thread_pools::TaskKind;
+use crate::manager::thread_pools::ThreadPools;
+
+/// A prover which can run on any thread pool.
+pub(crate) type BoxedProver<T, R> = Box<dyn LgnProver<T, R> + Send + Sync>;
+
 /// Manages provers for different proving task types
 pub(crate) struct ProversManager<T, R>
 where
     T: ToProverType + UnwindSafe,
 {
-    provers: HashMap<ProverType, Box<dyn LgnProver<T, R>>>,
+    provers: HashMap<ProverType, BoxedProver<T, R>>,
+    thread_pools: Option<(
+        ThreadPools,
+        fn(&T) -> Option<TaskKind>,
+    )>,
 }
 
 impl<T: ToProverType + UnwindSafe, R> UnwindSafe for ProversManager<T, R>
@@ -37,9 +50,25 @@ where
     {
         Self {
             provers: HashMap::default(),
+            thread_pools: None,
         }
     }
 
+    /// Proves the tasks in the pool of their kind, as returned by `kind`.
+    pub(crate) fn set_thread_pools(
+        &mut self,
+        thread_pools: ThreadPools,
+        kind: fn(&T) -> Option<TaskKind>,
+    )
+    {
+        self.thread_pools = Some(
+            (
+                thread_pools,
+                kind,
+            ),
+        );
+    }
+
     /// Registers a new prover.
     ///
     /// # Arguments
@@ -48,7 +77,7 @@ where
     pub(crate) fn add_prover(
         &mut self,
         task_type: ProverType,
-        prover: Box<dyn LgnProver<T, R>>,
+        prover: BoxedProver<T, R>,
     )
     {
         self.provers
@@ -58,17 +87,32 @@ where
             );
     }
 
+    /// The types of the provers registered.
+    pub(crate) fn prover_types(&self) -> Vec<ProverType>
+    {
+        self.provers
+            .keys()
+            .copied()
+            .collect()
+    }
+
     /// Sends proving request to a matching prover
     ///
     /// # Arguments
     /// * `envelope` - The message envelope containing the task to be processed
+    /// * `yield_point` - Where the prover hands over between the stages of the proof, `&()` to run
+    ///   it through
     ///
     /// # Returns
     /// A message reply envelope containing the result of the proving task
     pub(crate) fn delegate_proving(
         &self,
         envelope: &MessageEnvelope<T>,
+        yield_point: &dyn YieldPoint,
     ) -> anyhow::Result<MessageReplyEnvelope<R>>
+    where
+        T: Sync,
+        R: Send,
     {
         let prover_type: ProverType = envelope
             .inner
@@ -87,7 +131,28 @@ where
 
                 let start_time = std::time::Instant::now();
 
-                let result = prover.run(envelope)?;
+                let result = match &self.thread_pools
+                {
+                    Some((thread_pools, kind)) =>
+                    {
+                        thread_pools.install(
+                            kind(&envelope.inner),
+                            || {
+                                prover.run_cooperatively(
+                                    envelope,
+                                    yield_point,
+                                )
+                            },
+                        )?
+                    },
+                    None =>
+                    {
+                        prover.run_cooperatively(
+                            envelope,
+                            yield_point,
+                        )?
+                    },
+                };
 
                 counter!("zkmr_worker_tasks_processed_total", "task_type" => prover_type.to_string())
                     .increment(1);
@@ -0,0 +1,300 @@
+//! Ordering of the tasks held locally before proving them.
+//!
+//! When several tasks are buffered, proving them in arrival order can miss the deadline of a task
+//! queued behind longer ones. Deadline-aware queues prove the earliest deadline first, then the
+//! highest routing priority, then the least gas, the arrival order breaking the ties.
+
+use std::cmp::Ordering;
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::collections::BinaryHeap;
+
+use lgn_messages::types::MessageEnvelope;
+use metrics::counter;
+use metrics::gauge;
+
+/// The tasks waiting to be proven, in arrival order unless deadline-aware.
+pub(crate) struct TaskQueue<T>
+{
+    deadline_aware: bool,
+    heap: BinaryHeap<Entry<T>>,
+    /// The arrival numbers of the queued tasks, to detect the reorderings.
+    arrivals: BTreeSet<u64>,
+    next_arrival: u64,
+}
+
+/// What a task is ordered by.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TaskOrder
+{
+    deadline_unix: Option<u64>,
+    priority: u64,
+    gas: Option<u64>,
+}
+
+impl TaskOrder
+{
+    pub(crate) fn of<E>(envelope: &MessageEnvelope<E>) -> Self
+    {
+        Self {
+            deadline_unix: envelope.deadline_unix,
+            priority: envelope
+                .routing_key
+                .priority(),
+            gas: envelope.gas,
+        }
+    }
+}
+
+struct Entry<T>
+{
+    /// Smallest first.
+    key: (
+        u64,
+        Reverse<u64>,
+        u64,
+        u64,
+    ),
+    item: T,
+}
+
+impl<T> TaskQueue<T>
+{
+    pub(crate) fn new(deadline_aware: bool) -> Self
+    {
+        Self {
+            deadline_aware,
+            heap: BinaryHeap::new(),
+            arrivals: BTreeSet::new(),
+            next_arrival: 0,
+        }
+    }
+
+    /// Queues `item`, ordered by `order`.
+    pub(crate) fn push(
+        &mut self,
+        order: TaskOrder,
+        item: T,
+    )
+    {
+        let arrival = self.next_arrival;
+        self.next_arrival += 1;
+        let key = if self.deadline_aware
+        {
+            (
+                order
+                    .deadline_unix
+                    .unwrap_or(u64::MAX),
+                Reverse(order.priority),
+                order
+                    .gas
+                    .unwrap_or(u64::MAX),
+                arrival,
+            )
+        }
+        else
+        {
+            (
+                0,
+                Reverse(0),
+                0,
+                arrival,
+            )
+        };
+        self.heap
+            .push(
+                Entry {
+                    key,
+                    item,
+                },
+            );
+        self.arrivals
+            .insert(arrival);
+        gauge!("zkmr_worker_tasks_buffered").set(
+            self.heap
+                .len() as f64,
+        );
+    }
+
+    /// The next task to prove.
+    pub(crate) fn pop(&mut self) -> Option<T>
+    {
+        let entry = self
+            .heap
+            .pop()?;
+        let arrival = entry
+            .key
+            .3;
+        if self
+            .arrivals
+            .first()
+            != Some(&arrival)
+        {
+            counter!("zkmr_worker_tasks_reordered_total").increment(1);
+        }
+        self.arrivals
+            .remove(&arrival);
+        gauge!("zkmr_worker_tasks_buffered").set(
+            self.heap
+                .len() as f64,
+        );
+
+        Some(entry.item)
+    }
+}
+
+impl<T> PartialEq for Entry<T>
+{
+    fn eq(
+        &self,
+        other: &Self,
+    ) -> bool
+    {
+        self.key == other.key
+    }
+}
+
+impl<T> Eq for Entry<T>
+{
+}
+
+impl<T> PartialOrd for Entry<T>
+{
+    fn partial_cmp(
+        &self,
+        other: &Self,
+    ) -> Option<Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T>
+{
+    /// Reversed, the heap popping the greatest entry.
+    fn cmp(
+        &self,
+        other: &Self,
+    ) -> Ordering
+    {
+        other
+            .key
+            .cmp(&self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn order(deadline_unix: Option<u64>) -> TaskOrder
+    {
+        TaskOrder {
+            deadline_unix,
+            priority: 0,
+            gas: None,
+        }
+    }
+
+    fn drain(queue: &mut TaskQueue<&'static str>) -> Vec<&'static str>
+    {
+        std::iter::from_fn(|| queue.pop()).collect()
+    }
+
+    #[test]
+    fn proves_the_earliest_deadline_first()
+    {
+        let mut queue = TaskQueue::new(true);
+        for (deadline, task) in [
+            (
+                Some(30),
+                "a",
+            ),
+            (
+                None,
+                "b",
+            ),
+            (
+                Some(10),
+                "c",
+            ),
+            (
+                Some(20),
+                "d",
+            ),
+        ]
+        {
+            queue.push(
+                order(deadline),
+                task,
+            );
+        }
+
+        assert_eq!(
+            drain(&mut queue),
+            [
+                "c",
+                "d",
+                "a",
+                "b"
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_arrival_order_without_deadlines()
+    {
+        let mut queue = TaskQueue::new(true);
+        for task in [
+            "a",
+            "b",
+            "c",
+        ]
+        {
+            queue.push(
+                order(None),
+                task,
+            );
+        }
+        assert_eq!(
+            drain(&mut queue),
+            [
+                "a",
+                "b",
+                "c"
+            ]
+        );
+
+        // Not deadline-aware, the deadlines are ignored.
+        let mut queue = TaskQueue::new(false);
+        for (deadline, task) in [
+            (
+                Some(30),
+                "a",
+            ),
+            (
+                None,
+                "b",
+            ),
+            (
+                Some(10),
+                "c",
+            ),
+        ]
+        {
+            queue.push(
+                order(deadline),
+                task,
+            );
+        }
+        assert_eq!(
+            drain(&mut queue),
+            [
+                "a",
+                "b",
+                "c"
+            ]
+        );
+    }
+}
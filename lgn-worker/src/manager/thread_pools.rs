@@ -0,0 +1,173 @@
+//! Dedicated rayon pools for the task kinds whose proofs are too small to use every core.
+//!
+//! A cell leaf proof spread over the global pool, sized for the largest proofs, spends more time
+//! synchronizing the threads than proving. The tasks of a kind with a configured size are proven
+//! in a pool of that size instead.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use anyhow::Result;
+use lgn_messages::types::v1::preprocessing::db_tasks::DatabaseType;
+use lgn_messages::types::v1::preprocessing::WorkerTaskType;
+use lgn_messages::types::TaskDifficulty;
+use lgn_messages::types::TaskType;
+use rayon::ThreadPool;
+use tracing::info;
+
+use crate::config::ThreadsConfig;
+
+/// The task kinds with a configurable pool size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum TaskKind
+{
+    Extraction,
+    Cell,
+    Row,
+    Index,
+    Ivc,
+    Query,
+    Groth16,
+}
+
+impl TaskKind
+{
+    /// The kind of `task`, experimental tasks always using the global pool.
+    pub(crate) fn of(task: &TaskType) -> Option<Self>
+    {
+        match task
+        {
+            TaskType::V1Preprocessing(task) =>
+            {
+                Some(
+                    match &task.task_type
+                    {
+                        WorkerTaskType::Extraction(_) => TaskKind::Extraction,
+                        WorkerTaskType::Database(DatabaseType::Cell(_)) => TaskKind::Cell,
+                        WorkerTaskType::Database(
+                            DatabaseType::Row(_) | DatabaseType::RowUpdate(_),
+                        ) => TaskKind::Row,
+                        WorkerTaskType::Database(DatabaseType::Index(_)) => TaskKind::Index,
+                        WorkerTaskType::Database(DatabaseType::IVC(_)) => TaskKind::Ivc,
+                    },
+                )
+            },
+            TaskType::V1Query(_) => Some(TaskKind::Query),
+            TaskType::V1Groth16(_) => Some(TaskKind::Groth16),
+            TaskType::TxTrie(_) | TaskType::RecProof(_) => None,
+        }
+    }
+
+    /// The pool size of this kind, the global pool being used if `None`.
+    fn size(
+        self,
+        config: &ThreadsConfig,
+        instance_type: TaskDifficulty,
+    ) -> Option<usize>
+    {
+        let configured = match self
+        {
+            TaskKind::Extraction => config.extraction,
+            TaskKind::Cell => config.cell,
+            TaskKind::Row => config.row,
+            TaskKind::Index => config.index,
+            TaskKind::Ivc => config.ivc,
+            TaskKind::Query => config.query,
+            TaskKind::Groth16 => config.groth16,
+        };
+
+        // By default only the small proofs get a pool, sized after the worker class.
+        configured.or(
+            match (
+                self,
+                instance_type,
+            )
+            {
+                (TaskKind::Extraction | TaskKind::Cell | TaskKind::Row, TaskDifficulty::Medium) =>
+                {
+                    Some(4)
+                },
+                (TaskKind::Extraction | TaskKind::Cell | TaskKind::Row, TaskDifficulty::Large) =>
+                {
+                    Some(8)
+                },
+                _ => None,
+            },
+        )
+    }
+}
+
+/// The dedicated pools, by task kind.
+pub(crate) struct ThreadPools
+{
+    pools: HashMap<TaskKind, ThreadPool>,
+}
+
+impl ThreadPools
+{
+    pub(crate) fn new(
+        config: &ThreadsConfig,
+        instance_type: TaskDifficulty,
+    ) -> Result<Self>
+    {
+        let mut pools = HashMap::new();
+        for kind in [
+            TaskKind::Extraction,
+            TaskKind::Cell,
+            TaskKind::Row,
+            TaskKind::Index,
+            TaskKind::Ivc,
+            TaskKind::Query,
+            TaskKind::Groth16,
+        ]
+        {
+            let Some(size) = kind.size(
+                config,
+                instance_type,
+            )
+            else
+            {
+                continue;
+            };
+            // The global pool is sized after the CPUs the worker may use, a larger pool would
+            // only be throttled.
+            let size = size.min(rayon::current_num_threads());
+
+            info!("Proving the {kind:?} tasks with {size} threads");
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(size)
+                .thread_name(move |index| format!("prover-{kind:?}-{index}").to_lowercase())
+                .build()
+                .with_context(|| format!("failed to create the {kind:?} thread pool"))?;
+            pools.insert(
+                kind,
+                pool,
+            );
+        }
+
+        Ok(
+            Self {
+                pools,
+            },
+        )
+    }
+
+    /// Runs `op` in the pool of `kind`, or on the global pool if it has none.
+    pub(crate) fn install<R: Send>(
+        &self,
+        kind: Option<TaskKind>,
+        op: impl FnOnce() -> R + Send,
+    ) -> R
+    {
+        match kind.and_then(
+            |kind| {
+                self.pools
+                    .get(&kind)
+            },
+        )
+        {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+}
@@ -1,30 +1,82 @@
+#[cfg(feature = "prover-preprocessing")]
+use std::time::Duration;
+
 use anyhow::*;
 use lgn_messages::types::ProverType;
 use lgn_messages::types::ReplyType;
 use lgn_messages::types::TaskDifficulty;
 use lgn_messages::types::TaskType;
+use lgn_provers::params::ParamsLoader;
+#[cfg(feature = "prover-preprocessing")]
+use lgn_provers::provers::v1::preprocessing::checkpoints::IndexCheckpoints;
+#[cfg(feature = "prover-query")]
+use lgn_provers::provers::v1::query::pruned::PrunedShape;
 use tracing::debug;
 
 use crate::config::Config;
+use crate::manager::thread_pools::TaskKind;
+use crate::manager::thread_pools::ThreadPools;
+#[cfg(feature = "prover-query")]
+use crate::manager::BoxedProver;
 use crate::manager::ProversManager;
+#[cfg(feature = "prover-query")]
+use crate::schema::LintedQueryProver;
+#[cfg(feature = "prover-query")]
+use crate::schema::SchemaRegistry;
+use crate::startup;
 
 pub(crate) fn register_v1_provers(
     config: &Config,
     manager: &mut ProversManager<TaskType, ReplyType>,
 ) -> Result<()>
 {
+    manager.set_thread_pools(
+        ThreadPools::new(
+            &config
+                .worker
+                .threads,
+            config
+                .worker
+                .instance_type,
+        )?,
+        TaskKind::of,
+    );
+
+    // The provers load their params one after the other, verifying them all upfront lets the
+    // hashing of the present files run in parallel and overlap with the downloads.
+    let params_config = &config.public_params;
+    if !params_config.skip_checksum && !params_config.skip_store
+    {
+        ParamsLoader::prefetch(
+            &params_config.url,
+            &params_config.dir,
+            &params_files(config),
+            &params_config.checksum_expected_local_path,
+            params_config.verify_parallelism,
+        );
+    }
+
     if config
         .worker
         .instance_type
         >= TaskDifficulty::Small
     {
-        debug!("Creating v1 query prover");
-        register_v1_query(
-            config,
-            manager,
-        )
-        .context("failed to register the query prover")?;
-        debug!("Query prover created");
+        #[cfg(feature = "prover-query")]
+        {
+            debug!("Creating v1 query prover");
+            register_v1_query(
+                config,
+                manager,
+            )
+            .context("failed to register the query prover")?;
+            debug!("Query prover created");
+            startup::ready(
+                config,
+                ProverType::V1Query,
+            );
+        }
+        #[cfg(not(feature = "prover-query"))]
+        tracing::warn!("The query prover is not compiled in, skipping it");
     }
 
     if config
@@ -32,13 +84,22 @@ pub(crate) fn register_v1_provers(
         .instance_type
         >= TaskDifficulty::Medium
     {
-        debug!("Creating v1 preprocessing prover");
-        register_v1_preprocessor(
-            config,
-            manager,
-        )
-        .context("failed to register the pre-processing prover")?;
-        debug!("Preprocessing prover created");
+        #[cfg(feature = "prover-preprocessing")]
+        {
+            debug!("Creating v1 preprocessing prover");
+            register_v1_preprocessor(
+                config,
+                manager,
+            )
+            .context("failed to register the pre-processing prover")?;
+            debug!("Preprocessing prover created");
+            startup::ready(
+                config,
+                ProverType::V1Preprocessing,
+            );
+        }
+        #[cfg(not(feature = "prover-preprocessing"))]
+        tracing::warn!("The preprocessing prover is not compiled in, skipping it");
     }
 
     if config
@@ -46,18 +107,114 @@ pub(crate) fn register_v1_provers(
         .instance_type
         >= TaskDifficulty::Large
     {
-        debug!("Creating groth16 prover");
-        register_v1_groth16(
-            config,
-            manager,
-        )
-        .context("failed to register the groth16 prover")?;
-        debug!("Groth16 prover created");
+        #[cfg(feature = "prover-groth16")]
+        {
+            debug!("Creating groth16 prover");
+            register_v1_groth16(
+                config,
+                manager,
+            )
+            .context("failed to register the groth16 prover")?;
+            debug!("Groth16 prover created");
+            startup::ready(
+                config,
+                ProverType::V1Groth16,
+            );
+        }
+        #[cfg(not(feature = "prover-groth16"))]
+        tracing::warn!("The groth16 prover is not compiled in, skipping it");
     }
 
     Ok(())
 }
 
+/// The params files of the provers of the configured instance type.
+pub(crate) fn params_files(config: &Config) -> Vec<&str>
+{
+    prover_params_files(config)
+        .into_iter()
+        .flat_map(|(_, files)| files)
+        .collect()
+}
+
+/// The provers of the configured instance type, with their params files.
+pub(crate) fn prover_params_files(
+    config: &Config
+) -> Vec<(
+    ProverType,
+    Vec<&str>,
+)>
+{
+    let provers: Vec<(
+        TaskDifficulty,
+        ProverType,
+        Vec<&str>,
+    )> = vec![
+        #[cfg(feature = "prover-query")]
+        (
+            TaskDifficulty::Small,
+            ProverType::V1Query,
+            vec![
+                &config
+                    .public_params
+                    .query_params
+                    .file,
+            ],
+        ),
+        #[cfg(feature = "prover-preprocessing")]
+        (
+            TaskDifficulty::Medium,
+            ProverType::V1Preprocessing,
+            vec![
+                &config
+                    .public_params
+                    .preprocessing_params
+                    .file,
+            ],
+        ),
+        #[cfg(feature = "prover-groth16")]
+        (
+            TaskDifficulty::Large,
+            ProverType::V1Groth16,
+            vec![
+                &config
+                    .public_params
+                    .groth16_assets
+                    .circuit_file,
+                &config
+                    .public_params
+                    .groth16_assets
+                    .r1cs_file,
+                &config
+                    .public_params
+                    .groth16_assets
+                    .pk_file,
+            ],
+        ),
+    ];
+
+    provers
+        .into_iter()
+        .filter(
+            |(min_instance_type, ..)| {
+                config
+                    .worker
+                    .instance_type
+                    >= *min_instance_type
+            },
+        )
+        .map(
+            |(_, prover_type, files)| {
+                (
+                    prover_type,
+                    files,
+                )
+            },
+        )
+        .collect()
+}
+
+#[cfg(feature = "prover-preprocessing")]
 fn register_v1_preprocessor(
     config: &Config,
     manager: &mut ProversManager<TaskType, ReplyType>,
@@ -73,7 +230,25 @@ fn register_v1_preprocessor(
         &params_config.checksum_expected_local_path,
         params_config.skip_checksum,
         params_config.skip_store,
+        config
+            .dummy
+            .profile("preprocessing"),
     )?;
+    let preprocessing_prover = match &config
+        .worker
+        .index_checkpoints
+    {
+        Some(checkpoints) =>
+        {
+            preprocessing_prover.with_index_checkpoints(
+                IndexCheckpoints::new(
+                    &checkpoints.dir,
+                    Duration::from_secs(checkpoints.ttl_secs),
+                ),
+            )
+        },
+        None => preprocessing_prover,
+    };
 
     manager.add_prover(
         ProverType::V1Preprocessing,
@@ -82,6 +257,7 @@ fn register_v1_preprocessor(
     Ok(())
 }
 
+#[cfg(feature = "prover-query")]
 fn register_v1_query(
     config: &Config,
     manager: &mut ProversManager<TaskType, ReplyType>,
@@ -97,15 +273,63 @@ fn register_v1_query(
         &params_config.checksum_expected_local_path,
         params_config.skip_checksum,
         params_config.skip_store,
+        config
+            .dummy
+            .profile("query"),
     )?;
+    let query_prover = if params_config
+        .query_params
+        .pruned
+    {
+        query_prover.with_pruned_shape(
+            PrunedShape::fetch(
+                &params_config.url,
+                &params_config
+                    .query_params
+                    .file,
+            )?,
+        )
+    }
+    else
+    {
+        query_prover
+    };
+    let child_proof_cache_mb = config
+        .worker
+        .child_proof_cache_mb;
+    let query_prover = if child_proof_cache_mb > 0
+    {
+        query_prover.with_child_proof_cache(child_proof_cache_mb * 1024 * 1024)
+    }
+    else
+    {
+        query_prover
+    };
+
+    let query_prover: BoxedProver<TaskType, ReplyType> = match &config
+        .worker
+        .query_schema
+    {
+        Some(source) =>
+        {
+            Box::new(
+                LintedQueryProver::new(
+                    SchemaRegistry::load(source)?,
+                    Box::new(query_prover),
+                ),
+            )
+        },
+        None => Box::new(query_prover),
+    };
 
     manager.add_prover(
         ProverType::V1Query,
-        Box::new(query_prover),
+        query_prover,
     );
     Ok(())
 }
 
+#[cfg(feature = "prover-groth16")]
 fn register_v1_groth16(
     config: &Config,
     router: &mut ProversManager<TaskType, ReplyType>,
@@ -122,6 +346,9 @@ fn register_v1_groth16(
         &assets.r1cs_file,
         &assets.pk_file,
         params_config.skip_store,
+        config
+            .dummy
+            .profile("groth16"),
     )?;
 
     router.add_prover(
@@ -0,0 +1,351 @@
+//! Renaming of metrics without breaking the dashboards.
+//!
+//! The code only uses the new name of a renamed metric, the recorder also emits it under its
+//! deprecated name until the dashboards are migrated and the deprecated names are turned off.
+
+use std::sync::Arc;
+
+use metrics::Counter;
+use metrics::CounterFn;
+use metrics::Gauge;
+use metrics::GaugeFn;
+use metrics::Histogram;
+use metrics::HistogramFn;
+use metrics::Key;
+use metrics::KeyName;
+use metrics::Metadata;
+use metrics::Recorder;
+use metrics::SharedString;
+use metrics::Unit;
+
+/// The renamed metrics, as `(new name, deprecated name)`.
+const RENAMED_METRICS: &[(
+    &str,
+    &str,
+)] = &[
+    // Both the manager, per task type, and the gateway loops, per gateway, used the same name.
+    (
+        "zkmr_worker_gateway_tasks_processed_total",
+        "zkmr_worker_tasks_processed_total",
+    ),
+];
+
+fn deprecated_name(name: &str) -> Option<&'static str>
+{
+    RENAMED_METRICS
+        .iter()
+        .find(|(new, _)| *new == name)
+        .map(|(_, deprecated)| *deprecated)
+}
+
+/// Wraps `inner`, also emitting the renamed metrics under their deprecated name if
+/// `emit_deprecated` is set.
+pub(crate) struct DualNamesRecorder<R>
+{
+    inner: R,
+    emit_deprecated: bool,
+}
+
+impl<R> DualNamesRecorder<R>
+{
+    pub(crate) fn new(
+        inner: R,
+        emit_deprecated: bool,
+    ) -> Self
+    {
+        Self {
+            inner,
+            emit_deprecated,
+        }
+    }
+
+    fn deprecated_key(
+        &self,
+        key: &Key,
+    ) -> Option<Key>
+    {
+        if !self.emit_deprecated
+        {
+            return None;
+        }
+
+        deprecated_name(key.name()).map(
+            |name| {
+                Key::from_parts(
+                    name,
+                    key.labels()
+                        .cloned()
+                        .collect::<Vec<_>>(),
+                )
+            },
+        )
+    }
+
+    fn deprecated_key_name(
+        &self,
+        key: &KeyName,
+    ) -> Option<KeyName>
+    {
+        if !self.emit_deprecated
+        {
+            return None;
+        }
+
+        deprecated_name(key.as_str()).map(KeyName::from_const_str)
+    }
+}
+
+impl<R: Recorder> Recorder for DualNamesRecorder<R>
+{
+    fn describe_counter(
+        &self,
+        key: KeyName,
+        unit: Option<Unit>,
+        description: SharedString,
+    )
+    {
+        if let Some(deprecated) = self.deprecated_key_name(&key)
+        {
+            self.inner
+                .describe_counter(
+                    deprecated,
+                    unit,
+                    description.clone(),
+                );
+        }
+        self.inner
+            .describe_counter(
+                key,
+                unit,
+                description,
+            );
+    }
+
+    fn describe_gauge(
+        &self,
+        key: KeyName,
+        unit: Option<Unit>,
+        description: SharedString,
+    )
+    {
+        if let Some(deprecated) = self.deprecated_key_name(&key)
+        {
+            self.inner
+                .describe_gauge(
+                    deprecated,
+                    unit,
+                    description.clone(),
+                );
+        }
+        self.inner
+            .describe_gauge(
+                key,
+                unit,
+                description,
+            );
+    }
+
+    fn describe_histogram(
+        &self,
+        key: KeyName,
+        unit: Option<Unit>,
+        description: SharedString,
+    )
+    {
+        if let Some(deprecated) = self.deprecated_key_name(&key)
+        {
+            self.inner
+                .describe_histogram(
+                    deprecated,
+                    unit,
+                    description.clone(),
+                );
+        }
+        self.inner
+            .describe_histogram(
+                key,
+                unit,
+                description,
+            );
+    }
+
+    fn register_counter(
+        &self,
+        key: &Key,
+        metadata: &Metadata<'_>,
+    ) -> Counter
+    {
+        let counter = self
+            .inner
+            .register_counter(
+                key,
+                metadata,
+            );
+        match self.deprecated_key(key)
+        {
+            Some(deprecated) =>
+            {
+                Counter::from_arc(
+                    Arc::new(
+                        Both(
+                            counter,
+                            self.inner
+                                .register_counter(
+                                    &deprecated,
+                                    metadata,
+                                ),
+                        ),
+                    ),
+                )
+            },
+            None => counter,
+        }
+    }
+
+    fn register_gauge(
+        &self,
+        key: &Key,
+        metadata: &Metadata<'_>,
+    ) -> Gauge
+    {
+        let gauge = self
+            .inner
+            .register_gauge(
+                key,
+                metadata,
+            );
+        match self.deprecated_key(key)
+        {
+            Some(deprecated) =>
+            {
+                Gauge::from_arc(
+                    Arc::new(
+                        Both(
+                            gauge,
+                            self.inner
+                                .register_gauge(
+                                    &deprecated,
+                                    metadata,
+                                ),
+                        ),
+                    ),
+                )
+            },
+            None => gauge,
+        }
+    }
+
+    fn register_histogram(
+        &self,
+        key: &Key,
+        metadata: &Metadata<'_>,
+    ) -> Histogram
+    {
+        let histogram = self
+            .inner
+            .register_histogram(
+                key,
+                metadata,
+            );
+        match self.deprecated_key(key)
+        {
+            Some(deprecated) =>
+            {
+                Histogram::from_arc(
+                    Arc::new(
+                        Both(
+                            histogram,
+                            self.inner
+                                .register_histogram(
+                                    &deprecated,
+                                    metadata,
+                                ),
+                        ),
+                    ),
+                )
+            },
+            None => histogram,
+        }
+    }
+}
+
+/// A metric and its deprecated alias, updated together.
+struct Both<T>(
+    T,
+    T,
+);
+
+impl CounterFn for Both<Counter>
+{
+    fn increment(
+        &self,
+        value: u64,
+    )
+    {
+        self.0
+            .increment(value);
+        self.1
+            .increment(value);
+    }
+
+    fn absolute(
+        &self,
+        value: u64,
+    )
+    {
+        self.0
+            .absolute(value);
+        self.1
+            .absolute(value);
+    }
+}
+
+impl GaugeFn for Both<Gauge>
+{
+    fn increment(
+        &self,
+        value: f64,
+    )
+    {
+        self.0
+            .increment(value);
+        self.1
+            .increment(value);
+    }
+
+    fn decrement(
+        &self,
+        value: f64,
+    )
+    {
+        self.0
+            .decrement(value);
+        self.1
+            .decrement(value);
+    }
+
+    fn set(
+        &self,
+        value: f64,
+    )
+    {
+        self.0
+            .set(value);
+        self.1
+            .set(value);
+    }
+}
+
+impl HistogramFn for Both<Histogram>
+{
+    fn record(
+        &self,
+        value: f64,
+    )
+    {
+        self.0
+            .record(value);
+        self.1
+            .record(value);
+    }
+}
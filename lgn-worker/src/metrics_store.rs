@@ -0,0 +1,258 @@
+//! Persistence of selected counters across restarts, for long-window SLO tracking.
+//!
+//! The counters are periodically snapshotted from the Prometheus recorder to a JSON file, and
+//! restored from it on startup before any task is processed.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use lgn_messages::types::error_code::ErrorCode;
+use metrics::counter;
+use metrics_exporter_prometheus::PrometheusHandle;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use tracing::error;
+use tracing::info;
+
+/// The counters surviving restarts.
+const PERSISTED_COUNTERS: &[&str] = &[
+    "zkmr_worker_tasks_received_total",
+    "zkmr_worker_tasks_processed_total",
+    "zkmr_worker_gateway_tasks_processed_total",
+    "zkmr_worker_error_count",
+];
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+struct Snapshot
+{
+    counters: Vec<CounterValue>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct CounterValue
+{
+    name: String,
+    labels: BTreeMap<String, String>,
+    value: u64,
+}
+
+/// Restores the counters saved at `path`, a missing file meaning a first start.
+pub(crate) fn restore(path: &Path) -> Result<()>
+{
+    let content = match std::fs::read_to_string(path)
+    {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) =>
+        {
+            return Err(err).with_context(
+                || {
+                    format!(
+                        "failed to read `{}`",
+                        path.display()
+                    )
+                },
+            )
+        },
+    };
+    let snapshot: Snapshot = serde_json::from_str(&content).with_context(
+        || {
+            format!(
+                "failed to parse `{}`",
+                path.display()
+            )
+        },
+    )?;
+
+    for series in &snapshot.counters
+    {
+        let labels = series
+            .labels
+            .iter()
+            .map(
+                |(key, value)| {
+                    (
+                        key.clone(),
+                        value.clone(),
+                    )
+                },
+            )
+            .collect::<Vec<_>>();
+        counter!(
+            series
+                .name
+                .clone(),
+            &labels
+        )
+        .absolute(series.value);
+    }
+    info!(
+        "Restored {} counters from {}",
+        snapshot
+            .counters
+            .len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// Spawns a thread saving the persisted counters to `path` every `interval`.
+pub(crate) fn spawn_persistence(
+    handle: PrometheusHandle,
+    path: PathBuf,
+    interval: Duration,
+)
+{
+    std::thread::spawn(
+        move || {
+            loop
+            {
+                std::thread::sleep(interval);
+                if let Err(err) = save(
+                    &handle,
+                    &path,
+                )
+                {
+                    error!("Saving the counters failed: {err:?}");
+                    counter!(
+                        "zkmr_worker_error_count",
+                        "error_type" => ErrorCode::MetricsStore.label(),
+                        "error_code" => ErrorCode::MetricsStore.to_string(),
+                    )
+                    .increment(1);
+                }
+            }
+        },
+    );
+}
+
+/// Atomically replaces the file at `path` with the current value of the persisted counters.
+fn save(
+    handle: &PrometheusHandle,
+    path: &Path,
+) -> Result<()>
+{
+    let snapshot = Snapshot {
+        counters: handle
+            .render()
+            .lines()
+            .filter_map(parse_series)
+            .filter(
+                |series| {
+                    PERSISTED_COUNTERS.contains(
+                        &series
+                            .name
+                            .as_str(),
+                    )
+                },
+            )
+            .collect(),
+    };
+
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(
+        &tmp,
+        serde_json::to_vec(&snapshot)?,
+    )
+    .with_context(
+        || {
+            format!(
+                "failed to write `{}`",
+                tmp.display()
+            )
+        },
+    )?;
+    std::fs::rename(
+        &tmp,
+        path,
+    )
+    .with_context(
+        || {
+            format!(
+                "failed to replace `{}`",
+                path.display()
+            )
+        },
+    )
+}
+
+/// Parses a sample of the Prometheus text format, e.g. `name{key="value"} 42`.
+fn parse_series(line: &str) -> Option<CounterValue>
+{
+    if line.starts_with('#')
+    {
+        return None;
+    }
+    let (series, value) = line.rsplit_once(' ')?;
+    let value = value
+        .parse::<f64>()
+        .ok()?;
+
+    let (name, labels) = match series.split_once('{')
+    {
+        Some((name, labels)) =>
+        {
+            (
+                name,
+                parse_labels(labels.strip_suffix('}')?)?,
+            )
+        },
+        None =>
+        {
+            (
+                series,
+                BTreeMap::new(),
+            )
+        },
+    };
+
+    Some(
+        CounterValue {
+            name: name.to_string(),
+            labels,
+            value: value as u64,
+        },
+    )
+}
+
+/// Parses the `key="value",...` labels of a sample, undoing the text format escaping.
+fn parse_labels(mut input: &str) -> Option<BTreeMap<String, String>>
+{
+    let mut labels = BTreeMap::new();
+    while !input.is_empty()
+    {
+        let (key, rest) = input.split_once("=\"")?;
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        let end = loop
+        {
+            match chars.next()?
+            {
+                (i, '"') => break i,
+                (_, '\\') =>
+                {
+                    match chars
+                        .next()?
+                        .1
+                    {
+                        'n' => value.push('\n'),
+                        escaped => value.push(escaped),
+                    }
+                },
+                (_, c) => value.push(c),
+            }
+        };
+        labels.insert(
+            key.to_string(),
+            value,
+        );
+        input = rest[end + 1..].trim_start_matches(',');
+    }
+
+    Some(labels)
+}
@@ -0,0 +1,333 @@
+//! Notarization of the replies by a third party, as evidence of when their proofs were produced.
+//!
+//! The SHA-256 digest of each reply is submitted by a thread of its own to either an RFC 3161
+//! timestamping authority or an anchoring endpoint, so that a slow notary never delays the replies.
+//! The receipt returned is written to the receipts directory, named after the task, and logged
+//! with the task. The submissions are dropped when the queue is full.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::TrySendError;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use lgn_messages::types::MessageReplyEnvelope;
+use lgn_messages::types::ReplyType;
+use metrics::counter;
+use sha2::Digest;
+use sha2::Sha256;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::NotaryConfig;
+use crate::config::NotaryKind;
+
+static NOTARY: OnceLock<SyncSender<Submission>> = OnceLock::new();
+
+const NOTARY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The DER encoding of the SHA-256 algorithm identifier, with its absent parameters.
+const SHA256_ALGORITHM: &[u8; 15] = b"\x30\x0d\x06\x09\x60\x86\x48\x01\x65\x03\x04\x02\x01\x05\x00";
+
+struct Submission
+{
+    task_id: String,
+    digest: [u8; 32],
+}
+
+/// Starts the notarization of the replies, if configured.
+pub(crate) fn init(config: Option<&NotaryConfig>) -> Result<()>
+{
+    let Some(config) = config
+    else
+    {
+        return Ok(());
+    };
+    let receipts_dir = PathBuf::from(&config.receipts_dir);
+    std::fs::create_dir_all(&receipts_dir).with_context(
+        || {
+            format!(
+                "failed to create the receipts directory `{}`",
+                receipts_dir.display()
+            )
+        },
+    )?;
+    let (sender, receiver) = mpsc::sync_channel(config.queue_size);
+    if NOTARY
+        .set(sender)
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    info!(
+        "Notarizing the replies with {:?} endpoint `{}`",
+        config.kind, config.url
+    );
+    let kind = config.kind;
+    let url = config
+        .url
+        .clone();
+    std::thread::spawn(
+        move || {
+            let client = reqwest::blocking::Client::new();
+            for submission in receiver
+            {
+                let notarized = notarize(
+                    &client,
+                    kind,
+                    &url,
+                    &submission,
+                )
+                .and_then(
+                    |receipt| {
+                        write_receipt(
+                            &receipts_dir,
+                            kind,
+                            &submission,
+                            &receipt,
+                        )
+                    },
+                );
+                match notarized
+                {
+                    Ok(path) =>
+                    {
+                        counter!("zkmr_worker_notarized_total").increment(1);
+                        info!(
+                            task_id = submission.task_id,
+                            digest = hex::encode(submission.digest),
+                            receipt = %path.display(),
+                            "Reply notarized"
+                        );
+                    },
+                    Err(err) =>
+                    {
+                        counter!("zkmr_worker_notarization_failures_total").increment(1);
+                        warn!(
+                            task_id = submission.task_id,
+                            "Failed to notarize the reply: {err:?}"
+                        );
+                    },
+                }
+            }
+        },
+    );
+
+    Ok(())
+}
+
+/// Queues the notarization of `reply`, if enabled.
+pub(crate) fn submit(reply: &MessageReplyEnvelope<ReplyType>)
+{
+    let Some(sender) = NOTARY.get()
+    else
+    {
+        return;
+    };
+    let digest = match reply.canonical_bytes()
+    {
+        Ok(serialized) => Sha256::digest(serialized).into(),
+        Err(err) =>
+        {
+            warn!("Failed to serialize the reply to notarize: {err:?}");
+            return;
+        },
+    };
+    let submission = Submission {
+        task_id: reply
+            .task_id()
+            .to_string(),
+        digest,
+    };
+    if let Err(TrySendError::Full(_)) = sender.try_send(submission)
+    {
+        counter!("zkmr_worker_notarization_failures_total").increment(1);
+    }
+}
+
+/// Submits the digest to the notary, returning its receipt.
+fn notarize(
+    client: &reqwest::blocking::Client,
+    kind: NotaryKind,
+    url: &str,
+    submission: &Submission,
+) -> Result<Vec<u8>>
+{
+    let (content_type, body) = match kind
+    {
+        NotaryKind::Rfc3161 =>
+        {
+            (
+                "application/timestamp-query",
+                timestamp_request(
+                    &submission.digest,
+                    rand::random(),
+                ),
+            )
+        },
+        NotaryKind::Anchor =>
+        {
+            (
+                "application/json",
+                serde_json::to_vec(
+                    &serde_json::json!({
+                        "task_id": submission.task_id,
+                        "digest": format!("0x{}", hex::encode(submission.digest)),
+                        "algorithm": "sha256",
+                    }),
+                )?,
+            )
+        },
+    };
+    let receipt = client
+        .post(url)
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            content_type,
+        )
+        .timeout(NOTARY_TIMEOUT)
+        .body(body)
+        .send()
+        .and_then(reqwest::blocking::Response::error_for_status)
+        .and_then(reqwest::blocking::Response::bytes)
+        .with_context(|| format!("failed to post to `{url}`"))?
+        .to_vec();
+    if kind == NotaryKind::Rfc3161
+    {
+        check_timestamp_response(&receipt)?;
+    }
+
+    Ok(receipt)
+}
+
+/// Writes the receipt as `<task id>.tsr` for the timestamping authorities, `.json` otherwise.
+fn write_receipt(
+    receipts_dir: &Path,
+    kind: NotaryKind,
+    submission: &Submission,
+    receipt: &[u8],
+) -> Result<PathBuf>
+{
+    let extension = match kind
+    {
+        NotaryKind::Rfc3161 => "tsr",
+        NotaryKind::Anchor => "json",
+    };
+    // The task ids may hold path separators.
+    let name = submission
+        .task_id
+        .replace(
+            [
+                '/',
+                '\\',
+            ],
+            "_",
+        );
+    let path = receipts_dir.join(format!("{name}.{extension}"));
+    std::fs::write(
+        &path,
+        receipt,
+    )
+    .with_context(
+        || {
+            format!(
+                "failed to write `{}`",
+                path.display()
+            )
+        },
+    )?;
+    Ok(path)
+}
+
+/// The DER `TimeStampReq` of RFC 3161 for a SHA-256 `digest`, requesting the certificate of the
+/// authority in the response.
+fn timestamp_request(
+    digest: &[u8; 32],
+    nonce: u64,
+) -> Vec<u8>
+{
+    let mut message_imprint = SHA256_ALGORITHM.to_vec();
+    message_imprint.extend_from_slice(b"\x04\x20");
+    message_imprint.extend_from_slice(digest);
+
+    // The version, the message imprint, the nonce and the certificate request.
+    let mut request = b"\x02\x01\x01\x30".to_vec();
+    request.push(message_imprint.len() as u8);
+    request.extend_from_slice(&message_imprint);
+    // The leading zero keeps the nonce positive.
+    request.extend_from_slice(b"\x02\x09\x00");
+    request.extend_from_slice(&nonce.to_be_bytes());
+    request.extend_from_slice(b"\x01\x01\xff");
+
+    let mut encoded = b"\x30".to_vec();
+    encoded.push(request.len() as u8);
+    encoded.extend_from_slice(&request);
+    encoded
+}
+
+/// Checks that the DER `TimeStampResp` grants the timestamp.
+fn check_timestamp_response(response: &[u8]) -> Result<()>
+{
+    let (response, _) = der_sequence(response).context("invalid timestamp response")?;
+    let (status_info, _) = der_sequence(response).context("invalid timestamp status")?;
+    let [0x02, 0x01, status, ..] = status_info
+    else
+    {
+        bail!("invalid timestamp status");
+    };
+    // 0 is granted and 1 granted with modifications, the others are refusals.
+    ensure!(
+        *status <= 1,
+        "the timestamping authority refused the request with status {status}"
+    );
+    Ok(())
+}
+
+/// Splits a DER sequence into its content and the bytes following it.
+fn der_sequence(
+    der: &[u8]
+) -> Option<(
+    &[u8],
+    &[u8],
+)>
+{
+    let (&tag, der) = der.split_first()?;
+    if tag != 0x30
+    {
+        return None;
+    }
+    let (&first, der) = der.split_first()?;
+    let (len, der) = if first < 0x80
+    {
+        (
+            first as usize,
+            der,
+        )
+    }
+    else
+    {
+        let bytes = (first & 0x7F) as usize;
+        if bytes == 0 || bytes > std::mem::size_of::<usize>() || der.len() < bytes
+        {
+            return None;
+        }
+        let len = der[..bytes]
+            .iter()
+            .fold(
+                0usize,
+                |len, &b| len << 8 | b as usize,
+            );
+        (
+            len,
+            &der[bytes..],
+        )
+    };
+    (der.len() >= len).then(|| der.split_at(len))
+}
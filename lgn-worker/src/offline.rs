@@ -0,0 +1,350 @@
+//! Proving of tasks exchanged through local directories, for air-gapped workers.
+//!
+//! Task envelopes are read from the input directory and their replies written to the output
+//! directory, each of them atomically. The processed files are appended to a ledger, so that a
+//! restarted worker resumes where it stopped.
+
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use lgn_messages::types::error_code::ErrorCode;
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskTimings;
+use lgn_messages::types::TaskType;
+use metrics::counter;
+use tracing::error;
+use tracing::info;
+
+use crate::checksum::verify_directory_checksums;
+use crate::config::Config;
+use crate::config::OfflineConfig;
+use crate::manager::task_queue::TaskOrder;
+use crate::manager::task_queue::TaskQueue;
+use crate::manager::v1::register_v1_provers;
+use crate::manager::ProversManager;
+use crate::process_downstream_payload;
+
+/// The gateway label of the offline tasks in logs and metrics.
+const GATEWAY: &str = "offline";
+
+/// The ledger of the processed task files, in the output directory.
+const LEDGER_FILE: &str = "processed.ledger";
+
+/// Proves the task files of the input directory as they appear, never returning unless failing.
+pub(crate) fn run(
+    config: &Config,
+    offline: &OfflineConfig,
+) -> Result<()>
+{
+    let mut provers_manager = ProversManager::<TaskType, ReplyType>::new();
+    register_v1_provers(
+        config,
+        &mut provers_manager,
+    )
+    .context("while registering provers")?;
+
+    // No gateway to fetch the checksums from, the local checksum file is trusted.
+    if !config
+        .public_params
+        .skip_checksum
+    {
+        verify_directory_checksums(
+            &config
+                .public_params
+                .dir,
+            &config
+                .public_params
+                .checksum_expected_local_path,
+        )
+        .context("Failed to verify checksums")?;
+    }
+
+    let input_dir = Path::new(&offline.input_dir);
+    let output_dir = Path::new(&offline.output_dir);
+    std::fs::create_dir_all(output_dir).with_context(
+        || {
+            format!(
+                "failed to create `{}`",
+                output_dir.display()
+            )
+        },
+    )?;
+    let ledger_path = output_dir.join(LEDGER_FILE);
+    let mut processed = read_ledger(&ledger_path)?;
+    info!(
+        "Watching `{}` for tasks, {} already processed",
+        input_dir.display(),
+        processed.len()
+    );
+
+    loop
+    {
+        // The tasks which can not be read are reported right away, the others are queued.
+        let mut queue = TaskQueue::new(
+            config
+                .worker
+                .deadline_ordering,
+        );
+        for file_name in pending_tasks(
+            input_dir,
+            &processed,
+        )?
+        {
+            let path = input_dir.join(&file_name);
+            match read_task(&path)
+            {
+                Ok(envelope) =>
+                {
+                    queue.push(
+                        TaskOrder::of(&envelope),
+                        (
+                            file_name,
+                            envelope,
+                        ),
+                    )
+                },
+                Err(err) =>
+                {
+                    record_outcome(
+                        output_dir,
+                        &ledger_path,
+                        &mut processed,
+                        file_name,
+                        Err(err),
+                    )?
+                },
+            }
+        }
+
+        while let Some((file_name, envelope)) = queue.pop()
+        {
+            info!(
+                "Processing task file `{}`",
+                input_dir
+                    .join(&file_name)
+                    .display()
+            );
+            let result = process_downstream_payload(
+                &provers_manager,
+                GATEWAY,
+                None,
+                envelope,
+                TaskTimings::default(),
+            )
+            .map_err(|err| anyhow::anyhow!(err))
+            .and_then(|reply| Ok(serde_json::to_string(&reply)?));
+            record_outcome(
+                output_dir,
+                &ledger_path,
+                &mut processed,
+                file_name,
+                result,
+            )?;
+        }
+
+        std::thread::sleep(Duration::from_secs(offline.poll_interval_secs));
+    }
+}
+
+/// Writes the reply, or the error, of the task file `file_name` and records it as processed.
+fn record_outcome(
+    output_dir: &Path,
+    ledger_path: &Path,
+    processed: &mut HashSet<String>,
+    file_name: String,
+    result: Result<String>,
+) -> Result<()>
+{
+    let (output, content) = match result
+    {
+        Ok(reply) =>
+        {
+            (
+                "reply.json",
+                reply,
+            )
+        },
+        Err(err) =>
+        {
+            error!("Task file `{file_name}` failed: {err:?}");
+            counter!(
+                "zkmr_worker_error_count",
+                "error_type" => ErrorCode::OfflineTask.label(),
+                "error_code" => ErrorCode::OfflineTask.to_string(),
+                "gateway" => GATEWAY,
+            )
+            .increment(1);
+            (
+                "error.txt",
+                format!("{err:?}"),
+            )
+        },
+    };
+
+    let stem = file_name
+        .strip_suffix(".json")
+        .unwrap_or(&file_name);
+    write_atomically(
+        &output_dir.join(format!("{stem}.{output}")),
+        content.as_bytes(),
+    )?;
+    append_to_ledger(
+        ledger_path,
+        &file_name,
+    )?;
+    processed.insert(file_name);
+
+    Ok(())
+}
+
+/// Reads the task envelope in `path`.
+fn read_task(path: &Path) -> Result<MessageEnvelope<TaskType>>
+{
+    let content = std::fs::read_to_string(path).with_context(
+        || {
+            format!(
+                "failed to read `{}`",
+                path.display()
+            )
+        },
+    )?;
+    serde_json::from_str::<MessageEnvelope<TaskType>>(&content)
+        .context("failed to parse the task envelope")
+}
+
+/// The not yet processed `.json` files of `dir`, in name order.
+fn pending_tasks(
+    dir: &Path,
+    processed: &HashSet<String>,
+) -> Result<Vec<String>>
+{
+    let mut pending = vec![];
+    for entry in std::fs::read_dir(dir).with_context(
+        || {
+            format!(
+                "failed to list `{}`",
+                dir.display()
+            )
+        },
+    )?
+    {
+        let entry = entry?;
+        let Some(file_name) = entry
+            .file_name()
+            .to_str()
+            .map(str::to_string)
+        else
+        {
+            continue;
+        };
+        // Files being copied in are expected under another extension until renamed.
+        if file_name.ends_with(".json")
+            && entry
+                .file_type()?
+                .is_file()
+            && !processed.contains(&file_name)
+        {
+            pending.push(file_name);
+        }
+    }
+    pending.sort();
+
+    Ok(pending)
+}
+
+fn read_ledger(path: &Path) -> Result<HashSet<String>>
+{
+    match std::fs::read_to_string(path)
+    {
+        Ok(content) =>
+        {
+            Ok(
+                content
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            )
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(err) =>
+        {
+            Err(err).with_context(
+                || {
+                    format!(
+                        "failed to read `{}`",
+                        path.display()
+                    )
+                },
+            )
+        },
+    }
+}
+
+fn append_to_ledger(
+    path: &Path,
+    file_name: &str,
+) -> Result<()>
+{
+    let mut ledger = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(
+            || {
+                format!(
+                    "failed to open `{}`",
+                    path.display()
+                )
+            },
+        )?;
+    writeln!(
+        ledger,
+        "{file_name}"
+    )?;
+    ledger.sync_all()?;
+
+    Ok(())
+}
+
+/// Writes `content` to `path`, so that readers never see a partial file.
+fn write_atomically(
+    path: &Path,
+    content: &[u8],
+) -> Result<()>
+{
+    let mut tmp = PathBuf::from(path);
+    tmp.as_mut_os_string()
+        .push(".tmp");
+    std::fs::write(
+        &tmp,
+        content,
+    )
+    .with_context(
+        || {
+            format!(
+                "failed to write `{}`",
+                tmp.display()
+            )
+        },
+    )?;
+    std::fs::rename(
+        &tmp,
+        path,
+    )
+    .with_context(
+        || {
+            format!(
+                "failed to write `{}`",
+                path.display()
+            )
+        },
+    )
+}
@@ -15,6 +15,9 @@ use tracing_subscriber::EnvFilter;
 mod checksum;
 mod config;
 mod manager;
+#[cfg(feature = "prover-query")]
+mod schema;
+mod startup;
 
 #[derive(Parser, Clone, Debug)]
 /// Run the prover against a JSON file containing a task envelope as sent by the
@@ -157,7 +160,10 @@ fn main() -> Result<()>
         )?;
 
     provers_manager
-        .delegate_proving(&envelope)
+        .delegate_proving(
+            &envelope,
+            &(),
+        )
         .context("proof failed")?;
 
     Ok(())
@@ -0,0 +1,578 @@
+//! Serves a verified copy of the params to the workers of an operator, so that they do not each
+//! download them from the public CDN.
+//!
+//! The copy is verified against the checksums of the origin when the mirror starts, the
+//! corrupted files being deleted and the mirror refusing to start. Only the files listed in the
+//! checksums are served, with range requests and their BLAKE3 hash in the `X-Checksum-Blake3`
+//! header, and the checksums file itself is passed through under its origin name. The workers
+//! point `public_params.mirror_url` to the mirror and fall back to the origin when it fails.
+//!
+//! Restart the mirror after the params of the origin changed.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::*;
+use checksum::fetch_checksum_file;
+use checksum::verify_directory_checksums;
+use checksums::ops::read_hashes;
+use clap::Parser;
+use tracing::debug;
+use tracing::info;
+use tracing::level_filters::LevelFilter;
+use tracing::warn;
+use tracing_subscriber::EnvFilter;
+
+mod checksum;
+
+/// A request taking longer than this to be sent is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The request headers read at most, the workers only send a few.
+const MAX_HEADERS: usize = 64;
+
+/// The bytes of a request line or header read at most.
+const MAX_LINE_BYTES: u64 = 8 * 1024;
+
+/// The connections served at once, each on its own thread, the next ones being closed unanswered.
+const MAX_CONNECTIONS: usize = 256;
+
+#[derive(Parser, Clone, Debug)]
+/// Serve a verified copy of the params over HTTP to the workers of an operator.
+struct Cli
+{
+    #[clap(
+        short,
+        long
+    )]
+    /// The params directory, e.g. the `public_params.dir` of a worker which downloaded them.
+    dir: PathBuf,
+
+    #[clap(long)]
+    /// The URL of the checksums of the origin, as the `public_params.checksum_url` of the
+    /// workers.
+    checksum_url: String,
+
+    #[clap(
+        long,
+        default_value = "./mirror_checksums.txt"
+    )]
+    /// Where to store the checksums, outside of the params directory.
+    checksum_file: PathBuf,
+
+    #[clap(
+        short,
+        long,
+        default_value = "0.0.0.0:8080"
+    )]
+    /// The address to listen on.
+    listen: SocketAddr,
+}
+
+/// The files served, by request path.
+struct Catalog
+{
+    /// The params files with their BLAKE3 hash.
+    params: BTreeMap<String, String>,
+    /// The request path of the checksums file.
+    checksums_path: String,
+    dir: PathBuf,
+    checksum_file: PathBuf,
+}
+
+fn main() -> Result<()>
+{
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .init();
+    let cli = Cli::parse();
+
+    fetch_checksum_file(
+        &cli.checksum_url,
+        &cli.checksum_file,
+    )?;
+    verify_directory_checksums(
+        &cli.dir,
+        &cli.checksum_file,
+    )
+    .context("the params copy does not match the checksums of the origin")?;
+    let params = read_hashes(
+        &mut std::io::stderr(),
+        &(
+            "checksums".to_string(),
+            cli.checksum_file
+                .clone(),
+        ),
+    )
+    .map_err(|err| anyhow!("invalid checksums file: {err:?}"))?;
+    let checksums_path = cli
+        .checksum_url
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let catalog = Arc::new(
+        Catalog {
+            params,
+            checksums_path,
+            dir: cli.dir,
+            checksum_file: cli.checksum_file,
+        },
+    );
+
+    let listener = TcpListener::bind(cli.listen).with_context(
+        || {
+            format!(
+                "failed to listen on {}",
+                cli.listen
+            )
+        },
+    )?;
+    info!(
+        "Serving {} params files and `{}` on {}",
+        catalog
+            .params
+            .len(),
+        catalog.checksums_path,
+        cli.listen
+    );
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming()
+    {
+        let stream = match stream
+        {
+            Result::Ok(stream) => stream,
+            Err(err) =>
+            {
+                warn!("Failed to accept a connection: {err}");
+                continue;
+            },
+        };
+        let Some(slot) = ConnectionSlot::take(&connections)
+        else
+        {
+            warn!("Closing a connection, {MAX_CONNECTIONS} are already served");
+            continue;
+        };
+        let catalog = catalog.clone();
+        std::thread::spawn(
+            move || {
+                let _slot = slot;
+                if let Err(err) = serve(
+                    &catalog,
+                    stream,
+                )
+                {
+                    debug!("Connection ended: {err:?}");
+                }
+            },
+        );
+    }
+
+    Ok(())
+}
+
+/// One of the [`MAX_CONNECTIONS`] served at once, freed when dropped.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot
+{
+    fn take(connections: &Arc<AtomicUsize>) -> Option<Self>
+    {
+        connections
+            .fetch_update(
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                |served| (served < MAX_CONNECTIONS).then_some(served + 1),
+            )
+            .ok()
+            .map(|_| Self(connections.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot
+{
+    fn drop(&mut self)
+    {
+        self.0
+            .fetch_sub(
+                1,
+                Ordering::AcqRel,
+            );
+    }
+}
+
+/// Reads a line of the request, of at most [`MAX_LINE_BYTES`].
+fn read_line(
+    reader: &mut BufReader<TcpStream>,
+    line: &mut String,
+) -> Result<usize>
+{
+    let read = reader
+        .take(MAX_LINE_BYTES)
+        .read_line(line)?;
+    ensure!(
+        read == 0 || line.ends_with('\n'),
+        "a line of the request is longer than {MAX_LINE_BYTES} bytes"
+    );
+    Ok(read)
+}
+
+/// Answers one request of `stream`, then closes it.
+fn serve(
+    catalog: &Catalog,
+    mut stream: TcpStream,
+) -> Result<()>
+{
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    read_line(
+        &mut reader,
+        &mut request_line,
+    )?;
+    let mut range = None;
+    for _ in 0..MAX_HEADERS
+    {
+        let mut header = String::new();
+        if read_line(
+            &mut reader,
+            &mut header,
+        )? == 0
+            || header
+                .trim()
+                .is_empty()
+        {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+        {
+            if name.eq_ignore_ascii_case("range")
+            {
+                range = Some(
+                    value
+                        .trim()
+                        .to_string(),
+                );
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (
+        parts.next(),
+        parts.next(),
+    )
+    else
+    {
+        return respond(
+            &mut stream,
+            "400 Bad Request",
+            &[],
+        );
+    };
+    if method != "GET" && method != "HEAD"
+    {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            &[
+                (
+                    "Allow",
+                    "GET, HEAD",
+                ),
+            ],
+        );
+    }
+    let path = target
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches('/');
+    // Only the listed files are served, whatever else the directory holds.
+    let (file, hash) = if path == catalog.checksums_path
+    {
+        (
+            catalog
+                .checksum_file
+                .clone(),
+            None,
+        )
+    }
+    else if let Some(hash) = catalog
+        .params
+        .get(path)
+    {
+        (
+            catalog
+                .dir
+                .join(path),
+            Some(hash.as_str()),
+        )
+    }
+    else
+    {
+        return respond(
+            &mut stream,
+            "404 Not Found",
+            &[],
+        );
+    };
+    let Result::Ok(mut file) = File::open(&file)
+    else
+    {
+        return respond(
+            &mut stream,
+            "404 Not Found",
+            &[],
+        );
+    };
+    let len = file
+        .metadata()?
+        .len();
+
+    let (status, start, end) = match range
+        .as_deref()
+        .map(
+            |range| {
+                parse_range(
+                    range,
+                    len,
+                )
+            },
+        )
+    {
+        None | Some(RangeRequest::Ignored) =>
+        {
+            (
+                "200 OK",
+                0,
+                len,
+            )
+        },
+        Some(RangeRequest::Satisfiable(start, end)) =>
+        {
+            (
+                "206 Partial Content",
+                start,
+                end,
+            )
+        },
+        Some(RangeRequest::Unsatisfiable) =>
+        {
+            return respond(
+                &mut stream,
+                "416 Range Not Satisfiable",
+                &[
+                    (
+                        "Content-Range",
+                        &format!("bytes */{len}"),
+                    ),
+                ],
+            );
+        },
+    };
+    let content_length = (end - start).to_string();
+    let content_range = format!(
+        "bytes {start}-{}/{len}",
+        end.saturating_sub(1)
+    );
+    let etag = hash.map(|hash| format!("\"{hash}\""));
+    let mut headers = vec![
+        (
+            "Content-Type",
+            "application/octet-stream",
+        ),
+        (
+            "Content-Length",
+            content_length.as_str(),
+        ),
+        (
+            "Accept-Ranges",
+            "bytes",
+        ),
+    ];
+    if status.starts_with("206")
+    {
+        headers.push(
+            (
+                "Content-Range",
+                &content_range,
+            ),
+        );
+    }
+    if let (Some(hash), Some(etag)) = (
+        hash,
+        &etag,
+    )
+    {
+        headers.push(
+            (
+                "X-Checksum-Blake3",
+                hash,
+            ),
+        );
+        headers.push(
+            (
+                "ETag",
+                etag,
+            ),
+        );
+    }
+    respond(
+        &mut stream,
+        status,
+        &headers,
+    )?;
+    if method == "HEAD"
+    {
+        return Ok(());
+    }
+
+    file.seek(SeekFrom::Start(start))?;
+    let sent = std::io::copy(
+        &mut (&mut file).take(end - start),
+        &mut stream,
+    )?;
+    info!(
+        "Served `{path}` bytes {start}-{} to {}",
+        start + sent,
+        stream.peer_addr()?
+    );
+    Ok(())
+}
+
+/// The response to the `Range` header of a request.
+#[derive(Debug, PartialEq)]
+enum RangeRequest
+{
+    /// The bytes from the first offset to the second one, excluded.
+    Satisfiable(
+        u64,
+        u64,
+    ),
+    Unsatisfiable,
+    /// Not a single byte range, the whole file is sent.
+    Ignored,
+}
+
+/// Parses a single `bytes=` range of a file of `len` bytes, e.g. `bytes=0-1023`, `bytes=1024-`
+/// or `bytes=-1024` for the last KB.
+fn parse_range(
+    range: &str,
+    len: u64,
+) -> RangeRequest
+{
+    let Some(spec) = range.strip_prefix("bytes=")
+    else
+    {
+        return RangeRequest::Ignored;
+    };
+    let Some((start, end)) = spec.split_once('-')
+    else
+    {
+        return RangeRequest::Ignored;
+    };
+    if spec.contains(',')
+    {
+        return RangeRequest::Ignored;
+    }
+    let (start, end) = match (
+        start.trim(),
+        end.trim(),
+    )
+    {
+        ("", suffix) =>
+        {
+            let Result::Ok(suffix) = suffix.parse::<u64>()
+            else
+            {
+                return RangeRequest::Ignored;
+            };
+            if suffix == 0
+            {
+                return RangeRequest::Unsatisfiable;
+            }
+            (
+                len.saturating_sub(suffix),
+                len,
+            )
+        },
+        (start, end) =>
+        {
+            let Result::Ok(start) = start.parse::<u64>()
+            else
+            {
+                return RangeRequest::Ignored;
+            };
+            let end = if end.is_empty()
+            {
+                len
+            }
+            else
+            {
+                match end.parse::<u64>()
+                {
+                    Result::Ok(end) if end >= start => (end + 1).min(len),
+                    _ => return RangeRequest::Ignored,
+                }
+            };
+            (
+                start,
+                end,
+            )
+        },
+    };
+    if start >= len
+    {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Satisfiable(
+        start,
+        end,
+    )
+}
+
+/// Writes the status line and the `headers` of a response, closing the connection after it.
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(
+        &str,
+        &str,
+    )],
+) -> Result<()>
+{
+    let mut response = format!("HTTP/1.1 {status}\r\nConnection: close\r\n");
+    for (name, value) in headers
+    {
+        response.push_str(&format!("{name}: {value}\r\n"));
+    }
+    if !headers
+        .iter()
+        .any(|(name, _)| *name == "Content-Length")
+    {
+        response.push_str("Content-Length: 0\r\n");
+    }
+    response.push_str("\r\n");
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
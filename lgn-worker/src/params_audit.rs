@@ -0,0 +1,199 @@
+//! Periodic re-verification of the params on disk against the expected checksums.
+//!
+//! A bad disk can corrupt the params after they were verified at startup, producing invalid
+//! proofs until the next restart. The files are re-hashed in the background, with the IO rate
+//! throttled so that proving is not slowed down.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Context;
+use anyhow::Result;
+use checksums::ops::read_hashes;
+use lgn_messages::types::error_code::ErrorCode;
+use metrics::counter;
+use metrics::gauge;
+use tracing::error;
+use tracing::info;
+
+use crate::config::ParamsAuditConfig;
+
+const READ_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Set once a corrupted file was found and the worker was configured to drain.
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// Whether the worker stopped accepting tasks because its params are corrupted.
+pub(crate) fn is_draining() -> bool
+{
+    DRAINING.load(Ordering::Relaxed)
+}
+
+/// Spawns a thread re-verifying the files of `dir` every `interval_secs`.
+///
+/// Does nothing if the audit is disabled.
+pub(crate) fn spawn_audit(
+    config: ParamsAuditConfig,
+    dir: String,
+    checksum_file: String,
+)
+{
+    let Some(interval_secs) = config.interval_secs
+    else
+    {
+        return;
+    };
+
+    info!("Starting the params audit. dir: {dir}");
+    std::thread::spawn(
+        move || {
+            loop
+            {
+                std::thread::sleep(Duration::from_secs(interval_secs));
+                match audit(
+                    &config,
+                    Path::new(&dir),
+                    Path::new(&checksum_file),
+                )
+                {
+                    Ok(corrupted) if corrupted.is_empty() =>
+                    {
+                        gauge!("zkmr_worker_params_audit_ok").set(1.0);
+                    },
+                    Ok(corrupted) =>
+                    {
+                        gauge!("zkmr_worker_params_audit_ok").set(0.0);
+                        error!(
+                            "Params do not match their checksum anymore, restart the worker to \
+                             download them again. files: {corrupted:?}"
+                        );
+                        if config.drain_on_mismatch
+                        {
+                            DRAINING.store(
+                                true,
+                                Ordering::Relaxed,
+                            );
+                        }
+                    },
+                    Err(err) =>
+                    {
+                        error!("Params audit failed: {err:?}");
+                        counter!(
+                            "zkmr_worker_error_count",
+                            "error_type" => ErrorCode::ParamsAudit.label(),
+                            "error_code" => ErrorCode::ParamsAudit.to_string(),
+                        )
+                        .increment(1);
+                    },
+                }
+            }
+        },
+    );
+}
+
+/// Hashes every file listed in the checksum file, returning the ones which do not match.
+pub(crate) fn audit(
+    config: &ParamsAuditConfig,
+    dir: &Path,
+    checksum_file: &Path,
+) -> Result<Vec<String>>
+{
+    let expected = read_hashes(
+        &mut std::io::stderr(),
+        &(
+            "output".to_string(),
+            checksum_file.to_path_buf(),
+        ),
+    )
+    .map_err(
+        |err| {
+            anyhow::anyhow!(
+                "failed to read `{}`: {err:?}",
+                checksum_file.display()
+            )
+        },
+    )?;
+
+    let mut corrupted = vec![];
+    for (file_name, expected_hash) in expected
+    {
+        let path: PathBuf = dir.join(&file_name);
+        if !path.exists()
+        {
+            // Only the files of the enabled provers are downloaded.
+            continue;
+        }
+
+        let hash = hash_file(
+            &path,
+            config.max_bytes_per_sec,
+        )?;
+        if !hash.eq_ignore_ascii_case(&expected_hash)
+        {
+            counter!("zkmr_worker_params_audit_mismatch_total", "file" => file_name.clone())
+                .increment(1);
+            corrupted.push(file_name);
+        }
+    }
+
+    Ok(corrupted)
+}
+
+/// Computes the BLAKE3 hash of `path`, reading at most `max_bytes_per_sec`.
+fn hash_file(
+    path: &Path,
+    max_bytes_per_sec: u64,
+) -> Result<String>
+{
+    let mut file = File::open(path).with_context(
+        || {
+            format!(
+                "failed to open `{}`",
+                path.display()
+            )
+        },
+    )?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0; READ_CHUNK_SIZE];
+    let start = Instant::now();
+    let mut read_total = 0u64;
+    loop
+    {
+        let read = file
+            .read(&mut buffer)
+            .with_context(
+                || {
+                    format!(
+                        "failed to read `{}`",
+                        path.display()
+                    )
+                },
+            )?;
+        if read == 0
+        {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        read_total += read as u64;
+
+        // Sleep until the average rate is back under the budget.
+        let budget = Duration::from_secs_f64(read_total as f64 / max_bytes_per_sec.max(1) as f64);
+        if let Some(ahead) = budget.checked_sub(start.elapsed())
+        {
+            std::thread::sleep(ahead);
+        }
+    }
+
+    Ok(
+        hasher
+            .finalize()
+            .to_hex()
+            .to_string(),
+    )
+}
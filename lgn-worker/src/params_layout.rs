@@ -0,0 +1,155 @@
+//! Versioning of the on-disk layout of the params directory.
+//!
+//! The layout version is recorded next to the params directory, outside of the checksummed files,
+//! so that a worker can detect params written by another version and migrate them instead of
+//! downloading everything again.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use tracing::info;
+
+use crate::checksum::verify_directory_checksums;
+
+/// The layout written by this version of the worker: one file per params, named as in the
+/// configuration.
+pub(crate) const CURRENT_LAYOUT: u32 = 1;
+
+/// The file recording the layout version of the params directory `dir`.
+fn marker_path(dir: &str) -> PathBuf
+{
+    PathBuf::from(
+        format!(
+            "{}.layout",
+            dir.trim_end_matches('/')
+        ),
+    )
+}
+
+/// The layout version of `dir`, `None` if it predates the layout versioning.
+pub(crate) fn detect(dir: &str) -> Result<Option<u32>>
+{
+    let marker = marker_path(dir);
+    let content = match std::fs::read_to_string(&marker)
+    {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) =>
+        {
+            return Err(err).with_context(
+                || {
+                    format!(
+                        "failed to read `{}`",
+                        marker.display()
+                    )
+                },
+            )
+        },
+    };
+
+    let version = content
+        .trim()
+        .parse()
+        .with_context(
+            || {
+                format!(
+                    "invalid layout version in `{}`",
+                    marker.display()
+                )
+            },
+        )?;
+    Ok(Some(version))
+}
+
+/// Fails if `dir` was written by a newer worker, whose layout this version can not read.
+pub(crate) fn ensure_supported(dir: &str) -> Result<()>
+{
+    if let Some(version) = detect(dir)?
+    {
+        if version > CURRENT_LAYOUT
+        {
+            bail!(
+                "the params in `{dir}` use layout {version}, this worker only supports up to \
+                 {CURRENT_LAYOUT}, use a separate params directory or a newer worker"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Migrates `dir` to the current layout, verifying the files against the expected checksums.
+pub(crate) fn migrate(
+    dir: &str,
+    checksum_file: Option<&Path>,
+) -> Result<()>
+{
+    ensure_supported(dir)?;
+    match detect(dir)?
+    {
+        Some(CURRENT_LAYOUT) =>
+        {
+            info!("The params in `{dir}` already use layout {CURRENT_LAYOUT}");
+            return Ok(());
+        },
+        Some(version) => info!("Migrating the params in `{dir}` from layout {version}"),
+        None => info!("Migrating the unversioned params in `{dir}`"),
+    }
+
+    // Unversioned directories already use the flat layout of version 1, only their content needs
+    // to be verified. The steps of later layouts go here, staging the moved or linked files in a
+    // sibling directory which is renamed over `dir` once complete.
+    if let Some(checksum_file) = checksum_file
+    {
+        verify_directory_checksums(
+            dir,
+            checksum_file,
+        )
+        .context("the params to migrate do not match the expected checksums")?;
+    }
+
+    write_marker(
+        dir,
+        CURRENT_LAYOUT,
+    )?;
+    info!("The params in `{dir}` now use layout {CURRENT_LAYOUT}");
+
+    Ok(())
+}
+
+/// Atomically records `version` as the layout of `dir`.
+fn write_marker(
+    dir: &str,
+    version: u32,
+) -> Result<()>
+{
+    let marker = marker_path(dir);
+    let tmp = marker.with_extension("layout.tmp");
+    std::fs::write(
+        &tmp,
+        version.to_string(),
+    )
+    .with_context(
+        || {
+            format!(
+                "failed to write `{}`",
+                tmp.display()
+            )
+        },
+    )?;
+    std::fs::rename(
+        &tmp,
+        &marker,
+    )
+    .with_context(
+        || {
+            format!(
+                "failed to write `{}`",
+                marker.display()
+            )
+        },
+    )
+}
@@ -0,0 +1,131 @@
+//! Control of the proofs at their yield points, between the stages of the proofs made of several,
+//! e.g. the steps of an index task or the parts of a query.
+//!
+//! A stage is a single blocking computation which can not be interrupted, but the worker takes
+//! over between two of them: the progress of the proof is exported, and the proof is cancelled
+//! once the worker drains, rather than proving the remaining stages of a reply the params can not
+//! be trusted for.
+//!
+//! In eco mode, the proof also pauses after each step for the share of its time the cores are
+//! spared, e.g. for a fourth of the step at `eco_mode = 75`, up to `MAX_ECO_PAUSE`, for the
+//! services sharing the host to get the CPU between the bursts of the proof.
+
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
+
+use lgn_provers::provers::Cancelled;
+use lgn_provers::provers::YieldPoint;
+use metrics::counter;
+use metrics::gauge;
+use tracing::debug;
+use tracing::warn;
+
+use crate::params_audit;
+
+/// The longest pause after a step in eco mode.
+const MAX_ECO_PAUSE: Duration = Duration::from_secs(1);
+
+/// The percentage of the cores proving in eco mode, unset outside of it.
+static ECO_MODE_PERCENT: OnceLock<u8> = OnceLock::new();
+
+pub(crate) fn init(eco_mode_percent: Option<u8>)
+{
+    if let Some(percent) = eco_mode_percent
+    {
+        let _ = ECO_MODE_PERCENT.set(percent);
+    }
+}
+
+/// The yield point of the proof of a task.
+pub(crate) struct ProofControl<'a>
+{
+    task_id: &'a str,
+    started: Instant,
+    /// When the last step ended, or the proof started.
+    last_step: Mutex<Instant>,
+}
+
+impl<'a> ProofControl<'a>
+{
+    pub(crate) fn new(task_id: &'a str) -> Self
+    {
+        let started = Instant::now();
+        Self {
+            task_id,
+            started,
+            last_step: Mutex::new(started),
+        }
+    }
+
+    /// Spares the cores for a share of the time of the last step, in eco mode.
+    fn eco_pause(&self)
+    {
+        let Some(percent) = ECO_MODE_PERCENT.get()
+        else
+        {
+            return;
+        };
+        let mut last_step = self
+            .last_step
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let pause = last_step
+            .elapsed()
+            .mul_f64(f64::from(100u8.saturating_sub(*percent)) / 100.0)
+            .min(MAX_ECO_PAUSE);
+        if !pause.is_zero()
+        {
+            std::thread::sleep(pause);
+            counter!("zkmr_worker_eco_mode_paused_ms_total").increment(pause.as_millis() as u64);
+        }
+        *last_step = Instant::now();
+    }
+}
+
+impl YieldPoint for ProofControl<'_>
+{
+    fn reached(
+        &self,
+        stage: &'static str,
+        done: usize,
+        total: usize,
+    ) -> anyhow::Result<()>
+    {
+        debug!(
+            "Task {} proved {done}/{total} step(s) of {stage} in {:?}",
+            self.task_id,
+            self.started
+                .elapsed()
+        );
+        counter!("zkmr_worker_proof_steps_total", "stage" => stage).increment(1);
+        gauge!("zkmr_worker_proof_progress_ratio", "stage" => stage)
+            .set(done as f64 / total.max(1) as f64);
+
+        // A proof fully done is worth its reply, whatever happens next.
+        if done < total && params_audit::is_draining()
+        {
+            warn!(
+                "Cancelling task {} after {done}/{total} step(s) of {stage}, the worker is \
+                 draining",
+                self.task_id
+            );
+            counter!("zkmr_worker_proofs_cancelled_total", "stage" => stage).increment(1);
+            return Err(
+                Cancelled {
+                    stage,
+                    done,
+                    total,
+                    reason: "the params of the worker are corrupted, it is draining".to_string(),
+                }
+                .into(),
+            );
+        }
+        if done < total
+        {
+            self.eco_pause();
+        }
+        Ok(())
+    }
+}
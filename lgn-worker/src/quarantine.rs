@@ -0,0 +1,423 @@
+//! Quarantine of the prover types failing repeatedly.
+//!
+//! Consecutive failures of a prover type usually mean bad params or hardware rather than bad tasks.
+//! Refusing its tasks for a while lets the gateway hand them to healthy workers instead of one
+//! node failing a whole queue.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
+
+use lgn_messages::types::error_code::ErrorCode;
+use lgn_messages::types::ProverType;
+use metrics::counter;
+use metrics::gauge;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::config::ParamsAuditConfig;
+use crate::config::QuarantineConfig;
+use crate::params_audit;
+
+static QUARANTINE: OnceLock<Quarantine> = OnceLock::new();
+
+struct Quarantine
+{
+    config: QuarantineConfig,
+    audit: ParamsAuditConfig,
+    params_dir: String,
+    checksum_file: String,
+    states: Mutex<HashMap<ProverType, State>>,
+}
+
+#[derive(Default)]
+struct State
+{
+    consecutive_failures: u32,
+    /// Set while the prover type is quarantined, `None` meaning until the params are verified.
+    until: Option<Option<Instant>>,
+}
+
+/// Enables the quarantine, if configured.
+pub(crate) fn init(config: &Config)
+{
+    if config
+        .worker
+        .quarantine
+        .max_consecutive_failures
+        .is_none()
+    {
+        return;
+    }
+
+    let _ = QUARANTINE.set(
+        Quarantine {
+            config: config
+                .worker
+                .quarantine
+                .clone(),
+            audit: config
+                .public_params
+                .audit
+                .clone(),
+            params_dir: config
+                .public_params
+                .dir
+                .clone(),
+            checksum_file: config
+                .public_params
+                .checksum_expected_local_path
+                .clone(),
+            states: Default::default(),
+        },
+    );
+}
+
+/// Refuses the task if its prover type is quarantined.
+pub(crate) fn admit(prover_type: Option<ProverType>) -> Result<(), String>
+{
+    let (Some(quarantine), Some(prover_type)) = (
+        QUARANTINE.get(),
+        prover_type,
+    )
+    else
+    {
+        return Ok(());
+    };
+    quarantine.admit(
+        prover_type,
+        Instant::now(),
+    )
+}
+
+/// Records the outcome of a task, quarantining its prover type after too many failures.
+pub(crate) fn record(
+    prover_type: Option<ProverType>,
+    success: bool,
+)
+{
+    let (Some(quarantine), Some(prover_type)) = (
+        QUARANTINE.get(),
+        prover_type,
+    )
+    else
+    {
+        return;
+    };
+    if quarantine.record(
+        prover_type,
+        success,
+        Instant::now(),
+    )
+    {
+        spawn_reverification(prover_type);
+    }
+}
+
+impl Quarantine
+{
+    fn admit(
+        &self,
+        prover_type: ProverType,
+        now: Instant,
+    ) -> Result<(), String>
+    {
+        let mut states = self.lock();
+        let state = states
+            .entry(prover_type)
+            .or_default();
+        match state.until
+        {
+            Some(Some(until)) if now >= until =>
+            {
+                info!("Lifting the quarantine of the {prover_type} tasks");
+                lift(
+                    prover_type,
+                    state,
+                );
+                Ok(())
+            },
+            Some(_) =>
+            {
+                Err(format!("the {prover_type} tasks are quarantined after repeated failures"))
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Returns whether the params are to be verified again before the quarantine it started is
+    /// lifted.
+    fn record(
+        &self,
+        prover_type: ProverType,
+        success: bool,
+        now: Instant,
+    ) -> bool
+    {
+        let mut states = self.lock();
+        let state = states
+            .entry(prover_type)
+            .or_default();
+        if success
+        {
+            state.consecutive_failures = 0;
+            return false;
+        }
+
+        state.consecutive_failures += 1;
+        let max_failures = self
+            .config
+            .max_consecutive_failures
+            .unwrap_or(u32::MAX);
+        if state.consecutive_failures < max_failures
+            || state
+                .until
+                .is_some()
+        {
+            return false;
+        }
+
+        warn!(
+            "Quarantining the {prover_type} tasks after {} consecutive failures",
+            state.consecutive_failures
+        );
+        counter!("zkmr_worker_quarantine_total", "prover_type" => prover_type.to_string())
+            .increment(1);
+        gauge!("zkmr_worker_quarantined", "prover_type" => prover_type.to_string()).set(1.0);
+        if self
+            .config
+            .reverify_params
+        {
+            state.until = Some(None);
+            return true;
+        }
+        state.until = Some(
+            Some(
+                now + Duration::from_secs(
+                    self.config
+                        .cooldown_secs,
+                ),
+            ),
+        );
+        false
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<ProverType, State>>
+    {
+        self.states
+            .lock()
+            .expect("quarantine lock poisoned")
+    }
+}
+
+fn lift(
+    prover_type: ProverType,
+    state: &mut State,
+)
+{
+    state.consecutive_failures = 0;
+    state.until = None;
+    gauge!("zkmr_worker_quarantined", "prover_type" => prover_type.to_string()).set(0.0);
+}
+
+/// Verifies the params in the background, lifting the quarantine once the cooldown elapsed if
+/// they are intact.
+fn spawn_reverification(prover_type: ProverType)
+{
+    std::thread::spawn(
+        move || {
+            let quarantine = QUARANTINE
+                .get()
+                .expect("quarantine initialized");
+            let started = Instant::now();
+            let result = params_audit::audit(
+                &quarantine.audit,
+                Path::new(&quarantine.params_dir),
+                Path::new(&quarantine.checksum_file),
+            );
+            match result
+            {
+                Ok(corrupted) if corrupted.is_empty() =>
+                {
+                    info!(
+                        "The params are intact, the {prover_type} tasks resume after the cooldown"
+                    );
+                    let until = started
+                        + Duration::from_secs(
+                            quarantine
+                                .config
+                                .cooldown_secs,
+                        );
+                    quarantine
+                        .lock()
+                        .entry(prover_type)
+                        .or_default()
+                        .until = Some(Some(until));
+                },
+                Ok(corrupted) =>
+                {
+                    error!(
+                        "The {prover_type} tasks stay quarantined, the params do not match their \
+                         checksum, restart the worker to download them again. files: {corrupted:?}"
+                    );
+                },
+                Err(err) =>
+                {
+                    error!(
+                        "The {prover_type} tasks stay quarantined, verifying the params failed: \
+                         {err:?}"
+                    );
+                    counter!(
+                        "zkmr_worker_error_count",
+                        "error_type" => ErrorCode::ParamsAudit.label(),
+                        "error_code" => ErrorCode::ParamsAudit.to_string(),
+                    )
+                    .increment(1);
+                },
+            }
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn quarantine(reverify_params: bool) -> Quarantine
+    {
+        Quarantine {
+            config: QuarantineConfig {
+                max_consecutive_failures: Some(3),
+                cooldown_secs: 60,
+                reverify_params,
+            },
+            audit: ParamsAuditConfig::default(),
+            params_dir: String::new(),
+            checksum_file: String::new(),
+            states: Mutex::default(),
+        }
+    }
+
+    #[test]
+    fn quarantines_after_consecutive_failures_until_the_cooldown()
+    {
+        let quarantine = quarantine(false);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // A success in between resets the count.
+        for success in [
+            false,
+            false,
+            true,
+            false,
+            false,
+        ]
+        {
+            assert!(
+                !quarantine.record(
+                    ProverType::V1Query,
+                    success,
+                    at(0)
+                )
+            );
+        }
+        assert!(
+            quarantine
+                .admit(
+                    ProverType::V1Query,
+                    at(0)
+                )
+                .is_ok()
+        );
+
+        quarantine.record(
+            ProverType::V1Query,
+            false,
+            at(10),
+        );
+        assert!(
+            quarantine
+                .admit(
+                    ProverType::V1Query,
+                    at(69)
+                )
+                .is_err()
+        );
+        // The other prover types are not quarantined.
+        assert!(
+            quarantine
+                .admit(
+                    ProverType::V1Groth16,
+                    at(69)
+                )
+                .is_ok()
+        );
+
+        assert!(
+            quarantine
+                .admit(
+                    ProverType::V1Query,
+                    at(70)
+                )
+                .is_ok()
+        );
+        // Lifted, the count starts over.
+        for _ in 0..2
+        {
+            quarantine.record(
+                ProverType::V1Query,
+                false,
+                at(71),
+            );
+        }
+        assert!(
+            quarantine
+                .admit(
+                    ProverType::V1Query,
+                    at(71)
+                )
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn waits_for_the_params_to_be_verified_again()
+    {
+        let quarantine = quarantine(true);
+        let start = Instant::now();
+        let reverify = (0..3)
+            .map(
+                |_| {
+                    quarantine.record(
+                        ProverType::V1Preprocessing,
+                        false,
+                        start,
+                    )
+                },
+            )
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reverify,
+            [
+                false,
+                false,
+                true
+            ]
+        );
+        assert!(
+            quarantine
+                .admit(
+                    ProverType::V1Preprocessing,
+                    start + Duration::from_secs(3600)
+                )
+                .is_err()
+        );
+    }
+}
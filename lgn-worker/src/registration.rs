@@ -0,0 +1,194 @@
+//! Optional registration of the worker with the operator registry of a gateway.
+//!
+//! New operators otherwise have to register their worker manually before the gateway accepts its
+//! connections.
+
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use elliptic_curve::sec1::ToEncodedPoint;
+use ethers::utils::hash_message;
+use lgn_messages::types::error_code::ErrorCode;
+use lgn_worker::exit_code::ExitClass;
+use metrics::counter;
+use serde_derive::Serialize;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::AvsConfig;
+use crate::config::Config;
+use crate::get_wallet;
+use crate::unix_now;
+
+/// Delay between two registration attempts.
+const REGISTRATION_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Domain separator preventing registration signatures from being replayed in another context.
+const REGISTRATION_DOMAIN: &str = "lagrange-worker-registration-v1";
+
+/// The registration request sent to the operator registry.
+#[derive(Serialize, Debug)]
+struct RegistrationRequest
+{
+    worker_id: String,
+    worker_class: String,
+    version: String,
+    /// Hex-encoded uncompressed Lagrange public key, without the `0x04` prefix.
+    public_key: String,
+    timestamp: u64,
+    /// Hex-encoded EIP-191 signature of [`RegistrationRequest::message`].
+    signature: String,
+}
+
+impl RegistrationRequest
+{
+    /// The message signed by the operator, binding all the registered fields.
+    fn message(
+        worker_id: &str,
+        worker_class: &str,
+        public_key: &str,
+        timestamp: u64,
+    ) -> String
+    {
+        format!("{REGISTRATION_DOMAIN}:{worker_id}:{worker_class}:{public_key}:{timestamp}")
+    }
+}
+
+/// Registers the worker with the registry of `avs`, if one is configured.
+///
+/// Retries until the registry accepts the worker, a worker which is already registered being
+/// accepted as well.
+pub(crate) async fn maybe_register(
+    config: &Config,
+    avs: &AvsConfig,
+) -> Result<()>
+{
+    let Some(url) = &avs.registration_url
+    else
+    {
+        return Ok(());
+    };
+
+    let client = reqwest::Client::new();
+    let mut attempt = 1;
+    loop
+    {
+        let request = signed_request(
+            config,
+            avs,
+        )?;
+        match submit(
+            &client,
+            url,
+            &request,
+        )
+        .await
+        {
+            Ok(()) =>
+            {
+                info!(
+                    "Worker registered with gateway `{}`. attempt: {attempt}",
+                    avs.label()
+                );
+                return Ok(());
+            },
+            Err(err) =>
+            {
+                warn!(
+                    "Registration with gateway `{}` failed, retrying in {:?}. attempt: {attempt} \
+                     err: {err:?}",
+                    avs.label(),
+                    REGISTRATION_RETRY_INTERVAL
+                );
+                counter!(
+                    "zkmr_worker_error_count",
+                    "error_type" => ErrorCode::Registration.label(),
+                    "error_code" => ErrorCode::Registration.to_string(),
+                    "gateway" => avs.label().to_string(),
+                )
+                .increment(1);
+            },
+        }
+
+        attempt += 1;
+        tokio::time::sleep(REGISTRATION_RETRY_INTERVAL).await;
+    }
+}
+
+/// Builds a registration request signed with the operator Lagrange key.
+fn signed_request(
+    config: &Config,
+    avs: &AvsConfig,
+) -> Result<RegistrationRequest>
+{
+    let wallet = get_wallet(avs).context(ExitClass::Auth)?;
+    let public_key = wallet
+        .signer()
+        .verifying_key()
+        .to_encoded_point(
+            // compress =
+            false,
+        );
+    let public_key = hex::encode(&public_key.as_bytes()[1..]);
+
+    let worker_class = config
+        .worker
+        .instance_type
+        .to_string();
+    let timestamp = unix_now();
+    let message = RegistrationRequest::message(
+        &avs.worker_id,
+        &worker_class,
+        &public_key,
+        timestamp,
+    );
+    let signature = wallet
+        .sign_hash(hash_message(message.as_bytes()))
+        .context("failed to sign the registration request")?;
+
+    Ok(
+        RegistrationRequest {
+            worker_id: avs
+                .worker_id
+                .clone(),
+            worker_class,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            public_key,
+            timestamp,
+            signature: hex::encode(signature.to_vec()),
+        },
+    )
+}
+
+/// Sends the request, succeeding if the registry accepted it.
+async fn submit(
+    client: &reqwest::Client,
+    url: &str,
+    request: &RegistrationRequest,
+) -> Result<()>
+{
+    let response = client
+        .post(url)
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "application/json",
+        )
+        .body(serde_json::to_vec(request)?)
+        .send()
+        .await
+        .with_context(|| format!("failed to reach the registry at `{url}`"))?;
+
+    let status = response.status();
+    if status.is_success() || status == reqwest::StatusCode::CONFLICT
+    {
+        return Ok(());
+    }
+
+    let body = response
+        .text()
+        .await
+        .unwrap_or_default();
+    bail!("the registry rejected the worker with status {status}: {body}")
+}
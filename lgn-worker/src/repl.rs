@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::io::Write;
+use std::time::Instant;
+
+use anyhow::*;
+use checksum::fetch_checksum_file;
+use checksum::verify_directory_checksums;
+use clap::Parser;
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskType;
+use manager::v1::register_v1_provers;
+use manager::ProversManager;
+use serde_json::Value;
+use tracing::info;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+mod checksum;
+mod config;
+mod manager;
+#[cfg(feature = "prover-query")]
+mod schema;
+mod startup;
+
+const HELP: &str = "\
+prove <name> <task.json>  prove the task envelope in the file, storing the proof as <name>;
+                          a \"$<proof>\" string in the file is replaced with the bytes of <proof>
+load <name> <file>        store the content of <file> as the proof <name>
+save <name> <file>        write the proof <name> to <file>
+list                      list the stored proofs
+help                      show this message
+quit                      exit";
+
+#[derive(Parser, Clone, Debug)]
+/// Interactively prove task envelopes, feeding the proofs of a step into the next ones, e.g. a
+/// cell leaf into a row leaf, to debug a preprocessing pipeline step by step.
+///
+/// The commands are read from stdin, so that a session can be scripted.
+struct Cli
+{
+    #[clap(
+        short,
+        long
+    )]
+    /// The config file; `$(toml-worker-lgn)` can be used if devenv is enabled.
+    config: String,
+}
+
+fn main() -> Result<()>
+{
+    let subscriber = tracing_subscriber::fmt()
+        .pretty()
+        .compact()
+        .with_level(true)
+        .with_file(false)
+        .with_line_number(false)
+        .without_time()
+        .with_target(false)
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("Setting up logging failed");
+
+    let cli = Cli::parse();
+
+    let config = config::Config::load(Some(cli.config));
+    config.validate();
+
+    let expected_checksums_file = &config
+        .public_params
+        .checksum_expected_local_path;
+    info!("Fetching the checksum file... ");
+    fetch_checksum_file(
+        &config
+            .public_params
+            .checksum_url,
+        expected_checksums_file,
+    )?;
+
+    info!("Loading the params... ");
+    let mut provers_manager = ProversManager::<TaskType, ReplyType>::new();
+    register_v1_provers(
+        &config,
+        &mut provers_manager,
+    )
+    .context("while registering provers")?;
+    verify_directory_checksums(
+        &config
+            .public_params
+            .dir,
+        expected_checksums_file,
+    )
+    .context("Failed to verify checksums")?;
+    info!("done.");
+
+    let mut proofs = BTreeMap::<String, Vec<u8>>::new();
+    let stdin = std::io::stdin();
+    prompt()?;
+    for line in stdin
+        .lock()
+        .lines()
+    {
+        let line = line?;
+        let args = line
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        match args[..]
+        {
+            [] =>
+            {},
+            ["quit" | "exit"] => break,
+            ["help"] => println!("{HELP}"),
+            ["list"] =>
+            {
+                for (name, proof) in &proofs
+                {
+                    println!(
+                        "{name}\t{} bytes",
+                        proof.len()
+                    );
+                }
+            },
+            ["prove", name, file] =>
+            {
+                match prove(
+                    &provers_manager,
+                    &proofs,
+                    file,
+                )
+                {
+                    Result::Ok(proof) =>
+                    {
+                        proofs.insert(
+                            name.to_string(),
+                            proof,
+                        );
+                    },
+                    Err(err) => eprintln!("error: {err:?}"),
+                }
+            },
+            ["load", name, file] =>
+            {
+                match std::fs::read(file)
+                {
+                    Result::Ok(proof) =>
+                    {
+                        proofs.insert(
+                            name.to_string(),
+                            proof,
+                        );
+                    },
+                    Err(err) => eprintln!("error: failed to read `{file}`: {err}"),
+                }
+            },
+            ["save", name, file] =>
+            {
+                match proofs.get(name)
+                {
+                    Some(proof) =>
+                    {
+                        if let Err(err) = std::fs::write(
+                            file,
+                            proof,
+                        )
+                        {
+                            eprintln!("error: failed to write `{file}`: {err}");
+                        }
+                    },
+                    None => eprintln!("error: unknown proof `{name}`"),
+                }
+            },
+            _ => eprintln!("error: invalid command, try `help`"),
+        }
+        prompt()?;
+    }
+
+    Ok(())
+}
+
+fn prompt() -> Result<()>
+{
+    print!("> ");
+    std::io::stdout().flush()?;
+    Ok(())
+}
+
+/// Proves the task envelope in `file`, after substituting the stored proofs.
+fn prove(
+    provers_manager: &ProversManager<TaskType, ReplyType>,
+    proofs: &BTreeMap<String, Vec<u8>>,
+    file: &str,
+) -> Result<Vec<u8>>
+{
+    let content =
+        std::fs::read_to_string(file).with_context(|| format!("failed to open `{file}`"))?;
+    let mut envelope = serde_json::from_str::<Value>(&content).context("failed to parse JSON")?;
+    substitute(
+        &mut envelope,
+        proofs,
+    )?;
+    let envelope = serde_json::from_value::<MessageEnvelope<TaskType>>(envelope)
+        .context("failed to parse the task envelope")?;
+
+    let start = Instant::now();
+    let reply = std::panic::catch_unwind(
+        || {
+            provers_manager.delegate_proving(
+                &envelope,
+                &(),
+            )
+        },
+    )
+    .map_err(|_| anyhow!("the prover panicked"))?
+    .context("proof failed")?;
+    let proof = reply
+        .content()
+        .proof()
+        .context("the reply carries no proof")?
+        .to_vec();
+    println!(
+        "{}: {} bytes in {:?}",
+        envelope.id(),
+        proof.len(),
+        start.elapsed()
+    );
+
+    Ok(proof)
+}
+
+/// Replaces the `"$<name>"` strings of `value` with the bytes of the stored proof `<name>`.
+fn substitute(
+    value: &mut Value,
+    proofs: &BTreeMap<String, Vec<u8>>,
+) -> Result<()>
+{
+    match value
+    {
+        Value::String(s) if s.starts_with('$') =>
+        {
+            let proof = proofs
+                .get(&s[1..])
+                .with_context(
+                    || {
+                        format!(
+                            "unknown proof `{}`",
+                            &s[1..]
+                        )
+                    },
+                )?;
+            *value = Value::from(proof.clone());
+        },
+        Value::Array(values) =>
+        {
+            for value in values
+            {
+                substitute(
+                    value,
+                    proofs,
+                )?;
+            }
+        },
+        Value::Object(values) =>
+        {
+            for value in values.values_mut()
+            {
+                substitute(
+                    value,
+                    proofs,
+                )?;
+            }
+        },
+        _ =>
+        {},
+    }
+
+    Ok(())
+}
@@ -0,0 +1,404 @@
+//! Protection against the task messages replayed by a gateway connection.
+//!
+//! A misbehaving or compromised connection could send old task messages again, wasting the CPU of
+//! the operator on proofs already delivered. The gateway stamps each task with the time it sent
+//! it, `issued_at_unix`. With `worker.replay_window` set, the `(task_id, issued_at_unix)` pairs
+//! received within the window are remembered, and the messages issued outside of the window, or
+//! received twice within it, are dropped. A task sent again on purpose, e.g. reassigned after a
+//! failure, is issued anew and accepted.
+//!
+//! At most `max_tracked` pairs are remembered. Forgetting one still within the window would let it
+//! be replayed, so the new messages are dropped instead until the oldest pairs expire.
+//!
+//! The messages without `issued_at_unix`, from gateways predating it, can not be told from their
+//! replays, they are accepted unchecked unless `require_issued_at` is set.
+
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::WorkerError;
+use metrics::counter;
+use metrics::gauge;
+use tracing::error;
+
+use crate::config::ReplayWindowConfig;
+
+static WINDOW: OnceLock<Window> = OnceLock::new();
+
+struct Window
+{
+    config: ReplayWindowConfig,
+    seen: Mutex<Seen>,
+}
+
+/// The messages received within the window.
+#[derive(Default)]
+struct Seen
+{
+    pairs: HashSet<(
+        String,
+        u64,
+    )>,
+    /// The pairs in the order they were received, to forget the oldest first.
+    order: VecDeque<(
+        String,
+        u64,
+    )>,
+}
+
+pub(crate) fn init(config: Option<&ReplayWindowConfig>)
+{
+    if let Some(config) = config
+    {
+        let _ = WINDOW.set(
+            Window {
+                config: config.clone(),
+                seen: Mutex::default(),
+            },
+        );
+    }
+}
+
+/// The messages received within the window, the oldest first, for the next version of the worker.
+pub(crate) fn export() -> Vec<(
+    String,
+    u64,
+)>
+{
+    WINDOW
+        .get()
+        .map(
+            |window| {
+                window
+                    .seen
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .order
+                    .iter()
+                    .cloned()
+                    .collect()
+            },
+        )
+        .unwrap_or_default()
+}
+
+/// Remembers the messages received by the previous version of the worker, see [`export`].
+pub(crate) fn import(
+    pairs: Vec<(
+        String,
+        u64,
+    )>
+)
+{
+    if let Some(window) = WINDOW.get()
+    {
+        let mut seen = window
+            .seen
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for pair in pairs
+        {
+            if seen
+                .pairs
+                .insert(pair.clone())
+            {
+                seen.order
+                    .push_back(pair);
+            }
+        }
+    }
+}
+
+/// Checks that the message of `envelope`, received from `gateway` at `now_unix`, is neither stale
+/// nor replayed, remembering it if so.
+///
+/// The refused messages are logged and counted, the caller drops them without a reply: the reply
+/// to the original message was already sent. Accepts everything if the protection is disabled.
+pub(crate) fn admit<T>(
+    gateway: &str,
+    envelope: &MessageEnvelope<T>,
+    now_unix: u64,
+) -> Result<(), WorkerError>
+{
+    let Some(window) = WINDOW.get()
+    else
+    {
+        return Ok(());
+    };
+    let result = window.admit(
+        &envelope.task_id,
+        envelope.issued_at_unix,
+        now_unix,
+    );
+    if let Err(err) = &result
+    {
+        error!("Dropping a task message from gateway `{gateway}`: {err}");
+        let reason = match err
+        {
+            WorkerError::ReplayedTask {
+                ..
+            } => "replayed",
+            WorkerError::ReplayWindowFull {
+                ..
+            } => "full",
+            _ => "stale",
+        };
+        counter!(
+            "zkmr_worker_replays_rejected_total",
+            "gateway" => gateway.to_string(),
+            "reason" => reason,
+        )
+        .increment(1);
+        counter!(
+            "zkmr_worker_error_count",
+            "error_type" => err.as_code().label(),
+            "error_code" => err.as_code().to_string(),
+            "gateway" => gateway.to_string(),
+        )
+        .increment(1);
+    }
+    result
+}
+
+impl Window
+{
+    fn admit(
+        &self,
+        task_id: &str,
+        issued_at_unix: Option<u64>,
+        now_unix: u64,
+    ) -> Result<(), WorkerError>
+    {
+        let window_secs = self
+            .config
+            .window_secs;
+        let Some(issued_at_unix) = issued_at_unix
+        else
+        {
+            if self
+                .config
+                .require_issued_at
+            {
+                return Err(
+                    WorkerError::StaleTask {
+                        task_id: task_id.to_string(),
+                        issued_at_unix: 0,
+                        window_secs,
+                    },
+                );
+            }
+            counter!("zkmr_worker_replay_unchecked_total").increment(1);
+            return Ok(());
+        };
+        // A clock ahead of the worker one is as suspicious as a late one.
+        if now_unix.abs_diff(issued_at_unix) > window_secs
+        {
+            return Err(
+                WorkerError::StaleTask {
+                    task_id: task_id.to_string(),
+                    issued_at_unix,
+                    window_secs,
+                },
+            );
+        }
+
+        let mut seen = self
+            .seen
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        seen.forget(now_unix.saturating_sub(window_secs));
+        let pair = (
+            task_id.to_string(),
+            issued_at_unix,
+        );
+        if seen
+            .pairs
+            .contains(&pair)
+        {
+            return Err(
+                WorkerError::ReplayedTask {
+                    task_id: task_id.to_string(),
+                    issued_at_unix,
+                },
+            );
+        }
+        let max_tracked = self
+            .config
+            .max_tracked;
+        if seen
+            .order
+            .len()
+            >= max_tracked
+        {
+            return Err(
+                WorkerError::ReplayWindowFull {
+                    task_id: task_id.to_string(),
+                    max_tracked,
+                },
+            );
+        }
+        seen.pairs
+            .insert(pair.clone());
+        seen.order
+            .push_back(pair);
+        gauge!("zkmr_worker_replay_window_tracked").set(
+            seen.order
+                .len() as f64,
+        );
+        Ok(())
+    }
+}
+
+impl Seen
+{
+    /// Forgets the messages issued before `cutoff_unix`.
+    ///
+    /// The messages are forgotten in the order they were received, one issued slightly earlier
+    /// than the ones before it is remembered a little longer.
+    fn forget(
+        &mut self,
+        cutoff_unix: u64,
+    )
+    {
+        while let Some((_, issued_at_unix)) = self
+            .order
+            .front()
+        {
+            if *issued_at_unix >= cutoff_unix
+            {
+                break;
+            }
+            if let Some(pair) = self
+                .order
+                .pop_front()
+            {
+                self.pairs
+                    .remove(&pair);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn refuses_the_stale_and_replayed_messages()
+    {
+        let window = Window {
+            config: ReplayWindowConfig {
+                window_secs: 60,
+                max_tracked: 2,
+                require_issued_at: false,
+            },
+            seen: Mutex::default(),
+        };
+
+        assert!(
+            window
+                .admit(
+                    "t1",
+                    Some(1000),
+                    1010
+                )
+                .is_ok()
+        );
+        assert!(
+            matches!(
+                window.admit(
+                    "t1",
+                    Some(1000),
+                    1020
+                ),
+                Err(WorkerError::ReplayedTask { .. })
+            )
+        );
+        // Reissued on purpose.
+        assert!(
+            window
+                .admit(
+                    "t1",
+                    Some(1015),
+                    1020
+                )
+                .is_ok()
+        );
+        assert!(
+            matches!(
+                window.admit(
+                    "t2",
+                    Some(900),
+                    1020
+                ),
+                Err(WorkerError::StaleTask { .. })
+            )
+        );
+        assert!(
+            matches!(
+                window.admit(
+                    "t2",
+                    Some(1200),
+                    1020
+                ),
+                Err(WorkerError::StaleTask { .. })
+            )
+        );
+        assert!(
+            window
+                .admit(
+                    "t2",
+                    None,
+                    1020
+                )
+                .is_ok()
+        );
+
+        // Past `max_tracked`, the new messages are refused rather than the unexpired ones
+        // forgotten, and the replays are still told apart.
+        assert!(
+            matches!(
+                window.admit(
+                    "t3",
+                    Some(1020),
+                    1020
+                ),
+                Err(WorkerError::ReplayWindowFull { .. })
+            )
+        );
+        assert!(
+            matches!(
+                window.admit(
+                    "t1",
+                    Some(1000),
+                    1020
+                ),
+                Err(WorkerError::ReplayedTask { .. })
+            )
+        );
+        // Until the oldest message expires.
+        assert!(
+            window
+                .admit(
+                    "t3",
+                    Some(1061),
+                    1061
+                )
+                .is_ok()
+        );
+        assert!(
+            matches!(
+                window.admit(
+                    "t1",
+                    Some(1015),
+                    1061
+                ),
+                Err(WorkerError::ReplayedTask { .. })
+            )
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Admission-time estimation of the gRPC reply sizes.
+//!
+//! The gateway protocol has no chunked replies, so a reply larger than the negotiated message size
+//! can not be delivered. Tasks whose reply is expected to exceed it are rejected before spending
+//! minutes proving them.
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use lgn_messages::types::error_code::ErrorCode;
+use lgn_messages::types::ProverType;
+use lgn_messages::types::TaskType;
+use lgn_messages::types::WorkerError;
+use metrics::counter;
+use metrics::gauge;
+
+/// The latest reply sizes the estimate is the median of, so that an outlier does not reject the
+/// following tasks of its type.
+const WINDOW: usize = 5;
+
+/// The replies to have been sent before the estimate rejects any task.
+const MIN_SAMPLES: usize = 3;
+
+/// Every how many tasks refused in a row one is proven anyway, for its reply to correct the
+/// estimate rather than it rejecting every later task of its type.
+const PROBE_EVERY: usize = 10;
+
+/// The latest reply sizes of a prover type.
+#[derive(Default)]
+struct Samples
+{
+    sizes: VecDeque<usize>,
+    /// The tasks refused since the last one admitted.
+    refused: usize,
+}
+
+impl Samples
+{
+    fn median(&self) -> Option<usize>
+    {
+        if self
+            .sizes
+            .len()
+            < MIN_SAMPLES
+        {
+            return None;
+        }
+        let mut sizes = self
+            .sizes
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        sizes.sort_unstable();
+        Some(sizes[sizes.len() / 2])
+    }
+}
+
+/// The recent reply sizes for every prover type.
+#[derive(Default)]
+pub(crate) struct ReplySizeStats
+{
+    samples: Mutex<HashMap<ProverType, Samples>>,
+}
+
+impl ReplySizeStats
+{
+    /// The expected size of a reply of `prover_type`, once enough were already sent.
+    pub(crate) fn estimate(
+        &self,
+        prover_type: Option<ProverType>,
+    ) -> Option<usize>
+    {
+        let prover_type = prover_type?;
+        self.samples
+            .lock()
+            .expect("reply size stats lock poisoned")
+            .get(&prover_type)
+            .and_then(Samples::median)
+    }
+
+    /// Records the serialized size of a reply of `prover_type`.
+    pub(crate) fn record(
+        &self,
+        prover_type: Option<ProverType>,
+        size: usize,
+    )
+    {
+        let Some(prover_type) = prover_type
+        else
+        {
+            return;
+        };
+
+        let mut samples = self
+            .samples
+            .lock()
+            .expect("reply size stats lock poisoned");
+        let samples = samples
+            .entry(prover_type)
+            .or_default();
+        if samples
+            .sizes
+            .len()
+            == WINDOW
+        {
+            samples
+                .sizes
+                .pop_front();
+        }
+        samples
+            .sizes
+            .push_back(size);
+        if let Some(estimate) = samples.median()
+        {
+            gauge!("zkmr_worker_reply_size_estimate_bytes", "prover_type" => prover_type.to_string())
+                .set(estimate as f64);
+        }
+    }
+
+    /// Rejects the task if its reply is expected to exceed `max_message_size`, but for one in every
+    /// [`PROBE_EVERY`] refused in a row.
+    pub(crate) fn admit(
+        &self,
+        task_id: &str,
+        prover_type: Option<ProverType>,
+        max_message_size: usize,
+    ) -> Result<(), WorkerError>
+    {
+        let Some(prover_type) = prover_type
+        else
+        {
+            return Ok(());
+        };
+        let mut samples = self
+            .samples
+            .lock()
+            .expect("reply size stats lock poisoned");
+        let Some(samples) = samples.get_mut(&prover_type)
+        else
+        {
+            return Ok(());
+        };
+        match samples.median()
+        {
+            Some(estimated)
+                if estimated > max_message_size && samples.refused + 1 < PROBE_EVERY =>
+            {
+                samples.refused += 1;
+                counter!(
+                    "zkmr_worker_error_count",
+                    "error_type" => ErrorCode::ReplyTooLarge.label(),
+                    "error_code" => ErrorCode::ReplyTooLarge.to_string(),
+                )
+                .increment(1);
+                Err(
+                    WorkerError::ReplyTooLarge {
+                        task_id: task_id.to_string(),
+                        size: estimated,
+                        max_message_size,
+                    },
+                )
+            },
+            _ =>
+            {
+                samples.refused = 0;
+                Ok(())
+            },
+        }
+    }
+}
+
+/// The statistics key of `task`, experimental tasks being ignored.
+pub(crate) fn prover_type(task: &TaskType) -> Option<ProverType>
+{
+    match task
+    {
+        TaskType::V1Preprocessing(_) => Some(ProverType::V1Preprocessing),
+        TaskType::V1Query(_) => Some(ProverType::V1Query),
+        TaskType::V1Groth16(_) => Some(ProverType::V1Groth16),
+        TaskType::TxTrie(_) | TaskType::RecProof(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const MAX: usize = 1000;
+
+    fn admitted(
+        stats: &ReplySizeStats,
+        tasks: usize,
+    ) -> usize
+    {
+        (0..tasks)
+            .filter(
+                |task| {
+                    stats
+                        .admit(
+                            &task.to_string(),
+                            Some(ProverType::V1Query),
+                            MAX,
+                        )
+                        .is_ok()
+                },
+            )
+            .count()
+    }
+
+    #[test]
+    fn an_outlier_does_not_reject_the_following_tasks()
+    {
+        let stats = ReplySizeStats::default();
+        stats.record(
+            Some(ProverType::V1Query),
+            100 * MAX,
+        );
+        assert_eq!(
+            admitted(
+                &stats,
+                5
+            ),
+            5
+        );
+        for _ in 0..MIN_SAMPLES
+        {
+            stats.record(
+                Some(ProverType::V1Query),
+                MAX / 2,
+            );
+        }
+        assert_eq!(
+            stats.estimate(Some(ProverType::V1Query)),
+            Some(MAX / 2)
+        );
+        assert_eq!(
+            admitted(
+                &stats,
+                5
+            ),
+            5
+        );
+    }
+
+    #[test]
+    fn probes_while_the_replies_are_too_large()
+    {
+        let stats = ReplySizeStats::default();
+        for _ in 0..WINDOW
+        {
+            stats.record(
+                Some(ProverType::V1Query),
+                2 * MAX,
+            );
+        }
+        assert_eq!(
+            admitted(
+                &stats,
+                2 * PROBE_EVERY
+            ),
+            2
+        );
+        // The probes replying small again, the tasks are admitted.
+        for _ in 0..WINDOW / 2 + 1
+        {
+            stats.record(
+                Some(ProverType::V1Query),
+                MAX / 2,
+            );
+        }
+        assert_eq!(
+            admitted(
+                &stats,
+                5
+            ),
+            5
+        );
+    }
+}
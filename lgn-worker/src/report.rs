@@ -0,0 +1,458 @@
+//! Capability and sizing report of a node, submitted by the operators when onboarding to the AVS.
+//!
+//! The report measures what the worker class depends on, the CPU, memory, disk and the bandwidth
+//! to the params CDN, optionally times actual proofs, and recommends the largest worker class the
+//! node can run. It is signed with the Lagrange key, binding it to the operator.
+
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Context;
+use anyhow::Result;
+use elliptic_curve::sec1::ToEncodedPoint;
+use ethers::utils::hash_message;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskDifficulty;
+use lgn_messages::types::TaskType;
+use rayon::prelude::*;
+use serde_derive::Serialize;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::cpu_features::CpuFeatures;
+use crate::cpu_quota;
+use crate::get_wallet;
+use crate::manager::v1::params_files;
+use crate::manager::ProversManager;
+use crate::self_test;
+use crate::unix_now;
+
+/// Domain separator preventing report signatures from being replayed in another context.
+const REPORT_DOMAIN: &str = "lagrange-worker-report-v1";
+
+/// Size of the buffer hashed and copied by the CPU and memory benchmarks.
+const BENCH_BUFFER_BYTES: usize = 256 * 1024 * 1024;
+
+/// How much of a params file is downloaded to measure the bandwidth to the CDN.
+const DOWNLOAD_BENCH_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The requirements of each worker class, the vCPU and memory of the README table.
+const CLASS_REQUIREMENTS: &[(
+    TaskDifficulty,
+    f64,
+    f64,
+)] = &[
+    (
+        TaskDifficulty::Large,
+        90.0,
+        180.0,
+    ),
+    (
+        TaskDifficulty::Medium,
+        40.0,
+        80.0,
+    ),
+    (
+        TaskDifficulty::Small,
+        20.0,
+        40.0,
+    ),
+];
+
+#[derive(Serialize, Debug)]
+struct Report
+{
+    worker_id: String,
+    version: String,
+    timestamp: u64,
+    configured_instance_type: String,
+    recommended_instance_type: String,
+    host: Host,
+    benchmarks: Benchmarks,
+    /// Empty unless the proofs were requested.
+    proofs: Vec<ProofTiming>,
+}
+
+#[derive(Serialize, Debug)]
+struct Host
+{
+    /// E.g. `x86_64` or `aarch64`.
+    arch: &'static str,
+    logical_cpus: usize,
+    /// The CPU quota of the container, in CPUs, if limited.
+    cpu_quota: Option<f64>,
+    memory_gb: Option<f64>,
+    cpu_features: Vec<&'static str>,
+}
+
+#[derive(Serialize, Debug)]
+struct Benchmarks
+{
+    hash_single_thread_mb_s: f64,
+    hash_all_threads_mb_s: f64,
+    memory_copy_gb_s: f64,
+    /// Synced writes to the params directory, `None` if it is not writable.
+    disk_write_mb_s: Option<f64>,
+    /// Download of the start of a params file, `None` if the CDN could not be reached.
+    params_download_mb_s: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+struct ProofTiming
+{
+    category: &'static str,
+    name: String,
+    seconds: f64,
+    error: Option<String>,
+}
+
+/// The report and the operator signature over it.
+#[derive(Serialize, Debug)]
+struct SignedReport
+{
+    report: Report,
+    /// Hex-encoded uncompressed Lagrange public key, without the `0x04` prefix.
+    public_key: String,
+    /// Hex-encoded EIP-191 signature of the report domain followed by the JSON of the report.
+    signature: String,
+}
+
+/// Measures the node and writes the signed report to `output`, or to the standard output.
+///
+/// The proofs are timed only if `provers_manager` is given, loading the params taking a while.
+pub(crate) fn run_report(
+    config: &Config,
+    provers_manager: Option<&ProversManager<TaskType, ReplyType>>,
+    fixtures: Option<&Path>,
+    output: Option<&Path>,
+) -> Result<()>
+{
+    let avs = config
+        .avs
+        .first()
+        .context("the report is signed with the Lagrange key of an `[avs]` block")?;
+
+    let host = Host {
+        arch: std::env::consts::ARCH,
+        logical_cpus: std::thread::available_parallelism()
+            .map(usize::from)
+            .unwrap_or(1),
+        cpu_quota: cpu_quota::detect_quota(),
+        memory_gb: total_memory_bytes().map(|bytes| bytes as f64 / 1e9),
+        cpu_features: CpuFeatures::detect().detected(),
+    };
+    let benchmarks = run_benchmarks(config);
+    let proofs = match provers_manager
+    {
+        Some(provers_manager) =>
+        {
+            time_proofs(
+                config,
+                provers_manager,
+                fixtures,
+            )?
+        },
+        None => vec![],
+    };
+
+    let report = Report {
+        worker_id: avs
+            .worker_id
+            .clone(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        timestamp: unix_now(),
+        configured_instance_type: config
+            .worker
+            .instance_type
+            .to_string(),
+        recommended_instance_type: recommend(&host).map_or(
+            "none".to_string(),
+            |class| class.to_string(),
+        ),
+        host,
+        benchmarks,
+        proofs,
+    };
+
+    let wallet = get_wallet(avs)?;
+    let public_key = wallet
+        .signer()
+        .verifying_key()
+        .to_encoded_point(
+            // compress =
+            false,
+        );
+    let mut message = format!("{REPORT_DOMAIN}:").into_bytes();
+    // Canonical, for a verifier to sign the report read back from the file by the same bytes.
+    message.extend(lgn_messages::canonical::to_vec(&report)?);
+    let signature = wallet
+        .sign_hash(hash_message(message))
+        .context("failed to sign the report")?;
+    let signed = SignedReport {
+        report,
+        public_key: hex::encode(&public_key.as_bytes()[1..]),
+        signature: hex::encode(signature.to_vec()),
+    };
+
+    let json = serde_json::to_string_pretty(&signed)?;
+    match output
+    {
+        Some(path) =>
+        {
+            std::fs::write(
+                path,
+                json,
+            )
+            .with_context(
+                || {
+                    format!(
+                        "failed to write the report to `{}`",
+                        path.display()
+                    )
+                },
+            )?;
+            info!(
+                "Report written to `{}`, recommended instance type: {}",
+                path.display(),
+                signed
+                    .report
+                    .recommended_instance_type
+            );
+        },
+        None => println!("{json}"),
+    }
+
+    Ok(())
+}
+
+/// The largest class whose vCPU and memory requirements the host meets.
+fn recommend(host: &Host) -> Option<TaskDifficulty>
+{
+    let cpus = host
+        .cpu_quota
+        .unwrap_or(host.logical_cpus as f64)
+        .min(host.logical_cpus as f64);
+    let memory_gb = host
+        .memory_gb
+        .unwrap_or_default();
+    CLASS_REQUIREMENTS
+        .iter()
+        .find(|(_, min_cpus, min_memory_gb)| cpus >= *min_cpus && memory_gb >= *min_memory_gb)
+        .map(|(class, ..)| *class)
+}
+
+fn run_benchmarks(config: &Config) -> Benchmarks
+{
+    let buffer = (0..BENCH_BUFFER_BYTES)
+        .map(|i| i as u8)
+        .collect::<Vec<_>>();
+    let mb = buffer.len() as f64 / 1e6;
+
+    info!("Benchmarking the hash throughput");
+    let elapsed = time(|| blake3::hash(&buffer));
+    let hash_single_thread_mb_s = mb / elapsed.as_secs_f64();
+    let elapsed = time(
+        || {
+            buffer
+                .par_chunks(1024 * 1024)
+                .for_each(
+                    |chunk| {
+                        std::hint::black_box(blake3::hash(chunk));
+                    },
+                )
+        },
+    );
+    let hash_all_threads_mb_s = mb / elapsed.as_secs_f64();
+
+    info!("Benchmarking the memory bandwidth");
+    let mut copy = vec![0u8; buffer.len()];
+    let copies = 4;
+    let elapsed = time(
+        || {
+            for _ in 0..copies
+            {
+                copy.copy_from_slice(&buffer);
+                std::hint::black_box(&copy);
+            }
+        },
+    );
+    let memory_copy_gb_s = copies as f64 * mb / 1e3 / elapsed.as_secs_f64();
+
+    info!("Benchmarking the disk throughput");
+    let disk_write_mb_s = disk_write(
+        Path::new(
+            &config
+                .public_params
+                .dir,
+        ),
+        &buffer,
+    )
+    .inspect_err(|err| warn!("Disk benchmark failed: {err:?}"))
+    .ok();
+
+    info!("Benchmarking the download from the params CDN");
+    let params_download_mb_s = params_download(config)
+        .inspect_err(|err| warn!("Download benchmark failed: {err:?}"))
+        .ok();
+
+    Benchmarks {
+        hash_single_thread_mb_s,
+        hash_all_threads_mb_s,
+        memory_copy_gb_s,
+        disk_write_mb_s,
+        params_download_mb_s,
+    }
+}
+
+fn time<T>(f: impl FnOnce() -> T) -> Duration
+{
+    let start = Instant::now();
+    std::hint::black_box(f());
+    start.elapsed()
+}
+
+/// Writes `buffer` to a scratch file of `dir` and syncs it, in MB/s.
+fn disk_write(
+    dir: &Path,
+    buffer: &[u8],
+) -> Result<f64>
+{
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(".report-disk-bench");
+    let start = Instant::now();
+    let result = std::fs::File::create(&path).and_then(
+        |mut file| {
+            file.write_all(buffer)?;
+            file.sync_all()
+        },
+    );
+    let elapsed = start.elapsed();
+    let _ = std::fs::remove_file(&path);
+    result.with_context(
+        || {
+            format!(
+                "failed to write `{}`",
+                path.display()
+            )
+        },
+    )?;
+
+    Ok(buffer.len() as f64 / 1e6 / elapsed.as_secs_f64())
+}
+
+/// Downloads the start of the first params file of the worker class, in MB/s.
+fn params_download(config: &Config) -> Result<f64>
+{
+    let file_name = params_files(config)
+        .first()
+        .copied()
+        .context("no params file for the worker class")?;
+    let url = format!(
+        "{}/{file_name}",
+        config
+            .public_params
+            .url
+    );
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(120))
+        .build()?;
+
+    let start = Instant::now();
+    let response = client
+        .get(&url)
+        .header(
+            reqwest::header::RANGE,
+            format!(
+                "bytes=0-{}",
+                DOWNLOAD_BENCH_BYTES - 1
+            ),
+        )
+        .send()
+        .and_then(reqwest::blocking::Response::error_for_status)
+        .with_context(|| format!("failed to download `{url}`"))?;
+    // A server ignoring the range sends the whole file, only the first bytes are read.
+    let mut body = std::io::Read::take(
+        response,
+        DOWNLOAD_BENCH_BYTES,
+    );
+    let downloaded = std::io::copy(
+        &mut body,
+        &mut std::io::sink(),
+    )
+    .with_context(|| format!("failed to download `{url}`"))?;
+
+    Ok(
+        downloaded as f64
+            / 1e6
+            / start
+                .elapsed()
+                .as_secs_f64(),
+    )
+}
+
+/// Times the self-test proofs of the worker class and the recorded envelopes of `fixtures`.
+fn time_proofs(
+    config: &Config,
+    provers_manager: &ProversManager<TaskType, ReplyType>,
+    fixtures: Option<&Path>,
+) -> Result<Vec<ProofTiming>>
+{
+    let mut tasks = self_test::synthetic_tasks(
+        config
+            .worker
+            .instance_type,
+    );
+    if let Some(fixtures) = fixtures
+    {
+        tasks.extend(self_test::load_fixtures(fixtures)?);
+    }
+
+    Ok(
+        tasks
+            .into_iter()
+            .map(
+                |(category, name, envelope)| {
+                    info!("Timing {category}/{name}");
+                    let start = Instant::now();
+                    let outcome = std::panic::catch_unwind(
+                        || {
+                            provers_manager.delegate_proving(
+                                &envelope,
+                                &(),
+                            )
+                        },
+                    )
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("prover panicked")));
+                    ProofTiming {
+                        category,
+                        name,
+                        seconds: start
+                            .elapsed()
+                            .as_secs_f64(),
+                        error: outcome
+                            .err()
+                            .map(|err| format!("{err:#}")),
+                    }
+                },
+            )
+            .collect(),
+    )
+}
+
+/// The memory of the host, from `/proc/meminfo`.
+pub(crate) fn total_memory_bytes() -> Option<u64>
+{
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let kb = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
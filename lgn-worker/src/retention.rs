@@ -0,0 +1,421 @@
+//! Background compaction of the worker-owned local stores, e.g. proofs and journals.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use anyhow::Result;
+use lgn_messages::types::error_code::ErrorCode;
+use metrics::counter;
+use metrics::gauge;
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::RetentionConfig;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// A file subject to the retention policy.
+struct Artifact
+{
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Spawns a thread compacting the configured directories every `interval_secs`.
+///
+/// Does nothing if no directory is configured.
+pub(crate) fn spawn_compaction(config: RetentionConfig)
+{
+    if config
+        .dirs
+        .is_empty()
+    {
+        return;
+    }
+
+    info!(
+        "Starting the compaction job. dirs: {:?}",
+        config.dirs
+    );
+    std::thread::spawn(
+        move || {
+            loop
+            {
+                if let Err(err) = compact(
+                    &config,
+                    |path| fs::remove_file(path),
+                )
+                {
+                    error!("Compaction failed: {err:?}");
+                    counter!(
+                        "zkmr_worker_error_count",
+                        "error_type" => ErrorCode::Compaction.label(),
+                        "error_code" => ErrorCode::Compaction.to_string(),
+                    )
+                    .increment(1);
+                }
+                std::thread::sleep(Duration::from_secs(config.interval_secs));
+            }
+        },
+    );
+}
+
+/// Deletes the artifacts older than `max_age_days`, then the oldest remaining ones until the
+/// total size fits in `max_bytes`.
+fn compact(
+    config: &RetentionConfig,
+    remove_file: impl Fn(&Path) -> std::io::Result<()>,
+) -> Result<()>
+{
+    let mut artifacts = vec![];
+    for dir in &config.dirs
+    {
+        collect_artifacts(
+            Path::new(dir),
+            &mut artifacts,
+        )?;
+    }
+    // Oldest first.
+    artifacts.sort_by_key(|a| a.modified);
+
+    let now = SystemTime::now();
+    let mut total_size = artifacts
+        .iter()
+        .map(|a| a.size)
+        .sum::<u64>();
+    let mut deleted = 0;
+
+    for artifact in artifacts
+    {
+        let expired = config
+            .max_age_days
+            .is_some_and(
+                |days| {
+                    now.duration_since(artifact.modified)
+                        .unwrap_or_default()
+                        > Duration::from_secs(days * SECONDS_PER_DAY)
+                },
+            );
+        let over_budget = config
+            .max_bytes
+            .is_some_and(|max_bytes| total_size > max_bytes);
+        if !expired && !over_budget
+        {
+            // Artifacts are sorted by age, the remaining ones are younger and fit in the budget.
+            break;
+        }
+
+        debug!(
+            "Deleting {}. size: {}",
+            artifact
+                .path
+                .display(),
+            artifact.size
+        );
+        match remove_file(&artifact.path)
+        {
+            Ok(()) => deleted += 1,
+            // Deleted meanwhile, e.g. by its owner.
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound =>
+            {},
+            Err(err) =>
+            {
+                // A locked file must not keep the next ones from being deleted.
+                warn!(
+                    "Failed to delete `{}`, skipping it: {err}",
+                    artifact
+                        .path
+                        .display()
+                );
+                counter!(
+                    "zkmr_worker_error_count",
+                    "error_type" => ErrorCode::Compaction.label(),
+                    "error_code" => ErrorCode::Compaction.to_string(),
+                )
+                .increment(1);
+                continue;
+            },
+        }
+        total_size -= artifact.size;
+    }
+
+    if deleted > 0
+    {
+        info!("Compaction deleted {deleted} files, {total_size} bytes remaining");
+    }
+    counter!("zkmr_worker_store_gc_deleted_files_total").increment(deleted);
+    gauge!("zkmr_worker_store_size_bytes").set(total_size as f64);
+    gauge!("zkmr_worker_store_last_gc_timestamp_seconds").set(
+        now.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64(),
+    );
+
+    Ok(())
+}
+
+/// Recursively lists the files under `dir`, a missing directory being empty.
+fn collect_artifacts(
+    dir: &Path,
+    artifacts: &mut Vec<Artifact>,
+) -> Result<()>
+{
+    let entries = match fs::read_dir(dir)
+    {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) =>
+        {
+            return Err(err).with_context(
+                || {
+                    format!(
+                        "failed to list `{}`",
+                        dir.display()
+                    )
+                },
+            )
+        },
+    };
+
+    for entry in entries
+    {
+        let entry = entry?;
+        let metadata = match entry.metadata()
+        {
+            Ok(metadata) => metadata,
+            // Deleted since it was listed.
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if metadata.is_dir()
+        {
+            collect_artifacts(
+                &entry.path(),
+                artifacts,
+            )?;
+        }
+        else if metadata.is_file()
+        {
+            artifacts.push(
+                Artifact {
+                    path: entry.path(),
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                },
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs::File;
+
+    use super::*;
+
+    /// Writes a 100 bytes file last modified `age_secs` ago.
+    fn artifact(
+        path: &Path,
+        age_secs: u64,
+    )
+    {
+        fs::create_dir_all(
+            path.parent()
+                .unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            path,
+            [0u8; 100],
+        )
+        .unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+            .unwrap();
+    }
+
+    fn remaining(dir: &Path) -> Vec<PathBuf>
+    {
+        let mut artifacts = vec![];
+        collect_artifacts(
+            dir,
+            &mut artifacts,
+        )
+        .unwrap();
+        let mut paths = artifacts
+            .into_iter()
+            .map(|a| a.path)
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+
+    fn config(
+        dir: &Path,
+        max_age_days: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> RetentionConfig
+    {
+        RetentionConfig {
+            dirs: vec![
+                dir.display()
+                    .to_string(),
+            ],
+            max_age_days,
+            max_bytes,
+            interval_secs: 1,
+        }
+    }
+
+    #[test]
+    fn deletes_the_expired_then_the_oldest_artifacts()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let [a, b, c, d, e] = [
+            "a",
+            "b",
+            "nested/c",
+            "d",
+            "nested/e",
+        ]
+        .map(
+            |name| {
+                dir.path()
+                    .join(name)
+            },
+        );
+        artifact(
+            &a,
+            5 * SECONDS_PER_DAY,
+        );
+        artifact(
+            &b,
+            3 * SECONDS_PER_DAY,
+        );
+        artifact(
+            &c,
+            2 * SECONDS_PER_DAY,
+        );
+        artifact(
+            &d,
+            SECONDS_PER_DAY,
+        );
+        artifact(
+            &e,
+            60,
+        );
+
+        // Only `a` is expired, then `b` is the oldest over the 300 bytes budget.
+        compact(
+            &config(
+                dir.path(),
+                Some(4),
+                Some(300),
+            ),
+            |path| fs::remove_file(path),
+        )
+        .unwrap();
+        assert_eq!(
+            remaining(dir.path()),
+            [
+                d.clone(),
+                c.clone(),
+                e.clone()
+            ]
+        );
+
+        // Within the budget and younger, nothing else is deleted.
+        compact(
+            &config(
+                dir.path(),
+                Some(4),
+                Some(300),
+            ),
+            |path| fs::remove_file(path),
+        )
+        .unwrap();
+        assert_eq!(
+            remaining(dir.path()),
+            [
+                d,
+                c,
+                e
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_the_artifacts_failing_to_be_deleted()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let [a, b, c, d] = [
+            "a",
+            "b",
+            "c",
+            "d",
+        ]
+        .map(
+            |name| {
+                dir.path()
+                    .join(name)
+            },
+        );
+        artifact(
+            &a,
+            4 * SECONDS_PER_DAY,
+        );
+        artifact(
+            &b,
+            3 * SECONDS_PER_DAY,
+        );
+        artifact(
+            &c,
+            2 * SECONDS_PER_DAY,
+        );
+        artifact(
+            &d,
+            60,
+        );
+
+        // `a` is locked, the next oldest are deleted in its stead.
+        compact(
+            &config(
+                dir.path(),
+                None,
+                Some(200),
+            ),
+            |path| {
+                if path == a
+                {
+                    Err(std::io::ErrorKind::PermissionDenied.into())
+                }
+                else
+                {
+                    fs::remove_file(path)
+                }
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            remaining(dir.path()),
+            [
+                a,
+                d
+            ]
+        );
+    }
+}
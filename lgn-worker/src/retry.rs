@@ -0,0 +1,357 @@
+//! Local retry of the proving failures caused by the environment rather than by the task.
+//!
+//! A failed task used to go back to the gateway, which reassigns it at the cost of a full
+//! roundtrip. The failures of the environment, e.g. an interrupted read of the params, are
+//! retried locally instead. The failures of the task itself would fail again, so the fingerprints
+//! of the inputs which failed are remembered and never retried.
+
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::MessageReplyEnvelope;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskType;
+use lgn_provers::params::ParamsError;
+use lgn_provers::provers::Cancelled;
+use lgn_provers::provers::YieldPoint;
+use metrics::counter;
+use tracing::warn;
+
+use crate::manager::ProversManager;
+
+static MAX_RETRIES: OnceLock<u32> = OnceLock::new();
+
+/// The fingerprints of the inputs which failed, the oldest first.
+static FAILED: Mutex<VecDeque<blake3::Hash>> = Mutex::new(VecDeque::new());
+
+/// How many failed inputs are remembered, a few hours of tasks on a busy worker.
+const MAX_FAILED: usize = 4096;
+
+pub(crate) fn init(max_retries: u32)
+{
+    let _ = MAX_RETRIES.set(max_retries);
+}
+
+/// Proves the task of `envelope`, retrying the transient failures, returning the outcome of the
+/// last attempt and the number of attempts.
+///
+/// A cancelled proof is neither retried nor remembered as failed.
+pub(crate) fn prove(
+    provers_manager: &ProversManager<TaskType, ReplyType>,
+    envelope: &MessageEnvelope<TaskType>,
+    yield_point: &dyn YieldPoint,
+) -> (
+    std::thread::Result<anyhow::Result<MessageReplyEnvelope<ReplyType>>>,
+    u32,
+)
+{
+    let max_retries = MAX_RETRIES
+        .get()
+        .copied()
+        .unwrap_or_default();
+    // Hashing the inputs is only worth it once the task failed.
+    let mut task_fingerprint = None;
+    let mut attempts = 0;
+    loop
+    {
+        attempts += 1;
+        // The yield point is only called between the stages, it is not left half updated.
+        let result = std::panic::catch_unwind(
+            std::panic::AssertUnwindSafe(
+                || {
+                    provers_manager.delegate_proving(
+                        envelope,
+                        yield_point,
+                    )
+                },
+            ),
+        );
+        let cancelled = matches!(
+            &result,
+            Ok(Err(err)) if err.chain().any(|cause| cause.is::<Cancelled>())
+        );
+        if cancelled
+            || matches!(
+                result,
+                Ok(Ok(_))
+            )
+        {
+            return (
+                result,
+                attempts,
+            );
+        }
+
+        // A task which can not be fingerprinted is retried without its failures being remembered.
+        let fingerprint = *task_fingerprint.get_or_insert_with(|| fingerprint(&envelope.inner));
+        let retry = match &result
+        {
+            Ok(Err(err)) =>
+            {
+                should_retry(
+                    err,
+                    attempts,
+                    max_retries,
+                    fingerprint.as_ref(),
+                )
+            },
+            _ => false,
+        };
+        if !retry
+        {
+            if let Some(fingerprint) = fingerprint
+            {
+                remember_failure(fingerprint);
+            }
+            return (
+                result,
+                attempts,
+            );
+        }
+
+        if let Ok(Err(err)) = &result
+        {
+            warn!(
+                "Retrying task {} after a transient failure: {err:?}",
+                envelope.id()
+            );
+        }
+        counter!("zkmr_worker_task_retries_total").increment(1);
+    }
+}
+
+/// The fingerprint of the inputs of a task, whatever its ids, if they serialize.
+fn fingerprint(task: &TaskType) -> Option<blake3::Hash>
+{
+    match lgn_messages::canonical::to_vec(task)
+    {
+        Ok(inputs) => Some(blake3::hash(&inputs)),
+        Err(err) =>
+        {
+            warn!("Failed to fingerprint the inputs of a task, its failures are not remembered: {err:?}");
+            None
+        },
+    }
+}
+
+/// Whether the task failing with `err` at its `attempts`th attempt is proven again, `fingerprint`
+/// being the one of its inputs if they could be fingerprinted.
+fn should_retry(
+    err: &anyhow::Error,
+    attempts: u32,
+    max_retries: u32,
+    fingerprint: Option<&blake3::Hash>,
+) -> bool
+{
+    attempts <= max_retries && is_transient(err) && !fingerprint.is_some_and(failed_before)
+}
+
+/// Whether `err` comes from the environment, e.g. an interrupted read, and may not happen again.
+///
+/// Anything else, including the panics and the params errors, is assumed to be deterministic.
+fn is_transient(err: &anyhow::Error) -> bool
+{
+    if err
+        .chain()
+        .any(|cause| cause.is::<ParamsError>())
+    {
+        return false;
+    }
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(
+            |err| {
+                matches!(
+                    err.kind(),
+                    ErrorKind::Interrupted
+                        | ErrorKind::TimedOut
+                        | ErrorKind::WouldBlock
+                        | ErrorKind::ConnectionReset
+                        | ErrorKind::ConnectionAborted
+                        | ErrorKind::OutOfMemory
+                )
+            },
+        )
+}
+
+fn failed_before(fingerprint: &blake3::Hash) -> bool
+{
+    FAILED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .contains(fingerprint)
+}
+
+fn remember_failure(fingerprint: blake3::Hash)
+{
+    let mut failed = FAILED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if failed.contains(&fingerprint)
+    {
+        return;
+    }
+    if failed.len() == MAX_FAILED
+    {
+        failed.pop_front();
+    }
+    failed.push_back(fingerprint);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use lgn_messages::routing::RoutingKey;
+    use lgn_messages::types::v1::groth16;
+    use lgn_messages::types::v1::query::keys::ProofKey;
+    use lgn_messages::types::ProverType;
+    use lgn_provers::provers::LgnProver;
+
+    use super::*;
+
+    const RETRIES: u32 = 2;
+
+    /// Fails every task with the error of `error`.
+    struct Failing
+    {
+        error: fn() -> anyhow::Error,
+    }
+
+    impl LgnProver<TaskType, ReplyType> for Failing
+    {
+        fn run(
+            &self,
+            _envelope: &MessageEnvelope<TaskType>,
+        ) -> anyhow::Result<MessageReplyEnvelope<ReplyType>>
+        {
+            Err((self.error)())
+        }
+    }
+
+    fn failing(error: fn() -> anyhow::Error) -> ProversManager<TaskType, ReplyType>
+    {
+        init(RETRIES);
+        let mut provers_manager = ProversManager::new();
+        provers_manager.add_prover(
+            ProverType::V1Groth16,
+            Box::new(
+                Failing {
+                    error,
+                },
+            ),
+        );
+        provers_manager
+    }
+
+    /// A task of its own for each test, the failed inputs being remembered across them.
+    fn envelope(query_id: &str) -> MessageEnvelope<TaskType>
+    {
+        MessageEnvelope::new(
+            query_id.to_string(),
+            "task".to_string(),
+            TaskType::V1Groth16(
+                groth16::WorkerTask::new(
+                    1,
+                    ProofKey::Revelation(query_id.to_string()),
+                ),
+            ),
+            RoutingKey::combined(
+                "domain".to_string(),
+                0,
+            ),
+        )
+    }
+
+    fn interrupted() -> anyhow::Error
+    {
+        anyhow::Error::new(std::io::Error::from(ErrorKind::Interrupted))
+            .context("reading the params")
+    }
+
+    #[test]
+    fn retries_the_transient_failures_once_per_inputs()
+    {
+        let provers_manager = failing(interrupted);
+        let envelope = envelope("transient");
+
+        let (result, attempts) = prove(
+            &provers_manager,
+            &envelope,
+            &(),
+        );
+        assert!(
+            matches!(
+                result,
+                Ok(Err(_))
+            )
+        );
+        assert_eq!(
+            attempts,
+            RETRIES + 1
+        );
+
+        // The inputs failed every attempt, they are not retried again.
+        let (_, attempts) = prove(
+            &provers_manager,
+            &envelope,
+            &(),
+        );
+        assert_eq!(
+            attempts,
+            1
+        );
+    }
+
+    #[test]
+    fn gives_up_on_the_failures_of_the_task()
+    {
+        let provers_manager = failing(|| anyhow::anyhow!("the proof does not verify"));
+        let (_, attempts) = prove(
+            &provers_manager,
+            &envelope("permanent"),
+            &(),
+        );
+        assert_eq!(
+            attempts,
+            1
+        );
+    }
+
+    #[test]
+    fn retries_the_inputs_which_can_not_be_fingerprinted()
+    {
+        let fingerprint = blake3::hash(b"remembered");
+        remember_failure(fingerprint);
+        assert!(
+            !should_retry(
+                &interrupted(),
+                1,
+                RETRIES,
+                Some(&fingerprint),
+            )
+        );
+
+        for attempts in 1..=RETRIES
+        {
+            assert!(
+                should_retry(
+                    &interrupted(),
+                    attempts,
+                    RETRIES,
+                    None,
+                )
+            );
+        }
+        assert!(
+            !should_retry(
+                &interrupted(),
+                RETRIES + 1,
+                RETRIES,
+                None,
+            )
+        );
+    }
+}
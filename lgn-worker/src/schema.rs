@@ -0,0 +1,323 @@
+//! Worker-side linting of query tasks against the declared table schemas.
+//!
+//! Malformed query inputs otherwise surface as circuit panics, checking them beforehand gives the
+//! gateway a descriptive error instead.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use lgn_messages::types::error_code::ErrorCode;
+use lgn_messages::types::v1::query::tasks::EmbeddedProofInputType;
+use lgn_messages::types::v1::query::tasks::ProofInputKind;
+use lgn_messages::types::v1::query::tasks::QueryStep;
+use lgn_messages::types::v1::query::tasks::RevelationInput;
+use lgn_messages::types::v1::query::PlaceHolderLgn;
+use lgn_messages::types::v1::query::WorkerTask;
+use lgn_messages::types::v1::query::WorkerTaskType;
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::MessageReplyEnvelope;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskType;
+use lgn_messages::TableId;
+use lgn_provers::provers::LgnProver;
+use lgn_provers::provers::YieldPoint;
+use metrics::counter;
+use serde_derive::Deserialize;
+use tracing::info;
+
+use crate::manager::BoxedProver;
+
+/// The declared schema of a table.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct TableSchema
+{
+    /// The identifiers of the table columns.
+    pub(crate) column_ids: Vec<u64>,
+
+    /// The maximum number of generic placeholders a query over this table may use.
+    #[serde(default)]
+    pub(crate) max_placeholders: Option<usize>,
+
+    /// The maximum number of items in a result row of a query over this table.
+    #[serde(default)]
+    pub(crate) max_result_columns: Option<usize>,
+}
+
+/// The schemas of the known tables, indexed by table id.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub(crate) struct SchemaRegistry
+{
+    tables: HashMap<TableId, TableSchema>,
+}
+
+impl SchemaRegistry
+{
+    /// Loads the JSON registry from a `http(s)://` URL or a local file.
+    pub(crate) fn load(source: &str) -> Result<Self>
+    {
+        let content = if source.starts_with("http://") || source.starts_with("https://")
+        {
+            reqwest::blocking::get(source)
+                .and_then(|response| response.error_for_status())
+                .and_then(|response| response.text())
+                .with_context(|| format!("failed to fetch the table schemas from `{source}`"))?
+        }
+        else
+        {
+            std::fs::read_to_string(source)
+                .with_context(|| format!("failed to read the table schemas from `{source}`"))?
+        };
+
+        let registry: Self = serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse the table schemas from `{source}`"))?;
+        info!(
+            "Loaded the schemas of {} tables",
+            registry
+                .tables
+                .len()
+        );
+
+        Ok(registry)
+    }
+
+    /// Checks a query task against the schema of its table.
+    ///
+    /// Tasks without a table id, or for a table without a declared schema, are accepted as is.
+    pub(crate) fn lint(
+        &self,
+        task: &WorkerTask,
+    ) -> Result<()>
+    {
+        let Some(table_id) = task.table_id
+        else
+        {
+            return Ok(());
+        };
+        let Some(schema) = self
+            .tables
+            .get(&table_id)
+        else
+        {
+            return Ok(());
+        };
+
+        let WorkerTaskType::Query(input) = &task.task_type;
+        match &input.query_step
+        {
+            QueryStep::Prepare(parts) =>
+            {
+                for part in parts
+                {
+                    if let Some(EmbeddedProofInputType::RowsTree(embedded)) =
+                        &part.embedded_proof_input
+                    {
+                        lint_placeholders(
+                            schema,
+                            &embedded.placeholders,
+                        )?;
+                    }
+                    if let Some(ProofInputKind::NonExistence(non_existence)) =
+                        &part.aggregation_input_kind
+                    {
+                        lint_placeholders(
+                            schema,
+                            &non_existence.placeholders,
+                        )?;
+                        lint_column_ids(
+                            table_id,
+                            schema,
+                            &non_existence.column_ids,
+                        )?;
+                    }
+                }
+            },
+            QueryStep::Revelation(RevelationInput::Aggregated {
+                placeholders,
+                ..
+            }) =>
+            {
+                lint_placeholders(
+                    schema,
+                    placeholders,
+                )?;
+            },
+            QueryStep::Revelation(RevelationInput::Tabular {
+                placeholders,
+                matching_rows,
+                ..
+            }) =>
+            {
+                lint_placeholders(
+                    schema,
+                    placeholders,
+                )?;
+
+                let mut widths = matching_rows
+                    .iter()
+                    .map(
+                        |row| {
+                            row.result
+                                .len()
+                        },
+                    )
+                    .collect::<HashSet<_>>();
+                ensure!(
+                    widths.len() <= 1,
+                    "matching rows have results of different sizes: {widths:?}"
+                );
+                if let (Some(width), Some(max)) = (
+                    widths
+                        .drain()
+                        .next(),
+                    schema.max_result_columns,
+                )
+                {
+                    ensure!(
+                        width <= max,
+                        "result rows have {width} items, but table {table_id} allows at most {max}"
+                    );
+                }
+            },
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that the placeholders are well-formed and within the schema limits.
+fn lint_placeholders(
+    schema: &TableSchema,
+    placeholders: &PlaceHolderLgn,
+) -> Result<()>
+{
+    let mut has_min_block = false;
+    let mut has_max_block = false;
+    let mut generic = 0;
+    for identifier in placeholders.identifiers()
+    {
+        match identifier
+        {
+            "0" => has_min_block = true,
+            "1" => has_max_block = true,
+            _ =>
+            {
+                if identifier
+                    .parse::<usize>()
+                    .is_err()
+                {
+                    bail!("invalid placeholder identifier `{identifier}`");
+                }
+                generic += 1;
+            },
+        }
+    }
+
+    ensure!(
+        has_min_block && has_max_block,
+        "the placeholders must define the query bounds `0` and `1`"
+    );
+    if let Some(max) = schema.max_placeholders
+    {
+        ensure!(
+            generic <= max,
+            "the query uses {generic} placeholders, at most {max} are allowed"
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that every column identifier is declared in the schema.
+fn lint_column_ids(
+    table_id: TableId,
+    schema: &TableSchema,
+    column_ids: &[u64],
+) -> Result<()>
+{
+    for column_id in column_ids
+    {
+        ensure!(
+            schema
+                .column_ids
+                .contains(column_id),
+            "column {column_id} is not declared in the schema of table {table_id}"
+        );
+    }
+
+    Ok(())
+}
+
+/// Lints the query tasks before handing them over to the wrapped prover.
+pub(crate) struct LintedQueryProver
+{
+    registry: SchemaRegistry,
+    prover: BoxedProver<TaskType, ReplyType>,
+}
+
+impl LintedQueryProver
+{
+    pub(crate) fn new(
+        registry: SchemaRegistry,
+        prover: BoxedProver<TaskType, ReplyType>,
+    ) -> Self
+    {
+        Self {
+            registry,
+            prover,
+        }
+    }
+
+    fn check(
+        &self,
+        envelope: &MessageEnvelope<TaskType>,
+    ) -> Result<()>
+    {
+        if let TaskType::V1Query(task) = &envelope.inner
+        {
+            if let Err(err) = self
+                .registry
+                .lint(task)
+            {
+                counter!(
+                    "zkmr_worker_error_count",
+                    "error_type" => ErrorCode::QuerySchema.label(),
+                    "error_code" => ErrorCode::QuerySchema.to_string(),
+                )
+                .increment(1);
+                return Err(err.context("query task does not match the table schema"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LgnProver<TaskType, ReplyType> for LintedQueryProver
+{
+    fn run(
+        &self,
+        envelope: &MessageEnvelope<TaskType>,
+    ) -> Result<MessageReplyEnvelope<ReplyType>>
+    {
+        self.check(envelope)?;
+        self.prover
+            .run(envelope)
+    }
+
+    fn run_cooperatively(
+        &self,
+        envelope: &MessageEnvelope<TaskType>,
+        yield_point: &dyn YieldPoint,
+    ) -> Result<MessageReplyEnvelope<ReplyType>>
+    {
+        self.check(envelope)?;
+        self.prover
+            .run_cooperatively(
+                envelope,
+                yield_point,
+            )
+    }
+}
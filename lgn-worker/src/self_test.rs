@@ -0,0 +1,386 @@
+//! Smoke test of a node running one proof per prover category before registering with a gateway.
+//!
+//! The proofs are not verified against the circuit data: the test tells that the params load and
+//! the provers produce well-formed proofs, see [`check_reply`], not that the proofs are valid.
+//!
+//! Only the preprocessing tasks can be synthesized, the query and groth16 ones proving upstream
+//! proofs: a worker registering their provers is self-tested with recorded tasks, see
+//! [`load_fixtures`], and refuses to pass without them.
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use ethers::types::Address;
+use ethers::types::Bloom;
+use ethers::types::H256;
+use ethers::types::H64;
+use ethers::types::U256;
+use ethers::utils::rlp::RlpStream;
+use lgn_messages::routing::RoutingKey;
+use lgn_messages::types::v1;
+use lgn_messages::types::v1::preprocessing::WorkerTaskType;
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskDifficulty;
+use lgn_messages::types::TaskType;
+use tracing::info;
+
+use crate::config::Config;
+use crate::manager::ProversManager;
+use crate::reply_size;
+
+const SELF_TEST_QUERY_ID: &str = "self-test";
+const SELF_TEST_CHAIN_ID: u64 = 1;
+const SELF_TEST_BLOCK_NR: u64 = 1;
+
+/// The outcome of a single self-test proof.
+struct SelfTestResult
+{
+    category: &'static str,
+    name: String,
+    elapsed: Duration,
+    outcome: Result<()>,
+}
+
+/// Runs the synthetic tasks matching the worker class, plus the recorded envelopes found in
+/// `fixtures`, and prints a pass/fail matrix.
+///
+/// Fails if any of the proofs failed.
+pub(crate) fn run_self_test(
+    config: &Config,
+    provers_manager: &ProversManager<TaskType, ReplyType>,
+    fixtures: Option<&Path>,
+) -> Result<()>
+{
+    let mut tasks = synthetic_tasks(
+        config
+            .worker
+            .instance_type,
+    );
+    if let Some(fixtures) = fixtures
+    {
+        tasks.extend(load_fixtures(fixtures)?);
+    }
+    ensure!(
+        !tasks.is_empty(),
+        "no self-test task for worker class `{}`",
+        config
+            .worker
+            .instance_type
+    );
+    let uncovered = provers_manager
+        .prover_types()
+        .into_iter()
+        .filter(
+            |prover_type| {
+                !tasks
+                    .iter()
+                    .any(
+                        |(_, _, envelope)| {
+                            reply_size::prover_type(&envelope.inner) == Some(*prover_type)
+                        },
+                    )
+            },
+        )
+        .map(|prover_type| prover_type.to_string())
+        .collect::<Vec<_>>();
+    ensure!(
+        uncovered.is_empty(),
+        "no self-test task for the {} prover(s), pass recorded tasks with `--fixtures`",
+        uncovered.join(", ")
+    );
+
+    let results = tasks
+        .into_iter()
+        .map(
+            |(category, name, envelope)| {
+                info!("Self-testing {category}/{name}");
+                let start = Instant::now();
+                let outcome = std::panic::catch_unwind(
+                    || {
+                        provers_manager
+                            .delegate_proving(
+                                &envelope,
+                                &(),
+                            )
+                            .and_then(
+                                |reply| {
+                                    check_reply(
+                                        &envelope.inner,
+                                        reply,
+                                    )
+                                },
+                            )
+                    },
+                )
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("prover panicked")));
+
+                SelfTestResult {
+                    category,
+                    name,
+                    elapsed: start.elapsed(),
+                    outcome,
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+
+    print_matrix(&results);
+
+    let failures = results
+        .iter()
+        .filter(
+            |r| {
+                r.outcome
+                    .is_err()
+            },
+        )
+        .count();
+    if failures > 0
+    {
+        bail!(
+            "{failures} out of {} self-test proofs failed",
+            results.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks that a reply carries a well-formed proof, without verifying it: a preprocessing proof
+/// deserializes with its verifying key, a groth16 proof encodes as calldata, and a query proof is
+/// not empty.
+fn check_reply(
+    task: &TaskType,
+    reply: lgn_messages::types::MessageReplyEnvelope<ReplyType>,
+) -> Result<()>
+{
+    let content = reply
+        .inner()
+        .map_err(|e| anyhow::anyhow!("worker error: {e}"))?;
+    let Some(proof) = content
+        .proof()
+        .filter(|proof| !proof.is_empty())
+    else
+    {
+        bail!("the reply does not contain any proof");
+    };
+    match task
+    {
+        #[cfg(feature = "prover-preprocessing")]
+        TaskType::V1Preprocessing(_) =>
+        {
+            lgn_provers::provers::v1::preprocessing::check_proof(proof)
+                .context("the preprocessing proof does not deserialize")
+        },
+        #[cfg(feature = "prover-groth16")]
+        TaskType::V1Groth16(_) =>
+        {
+            lgn_provers::provers::v1::groth16::check_proof(proof)
+                .context("the groth16 proof does not encode as calldata")
+        },
+        _ => Ok(()),
+    }
+}
+
+fn print_matrix(results: &[SelfTestResult])
+{
+    println!(
+        "{:<16} {:<32} {:<6} {:>10}",
+        "CATEGORY", "TASK", "STATUS", "TIME (s)"
+    );
+    for result in results
+    {
+        println!(
+            "{:<16} {:<32} {:<6} {:>10.3}",
+            result.category,
+            result.name,
+            if result
+                .outcome
+                .is_ok()
+            {
+                "PASS"
+            }
+            else
+            {
+                "FAIL"
+            },
+            result
+                .elapsed
+                .as_secs_f64(),
+        );
+        if let Err(err) = &result.outcome
+        {
+            println!("    {err:#}");
+        }
+    }
+}
+
+/// Builds the tasks which can be proven without any upstream proof.
+pub(crate) fn synthetic_tasks(
+    class: TaskDifficulty
+) -> Vec<(
+    &'static str,
+    String,
+    MessageEnvelope<TaskType>,
+)>
+{
+    let mut tasks = vec![];
+
+    if cfg!(feature = "prover-preprocessing") && class >= TaskDifficulty::Medium
+    {
+        tasks.push(
+            (
+                "preprocessing",
+                "cell leaf".to_string(),
+                preprocessing_envelope(
+                    "cell-leaf",
+                    WorkerTaskType::db_cell_leaf(
+                        0,
+                        "self-test".to_string(),
+                        0,
+                        1,
+                        alloy_primitives::U256::from(42),
+                        false,
+                    ),
+                ),
+            ),
+        );
+        tasks.push(
+            (
+                "preprocessing",
+                "block extraction".to_string(),
+                preprocessing_envelope(
+                    "block-extraction",
+                    WorkerTaskType::ext_block(synthetic_block_header(SELF_TEST_BLOCK_NR)),
+                ),
+            ),
+        );
+    }
+
+    tasks
+}
+
+/// Loads the recorded task envelopes, e.g. tabular queries or groth16 tasks, from a directory.
+pub(crate) fn load_fixtures(
+    dir: &Path
+) -> Result<
+    Vec<(
+        &'static str,
+        String,
+        MessageEnvelope<TaskType>,
+    )>,
+>
+{
+    let mut paths = std::fs::read_dir(dir)
+        .with_context(
+            || {
+                format!(
+                    "failed to list fixtures in `{}`",
+                    dir.display()
+                )
+            },
+        )?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter(|path| path.extension() == Some("json".as_ref()))
+        .map(
+            |path| {
+                let content = std::fs::read_to_string(&path).with_context(
+                    || {
+                        format!(
+                            "failed to read `{}`",
+                            path.display()
+                        )
+                    },
+                )?;
+                let envelope = serde_json::from_str::<MessageEnvelope<TaskType>>(&content)
+                    .with_context(
+                        || {
+                            format!(
+                                "failed to parse `{}`",
+                                path.display()
+                            )
+                        },
+                    )?;
+                let category = match envelope.inner
+                {
+                    TaskType::V1Preprocessing(_) => "preprocessing",
+                    TaskType::V1Query(_) => "query",
+                    TaskType::V1Groth16(_) => "groth16",
+                    TaskType::TxTrie(_) | TaskType::RecProof(_) => "experimental",
+                };
+                let name = path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy())
+                    .unwrap_or_default()
+                    .to_string();
+
+                Ok(
+                    (
+                        category,
+                        name,
+                        envelope,
+                    ),
+                )
+            },
+        )
+        .collect()
+}
+
+fn preprocessing_envelope(
+    task_id: &str,
+    task_type: WorkerTaskType,
+) -> MessageEnvelope<TaskType>
+{
+    MessageEnvelope::new(
+        SELF_TEST_QUERY_ID.to_string(),
+        task_id.to_string(),
+        TaskType::V1Preprocessing(
+            v1::preprocessing::WorkerTask::new(
+                SELF_TEST_CHAIN_ID,
+                SELF_TEST_BLOCK_NR,
+                task_type,
+            ),
+        ),
+        RoutingKey::combined(
+            v1::preprocessing::ROUTING_DOMAIN.to_string(),
+            0,
+        ),
+    )
+}
+
+/// RLP-encodes a pre-London block header with the given number and zeroed fields.
+fn synthetic_block_header(block_nr: u64) -> Vec<u8>
+{
+    let mut stream = RlpStream::new_list(15);
+    stream.append(&H256::zero()); // parent hash
+    stream.append(&H256::zero()); // ommers hash
+    stream.append(&Address::zero()); // beneficiary
+    stream.append(&H256::zero()); // state root
+    stream.append(&H256::zero()); // transactions root
+    stream.append(&H256::zero()); // receipts root
+    stream.append(&Bloom::zero()); // logs bloom
+    stream.append(&U256::zero()); // difficulty
+    stream.append(&block_nr);
+    stream.append(&30_000_000u64); // gas limit
+    stream.append(&0u64); // gas used
+    stream.append(&0u64); // timestamp
+    stream.append_empty_data(); // extra data
+    stream.append(&H256::zero()); // mix hash
+    stream.append(&H64::zero()); // nonce
+
+    stream
+        .out()
+        .to_vec()
+}
@@ -0,0 +1,194 @@
+//! The identity of the worker across its reconnections and restarts.
+//!
+//! A worker is identified by a session id, generated on its first start and kept in
+//! `worker.session_file`, which it sends with the metadata of the task stream, next to
+//! `WorkerReady`. A gateway can also hand the worker a resumption token in the metadata of its
+//! answer; the worker keeps the latest one of each gateway in the session file and sends it back
+//! when it opens the stream again, for the gateway to reconcile the tasks it assigned before the
+//! stream was interrupted, rather than treating the worker as a new one.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use tonic::metadata::MetadataMap;
+use tonic::metadata::MetadataValue;
+use tracing::info;
+use tracing::warn;
+
+/// The metadata of the task stream carrying the session id.
+const SESSION_ID_KEY: &str = "x-worker-session-id";
+
+/// The metadata of the task stream carrying the resumption token, in both directions.
+const RESUMPTION_TOKEN_KEY: &str = "x-resumption-token";
+
+static SESSION: OnceLock<Session> = OnceLock::new();
+
+struct Session
+{
+    file: PathBuf,
+    state: Mutex<State>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+struct State
+{
+    id: String,
+    /// The latest resumption token of each gateway, by label.
+    #[serde(default)]
+    resumption_tokens: BTreeMap<String, String>,
+}
+
+/// Reads the session of the worker from `session_file`, starting a new one if missing.
+pub(crate) fn init(session_file: &str) -> Result<()>
+{
+    let file = PathBuf::from(session_file);
+    let mut state = read(&file);
+    if state
+        .id
+        .is_empty()
+    {
+        state.id = hex::encode(rand::random::<[u8; 16]>());
+        info!(
+            "Starting the session `{}` of the worker",
+            state.id
+        );
+        write(
+            &file,
+            &state,
+        )?;
+    }
+    else
+    {
+        info!(
+            "Resuming the session `{}` of the worker",
+            state.id
+        );
+    }
+    let _ = SESSION.set(
+        Session {
+            file,
+            state: Mutex::new(state),
+        },
+    );
+    Ok(())
+}
+
+/// Adds the session id and the resumption token of `gateway` to the metadata opening its task
+/// stream.
+pub(crate) fn annotate(
+    gateway: &str,
+    metadata: &mut MetadataMap,
+)
+{
+    let Some(session) = SESSION.get()
+    else
+    {
+        return;
+    };
+    let state = session
+        .state
+        .lock()
+        .expect("session lock poisoned");
+    for (key, value) in [
+        (
+            SESSION_ID_KEY,
+            Some(&state.id),
+        ),
+        (
+            RESUMPTION_TOKEN_KEY,
+            state
+                .resumption_tokens
+                .get(gateway),
+        ),
+    ]
+    {
+        if let Some(value) = value.and_then(|value| MetadataValue::try_from(value.as_str()).ok())
+        {
+            metadata.insert(
+                key,
+                value,
+            );
+        }
+    }
+}
+
+/// Keeps the resumption token `gateway` answered the opening of its task stream with, if any.
+pub(crate) fn record(
+    gateway: &str,
+    metadata: &MetadataMap,
+)
+{
+    let Some(session) = SESSION.get()
+    else
+    {
+        return;
+    };
+    let Some(token) = metadata
+        .get(RESUMPTION_TOKEN_KEY)
+        .and_then(
+            |token| {
+                token
+                    .to_str()
+                    .ok()
+            },
+        )
+    else
+    {
+        return;
+    };
+    let mut state = session
+        .state
+        .lock()
+        .expect("session lock poisoned");
+    let previous = state
+        .resumption_tokens
+        .insert(
+            gateway.to_string(),
+            token.to_string(),
+        );
+    if previous.as_deref() == Some(token)
+    {
+        return;
+    }
+    if let Err(err) = write(
+        &session.file,
+        &state,
+    )
+    {
+        warn!("Failed to keep the resumption token of gateway `{gateway}`: {err:?}");
+    }
+}
+
+fn read(session_file: &Path) -> State
+{
+    std::fs::read(session_file)
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write(
+    session_file: &Path,
+    state: &State,
+) -> Result<()>
+{
+    std::fs::write(
+        session_file,
+        serde_json::to_vec(state)?,
+    )
+    .with_context(
+        || {
+            format!(
+                "failed to save the session to `{}`",
+                session_file.display()
+            )
+        },
+    )
+}
@@ -0,0 +1,256 @@
+//! The A/B validation of a second params set, e.g. regenerated or re-uploaded params, against the
+//! one the worker proves with.
+//!
+//! With `[shadow_params]` set, a thread of its own loads provers from the second params set, then
+//! proves each task the worker proved again with them and compares both outcomes: whether both
+//! proved the task and, for the plonky2 proofs which are deterministic, whether the proofs are the
+//! same bytes, public inputs included. The groth16 proofs are randomized, only their outcome is
+//! compared. The discrepancies are logged and counted, the replies to the gateway are left as is.
+//! The tasks are not compared when the queue is full.
+//!
+//! The second provers take as much memory as the first, and their proofs compete with the tasks
+//! for the CPU.
+
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::TrySendError;
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use anyhow::Result;
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::MessageReplyEnvelope;
+use lgn_messages::types::ProverType;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskType;
+use metrics::counter;
+use tracing::error;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::manager::ProversManager;
+use crate::reply_size;
+
+static SHADOW: OnceLock<SyncSender<Comparison>> = OnceLock::new();
+
+/// A task proven with the params of the worker, to prove again with the second set.
+struct Comparison
+{
+    envelope: MessageEnvelope<TaskType>,
+    primary: Outcome,
+}
+
+/// What proving a task resulted in, as compared between the params sets.
+#[derive(Debug, PartialEq)]
+enum Outcome
+{
+    /// The task was proven, with the digest of the proof if it is deterministic.
+    Proven(Option<blake3::Hash>),
+    Failed,
+}
+
+impl Outcome
+{
+    fn of(
+        prover_type: Option<ProverType>,
+        result: &std::thread::Result<anyhow::Result<MessageReplyEnvelope<ReplyType>>>,
+    ) -> Self
+    {
+        match result
+        {
+            Ok(Ok(reply)) =>
+            {
+                let deterministic = !matches!(
+                    prover_type,
+                    Some(ProverType::V1Groth16)
+                );
+                Outcome::Proven(
+                    reply
+                        .content()
+                        .proof()
+                        .filter(|_| deterministic)
+                        .map(blake3::hash),
+                )
+            },
+            Ok(Err(_)) | Err(_) => Outcome::Failed,
+        }
+    }
+}
+
+/// Starts loading the second params set, if configured.
+pub(crate) fn init(config: &Config) -> Result<()>
+{
+    let Some(shadow) = &config.shadow_params
+    else
+    {
+        return Ok(());
+    };
+    if let Some(parent) = Path::new(&shadow.checksum_expected_local_path).parent()
+    {
+        std::fs::create_dir_all(parent).with_context(
+            || {
+                format!(
+                    "failed to create the directory of the shadow checksums `{}`",
+                    parent.display()
+                )
+            },
+        )?;
+    }
+    let (sender, receiver) = mpsc::sync_channel::<Comparison>(shadow.queue_size);
+    if SHADOW
+        .set(sender)
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    let mut shadow_config = config.clone();
+    let params = &mut shadow_config.public_params;
+    params.dir = shadow
+        .dir
+        .clone();
+    if let Some(url) = &shadow.url
+    {
+        params.url = url.clone();
+    }
+    if let Some(checksum_url) = &shadow.checksum_url
+    {
+        params.checksum_url = checksum_url.clone();
+    }
+    params.checksum_expected_local_path = shadow
+        .checksum_expected_local_path
+        .clone();
+    params.mirror_url = None;
+
+    std::thread::Builder::new()
+        .name("shadow-params".to_string())
+        .spawn(
+            move || {
+                info!(
+                    "Loading the shadow params from `{}`",
+                    shadow_config
+                        .public_params
+                        .dir
+                );
+                let provers_manager = match crate::load_provers(&shadow_config)
+                {
+                    Ok(provers_manager) => provers_manager,
+                    Err(err) =>
+                    {
+                        error!("Failed to load the shadow params, no task is compared: {err:?}");
+                        return;
+                    },
+                };
+                info!("Comparing the proofs with the shadow params");
+                for comparison in receiver
+                {
+                    compare(
+                        &provers_manager,
+                        comparison,
+                    );
+                }
+            },
+        )
+        .context("failed to spawn the shadow params thread")?;
+
+    Ok(())
+}
+
+/// Queues the comparison of the `result` of proving `envelope`, if enabled.
+pub(crate) fn submit(
+    envelope: &MessageEnvelope<TaskType>,
+    result: &std::thread::Result<anyhow::Result<MessageReplyEnvelope<ReplyType>>>,
+)
+{
+    let Some(sender) = SHADOW.get()
+    else
+    {
+        return;
+    };
+    let comparison = Comparison {
+        envelope: envelope.clone(),
+        primary: Outcome::of(
+            reply_size::prover_type(&envelope.inner),
+            result,
+        ),
+    };
+    let reason = match sender.try_send(comparison)
+    {
+        Ok(()) => return,
+        Err(TrySendError::Full(_)) => "queue_full",
+        // The shadow params failed to load.
+        Err(TrySendError::Disconnected(_)) => "unavailable",
+    };
+    counter!(
+        "zkmr_worker_shadow_skipped_total",
+        "reason" => reason,
+    )
+    .increment(1);
+}
+
+/// Proves the task of `comparison` with the second params set and reports a discrepancy.
+fn compare(
+    provers_manager: &ProversManager<TaskType, ReplyType>,
+    comparison: Comparison,
+)
+{
+    let envelope = &comparison.envelope;
+    let prover_type = reply_size::prover_type(&envelope.inner);
+    let result = std::panic::catch_unwind(
+        std::panic::AssertUnwindSafe(
+            || {
+                provers_manager.delegate_proving(
+                    envelope,
+                    &(),
+                )
+            },
+        ),
+    );
+    let shadow = Outcome::of(
+        prover_type,
+        &result,
+    );
+    let prover_type = prover_type.map_or_else(
+        || "unknown".to_string(),
+        |prover_type| prover_type.to_string(),
+    );
+    counter!(
+        "zkmr_worker_shadow_compared_total",
+        "prover_type" => prover_type.clone(),
+    )
+    .increment(1);
+
+    let kind = match (
+        &comparison.primary,
+        &shadow,
+    )
+    {
+        (primary, shadow) if primary == shadow => return,
+        (Outcome::Proven(_), Outcome::Proven(_)) => "proof",
+        _ => "outcome",
+    };
+    counter!(
+        "zkmr_worker_shadow_discrepancies_total",
+        "prover_type" => prover_type.clone(),
+        "kind" => kind,
+    )
+    .increment(1);
+    let shadow_error = match &result
+    {
+        Ok(Ok(_)) => None,
+        Ok(Err(err)) => Some(format!("{err:?}")),
+        Err(_) => Some("the prover panicked".to_string()),
+    };
+    warn!(
+        task_id = envelope.task_id,
+        query_id = envelope.query_id,
+        prover_type,
+        kind,
+        primary = ?comparison.primary,
+        shadow = ?shadow,
+        shadow_error,
+        "The shadow params disagree with the params of the worker"
+    );
+}
@@ -0,0 +1,500 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::*;
+use checksum::fetch_checksum_file;
+use checksum::verify_directory_checksums;
+use clap::Parser;
+use lgn_messages::types::MessageEnvelope;
+use lgn_messages::types::ReplyType;
+use lgn_messages::types::TaskType;
+use lgn_provers::params::peak_rss_bytes;
+use manager::v1::register_v1_provers;
+use manager::ProversManager;
+use serde_derive::Deserialize;
+use tracing::info;
+use tracing::level_filters::LevelFilter;
+use tracing::warn;
+use tracing_subscriber::EnvFilter;
+
+mod checksum;
+mod config;
+mod manager;
+#[cfg(feature = "prover-query")]
+mod schema;
+
+#[derive(Parser, Clone, Debug)]
+/// Replays a traffic profile against an embedded worker, failing if the latencies or the memory
+/// usage exceed the limits of the profile.
+///
+/// The provers are the ones compiled in, a build with the dummy provers soaks the manager alone.
+struct Cli
+{
+    #[clap(
+        short,
+        long
+    )]
+    /// The config file; `$(toml-worker-lgn)` can be used if devenv is enabled.
+    config: String,
+
+    #[clap(
+        short,
+        long
+    )]
+    /// The JSON traffic profile.
+    profile: String,
+}
+
+/// The shape of the traffic to replay.
+#[derive(Deserialize, Debug)]
+struct Profile
+{
+    /// Length of the replayed traffic, in profile time.
+    duration_secs: u64,
+    /// How many seconds of profile time elapse per second, e.g. 24 to replay a day in an hour.
+    #[serde(default = "default_time_scale")]
+    time_scale: f64,
+    /// Multipliers of the arrival rates for each hour of the day, in profile time, flat if empty.
+    #[serde(default)]
+    hourly_weights: Vec<f64>,
+    tasks: Vec<TaskProfile>,
+    limits: Limits,
+}
+
+fn default_time_scale() -> f64
+{
+    1.0
+}
+
+/// A kind of task of the mix.
+#[derive(Deserialize, Debug)]
+struct TaskProfile
+{
+    name: String,
+    /// The task envelope replayed, each arrival getting its own task id.
+    envelope: String,
+    /// Mean arrivals per second of profile time.
+    rate_per_sec: f64,
+}
+
+/// The release gate.
+#[derive(Deserialize, Debug, Clone)]
+struct Limits
+{
+    p50_ms: Option<u64>,
+    p99_ms: Option<u64>,
+    max_rss_mb: Option<u64>,
+    /// The tolerated ratio of failed tasks.
+    #[serde(default)]
+    max_failure_ratio: f64,
+}
+
+struct Arrival
+{
+    name: String,
+    envelope: MessageEnvelope<TaskType>,
+    at: Instant,
+}
+
+#[derive(Default)]
+struct Stats
+{
+    latencies: Vec<Duration>,
+    failures: usize,
+}
+
+fn main() -> Result<()>
+{
+    let subscriber = tracing_subscriber::fmt()
+        .pretty()
+        .compact()
+        .with_level(true)
+        .with_file(false)
+        .with_line_number(false)
+        .without_time()
+        .with_target(false)
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).expect("Setting up logging failed");
+
+    let cli = Cli::parse();
+
+    let profile = serde_json::from_str::<Profile>(
+        &std::fs::read_to_string(&cli.profile).with_context(
+            || {
+                format!(
+                    "failed to read `{}`",
+                    cli.profile
+                )
+            },
+        )?,
+    )
+    .context("failed to parse the traffic profile")?;
+    ensure!(
+        profile.time_scale > 0.0,
+        "the time scale must be positive"
+    );
+    ensure!(
+        profile
+            .hourly_weights
+            .is_empty()
+            || profile
+                .hourly_weights
+                .len()
+                == 24,
+        "the hourly weights must cover the 24 hours of a day"
+    );
+    let templates = profile
+        .tasks
+        .iter()
+        .map(
+            |task| {
+                let content = std::fs::read_to_string(&task.envelope).with_context(
+                    || {
+                        format!(
+                            "failed to read `{}`",
+                            task.envelope
+                        )
+                    },
+                )?;
+                serde_json::from_str::<MessageEnvelope<TaskType>>(&content).with_context(
+                    || {
+                        format!(
+                            "failed to parse `{}`",
+                            task.envelope
+                        )
+                    },
+                )
+            },
+        )
+        .collect::<Result<Vec<_>>>()?;
+
+    let config = config::Config::load(Some(cli.config));
+    config.validate();
+
+    let expected_checksums_file = &config
+        .public_params
+        .checksum_expected_local_path;
+    fetch_checksum_file(
+        &config
+            .public_params
+            .checksum_url,
+        expected_checksums_file,
+    )?;
+    let mut provers_manager = ProversManager::<TaskType, ReplyType>::new();
+    register_v1_provers(
+        &config,
+        &mut provers_manager,
+    )
+    .context("while registering provers")?;
+    if !config
+        .public_params
+        .skip_checksum
+    {
+        verify_directory_checksums(
+            &config
+                .public_params
+                .dir,
+            expected_checksums_file,
+        )
+        .context("Failed to verify checksums")?;
+    }
+
+    // The arrivals are generated on their own thread, so that a slow proof delays the next ones
+    // like on a real worker, the queueing time counting in their latency.
+    let limits = profile
+        .limits
+        .clone();
+    let (arrivals, queue) = mpsc::channel();
+    let generator = std::thread::spawn(
+        move || {
+            generate(
+                &profile,
+                &templates,
+                arrivals,
+            )
+        },
+    );
+
+    let mut stats = BTreeMap::<String, Stats>::new();
+    for arrival in queue
+    {
+        let result = std::panic::catch_unwind(
+            || {
+                provers_manager.delegate_proving(
+                    &arrival.envelope,
+                    &(),
+                )
+            },
+        );
+        let task_stats = stats
+            .entry(arrival.name)
+            .or_default();
+        match result
+        {
+            Result::Ok(Result::Ok(_)) =>
+            {
+                task_stats
+                    .latencies
+                    .push(
+                        arrival
+                            .at
+                            .elapsed(),
+                    )
+            },
+            Result::Ok(Err(err)) =>
+            {
+                warn!(
+                    "Task {} failed: {err:?}",
+                    arrival
+                        .envelope
+                        .id()
+                );
+                task_stats.failures += 1;
+            },
+            Err(_) =>
+            {
+                warn!(
+                    "Task {} panicked",
+                    arrival
+                        .envelope
+                        .id()
+                );
+                task_stats.failures += 1;
+            },
+        }
+    }
+    generator
+        .join()
+        .map_err(|_| anyhow!("the task generator panicked"))?;
+
+    report(
+        &stats,
+        &limits,
+    )
+}
+
+/// Sends the arrivals of the profile at their time.
+fn generate(
+    profile: &Profile,
+    templates: &[MessageEnvelope<TaskType>],
+    arrivals: mpsc::Sender<Arrival>,
+)
+{
+    let start = Instant::now();
+    let mut count = 0u64;
+    // Profile time, in seconds.
+    let mut now = 0.0;
+    let total_rate: f64 = profile
+        .tasks
+        .iter()
+        .map(|task| task.rate_per_sec)
+        .sum();
+    while total_rate > 0.0
+    {
+        // Poisson arrivals, the hourly weight being applied to the whole mix.
+        let hour = (now / 3600.0) as usize % 24;
+        let weight = profile
+            .hourly_weights
+            .get(hour)
+            .copied()
+            .unwrap_or(1.0)
+            .max(f64::EPSILON);
+        now += -(1.0 - rand::random::<f64>()).ln() / (total_rate * weight);
+        if now >= profile.duration_secs as f64
+        {
+            break;
+        }
+
+        let mut pick = rand::random::<f64>() * total_rate;
+        let index = profile
+            .tasks
+            .iter()
+            .position(
+                |task| {
+                    pick -= task.rate_per_sec;
+                    pick < 0.0
+                },
+            )
+            .unwrap_or(
+                profile
+                    .tasks
+                    .len()
+                    - 1,
+            );
+
+        let due = start + Duration::from_secs_f64(now / profile.time_scale);
+        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+
+        count += 1;
+        let mut envelope = templates[index].clone();
+        envelope.task_id = format!(
+            "{}-{count}",
+            envelope.task_id
+        );
+        if arrivals
+            .send(
+                Arrival {
+                    name: profile.tasks[index]
+                        .name
+                        .clone(),
+                    envelope,
+                    at: Instant::now(),
+                },
+            )
+            .is_err()
+        {
+            break;
+        }
+        if count % 1000 == 0
+        {
+            info!("{count} tasks generated, at {now:.0}s of the profile");
+        }
+    }
+}
+
+/// Prints the latency percentiles of each kind of task, failing if a limit is exceeded.
+fn report(
+    stats: &BTreeMap<String, Stats>,
+    limits: &Limits,
+) -> Result<()>
+{
+    println!(
+        "{:<24} {:>8} {:>8} {:>10} {:>10} {:>10}",
+        "TASK", "DONE", "FAILED", "P50 (ms)", "P90 (ms)", "P99 (ms)"
+    );
+    let mut all = Stats::default();
+    for (name, task_stats) in stats
+    {
+        print_row(
+            name,
+            task_stats,
+        );
+        all.latencies
+            .extend(&task_stats.latencies);
+        all.failures += task_stats.failures;
+    }
+    print_row(
+        "all",
+        &all,
+    );
+    let peak_rss_mb = peak_rss_bytes().map(|bytes| bytes / (1024 * 1024));
+    println!(
+        "peak RSS: {}",
+        peak_rss_mb.map_or(
+            "n/a".to_string(),
+            |mb| format!("{mb} MB")
+        )
+    );
+
+    let mut violations = vec![];
+    let total = all
+        .latencies
+        .len()
+        + all.failures;
+    if total > 0 && all.failures as f64 / total as f64 > limits.max_failure_ratio
+    {
+        violations.push(
+            format!(
+                "{} of {total} tasks failed",
+                all.failures
+            ),
+        );
+    }
+    for (label, limit, quantile) in [
+        (
+            "p50",
+            limits.p50_ms,
+            0.5,
+        ),
+        (
+            "p99",
+            limits.p99_ms,
+            0.99,
+        ),
+    ]
+    {
+        if let (Some(limit), Some(latency)) = (
+            limit,
+            percentile(
+                &all.latencies,
+                quantile,
+            ),
+        )
+        {
+            if latency.as_millis() > u128::from(limit)
+            {
+                violations.push(format!("{label} latency {latency:?} over {limit} ms"));
+            }
+        }
+    }
+    if let (Some(limit), Some(peak)) = (
+        limits.max_rss_mb,
+        peak_rss_mb,
+    )
+    {
+        if peak > limit
+        {
+            violations.push(format!("peak RSS {peak} MB over {limit} MB"));
+        }
+    }
+
+    ensure!(
+        violations.is_empty(),
+        "the soak test failed: {}",
+        violations.join(", ")
+    );
+    Ok(())
+}
+
+fn print_row(
+    name: &str,
+    stats: &Stats,
+)
+{
+    let ms = |quantile| {
+        percentile(
+            &stats.latencies,
+            quantile,
+        )
+        .map_or(
+            "n/a".to_string(),
+            |latency| {
+                latency
+                    .as_millis()
+                    .to_string()
+            },
+        )
+    };
+    println!(
+        "{:<24} {:>8} {:>8} {:>10} {:>10} {:>10}",
+        name,
+        stats
+            .latencies
+            .len(),
+        stats.failures,
+        ms(0.5),
+        ms(0.9),
+        ms(0.99),
+    );
+}
+
+/// The nearest-rank `quantile` of `latencies`.
+fn percentile(
+    latencies: &[Duration],
+    quantile: f64,
+) -> Option<Duration>
+{
+    let mut sorted = latencies.to_vec();
+    sorted.sort();
+    let rank = ((quantile * sorted.len() as f64).ceil() as usize).max(1);
+    sorted
+        .get(rank - 1)
+        .copied()
+}
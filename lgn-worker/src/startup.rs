@@ -0,0 +1,242 @@
+//! The progress of the startup of the worker, which spends minutes loading the params of its
+//! provers, for the orchestrators to tell a loading worker from a stuck one.
+//!
+//! Each prover of the class is `pending` until its params are verified, downloaded or
+//! deserialized, as reported by [`ParamsLoader::observe_progress`], then `ready` once registered.
+//! The startup is complete once every prover is ready, see `GET /startup` and `GET /readiness` of
+//! the admin listener.
+//!
+//! Only the params of the worker are followed, by their path: the shadow params are loaded aside.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+use lgn_messages::types::ProverType;
+use lgn_provers::params::LoadPhase;
+use lgn_provers::params::ParamsLoader;
+use serde_derive::Serialize;
+
+use crate::config::Config;
+use crate::manager::v1::prover_params_files;
+
+static STARTUP: OnceLock<Startup> = OnceLock::new();
+
+struct Startup
+{
+    dir: PathBuf,
+    provers: Mutex<BTreeMap<String, Prover>>,
+}
+
+struct Prover
+{
+    files: Vec<PathBuf>,
+    state: LoadState,
+}
+
+/// How far along a prover is in loading its params.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(
+    tag = "state",
+    rename_all = "snake_case"
+)]
+pub(crate) enum LoadState
+{
+    Pending,
+    Verifying
+    {
+        file: String,
+    },
+    Downloading
+    {
+        file: String,
+        /// Unknown until the server answered with the size of the file.
+        progress_pct: Option<f64>,
+    },
+    Deserializing
+    {
+        file: String,
+    },
+    Ready,
+}
+
+/// The progress of the startup, as served by the admin listener.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct Status
+{
+    /// Whether every prover is ready.
+    pub(crate) complete: bool,
+    pub(crate) provers: BTreeMap<String, LoadState>,
+}
+
+/// Follows the loading of the provers of the class of `config`.
+pub(crate) fn init(config: &Config)
+{
+    let dir = PathBuf::from(
+        &config
+            .public_params
+            .dir,
+    );
+    let provers = prover_params_files(config)
+        .into_iter()
+        .map(
+            |(prover_type, files)| {
+                (
+                    prover_type.to_string(),
+                    Prover {
+                        files: files
+                            .into_iter()
+                            .map(|file| dir.join(file))
+                            .collect(),
+                        state: LoadState::Pending,
+                    },
+                )
+            },
+        )
+        .collect();
+    if STARTUP
+        .set(
+            Startup {
+                dir,
+                provers: Mutex::new(provers),
+            },
+        )
+        .is_ok()
+    {
+        ParamsLoader::observe_progress(observe);
+    }
+}
+
+/// Records that the prover of `prover_type` was registered from the params of `config`.
+pub(crate) fn ready(
+    config: &Config,
+    prover_type: ProverType,
+)
+{
+    let Some(startup) = STARTUP.get()
+    else
+    {
+        return;
+    };
+    if startup.dir
+        != Path::new(
+            &config
+                .public_params
+                .dir,
+        )
+    {
+        return;
+    }
+    if let Some(prover) = startup
+        .provers
+        .lock()
+        .expect("startup lock poisoned")
+        .get_mut(&prover_type.to_string())
+    {
+        prover.state = LoadState::Ready;
+    }
+}
+
+pub(crate) fn status() -> Status
+{
+    let provers = STARTUP
+        .get()
+        .map(
+            |startup| {
+                startup
+                    .provers
+                    .lock()
+                    .expect("startup lock poisoned")
+                    .iter()
+                    .map(
+                        |(prover_type, prover)| {
+                            (
+                                prover_type.clone(),
+                                prover
+                                    .state
+                                    .clone(),
+                            )
+                        },
+                    )
+                    .collect::<BTreeMap<_, _>>()
+            },
+        )
+        .unwrap_or_default();
+    Status {
+        complete: STARTUP
+            .get()
+            .is_some()
+            && provers
+                .values()
+                .all(|state| *state == LoadState::Ready),
+        provers,
+    }
+}
+
+fn observe(
+    file: &Path,
+    phase: LoadPhase,
+)
+{
+    let Some(startup) = STARTUP.get()
+    else
+    {
+        return;
+    };
+    let mut provers = startup
+        .provers
+        .lock()
+        .expect("startup lock poisoned");
+    let Some(prover) = provers
+        .values_mut()
+        .find(
+            |prover| {
+                prover
+                    .files
+                    .iter()
+                    .any(|prover_file| prover_file == file)
+            },
+        )
+    else
+    {
+        return;
+    };
+    // A prover registered is ready, whatever is read again afterwards, e.g. by the audit.
+    if prover.state == LoadState::Ready
+    {
+        return;
+    }
+    let file = file
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    prover.state = match phase
+    {
+        LoadPhase::Verifying =>
+        {
+            LoadState::Verifying {
+                file,
+            }
+        },
+        LoadPhase::Downloading {
+            downloaded,
+            total,
+        } =>
+        {
+            LoadState::Downloading {
+                file,
+                progress_pct: total
+                    .map(|total| (1000.0 * downloaded as f64 / total.max(1) as f64).round() / 10.0),
+            }
+        },
+        LoadPhase::Deserializing =>
+        {
+            LoadState::Deserializing {
+                file,
+            }
+        },
+    };
+}
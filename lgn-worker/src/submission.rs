@@ -0,0 +1,357 @@
+//! The hand-off of the final groth16 proofs to an on-chain submission helper.
+//!
+//! With `[submission]` set, the combined proof of each groth16 reply is encoded as the calldata of
+//! the verifier contract, see `lgn-worker groth16-calldata`, and posted as JSON to `url`, written
+//! to `payload_dir` ready to send, or both. A thread of its own submits the proofs, so that a slow
+//! helper never delays the replies, and retries the posts failing for the helper, i.e. no answer,
+//! a `5xx` or a `429`, after a growing delay. Each submission, successful or not, is logged with
+//! the task and recorded in `audit_file`. The proofs are not submitted when the queue is full.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::TrySendError;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use lgn_messages::types::MessageReplyEnvelope;
+use lgn_messages::types::ReplyType;
+use lgn_provers::provers::v1::groth16::calldata;
+use lgn_provers::provers::v1::groth16::calldata::Calldata;
+use metrics::counter;
+use serde_derive::Serialize;
+use tracing::info;
+use tracing::warn;
+
+use crate::config::SubmissionConfig;
+use crate::unix_now;
+
+static SUBMISSION: OnceLock<SyncSender<Payload>> = OnceLock::new();
+
+const SUBMISSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+const RETRY_BASE: Duration = Duration::from_secs(1);
+const RETRY_CAP: Duration = Duration::from_secs(60);
+
+/// What the submission helper receives for a proof.
+#[derive(Serialize, Debug)]
+struct Payload
+{
+    query_id: String,
+    task_id: String,
+    #[serde(flatten)]
+    calldata: Calldata,
+}
+
+/// A line of the audit file.
+#[derive(Serialize, Debug)]
+struct AuditRecord<'a>
+{
+    query_id: &'a str,
+    task_id: &'a str,
+    /// The URL posted to or the file written.
+    destination: String,
+    attempts: u32,
+    /// The error of the last attempt, if the submission failed.
+    error: Option<String>,
+    at: u64,
+}
+
+/// Starts the submission of the groth16 proofs, if configured.
+pub(crate) fn init(config: Option<&SubmissionConfig>) -> Result<()>
+{
+    let Some(config) = config
+    else
+    {
+        return Ok(());
+    };
+    if let Some(payload_dir) = &config.payload_dir
+    {
+        std::fs::create_dir_all(payload_dir)
+            .with_context(|| format!("failed to create the payload directory `{payload_dir}`"))?;
+    }
+    let (sender, receiver) = mpsc::sync_channel(config.queue_size);
+    if SUBMISSION
+        .set(sender)
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    info!(
+        "Submitting the groth16 proofs to {}",
+        [
+            config
+                .url
+                .as_ref()
+                .map(|url| format!("`{url}`")),
+            config
+                .payload_dir
+                .as_ref()
+                .map(|dir| format!("the directory `{dir}`")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" and ")
+    );
+    let config = config.clone();
+    std::thread::Builder::new()
+        .name("submission".to_string())
+        .spawn(
+            move || {
+                let client = reqwest::blocking::Client::new();
+                for payload in receiver
+                {
+                    if let Some(payload_dir) = &config.payload_dir
+                    {
+                        let path = PathBuf::from(payload_dir).join(file_name(&payload.task_id));
+                        let written = serde_json::to_vec_pretty(&payload)
+                            .map_err(anyhow::Error::from)
+                            .and_then(
+                                |json| {
+                                    std::fs::write(
+                                        &path,
+                                        json,
+                                    )
+                                    .with_context(
+                                        || {
+                                            format!(
+                                                "failed to write `{}`",
+                                                path.display()
+                                            )
+                                        },
+                                    )
+                                },
+                            );
+                        record(
+                            &config,
+                            &payload,
+                            path.display()
+                                .to_string(),
+                            1,
+                            written.err(),
+                        );
+                    }
+                    if let Some(url) = &config.url
+                    {
+                        let (attempts, posted) = post(
+                            &client,
+                            url,
+                            &payload,
+                            config.max_attempts,
+                        );
+                        record(
+                            &config,
+                            &payload,
+                            url.clone(),
+                            attempts,
+                            posted.err(),
+                        );
+                    }
+                }
+            },
+        )
+        .context("failed to spawn the submission thread")?;
+
+    Ok(())
+}
+
+/// Queues the submission of the proof of `reply`, if enabled and a groth16 proof in plaintext.
+pub(crate) fn submit(reply: &MessageReplyEnvelope<ReplyType>)
+{
+    let Some(sender) = SUBMISSION.get()
+    else
+    {
+        return;
+    };
+    let Some(proof) = reply
+        .inner()
+        .ok()
+        // An encrypted proof is only for the customer to read, and to submit.
+        .filter(
+            |content| {
+                matches!(
+                    content,
+                    ReplyType::V1Groth16(_)
+                ) && content
+                    .encryption()
+                    .is_none()
+            },
+        )
+        .and_then(ReplyType::proof)
+    else
+    {
+        return;
+    };
+    let calldata = match calldata::encode(proof)
+    {
+        Ok(calldata) => calldata,
+        Err(err) =>
+        {
+            counter!("zkmr_worker_submission_failures_total").increment(1);
+            warn!(
+                task_id = reply.task_id,
+                "Not submitting a proof the verifier would reject: {err:?}"
+            );
+            return;
+        },
+    };
+    let payload = Payload {
+        query_id: reply
+            .query_id
+            .clone(),
+        task_id: reply
+            .task_id
+            .clone(),
+        calldata,
+    };
+    if let Err(TrySendError::Full(_)) = sender.try_send(payload)
+    {
+        counter!("zkmr_worker_submission_failures_total").increment(1);
+    }
+}
+
+/// Posts `payload` to `url`, returning how many attempts were made and the outcome of the last.
+fn post(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    payload: &Payload,
+    max_attempts: u32,
+) -> (
+    u32,
+    Result<()>,
+)
+{
+    let body = match serde_json::to_vec(payload)
+    {
+        Ok(body) => body,
+        Err(err) =>
+        {
+            return (
+                0,
+                Err(err.into()),
+            )
+        },
+    };
+    let mut delay = RETRY_BASE;
+    let mut attempts = 0;
+    loop
+    {
+        attempts += 1;
+        let response = client
+            .post(url)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "application/json",
+            )
+            .timeout(SUBMISSION_TIMEOUT)
+            .body(body.clone())
+            .send();
+        let retryable = match &response
+        {
+            Ok(response) =>
+            {
+                response
+                    .status()
+                    .is_server_error()
+                    || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+            },
+            Err(_) => true,
+        };
+        let result = response
+            .and_then(reqwest::blocking::Response::error_for_status)
+            .map(|_| ())
+            .with_context(|| format!("failed to post to `{url}`"));
+        if result.is_ok() || !retryable || attempts >= max_attempts
+        {
+            return (
+                attempts,
+                result,
+            );
+        }
+        warn!(
+            task_id = payload.task_id,
+            "Failed to submit the proof, retrying in {delay:?}: {:?}",
+            result.unwrap_err()
+        );
+        std::thread::sleep(delay);
+        delay = (delay * 2).min(RETRY_CAP);
+    }
+}
+
+/// Logs, counts and audits the submission of `payload` to `destination`.
+fn record(
+    config: &SubmissionConfig,
+    payload: &Payload,
+    destination: String,
+    attempts: u32,
+    error: Option<anyhow::Error>,
+)
+{
+    match &error
+    {
+        None =>
+        {
+            counter!("zkmr_worker_submissions_total").increment(1);
+            info!(
+                task_id = payload.task_id,
+                destination, attempts, "Proof submitted"
+            );
+        },
+        Some(err) =>
+        {
+            counter!("zkmr_worker_submission_failures_total").increment(1);
+            warn!(
+                task_id = payload.task_id,
+                destination, attempts, "Failed to submit the proof: {err:?}"
+            );
+        },
+    }
+
+    let record = AuditRecord {
+        query_id: &payload.query_id,
+        task_id: &payload.task_id,
+        destination,
+        attempts,
+        error: error.map(|err| format!("{err:#}")),
+        at: unix_now(),
+    };
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.audit_file)
+        .and_then(
+            |mut file| {
+                let mut line = serde_json::to_vec(&record)?;
+                line.push(b'\n');
+                file.write_all(&line)
+            },
+        );
+    if let Err(err) = result
+    {
+        warn!(
+            "Failed to audit a submission to `{}`: {err}",
+            config.audit_file
+        );
+    }
+}
+
+/// The payload file of `task_id`, whose path separators are replaced.
+fn file_name(task_id: &str) -> String
+{
+    format!(
+        "{}.json",
+        task_id.replace(
+            [
+                '/',
+                '\\',
+            ],
+            "_",
+        )
+    )
+}
@@ -0,0 +1,247 @@
+//! How far along the worker is on each table: the highest block it proved per table and task
+//! family, e.g. `index` or `ivc`.
+//!
+//! Only the database tasks of the preprocessing name their table. The table IDs are set by the
+//! gateways and unbounded: the tables of `tables.allowlist` are exported as the
+//! `zkmr_worker_table_max_block{table_id, family}` gauges, and the `MAX_TRACKED` most recent of
+//! all of them are summarized by `GET /tables` of the admin listener.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+use lgn_messages::types::v1::preprocessing::db_tasks::DatabaseType;
+use lgn_messages::types::v1::preprocessing::db_tasks::DbCellType;
+use lgn_messages::types::v1::preprocessing::db_tasks::DbRowType;
+use lgn_messages::types::v1::preprocessing::WorkerTaskType;
+use lgn_messages::types::TaskType;
+use lgn_messages::BlockNr;
+use lgn_messages::TableId;
+use metrics::gauge;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+
+use crate::config::TablesConfig;
+use crate::manager::thread_pools::TaskKind;
+use crate::unix_now;
+
+/// The (table, family) pairs summarized at most, the least recent being forgotten.
+const MAX_TRACKED: usize = 1024;
+
+static TABLES: OnceLock<Tables> = OnceLock::new();
+
+struct Tables
+{
+    allowlist: Vec<TableId>,
+    progress: Mutex<
+        BTreeMap<
+            (
+                TableId,
+                String,
+            ),
+            Progress,
+        >,
+    >,
+}
+
+/// The activity of the worker on a table, for a task family.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Progress
+{
+    pub(crate) table_id: TableId,
+    pub(crate) family: String,
+    /// The highest block proven.
+    pub(crate) max_block: BlockNr,
+    /// The tasks proven since the worker started.
+    pub(crate) tasks: u64,
+    /// When the last task was proven, as a UNIX timestamp.
+    pub(crate) last_at: u64,
+}
+
+pub(crate) fn init(config: &TablesConfig)
+{
+    let _ = TABLES.set(
+        Tables {
+            allowlist: config
+                .allowlist
+                .clone(),
+            progress: Mutex::new(BTreeMap::new()),
+        },
+    );
+}
+
+/// Records that `task` was proven.
+pub(crate) fn record(task: &TaskType)
+{
+    let Some(tables) = TABLES.get()
+    else
+    {
+        return;
+    };
+    let Some((table_id, block_nr)) = table_block(task)
+    else
+    {
+        return;
+    };
+    let Some(kind) = TaskKind::of(task)
+    else
+    {
+        return;
+    };
+    let family = format!("{kind:?}").to_lowercase();
+
+    let mut progress = tables
+        .progress
+        .lock()
+        .expect("tables lock poisoned");
+    let entry = progress
+        .entry(
+            (
+                table_id,
+                family.clone(),
+            ),
+        )
+        .or_insert_with(
+            || {
+                Progress {
+                    table_id,
+                    family: family.clone(),
+                    max_block: block_nr,
+                    tasks: 0,
+                    last_at: 0,
+                }
+            },
+        );
+    entry.max_block = entry
+        .max_block
+        .max(block_nr);
+    entry.tasks += 1;
+    entry.last_at = unix_now();
+    let max_block = entry.max_block;
+    if progress.len() > MAX_TRACKED
+    {
+        let least_recent = progress
+            .iter()
+            .min_by_key(|(_, progress)| progress.last_at)
+            .map(|(key, _)| key.clone());
+        if let Some(least_recent) = least_recent
+        {
+            progress.remove(&least_recent);
+        }
+    }
+    drop(progress);
+
+    if tables
+        .allowlist
+        .contains(&table_id)
+    {
+        gauge!(
+            "zkmr_worker_table_max_block",
+            "table_id" => table_id.to_string(),
+            "family" => family,
+        )
+        .set(max_block as f64);
+    }
+}
+
+/// The activity of the worker on the tables, the most recent first.
+pub(crate) fn summary() -> Vec<Progress>
+{
+    let Some(tables) = TABLES.get()
+    else
+    {
+        return vec![];
+    };
+    let mut summary = tables
+        .progress
+        .lock()
+        .expect("tables lock poisoned")
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    summary.sort_by_key(|progress| std::cmp::Reverse(progress.last_at));
+    summary
+}
+
+/// The activity of the worker on the tables, for the next version of the worker.
+pub(crate) fn export() -> Vec<Progress>
+{
+    summary()
+}
+
+/// Continues the activity on the tables of the previous version of the worker, see [`export`].
+pub(crate) fn import(summary: Vec<Progress>)
+{
+    let Some(tables) = TABLES.get()
+    else
+    {
+        return;
+    };
+    let mut progress = tables
+        .progress
+        .lock()
+        .expect("tables lock poisoned");
+    for entry in summary
+        .into_iter()
+        .take(MAX_TRACKED)
+    {
+        if tables
+            .allowlist
+            .contains(&entry.table_id)
+        {
+            gauge!(
+                "zkmr_worker_table_max_block",
+                "table_id" => entry.table_id.to_string(),
+                "family" => entry.family.clone(),
+            )
+            .set(entry.max_block as f64);
+        }
+        progress.insert(
+            (
+                entry.table_id,
+                entry
+                    .family
+                    .clone(),
+            ),
+            entry,
+        );
+    }
+}
+
+/// The table and the block of `task`, if it names its table.
+fn table_block(
+    task: &TaskType
+) -> Option<(
+    TableId,
+    BlockNr,
+)>
+{
+    let TaskType::V1Preprocessing(task) = task
+    else
+    {
+        return None;
+    };
+    let WorkerTaskType::Database(database) = &task.task_type
+    else
+    {
+        return None;
+    };
+    let table_id = match database
+    {
+        DatabaseType::Cell(DbCellType::Leaf(input)) => input.table_id,
+        DatabaseType::Cell(DbCellType::Partial(input)) => input.table_id,
+        DatabaseType::Cell(DbCellType::Full(input)) => input.table_id,
+        DatabaseType::Row(DbRowType::Leaf(input)) => input.table_id,
+        DatabaseType::Row(DbRowType::Partial(input)) => input.table_id,
+        DatabaseType::Row(DbRowType::Full(input)) => input.table_id,
+        DatabaseType::RowUpdate(input) => input.table_id,
+        DatabaseType::Index(input) => input.table_id,
+        DatabaseType::IVC(input) => input.table_id,
+    };
+    Some(
+        (
+            table_id,
+            task.block_nr,
+        ),
+    )
+}
@@ -0,0 +1,526 @@
+//! Emergency patches of the incoming tasks, e.g. to fix the location of a proof while the gateway
+//! can not be redeployed.
+//!
+//! The rules are read on startup from a local JSON file, a list of:
+//!
+//! ```json
+//! {
+//!     "name": "no-deadlines-on-mainnet",
+//!     "reason": "INC-123, the gateway sets deadlines in milliseconds",
+//!     "expires_unix": 1767225600,
+//!     "match": { "$.inner.V1Query.chain_id": 1 },
+//!     "patch": { "$.deadline_unix": null }
+//! }
+//! ```
+//!
+//! The paths are JSONPath-style, `$` being the task envelope, followed by `.key` and `[index]`
+//! segments. A rule patches the tasks whose values at its `match` paths equal the given ones, until
+//! it expires. The patches are applied at admission, before the task is parsed, each of them
+//! being logged and appended to the audit file.
+
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use metrics::counter;
+use metrics::gauge;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::config::TaskPatchesConfig;
+use crate::unix_now;
+
+static PATCHES: OnceLock<Patches> = OnceLock::new();
+
+struct Patches
+{
+    rules: Vec<Rule>,
+    audit_file: PathBuf,
+}
+
+/// A rule as written in the rules file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec
+{
+    name: String,
+    /// Why the rule exists, e.g. the incident it works around.
+    reason: String,
+    /// The rule is ignored past this time, so that a forgotten rule does not patch tasks forever.
+    expires_unix: u64,
+    #[serde(
+        rename = "match",
+        default
+    )]
+    matches: BTreeMap<String, Value>,
+    patch: BTreeMap<String, Value>,
+}
+
+struct Rule
+{
+    name: String,
+    reason: String,
+    expires_unix: u64,
+    matches: Vec<(
+        Vec<Segment>,
+        Value,
+    )>,
+    patch: Vec<(
+        String,
+        Vec<Segment>,
+        Value,
+    )>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment
+{
+    Key(String),
+    Index(usize),
+}
+
+/// A patch applied, as appended to the audit file.
+#[derive(Serialize)]
+struct AuditRecord<'a>
+{
+    at: u64,
+    rule: &'a str,
+    reason: &'a str,
+    task_id: &'a str,
+    path: &'a str,
+    before: Value,
+    after: &'a Value,
+}
+
+/// Loads the rules, if configured, failing on an invalid rules file.
+pub(crate) fn init(config: Option<&TaskPatchesConfig>) -> Result<()>
+{
+    let Some(config) = config
+    else
+    {
+        return Ok(());
+    };
+    let content = std::fs::read(&config.rules_file).with_context(
+        || {
+            format!(
+                "failed to read the task patches `{}`",
+                config.rules_file
+            )
+        },
+    )?;
+    let specs = serde_json::from_slice::<Vec<RuleSpec>>(&content).with_context(
+        || {
+            format!(
+                "`{}` is not a list of task patches",
+                config.rules_file
+            )
+        },
+    )?;
+    let rules = specs
+        .into_iter()
+        .map(Rule::parse)
+        .collect::<Result<Vec<_>>>()?;
+
+    let now = unix_now();
+    let mut active = 0;
+    for rule in &rules
+    {
+        if rule.expires_unix <= now
+        {
+            warn!(
+                "Task patch rule `{}` expired at {}, ignoring it",
+                rule.name, rule.expires_unix
+            );
+            continue;
+        }
+        active += 1;
+        warn!(
+            "TASK PATCHES ACTIVE: rule `{}` patches {} of the tasks matching {}, until {}, \
+             because: {}",
+            rule.name,
+            rule.patch
+                .iter()
+                .map(|(path, ..)| path.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            if rule
+                .matches
+                .is_empty()
+            {
+                "anything".to_string()
+            }
+            else
+            {
+                format!(
+                    "{} path(s)",
+                    rule.matches
+                        .len()
+                )
+            },
+            rule.expires_unix,
+            rule.reason
+        );
+    }
+    gauge!("zkmr_worker_task_patch_rules_active").set(active as f64);
+
+    let _ = PATCHES.set(
+        Patches {
+            rules,
+            audit_file: PathBuf::from(&config.audit_file),
+        },
+    );
+    Ok(())
+}
+
+/// The task `document` patched by the rules, `None` if no rule applied to it.
+pub(crate) fn apply(document: &str) -> Option<String>
+{
+    let patches = PATCHES.get()?;
+    let now = unix_now();
+    // A task which does not parse is left to the admission.
+    let mut task = serde_json::from_str::<Value>(document).ok()?;
+    let task_id = task
+        .get("task_id")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+
+    let mut patched = false;
+    for rule in patches
+        .rules
+        .iter()
+        .filter(|rule| rule.expires_unix > now)
+    {
+        if !rule
+            .matches
+            .iter()
+            .all(
+                |(path, expected)| {
+                    get(
+                        &task,
+                        path,
+                    ) == Some(expected)
+                },
+            )
+        {
+            continue;
+        }
+        for (text, path, value) in &rule.patch
+        {
+            let Some(before) = set(
+                &mut task,
+                path,
+                value.clone(),
+            )
+            else
+            {
+                warn!(
+                    "Task patch rule `{}` could not set `{text}` of task {task_id}",
+                    rule.name
+                );
+                continue;
+            };
+            patched = true;
+            warn!(
+                "Task patch rule `{}` set `{text}` of task {task_id} from {before} to {value}",
+                rule.name
+            );
+            counter!("zkmr_worker_task_patches_applied_total", "rule" => rule.name.clone())
+                .increment(1);
+            audit(
+                patches,
+                &AuditRecord {
+                    at: now,
+                    rule: &rule.name,
+                    reason: &rule.reason,
+                    task_id: &task_id,
+                    path: text,
+                    before,
+                    after: value,
+                },
+            );
+        }
+    }
+
+    patched.then(|| task.to_string())
+}
+
+impl Rule
+{
+    fn parse(spec: RuleSpec) -> Result<Self>
+    {
+        ensure!(
+            !spec
+                .patch
+                .is_empty(),
+            "task patch rule `{}` patches nothing",
+            spec.name
+        );
+        let matches = spec
+            .matches
+            .into_iter()
+            .map(
+                |(path, value)| {
+                    Ok(
+                        (
+                            parse_path(&path)?,
+                            value,
+                        ),
+                    )
+                },
+            )
+            .collect::<Result<_>>()
+            .with_context(
+                || {
+                    format!(
+                        "invalid match of task patch rule `{}`",
+                        spec.name
+                    )
+                },
+            )?;
+        let patch = spec
+            .patch
+            .into_iter()
+            .map(
+                |(path, value)| {
+                    Ok(
+                        (
+                            path.clone(),
+                            parse_path(&path)?,
+                            value,
+                        ),
+                    )
+                },
+            )
+            .collect::<Result<_>>()
+            .with_context(
+                || {
+                    format!(
+                        "invalid patch of task patch rule `{}`",
+                        spec.name
+                    )
+                },
+            )?;
+
+        Ok(
+            Self {
+                name: spec.name,
+                reason: spec.reason,
+                expires_unix: spec.expires_unix,
+                matches,
+                patch,
+            },
+        )
+    }
+}
+
+/// Appends `record` to the audit file, a failure only being logged.
+fn audit(
+    patches: &Patches,
+    record: &AuditRecord,
+)
+{
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&patches.audit_file)
+        .and_then(
+            |mut file| {
+                let mut line = serde_json::to_vec(record)?;
+                line.push(b'\n');
+                file.write_all(&line)
+            },
+        );
+    if let Err(err) = result
+    {
+        warn!(
+            "Failed to audit a task patch to `{}`: {err}",
+            patches
+                .audit_file
+                .display()
+        );
+        counter!("zkmr_worker_task_patch_audit_failures_total").increment(1);
+    }
+}
+
+/// Parses a path such as `$.inner.V1Query.inputs[0].key`.
+fn parse_path(path: &str) -> Result<Vec<Segment>>
+{
+    let mut rest = path
+        .strip_prefix('$')
+        .with_context(|| format!("`{path}` does not start with `$`"))?;
+    let mut segments = vec![];
+    while !rest.is_empty()
+    {
+        if let Some(tail) = rest.strip_prefix('.')
+        {
+            let end = tail
+                .find(
+                    [
+                        '.',
+                        '[',
+                    ],
+                )
+                .unwrap_or(tail.len());
+            ensure!(
+                end > 0,
+                "`{path}` has an empty key"
+            );
+            segments.push(Segment::Key(tail[..end].to_string()));
+            rest = &tail[end..];
+        }
+        else if let Some(tail) = rest.strip_prefix('[')
+        {
+            let (index, tail) = tail
+                .split_once(']')
+                .with_context(|| format!("`{path}` has an unclosed `[`"))?;
+            segments.push(
+                Segment::Index(
+                    index
+                        .parse()
+                        .with_context(|| format!("`{path}` has an invalid index `{index}`"))?,
+                ),
+            );
+            rest = tail;
+        }
+        else
+        {
+            bail!("`{path}` has an unexpected `{rest}`");
+        }
+    }
+    ensure!(
+        !segments.is_empty(),
+        "`{path}` designates the whole task"
+    );
+    Ok(segments)
+}
+
+fn get<'a>(
+    value: &'a Value,
+    path: &[Segment],
+) -> Option<&'a Value>
+{
+    path.iter()
+        .try_fold(
+            value,
+            |value, segment| {
+                match segment
+                {
+                    Segment::Key(key) => value.get(key),
+                    Segment::Index(index) => value.get(index),
+                }
+            },
+        )
+}
+
+/// Sets the value at `path`, whose parent must exist, returning the previous value, null if none.
+fn set(
+    value: &mut Value,
+    path: &[Segment],
+    new: Value,
+) -> Option<Value>
+{
+    let (last, parents) = path.split_last()?;
+    let parent = parents
+        .iter()
+        .try_fold(
+            value,
+            |value, segment| {
+                match segment
+                {
+                    Segment::Key(key) => value.get_mut(key),
+                    Segment::Index(index) => value.get_mut(index),
+                }
+            },
+        )?;
+    match (
+        last,
+        parent,
+    )
+    {
+        (Segment::Key(key), Value::Object(object)) =>
+        {
+            Some(
+                object
+                    .insert(
+                        key.clone(),
+                        new,
+                    )
+                    .unwrap_or_default(),
+            )
+        },
+        (Segment::Index(index), Value::Array(items)) =>
+        {
+            items
+                .get_mut(*index)
+                .map(
+                    |item| {
+                        std::mem::replace(
+                            item,
+                            new,
+                        )
+                    },
+                )
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn patches_the_matching_paths()
+    {
+        let mut task = json!({
+            "task_id": "t1",
+            "inner": { "V1Query": { "chain_id": 1, "proofs": [{ "location": "old" }] } }
+        });
+        let chain_id = parse_path("$.inner.V1Query.chain_id").unwrap();
+        let location = parse_path("$.inner.V1Query.proofs[0].location").unwrap();
+
+        assert_eq!(
+            get(
+                &task,
+                &chain_id
+            ),
+            Some(&json!(1))
+        );
+        assert_eq!(
+            set(
+                &mut task,
+                &location,
+                json!("new")
+            ),
+            Some(json!("old"))
+        );
+        assert_eq!(
+            task["inner"]["V1Query"]["proofs"][0]["location"],
+            "new"
+        );
+        assert_eq!(
+            set(
+                &mut task,
+                &parse_path("$.inner.V1Groth16.chain_id").unwrap(),
+                json!(2)
+            ),
+            None
+        );
+
+        assert!(parse_path("inner.chain_id").is_err());
+        assert!(parse_path("$.inner..chain_id").is_err());
+        assert!(parse_path("$.proofs[first]").is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! Sampling profiles of the proofs, aggregated per task type, for `--profile-tasks <dir>`.
+//!
+//! The stacks of the worker are sampled while a task is proven, and the samples are added to the
+//! profile of its task type. The profiles are written every minute, after a proof, as
+//! `<dir>/<task type>.svg` flamegraphs and `<dir>/<task type>.pb` pprof protobufs, e.g. for
+//! `go tool pprof`. The files hold every sample since the worker started.
+//!
+//! The sampler is process-wide: the proofs are profiled one at a time, a task proven while another
+//! one is profiled is not.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use anyhow::Result;
+use metrics::counter;
+use pprof::protos::Message;
+use pprof::Frames;
+use pprof::ProfilerGuard;
+use pprof::ProfilerGuardBuilder;
+use pprof::Report;
+use pprof::ReportTiming;
+use tracing::info;
+use tracing::warn;
+
+/// The sampling frequency, off the round numbers not to beat with periodic work.
+const FREQUENCY_HZ: i32 = 99;
+
+/// How often the profiles are written.
+const WRITE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The libraries of the sampler signal handler, cut from the stacks.
+const BLOCKLIST: [&str; 4] = [
+    "libc",
+    "libgcc",
+    "pthread",
+    "vdso",
+];
+
+static PROFILES: OnceLock<Profiles> = OnceLock::new();
+
+struct Profiles
+{
+    dir: PathBuf,
+    state: Mutex<State>,
+}
+
+struct State
+{
+    /// The samples of each task type.
+    task_types: HashMap<String, TaskTypeProfile>,
+    written: Instant,
+}
+
+struct TaskTypeProfile
+{
+    samples: HashMap<Frames, isize>,
+    started: SystemTime,
+    /// The time spent profiling this task type.
+    duration: Duration,
+}
+
+/// The profile of a task being proven.
+pub(crate) struct TaskProfile
+{
+    task_type: String,
+    guard: ProfilerGuard<'static>,
+    started: Instant,
+}
+
+/// Enables the profiling of the proofs into `dir`, if set.
+pub(crate) fn init(dir: Option<&Path>) -> Result<()>
+{
+    let Some(dir) = dir
+    else
+    {
+        return Ok(());
+    };
+    std::fs::create_dir_all(dir).with_context(
+        || {
+            format!(
+                "failed to create the profiles directory `{}`",
+                dir.display()
+            )
+        },
+    )?;
+    warn!(
+        "Profiling the proofs into `{}`, at {FREQUENCY_HZ} Hz",
+        dir.display()
+    );
+    let _ = PROFILES.set(
+        Profiles {
+            dir: dir.to_path_buf(),
+            state: Mutex::new(
+                State {
+                    task_types: HashMap::new(),
+                    written: Instant::now(),
+                },
+            ),
+        },
+    );
+    Ok(())
+}
+
+/// Starts sampling the proof of a task of `task_type`, if the profiling is enabled and no other
+/// proof is being sampled.
+pub(crate) fn start(task_type: &str) -> Option<TaskProfile>
+{
+    PROFILES.get()?;
+    let guard = ProfilerGuardBuilder::default()
+        .frequency(FREQUENCY_HZ)
+        .blocklist(&BLOCKLIST)
+        .build();
+    match guard
+    {
+        Ok(guard) =>
+        {
+            Some(
+                TaskProfile {
+                    task_type: task_type.to_string(),
+                    guard,
+                    started: Instant::now(),
+                },
+            )
+        },
+        Err(err) =>
+        {
+            counter!("zkmr_worker_task_profiles_skipped_total").increment(1);
+            warn!("Not profiling a `{task_type}` task: {err}");
+            None
+        },
+    }
+}
+
+impl TaskProfile
+{
+    /// Stops sampling, adding the samples to the profile of the task type, and writes the
+    /// profiles if they were not written for a while.
+    pub(crate) fn finish(self)
+    {
+        let Some(profiles) = PROFILES.get()
+        else
+        {
+            return;
+        };
+        let report = match self
+            .guard
+            .report()
+            .build()
+        {
+            Ok(report) => report,
+            Err(err) =>
+            {
+                warn!(
+                    "Failed to collect the profile of a `{}` task: {err}",
+                    self.task_type
+                );
+                return;
+            },
+        };
+        drop(self.guard);
+
+        let mut state = profiles
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let profile = state
+            .task_types
+            .entry(self.task_type)
+            .or_insert_with(
+                || {
+                    TaskTypeProfile {
+                        samples: HashMap::new(),
+                        started: SystemTime::now()
+                            - self
+                                .started
+                                .elapsed(),
+                        duration: Duration::ZERO,
+                    }
+                },
+            );
+        for (frames, count) in report.data
+        {
+            *profile
+                .samples
+                .entry(frames)
+                .or_default() += count;
+        }
+        profile.duration += self
+            .started
+            .elapsed();
+
+        if state
+            .written
+            .elapsed()
+            >= WRITE_INTERVAL
+        {
+            state.written = Instant::now();
+            for (task_type, profile) in &state.task_types
+            {
+                if let Err(err) = profile.write(
+                    &profiles.dir,
+                    task_type,
+                )
+                {
+                    warn!("Failed to write the profile of the `{task_type}` tasks: {err:?}");
+                }
+            }
+        }
+    }
+}
+
+impl TaskTypeProfile
+{
+    fn write(
+        &self,
+        dir: &Path,
+        task_type: &str,
+    ) -> Result<()>
+    {
+        let report = Report {
+            data: self
+                .samples
+                .clone(),
+            timing: ReportTiming {
+                frequency: FREQUENCY_HZ,
+                start_time: self.started,
+                duration: self.duration,
+            },
+        };
+
+        let mut svg = Vec::new();
+        report
+            .flamegraph(&mut svg)
+            .context("failed to render the flamegraph")?;
+        let mut pb = Vec::new();
+        report
+            .pprof()
+            .context("failed to encode the profile")?
+            .encode(&mut pb)?;
+
+        for (extension, content) in [
+            (
+                "svg",
+                svg,
+            ),
+            (
+                "pb",
+                pb,
+            ),
+        ]
+        {
+            // Written aside then renamed, a reader never sees half a profile.
+            let path = dir.join(format!("{task_type}.{extension}"));
+            let partial = path.with_extension(format!("{extension}.partial"));
+            std::fs::write(
+                &partial,
+                content,
+            )?;
+            std::fs::rename(
+                &partial,
+                &path,
+            )?;
+        }
+        info!(
+            "Wrote the profile of the `{task_type}` tasks to `{}`",
+            dir.display()
+        );
+        Ok(())
+    }
+}
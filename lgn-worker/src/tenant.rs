@@ -0,0 +1,51 @@
+//! The tenant label of the metrics, for the cost attribution of shared workers.
+//!
+//! Tenants are set by the gateway and unbounded, labelling the metrics with them as-is could grow
+//! the number of series without limit. The allowed tenants are labelled by name, the others by a
+//! bucket of their hash.
+
+use std::sync::OnceLock;
+
+use crate::config::TenantsConfig;
+
+static TENANTS: OnceLock<TenantsConfig> = OnceLock::new();
+
+/// The label of the tasks without a tenant.
+const NO_TENANT: &str = "none";
+
+pub(crate) fn init(config: &TenantsConfig)
+{
+    let _ = TENANTS.set(config.clone());
+}
+
+/// The metrics label of `tenant`.
+pub(crate) fn label(tenant: Option<&str>) -> String
+{
+    let Some(tenant) = tenant
+    else
+    {
+        return NO_TENANT.to_string();
+    };
+    let Some(config) = TENANTS.get()
+    else
+    {
+        return NO_TENANT.to_string();
+    };
+
+    if config
+        .allowlist
+        .iter()
+        .any(|allowed| allowed == tenant)
+    {
+        return tenant.to_string();
+    }
+
+    // A stable hash, so that a tenant keeps its bucket across restarts and versions.
+    let hash = blake3::hash(tenant.as_bytes());
+    let mut head = [0; 8];
+    head.copy_from_slice(&hash.as_bytes()[..8]);
+    format!(
+        "bucket-{}",
+        u64::from_le_bytes(head) % u64::from(config.hash_buckets)
+    )
+}
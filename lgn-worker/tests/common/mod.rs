@@ -0,0 +1,36 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+/// A scratch directory removed on drop.
+pub struct ScratchDir(pub PathBuf);
+
+impl ScratchDir
+{
+    pub fn new(name: &str) -> Self
+    {
+        let dir = std::env::temp_dir().join(
+            format!(
+                "lgn-worker-{name}-{}",
+                std::process::id()
+            ),
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Drop for ScratchDir
+{
+    fn drop(&mut self)
+    {
+        let _ = std::fs::set_permissions(
+            &self.0,
+            std::fs::Permissions::from_mode(0o755),
+        );
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
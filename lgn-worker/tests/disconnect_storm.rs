@@ -0,0 +1,319 @@
+//! Runs the worker against a local gateway while injecting faults into its transport, and checks
+//! that it either keeps replying or exits with a precise reason.
+//!
+//! The tasks are answered with errors, the test needs a worker without provers:
+//! `cargo test -p lgn-worker --no-default-features --features fault-injection --test
+//! disconnect_storm`
+#![cfg(
+    all(
+        unix,
+        feature = "fault-injection",
+        not(
+            any(
+                feature = "prover-preprocessing",
+                feature = "prover-query",
+                feature = "prover-groth16"
+            )
+        )
+    )
+)]
+
+use std::io::Read;
+use std::pin::Pin;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use common::ScratchDir;
+use lagrange::worker_to_gw_request;
+use lagrange::worker_to_gw_response;
+use lagrange::workers_service_server::WorkersService;
+use lagrange::workers_service_server::WorkersServiceServer;
+use lagrange::WorkerToGwRequest;
+use lagrange::WorkerToGwResponse;
+use tokio_stream::wrappers::IntervalStream;
+use tokio_stream::wrappers::TcpListenerStream;
+use tokio_stream::Stream;
+use tokio_stream::StreamExt;
+
+mod common;
+
+pub mod lagrange
+{
+    tonic::include_proto!("lagrange");
+}
+
+/// How often the gateway sends a task on each stream.
+const TASK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long the worker runs in each scenario.
+const SCENARIO_DURATION: Duration = Duration::from_secs(10);
+
+/// How recent the last reply of a worker still running must be.
+const MAX_SILENCE: Duration = Duration::from_secs(5);
+
+/// A throwaway key, the gateway does not check the tokens.
+const PRIVATE_KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+/// A gateway sending tasks on every stream and recording the replies.
+#[derive(Clone, Default)]
+struct Gateway
+{
+    last_reply: Arc<Mutex<Option<Instant>>>,
+}
+
+#[tonic::async_trait]
+impl WorkersService for Gateway
+{
+    type WorkerToGwStream =
+        Pin<Box<dyn Stream<Item = Result<WorkerToGwResponse, tonic::Status>> + Send>>;
+
+    async fn worker_to_gw(
+        &self,
+        request: tonic::Request<tonic::Streaming<WorkerToGwRequest>>,
+    ) -> Result<tonic::Response<Self::WorkerToGwStream>, tonic::Status>
+    {
+        let mut inbound = request.into_inner();
+        let last_reply = self
+            .last_reply
+            .clone();
+        tokio::spawn(
+            async move {
+                while let Ok(Some(message)) = inbound
+                    .message()
+                    .await
+                {
+                    if let Some(worker_to_gw_request::Request::WorkerDone(_)) = message.request
+                    {
+                        *last_reply
+                            .lock()
+                            .unwrap() = Some(Instant::now());
+                    }
+                }
+            },
+        );
+
+        let mut sent = 0;
+        let tasks = IntervalStream::new(tokio::time::interval(TASK_INTERVAL)).map(
+            move |_| {
+                sent += 1;
+                Ok(
+                    WorkerToGwResponse {
+                        response: Some(worker_to_gw_response::Response::Todo(task(sent))),
+                    },
+                )
+            },
+        );
+        Ok(tonic::Response::new(Box::pin(tasks)))
+    }
+}
+
+/// A groth16 task, which a worker without provers fails.
+fn task(index: u64) -> String
+{
+    format!(
+        r#"{{
+            "query_id": "storm",
+            "task_id": "storm-{index}",
+            "db_task_id": null,
+            "rtt": 18446744073709551615,
+            "gas": null,
+            "routing_key": {{ "domain": "sg", "priority": 0 }},
+            "inner": {{
+                "V1Groth16": {{
+                    "chain_id": 1,
+                    "revelation_proof": {{ "Dehydrated": {{ "Revelation": "storm" }} }}
+                }}
+            }}
+        }}"#
+    )
+}
+
+/// Reads the output of `child` on threads of their own, so that it never blocks on a full pipe.
+fn collect_output(child: &mut Child) -> Vec<std::thread::JoinHandle<String>>
+{
+    let stdout = child
+        .stdout
+        .take()
+        .map(|pipe| Box::new(pipe) as Box<dyn Read + Send>);
+    let stderr = child
+        .stderr
+        .take()
+        .map(|pipe| Box::new(pipe) as Box<dyn Read + Send>);
+    [
+        stdout,
+        stderr,
+    ]
+    .into_iter()
+    .flatten()
+    .map(
+        |mut pipe| {
+            std::thread::spawn(
+                move || {
+                    let mut output = String::new();
+                    let _ = pipe.read_to_string(&mut output);
+                    output
+                },
+            )
+        },
+    )
+    .collect()
+}
+
+/// Runs the worker with the `faults`, checking that it is still replying at the end of the
+/// scenario or that it exited with a precise reason.
+async fn run_scenario(
+    faults: &str,
+    must_survive: bool,
+)
+{
+    let gateway = Gateway::default();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let address = listener
+        .local_addr()
+        .unwrap();
+    let server = tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(WorkersServiceServer::new(gateway.clone()))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    // The scenarios of the tests run concurrently.
+    static SCENARIOS: AtomicUsize = AtomicUsize::new(0);
+    let data_dir = ScratchDir::new(
+        &format!(
+            "disconnect-storm-{}",
+            SCENARIOS.fetch_add(
+                1,
+                Ordering::Relaxed
+            )
+        ),
+    );
+    let metrics_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = data_dir
+        .0
+        .join("worker.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "data_dir = {:?}\n\n[avs]\ngateway_grpc_url = \"http://{address}\"\nlagr_keystore = \
+             \"unused\"\nlagr_private_key = \"{PRIVATE_KEY}\"\n\n[prometheus]\nport = \
+             {metrics_port}\n\n[public_params]\nskip_checksum = true\n",
+            data_dir.0,
+        ),
+    )
+    .unwrap();
+
+    let mut worker = Command::new(env!("CARGO_BIN_EXE_lgn-worker"))
+        .env(
+            "LGN_FAULT_INJECTION",
+            faults,
+        )
+        .arg("--config")
+        .arg(&config)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let output = collect_output(&mut worker);
+
+    let started = Instant::now();
+    let status = loop
+    {
+        if let Some(status) = worker
+            .try_wait()
+            .unwrap()
+        {
+            break Some(status);
+        }
+        if started.elapsed() > SCENARIO_DURATION
+        {
+            break None;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    };
+    if status.is_none()
+    {
+        let _ = worker.kill();
+        let _ = worker.wait();
+    }
+    server.abort();
+    let output = output
+        .into_iter()
+        .map(
+            |reader| {
+                reader
+                    .join()
+                    .unwrap()
+            },
+        )
+        .collect::<String>();
+
+    match status
+    {
+        Some(status) =>
+        {
+            assert!(
+                !must_survive,
+                "the worker exited under `{faults}` ({status}): {output}"
+            );
+            assert!(
+                !status.success(),
+                "the worker stopped without error under `{faults}`: {output}"
+            );
+            assert!(
+                output.contains("exit_reason=") && output.contains("error_code=E"),
+                "the worker exited without a reason under `{faults}`: {output}"
+            );
+        },
+        None =>
+        {
+            let last_reply = *gateway
+                .last_reply
+                .lock()
+                .unwrap();
+            assert!(
+                last_reply.is_some_and(|at| at.elapsed() < MAX_SILENCE),
+                "the worker stopped replying under `{faults}`: {output}"
+            );
+        },
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn malformed_tasks_and_slow_sends_are_survived()
+{
+    for seed in 0..2
+    {
+        run_scenario(
+            &format!("malformed=0.3,send_delay_ms=200,seed={seed}"),
+            true,
+        )
+        .await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn disconnect_storms_end_with_a_reason()
+{
+    for seed in 0..4
+    {
+        run_scenario(
+            &format!("stream_error=0.02,malformed=0.1,send_delay_ms=100,seed={seed}"),
+            false,
+        )
+        .await;
+    }
+}
@@ -0,0 +1,127 @@
+//! Runs the worker with a read-only working directory and checks that every file it writes goes
+//! under its data directory.
+#![cfg(unix)]
+
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+
+use common::ScratchDir;
+
+mod common;
+
+/// Serves an empty checksum file to a single request, returning its URL.
+fn serve_checksums() -> String
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!(
+        "http://{}/checksums.txt",
+        listener
+            .local_addr()
+            .unwrap()
+    );
+    std::thread::spawn(
+        move || {
+            let (mut stream, _) = listener
+                .accept()
+                .unwrap();
+            let mut request = vec![];
+            let mut buffer = [0u8; 1024];
+            while !request
+                .windows(4)
+                .any(|window| window == b"\r\n\r\n")
+            {
+                let read = stream
+                    .read(&mut buffer)
+                    .unwrap();
+                if read == 0
+                {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+        },
+    );
+    url
+}
+
+fn is_empty(dir: &Path) -> bool
+{
+    std::fs::read_dir(dir)
+        .unwrap()
+        .next()
+        .is_none()
+}
+
+#[test]
+fn writes_stay_under_the_data_dir()
+{
+    let data_dir = ScratchDir::new("data");
+    let read_only = ScratchDir::new("read-only");
+    std::fs::create_dir_all(
+        data_dir
+            .0
+            .join("zkmr_params"),
+    )
+    .unwrap();
+
+    let config = data_dir
+        .0
+        .join("worker.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "data_dir = {:?}\n\n[public_params]\nchecksum_url = {:?}\nskip_checksum = false\n",
+            data_dir.0,
+            serve_checksums(),
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(
+        &read_only.0,
+        std::fs::Permissions::from_mode(0o555),
+    )
+    .unwrap();
+
+    // Migrating the params fetches the checksum file, hashes the params and records their layout.
+    let output = Command::new(env!("CARGO_BIN_EXE_lgn-worker"))
+        .current_dir(&read_only.0)
+        .env_remove("TMPDIR")
+        .arg("--config")
+        .arg(&config)
+        .arg("--migrate-params")
+        .output()
+        .unwrap();
+    assert!(
+        output
+            .status
+            .success(),
+        "the worker failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    for written in [
+        "expected_checksums.txt",
+        "zkmr_params.layout",
+    ]
+    {
+        assert!(
+            data_dir
+                .0
+                .join(written)
+                .exists(),
+            "`{written}` was not written under the data directory"
+        );
+    }
+    // Also catches the writes of a root user, which the permissions do not stop.
+    assert!(
+        is_empty(&read_only.0),
+        "the worker wrote to its working directory"
+    );
+}